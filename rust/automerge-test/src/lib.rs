@@ -6,6 +6,8 @@ use std::{
 
 use serde::ser::{SerializeMap, SerializeSeq};
 
+pub mod trace;
+
 pub fn new_doc() -> automerge::AutoCommit {
     let mut d = automerge::AutoCommit::new();
     d.set_actor(automerge::ActorId::random());
@@ -0,0 +1,348 @@
+//! Recording and replaying editing traces.
+//!
+//! A [`TraceRecorder`] is an [`OpObserver`] which records every operation applied to a document.
+//! The resulting [`Trace`] can be saved as JSON and later replayed against a fresh document with
+//! [`Trace::replay`], which makes it possible to benchmark or regression test a new version of
+//! the library against a real application workload rather than a synthetic one.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use automerge::transaction::Transactable;
+use automerge::{
+    AutoCommit, AutomergeError, ObjId, ObjType, OpObserver, Parents, Prop, ScalarValue, Value,
+};
+use serde::{Deserialize, Serialize};
+
+/// A recorded sequence of operations.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Trace {
+    pub ops: Vec<TraceOp>,
+}
+
+/// A single recorded operation. Objects are identified by the string form of their id in the
+/// recorded document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum TraceOp {
+    Put {
+        obj: String,
+        prop: TraceProp,
+        value: TraceValue,
+    },
+    Insert {
+        obj: String,
+        index: usize,
+        value: TraceValue,
+    },
+    SpliceText {
+        obj: String,
+        index: usize,
+        text: String,
+    },
+    Increment {
+        obj: String,
+        prop: TraceProp,
+        by: i64,
+    },
+    Delete {
+        obj: String,
+        prop: TraceProp,
+    },
+    /// The source label of the operations which follow, see
+    /// [`automerge::transaction::Transaction::set_source`].
+    Source {
+        source: Option<String>,
+    },
+    /// The end of a transaction.
+    Commit,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TraceProp {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum TraceValue {
+    /// A new object, `id` is its id in the recorded document.
+    Object {
+        obj_type: TraceObjType,
+        id: String,
+    },
+    Bytes(Vec<u8>),
+    Str(String),
+    Int(i64),
+    Uint(u64),
+    F64(f64),
+    Counter(i64),
+    Timestamp(i64),
+    Boolean(bool),
+    Null,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TraceObjType {
+    Map,
+    Table,
+    List,
+    Text,
+}
+
+impl From<ObjType> for TraceObjType {
+    fn from(o: ObjType) -> Self {
+        match o {
+            ObjType::Map => TraceObjType::Map,
+            ObjType::Table => TraceObjType::Table,
+            ObjType::List => TraceObjType::List,
+            ObjType::Text => TraceObjType::Text,
+        }
+    }
+}
+
+impl From<TraceObjType> for ObjType {
+    fn from(o: TraceObjType) -> Self {
+        match o {
+            TraceObjType::Map => ObjType::Map,
+            TraceObjType::Table => ObjType::Table,
+            TraceObjType::List => ObjType::List,
+            TraceObjType::Text => ObjType::Text,
+        }
+    }
+}
+
+impl From<Prop> for TraceProp {
+    fn from(p: Prop) -> Self {
+        match p {
+            Prop::Map(key) => TraceProp::Key(key),
+            Prop::Seq(index) => TraceProp::Index(index),
+        }
+    }
+}
+
+impl From<&TraceProp> for Prop {
+    fn from(p: &TraceProp) -> Self {
+        match p {
+            TraceProp::Key(key) => Prop::Map(key.clone()),
+            TraceProp::Index(index) => Prop::Seq(*index),
+        }
+    }
+}
+
+impl TraceValue {
+    fn new(value: Value<'_>, id: &ObjId) -> Self {
+        match value {
+            Value::Object(obj_type) => TraceValue::Object {
+                obj_type: obj_type.into(),
+                id: id.to_string(),
+            },
+            Value::Scalar(s) => match s.as_ref() {
+                ScalarValue::Bytes(b) => TraceValue::Bytes(b.clone()),
+                ScalarValue::Str(s) => TraceValue::Str(s.to_string()),
+                ScalarValue::Int(i) => TraceValue::Int(*i),
+                ScalarValue::Uint(u) => TraceValue::Uint(*u),
+                ScalarValue::F64(f) => TraceValue::F64(*f),
+                ScalarValue::Counter(c) => TraceValue::Counter(c.into()),
+                ScalarValue::Timestamp(t) => TraceValue::Timestamp(*t),
+                ScalarValue::Boolean(b) => TraceValue::Boolean(*b),
+                ScalarValue::Unknown { .. } | ScalarValue::Null => TraceValue::Null,
+            },
+        }
+    }
+
+    fn scalar(&self) -> ScalarValue {
+        match self {
+            TraceValue::Bytes(b) => ScalarValue::Bytes(b.clone()),
+            TraceValue::Str(s) => ScalarValue::Str(s.into()),
+            TraceValue::Int(i) => ScalarValue::Int(*i),
+            TraceValue::Uint(u) => ScalarValue::Uint(*u),
+            TraceValue::F64(f) => ScalarValue::F64(*f),
+            TraceValue::Counter(c) => ScalarValue::counter(*c),
+            TraceValue::Timestamp(t) => ScalarValue::Timestamp(*t),
+            TraceValue::Boolean(b) => ScalarValue::Boolean(*b),
+            TraceValue::Object { .. } | TraceValue::Null => ScalarValue::Null,
+        }
+    }
+}
+
+/// An [`OpObserver`] which records every operation it observes into a [`Trace`].
+///
+/// Attach it to a document with [`AutoCommit::with_observer`] and take the trace with
+/// [`Self::take_trace`] once the session is over.
+#[derive(Debug, Clone, Default)]
+pub struct TraceRecorder {
+    ops: Vec<TraceOp>,
+}
+
+impl TraceRecorder {
+    /// Take the trace recorded so far, leaving the recorder empty.
+    pub fn take_trace(&mut self) -> Trace {
+        Trace {
+            ops: std::mem::take(&mut self.ops),
+        }
+    }
+}
+
+impl OpObserver for TraceRecorder {
+    fn insert(
+        &mut self,
+        _parents: Parents<'_>,
+        obj: ObjId,
+        index: usize,
+        (value, id): (Value<'_>, ObjId),
+    ) {
+        self.ops.push(TraceOp::Insert {
+            obj: obj.to_string(),
+            index,
+            value: TraceValue::new(value, &id),
+        });
+    }
+
+    fn splice_text(&mut self, _parents: Parents<'_>, obj: ObjId, index: usize, text: &str) {
+        self.ops.push(TraceOp::SpliceText {
+            obj: obj.to_string(),
+            index,
+            text: text.to_string(),
+        });
+    }
+
+    fn put(
+        &mut self,
+        _parents: Parents<'_>,
+        obj: ObjId,
+        prop: Prop,
+        (value, id): (Value<'_>, ObjId),
+        _conflict: bool,
+    ) {
+        self.ops.push(TraceOp::Put {
+            obj: obj.to_string(),
+            prop: prop.into(),
+            value: TraceValue::new(value, &id),
+        });
+    }
+
+    fn increment(&mut self, _parents: Parents<'_>, obj: ObjId, prop: Prop, (by, _): (i64, ObjId)) {
+        self.ops.push(TraceOp::Increment {
+            obj: obj.to_string(),
+            prop: prop.into(),
+            by,
+        });
+    }
+
+    fn delete(&mut self, _parents: Parents<'_>, obj: ObjId, prop: Prop) {
+        self.ops.push(TraceOp::Delete {
+            obj: obj.to_string(),
+            prop: prop.into(),
+        });
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.ops.extend_from_slice(&other.ops);
+        if !other.ops.is_empty() {
+            self.ops.push(TraceOp::Commit);
+        }
+    }
+
+    fn source(&mut self, source: Option<&str>) {
+        self.ops.push(TraceOp::Source {
+            source: source.map(String::from),
+        });
+    }
+}
+
+/// Timings from replaying a [`Trace`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayStats {
+    /// The number of operations applied.
+    pub ops: usize,
+    /// The number of transactions committed.
+    pub commits: usize,
+    /// The total time spent applying operations and committing.
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("trace refers to unknown object {0}")]
+    UnknownObject(String),
+    #[error(transparent)]
+    Automerge(#[from] AutomergeError),
+}
+
+impl Trace {
+    pub fn to_json(&self) -> String {
+        // SAFETY: the trace contains only types which serialize infallibly
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Apply every operation in the trace to `doc`, committing at each recorded transaction
+    /// boundary and once more at the end.
+    pub fn replay(&self, doc: &mut AutoCommit) -> Result<ReplayStats, ReplayError> {
+        let mut objects: HashMap<String, ObjId> = HashMap::new();
+        objects.insert(ObjId::Root.to_string(), ObjId::Root);
+        let lookup = |objects: &HashMap<String, ObjId>, obj: &str| {
+            objects
+                .get(obj)
+                .cloned()
+                .ok_or_else(|| ReplayError::UnknownObject(obj.to_string()))
+        };
+        let mut stats = ReplayStats::default();
+        let start = Instant::now();
+        for op in &self.ops {
+            match op {
+                TraceOp::Put { obj, prop, value } => {
+                    let obj = lookup(&objects, obj)?;
+                    if let TraceValue::Object { obj_type, id } = value {
+                        let new_obj = doc.put_object(&obj, prop, (*obj_type).into())?;
+                        objects.insert(id.clone(), new_obj);
+                    } else {
+                        doc.put(&obj, prop, value.scalar())?;
+                    }
+                }
+                TraceOp::Insert { obj, index, value } => {
+                    let obj = lookup(&objects, obj)?;
+                    if let TraceValue::Object { obj_type, id } = value {
+                        let new_obj = doc.insert_object(&obj, *index, (*obj_type).into())?;
+                        objects.insert(id.clone(), new_obj);
+                    } else {
+                        doc.insert(&obj, *index, value.scalar())?;
+                    }
+                }
+                TraceOp::SpliceText { obj, index, text } => {
+                    doc.splice_text(lookup(&objects, obj)?, *index, 0, text)?;
+                    stats.ops += text.chars().count();
+                    continue;
+                }
+                TraceOp::Increment { obj, prop, by } => {
+                    doc.increment(lookup(&objects, obj)?, prop, *by)?;
+                }
+                TraceOp::Delete { obj, prop } => {
+                    doc.delete(lookup(&objects, obj)?, prop)?;
+                }
+                TraceOp::Source { source } => {
+                    doc.set_source(source.as_deref());
+                    continue;
+                }
+                TraceOp::Commit => {
+                    doc.commit();
+                    stats.commits += 1;
+                    continue;
+                }
+            }
+            stats.ops += 1;
+        }
+        if doc.pending_ops() > 0 {
+            doc.commit();
+            stats.commits += 1;
+        }
+        stats.elapsed = start.elapsed();
+        Ok(stats)
+    }
+}
@@ -153,6 +153,7 @@ export class Automerge {
   push(obj: ObjID, value: Value, datatype?: Datatype): void;
   pushObject(obj: ObjID, value: ObjType): ObjID;
   splice(obj: ObjID, start: number, delete_count: number, text?: string | Array<Value>): ObjID[] | undefined;
+  updateText(obj: ObjID, newText: string): void;
   increment(obj: ObjID, prop: Prop, value: number): void;
   delete(obj: ObjID, prop: Prop): void;
 
@@ -183,15 +183,15 @@ impl TryFrom<JS> for am::sync::State {
             .0
             .as_bool()
             .ok_or_else(|| JsValue::from_str("SyncState.inFLight must be a boolean"))?;
-        Ok(am::sync::State {
-            shared_heads,
-            last_sent_heads,
-            their_heads,
-            their_need,
-            their_have,
-            sent_hashes,
-            in_flight,
-        })
+        let mut state = am::sync::State::new();
+        state.shared_heads = shared_heads;
+        state.last_sent_heads = last_sent_heads;
+        state.their_heads = their_heads;
+        state.their_need = their_need;
+        state.their_have = their_have;
+        state.sent_hashes = sent_hashes;
+        state.in_flight = in_flight;
+        Ok(state)
     }
 }
 
@@ -565,6 +565,17 @@ impl Automerge {
             }
             Patch::DeleteSeq { index, .. } => self.sub_splice(result, *index, 1, vec![], meta),
             Patch::Insert { index, values, .. } => self.sub_splice(result, *index, 0, values, meta),
+            Patch::SpliceText { index, value, .. } => {
+                let args: Array = value
+                    .chars()
+                    .map(|c| JsValue::from(c.to_string()))
+                    .collect();
+                args.unshift(&0.into());
+                args.unshift(&(*index as u32).into());
+                let method = Reflect::get(&result, &"splice".into())?.dyn_into::<Function>()?;
+                Reflect::apply(&method, &result, &args)?;
+                Ok(result.into())
+            }
             Patch::Increment { prop, value, .. } => {
                 if let Prop::Seq(index) = prop {
                     let index = (*index as f64).into();
@@ -621,7 +632,9 @@ impl Automerge {
                     Err(to_js_err("cant increment an index on a map"))
                 }
             }
-            Patch::Insert { .. } => Err(to_js_err("cannot insert into map")),
+            Patch::Insert { .. } | Patch::SpliceText { .. } => {
+                Err(to_js_err("cannot insert into map"))
+            }
             Patch::DeleteSeq { .. } => Err(to_js_err("cannot splice a map")),
             Patch::PutSeq { .. } => Err(to_js_err("cannot array index a map")),
         }
@@ -197,6 +197,13 @@ impl Automerge {
         Ok(())
     }
 
+    #[wasm_bindgen(js_name = updateText)]
+    pub fn update_text(&mut self, obj: JsValue, new_text: String) -> Result<(), JsValue> {
+        let obj = self.import(obj)?;
+        self.doc.update_text(&obj, &new_text)?;
+        Ok(())
+    }
+
     pub fn push(&mut self, obj: JsValue, value: JsValue, datatype: JsValue) -> Result<(), JsValue> {
         let obj = self.import(obj)?;
         let value = self
@@ -842,14 +849,9 @@ pub fn encode_sync_message(message: JsValue) -> Result<Uint8Array, JsValue> {
     let changes = js_get(&message, "changes")?.try_into()?;
     let have = js_get(&message, "have")?.try_into()?;
     Ok(Uint8Array::from(
-        am::sync::Message {
-            heads,
-            need,
-            have,
-            changes,
-        }
-        .encode()
-        .as_slice(),
+        am::sync::Message::new(heads, need, have, changes)
+            .encode()
+            .as_slice(),
     ))
 }
 
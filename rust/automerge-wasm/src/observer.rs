@@ -47,6 +47,12 @@ pub(crate) enum Patch {
         index: usize,
         values: SequenceTree<(Value<'static>, ObjId)>,
     },
+    SpliceText {
+        obj: ObjId,
+        path: Vec<(ObjId, Prop)>,
+        index: usize,
+        value: String,
+    },
     Increment {
         obj: ObjId,
         path: Vec<(ObjId, Prop)>,
@@ -103,21 +109,61 @@ impl OpObserver for Observer {
         }
     }
 
-    fn delete(&mut self, mut parents: Parents<'_>, obj: ObjId, prop: Prop) {
+    fn splice_text(&mut self, mut parents: Parents<'_>, obj: ObjId, index: usize, value: &str) {
         if self.enabled {
-            if let Some(Patch::Insert {
+            if let Some(Patch::SpliceText {
                 obj: tail_obj,
                 index: tail_index,
-                values,
+                value: tail_value,
                 ..
             }) = self.patches.last_mut()
             {
-                if let Prop::Seq(index) = prop {
-                    let range = *tail_index..*tail_index + values.len();
-                    if tail_obj == &obj && range.contains(&index) {
-                        values.remove(index - *tail_index);
-                        return;
+                let range = *tail_index..=*tail_index + tail_value.chars().count();
+                if tail_obj == &obj && range.contains(&index) {
+                    let offset = char_offset(tail_value, index - *tail_index);
+                    tail_value.insert_str(offset, value);
+                    return;
+                }
+            }
+            let path = parents.path();
+            self.patches.push(Patch::SpliceText {
+                path,
+                obj,
+                index,
+                value: value.to_string(),
+            });
+        }
+    }
+
+    fn delete(&mut self, mut parents: Parents<'_>, obj: ObjId, prop: Prop) {
+        if self.enabled {
+            if let Prop::Seq(index) = prop {
+                match self.patches.last_mut() {
+                    Some(Patch::Insert {
+                        obj: tail_obj,
+                        index: tail_index,
+                        values,
+                        ..
+                    }) => {
+                        let range = *tail_index..*tail_index + values.len();
+                        if tail_obj == &obj && range.contains(&index) {
+                            values.remove(index - *tail_index);
+                            return;
+                        }
                     }
+                    Some(Patch::SpliceText {
+                        obj: tail_obj,
+                        index: tail_index,
+                        value,
+                        ..
+                    }) => {
+                        let range = *tail_index..*tail_index + value.chars().count();
+                        if tail_obj == &obj && range.contains(&index) {
+                            value.remove(char_offset(value, index - *tail_index));
+                            return;
+                        }
+                    }
+                    _ => {}
                 }
             }
             let path = parents.path();
@@ -196,6 +242,11 @@ impl OpObserver for Observer {
     }
 }
 
+/// The byte offset of the `n`th char of `s`, or its length if it has `n` chars.
+fn char_offset(s: &str, n: usize) -> usize {
+    s.char_indices().nth(n).map_or(s.len(), |(i, _)| i)
+}
+
 fn prop_to_js(p: &Prop) -> JsValue {
     match p {
         Prop::Map(key) => JsValue::from_str(key),
@@ -219,6 +270,7 @@ impl Patch {
             Self::PutSeq { path, .. } => path.as_slice(),
             Self::Increment { path, .. } => path.as_slice(),
             Self::Insert { path, .. } => path.as_slice(),
+            Self::SpliceText { path, .. } => path.as_slice(),
             Self::DeleteMap { path, .. } => path.as_slice(),
             Self::DeleteSeq { path, .. } => path.as_slice(),
         }
@@ -230,6 +282,7 @@ impl Patch {
             Self::PutSeq { obj, .. } => obj,
             Self::Increment { obj, .. } => obj,
             Self::Insert { obj, .. } => obj,
+            Self::SpliceText { obj, .. } => obj,
             Self::DeleteMap { obj, .. } => obj,
             Self::DeleteSeq { obj, .. } => obj,
         }
@@ -295,6 +348,18 @@ impl TryFrom<Patch> for JsValue {
                 )?;
                 Ok(result.into())
             }
+            Patch::SpliceText {
+                path, index, value, ..
+            } => {
+                js_set(&result, "action", "splice")?;
+                js_set(
+                    &result,
+                    "path",
+                    export_path(path.as_slice(), &Prop::Seq(index)),
+                )?;
+                js_set(&result, "value", value)?;
+                Ok(result.into())
+            }
             Patch::Increment {
                 path, prop, value, ..
             } => {
@@ -230,6 +230,36 @@ describe('Automerge', () => {
       assert.deepEqual(doc.getWithType(text, 12), ["str", "?"])
     })
 
+    it('should be able to update text with a diff', () => {
+      const doc = create()
+      const text = doc.putObject("/", "text", "the quick fox")
+      const id = (index: number) => {
+        const [value] = doc.getAll(text, index)
+        return value[value.length - 1]
+      }
+      const [the, fox] = [id(0), id(10)]
+
+      // an insertion keeps the characters around it
+      doc.updateText(text, "the quick brown fox")
+      assert.deepEqual(doc.text(text), "the quick brown fox")
+      assert.deepEqual(doc.getAll(text, 16)[0], ["str", "f", fox])
+
+      // so does a deletion
+      doc.updateText(text, "the brown fox")
+      assert.deepEqual(doc.text(text), "the brown fox")
+      assert.deepEqual(doc.length(text), 13)
+      assert.deepEqual(id(0), the)
+      assert.deepEqual(id(10), fox)
+
+      // a replacement only replaces the characters which changed
+      const brown = id(4)
+      doc.updateText(text, "the brown cat")
+      assert.deepEqual(doc.text(text), "the brown cat")
+      assert.deepEqual(doc.length(text), 13)
+      assert.deepEqual(id(4), brown)
+      assert.notDeepEqual(id(10), fox)
+    })
+
     it('should be able to insert objects into text', () => {
       const doc = create()
       const text = doc.putObject("/", "text", "Hello world");
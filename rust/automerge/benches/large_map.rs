@@ -0,0 +1,60 @@
+use automerge::{transaction::Transactable, Automerge, ROOT};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// A map with a key per entity, like a dashboard keeps
+fn entities(n: u64) -> Automerge {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    for i in 0..n {
+        tx.put(ROOT, key(i), i).unwrap();
+    }
+    tx.commit();
+    doc
+}
+
+fn key(i: u64) -> String {
+    format!("entity-{:08}", i)
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let sizes = [1_000, 10_000, 100_000];
+
+    let mut group = c.benchmark_group("large map");
+    for size in &sizes {
+        let doc = entities(*size);
+        let middle = size / 2;
+
+        group.bench_with_input(BenchmarkId::new("get", size), &doc, |b, doc| {
+            b.iter(|| doc.get(ROOT, key(middle)).unwrap())
+        });
+
+        group.bench_with_input(BenchmarkId::new("narrow range", size), &doc, |b, doc| {
+            b.iter(|| doc.map_range(ROOT, key(middle)..key(middle + 10)).count())
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("narrow range rev", size),
+            &doc,
+            |b, doc| {
+                b.iter(|| {
+                    doc.map_range(ROOT, key(middle)..key(middle + 10))
+                        .rev()
+                        .count()
+                })
+            },
+        );
+
+        group.bench_with_input(BenchmarkId::new("put", size), &doc, |b, doc| {
+            let mut doc = doc.clone();
+            b.iter(|| {
+                let mut tx = doc.transaction();
+                tx.put(ROOT, key(middle), 0).unwrap();
+                tx.rollback()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,73 @@
+use automerge::{transaction::Transactable, AutoCommit, ObjId, ObjType, ROOT};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// A map with `n` integer keys
+fn large_map(n: usize) -> (AutoCommit, ObjId) {
+    let mut doc = AutoCommit::new();
+    let map = doc.put_object(ROOT, "map", ObjType::Map).unwrap();
+    for i in 0..n {
+        doc.put(&map, format!("key {}", i), i as i64).unwrap();
+    }
+    doc.commit();
+    (doc, map)
+}
+
+/// `count` pseudo random keys of a map of `n` keys, drawn from the first `hot` of them
+fn random_keys(n: usize, hot: usize, count: usize) -> Vec<String> {
+    let mut seed: u64 = 1;
+    (0..count)
+        .map(|_| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            format!("key {}", (seed >> 33) as usize % hot.min(n))
+        })
+        .collect()
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lookup cache");
+    group.sample_size(10);
+    let size = 100_000;
+    let (doc, map) = large_map(size);
+    // a working set which fits the default capacity, and one which keeps evicting
+    for hot in [100, 10_000] {
+        let keys = random_keys(size, hot, 10_000);
+        group.throughput(criterion::Throughput::Elements(keys.len() as u64));
+        for capacity in [0, 256] {
+            let mut doc = doc.clone();
+            doc.set_cache_capacity(capacity);
+            group.bench_with_input(
+                BenchmarkId::new(format!("get, capacity {}", capacity), hot),
+                &keys,
+                |b, keys| {
+                    b.iter(|| {
+                        for key in keys {
+                            doc.get(&map, key.as_str()).unwrap();
+                        }
+                    })
+                },
+            );
+            group.bench_with_input(
+                BenchmarkId::new(format!("put and get, capacity {}", capacity), hot),
+                &keys,
+                |b, keys| {
+                    b.iter_batched(
+                        || doc.clone(),
+                        |mut doc| {
+                            for key in keys {
+                                doc.put(&map, key.as_str(), 1).unwrap();
+                                doc.get(&map, key.as_str()).unwrap();
+                            }
+                        },
+                        criterion::BatchSize::LargeInput,
+                    )
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,86 @@
+use automerge::{transaction::Transactable, AutoCommit, NodeSize, ObjType, ROOT};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const NODE_SIZES: [NodeSize; 5] = [
+    NodeSize::Fixed(8),
+    NodeSize::Fixed(16),
+    NodeSize::Fixed(32),
+    NodeSize::Fixed(64),
+    NodeSize::Adaptive,
+];
+
+fn label(node_size: &NodeSize) -> String {
+    match node_size {
+        NodeSize::Fixed(b) => format!("fixed {}", b),
+        NodeSize::Adaptive => "adaptive".to_string(),
+    }
+}
+
+/// Type `n` characters at pseudo random positions in a single text object
+fn random_typing(node_size: NodeSize, n: u64) -> AutoCommit {
+    let mut doc = AutoCommit::new();
+    doc.set_node_size(node_size).unwrap();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    let mut seed: u64 = 1;
+    for i in 0..n {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let pos = (seed >> 33) % (i + 1);
+        doc.splice_text(&text, pos as usize, 0, "a").unwrap();
+    }
+    doc.commit();
+    doc
+}
+
+/// Create `n` lists of ten elements each
+fn many_small_lists(node_size: NodeSize, n: u64) -> AutoCommit {
+    let mut doc = AutoCommit::new();
+    doc.set_node_size(node_size).unwrap();
+    let outer = doc.put_object(ROOT, "lists", ObjType::List).unwrap();
+    for i in 0..n {
+        let list = doc
+            .insert_object(&outer, i as usize, ObjType::List)
+            .unwrap();
+        for j in 0..10 {
+            doc.insert(&list, j, j as i64).unwrap();
+        }
+    }
+    doc.commit();
+    doc
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("node size random typing");
+    for size in [1_000, 10_000, 100_000] {
+        group.throughput(criterion::Throughput::Elements(size));
+        for node_size in &NODE_SIZES {
+            group.bench_with_input(
+                BenchmarkId::new(label(node_size), size),
+                &size,
+                |b, &size| b.iter(|| random_typing(*node_size, size)),
+            );
+        }
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("node size small lists");
+    for size in [100, 1_000] {
+        group.throughput(criterion::Throughput::Elements(size * 10));
+        for node_size in &NODE_SIZES {
+            group.bench_with_input(
+                BenchmarkId::new(label(node_size), size),
+                &size,
+                |b, &size| b.iter(|| many_small_lists(*node_size, size)),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = criterion_benchmark
+}
+criterion_main!(benches);
@@ -0,0 +1,63 @@
+use automerge::{transaction::Transactable, AutoCommit, ObjId, ObjType, ROOT};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// A list of `n` integers
+fn long_list(n: usize) -> (AutoCommit, ObjId) {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..n {
+        doc.insert(&list, i, i as i64).unwrap();
+    }
+    doc.commit();
+    (doc, list)
+}
+
+/// `count` pseudo random indexes less than `n`
+fn random_indexes(n: usize, count: usize) -> Vec<usize> {
+    let mut seed: u64 = 1;
+    (0..count)
+        .map(|_| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n
+        })
+        .collect()
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("seq index");
+    group.sample_size(10);
+    for size in [10_000, 100_000, 1_000_000] {
+        let (doc, list) = long_list(size);
+        let indexes = random_indexes(size, 1000);
+        group.throughput(criterion::Throughput::Elements(indexes.len() as u64));
+        group.bench_with_input(BenchmarkId::new("random get", size), &indexes, |b, i| {
+            b.iter(|| {
+                for index in i {
+                    doc.get(&list, *index).unwrap();
+                }
+            })
+        });
+        group.bench_with_input(
+            BenchmarkId::new("random put and get", size),
+            &indexes,
+            |b, i| {
+                b.iter_batched(
+                    || doc.clone(),
+                    |mut doc| {
+                        for index in i {
+                            doc.put(&list, *index, 1).unwrap();
+                            doc.get(&list, *index).unwrap();
+                        }
+                    },
+                    criterion::BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
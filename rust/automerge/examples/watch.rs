@@ -83,6 +83,37 @@ fn get_changes(doc: &Automerge, patches: Vec<Patch>) {
                 obj,
                 doc.path_to_object(&obj)
             ),
+            Patch::Splice {
+                obj, index, values, ..
+            } => {
+                println!(
+                    "splice {:?} at {:?} in obj {:?}, object path {:?}",
+                    values,
+                    index,
+                    obj,
+                    doc.path_to_object(&obj)
+                )
+            }
+            Patch::SpliceText {
+                obj, index, value, ..
+            } => {
+                println!(
+                    "splice {:?} at {:?} in text {:?}, object path {:?}",
+                    value,
+                    index,
+                    obj,
+                    doc.path_to_object(&obj)
+                )
+            }
+            Patch::DeleteRange {
+                obj, index, length, ..
+            } => println!(
+                "delete {:?} elements at {:?} in obj {:?}, object path {:?}",
+                length,
+                index,
+                obj,
+                doc.path_to_object(&obj)
+            ),
         }
     }
 }
@@ -0,0 +1,878 @@
+//! A flat `extern "C"` API for hosts which cannot use `wasm-bindgen` glue.
+//!
+//! This is enabled by the `wasm-abi` feature and is intended for WASM hosts such as Wasmtime
+//! plugins or edge runtimes which load a module and call its exports directly. Build a module
+//! with e.g.
+//!
+//! ```text
+//! cargo rustc -p automerge --release --features wasm-abi --target wasm32-wasi --crate-type cdylib
+//! ```
+//!
+//! Every function takes and returns integers and pointers only. Documents and sync states are
+//! passed around as opaque pointers. Byte buffers passed in are borrowed for the duration of the
+//! call; the host writes them into memory obtained from [`automerge_alloc`]. Byte buffers returned
+//! by the API are owned by the host, which must release them with [`automerge_free`].
+//!
+//! Functions which can fail return a null pointer or a negative number on failure, the message of
+//! the most recent error is available from [`automerge_last_error`].
+//!
+//! Objects are named by the strings returned by [`crate::ObjId`]'s `Display`, `_root` for the
+//! root. A property is a map key when `key` is not null, and the list index `index` otherwise.
+//! Values are read and written as a tag byte followed by the value:
+//!
+//! | tag | value                          |
+//! |-----|--------------------------------|
+//! | 0   | null, no bytes                 |
+//! | 1   | boolean, one byte of 0 or 1    |
+//! | 2   | int, 8 bytes little endian     |
+//! | 3   | uint, 8 bytes little endian    |
+//! | 4   | f64, 8 bytes little endian     |
+//! | 5   | string, UTF-8                  |
+//! | 6   | bytes                          |
+//! | 7   | counter, 8 bytes little endian |
+//! | 8   | timestamp, as for int          |
+//! | 9   | map, followed by its id        |
+//! | 10  | list, followed by its id       |
+//! | 11  | text, followed by its id       |
+//! | 12  | table, followed by its id      |
+//!
+//! Objects are created with [`automerge_put_object`]. Each function which writes to a document
+//! commits its own change.
+//!
+//! Documents are passed as plain pointers, which are `i32`s on `wasm32`, rather than as
+//! `externref`s: a Rust function can only take or return an `externref` with `wasm-bindgen`,
+//! which this API avoids. A host which wants to hand documents around as references can keep
+//! the pointers in a table of its own.
+use std::cell::RefCell;
+use std::ptr;
+
+use crate::exid::ExId;
+use crate::sync;
+use crate::transaction::Transactable;
+use crate::{Automerge, ObjType, Prop, ScalarValue, Value};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+}
+
+fn set_error<E: std::fmt::Display>(e: E) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = e.to_string().into_bytes());
+}
+
+/// Hand ownership of `bytes` to the host, writing the length to `out_len`.
+unsafe fn give(bytes: Vec<u8>, out_len: *mut usize) -> *mut u8 {
+    let bytes = bytes.into_boxed_slice();
+    *out_len = bytes.len();
+    Box::into_raw(bytes) as *mut u8
+}
+
+/// Borrow `len` bytes at `ptr`, which may be null if `len` is zero.
+unsafe fn borrow<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(ptr, len)
+    }
+}
+
+/// Read the object named by the `len` bytes at `ptr`.
+unsafe fn object(doc: &Automerge, ptr: *const u8, len: usize) -> Result<ExId, String> {
+    let id = std::str::from_utf8(borrow(ptr, len)).map_err(|e| e.to_string())?;
+    doc.import(id).map_err(|e| e.to_string())
+}
+
+/// The map key `key` if it isn't null, otherwise the list index `index`.
+unsafe fn prop(key: *const u8, key_len: usize, index: usize) -> Result<Prop, String> {
+    if key.is_null() {
+        Ok(Prop::Seq(index))
+    } else {
+        let key = std::str::from_utf8(borrow(key, key_len)).map_err(|e| e.to_string())?;
+        Ok(Prop::Map(key.to_string()))
+    }
+}
+
+/// The tag of the objects of type `obj_type`.
+fn object_tag(obj_type: ObjType) -> u8 {
+    match obj_type {
+        ObjType::Map => 9,
+        ObjType::List => 10,
+        ObjType::Text => 11,
+        ObjType::Table => 12,
+    }
+}
+
+fn encode_value(value: &Value<'_>, id: &ExId) -> Vec<u8> {
+    let scalar = match value {
+        Value::Object(obj_type) => {
+            let mut bytes = vec![object_tag(*obj_type)];
+            bytes.extend(id.to_string().into_bytes());
+            return bytes;
+        }
+        Value::Scalar(s) => s.as_ref(),
+    };
+    let (tag, payload) = match scalar {
+        ScalarValue::Null => (0, Vec::new()),
+        ScalarValue::Boolean(b) => (1, vec![u8::from(*b)]),
+        ScalarValue::Int(i) => (2, i.to_le_bytes().to_vec()),
+        ScalarValue::Uint(u) => (3, u.to_le_bytes().to_vec()),
+        ScalarValue::F64(f) => (4, f.to_le_bytes().to_vec()),
+        ScalarValue::Str(s) => (5, s.as_bytes().to_vec()),
+        ScalarValue::Bytes(b) => (6, b.clone()),
+        ScalarValue::Counter(c) => (7, i64::from(c).to_le_bytes().to_vec()),
+        ScalarValue::Timestamp(t) => (8, t.to_le_bytes().to_vec()),
+        ScalarValue::Unknown { bytes, .. } => (6, bytes.clone()),
+    };
+    let mut bytes = vec![tag];
+    bytes.extend(payload);
+    bytes
+}
+
+fn decode_scalar(bytes: &[u8]) -> Result<ScalarValue, String> {
+    let (tag, payload) = bytes
+        .split_first()
+        .ok_or_else(|| "empty value".to_string())?;
+    let word = || -> Result<[u8; 8], String> {
+        payload
+            .try_into()
+            .map_err(|_| format!("expected 8 bytes for tag {}", tag))
+    };
+    Ok(match tag {
+        0 if payload.is_empty() => ScalarValue::Null,
+        1 if payload == [0] || payload == [1] => ScalarValue::Boolean(payload[0] == 1),
+        2 => ScalarValue::Int(i64::from_le_bytes(word()?)),
+        3 => ScalarValue::Uint(u64::from_le_bytes(word()?)),
+        4 => ScalarValue::F64(f64::from_le_bytes(word()?)),
+        5 => ScalarValue::Str(
+            std::str::from_utf8(payload)
+                .map_err(|e| e.to_string())?
+                .into(),
+        ),
+        6 => ScalarValue::Bytes(payload.to_vec()),
+        7 => ScalarValue::counter(i64::from_le_bytes(word()?)),
+        8 => ScalarValue::Timestamp(i64::from_le_bytes(word()?)),
+        _ => return Err(format!("invalid value with tag {}", tag)),
+    })
+}
+
+/// Report the result of a write as 0 on success or -1 on failure.
+fn status<T, E: std::fmt::Display>(result: Result<T, E>) -> i32 {
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+/// Allocate a zeroed buffer of `len` bytes for the host to write input into.
+///
+/// The buffer must be released with [`automerge_free`].
+#[no_mangle]
+pub extern "C" fn automerge_alloc(len: usize) -> *mut u8 {
+    Box::into_raw(vec![0; len].into_boxed_slice()) as *mut u8
+}
+
+/// Release a buffer returned by [`automerge_alloc`] or any of the functions returning bytes.
+///
+/// # Safety
+///
+/// `ptr` and `len` must describe a buffer previously returned by this API which has not already
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_free(ptr: *mut u8, len: usize) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)));
+    }
+}
+
+/// The message of the most recent error on this thread, valid until the next call into the API.
+///
+/// # Safety
+///
+/// `out_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_last_error(out_len: *mut usize) -> *const u8 {
+    LAST_ERROR.with(|last| {
+        let last = last.borrow();
+        *out_len = last.len();
+        last.as_ptr()
+    })
+}
+
+/// Create a new, empty document.
+#[no_mangle]
+pub extern "C" fn automerge_create() -> *mut Automerge {
+    Box::into_raw(Box::new(Automerge::new()))
+}
+
+/// Load a document from the output of [`automerge_save`], returning null on failure.
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_load(data: *const u8, len: usize) -> *mut Automerge {
+    match Automerge::load(borrow(data, len)) {
+        Ok(doc) => Box::into_raw(Box::new(doc)),
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Release a document.
+///
+/// # Safety
+///
+/// `doc` must have been returned by this API and not already been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_destroy(doc: *mut Automerge) {
+    if !doc.is_null() {
+        drop(Box::from_raw(doc));
+    }
+}
+
+/// Save the whole document.
+///
+/// # Safety
+///
+/// `doc` must be a live document and `out_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_save(doc: *mut Automerge, out_len: *mut usize) -> *mut u8 {
+    give((*doc).save(), out_len)
+}
+
+/// Save the changes made since the last call to [`automerge_save`] or this function.
+///
+/// # Safety
+///
+/// `doc` must be a live document and `out_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_save_incremental(
+    doc: *mut Automerge,
+    out_len: *mut usize,
+) -> *mut u8 {
+    give((*doc).save_incremental(), out_len)
+}
+
+/// Apply saved changes or documents to `doc`, returning the number of ops applied or -1 on
+/// failure.
+///
+/// # Safety
+///
+/// `doc` must be a live document and `data` must be valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_load_incremental(
+    doc: *mut Automerge,
+    data: *const u8,
+    len: usize,
+) -> i64 {
+    match (*doc).load_incremental(borrow(data, len)) {
+        Ok(n) => n as i64,
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+/// Merge the changes in `other` into `doc`, returning 0 on success or -1 on failure.
+///
+/// # Safety
+///
+/// `doc` and `other` must be distinct live documents.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_merge(doc: *mut Automerge, other: *mut Automerge) -> i32 {
+    match (*doc).merge(&mut *other) {
+        Ok(_) => 0,
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+/// The heads of the document, as the concatenation of 32 byte hashes.
+///
+/// # Safety
+///
+/// `doc` must be a live document and `out_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_get_heads(doc: *mut Automerge, out_len: *mut usize) -> *mut u8 {
+    let heads = (*doc).get_heads();
+    give(heads.iter().flat_map(|h| h.0).collect(), out_len)
+}
+
+/// Create a new sync state.
+#[no_mangle]
+pub extern "C" fn automerge_sync_state_create() -> *mut sync::State {
+    Box::into_raw(Box::new(sync::State::new()))
+}
+
+/// Release a sync state.
+///
+/// # Safety
+///
+/// `state` must have been returned by this API and not already been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_sync_state_destroy(state: *mut sync::State) {
+    if !state.is_null() {
+        drop(Box::from_raw(state));
+    }
+}
+
+/// Generate the next sync message for the peer, returning null if there is nothing to send.
+///
+/// # Safety
+///
+/// `doc` and `state` must be live and `out_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_generate_sync_message(
+    doc: *mut Automerge,
+    state: *mut sync::State,
+    out_len: *mut usize,
+) -> *mut u8 {
+    match (*doc).generate_sync_message(&mut *state) {
+        Some(message) => give(message.encode(), out_len),
+        None => {
+            *out_len = 0;
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Receive a sync message from the peer, returning 0 on success or -1 on failure.
+///
+/// # Safety
+///
+/// `doc` and `state` must be live and `data` must be valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_receive_sync_message(
+    doc: *mut Automerge,
+    state: *mut sync::State,
+    data: *const u8,
+    len: usize,
+) -> i32 {
+    let message = match sync::Message::decode(borrow(data, len)) {
+        Ok(m) => m,
+        Err(e) => {
+            set_error(e);
+            return -1;
+        }
+    };
+    match (*doc).receive_sync_message(&mut *state, message) {
+        Ok(()) => 0,
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+/// Read the value of a property of `obj`, see the [module documentation](self) for how values
+/// are encoded. When there are conflicting values this is the one [`Automerge::get`] returns.
+///
+/// Returns 1 and writes the value to `out` and `out_len` if there is a value, 0 if there is
+/// none, or -1 on failure.
+///
+/// # Safety
+///
+/// `doc` must be a live document, `obj` and `key` must be valid for reads of `obj_len` and
+/// `key_len` bytes, and `out` and `out_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_get(
+    doc: *mut Automerge,
+    obj: *const u8,
+    obj_len: usize,
+    key: *const u8,
+    key_len: usize,
+    index: usize,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    let value = object(&*doc, obj, obj_len)
+        .and_then(|obj| Ok((obj, prop(key, key_len, index)?)))
+        .and_then(|(obj, prop)| (*doc).get(obj, prop).map_err(|e| e.to_string()));
+    match value {
+        Ok(Some((value, id))) => {
+            *out = give(encode_value(&value, &id), out_len);
+            1
+        }
+        Ok(None) => {
+            *out = ptr::null_mut();
+            *out_len = 0;
+            0
+        }
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+/// Put the encoded scalar `value` at a property of `obj`, returning 0 on success or -1 on
+/// failure.
+///
+/// # Safety
+///
+/// `doc` must be a live document and `obj`, `key` and `value` must be valid for reads of
+/// `obj_len`, `key_len` and `value_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_put(
+    doc: *mut Automerge,
+    obj: *const u8,
+    obj_len: usize,
+    key: *const u8,
+    key_len: usize,
+    index: usize,
+    value: *const u8,
+    value_len: usize,
+) -> i32 {
+    status((|| {
+        let obj = object(&*doc, obj, obj_len)?;
+        let prop = prop(key, key_len, index)?;
+        let value = decode_scalar(borrow(value, value_len))?;
+        let mut tx = (*doc).transaction();
+        tx.put(obj, prop, value).map_err(|e| e.to_string())?;
+        tx.commit();
+        Ok::<_, String>(())
+    })())
+}
+
+/// Insert the encoded scalar `value` at `index` of the list `obj`, returning 0 on success or -1
+/// on failure.
+///
+/// # Safety
+///
+/// `doc` must be a live document and `obj` and `value` must be valid for reads of `obj_len` and
+/// `value_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_insert(
+    doc: *mut Automerge,
+    obj: *const u8,
+    obj_len: usize,
+    index: usize,
+    value: *const u8,
+    value_len: usize,
+) -> i32 {
+    status((|| {
+        let obj = object(&*doc, obj, obj_len)?;
+        let value = decode_scalar(borrow(value, value_len))?;
+        let mut tx = (*doc).transaction();
+        tx.insert(obj, index, value).map_err(|e| e.to_string())?;
+        tx.commit();
+        Ok::<_, String>(())
+    })())
+}
+
+/// Put a new, empty object at a property of `obj`, where `tag` is the tag of its type, returning
+/// the id of the new object or null on failure.
+///
+/// # Safety
+///
+/// `doc` must be a live document, `obj` and `key` must be valid for reads of `obj_len` and
+/// `key_len` bytes, and `out_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_put_object(
+    doc: *mut Automerge,
+    obj: *const u8,
+    obj_len: usize,
+    key: *const u8,
+    key_len: usize,
+    index: usize,
+    tag: u8,
+    out_len: *mut usize,
+) -> *mut u8 {
+    let result = (|| {
+        let obj = object(&*doc, obj, obj_len)?;
+        let prop = prop(key, key_len, index)?;
+        let obj_type = [ObjType::Map, ObjType::List, ObjType::Text, ObjType::Table]
+            .into_iter()
+            .find(|t| object_tag(*t) == tag)
+            .ok_or_else(|| format!("invalid object type with tag {}", tag))?;
+        let mut tx = (*doc).transaction();
+        let id = tx
+            .put_object(obj, prop, obj_type)
+            .map_err(|e| e.to_string())?;
+        tx.commit();
+        Ok::<_, String>(id)
+    })();
+    match result {
+        Ok(id) => give(id.to_string().into_bytes(), out_len),
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Delete a property of `obj`, returning 0 on success or -1 on failure.
+///
+/// # Safety
+///
+/// `doc` must be a live document and `obj` and `key` must be valid for reads of `obj_len` and
+/// `key_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_delete(
+    doc: *mut Automerge,
+    obj: *const u8,
+    obj_len: usize,
+    key: *const u8,
+    key_len: usize,
+    index: usize,
+) -> i32 {
+    status((|| {
+        let obj = object(&*doc, obj, obj_len)?;
+        let prop = prop(key, key_len, index)?;
+        let mut tx = (*doc).transaction();
+        tx.delete(obj, prop).map_err(|e| e.to_string())?;
+        tx.commit();
+        Ok::<_, String>(())
+    })())
+}
+
+/// Create a document from UTF-8 encoded JSON, returning null on failure.
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `len` bytes.
+#[cfg(feature = "serde_json")]
+#[no_mangle]
+pub unsafe extern "C" fn automerge_from_json(data: *const u8, len: usize) -> *mut Automerge {
+    let json = match serde_json::from_slice(borrow(data, len)) {
+        Ok(json) => json,
+        Err(e) => {
+            set_error(e);
+            return ptr::null_mut();
+        }
+    };
+    match Automerge::from_json(&json) {
+        Ok(doc) => Box::into_raw(Box::new(doc)),
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// The current state of the document as UTF-8 encoded JSON.
+///
+/// # Safety
+///
+/// `doc` must be a live document and `out_len` must be valid for writes.
+#[cfg(feature = "serde_json")]
+#[no_mangle]
+pub unsafe extern "C" fn automerge_to_json(doc: *mut Automerge, out_len: *mut usize) -> *mut u8 {
+    give((*doc).to_json().to_string().into_bytes(), out_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::Transactable;
+
+    unsafe fn take(ptr: *mut u8, len: usize) -> Vec<u8> {
+        let bytes = borrow(ptr, len).to_vec();
+        automerge_free(ptr, len);
+        bytes
+    }
+
+    #[test]
+    fn save_load_and_sync() {
+        unsafe {
+            let mut source = Automerge::new();
+            let mut tx = source.transaction();
+            tx.put(crate::ROOT, "hello", "world").unwrap();
+            tx.commit();
+            let saved = source.save();
+
+            let input = automerge_alloc(saved.len());
+            std::slice::from_raw_parts_mut(input, saved.len()).copy_from_slice(&saved);
+            let doc1 = automerge_load(input, saved.len());
+            automerge_free(input, saved.len());
+            assert!(!doc1.is_null());
+
+            let doc2 = automerge_create();
+            let s1 = automerge_sync_state_create();
+            let s2 = automerge_sync_state_create();
+            let mut len = 0;
+            for _ in 0..10 {
+                let msg = automerge_generate_sync_message(doc1, s1, &mut len);
+                if !msg.is_null() {
+                    let msg = take(msg, len);
+                    assert_eq!(
+                        automerge_receive_sync_message(doc2, s2, msg.as_ptr(), msg.len()),
+                        0
+                    );
+                }
+                let msg = automerge_generate_sync_message(doc2, s2, &mut len);
+                if !msg.is_null() {
+                    let msg = take(msg, len);
+                    assert_eq!(
+                        automerge_receive_sync_message(doc1, s1, msg.as_ptr(), msg.len()),
+                        0
+                    );
+                }
+            }
+            let heads = take(automerge_get_heads(doc2, &mut len), len);
+            assert_eq!(heads, source.get_heads()[0].0.to_vec());
+
+            assert_eq!(automerge_receive_sync_message(doc1, s1, ptr::null(), 0), -1);
+            let err = automerge_last_error(&mut len);
+            assert!(!borrow(err, len).is_empty());
+
+            automerge_sync_state_destroy(s1);
+            automerge_sync_state_destroy(s2);
+            automerge_destroy(doc1);
+            automerge_destroy(doc2);
+        }
+    }
+
+    /// Get `prop` of `obj`, `Some(None)` if there is no value and `None` on failure.
+    unsafe fn get(doc: *mut Automerge, obj: &str, prop: Prop) -> Option<Option<Vec<u8>>> {
+        let (key, key_len, index) = match &prop {
+            Prop::Map(key) => (key.as_ptr(), key.len(), 0),
+            Prop::Seq(index) => (ptr::null(), 0, *index),
+        };
+        let mut out = ptr::null_mut();
+        let mut len = 0;
+        match automerge_get(
+            doc,
+            obj.as_ptr(),
+            obj.len(),
+            key,
+            key_len,
+            index,
+            &mut out,
+            &mut len,
+        ) {
+            1 => Some(Some(take(out, len))),
+            0 => Some(None),
+            _ => None,
+        }
+    }
+
+    unsafe fn put(doc: *mut Automerge, obj: &str, key: &str, value: &[u8]) -> i32 {
+        automerge_put(
+            doc,
+            obj.as_ptr(),
+            obj.len(),
+            key.as_ptr(),
+            key.len(),
+            0,
+            value.as_ptr(),
+            value.len(),
+        )
+    }
+
+    fn tagged(tag: u8, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![tag];
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    unsafe fn last_error() -> String {
+        let mut len = 0;
+        let err = automerge_last_error(&mut len);
+        String::from_utf8(borrow(err, len).to_vec()).unwrap()
+    }
+
+    #[test]
+    fn get_put_and_delete_values() {
+        unsafe {
+            let doc = automerge_create();
+            let values = [
+                tagged(0, &[]),
+                tagged(1, &[1]),
+                tagged(2, &(-3_i64).to_le_bytes()),
+                tagged(3, &u64::MAX.to_le_bytes()),
+                tagged(4, &1.5_f64.to_le_bytes()),
+                tagged(5, "héllo".as_bytes()),
+                tagged(6, &[0, 255]),
+                tagged(7, &10_i64.to_le_bytes()),
+                tagged(8, &1_650_000_000_123_i64.to_le_bytes()),
+            ];
+            for (i, value) in values.iter().enumerate() {
+                assert_eq!(put(doc, "_root", &format!("k{}", i), value), 0);
+            }
+            for (i, value) in values.iter().enumerate() {
+                let key = format!("k{}", i);
+                assert_eq!(get(doc, "_root", Prop::Map(key)), Some(Some(value.clone())));
+            }
+            assert_eq!((*doc).get_int(crate::ROOT, "k2").unwrap(), Some(-3));
+            assert_eq!(get(doc, "_root", Prop::from("missing")), Some(None));
+
+            assert_eq!(
+                automerge_delete(doc, "_root".as_ptr(), 5, "k1".as_ptr(), 2, 0),
+                0
+            );
+            assert_eq!(get(doc, "_root", Prop::from("k1")), Some(None));
+            // every write is a change of its own
+            assert_eq!((*doc).get_changes(&[]).unwrap().len(), values.len() + 1);
+            automerge_destroy(doc);
+        }
+    }
+
+    #[test]
+    fn lists_and_nested_objects() {
+        unsafe {
+            let doc = automerge_create();
+            let mut len = 0;
+            let list =
+                automerge_put_object(doc, "_root".as_ptr(), 5, "l".as_ptr(), 1, 0, 10, &mut len);
+            assert!(!list.is_null());
+            let list = String::from_utf8(take(list, len)).unwrap();
+            assert_eq!(
+                get(doc, "_root", Prop::from("l")),
+                Some(Some(tagged(10, list.as_bytes())))
+            );
+
+            for (i, s) in ["a", "c"].iter().enumerate() {
+                let value = tagged(5, s.as_bytes());
+                assert_eq!(
+                    automerge_insert(
+                        doc,
+                        list.as_ptr(),
+                        list.len(),
+                        i,
+                        value.as_ptr(),
+                        value.len()
+                    ),
+                    0
+                );
+            }
+            let b = tagged(5, b"b");
+            assert_eq!(
+                automerge_insert(doc, list.as_ptr(), list.len(), 1, b.as_ptr(), b.len()),
+                0
+            );
+            let z = tagged(5, b"z");
+            assert_eq!(
+                automerge_put(
+                    doc,
+                    list.as_ptr(),
+                    list.len(),
+                    ptr::null(),
+                    0,
+                    2,
+                    z.as_ptr(),
+                    z.len()
+                ),
+                0
+            );
+            assert_eq!(
+                automerge_delete(doc, list.as_ptr(), list.len(), ptr::null(), 0, 0),
+                0
+            );
+            assert_eq!(get(doc, &list, Prop::Seq(0)), Some(Some(b.clone())));
+            assert_eq!(get(doc, &list, Prop::Seq(1)), Some(Some(z)));
+            assert_eq!(get(doc, &list, Prop::Seq(2)), Some(None));
+
+            let map = automerge_put_object(
+                doc,
+                list.as_ptr(),
+                list.len(),
+                ptr::null(),
+                0,
+                0,
+                9,
+                &mut len,
+            );
+            let map = String::from_utf8(take(map, len)).unwrap();
+            assert_eq!(put(doc, &map, "deep", &b), 0);
+            assert_eq!(get(doc, &map, Prop::from("deep")), Some(Some(b)));
+            automerge_destroy(doc);
+        }
+    }
+
+    #[test]
+    fn invalid_input_is_an_error() {
+        unsafe {
+            let doc = automerge_create();
+            let value = tagged(2, &1_i64.to_le_bytes());
+
+            assert_eq!(put(doc, "1@abc", "k", &value), -1);
+            assert!(!last_error().is_empty());
+            assert_eq!(get(doc, "not an id", Prop::from("k")), None);
+
+            for bad in [
+                vec![],
+                tagged(2, &[1, 2]),
+                tagged(1, &[2]),
+                tagged(9, b"_root"),
+                tagged(99, &[]),
+            ] {
+                assert_eq!(put(doc, "_root", "k", &bad), -1);
+            }
+            assert!(last_error().contains("99"));
+            assert_eq!(put(doc, "_root", "k", &tagged(5, &[0xff])), -1);
+            let key = [0xff];
+            assert_eq!(
+                automerge_put(
+                    doc,
+                    "_root".as_ptr(),
+                    5,
+                    key.as_ptr(),
+                    1,
+                    0,
+                    value.as_ptr(),
+                    value.len()
+                ),
+                -1
+            );
+
+            let mut len = 0;
+            let obj =
+                automerge_put_object(doc, "_root".as_ptr(), 5, "o".as_ptr(), 1, 0, 2, &mut len);
+            assert!(obj.is_null());
+            assert!(last_error().contains("object type"));
+
+            // a list index out of range, and a map key on the root, which is a map
+            let list =
+                automerge_put_object(doc, "_root".as_ptr(), 5, "l".as_ptr(), 1, 0, 10, &mut len);
+            let list = String::from_utf8(take(list, len)).unwrap();
+            assert_eq!(
+                automerge_insert(
+                    doc,
+                    list.as_ptr(),
+                    list.len(),
+                    5,
+                    value.as_ptr(),
+                    value.len()
+                ),
+                -1
+            );
+            assert_eq!(
+                automerge_delete(doc, "_root".as_ptr(), 5, ptr::null(), 0, 0),
+                -1
+            );
+
+            // failed writes don't leave changes behind
+            assert_eq!((*doc).get_changes(&[]).unwrap().len(), 1);
+            automerge_destroy(doc);
+        }
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn json_round_trip() {
+        unsafe {
+            let json = br#"{"title":"hello","n":[1,2]}"#;
+            let doc = automerge_from_json(json.as_ptr(), json.len());
+            assert!(!doc.is_null());
+            let mut len = 0;
+            let out = take(automerge_to_json(doc, &mut len), len);
+            let out: serde_json::Value = serde_json::from_slice(&out).unwrap();
+            assert_eq!(
+                out,
+                serde_json::from_slice::<serde_json::Value>(json).unwrap()
+            );
+            automerge_destroy(doc);
+
+            assert!(automerge_from_json(b"{".as_ptr(), 1).is_null());
+            assert!(!last_error().is_empty());
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::legacy::{ElementId, Key, ObjectId, OpId};
+use crate::{ActorId, Automerge, AutomergeError, Change, ChangeHash, ExpandedChange};
+
+impl Automerge {
+    /// Apply `changes` with the actors in `actors` replaced, returning the hash each change has
+    /// after the replacement by the hash it had before.
+    ///
+    /// This is for importing history from another implementation, e.g. the changes of an
+    /// `automerge@1.x` JavaScript document read with [`Change::from_js_bytes`], whose actor ids
+    /// need normalising, for instance to one stable id per user. Every op id, object id, list
+    /// element and predecessor which names a mapped actor is rewritten, so the changes get new
+    /// hashes, and the dependencies of changes in the batch are rewritten to match. Changes
+    /// which don't mention a mapped actor or depend on a rewritten change are applied as they
+    /// are.
+    ///
+    /// A change which depends on a rewritten change must be in the same batch as it, as its
+    /// old hash is forgotten once this returns. Mapping two actors to the same actor would mix
+    /// up their ops, so that is an error.
+    pub fn apply_changes_with_actor_map(
+        &mut self,
+        changes: impl IntoIterator<Item = Change>,
+        actors: &HashMap<ActorId, ActorId>,
+    ) -> Result<HashMap<ChangeHash, ChangeHash>, AutomergeError> {
+        let (changes, hashes) = with_actor_map(changes, actors)?;
+        self.apply_changes(changes)?;
+        Ok(hashes)
+    }
+}
+
+/// `changes` with the actors in `actors` replaced, in causal order, and the hash each change has
+/// after the replacement by the hash it had before.
+pub(crate) fn with_actor_map(
+    changes: impl IntoIterator<Item = Change>,
+    actors: &HashMap<ActorId, ActorId>,
+) -> Result<(Vec<Change>, HashMap<ChangeHash, ChangeHash>), AutomergeError> {
+    let mut targets = HashSet::new();
+    for target in actors.values() {
+        if !targets.insert(target) {
+            return Err(AutomergeError::AmbiguousActorMap(target.clone()));
+        }
+    }
+
+    let mut hashes = HashMap::new();
+    let mut mapped = Vec::new();
+    for change in causal_order(changes.into_iter().collect()) {
+        let old_hash = change.hash();
+        let change = map_actors(change, actors, &hashes);
+        hashes.insert(old_hash, change.hash());
+        mapped.push(change);
+    }
+    Ok((mapped, hashes))
+}
+
+/// `changes` with every change after the changes in the list it depends on.
+fn causal_order(changes: Vec<Change>) -> Vec<Change> {
+    let in_batch = changes.iter().map(|c| c.hash()).collect::<HashSet<_>>();
+    let mut done = HashSet::new();
+    let mut ordered = Vec::with_capacity(changes.len());
+    let mut pending = changes;
+    while !pending.is_empty() {
+        let before = pending.len();
+        let (ready, waiting): (Vec<_>, Vec<_>) = pending.into_iter().partition(|c| {
+            c.deps()
+                .iter()
+                .all(|d| !in_batch.contains(d) || done.contains(d))
+        });
+        done.extend(ready.iter().map(|c| c.hash()));
+        ordered.extend(ready);
+        pending = waiting;
+        if pending.len() == before {
+            // a dependency cycle can't happen with valid hashes, leave it to `apply_changes`
+            ordered.append(&mut pending);
+        }
+    }
+    ordered
+}
+
+fn map_actors(
+    change: Change,
+    actors: &HashMap<ActorId, ActorId>,
+    hashes: &HashMap<ChangeHash, ChangeHash>,
+) -> Change {
+    let mentions_actor = std::iter::once(change.actor_id())
+        .chain(change.other_actor_ids())
+        .any(|a| actors.contains_key(a));
+    let deps_rewritten = change
+        .deps()
+        .iter()
+        .any(|d| hashes.get(d).map_or(false, |h| h != d));
+    if !mentions_actor && !deps_rewritten {
+        return change;
+    }
+
+    let map_actor = |actor: &ActorId| actors.get(actor).unwrap_or(actor).clone();
+    let map_opid = |id: &OpId| OpId(id.0, map_actor(&id.1));
+    let mut expanded = ExpandedChange::from(&change);
+    expanded.actor_id = map_actor(&expanded.actor_id);
+    expanded.hash = None;
+    expanded.deps = expanded
+        .deps
+        .iter()
+        .map(|d| hashes.get(d).copied().unwrap_or(*d))
+        .collect();
+    expanded.deps.sort();
+    for op in &mut expanded.operations {
+        if let ObjectId::Id(id) = &op.obj {
+            op.obj = ObjectId::Id(map_opid(id));
+        }
+        if let Key::Seq(ElementId::Id(id)) = &op.key {
+            op.key = Key::Seq(ElementId::Id(map_opid(id)));
+        }
+        op.pred = op.pred.iter().map(map_opid).collect();
+    }
+    Change::from(expanded)
+}
@@ -0,0 +1,84 @@
+use crate::exid::ExId;
+use crate::reserved;
+use crate::{ActorId, Automerge, AutomergeError, ObjType, ScalarValue, Value};
+
+/// The key of the root map under which actor metadata is kept, see [`ActorMetadata`].
+pub const ACTOR_METADATA_KEY: &str = "_actors";
+
+/// Information about the person or device behind an actor, so that applications can show who
+/// made a change without keeping a registry of users elsewhere.
+///
+/// Metadata is part of the document, so it is saved and synced along with everything else. It
+/// lives in a registry map under [`ACTOR_METADATA_KEY`] in the root map, keyed by the hex encoded
+/// actor ID, and is written with [`crate::transaction::Transactable::set_actor_metadata`]. If
+/// two peers concurrently write metadata for the first time the document ends up with two such
+/// maps. Metadata is written to the one which wins the conflict, and read from it for
+/// preference, so both peers' metadata can still be read. A value at [`ACTOR_METADATA_KEY`]
+/// which is not a registry is never read as metadata or replaced, see
+/// [`crate::REGISTRY_MARKER_KEY`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActorMetadata {
+    /// A name to display for the actor.
+    pub name: Option<String>,
+    /// A description of the device the actor runs on.
+    pub device: Option<String>,
+    /// A public key the actor can be identified by, e.g. the key which checks the signatures of
+    /// its changes.
+    pub public_key: Option<Vec<u8>>,
+}
+
+impl ActorMetadata {
+    pub(crate) const NAME: &'static str = "name";
+    pub(crate) const DEVICE: &'static str = "device";
+    pub(crate) const PUBLIC_KEY: &'static str = "public_key";
+
+    /// Read the metadata of `actor` with `get_all`, which is the `get_all` method of a document.
+    pub(crate) fn read<'a, F>(actor: &ActorId, get_all: F) -> Result<Option<Self>, AutomergeError>
+    where
+        F: Fn(&ExId, &str) -> Result<Vec<(Value<'a>, ExId)>, AutomergeError>,
+    {
+        let key = actor.to_hex_string();
+        // the winning registry holds the latest metadata, the others are only consulted for
+        // actors it doesn't know about
+        for registry in reserved::registries(ACTOR_METADATA_KEY, &get_all)? {
+            if let Some((_, entry)) = objects(get_all(&registry, &key)?).next() {
+                let public_key = last(get_all(&entry, Self::PUBLIC_KEY)?).and_then(|v| match v {
+                    Value::Scalar(s) => match s.as_ref() {
+                        ScalarValue::Bytes(b) => Some(b.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                });
+                return Ok(Some(Self {
+                    name: string(get_all(&entry, Self::NAME)?),
+                    device: string(get_all(&entry, Self::DEVICE)?),
+                    public_key,
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The maps among `values`, winner first.
+fn objects<'a>(values: Vec<(Value<'a>, ExId)>) -> impl Iterator<Item = (Value<'a>, ExId)> {
+    values
+        .into_iter()
+        .rev()
+        .filter(|(v, _)| matches!(v, Value::Object(ObjType::Map)))
+}
+
+fn last<'a>(mut values: Vec<(Value<'a>, ExId)>) -> Option<Value<'a>> {
+    values.pop().map(|(v, _)| v)
+}
+
+fn string(values: Vec<(Value<'_>, ExId)>) -> Option<String> {
+    last(values).and_then(|v| v.to_str().map(str::to_string))
+}
+
+impl Automerge {
+    /// The metadata attached to `actor`, if any, see [`ActorMetadata`].
+    pub fn actor_metadata(&self, actor: &ActorId) -> Result<Option<ActorMetadata>, AutomergeError> {
+        ActorMetadata::read(actor, |obj, key| self.get_all(obj, key))
+    }
+}
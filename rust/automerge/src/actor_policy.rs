@@ -0,0 +1,98 @@
+use crate::{ActorId, Automerge, AutomergeError};
+
+/// How a document expects its actor to be reused, checked by the first op of every transaction,
+/// see [`Automerge::set_actor_policy`].
+///
+/// Two changes by the same actor with the same sequence number are a corruption other peers
+/// can't recover from. This happens when a device reuses its actor with a copy of the document
+/// which is missing some of the changes it made with it, e.g. an older save, so the sequence
+/// numbers of its new changes start again from where that copy left off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActorPolicy {
+    /// No checks, which is the default
+    Any,
+    /// Every session uses an actor of its own, like the random actor a document is created or
+    /// loaded with. Changes fail if the actor has changes in the document which were not made
+    /// by this session.
+    PerSession,
+    /// A device uses the same actor in every session, and `last_seq` is the sequence number of
+    /// the last change the device made with it, as returned by [`Automerge::local_seq`] and
+    /// persisted by the application. Changes fail until the document has every change up to
+    /// `last_seq`.
+    PerDevice { last_seq: u64 },
+}
+
+impl Default for ActorPolicy {
+    fn default() -> Self {
+        Self::Any
+    }
+}
+
+impl ActorId {
+    /// A random actor ID of the same length as [`Self::random`], drawn from `rng`, for
+    /// applications which need to control where randomness comes from, e.g. to make tests
+    /// deterministic.
+    #[cfg(feature = "rand")]
+    pub fn generate_with<R: rand::RngCore + ?Sized>(rng: &mut R) -> ActorId {
+        let mut bytes = [0; 16];
+        rng.fill_bytes(&mut bytes);
+        ActorId::from(&bytes[..])
+    }
+}
+
+impl Automerge {
+    /// Check the actor of this document against `policy` when it is changed, see
+    /// [`Self::set_actor_policy`].
+    pub fn with_actor_policy(mut self, policy: ActorPolicy) -> Self {
+        self.actor_policy = policy;
+        self
+    }
+
+    /// Change the policy the actor of this document is checked against, see [`ActorPolicy`].
+    ///
+    /// The first op of every transaction checks the policy and returns the error of
+    /// [`Self::check_actor`] if it is broken, however the transaction is then committed. A
+    /// transaction without ops has nothing to check, so committing one with `commit` makes an
+    /// empty change whatever the policy; `try_commit` checks the policy again and refuses to.
+    pub fn set_actor_policy(&mut self, policy: ActorPolicy) -> &mut Self {
+        self.actor_policy = policy;
+        self
+    }
+
+    /// The policy the actor of this document is checked against.
+    pub fn actor_policy(&self) -> ActorPolicy {
+        self.actor_policy
+    }
+
+    /// The sequence number of the last change this document has from its actor, or 0 if it
+    /// has none. A device which keeps its actor between sessions persists this after each
+    /// change for [`ActorPolicy::PerDevice`].
+    pub fn local_seq(&self) -> u64 {
+        match self.ops.m.actors.lookup(self.get_actor()) {
+            Some(actor) => {
+                self.dropped_seqs(actor) + self.states.get(&actor).map_or(0, |v| v.len()) as u64
+            }
+            None => 0,
+        }
+    }
+
+    /// Check that the next change made with the actor of this document is allowed by its
+    /// [`ActorPolicy`].
+    pub fn check_actor(&self) -> Result<(), AutomergeError> {
+        let seq = self.local_seq();
+        match self.actor_policy {
+            ActorPolicy::Any => Ok(()),
+            ActorPolicy::PerSession if seq > self.session_changes => {
+                Err(AutomergeError::ActorReused(self.get_actor().clone()))
+            }
+            ActorPolicy::PerDevice { last_seq } if seq < last_seq => {
+                Err(AutomergeError::StaleActor {
+                    actor: self.get_actor().clone(),
+                    seq,
+                    last_seq,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
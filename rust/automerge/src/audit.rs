@@ -0,0 +1,127 @@
+use std::fmt;
+
+use crate::exid::ExId;
+use crate::Automerge;
+
+/// The result of [`Automerge::audit`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    /// The number of objects checked, including deleted objects.
+    pub objects: usize,
+    /// The number of ops checked.
+    pub ops: usize,
+    /// Everything found to be wrong, in no particular order. Empty for a healthy document.
+    pub problems: Vec<AuditProblem>,
+}
+
+impl AuditReport {
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "checked {} ops in {} objects, found {} problems",
+            self.ops,
+            self.objects,
+            self.problems.len()
+        )?;
+        for problem in &self.problems {
+            write!(f, "\n  {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// An internal invariant of a document which doesn't hold, see [`Automerge::audit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditProblem {
+    /// The number of ops the document records doesn't match the number stored in its objects.
+    OpCount { recorded: usize, found: usize },
+    /// The object's parent is not in the document.
+    Orphan { obj: ExId, parent: ExId },
+    /// The length recorded in a node of the tree holding the object's ops doesn't match the
+    /// number of ops beneath it, so ops will be looked up at the wrong index.
+    Length { obj: ExId },
+    /// The index kept in a node of the tree holding the object's ops, which records the ops
+    /// beneath it and which of them are visible, doesn't match those ops.
+    Index { obj: ExId },
+    /// The tree holding the object's ops has leaves at different depths or nodes with the wrong
+    /// number of ops or children.
+    Unbalanced { obj: ExId },
+    /// The number of visible ops the object records doesn't match the number of ops which are
+    /// visible.
+    VisibleCount {
+        obj: ExId,
+        recorded: usize,
+        found: usize,
+    },
+    /// `op` overwrites `pred`, which is not an op of the same object.
+    DanglingPred { obj: ExId, op: ExId, pred: ExId },
+    /// `op` overwrites `pred`, but `pred` doesn't list `op` among its successors, so it is still
+    /// treated as visible.
+    MissingSucc { obj: ExId, op: ExId, pred: ExId },
+    /// `op` doesn't belong to any change in the document's history, so it won't be saved.
+    NoChange { obj: ExId, op: ExId },
+}
+
+impl fmt::Display for AuditProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OpCount { recorded, found } => write!(
+                f,
+                "document records {} ops but its objects hold {}",
+                recorded, found
+            ),
+            Self::Orphan { obj, parent } => {
+                write!(f, "{}: parent {} does not exist", obj, parent)
+            }
+            Self::Length { obj } => write!(f, "{}: op tree lengths are inconsistent", obj),
+            Self::Index { obj } => write!(f, "{}: op tree index is inconsistent", obj),
+            Self::Unbalanced { obj } => write!(f, "{}: op tree is unbalanced", obj),
+            Self::VisibleCount {
+                obj,
+                recorded,
+                found,
+            } => write!(
+                f,
+                "{}: records {} visible ops but {} are visible",
+                obj, recorded, found
+            ),
+            Self::DanglingPred { obj, op, pred } => {
+                write!(f, "{}: {} overwrites missing op {}", obj, op, pred)
+            }
+            Self::MissingSucc { obj, op, pred } => write!(
+                f,
+                "{}: {} overwrites {} which does not list it as a successor",
+                obj, op, pred
+            ),
+            Self::NoChange { obj, op } => write!(f, "{}: {} is not part of any change", obj, op),
+        }
+    }
+}
+
+impl Automerge {
+    /// Check the internal invariants of the document: that the trees holding the ops of each
+    /// object are balanced and their indexes and lengths match their contents, that each op's
+    /// predecessors exist and list it as a successor, and that each op belongs to a change.
+    ///
+    /// A healthy document has no problems. Anything else is a bug, and the report is a good
+    /// thing to attach to an issue. This reads every op in the document, so it is slow for large
+    /// documents.
+    pub fn audit(&self) -> AuditReport {
+        let mut report = self.ops.audit();
+        for (obj, op) in self.ops.iter() {
+            if self.change_index_for_op(op.id).is_none() {
+                report.problems.push(AuditProblem::NoChange {
+                    obj: self.id_to_exid(obj.0),
+                    op: self.id_to_exid(op.id),
+                });
+            }
+        }
+        report
+    }
+}
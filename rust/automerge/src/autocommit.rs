@@ -1,10 +1,19 @@
+use std::collections::HashMap;
 use std::ops::RangeBounds;
 
+use smol_str::SmolStr;
+
 use crate::exid::ExId;
+use crate::materialize::MaterializeTarget;
 use crate::op_observer::OpObserver;
 use crate::transaction::{CommitOptions, Transactable};
 use crate::{
-    sync, Keys, KeysAt, ListRange, ListRangeAt, MapRange, MapRangeAt, ObjType, Parents, ScalarValue,
+    sync, ActorPolicy, ApplyProgress, AuditReport, CancellationToken, ChangeGraph, ChunkCodec,
+    CommitQuery, ConflictPolicy, Cursor, DocumentConfig, DocumentStats, FrozenDoc, HistoryStates,
+    HookId, KeyOrder, Keys, KeysAt, LastModified, Limits, ListElementMeta, ListRange, ListRangeAt,
+    ListWindow, MapRange, MapRangeAt, MemoryUsage, NodeSize, NumericMode, NumericType, ObjType,
+    ObjectStats, Parents, PathCache, PendingCommit, RawOps, ReadTransaction, RowOp, ScalarValue,
+    Schema, SchemaType, Snapshot, TextAttribution, TextSpans,
 };
 use crate::{
     transaction::{Observation, Observed, TransactionInner, UnObserved},
@@ -12,11 +21,31 @@ use crate::{
 };
 
 /// An automerge document that automatically manages transactions.
+///
+/// Every modification opens a transaction if one is not already open, and the transaction is
+/// committed by [`Self::commit`] or by any method which needs to see the committed history, such
+/// as [`Self::save`] or [`Self::get_heads`]. Because the document owns its transaction there is no
+/// [`crate::transaction::Transaction`] borrowing the document to keep track of.
+///
+/// The `Obs` parameter says whether changes are observed. [`AutoCommit`] observes nothing, use
+/// [`Self::with_observer`] to attach an [`OpObserver`]. The observer sees each transaction when
+/// it is committed, as well as any changes applied by merging or loading.
+///
+/// ```
+/// # use automerge::{AutoCommit, Patch, VecOpObserver, ROOT};
+/// # use automerge::transaction::Transactable;
+/// let mut doc = AutoCommit::new().with_observer(VecOpObserver::default());
+/// doc.put(ROOT, "key", "value").unwrap();
+/// doc.commit();
+/// let patches = doc.observer().take_patches();
+/// assert!(matches!(patches.as_slice(), [Patch::Put { .. }]));
+/// ```
 #[derive(Debug, Clone)]
 pub struct AutoCommitWithObs<Obs: Observation> {
     doc: Automerge,
     transaction: Option<(Obs, TransactionInner)>,
     observation: Obs,
+    source: Option<SmolStr>,
 }
 
 pub type AutoCommit = AutoCommitWithObs<UnObserved>;
@@ -27,6 +56,7 @@ impl<O: Observation> AutoCommitWithObs<O> {
             doc: Automerge::new(),
             transaction: None,
             observation: UnObserved::new(),
+            source: None,
         }
     }
 }
@@ -38,6 +68,7 @@ impl<O: OpObserver> Default for AutoCommitWithObs<Observed<O>> {
             doc: Automerge::new(),
             transaction: None,
             observation: Observed::new(op_observer),
+            source: None,
         }
     }
 }
@@ -48,6 +79,7 @@ impl AutoCommit {
             doc: Automerge::new(),
             transaction: None,
             observation: UnObserved,
+            source: None,
         }
     }
 
@@ -57,6 +89,34 @@ impl AutoCommit {
             doc,
             transaction: None,
             observation: UnObserved,
+            source: None,
+        })
+    }
+
+    /// Load a document saved with [`Self::save_with_codec`], see [`Automerge::load_with_codec`].
+    pub fn load_with_codec<C: ChunkCodec>(data: &[u8], codec: &C) -> Result<Self, AutomergeError> {
+        let doc = Automerge::load_with_codec(data, codec)?;
+        Ok(Self {
+            doc,
+            transaction: None,
+            observation: UnObserved,
+            source: None,
+        })
+    }
+
+    /// Load a document, checking the signature of every change with `verifier`.
+    ///
+    /// See [`Automerge::load_verified`].
+    pub fn load_verified<F>(data: &[u8], verifier: F) -> Result<Self, AutomergeError>
+    where
+        F: Fn(&ActorId, &[u8], &[u8]) -> bool + Send + Sync + 'static,
+    {
+        let doc = Automerge::load_verified(data, verifier)?;
+        Ok(Self {
+            doc,
+            transaction: None,
+            observation: UnObserved,
+            source: None,
         })
     }
 }
@@ -75,17 +135,114 @@ impl<Obs: Observation + Clone> AutoCommitWithObs<Obs> {
             doc: self.doc.fork(),
             transaction: self.transaction.clone(),
             observation: self.observation.clone(),
+            source: self.source.clone(),
         }
     }
 
+    /// See [`Automerge::replay_changes`].
+    pub fn replay_changes<Obs2: OpObserver>(
+        &mut self,
+        from_heads: &[ChangeHash],
+        to_heads: &[ChangeHash],
+        op_observer: &mut Obs2,
+    ) -> Result<(), AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.replay_changes(from_heads, to_heads, op_observer)
+    }
+
     pub fn fork_at(&mut self, heads: &[ChangeHash]) -> Result<Self, AutomergeError> {
         self.ensure_transaction_closed();
         Ok(Self {
             doc: self.doc.fork_at(heads)?,
             transaction: self.transaction.clone(),
             observation: self.observation.clone(),
+            source: self.source.clone(),
         })
     }
+
+    /// See [`Automerge::object_stats`]
+    pub fn object_stats<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjectStats, AutomergeError> {
+        self.doc.object_stats(obj)
+    }
+
+    /// See [`Automerge::document_stats`]
+    pub fn document_stats(&self) -> DocumentStats {
+        self.doc.document_stats()
+    }
+
+    /// See [`Automerge::memory_usage`]
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.doc.memory_usage()
+    }
+
+    /// See [`Automerge::materialize_into`]
+    pub fn materialize_into<T: MaterializeTarget + ?Sized>(&self, target: &mut T) {
+        self.doc.materialize_into(target)
+    }
+
+    /// See [`Automerge::list_window`]
+    pub fn list_window<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        start: usize,
+        len: usize,
+    ) -> Result<ListWindow, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.list_window(obj, start, len)
+    }
+
+    /// See [`Automerge::is_window_current`]
+    pub fn is_window_current(&mut self, window: &ListWindow) -> bool {
+        self.ensure_transaction_closed();
+        self.doc.is_window_current(window)
+    }
+
+    /// See [`Automerge::changes_affecting`]
+    pub fn changes_affecting<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+    ) -> Result<Vec<&Change>, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.changes_affecting(obj)
+    }
+
+    /// See [`Automerge::change_graph`]
+    pub fn change_graph(&mut self) -> ChangeGraph<'_> {
+        self.ensure_transaction_closed();
+        self.doc.change_graph()
+    }
+
+    /// See [`Automerge::is_ancestor`]
+    pub fn is_ancestor(&mut self, a: &ChangeHash, b: &ChangeHash) -> Result<bool, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.is_ancestor(a, b)
+    }
+
+    /// See [`Automerge::heads_equal`]
+    pub fn heads_equal(
+        &mut self,
+        x: &[ChangeHash],
+        y: &[ChangeHash],
+    ) -> Result<bool, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.heads_equal(x, y)
+    }
+
+    /// See [`Automerge::heads_diff`]
+    pub fn heads_diff(
+        &mut self,
+        x: &[ChangeHash],
+        y: &[ChangeHash],
+    ) -> Result<(Vec<ChangeHash>, Vec<ChangeHash>), AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.heads_diff(x, y)
+    }
+
+    /// See [`Automerge::history_states`]
+    pub fn history_states(&mut self) -> HistoryStates<'_> {
+        self.ensure_transaction_closed();
+        self.doc.history_states()
+    }
 }
 
 impl<Obs: Observation> AutoCommitWithObs<Obs> {
@@ -99,6 +256,7 @@ impl<Obs: Observation> AutoCommitWithObs<Obs> {
                 .transaction
                 .map(|(_, t)| (Observed::new(op_observer.branch()), t)),
             observation: Observed::new(op_observer),
+            source: self.source,
         }
     }
 
@@ -121,20 +279,415 @@ impl<Obs: Observation> AutoCommitWithObs<Obs> {
         self
     }
 
+    /// Set the configuration of this document, see [`Automerge::with_config`].
+    pub fn with_config(mut self, config: DocumentConfig) -> Self {
+        self.doc = self.doc.with_config(config);
+        self
+    }
+
+    pub fn config(&self) -> &DocumentConfig {
+        self.doc.config()
+    }
+
+    /// See [`Automerge::set_header`]
+    pub fn set_header<K: Into<String>, V: Into<Vec<u8>>>(&mut self, key: K, value: V) {
+        self.doc.set_header(key, value)
+    }
+
+    /// See [`Automerge::header`]
+    pub fn header(&self, key: &str) -> Option<&[u8]> {
+        self.doc.header(key)
+    }
+
+    /// See [`Automerge::remove_header`]
+    pub fn remove_header(&mut self, key: &str) -> Option<Vec<u8>> {
+        self.doc.remove_header(key)
+    }
+
+    /// See [`Automerge::headers`]
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.doc.headers()
+    }
+
+    /// Enforce `limits` on this document, see [`Automerge::with_limits`].
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.doc.set_limits(limits);
+        self
+    }
+
+    /// Change the limits enforced on this document, see [`Automerge::with_limits`].
+    pub fn set_limits(&mut self, limits: Limits) -> &mut Self {
+        self.doc.set_limits(limits);
+        self
+    }
+
+    pub fn limits(&self) -> &Limits {
+        self.doc.limits()
+    }
+
+    /// Check the values put by this document against `schema`, see [`Automerge::with_schema`].
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.doc.set_schema(Some(schema));
+        self
+    }
+
+    /// Change the schema of this document, or remove it, see [`Automerge::with_schema`].
+    pub fn set_schema(&mut self, schema: Option<Schema>) -> &mut Self {
+        self.doc.set_schema(schema);
+        self
+    }
+
+    pub fn schema(&self) -> Option<&Schema> {
+        self.doc.schema()
+    }
+
     pub fn get_actor(&self) -> &ActorId {
         self.doc.get_actor()
     }
 
+    /// Set the policy the actor of this document is checked against, see
+    /// [`Automerge::set_actor_policy`].
+    pub fn set_actor_policy(&mut self, policy: ActorPolicy) -> &mut Self {
+        self.doc.set_actor_policy(policy);
+        self
+    }
+
+    /// The policy the actor of this document is checked against.
+    pub fn actor_policy(&self) -> ActorPolicy {
+        self.doc.actor_policy()
+    }
+
+    /// Change how numbers written by peers with only one number type are read and overwritten,
+    /// see [`Automerge::set_numeric_mode`].
+    pub fn set_numeric_mode(&mut self, mode: NumericMode) -> &mut Self {
+        self.doc.set_numeric_mode(mode);
+        self
+    }
+
+    /// How numbers of this document are read.
+    pub fn numeric_mode(&self) -> NumericMode {
+        self.doc.numeric_mode()
+    }
+
+    /// See [`Automerge::get_number`]
+    pub fn get_number<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<(ScalarValue, ExId)>, AutomergeError> {
+        self.doc.get_number(obj, prop)
+    }
+
+    /// See [`Automerge::numeric_type`]
+    pub fn numeric_type<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<NumericType>, AutomergeError> {
+        self.doc.numeric_type(obj, prop)
+    }
+
+    /// The sequence number of the last change this document has from its actor, see
+    /// [`Automerge::local_seq`].
+    pub fn local_seq(&mut self) -> u64 {
+        self.ensure_transaction_closed();
+        self.doc.local_seq()
+    }
+
+    /// Call `hook` each time a change is about to be committed, see
+    /// [`Automerge::on_before_commit`].
+    pub fn on_before_commit<F>(&mut self, hook: F) -> HookId
+    where
+        F: Fn(&PendingCommit<'_>) + Send + Sync + 'static,
+    {
+        self.doc.on_before_commit(hook)
+    }
+
+    /// Call `hook` with each change once it is committed, see [`Automerge::on_commit`].
+    ///
+    /// Changes are committed when [`Self::commit`] is called, or when any method which needs
+    /// the transaction in progress to be closed is, e.g. [`Self::save`].
+    pub fn on_commit<F>(&mut self, hook: F) -> HookId
+    where
+        F: Fn(&Change) + Send + Sync + 'static,
+    {
+        self.doc.on_commit(hook)
+    }
+
+    /// Remove a hook, see [`Automerge::remove_commit_hook`].
+    pub fn remove_commit_hook(&mut self, id: HookId) -> bool {
+        self.doc.remove_commit_hook(id)
+    }
+
+    /// Check that the next change is allowed by the actor policy, see
+    /// [`Automerge::check_actor`].
+    pub fn check_actor(&mut self) -> Result<(), AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.check_actor()
+    }
+
+    /// Set the node size of the internal op trees, see [`Automerge::set_node_size`].
+    pub fn set_node_size(&mut self, node_size: NodeSize) -> Result<&mut Self, AutomergeError> {
+        self.doc.set_node_size(node_size)?;
+        Ok(self)
+    }
+
+    /// Set how many recent lookups are cached, see [`Automerge::set_cache_capacity`].
+    pub fn set_cache_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.doc.set_cache_capacity(capacity);
+        self
+    }
+
+    /// Sign every change created by this document, see [`Automerge::set_signer`].
+    pub fn set_signer<F>(&mut self, signer: F) -> &mut Self
+    where
+        F: Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static,
+    {
+        self.doc.set_signer(signer);
+        self
+    }
+
+    /// Check the signature of every change applied to this document, see
+    /// [`Automerge::set_verifier`].
+    pub fn set_verifier<F>(&mut self, verifier: F) -> &mut Self
+    where
+        F: Fn(&ActorId, &[u8], &[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.doc.set_verifier(verifier);
+        self
+    }
+
     fn ensure_transaction_open(&mut self) {
         if self.transaction.is_none() {
-            self.transaction = Some((self.observation.branch(), self.doc.transaction_inner()));
+            let mut tx = self.doc.transaction_inner();
+            tx.source = self.source.clone();
+            let mut observation = self.observation.branch();
+            if let (Some(observer), Some(source)) = (observation.observer(), &self.source) {
+                observer.source(Some(source));
+            }
+            self.transaction = Some((observation, tx));
+        }
+    }
+
+    /// Label the ops created from now on with `source`, or stop labelling them if `source` is
+    /// `None`. See [`crate::transaction::Transaction::set_source`].
+    pub fn set_source(&mut self, source: Option<&str>) {
+        self.source = source.map(SmolStr::new);
+        if let Some((observation, tx)) = self.transaction.as_mut() {
+            tx.source = self.source.clone();
+            if let Some(observer) = observation.observer() {
+                observer.source(source);
+            }
         }
     }
 
+    /// See [`Automerge::set_conflict_policy`]
+    pub fn set_conflict_policy<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        key: &str,
+        policy: Option<ConflictPolicy>,
+    ) -> Result<(), AutomergeError> {
+        self.doc.set_conflict_policy(obj, key, policy)
+    }
+
+    /// See [`Automerge::set_object_conflict_policy`]
+    pub fn set_object_conflict_policy<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        policy: Option<ConflictPolicy>,
+    ) -> Result<(), AutomergeError> {
+        self.doc.set_object_conflict_policy(obj, policy)
+    }
+
+    /// See [`Automerge::set_type_conflict_policy`]
+    pub fn set_type_conflict_policy(&mut self, ty: SchemaType, policy: Option<ConflictPolicy>) {
+        self.doc.set_type_conflict_policy(ty, policy)
+    }
+
+    /// See [`Automerge::iter_ops`]
+    pub fn iter_ops<O: AsRef<ExId>>(&mut self, obj: O) -> Result<RawOps<'_>, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.iter_ops(obj)
+    }
+
+    /// See [`Automerge::enable_message_index`]
+    pub fn enable_message_index(&mut self) {
+        self.doc.enable_message_index()
+    }
+
+    /// See [`Automerge::find_commits`]
+    pub fn find_commits(&mut self, query: &CommitQuery) -> Vec<&Change> {
+        self.ensure_transaction_closed();
+        self.doc.find_commits(query)
+    }
+
+    /// See [`Automerge::attribute_text`]
+    pub fn attribute_text<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+    ) -> Result<Vec<TextAttribution>, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.attribute_text(obj)
+    }
+
+    /// See [`Automerge::attribute_text_at`]
+    pub fn attribute_text_at<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Vec<TextAttribution>, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.attribute_text_at(obj, heads)
+    }
+
+    /// See [`Automerge::attribute_text_between`]
+    pub fn attribute_text_between<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        before: &[ChangeHash],
+        after: &[ChangeHash],
+    ) -> Result<Vec<TextAttribution>, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.attribute_text_between(obj, before, after)
+    }
+
+    /// See [`Automerge::keys_ordered`]
+    pub fn keys_ordered<O: AsRef<ExId>>(&self, obj: O, order: KeyOrder) -> Vec<String> {
+        self.doc.keys_ordered(obj, order)
+    }
+
+    /// See [`Automerge::text_spans`]
+    pub fn text_spans<O: AsRef<ExId>>(&self, obj: O) -> Result<TextSpans<'_>, AutomergeError> {
+        self.doc.text_spans(obj)
+    }
+
+    /// See [`Automerge::text_index_of`]
+    pub fn text_index_of<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        id: &ExId,
+    ) -> Result<Option<usize>, AutomergeError> {
+        self.doc.text_index_of(obj, id)
+    }
+
+    /// See [`Automerge::cursor`]
+    pub fn cursor<O: AsRef<ExId>>(&self, obj: O, index: usize) -> Result<Cursor, AutomergeError> {
+        self.doc.cursor(obj, index)
+    }
+
+    /// See [`Automerge::cursor_position`]
+    pub fn cursor_position(&self, cursor: &Cursor) -> Result<usize, AutomergeError> {
+        self.doc.cursor_position(cursor)
+    }
+
+    /// See [`Automerge::audit`]
+    pub fn audit(&mut self) -> AuditReport {
+        self.ensure_transaction_closed();
+        self.doc.audit()
+    }
+
+    /// See [`Automerge::content_hash`]
+    pub fn content_hash(&mut self, heads: &[ChangeHash]) -> Result<[u8; 32], AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.content_hash(heads)
+    }
+
+    /// See [`Automerge::path_cache`]. Objects made in the open transaction are included.
+    pub fn path_cache(&self) -> PathCache<'_> {
+        self.doc.path_cache()
+    }
+
+    /// See [`Automerge::last_modified`]
+    pub fn last_modified<O: AsRef<ExId>, P: Into<Prop>>(
+        &mut self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<LastModified>, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.last_modified(obj, prop)
+    }
+
+    /// See [`Automerge::last_modified_all`]
+    pub fn last_modified_all<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+    ) -> Result<Vec<(Prop, LastModified)>, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.last_modified_all(obj)
+    }
+
+    /// See [`Automerge::project_rows`]
+    pub fn project_rows<O: AsRef<ExId>>(&mut self, obj: O) -> Result<Vec<RowOp>, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.project_rows(obj)
+    }
+
+    /// See [`Automerge::list_elements_meta`]
+    pub fn list_elements_meta<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+    ) -> Result<Vec<ListElementMeta>, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.list_elements_meta(obj)
+    }
+
+    /// See [`Automerge::deep_eq_at`]
+    pub fn deep_eq_at<A: AsRef<ExId>, B: AsRef<ExId>>(
+        &mut self,
+        obj_a: A,
+        heads_a: &[ChangeHash],
+        obj_b: B,
+        heads_b: &[ChangeHash],
+    ) -> Result<bool, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.deep_eq_at(obj_a, heads_a, obj_b, heads_b)
+    }
+
+    /// See [`Automerge::materialized_eq`]
+    pub fn materialized_eq<Obs2: Observation>(
+        &mut self,
+        other: &mut AutoCommitWithObs<Obs2>,
+    ) -> bool {
+        self.ensure_transaction_closed();
+        other.ensure_transaction_closed();
+        self.doc.materialized_eq(&other.doc)
+    }
+
+    /// See [`Automerge::set_history_fence`]
+    pub fn set_history_fence(&mut self, heads: &[ChangeHash]) -> Result<(), AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.set_history_fence(heads)
+    }
+
+    /// See [`Automerge::history_fence`]
+    pub fn history_fence(&self) -> &[ChangeHash] {
+        self.doc.history_fence()
+    }
+
+    /// See [`Automerge::op_source`]
+    pub fn op_source(&self, id: &ExId) -> Option<&str> {
+        self.doc.op_source(id)
+    }
+
+    /// See [`Automerge::change_sources`]
+    pub fn change_sources(
+        &mut self,
+        hash: &ChangeHash,
+    ) -> Result<Vec<(&str, usize)>, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.change_sources(hash)
+    }
+
     fn ensure_transaction_closed(&mut self) {
         if let Some((current, tx)) = self.transaction.take() {
+            if tx.pending_ops() == 0 && self.doc.check_actor().is_err() {
+                // every op was refused by the actor policy, don't make an empty change it refuses
+                tx.rollback(&mut self.doc);
+                return;
+            }
             self.observation.merge(&current);
-            tx.commit(&mut self.doc, None, None);
+            tx.commit(&mut self.doc, CommitOptions::default());
         }
     }
 
@@ -160,6 +713,47 @@ impl<Obs: Observation> AutoCommitWithObs<Obs> {
         }
     }
 
+    /// See [`Automerge::apply_changes_batched`]
+    pub fn apply_changes_batched(
+        &mut self,
+        changes: impl IntoIterator<Item = Change>,
+    ) -> Result<(), AutomergeError> {
+        self.ensure_transaction_closed();
+        if let Some(observer) = self.observation.observer() {
+            self.doc.apply_changes_batched_with(changes, Some(observer))
+        } else {
+            self.doc.apply_changes_batched(changes)
+        }
+    }
+
+    /// See [`Automerge::apply_changes_with_progress`]
+    pub fn apply_changes_with_progress<I, F>(
+        &mut self,
+        changes: I,
+        progress: F,
+        cancel: &CancellationToken,
+    ) -> Result<(), AutomergeError>
+    where
+        I: IntoIterator<Item = Change>,
+        F: FnMut(ApplyProgress),
+    {
+        self.ensure_transaction_closed();
+        let observer = self.observation.observer();
+        self.doc
+            .apply_changes_with_progress_inner(changes, observer, progress, cancel)
+    }
+
+    /// See [`Automerge::apply_changes_with_actor_map`]
+    pub fn apply_changes_with_actor_map(
+        &mut self,
+        changes: impl IntoIterator<Item = Change>,
+        actors: &HashMap<ActorId, ActorId>,
+    ) -> Result<HashMap<ChangeHash, ChangeHash>, AutomergeError> {
+        let (changes, hashes) = crate::actor_map::with_actor_map(changes, actors)?;
+        self.apply_changes(changes)?;
+        Ok(hashes)
+    }
+
     /// Takes all the changes in `other` which are not in `self` and applies them
     pub fn merge<Obs2: Observation>(
         &mut self,
@@ -179,17 +773,36 @@ impl<Obs: Observation> AutoCommitWithObs<Obs> {
         self.doc.save()
     }
 
+    /// Commit any open transaction and turn the document into one which can't change, see
+    /// [`Automerge::freeze`].
+    pub fn freeze(mut self) -> FrozenDoc {
+        self.ensure_transaction_closed();
+        self.doc.freeze()
+    }
+
     pub fn save_nocompress(&mut self) -> Vec<u8> {
         self.ensure_transaction_closed();
         self.doc.save_nocompress()
     }
 
+    /// See [`Automerge::save_with_codec`].
+    pub fn save_with_codec<C: ChunkCodec>(&mut self, codec: &C) -> Vec<u8> {
+        self.ensure_transaction_closed();
+        self.doc.save_with_codec(codec)
+    }
+
     // should this return an empty vec instead of None?
     pub fn save_incremental(&mut self) -> Vec<u8> {
         self.ensure_transaction_closed();
         self.doc.save_incremental()
     }
 
+    /// See [`Automerge::save_incremental_with_codec`].
+    pub fn save_incremental_with_codec<C: ChunkCodec>(&mut self, codec: &C) -> Vec<u8> {
+        self.ensure_transaction_closed();
+        self.doc.save_incremental_with_codec(codec)
+    }
+
     pub fn get_missing_deps(&mut self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
         self.ensure_transaction_closed();
         self.doc.get_missing_deps(heads)
@@ -233,6 +846,15 @@ impl<Obs: Observation> AutoCommitWithObs<Obs> {
         self.doc.generate_sync_message(sync_state)
     }
 
+    pub fn generate_sync_chunk(
+        &mut self,
+        sync_state: &mut sync::State,
+        max_chunk_len: usize,
+    ) -> Option<Vec<u8>> {
+        self.ensure_transaction_closed();
+        self.doc.generate_sync_chunk(sync_state, max_chunk_len)
+    }
+
     pub fn receive_sync_message(
         &mut self,
         sync_state: &mut sync::State,
@@ -266,6 +888,20 @@ impl<Obs: Observation> AutoCommitWithObs<Obs> {
         self.doc.get_heads()
     }
 
+    /// Commit any pending changes and start a sequence of reads at the resulting heads, see
+    /// [`Automerge::read_tx`].
+    pub fn read_tx(&mut self) -> ReadTransaction<'_> {
+        self.ensure_transaction_closed();
+        self.doc.read_tx()
+    }
+
+    /// Commit any pending changes and take a snapshot of the result, see
+    /// [`Automerge::snapshot`].
+    pub fn snapshot(&mut self) -> Snapshot {
+        self.ensure_transaction_closed();
+        self.doc.snapshot()
+    }
+
     pub fn commit(&mut self) -> ChangeHash {
         self.commit_with(CommitOptions::default())
     }
@@ -290,7 +926,87 @@ impl<Obs: Observation> AutoCommitWithObs<Obs> {
         self.ensure_transaction_open();
         let (current, tx) = self.transaction.take().unwrap();
         self.observation.merge(&current);
-        tx.commit(&mut self.doc, options.message, options.time)
+        tx.commit(&mut self.doc, options)
+    }
+
+    /// Splice `vals` into the sequence `obj` like [`Transactable::splice`], but split across as
+    /// many changes as it takes for each to have at most `chunk_size` ops, so that inserting a
+    /// huge number of elements doesn't make one huge change.
+    ///
+    /// Any open transaction is committed first. The deletions come first, followed by the
+    /// inserts in order, and a change is committed after each chunk. The observer sees the whole
+    /// splice as one transaction, so a coalescing [`crate::VecOpObserver`] reports it as a single
+    /// patch. If a chunk fails it is rolled back and the chunks before it stay committed.
+    pub fn splice_chunked<O: AsRef<ExId>, V: IntoIterator<Item = ScalarValue>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        del: usize,
+        vals: V,
+        chunk_size: usize,
+    ) -> Result<(), AutomergeError> {
+        self.ensure_transaction_closed();
+        let obj = obj.as_ref();
+        self.doc.exid_to_obj(obj)?;
+        // check the whole splice up front, so that it doesn't fail part way through
+        if pos + del > self.doc.length(obj) {
+            return Err(AutomergeError::InvalidIndex(pos + del));
+        }
+
+        let chunk_size = chunk_size.max(1);
+        let mut observation = self.observation.branch();
+        let mut vals = vals.into_iter().peekable();
+        let (mut pos, mut del) = (pos, del);
+        while del > 0 || vals.peek().is_some() {
+            let chunk_del = del.min(chunk_size);
+            let chunk = vals
+                .by_ref()
+                .take(chunk_size - chunk_del)
+                .collect::<Vec<_>>();
+            let inserted = chunk.len();
+            let mut tx = self.doc.transaction_inner();
+            tx.source = self.source.clone();
+            let result = tx.splice(
+                &mut self.doc,
+                observation.observer(),
+                obj,
+                pos,
+                chunk_del,
+                chunk,
+            );
+            if let Err(e) = result {
+                tx.rollback(&mut self.doc);
+                self.observation.merge(&observation);
+                return Err(e);
+            }
+            tx.commit(&mut self.doc, CommitOptions::default());
+            pos += inserted;
+            del -= chunk_del;
+        }
+        self.observation.merge(&observation);
+        Ok(())
+    }
+
+    /// Commit the current operations unless the document's actor breaks its
+    /// [`crate::ActorPolicy`], see [`crate::transaction::Transaction::try_commit`]. If it does the
+    /// operations are rolled back, and so is what they told the observer.
+    pub fn try_commit(&mut self) -> Result<ChangeHash, AutomergeError> {
+        self.try_commit_with(CommitOptions::default())
+    }
+
+    /// Like [`Self::try_commit`] with some options, see [`Self::commit_with`].
+    pub fn try_commit_with(
+        &mut self,
+        options: CommitOptions,
+    ) -> Result<ChangeHash, AutomergeError> {
+        self.ensure_transaction_open();
+        let (current, tx) = self.transaction.take().unwrap();
+        if let Err(e) = self.doc.check_actor() {
+            tx.rollback(&mut self.doc);
+            return Err(e);
+        }
+        self.observation.merge(&current);
+        Ok(tx.commit(&mut self.doc, options))
     }
 
     pub fn rollback(&mut self) -> usize {
@@ -448,6 +1164,16 @@ impl<Obs: Observation> Transactable for AutoCommitWithObs<Obs> {
         tx.delete(&mut self.doc, current.observer(), obj.as_ref(), prop)
     }
 
+    fn delete_range<O: AsRef<ExId>, R: RangeBounds<String>>(
+        &mut self,
+        obj: O,
+        range: R,
+    ) -> Result<usize, AutomergeError> {
+        self.ensure_transaction_open();
+        let (current, tx) = self.transaction.as_mut().unwrap();
+        tx.delete_range(&mut self.doc, current.observer(), obj.as_ref(), range)
+    }
+
     /// Splice new elements into the given sequence. Returns a vector of the OpIds used to insert
     /// the new elements
     fn splice<O: AsRef<ExId>, V: IntoIterator<Item = ScalarValue>>(
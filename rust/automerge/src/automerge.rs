@@ -1,28 +1,41 @@
 use std::cmp::Ordering;
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt::Debug;
 use std::num::NonZeroU64;
 use std::ops::RangeBounds;
 
+use crate::actor_policy::ActorPolicy;
 use crate::clock::ClockData;
 use crate::clocks::Clocks;
 use crate::columnar::Key as EncodedKey;
+use crate::commit_hooks::CommitHooks;
+use crate::conflict_policy::{ConflictPolicies, ConflictPolicy, PolicyBuffer};
 use crate::exid::ExId;
+use crate::history_fence::HistoryFence;
 use crate::keys::Keys;
+use crate::limits::Limits;
+use crate::message_index::{CommitQuery, MessageIndex};
 use crate::op_observer::OpObserver;
 use crate::op_set::OpSet;
+use crate::op_sources::OpSources;
 use crate::parents::Parents;
+use crate::raw_ops::RawOps;
+use crate::signing::{Signer, Verifier};
 use crate::storage::{self, load, CompressConfig};
 use crate::transaction::{
-    self, CommitOptions, Failure, Observed, Success, Transaction, TransactionInner, UnObserved,
+    self, typed, CommitOptions, DryRunTransaction, Failure, Observed, Success, Transactable,
+    Transaction, TransactionInner, UnObserved,
 };
 use crate::types::{
     ActorId, ChangeHash, Clock, ElemId, Export, Exportable, Key, ObjId, Op, OpId, OpType,
     ScalarValue, Value,
 };
 use crate::{
-    query, AutomergeError, Change, KeysAt, ListRange, ListRangeAt, MapRange, MapRangeAt, ObjType,
-    Prop, Values,
+    query, ApplyProgress, AutomergeError, BytesReader, CancellationToken, Change, ChangeGraph,
+    ChunkCodec, DocumentConfig, DocumentStats, FrozenDoc, HistoryStates, KeysAt, LazyDocument,
+    ListRange, ListRangeAt, LoadOptions, MapRange, MapRangeAt, MemoryUsage, NodeSize, ObjType,
+    ObjectStats, Patch, PathCache, Prop, ReadTransaction, Schema, SchemaType, Snapshot, Values,
+    VecOpObserver, VerificationMode,
 };
 use serde::Serialize;
 
@@ -58,6 +71,35 @@ pub struct Automerge {
     pub(crate) actor: Actor,
     /// The maximum operation counter this document has seen.
     pub(crate) max_op: u64,
+    /// Signs changes created by this document.
+    pub(crate) signer: Option<Signer>,
+    /// Checks the signatures of changes applied to this document.
+    pub(crate) verifier: Option<Verifier>,
+    /// Settings which every replica of this document must agree on.
+    pub(crate) config: DocumentConfig,
+    /// The source labels of ops created by this document.
+    pub(crate) op_sources: OpSources,
+    /// The policies which choose the value `get` returns for conflicting properties.
+    pub(crate) conflict_policies: ConflictPolicies,
+    /// An index of the messages of the changes in `history`, if enabled.
+    pub(crate) message_index: Option<MessageIndex>,
+    /// The fence beneath which changes are dropped when saving.
+    pub(crate) history_fence: Option<HistoryFence>,
+    /// The fence beneath which changes were dropped when the document was saved, if it was
+    /// loaded from such a save.
+    pub(crate) dropped_history: Option<HistoryFence>,
+    /// The limits enforced on changes made to and applied to this document.
+    pub(crate) limits: Limits,
+    /// The schema local changes are checked against.
+    pub(crate) schema: Option<Schema>,
+    /// Metadata saved with the document but not part of its history.
+    pub(crate) headers: BTreeMap<String, Vec<u8>>,
+    /// How the actor is expected to be reused.
+    pub(crate) actor_policy: ActorPolicy,
+    /// The number of changes made with the current actor since it was set.
+    pub(crate) session_changes: u64,
+    /// The hooks run when a transaction is committed.
+    pub(crate) commit_hooks: CommitHooks,
 }
 
 impl Automerge {
@@ -74,21 +116,89 @@ impl Automerge {
             saved: Default::default(),
             actor: Actor::Unused(ActorId::random()),
             max_op: 0,
+            signer: None,
+            verifier: None,
+            config: Default::default(),
+            op_sources: Default::default(),
+            conflict_policies: Default::default(),
+            message_index: None,
+            history_fence: None,
+            dropped_history: None,
+            limits: Default::default(),
+            schema: None,
+            headers: BTreeMap::new(),
+            actor_policy: Default::default(),
+            session_changes: 0,
+            commit_hooks: Default::default(),
         }
     }
 
     /// Set the actor id for this document.
     pub fn with_actor(mut self, actor: ActorId) -> Self {
-        self.actor = Actor::Unused(actor);
+        self.set_actor(actor);
         self
     }
 
     /// Set the actor id for this document.
     pub fn set_actor(&mut self, actor: ActorId) -> &mut Self {
         self.actor = Actor::Unused(actor);
+        self.session_changes = 0;
+        self
+    }
+
+    /// Set the configuration of this document, see [`DocumentConfig`].
+    ///
+    /// This is intended for new documents. The configuration of a saved document is loaded along
+    /// with it.
+    pub fn with_config(mut self, config: DocumentConfig) -> Self {
+        self.config = config;
         self
     }
 
+    /// The configuration of this document, see [`DocumentConfig`].
+    pub fn config(&self) -> &DocumentConfig {
+        &self.config
+    }
+
+    /// Set the header `key` of this document to `value`.
+    ///
+    /// Headers are a small amount of metadata, such as a schema version or a migration flag,
+    /// which is saved along with the document by [`Self::save`] and read back by
+    /// [`Self::load`], but is not part of the document's history: setting a header doesn't make
+    /// a change, headers aren't sent to other peers or merged, and
+    /// [`Self::load_incremental`] ignores them.
+    pub fn set_header<K: Into<String>, V: Into<Vec<u8>>>(&mut self, key: K, value: V) {
+        self.headers.insert(key.into(), value.into());
+    }
+
+    /// The value of the header `key`, see [`Self::set_header`].
+    pub fn header(&self, key: &str) -> Option<&[u8]> {
+        self.headers.get(key).map(|v| v.as_slice())
+    }
+
+    /// Remove the header `key`, returning its value.
+    pub fn remove_header(&mut self, key: &str) -> Option<Vec<u8>> {
+        self.headers.remove(key)
+    }
+
+    /// The headers of this document in ascending order of key, see [`Self::set_header`].
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.headers.iter().map(|(k, v)| (k.as_str(), v.as_slice()))
+    }
+
+    /// Check that `theirs` matches our configuration, adopting it if we are a new document with
+    /// no configuration.
+    pub(crate) fn check_config(&mut self, theirs: &DocumentConfig) -> Result<(), AutomergeError> {
+        if &self.config == theirs {
+            Ok(())
+        } else if self.config.is_empty() && self.history.is_empty() && self.queue.is_empty() {
+            self.config = theirs.clone();
+            Ok(())
+        } else {
+            Err(AutomergeError::ConfigMismatch)
+        }
+    }
+
     /// Get the current actor id of this document.
     pub fn get_actor(&self) -> &ActorId {
         match &self.actor {
@@ -97,6 +207,63 @@ impl Automerge {
         }
     }
 
+    /// Set the node size of the trees used to store the ops of each object.
+    ///
+    /// This is a performance tuning knob which does not affect the content of the document. The
+    /// default is a fixed branching factor of 16, [`NodeSize::Adaptive`] increases the branching
+    /// factor for large objects such as long text. Existing objects are rebuilt with the new node
+    /// size.
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::InvalidNodeSize`] if `node_size` is a fixed branching factor of less than
+    /// 2, in which case the document is unchanged.
+    pub fn set_node_size(&mut self, node_size: NodeSize) -> Result<&mut Self, AutomergeError> {
+        node_size.validate()?;
+        self.ops.set_node_size(node_size);
+        Ok(self)
+    }
+
+    /// Set how many recent lookups of objects and of map keys are remembered, so that repeatedly
+    /// reading or writing the same keys of the same objects doesn't search for them each time.
+    ///
+    /// This is a performance tuning knob which does not affect the content of the document, and
+    /// it isn't saved. The default is 256 of each, zero turns the cache off.
+    pub fn set_cache_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.ops.lookup_cache.set_capacity(capacity);
+        self
+    }
+
+    /// Sign every change created by this document with `signer`.
+    ///
+    /// `signer` is passed the bytes returned by [`Change::signed_bytes`] and the signature it
+    /// returns is stored in the extra bytes of the change, after any data stored with
+    /// [`crate::transaction::CommitOptions::with_extra_bytes`], and returned by
+    /// [`Change::signature`].
+    pub fn set_signer<F>(&mut self, signer: F) -> &mut Self
+    where
+        F: Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static,
+    {
+        self.signer = Some(Signer::new(signer));
+        self
+    }
+
+    /// Check the signature of every change applied to this document with `verifier`.
+    ///
+    /// `verifier` is passed the actor ID of the change author, the bytes which were signed and the
+    /// signature (which will be empty for unsigned changes). If it returns `false` then applying
+    /// the changes fails with [`AutomergeError::InvalidSignature`]. This applies to
+    /// [`Self::apply_changes`], [`Self::load_incremental`] and
+    /// [`Self::receive_sync_message`]. Use [`Self::load_verified`] to check the changes in a saved
+    /// document.
+    pub fn set_verifier<F>(&mut self, verifier: F) -> &mut Self
+    where
+        F: Fn(&ActorId, &[u8], &[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.verifier = Some(Verifier::new(verifier));
+        self
+    }
+
     pub(crate) fn get_actor_index(&mut self) -> usize {
         match &mut self.actor {
             Actor::Unused(actor) => {
@@ -121,6 +288,12 @@ impl Automerge {
         }
     }
 
+    /// Start a transaction whose commit returns the change it made instead of applying it, see
+    /// [`DryRunTransaction`].
+    pub fn transaction_dry_run(&mut self) -> DryRunTransaction<'_> {
+        DryRunTransaction::new(self.transaction())
+    }
+
     pub fn transaction_with_observer<Obs: OpObserver>(
         &mut self,
         op_observer: Obs,
@@ -134,10 +307,11 @@ impl Automerge {
 
     pub(crate) fn transaction_inner(&mut self) -> TransactionInner {
         let actor = self.get_actor_index();
-        let seq = self.states.get(&actor).map_or(0, |v| v.len()) as u64 + 1;
+        let seq =
+            self.dropped_seqs(actor) + self.states.get(&actor).map_or(0, |v| v.len()) as u64 + 1;
         let mut deps = self.get_heads();
-        if seq > 1 {
-            let last_hash = self.get_hash(actor, seq - 1).unwrap();
+        // a last change which was dropped beneath a fence is already included by the heads
+        if let Ok(last_hash) = self.get_hash(actor, seq - 1) {
             if !deps.contains(&last_hash) {
                 deps.push(last_hash);
             }
@@ -152,6 +326,9 @@ impl Automerge {
             message: None,
             operations: vec![],
             deps,
+            source: None,
+            sources: Vec::new(),
+            next_insert: None,
         }
     }
 
@@ -264,14 +441,64 @@ impl Automerge {
         }
     }
 
+    /// Run a transaction on this document in a closure, committing it if the closure returns
+    /// `Ok` and rolling it back if it returns `Err`, like [`Self::transact`] but returning the
+    /// closure's own result so that it works with `?`.
+    ///
+    /// Returns the closure's result and the heads of the document once the transaction is
+    /// committed.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, Automerge, AutomergeError, ROOT};
+    /// let mut doc = Automerge::new();
+    /// let (count, heads) = doc.try_transact(|tx| {
+    ///     tx.put(ROOT, "count", 1)?;
+    ///     Ok::<_, AutomergeError>(1)
+    /// })?;
+    /// assert_eq!(heads, doc.get_heads());
+    /// # Ok::<(), AutomergeError>(())
+    /// ```
+    pub fn try_transact<F, O, E>(&mut self, f: F) -> Result<(O, Vec<ChangeHash>), E>
+    where
+        F: FnOnce(&mut Transaction<'_, UnObserved>) -> Result<O, E>,
+    {
+        match self.transact(f) {
+            Ok(success) => Ok((success.result, self.get_heads())),
+            Err(failure) => Err(failure.error),
+        }
+    }
+
+    /// Like [`Self::try_transact`] but also returning the patches made by the transaction, as
+    /// collected by a [`VecOpObserver`].
+    pub fn try_transact_observed<F, O, E>(
+        &mut self,
+        f: F,
+    ) -> Result<(O, Vec<Patch>, Vec<ChangeHash>), E>
+    where
+        F: FnOnce(&mut Transaction<'_, Observed<VecOpObserver>>) -> Result<O, E>,
+    {
+        match self.transact_observed(f) {
+            Ok(mut success) => Ok((
+                success.result,
+                success.op_observer.take_patches(),
+                self.get_heads(),
+            )),
+            Err(failure) => Err(failure.error),
+        }
+    }
+
     /// Fork this document at the current point for use by a different actor.
     pub fn fork(&self) -> Self {
         let mut f = self.clone();
         f.set_actor(ActorId::random());
+        f.commit_hooks = Default::default();
         f
     }
 
     /// Fork this document at the give heads
+    ///
+    /// Like [`Self::fork`] the fork keeps the settings of this document, such as its
+    /// configuration, conflict policies, numeric mode and actor policy, but not its commit hooks.
     pub fn fork_at(&self, heads: &[ChangeHash]) -> Result<Self, AutomergeError> {
         let mut seen = heads.iter().cloned().collect::<HashSet<_>>();
         let mut heads = heads.to_vec();
@@ -292,16 +519,75 @@ impl Automerge {
         }
         let mut f = Self::new();
         f.set_actor(ActorId::random());
+        f.signer = self.signer.clone();
+        f.verifier = self.verifier.clone();
+        f.limits = self.limits;
+        f.schema = self.schema.clone();
+        f.headers = self.headers.clone();
+        f.config = self.config.clone();
+        f.conflict_policies = self.conflict_policies.clone();
+        f.actor_policy = self.actor_policy;
+        f.ops.set_node_size(self.ops.node_size());
         f.apply_changes(changes.into_iter().rev().cloned())?;
+        if self.message_index.is_some() {
+            f.enable_message_index();
+        }
         Ok(f)
     }
 
+    /// Tell `op_observer` how the document changed between `from_heads` and `to_heads`.
+    ///
+    /// This lets something which last saw the document at `from_heads` catch up without
+    /// rebuilding its view of the whole document. Like [`Self::apply_changes_batched_with`] the
+    /// observer is told how each object differs rather than about every op, so a value which was
+    /// put and then overwritten in between is reported once, and objects which are not in the
+    /// document at `to_heads` are not reported at all. If `from_heads` isn't in the history of
+    /// `to_heads` the difference is from `from_heads` to the merge of both.
+    ///
+    /// ### Errors
+    ///
+    /// Returns an error if any of the heads are not changes in this document.
+    pub fn replay_changes<Obs: OpObserver>(
+        &self,
+        from_heads: &[ChangeHash],
+        to_heads: &[ChangeHash],
+        op_observer: &mut Obs,
+    ) -> Result<(), AutomergeError> {
+        let from = self.clock_at(from_heads)?;
+        let mut to = self.clock_at(to_heads)?;
+        to.merge(&from);
+        self.ops.observe_diff_between(&from, &to, op_observer);
+        Ok(())
+    }
+
+    /// Step through the states this document passed through as each change in its history was
+    /// applied, in causal order.
+    pub fn history_states(&self) -> HistoryStates<'_> {
+        HistoryStates::new(self)
+    }
+
+    /// Create a new document with the same content as `template` but none of its history.
+    ///
+    /// The content is copied in a single change made by a new random actor, so every object in
+    /// the new document has a fresh ID. Documents instantiated from the same template share no
+    /// changes, so merging them later will not interleave their contents.
+    pub fn instantiate_template(template: &Automerge) -> Result<Self, AutomergeError> {
+        let mut doc = Self::new();
+        let mut tx = doc.transaction();
+        copy_object(template, &ExId::Root, &mut tx, &ExId::Root)?;
+        tx.commit();
+        Ok(doc)
+    }
+
     // KeysAt::()
     // LenAt::()
     // PropAt::()
     // NthAt::()
 
-    /// Get the parents of an object in the document tree.
+    /// Get the parents of an object in the document tree, from the object it is in up to the root.
+    ///
+    /// Each item is the id of a parent, the prop of the parent the child is at and whether the
+    /// child is still there, see [`Parents`].
     ///
     /// ### Errors
     ///
@@ -317,13 +603,32 @@ impl Automerge {
         Ok(self.ops.parents(obj_id))
     }
 
+    /// Look up the paths of objects in this document without searching their parents, for
+    /// working out which part of a document each patch touches, see [`PathCache`].
+    pub fn path_cache(&self) -> PathCache<'_> {
+        PathCache::new(self)
+    }
+
+    /// Every op which has been applied to `obj`, see [`crate::RawOp`].
+    pub fn iter_ops<O: AsRef<ExId>>(&self, obj: O) -> Result<RawOps<'_>, AutomergeError> {
+        let obj = self.exid_to_obj(obj.as_ref())?;
+        Ok(RawOps {
+            iter: self.ops.iter_obj(&obj),
+            ops: &self.ops,
+        })
+    }
+
+    /// The path from the root to `obj`, as the object and prop of each step, see
+    /// [`Parents::path`].
+    ///
+    /// ### Errors
+    ///
+    /// Returns an error when the id given is not the id of an object in this document.
     pub fn path_to_object<O: AsRef<ExId>>(
         &self,
         obj: O,
     ) -> Result<Vec<(ExId, Prop)>, AutomergeError> {
-        let mut path = self.parents(obj.as_ref().clone())?.collect::<Vec<_>>();
-        path.reverse();
-        Ok(path)
+        Ok(self.parents(obj)?.path())
     }
 
     /// Get the keys of the object `obj`.
@@ -339,6 +644,36 @@ impl Automerge {
         }
     }
 
+    /// Turn this document into one which can't change, which can be shared between threads and
+    /// cloned without copying it, see [`FrozenDoc`].
+    pub fn freeze(mut self) -> FrozenDoc {
+        self.ops.lookup_cache.set_frozen(true);
+        FrozenDoc::new(self)
+    }
+
+    /// Take a cheap read-only copy of the current state of the document, which can be read from
+    /// other threads while this document carries on changing, see [`Snapshot`].
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::new(self)
+    }
+
+    /// Start a sequence of reads which all observe the current heads, see [`ReadTransaction`].
+    pub fn read_tx(&self) -> ReadTransaction<'_> {
+        ReadTransaction::new(self, self.get_heads())
+    }
+
+    /// Start a sequence of reads which all observe the document as of `heads`.
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::MissingHash`] if one of `heads` is not in this document.
+    pub fn read_tx_at(&self, heads: &[ChangeHash]) -> Result<ReadTransaction<'_>, AutomergeError> {
+        if let Some(missing) = heads.iter().find(|h| !self.has_change(h)) {
+            return Err(AutomergeError::MissingHash(*missing));
+        }
+        Ok(ReadTransaction::new(self, heads.to_vec()))
+    }
+
     /// Historical version of [`keys`](Self::keys).
     pub fn keys_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> KeysAt<'_, '_> {
         if let Ok(obj) = self.exid_to_obj(obj.as_ref()) {
@@ -350,15 +685,19 @@ impl Automerge {
     }
 
     /// Iterate over the keys and values of the map `obj` in the given range.
+    ///
+    /// Keys are kept in order, so the start and end of the range are found in time logarithmic
+    /// in the number of keys of the map, and iterating a narrow range of a map with many keys
+    /// only looks at the keys in the range.
     pub fn map_range<O: AsRef<ExId>, R: RangeBounds<String>>(
         &self,
         obj: O,
         range: R,
     ) -> MapRange<'_, R> {
         if let Ok(obj) = self.exid_to_obj(obj.as_ref()) {
-            MapRange::new(self, self.ops.map_range(obj, range))
+            MapRange::new(self, obj, self.ops.map_range(obj, range))
         } else {
-            MapRange::new(self, None)
+            MapRange::new(self, ObjId::root(), None)
         }
     }
 
@@ -372,10 +711,10 @@ impl Automerge {
         if let Ok(obj) = self.exid_to_obj(obj.as_ref()) {
             if let Ok(clock) = self.clock_at(heads) {
                 let iter_range = self.ops.map_range_at(obj, range, clock);
-                return MapRangeAt::new(self, iter_range);
+                return MapRangeAt::new(self, obj, heads, iter_range);
             }
         }
-        MapRangeAt::new(self, None)
+        MapRangeAt::new(self, ObjId::root(), heads, None)
     }
 
     /// Iterate over the indexes and values of the list `obj` in the given range.
@@ -385,9 +724,9 @@ impl Automerge {
         range: R,
     ) -> ListRange<'_, R> {
         if let Ok(obj) = self.exid_to_obj(obj.as_ref()) {
-            ListRange::new(self, self.ops.list_range(obj, range))
+            ListRange::new(self, obj, self.ops.list_range(obj, range))
         } else {
-            ListRange::new(self, None)
+            ListRange::new(self, ObjId::root(), None)
         }
     }
 
@@ -401,17 +740,19 @@ impl Automerge {
         if let Ok(obj) = self.exid_to_obj(obj.as_ref()) {
             if let Ok(clock) = self.clock_at(heads) {
                 let iter_range = self.ops.list_range_at(obj, range, clock);
-                return ListRangeAt::new(self, iter_range);
+                return ListRangeAt::new(self, obj, heads, iter_range);
             }
         }
-        ListRangeAt::new(self, None)
+        ListRangeAt::new(self, ObjId::root(), heads, None)
     }
 
     pub fn values<O: AsRef<ExId>>(&self, obj: O) -> Values<'_> {
         if let Ok(obj) = self.exid_to_obj(obj.as_ref()) {
             match self.ops.object_type(&obj) {
-                Some(t) if t.is_sequence() => Values::new(self, self.ops.list_range(obj, ..)),
-                Some(_) => Values::new(self, self.ops.map_range(obj, ..)),
+                Some(t) if t.is_sequence() => {
+                    Values::new(self, obj, None, self.ops.list_range(obj, ..))
+                }
+                Some(_) => Values::new(self, obj, None, self.ops.map_range(obj, ..)),
                 None => Values::empty(self),
             }
         } else {
@@ -425,11 +766,11 @@ impl Automerge {
                 return match self.ops.object_type(&obj) {
                     Some(ObjType::Map) | Some(ObjType::Table) => {
                         let iter_range = self.ops.map_range_at(obj, .., clock);
-                        Values::new(self, iter_range)
+                        Values::new(self, obj, Some(heads), iter_range)
                     }
                     Some(ObjType::List) | Some(ObjType::Text) => {
                         let iter_range = self.ops.list_range_at(obj, .., clock);
-                        Values::new(self, iter_range)
+                        Values::new(self, obj, Some(heads), iter_range)
                     }
                     None => Values::empty(self),
                 };
@@ -475,6 +816,38 @@ impl Automerge {
         self.ops.object_type(&obj)
     }
 
+    /// Storage statistics for `obj`, including the number of deleted elements whose operations
+    /// are still kept.
+    ///
+    /// Only `obj` itself is counted, not any objects nested inside it.
+    pub fn object_stats<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjectStats, AutomergeError> {
+        let obj = self.exid_to_obj(obj.as_ref())?;
+        // SAFETY: exid_to_obj only returns objects which exist
+        Ok(self.ops.object_stats(&obj).unwrap())
+    }
+
+    /// Storage statistics for every object in the document.
+    pub fn document_stats(&self) -> DocumentStats {
+        self.ops.document_stats()
+    }
+
+    /// An estimate of the memory used by the document.
+    ///
+    /// Strings longer than 23 bytes which are put more than once share their storage, so a
+    /// document which puts the same long string many times counts it once in
+    /// [`MemoryUsage::values`]. Shorter strings are stored inside the op which puts them and are
+    /// counted in [`MemoryUsage::ops`].
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = self.ops.memory_usage();
+        usage.history = self
+            .history
+            .iter()
+            .chain(self.queue.iter())
+            .map(|change| change.raw_bytes().len())
+            .sum();
+        usage
+    }
+
     pub(crate) fn exid_to_obj(&self, id: &ExId) -> Result<ObjId, AutomergeError> {
         match id {
             ExId::Root => Ok(ObjId::root()),
@@ -483,6 +856,8 @@ impl Automerge {
                 // bounds
                 let obj = if self.ops.m.actors.cache.get(*idx) == Some(actor) {
                     ObjId(OpId(*ctr, *idx))
+                } else if let Some(obj) = self.ops.lookup_cache.object(id) {
+                    return Ok(obj);
                 } else {
                     // FIXME - make a real error
                     let idx = self
@@ -491,7 +866,11 @@ impl Automerge {
                         .actors
                         .lookup(actor)
                         .ok_or(AutomergeError::Fail)?;
-                    ObjId(OpId(*ctr, idx))
+                    let obj = ObjId(OpId(*ctr, idx));
+                    if self.ops.object_type(&obj).is_some() {
+                        self.ops.lookup_cache.insert_object(id.clone(), obj);
+                    }
+                    obj
                 };
                 if self.ops.object_type(&obj).is_some() {
                     Ok(obj)
@@ -502,6 +881,21 @@ impl Automerge {
         }
     }
 
+    /// The op id `id` refers to, which unlike [`Self::exid_to_obj`] need not be an object.
+    pub(crate) fn exid_to_opid(&self, id: &ExId) -> Option<OpId> {
+        match id {
+            ExId::Root => None,
+            ExId::Id(ctr, actor, idx) => {
+                let idx = if self.ops.m.actors.cache.get(*idx) == Some(actor) {
+                    *idx
+                } else {
+                    self.ops.m.actors.lookup(actor)?
+                };
+                Some(OpId(*ctr, idx))
+            }
+        }
+    }
+
     pub(crate) fn id_to_exid(&self, id: OpId) -> ExId {
         self.ops.id_to_exid(id)
     }
@@ -553,7 +947,9 @@ impl Automerge {
         obj: O,
         prop: P,
     ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
-        Ok(self.get_all(obj, prop.into())?.last().cloned())
+        let prop = prop.into();
+        let values = self.get_all(obj.as_ref(), prop.clone())?;
+        Ok(self.resolve_conflict(obj.as_ref(), &prop, values))
     }
 
     /// Historical version of [`get`](Self::get).
@@ -563,13 +959,223 @@ impl Automerge {
         prop: P,
         heads: &[ChangeHash],
     ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
-        Ok(self.get_all_at(obj, prop, heads)?.last().cloned())
+        let prop = prop.into();
+        let values = self.get_all_at(obj.as_ref(), prop.clone(), heads)?;
+        Ok(self.resolve_conflict(obj.as_ref(), &prop, values))
+    }
+
+    /// Get the string at `prop` in `obj`.
+    ///
+    /// # Errors
+    ///
+    /// As well as the errors [`Self::get`] can return this returns
+    /// [`AutomergeError::InvalidValueType`] if the value is not a string. Text objects are not
+    /// strings, use [`Self::text`] to read them.
+    pub fn get_string<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<String>, AutomergeError> {
+        typed::string(self.get(obj, prop)?)
+    }
+
+    /// Get the signed integer at `prop` in `obj`, see [`Self::get_string`].
+    pub fn get_int<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<i64>, AutomergeError> {
+        typed::int(self.get(obj, prop)?)
+    }
+
+    /// Get the unsigned integer at `prop` in `obj`, see [`Self::get_string`].
+    pub fn get_uint<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<u64>, AutomergeError> {
+        typed::uint(self.get(obj, prop)?)
+    }
+
+    /// Get the float at `prop` in `obj`, see [`Self::get_string`].
+    pub fn get_f64<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<f64>, AutomergeError> {
+        typed::f64(self.get(obj, prop)?)
+    }
+
+    /// Get the boolean at `prop` in `obj`, see [`Self::get_string`].
+    pub fn get_bool<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<bool>, AutomergeError> {
+        typed::bool(self.get(obj, prop)?)
+    }
+
+    /// Get the bytes at `prop` in `obj`, see [`Self::get_string`].
+    pub fn get_bytes<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<Vec<u8>>, AutomergeError> {
+        typed::bytes(self.get(obj, prop)?)
+    }
+
+    /// Get the id of the list at `prop` in `obj`, see [`Self::get_string`].
+    pub fn get_list<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<ExId>, AutomergeError> {
+        typed::list(self.get(obj, prop)?)
+    }
+
+    /// Get the id of the map at `prop` in `obj`, see [`Self::get_string`].
+    pub fn get_map<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<ExId>, AutomergeError> {
+        typed::map(self.get(obj, prop)?)
+    }
+
+    /// Read the bytes at `prop` of `obj`, which may be a single bytes value or a list of chunks
+    /// written by [`crate::transaction::Transactable::put_bytes_stream`], without copying them
+    /// into one buffer.
+    pub fn get_bytes_reader<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<BytesReader<'_>>, AutomergeError> {
+        match self.get(obj, prop)? {
+            Some((value, id)) => BytesReader::new(value, || self.list_range(id, ..)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Set the policy which chooses the value [`Self::get`] returns when the map key `key` of
+    /// `obj` has conflicting values, or remove it with `None`.
+    ///
+    /// A policy for a key takes precedence over one for its whole object, see
+    /// [`Self::set_object_conflict_policy`].
+    pub fn set_conflict_policy<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        key: &str,
+        policy: Option<ConflictPolicy>,
+    ) -> Result<(), AutomergeError> {
+        let obj = self.exid_to_obj(obj.as_ref())?;
+        self.conflict_policies.set_key(obj, key.to_string(), policy);
+        Ok(())
+    }
+
+    /// Set the policy for every key or element of `obj` which doesn't have its own, or remove it
+    /// with `None`, see [`ConflictPolicy`].
+    pub fn set_object_conflict_policy<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        policy: Option<ConflictPolicy>,
+    ) -> Result<(), AutomergeError> {
+        let obj = self.exid_to_obj(obj.as_ref())?;
+        self.conflict_policies.set_object(obj, policy);
+        Ok(())
+    }
+
+    /// Set the policy for conflicts between values which are all of type `ty`, or remove it
+    /// with `None`. Policies for a key or an object take precedence over one for a type.
+    pub fn set_type_conflict_policy(&mut self, ty: SchemaType, policy: Option<ConflictPolicy>) {
+        self.conflict_policies.set_type(ty, policy);
+    }
+
+    /// Choose the winner of `values`, which are in the order returned by `get_all`.
+    pub(crate) fn resolve_conflict<'a>(
+        &self,
+        obj: &ExId,
+        prop: &Prop,
+        mut values: Vec<(Value<'a>, ExId)>,
+    ) -> Option<(Value<'a>, ExId)> {
+        if values.len() > 1 && !self.conflict_policies.is_empty() {
+            let key = match prop {
+                Prop::Map(key) => Some(key.as_str()),
+                Prop::Seq(_) => None,
+            };
+            let policy = self
+                .exid_to_obj(obj)
+                .ok()
+                .and_then(|obj| self.conflict_policies.get(obj, key, &values));
+            let winner = policy.and_then(|p| p.choose(&values, |id| self.op_timestamp(id)));
+            if let Some(winner) = winner {
+                return Some(values.swap_remove(winner));
+            }
+        }
+        values.pop()
+    }
+
+    /// The value the conflict policies choose for `prop` of `obj`, as of `heads` or now, where
+    /// `winner` is the value with the greatest id, which is what [`Self::get`] returns without
+    /// a policy.
+    pub(crate) fn with_conflict_policy<'a>(
+        &'a self,
+        obj: &ObjId,
+        prop: Prop,
+        winner: (Value<'a>, ExId),
+        heads: Option<&[ChangeHash]>,
+    ) -> (Value<'a>, ExId) {
+        if self.conflict_policies.is_empty() {
+            return winner;
+        }
+        let obj = self.id_to_exid(obj.0);
+        let values = match heads {
+            Some(heads) => self.get_all_at(&obj, prop.clone(), heads),
+            None => self.get_all(&obj, prop.clone()),
+        };
+        values
+            .ok()
+            .and_then(|values| self.resolve_conflict(&obj, &prop, values))
+            .unwrap_or(winner)
+    }
+
+    /// Report the ops in `buffer` to `observer`, with the value of each conflicting put chosen
+    /// by the conflict policies.
+    ///
+    /// The ops are reported once all of the changes they come from are applied, so the parents
+    /// of an object, and the value of a conflicting put, are those of the updated document.
+    fn replay_with_conflict_policies<Obs: OpObserver>(
+        &self,
+        buffer: PolicyBuffer,
+        observer: &mut Obs,
+    ) {
+        buffer.replay(
+            observer,
+            |obj| self.exid_to_obj(obj).ok().map(|obj| self.ops.parents(obj)),
+            |obj, prop, winner| {
+                self.get_all(obj, prop.clone())
+                    .ok()
+                    .and_then(|values| self.resolve_conflict(obj, prop, values))
+                    .map(|(value, id)| (value.into_owned(), id))
+                    .unwrap_or(winner)
+            },
+        )
+    }
+
+    /// The timestamp of the change containing the op `id`, ops which are not in a change yet
+    /// are later than every change.
+    fn op_timestamp(&self, id: &ExId) -> i64 {
+        self.exid_to_opid(id)
+            .and_then(|id| self.change_index_for_op(id))
+            .map(|i| self.history[i].timestamp())
+            .unwrap_or(i64::MAX)
     }
 
     /// Get all conflicting values out of the document at this prop that conflict.
     ///
     /// Returns both the value and the id of the operation that created it, useful for handling
-    /// conflicts and serves as the object id if the value is an object.
+    /// conflicts and serves as the object id if the value is an object. The values are ordered
+    /// by id, so without a [`crate::ConflictPolicy`] the last of them is the one [`Self::get`]
+    /// returns.
     pub fn get_all<O: AsRef<ExId>, P: Into<Prop>>(
         &self,
         obj: O,
@@ -581,7 +1187,7 @@ impl Automerge {
                 let prop = self.ops.m.props.lookup(&p);
                 if let Some(p) = prop {
                     self.ops
-                        .search(&obj, query::Prop::new(p))
+                        .search_prop(&obj, p)
                         .ops
                         .into_iter()
                         .map(|o| (o.value(), self.id_to_exid(o.id)))
@@ -592,13 +1198,13 @@ impl Automerge {
             }
             Prop::Seq(n) => self
                 .ops
-                .search(&obj, query::Nth::new(n))
+                .search_nth(&obj, n)
                 .ops
                 .into_iter()
                 .map(|o| (o.value(), self.id_to_exid(o.id)))
                 .collect(),
         };
-        result.sort_by(|a, b| b.1.cmp(&a.1));
+        result.sort_by(|a, b| a.1.cmp(&b.1));
         Ok(result)
     }
 
@@ -612,7 +1218,7 @@ impl Automerge {
         let prop = prop.into();
         let obj = self.exid_to_obj(obj.as_ref())?;
         let clock = self.clock_at(heads)?;
-        let result = match prop {
+        let mut result = match prop {
             Prop::Map(p) => {
                 let prop = self.ops.m.props.lookup(&p);
                 if let Some(p) = prop {
@@ -634,6 +1240,7 @@ impl Automerge {
                 .map(|o| (o.clone_value(), self.id_to_exid(o.id)))
                 .collect(),
         };
+        result.sort_by(|a, b| a.1.cmp(&b.1));
         Ok(result)
     }
 
@@ -642,6 +1249,18 @@ impl Automerge {
         Self::load_with::<()>(data, None)
     }
 
+    /// Load a document saved with [`Self::save_with_codec`], decoding each chunk with `codec`.
+    ///
+    /// Chunks which weren't encoded are loaded as they are.
+    ///
+    /// ### Errors
+    ///
+    /// Returns an error if a chunk was encoded with a codec with a different [`ChunkCodec::id`],
+    /// or `codec` fails to decode a chunk, as well as for all the reasons [`Self::load`] does.
+    pub fn load_with_codec<C: ChunkCodec>(data: &[u8], codec: &C) -> Result<Self, AutomergeError> {
+        Self::load(&storage::encoded::decode(data, codec)?)
+    }
+
     /// Load a document.
     #[tracing::instrument(skip(data, observer), err)]
     pub fn load_with<Obs: OpObserver>(
@@ -652,57 +1271,42 @@ impl Automerge {
             tracing::trace!("no data, initializing empty document");
             return Ok(Self::new());
         }
+        let config = load::config(data)?.unwrap_or_default();
+        let headers = load::headers(data)?;
         tracing::trace!("loading first chunk");
-        let (remaining, first_chunk) = storage::Chunk::parse(storage::parse::Input::new(data))
-            .map_err(|e| load::Error::Parse(Box::new(e)))?;
-        if !first_chunk.checksum_valid() {
-            return Err(load::Error::BadChecksum.into());
-        }
+        let mut input = storage::parse::Input::new(data);
+        let (mut remaining, first_chunk) = loop {
+            let (remaining, chunk) =
+                storage::Chunk::parse(input).map_err(|e| load::Error::Parse(Box::new(e)))?;
+            if !chunk.checksum_valid() {
+                return Err(load::Error::BadChecksum.into());
+            }
+            match chunk {
+                storage::Chunk::Config(..) | storage::Chunk::Headers(..)
+                    if remaining.is_empty() =>
+                {
+                    let mut am = Self::new().with_config(config);
+                    am.headers = headers;
+                    return Ok(am);
+                }
+                storage::Chunk::Config(..) | storage::Chunk::Headers(..) => {
+                    input = remaining.reset()
+                }
+                chunk => break (remaining, chunk),
+            }
+        };
 
         let mut am = match first_chunk {
             storage::Chunk::Document(d) => {
                 tracing::trace!("first chunk is document chunk, inflating");
-                let storage::load::Reconstructed {
-                    max_op,
-                    result: op_set,
-                    changes,
-                    heads,
-                } = match &mut observer {
-                    Some(o) => storage::load::reconstruct_document(&d, OpSet::observed_builder(*o)),
-                    None => storage::load::reconstruct_document(&d, OpSet::builder()),
-                }
-                .map_err(|e| load::Error::InflateDocument(Box::new(e)))?;
-                let mut hashes_by_index = HashMap::new();
-                let mut actor_to_history: HashMap<usize, Vec<usize>> = HashMap::new();
-                let mut clocks = Clocks::new();
-                for (index, change) in changes.iter().enumerate() {
-                    // SAFETY: This should be fine because we just constructed an opset containing
-                    // all the changes
-                    let actor_index = op_set.m.actors.lookup(change.actor_id()).unwrap();
-                    actor_to_history.entry(actor_index).or_default().push(index);
-                    hashes_by_index.insert(index, change.hash());
-                    clocks.add_change(change, actor_index)?;
-                }
-                let history_index = hashes_by_index.into_iter().map(|(k, v)| (v, k)).collect();
-                Self {
-                    queue: vec![],
-                    history: changes,
-                    history_index,
-                    states: actor_to_history,
-                    clocks: clocks.into(),
-                    ops: op_set,
-                    deps: heads.into_iter().collect(),
-                    saved: Default::default(),
-                    actor: Actor::Unused(ActorId::random()),
-                    max_op,
-                }
+                Self::from_document(&d, &mut observer)?
             }
             storage::Chunk::Change(stored_change) => {
                 tracing::trace!("first chunk is change chunk, applying");
                 let change = Change::new_from_unverified(stored_change.into_owned(), None)
                     .map_err(|e| load::Error::InvalidChangeColumns(Box::new(e)))?;
                 let mut am = Self::new();
-                am.apply_change(change, &mut observer);
+                am.apply_change(change, &mut observer)?;
                 am
             }
             storage::Chunk::CompressedChange(stored_change, compressed) => {
@@ -713,15 +1317,37 @@ impl Automerge {
                 )
                 .map_err(|e| load::Error::InvalidChangeColumns(Box::new(e)))?;
                 let mut am = Self::new();
-                am.apply_change(change, &mut observer);
+                am.apply_change(change, &mut observer)?;
                 am
             }
+            storage::Chunk::Fence(_, fence) => {
+                tracing::trace!("first chunk is fence chunk, inflating the document beneath it");
+                let (rest, chunk) = storage::Chunk::parse(remaining.reset())
+                    .map_err(|e| load::Error::Parse(Box::new(e)))?;
+                if !chunk.checksum_valid() {
+                    return Err(load::Error::BadChecksum.into());
+                }
+                remaining = rest;
+                match chunk {
+                    storage::Chunk::Document(d) => Self::from_fenced(&fence, &d, &mut observer)?,
+                    _ => return Err(load::Error::Fenced.into()),
+                }
+            }
+            storage::Chunk::Config(..) | storage::Chunk::Headers(..) => {
+                unreachable!("config and headers chunks are skipped above")
+            }
         };
+        am.config = config;
+        am.headers = headers;
         tracing::trace!("first chunk loaded, loading remaining chunks");
         match load::load_changes(remaining.reset()) {
+            // changes which don't follow the fence can't be applied, so check them
+            load::LoadedChanges::Complete(c) if am.dropped_history.is_some() => {
+                am.apply_changes_with(c, observer)?;
+            }
             load::LoadedChanges::Complete(c) => {
                 for change in c {
-                    am.apply_change(change, &mut observer);
+                    am.apply_change(change, &mut observer)?;
                 }
             }
             load::LoadedChanges::Partial { error, .. } => return Err(error.into()),
@@ -729,6 +1355,202 @@ impl Automerge {
         Ok(am)
     }
 
+    /// Load a saved document without decoding its ops, see [`LazyDocument`].
+    ///
+    /// `data` must be the output of [`Self::save`], i.e. a single document chunk, optionally
+    /// preceded by a configuration chunk.
+    pub fn load_lazy(data: &[u8]) -> Result<LazyDocument, AutomergeError> {
+        LazyDocument::load(data)
+    }
+
+    /// Load the objects of a saved document whose paths are chosen by `filter`, see
+    /// [`LazyDocument`].
+    ///
+    /// `filter` is passed the path from the root to each object which can be reached from the
+    /// root, starting with the root itself, whose path is empty. The objects it returns `false`
+    /// for are never decoded, and reading them returns [`AutomergeError::NotLoaded`], so a
+    /// client which only needs part of a large document only pays for the memory of that part.
+    /// Objects are decoded one at a time, in a single pass over the ops.
+    ///
+    /// The document keeps the saved data, which is much more compact than the decoded ops, so
+    /// it can be turned into a complete document to edit or merge with
+    /// [`LazyDocument::into_automerge`].
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, Prop, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let settings = doc.put_object(ROOT, "settings", ObjType::Map).unwrap();
+    /// doc.put(&settings, "theme", "dark").unwrap();
+    /// doc.put_object(ROOT, "archive", ObjType::List).unwrap();
+    ///
+    /// let mut partial = automerge::Automerge::load_filtered(&doc.save(), |path| {
+    ///     path.is_empty() || path[0] == Prop::from("settings")
+    /// })
+    /// .unwrap();
+    /// assert!(partial.get(&settings, "theme").unwrap().is_some());
+    /// ```
+    pub fn load_filtered<F>(data: &[u8], filter: F) -> Result<LazyDocument, AutomergeError>
+    where
+        F: FnMut(&[Prop]) -> bool,
+    {
+        LazyDocument::load_filtered(data, filter)
+    }
+
+    /// Reconstruct a document from a parsed document chunk.
+    pub(crate) fn from_document<Obs: OpObserver>(
+        d: &storage::Document<'_>,
+        observer: &mut Option<&mut Obs>,
+    ) -> Result<Self, AutomergeError> {
+        let storage::load::Reconstructed {
+            max_op,
+            result: op_set,
+            changes,
+            heads,
+        } = match observer {
+            Some(o) => storage::load::reconstruct_document(d, OpSet::observed_builder(*o)),
+            None => storage::load::reconstruct_document(d, OpSet::builder()),
+        }
+        .map_err(|e| load::Error::InflateDocument(Box::new(e)))?;
+        let mut hashes_by_index = HashMap::new();
+        let mut actor_to_history: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut clocks = Clocks::new();
+        for (index, change) in changes.iter().enumerate() {
+            // SAFETY: This should be fine because we just constructed an opset containing
+            // all the changes
+            let actor_index = op_set.m.actors.lookup(change.actor_id()).unwrap();
+            actor_to_history.entry(actor_index).or_default().push(index);
+            hashes_by_index.insert(index, change.hash());
+            clocks.add_change(change, actor_index)?;
+        }
+        let history_index = hashes_by_index.into_iter().map(|(k, v)| (v, k)).collect();
+        Ok(Self {
+            queue: vec![],
+            history: changes,
+            history_index,
+            states: actor_to_history,
+            clocks: clocks.into(),
+            ops: op_set,
+            deps: heads.into_iter().collect(),
+            saved: Default::default(),
+            actor: Actor::Unused(ActorId::random()),
+            max_op,
+            signer: None,
+            verifier: None,
+            config: Default::default(),
+            op_sources: Default::default(),
+            conflict_policies: Default::default(),
+            message_index: None,
+            history_fence: None,
+            dropped_history: None,
+            limits: Default::default(),
+            schema: None,
+            headers: BTreeMap::new(),
+            actor_policy: Default::default(),
+            session_changes: 0,
+            commit_hooks: Default::default(),
+        })
+    }
+
+    /// Load a document, see [`LoadOptions`].
+    pub fn load_with_options(data: &[u8], options: &LoadOptions) -> Result<Self, AutomergeError> {
+        if options.verification == VerificationMode::Strict {
+            storage::verify::strict(data)?;
+        }
+        let doc = Self::load_with_threads(data, options.threads)?;
+        if options.limits == Limits::default() {
+            return Ok(doc);
+        }
+        let doc = doc.with_limits(options.limits);
+        doc.check_loaded()?;
+        Ok(doc)
+    }
+
+    fn load_with_threads(data: &[u8], threads: usize) -> Result<Self, AutomergeError> {
+        #[cfg(feature = "rayon")]
+        if threads > 1 {
+            if let Ok(pool) = rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+                return pool.install(|| Self::load_parallel(data));
+            }
+        }
+        #[cfg(not(feature = "rayon"))]
+        let _ = threads;
+        Self::load(data)
+    }
+
+    #[cfg(feature = "rayon")]
+    fn load_parallel(data: &[u8]) -> Result<Self, AutomergeError> {
+        use rayon::prelude::*;
+
+        let chunks = load::split_chunks(data)
+            .into_iter()
+            .filter(|c| !load::is_settings_chunk(c))
+            .collect::<Vec<_>>();
+        if chunks.first().map_or(false, |c| load::is_fence_chunk(c)) {
+            return Self::load(data);
+        }
+        let (first, rest) = match chunks.split_first() {
+            Some((first, rest)) if load::is_document_chunk(first) => (Some(*first), rest),
+            _ => (None, &chunks[..]),
+        };
+        let (doc, changes) = rayon::join(
+            || first.map(Self::load).transpose(),
+            || {
+                rest.par_iter()
+                    .map(
+                        |chunk| match load::load_changes(storage::parse::Input::new(chunk)) {
+                            load::LoadedChanges::Complete(c) => Ok(c),
+                            load::LoadedChanges::Partial { error, .. } => Err(error),
+                        },
+                    )
+                    .collect::<Result<Vec<_>, _>>()
+            },
+        );
+        let mut doc = doc?.unwrap_or_default();
+        doc.config = load::config(data)?.unwrap_or_default();
+        doc.headers = load::headers(data)?;
+        doc.apply_changes(changes?.into_iter().flatten())?;
+        Ok(doc)
+    }
+
+    /// Load a document, calling `progress` after each change is applied and stopping early if
+    /// `cancel` is cancelled.
+    ///
+    /// Unlike [`Self::load`] this applies the changes in `data` one at a time, which is slower for
+    /// saved documents but means there is progress to report.
+    pub fn load_with_progress<F>(
+        data: &[u8],
+        progress: F,
+        cancel: &CancellationToken,
+    ) -> Result<Self, AutomergeError>
+    where
+        F: FnMut(ApplyProgress),
+    {
+        let changes = match load::load_changes(storage::parse::Input::new(data)) {
+            load::LoadedChanges::Complete(c) => c,
+            load::LoadedChanges::Partial { error, .. } => return Err(error.into()),
+        };
+        let mut doc = Self::new().with_config(load::config(data)?.unwrap_or_default());
+        doc.headers = load::headers(data)?;
+        doc.apply_changes_with_progress(changes, progress, cancel)?;
+        Ok(doc)
+    }
+
+    /// Load a document, checking the signature of every change with `verifier`.
+    ///
+    /// The verifier is retained for subsequent changes, see [`Self::set_verifier`].
+    pub fn load_verified<F>(data: &[u8], verifier: F) -> Result<Self, AutomergeError>
+    where
+        F: Fn(&ActorId, &[u8], &[u8]) -> bool + Send + Sync + 'static,
+    {
+        let mut doc = Self::load(data)?;
+        let verifier = Verifier::new(verifier);
+        for change in doc.history.iter().chain(doc.queue.iter()) {
+            verifier.verify(change)?;
+        }
+        doc.verifier = Some(verifier);
+        Ok(doc)
+    }
+
     /// Load an incremental save of a document.
     pub fn load_incremental(&mut self, data: &[u8]) -> Result<usize, AutomergeError> {
         self.load_incremental_with::<()>(data, None)
@@ -740,8 +1562,15 @@ impl Automerge {
         data: &[u8],
         op_observer: Option<&mut Obs>,
     ) -> Result<usize, AutomergeError> {
+        if let Some(config) = load::config(data)? {
+            self.check_config(&config)?;
+        }
         let changes = match load::load_changes(storage::parse::Input::new(data)) {
             load::LoadedChanges::Complete(c) => c,
+            load::LoadedChanges::Partial {
+                error: load::Error::Fenced,
+                ..
+            } => return Err(load::Error::Fenced.into()),
             load::LoadedChanges::Partial { error, loaded, .. } => {
                 tracing::warn!(successful_chunks=loaded.len(), err=?error, "partial load");
                 loaded
@@ -757,7 +1586,7 @@ impl Automerge {
         let mut dup = false;
         if let Some(actor_index) = self.ops.m.actors.lookup(change.actor_id()) {
             if let Some(s) = self.states.get(&actor_index) {
-                dup = s.len() >= change.seq() as usize;
+                dup = self.dropped_seqs(actor_index) + s.len() as u64 >= change.seq();
             }
         }
         dup
@@ -771,54 +1600,306 @@ impl Automerge {
         self.apply_changes_with::<_, ()>(changes, None)
     }
 
+    /// Apply changes to this document like [`Self::apply_changes`], telling `observer` about
+    /// each op they apply, see [`Self::merge_and_observe`].
+    pub fn apply_changes_and_observe<Obs: OpObserver>(
+        &mut self,
+        changes: impl IntoIterator<Item = Change>,
+        observer: &mut Obs,
+    ) -> Result<(), AutomergeError> {
+        self.apply_changes_with(changes, Some(observer))
+    }
+
     /// Apply changes to this document.
     pub fn apply_changes_with<I: IntoIterator<Item = Change>, Obs: OpObserver>(
         &mut self,
         changes: I,
         mut op_observer: Option<&mut Obs>,
     ) -> Result<(), AutomergeError> {
+        let changes = changes.into_iter().collect::<Vec<_>>();
+        self.apply_changes_inner(changes, &mut op_observer, |_| (), None)
+    }
+
+    /// Apply changes to this document, calling `progress` after each change is applied and
+    /// stopping early if `cancel` is cancelled.
+    ///
+    /// Cancellation is checked between changes, and a cancelled call returns
+    /// [`AutomergeError::Cancelled`]. The changes are applied to a copy of the document which
+    /// replaces it only once they are all applied, so a cancelled or failed call leaves the
+    /// document, and the queue of changes waiting for their dependencies, as they were before.
+    pub fn apply_changes_with_progress<I, F>(
+        &mut self,
+        changes: I,
+        progress: F,
+        cancel: &CancellationToken,
+    ) -> Result<(), AutomergeError>
+    where
+        I: IntoIterator<Item = Change>,
+        F: FnMut(ApplyProgress),
+    {
+        self.apply_changes_with_progress_inner::<_, _, ()>(changes, None, progress, cancel)
+    }
+
+    pub(crate) fn apply_changes_with_progress_inner<I, F, Obs>(
+        &mut self,
+        changes: I,
+        mut op_observer: Option<&mut Obs>,
+        mut progress: F,
+        cancel: &CancellationToken,
+    ) -> Result<(), AutomergeError>
+    where
+        I: IntoIterator<Item = Change>,
+        F: FnMut(ApplyProgress),
+        Obs: OpObserver,
+    {
+        let changes = changes
+            .into_iter()
+            .filter(|c| !self.has_applied(c))
+            .collect::<Vec<_>>();
+        let batch = changes.iter().map(|c| c.hash()).collect::<HashSet<_>>();
+        let mut report = ApplyProgress {
+            applied: 0,
+            total: batch.len(),
+        };
+        progress(report);
+        let mut doc = self.clone();
+        let mut branch = op_observer.as_ref().map(|o| o.branch());
+        doc.apply_changes_inner(
+            changes,
+            &mut branch.as_mut(),
+            |hash| {
+                if batch.contains(hash) {
+                    report.applied += 1;
+                    progress(report);
+                }
+            },
+            Some(cancel),
+        )?;
+        *self = doc;
+        if let (Some(observer), Some(branch)) = (op_observer.as_mut(), branch) {
+            observer.merge(&branch);
+        }
+        Ok(())
+    }
+
+    /// Apply `changes` once their dependencies have been applied, calling `applied` with the hash
+    /// of each change applied, including queued changes which became ready. If `cancel` is
+    /// cancelled this stops between changes, leaving the document partly updated.
+    fn apply_changes_inner<F, Obs>(
+        &mut self,
+        changes: Vec<Change>,
+        op_observer: &mut Option<&mut Obs>,
+        mut applied: F,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<(), AutomergeError>
+    where
+        F: FnMut(&ChangeHash),
+        Obs: OpObserver,
+    {
+        if let Some(verifier) = &self.verifier {
+            for c in &changes {
+                verifier.verify(c)?;
+            }
+        }
+        for c in &changes {
+            self.check_change(c)?;
+        }
+        let cancelled = || cancel.map(|c| c.is_cancelled()).unwrap_or(false);
         for c in changes {
-            if !self.history_index.contains_key(&c.hash()) {
-                if self.duplicate_seq(&c) {
-                    return Err(AutomergeError::DuplicateSeqNumber(
-                        c.seq(),
-                        c.actor_id().clone(),
-                    ));
+            if cancelled() {
+                return Err(AutomergeError::Cancelled);
+            }
+            if self.has_applied(&c) {
+                continue;
+            }
+            if self.duplicate_seq(&c) {
+                return Err(AutomergeError::DuplicateSeqNumber(
+                    c.seq(),
+                    c.actor_id().clone(),
+                ));
+            }
+            if self.is_causally_ready(&c) {
+                let hash = c.hash();
+                self.apply_change(c, op_observer)?;
+                applied(&hash);
+            } else {
+                self.queue.push(c);
+            }
+        }
+        loop {
+            if cancelled() {
+                return Err(AutomergeError::Cancelled);
+            }
+            let c = match self.pop_next_causally_ready_change() {
+                Some(c) => c,
+                None => break,
+            };
+            if !self.has_applied(&c) {
+                let hash = c.hash();
+                self.apply_change(c, op_observer)?;
+                applied(&hash);
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply a large batch of changes to this document, such as those queued up while offline.
+    ///
+    /// This ends in the same state as [`Self::apply_changes`], but is faster when there are many
+    /// changes. The changes are put in causal order once, rather than searching the queue for the
+    /// next ready change after each one is applied.
+    pub fn apply_changes_batched(
+        &mut self,
+        changes: impl IntoIterator<Item = Change>,
+    ) -> Result<(), AutomergeError> {
+        self.apply_changes_batched_with::<_, ()>(changes, None)
+    }
+
+    /// Apply a large batch of changes to this document, like [`Self::apply_changes_batched`],
+    /// and report how they changed the document to `op_observer`.
+    ///
+    /// Rather than reporting every op as it is applied, the observer is told once the whole
+    /// batch is applied how each object the batch touched differs from before it. So a value
+    /// which was put and then overwritten within the batch is reported once, and objects which
+    /// are no longer in the document are not reported at all. Only the changes which could be
+    /// applied are reported, the rest are queued as usual.
+    pub fn apply_changes_batched_with<I: IntoIterator<Item = Change>, Obs: OpObserver>(
+        &mut self,
+        changes: I,
+        op_observer: Option<&mut Obs>,
+    ) -> Result<(), AutomergeError> {
+        let mut seen = HashSet::new();
+        let changes = changes
+            .into_iter()
+            .filter(|c| !self.has_applied(c) && seen.insert(c.hash()))
+            .collect::<Vec<_>>();
+        if let Some(verifier) = &self.verifier {
+            for c in &changes {
+                verifier.verify(c)?;
+            }
+        }
+        for c in &changes {
+            self.check_change(c)?;
+        }
+        let before = match op_observer {
+            Some(_) => Some(self.clock_at(&self.get_heads())?),
+            None => None,
+        };
+
+        // Order the batch along with the queue: each change waits for the number of its deps
+        // which are in neither the document nor the batch, and is ready once they are applied.
+        let mut pending = std::mem::take(&mut self.queue);
+        pending.retain(|c| !seen.contains(&c.hash()));
+        pending.extend(changes);
+        let position = pending
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.hash(), i))
+            .collect::<HashMap<_, _>>();
+        let mut waiting = vec![0; pending.len()];
+        let mut dependents: HashMap<ChangeHash, Vec<usize>> = HashMap::new();
+        let mut ready = Vec::new();
+        for (i, c) in pending.iter().enumerate() {
+            for dep in c.deps() {
+                if !self.has_change(dep) && position.contains_key(dep) {
+                    waiting[i] += 1;
+                    dependents.entry(*dep).or_default().push(i);
                 }
-                if self.is_causally_ready(&c) {
-                    self.apply_change(c, &mut op_observer);
-                } else {
-                    self.queue.push(c);
+            }
+            if waiting[i] == 0 {
+                ready.push(i);
+            }
+        }
+        ready.reverse();
+
+        let mut pending = pending.into_iter().map(Some).collect::<Vec<_>>();
+        let mut touched = Vec::new();
+        let mut seen_objs = HashSet::new();
+        while let Some(i) = ready.pop() {
+            let c = match &pending[i] {
+                Some(c) if self.is_causally_ready(c) => pending[i].take().unwrap(),
+                _ => continue,
+            };
+            if self.has_applied(&c) {
+                continue;
+            }
+            if self.duplicate_seq(&c) {
+                self.queue.extend(pending.into_iter().flatten());
+                return Err(AutomergeError::DuplicateSeqNumber(
+                    c.seq(),
+                    c.actor_id().clone(),
+                ));
+            }
+            let hash = c.hash();
+            let ops = self.import_ops(&c);
+            if let Err(e) = self.check_change_depth(&ops) {
+                self.queue.extend(pending.into_iter().flatten());
+                return Err(e.into());
+            }
+            self.update_history(c, ops.len());
+            for (obj, op) in ops {
+                if before.is_some() && seen_objs.insert(obj) {
+                    touched.push(obj);
+                }
+                self.ops.insert_op(&obj, op);
+            }
+            for j in dependents
+                .remove(&hash)
+                .unwrap_or_default()
+                .into_iter()
+                .rev()
+            {
+                waiting[j] -= 1;
+                if waiting[j] == 0 {
+                    ready.push(j);
                 }
             }
         }
-        while let Some(c) = self.pop_next_causally_ready_change() {
-            if !self.history_index.contains_key(&c.hash()) {
-                self.apply_change(c, &mut op_observer);
+        self.queue.extend(pending.into_iter().flatten());
+
+        if let (Some(before), Some(observer)) = (before, op_observer) {
+            let mut buffer = PolicyBuffer::default();
+            for obj in touched {
+                let reachable = self.ops.parents(obj).all(|(_, _, visible)| visible);
+                if reachable && self.ops.object_type(&obj).is_some() {
+                    if self.conflict_policies.is_empty() {
+                        self.ops.observe_diff(&obj, &before, None, observer);
+                    } else {
+                        self.ops.observe_diff(&obj, &before, None, &mut buffer);
+                    }
+                }
             }
+            self.replay_with_conflict_policies(buffer, observer);
         }
         Ok(())
     }
 
-    fn apply_change<Obs: OpObserver>(&mut self, change: Change, observer: &mut Option<&mut Obs>) {
+    fn apply_change<Obs: OpObserver>(
+        &mut self,
+        change: Change,
+        observer: &mut Option<&mut Obs>,
+    ) -> Result<(), AutomergeError> {
         let ops = self.import_ops(&change);
+        self.check_change_depth(&ops)?;
         self.update_history(change, ops.len());
         if let Some(observer) = observer {
-            for (obj, op) in ops {
-                self.ops.insert_op_with_observer(&obj, op, *observer);
+            if self.conflict_policies.is_empty() {
+                self.ops.insert_ops_with_observer(ops, *observer);
+            } else {
+                let mut buffer = PolicyBuffer::default();
+                self.ops.insert_ops_with_observer(ops, &mut buffer);
+                self.replay_with_conflict_policies(buffer, *observer);
             }
         } else {
             for (obj, op) in ops {
                 self.ops.insert_op(&obj, op);
             }
         }
+        Ok(())
     }
 
     fn is_causally_ready(&self, change: &Change) -> bool {
-        change
-            .deps()
-            .iter()
-            .all(|d| self.history_index.contains_key(d))
+        change.deps().iter().all(|d| self.has_change(d)) && self.follows_dropped_history(change)
     }
 
     fn pop_next_causally_ready_change(&mut self) -> Option<Change> {
@@ -892,6 +1973,9 @@ impl Automerge {
         op_observer: Option<&mut Obs>,
     ) -> Result<Vec<ChangeHash>, AutomergeError> {
         // TODO: Make this fallible and figure out how to do this transactionally
+        if !other.history.is_empty() {
+            self.check_config(&other.config)?;
+        }
         let changes = self
             .get_changes_added(other)
             .into_iter()
@@ -902,52 +1986,114 @@ impl Automerge {
         Ok(self.get_heads())
     }
 
+    /// Merge the changes in `other` which are not in `self` like [`Self::merge`], telling
+    /// `observer` about each op they apply, e.g. a [`VecOpObserver`] to turn them into patches
+    /// for a UI.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, Automerge, AutomergeError, Patch, VecOpObserver, ROOT};
+    /// let mut doc = Automerge::new();
+    /// let mut other = doc.fork();
+    /// other.transact::<_, _, AutomergeError>(|tx| tx.put(ROOT, "greeting", "hello")).unwrap();
+    ///
+    /// let mut observer = VecOpObserver::default();
+    /// doc.merge_and_observe(&mut other, &mut observer).unwrap();
+    /// let patches = observer.take_patches();
+    /// assert!(matches!(&patches[..], [Patch::Put { .. }]));
+    /// ```
+    pub fn merge_and_observe<Obs: OpObserver>(
+        &mut self,
+        other: &mut Self,
+        observer: &mut Obs,
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
+        self.merge_with(other, Some(observer))
+    }
+
     /// Save the entirety of this document in a compact form.
+    ///
+    /// With a history fence, see [`Self::set_history_fence`], the changes beneath the fence are
+    /// left out.
     pub fn save(&mut self) -> Vec<u8> {
-        let heads = self.get_heads();
-        let c = self.history.iter();
-        let bytes = crate::storage::save::save_document(
-            c,
-            self.ops.iter(),
-            &self.ops.m.actors,
-            &self.ops.m.props,
-            &heads,
-            None,
-        );
+        self.save_with_compression(None)
+    }
+
+    pub fn save_nocompress(&mut self) -> Vec<u8> {
+        self.save_with_compression(Some(CompressConfig::None))
+    }
+
+    fn save_with_compression(&mut self, compress: Option<CompressConfig>) -> Vec<u8> {
+        let bytes = self.encode(compress);
         self.saved = self.get_heads();
         bytes
     }
 
-    pub fn save_nocompress(&mut self) -> Vec<u8> {
+    /// The saved form of this document, without recording that it was saved.
+    pub(crate) fn encode(&self, compress: Option<CompressConfig>) -> Vec<u8> {
         let heads = self.get_heads();
-        let c = self.history.iter();
-        let bytes = crate::storage::save::save_document(
-            c,
-            self.ops.iter(),
-            &self.ops.m.actors,
-            &self.ops.m.props,
-            &heads,
-            Some(CompressConfig::None),
-        );
-        self.saved = self.get_heads();
+        let mut bytes = self.config_chunk();
+        if !self.headers.is_empty() {
+            bytes.extend(storage::headers::write(&self.headers));
+        }
+        match self.fence_to_save() {
+            Some(fence) => bytes.extend(self.save_fenced(fence, compress)),
+            None => bytes.extend(crate::storage::save::save_document(
+                self.history.iter(),
+                self.ops.iter(),
+                &self.ops.m.actors,
+                &self.ops.m.props,
+                &heads,
+                compress,
+            )),
+        }
         bytes
     }
 
+    fn config_chunk(&self) -> Vec<u8> {
+        if self.config.is_empty() {
+            Vec::new()
+        } else {
+            storage::config::write(&self.config)
+        }
+    }
+
+    /// Save the entirety of this document like [`Self::save`], encoding each chunk with `codec`,
+    /// for example to encrypt the document at rest.
+    ///
+    /// Load the result with [`Self::load_with_codec`].
+    pub fn save_with_codec<C: ChunkCodec>(&mut self, codec: &C) -> Vec<u8> {
+        storage::encoded::encode(&self.save(), codec)
+    }
+
+    /// Save the changes since last save like [`Self::save_incremental`], encoding them with
+    /// `codec`. The result can be appended to the output of [`Self::save_with_codec`].
+    pub fn save_incremental_with_codec<C: ChunkCodec>(&mut self, codec: &C) -> Vec<u8> {
+        storage::encoded::encode(&self.save_incremental(), codec)
+    }
+
     /// Save the changes since last save in a compact form.
     pub fn save_incremental(&mut self) -> Vec<u8> {
-        let changes = self
-            .get_changes(self.saved.as_slice())
+        let bytes = self
+            .save_after(&self.saved)
             .expect("Should only be getting changes using previously saved heads");
-        let mut bytes = vec![];
-        for c in changes {
-            bytes.extend(c.raw_bytes());
-        }
         if !bytes.is_empty() {
             self.saved = self.get_heads()
         }
         bytes
     }
 
+    /// The changes which aren't ancestors of `heads`, in the form [`Self::save_incremental`]
+    /// produces, without recording that they were saved.
+    ///
+    /// This is useful when the caller tracks what has been written itself, for example to only
+    /// consider changes saved once a write to storage has succeeded.
+    pub fn save_after(&self, heads: &[ChangeHash]) -> Result<Vec<u8>, AutomergeError> {
+        let mut bytes = vec![];
+        for c in self.get_changes(heads)? {
+            bytes.extend(c.raw_bytes());
+        }
+        Ok(bytes)
+    }
+
     /// Filter the changes down to those that are not transitive dependencies of the heads.
     ///
     /// Thus a graph with these heads has not seen the remaining changes.
@@ -958,7 +2104,7 @@ impl Automerge {
     ) -> Result<(), AutomergeError> {
         let heads = heads
             .iter()
-            .filter(|hash| self.history_index.contains_key(hash))
+            .filter(|hash| self.has_change(hash))
             .copied()
             .collect::<Vec<_>>();
         let heads_clock = self.clock_at(&heads)?;
@@ -982,13 +2128,13 @@ impl Automerge {
         let mut missing = HashSet::new();
 
         for head in self.queue.iter().flat_map(|change| change.deps()) {
-            if !self.history_index.contains_key(head) {
+            if !self.has_change(head) {
                 missing.insert(head);
             }
         }
 
         for head in heads {
-            if !self.history_index.contains_key(head) {
+            if !self.has_change(head) {
                 missing.insert(head);
             }
         }
@@ -1002,6 +2148,81 @@ impl Automerge {
         missing
     }
 
+    /// The changes needed to describe the state of `obj` and everything nested in it at `heads`.
+    ///
+    /// These are the changes, up to `heads`, which contain an operation on `obj` or on any
+    /// object nested in it at `heads`, including deletions, together with the changes which
+    /// created `obj` and each of its ancestors, and every change those depend on. They are
+    /// returned in causal order.
+    ///
+    /// The result is closed under dependencies, so it can be applied to an empty document. It
+    /// leaves out changes which only touch other objects and which none of the selected changes
+    /// depend on, such as concurrent branches and changes after the last one affecting `obj`.
+    pub fn minimal_changes_for<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Vec<&Change>, AutomergeError> {
+        let clock = self.clock_at(heads)?;
+        let mut op_ids = HashSet::new();
+        let mut ancestor = self.exid_to_obj(obj.as_ref())?;
+        while !ancestor.is_root() {
+            op_ids.insert(ancestor.0);
+            match self.ops.parent_object(&ancestor) {
+                Some((parent, ..)) => ancestor = parent,
+                None => break,
+            }
+        }
+        let mut to_visit = vec![obj.as_ref().clone()];
+        while let Some(exid) = to_visit.pop() {
+            let obj = self.exid_to_obj(&exid)?;
+            for op in self.ops.iter_obj(&obj).into_iter().flatten() {
+                if clock.covers(&op.id) {
+                    op_ids.insert(op.id);
+                    op_ids.extend(op.succ.iter().filter(|id| clock.covers(id)));
+                }
+            }
+            match self.ops.object_type(&obj) {
+                Some(ObjType::Map) | Some(ObjType::Table) => to_visit.extend(
+                    self.map_range_at(&exid, .., heads)
+                        .filter(|(_, value, _)| value.is_object())
+                        .map(|(_, _, id)| id),
+                ),
+                Some(ObjType::List) | Some(ObjType::Text) => to_visit.extend(
+                    self.list_range_at(&exid, .., heads)
+                        .filter(|(_, value, _)| value.is_object())
+                        .map(|(_, _, id)| id),
+                ),
+                None => {}
+            }
+        }
+        let mut change_indexes = BTreeSet::new();
+        for id in op_ids {
+            if let Some(actor_changes) = self.states.get(&id.1) {
+                // the changes of each actor are in order so their op counters are ascending
+                let pos = actor_changes.partition_point(|i| self.history[*i].max_op() < id.0);
+                if let Some(index) = actor_changes.get(pos) {
+                    change_indexes.insert(*index);
+                }
+            }
+        }
+        let mut to_visit = change_indexes.iter().copied().collect::<Vec<_>>();
+        while let Some(index) = to_visit.pop() {
+            for dep in self.history[index].deps() {
+                // dependencies beneath a history fence are no longer stored
+                if let Some(dep_index) = self.history_index.get(dep) {
+                    if change_indexes.insert(*dep_index) {
+                        to_visit.push(*dep_index);
+                    }
+                }
+            }
+        }
+        Ok(change_indexes
+            .into_iter()
+            .map(|i| &self.history[i])
+            .collect())
+    }
+
     /// Get the changes since `have_deps` in this document using a clock internally.
     fn get_changes_clock(&self, have_deps: &[ChangeHash]) -> Result<Vec<&Change>, AutomergeError> {
         // get the clock for the given deps
@@ -1015,7 +2236,10 @@ impl Automerge {
             if let Some(clock_data) = clock.get_for_actor(actor_index) {
                 // find the change in this actors sequence of changes that corresponds to the max_op
                 // recorded for them in the clock
-                change_indexes.extend(&actor_changes[clock_data.seq as usize..]);
+                let seen = clock_data
+                    .seq
+                    .saturating_sub(self.dropped_seqs(*actor_index));
+                change_indexes.extend(&actor_changes[seen as usize..]);
             } else {
                 change_indexes.extend(&actor_changes[..]);
             }
@@ -1034,6 +2258,38 @@ impl Automerge {
         self.get_changes_clock(have_deps)
     }
 
+    /// The changes which created `obj` or put, inserted, incremented or deleted anything in it
+    /// or any object nested inside it, in the order they were applied.
+    ///
+    /// The changes are found from the ops of the objects in the op set, so no change needs to be
+    /// decoded and the cost depends on the size of the subtree rather than of the history.
+    pub fn changes_affecting<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+    ) -> Result<Vec<&Change>, AutomergeError> {
+        let obj = self.exid_to_obj(obj.as_ref())?;
+        let mut indices = BTreeSet::new();
+        if !obj.is_root() {
+            indices.extend(self.change_index_for_op(obj.0));
+        }
+        for o in self.ops.subtree(&obj) {
+            for op in self.ops.iter_obj(&o).into_iter().flatten() {
+                for id in std::iter::once(&op.id).chain(op.succ.iter()) {
+                    indices.extend(self.change_index_for_op(*id));
+                }
+            }
+        }
+        Ok(indices.into_iter().map(|i| &self.history[i]).collect())
+    }
+
+    /// The index in the history of the change containing the op `id`.
+    pub(crate) fn change_index_for_op(&self, id: OpId) -> Option<usize> {
+        let changes = self.states.get(&id.actor())?;
+        let pos = changes.partition_point(|i| self.history[*i].max_op() < id.counter());
+        let index = *changes.get(pos)?;
+        (self.history[index].start_op().get() <= id.counter()).then(|| index)
+    }
+
     /// Get the last change this actor made to the document.
     pub fn get_last_local_change(&self) -> Option<&Change> {
         return self
@@ -1043,7 +2299,7 @@ impl Automerge {
             .find(|c| c.actor_id() == self.get_actor());
     }
 
-    fn clock_at(&self, heads: &[ChangeHash]) -> Result<Clock, AutomergeError> {
+    pub(crate) fn clock_at(&self, heads: &[ChangeHash]) -> Result<Clock, AutomergeError> {
         if let Some(first_hash) = heads.first() {
             let mut clock = self
                 .clocks
@@ -1065,6 +2321,11 @@ impl Automerge {
         }
     }
 
+    /// The graph of the changes in this document and their dependencies.
+    pub fn change_graph(&self) -> ChangeGraph<'_> {
+        ChangeGraph::new(self)
+    }
+
     /// Get a change by its hash.
     pub fn get_change_by_hash(&self, hash: &ChangeHash) -> Option<&Change> {
         self.history_index
@@ -1072,6 +2333,60 @@ impl Automerge {
             .and_then(|index| self.history.get(*index))
     }
 
+    /// Index the messages of the changes in this document, and of every change added from now
+    /// on, to speed up [`Self::find_commits`].
+    ///
+    /// The index is kept in memory only, it is not saved, but forks of the document keep it.
+    pub fn enable_message_index(&mut self) {
+        if self.message_index.is_none() {
+            self.message_index = Some(MessageIndex::new(self.history.iter()));
+        }
+    }
+
+    /// The changes whose messages match `query`, in the order they were applied.
+    ///
+    /// Without an index, see [`Self::enable_message_index`], this checks the message of every
+    /// change.
+    pub fn find_commits(&self, query: &CommitQuery) -> Vec<&Change> {
+        match &self.message_index {
+            Some(index) => index
+                .find(query)
+                .into_iter()
+                .map(|i| &self.history[i])
+                .collect(),
+            None => self
+                .history
+                .iter()
+                .filter(|c| c.message().map(|m| query.matches(m)).unwrap_or(false))
+                .collect(),
+        }
+    }
+
+    /// The source label of the op with id `id`, if it was created by this document in a
+    /// transaction with a source set.
+    ///
+    /// See [`Transaction::set_source`]. The ids in patches are op ids, so this can be used to
+    /// attribute patches to the code which produced them.
+    pub fn op_source(&self, id: &ExId) -> Option<&str> {
+        self.op_sources.get(self.exid_to_opid(id)?)
+    }
+
+    /// The number of ops in the change `hash` with each source label, in order of first
+    /// appearance. Ops without a label are not counted.
+    pub fn change_sources(&self, hash: &ChangeHash) -> Result<Vec<(&str, usize)>, AutomergeError> {
+        let change = self
+            .get_change_by_hash(hash)
+            .ok_or(AutomergeError::MissingHash(*hash))?;
+        let actor = match self.ops.m.actors.lookup(change.actor_id()) {
+            Some(actor) => actor,
+            None => return Ok(Vec::new()),
+        };
+        let start = change.start_op().get();
+        Ok(self
+            .op_sources
+            .count(actor, start..start + change.len() as u64))
+    }
+
     /// Get the changes that the other document added compared to this document.
     #[tracing::instrument(skip(self, other))]
     pub fn get_changes_added<'a>(&self, other: &'a Self) -> Vec<&'a Change> {
@@ -1107,9 +2422,12 @@ impl Automerge {
     }
 
     fn get_hash(&self, actor: usize, seq: u64) -> Result<ChangeHash, AutomergeError> {
+        let index = seq
+            .checked_sub(self.dropped_seqs(actor) + 1)
+            .ok_or(AutomergeError::InvalidSeq(seq))?;
         self.states
             .get(&actor)
-            .and_then(|v| v.get(seq as usize - 1))
+            .and_then(|v| v.get(index as usize))
             .and_then(|&i| self.history.get(i))
             .map(|c| c.hash())
             .ok_or(AutomergeError::InvalidSeq(seq))
@@ -1147,6 +2465,9 @@ impl Automerge {
         self.clocks.insert(change.hash(), clock);
 
         self.history_index.insert(change.hash(), history_index);
+        if let Some(index) = &mut self.message_index {
+            index.insert(history_index, &change);
+        }
         self.history.push(change);
 
         history_index
@@ -1160,27 +2481,21 @@ impl Automerge {
     }
 
     pub fn import(&self, s: &str) -> Result<ExId, AutomergeError> {
-        if s == "_root" {
-            Ok(ExId::Root)
-        } else {
-            let n = s
-                .find('@')
-                .ok_or_else(|| AutomergeError::InvalidObjIdFormat(s.to_owned()))?;
-            let counter = s[0..n]
-                .parse()
-                .map_err(|_| AutomergeError::InvalidObjIdFormat(s.to_owned()))?;
-            let actor = ActorId::from(hex::decode(&s[(n + 1)..]).unwrap());
-            let actor = self
-                .ops
-                .m
-                .actors
-                .lookup(&actor)
-                .ok_or_else(|| AutomergeError::InvalidObjId(s.to_owned()))?;
-            Ok(ExId::Id(
-                counter,
-                self.ops.m.actors.cache[actor].clone(),
-                actor,
-            ))
+        match s.parse()? {
+            ExId::Root => Ok(ExId::Root),
+            ExId::Id(counter, actor, _) => {
+                let actor = self
+                    .ops
+                    .m
+                    .actors
+                    .lookup(&actor)
+                    .ok_or_else(|| AutomergeError::InvalidObjId(s.to_owned()))?;
+                Ok(ExId::Id(
+                    counter,
+                    self.ops.m.actors.cache[actor].clone(),
+                    actor,
+                ))
+            }
         }
     }
 
@@ -1249,6 +2564,49 @@ impl Default for Automerge {
     }
 }
 
+/// Recursively copy the contents of `src_obj` in `src` into `dst_obj` in `dst`
+fn copy_object<T: Transactable>(
+    src: &Automerge,
+    src_obj: &ExId,
+    dst: &mut T,
+    dst_obj: &ExId,
+) -> Result<(), AutomergeError> {
+    match src.object_type(src_obj) {
+        Some(ObjType::Map) | Some(ObjType::Table) => {
+            for (key, value, id) in src.map_range(src_obj, ..) {
+                match value {
+                    Value::Object(obj_type) => {
+                        let new_obj = dst.put_object(dst_obj, key, obj_type)?;
+                        copy_object(src, &id, dst, &new_obj)?;
+                    }
+                    Value::Scalar(s) => dst.put(dst_obj, key, fresh_scalar(&s))?,
+                }
+            }
+        }
+        Some(ObjType::List) | Some(ObjType::Text) => {
+            for (index, value, id) in src.list_range(src_obj, ..) {
+                match value {
+                    Value::Object(obj_type) => {
+                        let new_obj = dst.insert_object(dst_obj, index, obj_type)?;
+                        copy_object(src, &id, dst, &new_obj)?;
+                    }
+                    Value::Scalar(s) => dst.insert(dst_obj, index, fresh_scalar(&s))?,
+                }
+            }
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+/// A copy of `value` suitable for a new document, counters start at their current value
+fn fresh_scalar(value: &ScalarValue) -> ScalarValue {
+    match value {
+        ScalarValue::Counter(c) => ScalarValue::counter(c.into()),
+        other => other.clone(),
+    }
+}
+
 #[derive(Serialize, Debug, Clone, PartialEq)]
 pub(crate) struct SpanInfo {
     pub(crate) id: ExId,
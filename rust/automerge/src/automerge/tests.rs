@@ -6,6 +6,7 @@ use crate::op_tree::B;
 use crate::transaction::Transactable;
 use crate::*;
 use std::convert::TryInto;
+use std::io::Read;
 
 #[test]
 fn insert_op() -> Result<(), AutomergeError> {
@@ -1122,6 +1123,21 @@ fn loaded_doc_changes_have_hash() {
     assert_eq!(doc.get_change_by_hash(&hash).unwrap().hash(), hash);
 }
 
+#[test]
+fn load_with_observer_reports_ops_of_every_actor() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, "a").unwrap();
+    let saved = doc.save();
+
+    let mut observer = VecOpObserver::default();
+    let loaded = Automerge::load_with(&saved, Some(&mut observer)).unwrap();
+    let patches = observer.take_patches();
+    assert_eq!(patches.len(), 2);
+    assert!(matches!(&patches[1], Patch::Insert { obj, .. } if *obj == list));
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+}
+
 #[test]
 fn load_change_with_zero_start_op() {
     let bytes = &[
@@ -1324,15 +1340,15 @@ fn get_parent_objects() {
 
     assert_eq!(
         doc.parents(&map).unwrap().next(),
-        Some((ROOT, Prop::Map("a".into())))
+        Some((ROOT, Prop::Map("a".into()), true))
     );
     assert_eq!(
         doc.parents(&list).unwrap().next(),
-        Some((map, Prop::Seq(0)))
+        Some((map, Prop::Seq(0), true))
     );
     assert_eq!(
         doc.parents(&text).unwrap().next(),
-        Some((list, Prop::Seq(0)))
+        Some((list, Prop::Seq(0), true))
     );
 }
 
@@ -1371,9 +1387,9 @@ fn parents_iterator() {
     let text = doc.put_object(&list, 0, ObjType::Text).unwrap();
 
     let mut parents = doc.parents(text).unwrap();
-    assert_eq!(parents.next(), Some((list, Prop::Seq(0))));
-    assert_eq!(parents.next(), Some((map, Prop::Seq(0))));
-    assert_eq!(parents.next(), Some((ROOT, Prop::Map("a".into()))));
+    assert_eq!(parents.next(), Some((list, Prop::Seq(0), true)));
+    assert_eq!(parents.next(), Some((map, Prop::Seq(0), true)));
+    assert_eq!(parents.next(), Some((ROOT, Prop::Map("a".into()), true)));
     assert_eq!(parents.next(), None);
 }
 
@@ -1500,6 +1516,93 @@ fn observe_counter_change_application() {
     );
 }
 
+#[test]
+fn coalescing_observer_merges_successive_ops() {
+    let mut doc = AutoCommit::new().with_observer(VecOpObserver::coalescing());
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.commit();
+    doc.observer().take_patches();
+
+    doc.splice_text(&text, 0, 0, "hello").unwrap();
+    doc.splice_text(&text, 5, 0, " world").unwrap();
+    doc.put(ROOT, "x", 1).unwrap();
+    doc.put(ROOT, "x", 2).unwrap();
+    doc.commit();
+    let patches = doc.observer().take_patches();
+    assert_eq!(patches.len(), 2);
+    match &patches[0] {
+        Patch::SpliceText {
+            obj, index, value, ..
+        } => {
+            assert_eq!(obj, &text);
+            assert_eq!(*index, 0);
+            assert_eq!(value, "hello world");
+        }
+        other => panic!("expected a splice, got {:?}", other),
+    }
+    match &patches[1] {
+        Patch::Put { prop, value, .. } => {
+            assert_eq!(prop, &Prop::Map("x".into()));
+            assert_eq!(value.0, Value::from(2));
+        }
+        other => panic!("expected a put, got {:?}", other),
+    }
+
+    // backspacing is a single delete range
+    for i in (6..11).rev() {
+        doc.delete(&text, i).unwrap();
+    }
+    doc.commit();
+    assert_eq!(
+        doc.observer().take_patches(),
+        vec![Patch::DeleteRange {
+            obj: text.clone(),
+            path: vec![(ROOT, Prop::Map("text".into()))],
+            index: 6,
+            length: 5,
+        }]
+    );
+
+    // patches from separate transactions are not merged
+    doc.put(ROOT, "x", 3).unwrap();
+    doc.commit();
+    doc.put(ROOT, "x", 4).unwrap();
+    doc.commit();
+    assert_eq!(doc.observer().take_patches().len(), 2);
+}
+
+#[test]
+fn coalescing_observer_keeps_conflicts_of_spliced_elements() {
+    let mut doc1 = Automerge::new();
+    let mut tx = doc1.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List).unwrap();
+    tx.commit();
+
+    let mut doc2 = doc1.fork().with_actor(ActorId::random());
+    let mut tx = doc2.transaction();
+    tx.insert(&list, 0, "a").unwrap();
+    tx.commit();
+    let mut doc3 = doc2.fork().with_actor(ActorId::random());
+    let mut tx = doc2.transaction();
+    tx.put(&list, 0, "b").unwrap();
+    tx.commit();
+    let mut tx = doc3.transaction();
+    tx.put(&list, 0, "c").unwrap();
+    tx.commit();
+
+    let mut changes: Vec<_> = doc1.get_changes_added(&doc2).into_iter().cloned().collect();
+    changes.extend(doc2.get_changes_added(&doc3).into_iter().cloned());
+    let mut observer = VecOpObserver::coalescing();
+    doc1.apply_changes_and_observe(changes, &mut observer)
+        .unwrap();
+    let patches = observer.take_patches();
+    assert!(matches!(&patches[0], Patch::Splice { values, .. } if values.len() == 1));
+    assert!(matches!(
+        patches.last(),
+        Some(Patch::Put { conflict: true, .. })
+    ));
+}
+
 #[test]
 fn get_changes_heads_empty() {
     let mut doc = AutoCommit::unobserved();
@@ -1510,3 +1613,4171 @@ fn get_changes_heads_empty() {
     let heads = doc.get_heads();
     assert_eq!(doc.get_changes(&heads).unwrap(), Vec::<&Change>::new());
 }
+
+#[test]
+fn instantiate_template_copies_content_without_history() {
+    let mut template = AutoCommit::new();
+    template.put(ROOT, "title", "untitled").unwrap();
+    template
+        .put(ROOT, "count", ScalarValue::counter(1))
+        .unwrap();
+    template.increment(ROOT, "count", 2).unwrap();
+    let todos = template.put_object(ROOT, "todos", ObjType::List).unwrap();
+    let todo = template.insert_object(&todos, 0, ObjType::Map).unwrap();
+    template.put(&todo, "done", false).unwrap();
+    let text = template.put_object(ROOT, "notes", ObjType::Text).unwrap();
+    template.splice_text(&text, 0, 0, "hello").unwrap();
+    template.commit();
+    template.put(ROOT, "title", "a template").unwrap();
+    template.commit();
+
+    let doc1 = Automerge::instantiate_template(template.document()).unwrap();
+    let doc2 = Automerge::instantiate_template(template.document()).unwrap();
+
+    assert_eq!(doc1.history.len(), 1);
+    assert_ne!(doc1.get_actor(), template.get_actor());
+    assert_ne!(doc1.get_heads(), doc2.get_heads());
+    assert_eq!(
+        doc1.get(ROOT, "title").unwrap().unwrap().0,
+        Value::str("a template")
+    );
+    let (_, new_todos) = doc1.get(ROOT, "todos").unwrap().unwrap();
+    let (_, new_todo) = doc1.get(&new_todos, 0).unwrap().unwrap();
+    assert_ne!(new_todo, todo);
+    assert_eq!(
+        doc1.get(&new_todo, "done").unwrap().unwrap().0,
+        Value::from(false)
+    );
+    assert_eq!(
+        doc1.get(ROOT, "count").unwrap().unwrap().0,
+        Value::counter(3)
+    );
+    let (_, notes) = doc1.get(ROOT, "notes").unwrap().unwrap();
+    assert_ne!(notes, text);
+    assert_eq!(doc1.text(&notes).unwrap(), "hello");
+}
+
+#[test]
+fn fork_at_keeps_document_settings() {
+    let mut doc1 = AutoCommit::new()
+        .with_actor(ActorId::from(vec![1]))
+        .with_config(DocumentConfig::new().with("units", "metric"));
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(vec![2]));
+    doc1.put(ROOT, "x", 10).unwrap();
+    doc1.commit();
+    doc2.put(ROOT, "x", 20).unwrap();
+    doc2.commit();
+    doc1.merge(&mut doc2).unwrap();
+    let heads = doc1.get_heads();
+    doc1.put(ROOT, "y", 1).unwrap();
+    doc1.commit();
+
+    doc1.set_conflict_policy(ROOT, "x", Some(ConflictPolicy::MinWins))
+        .unwrap();
+    doc1.set_numeric_mode(NumericMode::PreserveIntegers);
+    doc1.set_actor_policy(ActorPolicy::PerSession);
+    doc1.enable_message_index();
+
+    let mut forked = doc1.fork_at(&heads).unwrap();
+    assert_eq!(forked.get_heads(), heads);
+    assert_eq!(forked.config(), doc1.config());
+    assert_eq!(forked.numeric_mode(), NumericMode::PreserveIntegers);
+    assert_eq!(forked.actor_policy(), ActorPolicy::PerSession);
+    assert!(forked.document().message_index.is_some());
+    assert_eq!(forked.get(ROOT, "x").unwrap().unwrap().0, Value::int(10));
+    assert_ne!(forked.get_actor(), doc1.get_actor());
+}
+
+#[test]
+fn error_categories() {
+    let mut doc = AutoCommit::new();
+    let err = doc.put(ROOT, "", 1).unwrap_err();
+    assert_eq!(err.category(), ErrorCategory::UserInput);
+    assert!(!err.is_retryable());
+
+    let mut other = AutoCommit::new().with_actor(ActorId::from([1]));
+    other.put(ROOT, "a", 1).unwrap();
+    let unknown = other.get_heads();
+    let err = doc.fork_at(&unknown).unwrap_err();
+    assert_eq!(err.category(), ErrorCategory::ConcurrencyConflict);
+    assert!(err.is_retryable());
+
+    let err = Automerge::load(&[1, 2, 3]).unwrap_err();
+    assert_eq!(err.category(), ErrorCategory::Corruption);
+}
+
+#[test]
+fn history_states() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from([1]));
+    doc1.put(ROOT, "a", 1).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from([2]));
+    doc1.put(ROOT, "a", 2).unwrap();
+    doc1.commit();
+    doc2.put(ROOT, "b", 3).unwrap();
+    doc2.commit();
+    doc1.merge(&mut doc2).unwrap();
+
+    let hashes = doc1
+        .document()
+        .get_changes(&[])
+        .unwrap()
+        .iter()
+        .map(|c| c.hash())
+        .collect::<Vec<_>>();
+    let mut states = doc1.history_states();
+    assert_eq!(states.len(), 3);
+
+    let (hash, heads, state) = states.next_state().unwrap();
+    assert_eq!(hash, hashes[0]);
+    assert_eq!(heads, vec![hash]);
+    assert_eq!(state.get(ROOT, "a").unwrap().unwrap().0, Value::int(1));
+    assert_eq!(state.get(ROOT, "b").unwrap(), None);
+
+    let rest = states.collect::<Vec<_>>();
+    assert_eq!(rest.len(), 2);
+    let (_, heads, last) = &rest[1];
+    assert_eq!(heads.len(), 2);
+    assert_eq!(last.get(ROOT, "a").unwrap().unwrap().0, Value::int(2));
+    assert_eq!(last.get(ROOT, "b").unwrap().unwrap().0, Value::int(3));
+    assert_eq!(last.get_heads(), doc1.get_heads());
+}
+
+#[test]
+fn object_stats() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..10 {
+        doc.insert(&list, i, i as i64).unwrap();
+    }
+    doc.commit();
+    for _ in 0..4 {
+        doc.delete(&list, 0).unwrap();
+    }
+    doc.put(&list, 0, "updated").unwrap();
+    doc.put(ROOT, "a", 1).unwrap();
+    doc.put(ROOT, "b", 2).unwrap();
+    doc.delete(ROOT, "b").unwrap();
+
+    let stats = doc.object_stats(&list).unwrap();
+    assert_eq!(stats.ops, 11);
+    assert_eq!(stats.visible_ops, 6);
+    assert_eq!(stats.live_elements, 6);
+    assert_eq!(stats.tombstones, 4);
+    assert!(stats.memory >= 11);
+
+    let root = doc.object_stats(ROOT).unwrap();
+    assert_eq!(root.live_elements, 2);
+    assert_eq!(root.tombstones, 1);
+
+    let totals = doc.document_stats();
+    assert_eq!(totals.objects, 2);
+    assert_eq!(totals.totals.ops, stats.ops + root.ops);
+    assert_eq!(totals.totals.tombstones, 5);
+}
+
+#[test]
+fn apply_changes_with_progress_and_cancellation() {
+    let mut source = AutoCommit::new();
+    for i in 0..5 {
+        source.put(ROOT, "n", i).unwrap();
+        source.commit();
+    }
+    let changes = source
+        .get_changes(&[])
+        .unwrap()
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut doc = Automerge::new();
+    let cancel = CancellationToken::new();
+    let mut seen = Vec::new();
+    let err = doc
+        .apply_changes_with_progress(
+            changes.iter().rev().cloned(),
+            |p| {
+                seen.push(p);
+                if p.applied == 2 {
+                    cancel.cancel();
+                }
+            },
+            &cancel,
+        )
+        .unwrap_err();
+    assert!(matches!(err, AutomergeError::Cancelled));
+    assert_eq!(err.category(), ErrorCategory::Cancelled);
+    assert_eq!(
+        seen.last(),
+        Some(&ApplyProgress {
+            applied: 2,
+            total: 5
+        })
+    );
+    assert!(doc.get_heads().is_empty());
+    assert!(doc.get_changes(&[]).unwrap().is_empty());
+    assert!(doc.queue.is_empty());
+
+    let mut seen = Vec::new();
+    doc.apply_changes_with_progress(changes, |p| seen.push(p), &CancellationToken::new())
+        .unwrap();
+    assert_eq!(
+        seen.last(),
+        Some(&ApplyProgress {
+            applied: 5,
+            total: 5
+        })
+    );
+    assert_eq!(doc.get_heads(), source.get_heads());
+
+    let saved = source.save();
+    let mut reported = 0;
+    let loaded =
+        Automerge::load_with_progress(&saved, |p| reported = p.applied, &CancellationToken::new())
+            .unwrap();
+    assert_eq!(reported, 5);
+    assert_eq!(loaded.get_heads(), source.get_heads());
+}
+
+#[test]
+fn cancelled_apply_changes_with_progress_leaves_the_document_unchanged() {
+    let mut source = AutoCommit::new();
+    source.put(ROOT, "n", 0).unwrap();
+    source.commit();
+    let mut doc = AutoCommit::new();
+    doc.merge(&mut source).unwrap();
+    let before = doc.get_heads();
+
+    let heads = source.get_heads();
+    for i in 1..5 {
+        source.put(ROOT, "n", i).unwrap();
+        source.commit();
+    }
+    let changes = source
+        .get_changes(&heads)
+        .unwrap()
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    let cancel = CancellationToken::new();
+    let err = doc
+        .apply_changes_with_progress(
+            changes.clone(),
+            |p| {
+                if p.applied == 2 {
+                    cancel.cancel();
+                }
+            },
+            &cancel,
+        )
+        .unwrap_err();
+    assert!(matches!(err, AutomergeError::Cancelled));
+    assert_eq!(doc.get_heads(), before);
+    assert_eq!(doc.get_int(ROOT, "n").unwrap(), Some(0));
+
+    doc.apply_changes_with_progress(changes, |_| (), &CancellationToken::new())
+        .unwrap();
+    assert_eq!(doc.get_heads(), source.get_heads());
+    assert_eq!(doc.get_int(ROOT, "n").unwrap(), Some(4));
+}
+
+#[test]
+fn load_with_options() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1).unwrap();
+    let mut saved = doc.save();
+    for i in 0..20 {
+        doc.put(ROOT, "a", i).unwrap();
+        let list = doc
+            .put_object(ROOT, format!("list{}", i), ObjType::List)
+            .unwrap();
+        doc.insert(&list, 0, "x").unwrap();
+        saved.extend(doc.save_incremental());
+    }
+
+    let mut loaded = Automerge::load_with_options(&saved, &LoadOptions::parallel(4)).unwrap();
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+    assert_eq!(loaded.save(), doc.save());
+
+    let changes_only = doc
+        .get_changes(&[])
+        .unwrap()
+        .iter()
+        .flat_map(|c| c.raw_bytes().to_vec())
+        .collect::<Vec<_>>();
+    let loaded = Automerge::load_with_options(&changes_only, &LoadOptions::parallel(4)).unwrap();
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+
+    saved.extend([0x85, 0x6f, 0x4a, 0x83, 1, 2]);
+    assert!(Automerge::load_with_options(&saved, &LoadOptions::parallel(4)).is_err());
+}
+
+#[test]
+fn update_text() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "the quick fox").unwrap();
+    doc.commit();
+    let (_, fox) = doc.get(&text, 10).unwrap().unwrap();
+
+    let mut other = doc.fork();
+    other.splice_text(&text, 13, 0, " jumps").unwrap();
+
+    doc.update_text(&text, "the quick brown fox").unwrap();
+    assert_eq!(doc.pending_ops(), 6);
+    assert_eq!(doc.get(&text, 16).unwrap().unwrap().1, fox);
+
+    doc.merge(&mut other).unwrap();
+    assert_eq!(doc.text(&text).unwrap(), "the quick brown fox jumps");
+}
+
+#[test]
+fn typed_getters() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "s", "hello").unwrap();
+    doc.put(ROOT, "i", -1).unwrap();
+    doc.put(ROOT, "u", 1_u64).unwrap();
+    doc.put(ROOT, "f", 0.5).unwrap();
+    doc.put(ROOT, "b", true).unwrap();
+    doc.put(ROOT, "bytes", vec![1, 2]).unwrap();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    let map = doc.put_object(ROOT, "map", ObjType::Map).unwrap();
+
+    assert_eq!(
+        doc.get_string(ROOT, "s").unwrap(),
+        Some("hello".to_string())
+    );
+    assert_eq!(doc.get_int(ROOT, "i").unwrap(), Some(-1));
+    assert_eq!(doc.get_uint(ROOT, "u").unwrap(), Some(1));
+    assert_eq!(doc.get_f64(ROOT, "f").unwrap(), Some(0.5));
+    assert_eq!(doc.get_bool(ROOT, "b").unwrap(), Some(true));
+    assert_eq!(doc.get_bytes(ROOT, "bytes").unwrap(), Some(vec![1, 2]));
+    assert_eq!(doc.get_list(ROOT, "list").unwrap(), Some(list.clone()));
+    assert_eq!(doc.get_map(ROOT, "map").unwrap(), Some(map));
+    assert_eq!(doc.get_string(ROOT, "missing").unwrap(), None);
+
+    match doc.get_int(ROOT, "s") {
+        Err(AutomergeError::InvalidValueType {
+            expected,
+            unexpected,
+        }) => {
+            assert_eq!(expected, "int");
+            assert_eq!(unexpected, "str");
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(doc.get_map(ROOT, "list").is_err());
+
+    let doc = doc.document();
+    assert_eq!(
+        doc.get_string(ROOT, "s").unwrap(),
+        Some("hello".to_string())
+    );
+    assert_eq!(doc.get_uint(ROOT, "u").unwrap(), Some(1));
+    assert_eq!(doc.get_list(ROOT, "list").unwrap(), Some(list));
+    assert_eq!(doc.get_bool(ROOT, "missing").unwrap(), None);
+    assert!(matches!(
+        doc.get_f64(ROOT, "i"),
+        Err(AutomergeError::InvalidValueType { .. })
+    ));
+}
+
+#[test]
+fn minimal_changes_for() {
+    let mut doc = AutoCommit::new();
+    let todos = doc.put_object(ROOT, "todos", ObjType::List).unwrap();
+    doc.commit();
+    doc.put(ROOT, "title", "unrelated").unwrap();
+    doc.commit();
+    let todo = doc.insert_object(&todos, 0, ObjType::Map).unwrap();
+    doc.put(&todo, "done", false).unwrap();
+    doc.commit();
+    doc.put(ROOT, "title", "still unrelated").unwrap();
+    doc.commit();
+    let before_delete = doc.get_heads();
+    doc.put(&todo, "done", true).unwrap();
+    doc.commit();
+    doc.delete(&todo, "done").unwrap();
+    doc.commit();
+
+    let all = doc
+        .get_changes(&[])
+        .unwrap()
+        .into_iter()
+        .map(|c| c.hash())
+        .collect::<Vec<_>>();
+    let heads = doc.get_heads();
+    let hashes = |changes: Vec<&Change>| changes.into_iter().map(|c| c.hash()).collect::<Vec<_>>();
+
+    // the unrelated changes are dependencies of the later changes to the todo
+    let for_todo = hashes(doc.document().minimal_changes_for(&todo, &heads).unwrap());
+    assert_eq!(for_todo, all);
+    let for_todos = hashes(
+        doc.document()
+            .minimal_changes_for(&todos, &before_delete)
+            .unwrap(),
+    );
+    assert_eq!(for_todos, vec![all[0], all[1], all[2]]);
+
+    // changes on other branches aren't needed
+    let mut other = doc.fork();
+    other.put(ROOT, "title", "concurrent").unwrap();
+    other.commit();
+    doc.put(&todo, "done", false).unwrap();
+    doc.commit();
+    doc.merge(&mut other).unwrap();
+    let heads = doc.get_heads();
+    let for_todo = doc.document().minimal_changes_for(&todo, &heads).unwrap();
+    assert_eq!(for_todo.len(), all.len() + 1);
+    assert!(!hashes(for_todo.clone()).contains(&other.get_heads()[0]));
+    let mut copy = Automerge::new();
+    copy.apply_changes(for_todo.into_iter().cloned()).unwrap();
+    assert!(copy.get_missing_deps(&[]).is_empty());
+    assert_eq!(
+        copy.get(&todo, "done").unwrap().unwrap().0,
+        Value::from(false)
+    );
+}
+
+#[test]
+fn change_graph() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from([1]));
+    doc1.put(ROOT, "a", 1).unwrap();
+    doc1.commit();
+    let base = doc1.get_heads()[0];
+    let mut doc2 = doc1.fork().with_actor(ActorId::from([2]));
+    doc1.put(ROOT, "b", 1).unwrap();
+    doc1.commit();
+    let left = doc1.get_heads()[0];
+    doc2.put(ROOT, "c", 1).unwrap();
+    doc2.commit();
+    let right = doc2.get_heads()[0];
+    doc1.merge(&mut doc2).unwrap();
+    doc1.put(ROOT, "d", 1).unwrap();
+    doc1.commit();
+    let merged = doc1.get_heads()[0];
+
+    let graph = doc1.change_graph();
+    assert_eq!(graph.len(), 4);
+    assert_eq!(graph.topological_order().next(), Some(base));
+    assert_eq!(graph.edges().count(), 4);
+    let mut deps = graph.deps(&merged).unwrap().to_vec();
+    deps.sort();
+    let mut expected = vec![left, right];
+    expected.sort();
+    assert_eq!(deps, expected);
+    assert_eq!(graph.dependents(&base).unwrap().len(), 2);
+    assert_eq!(graph.ancestors(&merged).unwrap().len(), 3);
+    assert_eq!(graph.ancestors(&merged).unwrap()[0], base);
+    assert_eq!(graph.common_ancestors(&left, &right).unwrap(), vec![base]);
+    assert_eq!(graph.common_ancestors(&left, &merged).unwrap(), vec![left]);
+    assert!(graph.ancestors(&ChangeHash([0; 32])).is_err());
+}
+
+#[test]
+fn document_config_round_trips_and_is_enforced() {
+    let config = DocumentConfig::new().with("text", "utf16");
+    let mut doc = Automerge::new().with_config(config.clone());
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "a", 1).unwrap();
+    tx.commit();
+    let saved = doc.save();
+
+    let loaded = Automerge::load(&saved).unwrap();
+    assert_eq!(loaded.config(), &config);
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+    assert_eq!(
+        Automerge::load(&doc.save_nocompress()).unwrap().config(),
+        &config
+    );
+
+    // a new document adopts the configuration of whatever it loads
+    let mut fresh = Automerge::new();
+    fresh.load_incremental(&saved).unwrap();
+    assert_eq!(fresh.config(), &config);
+
+    let mut other = Automerge::new().with_config(DocumentConfig::new().with("text", "utf8"));
+    let mut tx = other.transaction();
+    tx.put(ROOT, "b", 2).unwrap();
+    tx.commit();
+    assert!(matches!(
+        other.load_incremental(&saved),
+        Err(AutomergeError::ConfigMismatch)
+    ));
+    assert!(matches!(
+        doc.merge(&mut other),
+        Err(AutomergeError::ConfigMismatch)
+    ));
+
+    // incremental saves carry no configuration
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "c", 3).unwrap();
+    tx.commit();
+    let incremental = doc.save_incremental();
+    assert!(crate::storage::load::config(&incremental)
+        .unwrap()
+        .is_none());
+
+    assert_eq!(
+        Automerge::load(&Automerge::new().with_config(config.clone()).save())
+            .unwrap()
+            .config(),
+        &config
+    );
+    #[cfg(feature = "rayon")]
+    assert_eq!(
+        Automerge::load_with_options(&[saved, incremental].concat(), &LoadOptions::parallel(2))
+            .unwrap()
+            .config(),
+        &config
+    );
+}
+
+#[test]
+fn op_sources() {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    tx.set_source(Some("editor"));
+    let text = tx.put_object(ROOT, "text", ObjType::Text).unwrap();
+    tx.splice_text(&text, 0, 0, "hello").unwrap();
+    tx.set_source(None);
+    let list = tx.put_object(ROOT, "list", ObjType::List).unwrap();
+    tx.set_source(Some("importer"));
+    let map = tx.put_object(ROOT, "map", ObjType::Map).unwrap();
+    let hash = tx.commit();
+
+    assert_eq!(doc.op_source(&text), Some("editor"));
+    assert_eq!(doc.op_source(&list), None);
+    assert_eq!(doc.op_source(&map), Some("importer"));
+    assert_eq!(
+        doc.change_sources(&hash).unwrap(),
+        vec![("editor", 6), ("importer", 1)]
+    );
+
+    let mut tx = doc.transaction();
+    tx.set_source(Some("discarded"));
+    let rolled_back = tx.put_object(ROOT, "gone", ObjType::Map).unwrap();
+    tx.rollback();
+    assert_eq!(doc.op_source(&rolled_back), None);
+
+    let mut doc = AutoCommit::new();
+    doc.set_source(Some("autocommit"));
+    let first = doc.put_object(ROOT, "a", ObjType::Map).unwrap();
+    doc.commit();
+    let second = doc.put_object(ROOT, "b", ObjType::Map).unwrap();
+    let hash = doc.commit();
+    assert_eq!(doc.op_source(&first), Some("autocommit"));
+    assert_eq!(doc.op_source(&second), Some("autocommit"));
+    assert_eq!(doc.change_sources(&hash).unwrap(), vec![("autocommit", 1)]);
+}
+
+#[test]
+fn op_sources_are_reported_in_patches() {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction_with_observer(VecOpObserver::coalescing());
+    tx.set_source(Some("editor"));
+    let text = tx.put_object(ROOT, "text", ObjType::Text).unwrap();
+    tx.splice_text(&text, 0, 0, "ab").unwrap();
+    tx.set_source(Some("spellcheck"));
+    tx.splice_text(&text, 2, 0, "c").unwrap();
+    tx.set_source(None);
+    tx.put(ROOT, "x", 1).unwrap();
+    let (mut observer, _) = tx.commit();
+    let sources = observer
+        .take_patches_with_sources()
+        .into_iter()
+        .map(|(_, source)| source)
+        .collect::<Vec<_>>();
+    // the splices with different labels aren't coalesced
+    assert_eq!(
+        sources,
+        vec![
+            Some("editor".to_string()),
+            Some("editor".to_string()),
+            Some("spellcheck".to_string()),
+            None
+        ]
+    );
+
+    // ops applied from other documents have no label, even if the observer saw a label before
+    let mut other = doc.fork();
+    let mut tx = other.transaction();
+    tx.put(ROOT, "y", 2).unwrap();
+    tx.commit();
+    doc.merge_and_observe(&mut other, &mut observer).unwrap();
+    assert_eq!(observer.take_patches_with_sources()[0].1, None);
+
+    let mut doc = AutoCommit::new().with_observer(VecOpObserver::default());
+    doc.set_source(Some("autocommit"));
+    doc.put(ROOT, "a", 1).unwrap();
+    doc.commit();
+    doc.put(ROOT, "b", 1).unwrap();
+    doc.set_source(None);
+    doc.put(ROOT, "c", 1).unwrap();
+    doc.commit();
+    let sources = doc
+        .observer()
+        .take_patches_with_sources()
+        .into_iter()
+        .map(|(_, source)| source)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sources,
+        vec![
+            Some("autocommit".to_string()),
+            Some("autocommit".to_string()),
+            None
+        ]
+    );
+}
+
+#[test]
+fn lazy_load_decodes_objects_on_demand() {
+    let mut doc1 = Automerge::new();
+    let mut tx = doc1.transaction();
+    tx.put(ROOT, "title", "lazy").unwrap();
+    tx.put(ROOT, "deleted", 1).unwrap();
+    let list = tx.put_object(ROOT, "list", ObjType::List).unwrap();
+    tx.insert(&list, 0, "a").unwrap();
+    tx.insert(&list, 1, "b").unwrap();
+    tx.insert(&list, 2, "c").unwrap();
+    tx.delete(&list, 1).unwrap();
+    tx.put(&list, 0, "A").unwrap();
+    let text = tx.put_object(ROOT, "text", ObjType::Text).unwrap();
+    tx.splice_text(&text, 0, 0, "hello world").unwrap();
+    tx.splice_text(&text, 5, 6, "").unwrap();
+    let map = tx.put_object(ROOT, "map", ObjType::Map).unwrap();
+    tx.put(&map, "count", ScalarValue::counter(1)).unwrap();
+    tx.increment(&map, "count", 5).unwrap();
+    tx.delete(ROOT, "deleted").unwrap();
+    tx.commit();
+    let mut doc2 = doc1.fork();
+    let mut tx = doc1.transaction();
+    tx.put(ROOT, "title", "one").unwrap();
+    tx.commit();
+    let mut tx = doc2.transaction();
+    tx.put(ROOT, "title", "two").unwrap();
+    tx.commit();
+    doc1.merge(&mut doc2).unwrap();
+
+    let saved = doc1.save();
+    let mut lazy = Automerge::load_lazy(&saved).unwrap();
+    assert_eq!(lazy.heads(), doc1.get_heads());
+    assert_eq!(
+        lazy.get(ROOT, "title").unwrap(),
+        doc1.get(ROOT, "title").unwrap()
+    );
+    assert_eq!(lazy.decoded_objects(), 1);
+    assert_eq!(
+        lazy.keys(ROOT).unwrap(),
+        doc1.keys(ROOT).collect::<Vec<_>>()
+    );
+
+    assert_eq!(lazy.length(&list).unwrap(), 2);
+    for i in 0..2 {
+        assert_eq!(lazy.get(&list, i).unwrap(), doc1.get(&list, i).unwrap());
+    }
+    assert_eq!(lazy.text(&text).unwrap(), "hello");
+    assert_eq!(
+        lazy.get(&map, "count").unwrap(),
+        doc1.get(&map, "count").unwrap()
+    );
+    assert_eq!(lazy.decoded_objects(), 4);
+
+    let loaded = lazy.into_automerge().unwrap();
+    assert_eq!(loaded.get_heads(), doc1.get_heads());
+
+    let mut incremental = saved.clone();
+    incremental.extend(doc1.save_incremental());
+    let mut tx = doc1.transaction();
+    tx.put(ROOT, "more", 1).unwrap();
+    tx.commit();
+    incremental.extend(doc1.save_incremental());
+    assert!(matches!(
+        Automerge::load_lazy(&incremental),
+        Err(AutomergeError::Load(load::Error::NotLazyLoadable))
+    ));
+}
+
+#[test]
+fn lazy_document_resolves_conflicts_like_get() {
+    // the actor which writes first sorts last, and the values it writes have greater counters
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(vec![2]));
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc1.insert(&list, 0, "a").unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(vec![1]));
+    for i in 0..3 {
+        doc1.put(ROOT, "x", i).unwrap();
+        doc1.put(&list, 0, i).unwrap();
+        doc1.commit();
+    }
+    doc2.put(ROOT, "x", "b").unwrap();
+    doc2.put(&list, 0, "b").unwrap();
+    doc2.put(ROOT, "y", "b").unwrap();
+    doc2.commit();
+    doc1.put(ROOT, "y", "a").unwrap();
+    doc1.commit();
+    doc1.merge(&mut doc2).unwrap();
+    assert_eq!(doc1.get_all(ROOT, "x").unwrap().len(), 2);
+    assert_eq!(doc1.get_all(&list, 0).unwrap().len(), 2);
+    assert_eq!(doc1.get_all(ROOT, "y").unwrap().len(), 2);
+
+    let mut lazy = Automerge::load_lazy(&doc1.save()).unwrap();
+    for key in ["x", "y"] {
+        assert_eq!(lazy.get(ROOT, key).unwrap(), doc1.get(ROOT, key).unwrap());
+    }
+    assert_eq!(lazy.get(&list, 0).unwrap(), doc1.get(&list, 0).unwrap());
+    assert_eq!(
+        lazy.get(ROOT, "x").unwrap().map(|(v, _)| v),
+        Some(Value::int(2))
+    );
+}
+
+#[test]
+fn read_tx_reads_at_pinned_heads() {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let text = tx.put_object(ROOT, "text", ObjType::Text).unwrap();
+    tx.splice_text(&text, 0, 0, "hello").unwrap();
+    tx.put(ROOT, "title", "one").unwrap();
+    tx.commit();
+
+    let heads = {
+        let rtx = doc.read_tx();
+        assert_eq!(rtx.heads(), doc.get_heads());
+        assert_eq!(rtx.text(&text).unwrap(), "hello");
+        assert_eq!(rtx.length(&text), 5);
+        rtx.heads().to_vec()
+    };
+
+    // another writer commits in between
+    let mut tx = doc.transaction();
+    tx.splice_text(&text, 5, 0, " world").unwrap();
+    tx.put(ROOT, "title", "two").unwrap();
+    tx.put(ROOT, "extra", 1).unwrap();
+    tx.commit();
+
+    let rtx = doc.read_tx_at(&heads).unwrap();
+    assert_eq!(rtx.text(&text).unwrap(), "hello");
+    assert_eq!(rtx.length(&text), 5);
+    assert_eq!(rtx.length(ROOT), 2);
+    assert_eq!(
+        rtx.get(ROOT, "title").unwrap().unwrap().0,
+        Value::str("one")
+    );
+    assert_eq!(rtx.keys(ROOT).collect::<Vec<_>>(), vec!["text", "title"]);
+    assert_eq!(doc.read_tx().text(&text).unwrap(), "hello world");
+
+    let unknown = Automerge::new();
+    assert!(matches!(
+        unknown.read_tx_at(&heads),
+        Err(AutomergeError::MissingHash(_))
+    ));
+}
+
+#[test]
+fn spilling_observer_sends_batches_to_sink() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..50 {
+        doc.insert(&list, i, i as i64).unwrap();
+        doc.commit();
+    }
+    let saved = doc.save();
+
+    let mut expected = VecOpObserver::default();
+    Automerge::load_with(&saved, Some(&mut expected)).unwrap();
+    let expected = expected.take_patches();
+
+    let (send, recv) = std::sync::mpsc::channel();
+    let send = std::sync::Mutex::new(send);
+    let mut observer = SpillingOpObserver::new(VecOpObserver::default(), 10, move |batch| {
+        send.lock().unwrap().send(batch).unwrap();
+    });
+    Automerge::load_with(&saved, Some(&mut observer)).unwrap();
+    let remaining = observer.take_patches();
+    assert!(remaining.len() <= 10);
+    let batches = recv.try_iter().collect::<Vec<_>>();
+    assert_eq!(batches.len(), 4);
+    assert!(batches.iter().all(|b| b.len() == 11));
+    let mut received = batches.concat();
+    received.extend(remaining);
+    assert_eq!(received, expected);
+
+    // transactions spill once committed
+    let (send, recv) = std::sync::mpsc::channel();
+    let send = std::sync::Mutex::new(send);
+    let mut doc = AutoCommit::new().with_observer(SpillingOpObserver::new(
+        VecOpObserver::default(),
+        2,
+        move |batch| send.lock().unwrap().send(batch).unwrap(),
+    ));
+    for i in 0..3 {
+        doc.put(ROOT, i.to_string(), i).unwrap();
+    }
+    assert!(recv.try_recv().is_err());
+    doc.commit();
+    assert_eq!(recv.try_recv().unwrap().len(), 3);
+    doc.put(ROOT, "x", 1).unwrap();
+    doc.commit();
+    doc.observer().flush();
+    assert_eq!(recv.try_recv().unwrap().len(), 1);
+}
+
+#[test]
+fn strict_verification() {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List).unwrap();
+    tx.insert(&list, 0, "a").unwrap();
+    tx.commit();
+    let saved = doc.save_nocompress();
+    let strict = LoadOptions::default().with_verification(VerificationMode::Strict);
+
+    let loaded = Automerge::load_with_options(&saved, &strict).unwrap();
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+    let mut tx = doc.transaction();
+    tx.insert(&list, 1, "b").unwrap();
+    tx.commit();
+    let with_changes = [saved.clone(), doc.save_incremental()].concat();
+    assert_eq!(
+        Automerge::load_with_options(&with_changes, &strict)
+            .unwrap()
+            .get_heads(),
+        doc.get_heads()
+    );
+
+    let trailing = [saved.clone(), b"junk".to_vec()].concat();
+    match Automerge::load_with_options(&trailing, &strict) {
+        Err(AutomergeError::Verification(VerificationError::TrailingData { offset, len })) => {
+            assert_eq!((offset, len), (saved.len(), 4));
+        }
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+
+    // documents written without head indices load normally but not strictly
+    let mut len_bytes = &saved[9..];
+    leb128::read::unsigned(&mut len_bytes).unwrap();
+    let body = &saved[saved.len() - len_bytes.len()..saved.len() - 1];
+    let header = storage::Header::new(storage::ChunkType::Document, body);
+    let mut no_indices = Vec::new();
+    header.write(&mut no_indices);
+    no_indices.extend(body);
+    assert!(Automerge::load(&no_indices).is_ok());
+    assert!(matches!(
+        Automerge::load_with_options(&no_indices, &strict),
+        Err(AutomergeError::Verification(
+            VerificationError::HeadIndicesMismatch {
+                expected: 1,
+                found: 0,
+                ..
+            }
+        ))
+    ));
+    assert_eq!(
+        Automerge::load_with_options(&no_indices, &strict)
+            .unwrap_err()
+            .category(),
+        ErrorCategory::Corruption
+    );
+}
+
+#[test]
+fn list_window() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..1000 {
+        doc.insert(&list, i, i as i64).unwrap();
+    }
+    doc.delete(&list, 0).unwrap();
+    let mut other = doc.fork();
+    doc.put(&list, 501, "mine").unwrap();
+    other.put(&list, 501, "theirs").unwrap();
+    doc.merge(&mut other).unwrap();
+
+    let window = doc.list_window(&list, 500, 10).unwrap();
+    assert_eq!(window.total, 999);
+    assert_eq!(window.items.len(), 10);
+    for (item, (_, value, id)) in window.items.iter().zip(doc.list_range(&list, 500..510)) {
+        assert_eq!(item.value, value);
+        assert_eq!(item.id, id);
+    }
+    assert_eq!(doc.list_window(&list, 995, 10).unwrap().items.len(), 4);
+    assert!(doc.list_window(&list, 2000, 10).unwrap().items.is_empty());
+
+    // changes elsewhere leave the window intact
+    doc.put(ROOT, "other", 1).unwrap();
+    assert!(doc.is_window_current(&window));
+    doc.put(&list, 600, 0).unwrap();
+    assert!(doc.is_window_current(&window));
+
+    // updating an element keeps its id but invalidates the window
+    doc.put(&list, 505, "updated").unwrap();
+    assert!(!doc.is_window_current(&window));
+    let updated = doc.list_window(&list, 500, 10).unwrap();
+    assert_eq!(updated.items[5].elem, window.items[5].elem);
+    assert_ne!(updated.items[5].id, window.items[5].id);
+    assert_ne!(updated.generation(), window.generation());
+
+    doc.insert(&list, 0, "shift").unwrap();
+    assert!(!doc.is_window_current(&updated));
+    assert_eq!(
+        doc.list_window(&list, 501, 10).unwrap().items[0].elem,
+        updated.items[0].elem
+    );
+}
+
+#[test]
+fn changes_affecting_subtree() {
+    let mut doc = AutoCommit::new();
+    let a = doc.put_object(ROOT, "a", ObjType::Map).unwrap();
+    let b = doc.put_object(ROOT, "b", ObjType::Map).unwrap();
+    let created = doc.commit();
+    doc.put(&b, "x", 1).unwrap();
+    let b_only = doc.commit();
+    let list = doc.put_object(&a, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, ScalarValue::counter(0)).unwrap();
+    let nested = doc.commit();
+    doc.increment(&list, 0, 1).unwrap();
+    let incremented = doc.commit();
+    doc.delete(&list, 0).unwrap();
+    let deleted = doc.commit();
+    doc.put(ROOT, "c", 1).unwrap();
+    doc.commit();
+
+    let hashes = |changes: Vec<&Change>| changes.iter().map(|c| c.hash()).collect::<Vec<_>>();
+    assert_eq!(
+        hashes(doc.changes_affecting(&a).unwrap()),
+        vec![created, nested, incremented, deleted]
+    );
+    assert_eq!(
+        hashes(doc.changes_affecting(&list).unwrap()),
+        vec![nested, incremented, deleted]
+    );
+    assert_eq!(
+        hashes(doc.changes_affecting(&b).unwrap()),
+        vec![created, b_only]
+    );
+    assert_eq!(doc.changes_affecting(ROOT).unwrap().len(), 6);
+}
+
+#[test]
+fn conflict_policies() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from([1]));
+    let map = doc1.put_object(ROOT, "map", ObjType::Map).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from([2]));
+    let mut doc3 = doc1.fork().with_actor(ActorId::from([3]));
+    for (doc, value, time) in [
+        (&mut doc1, 5, 300),
+        (&mut doc2, 10, 100),
+        (&mut doc3, 1, 200),
+    ] {
+        doc.put(&map, "a", value).unwrap();
+        doc.put(&map, "b", value).unwrap();
+        doc.commit_with(CommitOptions::default().with_time(time));
+    }
+    doc1.merge(&mut doc2).unwrap();
+    doc1.merge(&mut doc3).unwrap();
+
+    let winner = |doc: &mut AutoCommit, key: &str| match doc.get(&map, key).unwrap() {
+        Some((Value::Scalar(s), _)) => s.to_i64().unwrap(),
+        other => panic!("unexpected {:?}", other),
+    };
+    // the default winner is from the greatest actor
+    assert_eq!(winner(&mut doc1, "a"), 1);
+
+    doc1.set_object_conflict_policy(&map, Some(ConflictPolicy::MaxWins))
+        .unwrap();
+    assert_eq!(winner(&mut doc1, "a"), 10);
+    assert_eq!(winner(&mut doc1, "b"), 10);
+    doc1.set_conflict_policy(&map, "b", Some(ConflictPolicy::MinWins))
+        .unwrap();
+    assert_eq!(winner(&mut doc1, "a"), 10);
+    assert_eq!(winner(&mut doc1, "b"), 1);
+    doc1.set_conflict_policy(&map, "b", Some(ConflictPolicy::LastWriterWins))
+        .unwrap();
+    assert_eq!(winner(&mut doc1, "b"), 5);
+    let second = |_: &[(Value<'_>, ExId)]| -> Option<usize> { Some(1) };
+    doc1.set_conflict_policy(
+        &map,
+        "b",
+        Some(ConflictPolicy::Custom(std::sync::Arc::new(second))),
+    )
+    .unwrap();
+    assert_eq!(
+        winner(&mut doc1, "b"),
+        doc1.get_all(&map, "b").unwrap()[1].0.to_i64().unwrap()
+    );
+    // get_all is unaffected
+    assert_eq!(doc1.get_all(&map, "a").unwrap().len(), 3);
+
+    doc1.set_object_conflict_policy(&map, None).unwrap();
+    assert_eq!(winner(&mut doc1, "a"), 1);
+
+    // a policy for a type applies to conflicts between values of that type
+    doc1.set_type_conflict_policy(SchemaType::Int, Some(ConflictPolicy::MaxWins));
+    assert_eq!(winner(&mut doc1, "a"), 10);
+    doc1.set_conflict_policy(&map, "b", None).unwrap();
+    assert_eq!(winner(&mut doc1, "b"), 10);
+
+    // ranges and values see the same winner as get
+    let ranged = doc1
+        .map_range(&map, ..)
+        .map(|(k, v, _)| (k.to_string(), v.to_i64().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(ranged, vec![("a".to_string(), 10), ("b".to_string(), 10)]);
+    let heads = doc1.get_heads();
+    assert_eq!(
+        doc1.map_range_at(&map, .., &heads)
+            .map(|(_, v, _)| v.to_i64().unwrap())
+            .collect::<Vec<_>>(),
+        vec![10, 10]
+    );
+    assert_eq!(
+        doc1.values(&map)
+            .map(|(v, _)| v.to_i64().unwrap())
+            .collect::<Vec<_>>(),
+        vec![10, 10]
+    );
+
+    // and so do the puts reported when the changes are applied
+    let mut patched = Automerge::new();
+    patched.set_type_conflict_policy(SchemaType::Int, Some(ConflictPolicy::MinWins));
+    let mut observer = VecOpObserver::default();
+    patched
+        .apply_changes_with(
+            doc1.get_changes(&[]).unwrap().into_iter().cloned(),
+            Some(&mut observer),
+        )
+        .unwrap();
+    let last_put = observer
+        .take_patches()
+        .into_iter()
+        .filter_map(|p| match p {
+            Patch::Put { prop, value, .. } if prop == Prop::Map("a".into()) => Some(value.0),
+            _ => None,
+        })
+        .last();
+    assert_eq!(last_put.and_then(|v| v.to_i64()), Some(1));
+}
+
+#[test]
+fn iter_ops() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from([1]));
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.splice_text(&text, 0, 0, "ab").unwrap();
+    doc1.put(ROOT, "count", ScalarValue::counter(1)).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from([2]));
+    doc2.splice_text(&text, 1, 1, "c").unwrap();
+    doc2.increment(ROOT, "count", 2).unwrap();
+    doc1.merge(&mut doc2).unwrap();
+
+    // authorship of each visible character
+    let authors: Vec<_> = doc1
+        .iter_ops(&text)
+        .unwrap()
+        .filter(|op| op.visible)
+        .map(|op| match op.id {
+            ExId::Id(_, actor, _) => actor,
+            ExId::Root => panic!("root op"),
+        })
+        .collect();
+    assert_eq!(authors, vec![ActorId::from([1]), ActorId::from([2])]);
+
+    let ops: Vec<_> = doc1.iter_ops(&text).unwrap().collect();
+    // "c" was inserted after "a" and so comes before the deleted "b", which is only marked as
+    // deleted by the successor of its op
+    assert_eq!(ops.len(), 3);
+    assert!(ops.iter().all(|op| op.insert && op.pred.is_empty()));
+    assert_eq!(ops[0].key, RawKey::Seq(None));
+    assert_eq!(ops[1].key, RawKey::Seq(Some(ops[0].id.clone())));
+    assert_eq!(ops[2].key, RawKey::Seq(Some(ops[0].id.clone())));
+    assert_eq!(ops[2].succ.len(), 1);
+    assert!(!ops[2].visible);
+
+    let root: Vec<_> = doc1.iter_ops(ROOT).unwrap().collect();
+    assert_eq!(root[0].key, RawKey::Map("count".into()));
+    assert_eq!(root[0].action, OpType::Put(ScalarValue::counter(1)));
+    assert_eq!(root[1].action, OpType::Increment(2));
+    assert_eq!(root[1].pred, vec![root[0].id.clone()]);
+    assert_eq!(root[2].action, OpType::Make(ObjType::Text));
+}
+
+#[test]
+fn find_commits_by_message() {
+    let mut doc = AutoCommit::new();
+    let commit = |doc: &mut AutoCommit, message: &str| {
+        doc.put(ROOT, "x", message).unwrap();
+        doc.commit_with(CommitOptions::default().with_message(message))
+    };
+    let pricing = commit(&mut doc, "Add pricing section");
+    let caprice = commit(&mut doc, "Fix caprice typo");
+    doc.put(ROOT, "x", 1).unwrap();
+    doc.commit();
+
+    let hashes = |changes: Vec<&Change>| changes.iter().map(|c| c.hash()).collect::<Vec<_>>();
+    let prefix = CommitQuery::WordPrefix("PRIC".into());
+    let substring = CommitQuery::Substring("pric".into());
+    assert_eq!(hashes(doc.find_commits(&prefix)), vec![pricing]);
+    assert_eq!(hashes(doc.find_commits(&substring)), vec![pricing, caprice]);
+
+    doc.enable_message_index();
+    let later = commit(&mut doc, "Reprice: update pricing table");
+    assert_eq!(hashes(doc.find_commits(&prefix)), vec![pricing, later]);
+    assert_eq!(
+        hashes(doc.find_commits(&substring)),
+        vec![pricing, caprice, later]
+    );
+}
+
+#[test]
+fn attribute_text() {
+    let alice = ActorId::from([1]);
+    let bob = ActorId::from([2]);
+    let mut doc1 = AutoCommit::new().with_actor(alice.clone());
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.splice_text(&text, 0, 0, "hello world").unwrap();
+    let first = doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(bob.clone());
+    doc2.splice_text(&text, 5, 1, ", dear ").unwrap();
+    let second = doc2.commit();
+    doc1.merge(&mut doc2).unwrap();
+    doc1.splice_text(&text, 0, 1, "H").unwrap();
+    let third = doc1.commit();
+
+    assert_eq!(doc1.text(&text).unwrap(), "Hello, dear world");
+    assert_eq!(
+        doc1.attribute_text(&text).unwrap(),
+        vec![
+            (0..1, alice.clone(), third),
+            (1..5, alice.clone(), first),
+            (5..12, bob.clone(), second),
+            (12..17, alice.clone(), first),
+        ]
+    );
+    assert_eq!(
+        doc1.attribute_text_at(&text, &[first]).unwrap(),
+        vec![(0..11, alice.clone(), first)]
+    );
+    assert_eq!(
+        doc1.attribute_text_between(&text, &[first], &[third])
+            .unwrap(),
+        vec![(0..1, alice, third), (5..12, bob, second)]
+    );
+}
+
+#[test]
+fn cursors_follow_their_element() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    for (i, v) in ["a", "b", "c"].iter().enumerate() {
+        doc.insert(&list, i, *v).unwrap();
+    }
+    let start = doc.cursor(&list, 0).unwrap();
+    let after_b = doc.cursor(&list, 2).unwrap();
+    let end = doc.cursor(&list, 3).unwrap();
+    assert!(doc.cursor(&list, 4).is_err());
+
+    doc.insert(&list, 0, "z").unwrap();
+    assert_eq!(doc.cursor_position(&start).unwrap(), 0);
+    assert_eq!(doc.cursor_position(&after_b).unwrap(), 3);
+    // the element before the cursor has been deleted
+    doc.delete(&list, 2).unwrap();
+    assert_eq!(doc.cursor_position(&after_b).unwrap(), 2);
+    assert_eq!(doc.cursor_position(&end).unwrap(), 3);
+}
+
+#[test]
+fn cursors_skip_nodes_without_their_element() {
+    let mut doc = AutoCommit::new();
+    doc.set_node_size(NodeSize::Fixed(2)).unwrap();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..100 {
+        doc.insert(&list, i, i as i64).unwrap();
+    }
+    let cursors = (0..=100)
+        .map(|i| doc.cursor(&list, i).unwrap())
+        .collect::<Vec<_>>();
+    // delete every third element and update some of the others, so elements are spread over
+    // several nodes
+    for i in (0..100).rev().filter(|i| i % 3 == 0) {
+        doc.delete(&list, i).unwrap();
+    }
+    for i in (0..doc.length(&list)).step_by(5) {
+        doc.put(&list, i, "x").unwrap();
+    }
+    for (i, cursor) in cursors.iter().enumerate() {
+        let deleted_before = (0..i).filter(|j| j % 3 == 0).count();
+        assert_eq!(doc.cursor_position(cursor).unwrap(), i - deleted_before);
+    }
+}
+
+#[test]
+fn text_session_reconciles_local_and_remote_edits() {
+    let mut doc = Automerge::new().with_actor(ActorId::from([1]));
+    let mut tx = doc.transaction();
+    let text = tx.put_object(ROOT, "text", ObjType::Text).unwrap();
+    tx.splice_text(&text, 0, 0, "hello world").unwrap();
+    tx.commit();
+    let mut remote = doc.fork().with_actor(ActorId::from([2]));
+
+    let mut session = TextSession::new(&doc, &text).unwrap();
+    // the user types while a remote peer edits the start of the text
+    session.input(11, 0, "!").unwrap();
+    session.input(5, 1, "_").unwrap();
+    assert_eq!(session.text(), "hello_world!");
+    assert_eq!(session.caret(), 6);
+    let mut tx = remote.transaction();
+    tx.splice_text(&text, 0, 1, "J").unwrap();
+    tx.splice_text(&text, 0, 0, ">> ").unwrap();
+    tx.commit();
+    doc.merge(&mut remote).unwrap();
+
+    let edits = session.reconcile(&mut doc).unwrap();
+    assert_eq!(doc.text(&text).unwrap(), ">> Jello_world!");
+    assert_eq!(session.text(), ">> Jello_world!");
+    // the caret is still just after the underscore
+    assert_eq!(session.caret(), 9);
+    let mut buffer = "hello_world!".to_string();
+    for edit in edits {
+        let start = buffer
+            .char_indices()
+            .nth(edit.index)
+            .map(|(i, _)| i)
+            .unwrap();
+        buffer.replace_range(start..start + edit.delete, &edit.insert);
+    }
+    assert_eq!(buffer, ">> Jello_world!");
+    assert!(session.reconcile(&mut doc).unwrap().is_empty());
+
+    // a document without the changes the session was reconciled at is an error, not a panic,
+    // and the pending input is kept
+    session.input(0, 3, "").unwrap();
+    let mut other = Automerge::new();
+    assert!(matches!(
+        session.reconcile(&mut other),
+        Err(AutomergeError::MissingHash(_))
+    ));
+    session.reconcile(&mut doc).unwrap();
+    assert_eq!(doc.text(&text).unwrap(), "Jello_world!");
+}
+
+#[test]
+fn audit() {
+    let mut doc = Automerge::new();
+    doc.set_node_size(NodeSize::Fixed(2)).unwrap();
+    let mut tx = doc.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..50 {
+        tx.insert(&list, i, i as i64).unwrap();
+    }
+    tx.put(ROOT, "a", 1).unwrap();
+    tx.commit();
+    let mut tx = doc.transaction();
+    for i in 0..20 {
+        tx.delete(&list, i).unwrap();
+        tx.put(&list, i, "x").unwrap();
+    }
+    tx.put(ROOT, "a", 2).unwrap();
+    tx.commit();
+
+    let report = doc.audit();
+    assert!(report.is_healthy(), "{}", report);
+    assert_eq!(report.objects, 2);
+    assert_eq!(report.ops, doc.ops.len());
+
+    // forget that the first value of "a" was overwritten
+    let root = crate::types::ObjId::root();
+    let index = doc
+        .ops
+        .iter_obj(&root)
+        .unwrap()
+        .position(|op| !op.succ.is_empty())
+        .unwrap();
+    doc.ops
+        .replace(&root, index, |op| op.succ = crate::types::OpIds::empty());
+    let report = doc.audit();
+    assert_eq!(report.problems.len(), 1, "{}", report);
+    assert!(matches!(
+        report.problems[0],
+        AuditProblem::MissingSucc { .. }
+    ));
+}
+
+#[test]
+fn exid_string_encoding() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, "a").unwrap();
+    let encoded = list.to_string();
+    assert_eq!(encoded, format!("1@{}", doc.get_actor()));
+    assert_eq!("_root".parse::<ExId>().unwrap(), ROOT);
+
+    // the same string refers to the same object after loading and in forks
+    let mut loaded = AutoCommit::load(&doc.save()).unwrap();
+    let mut forked = loaded.fork();
+    for other in [&mut loaded, &mut forked] {
+        let id: ExId = encoded.parse().unwrap();
+        assert_eq!(id, list);
+        assert_eq!(other.object_type(&id), Some(ObjType::List));
+        assert_eq!(other.get(&id, 0).unwrap().unwrap().0, Value::str("a"));
+    }
+
+    for bad in ["", "root", "1", "x@aabb", "1@xyz"] {
+        assert!(matches!(
+            bad.parse::<ExId>(),
+            Err(AutomergeError::InvalidObjIdFormat(_))
+        ));
+    }
+}
+
+#[test]
+fn lookup_cache() {
+    let mut doc = Automerge::new();
+    doc.set_cache_capacity(2);
+    let mut uncached = Automerge::new();
+    uncached.set_cache_capacity(0);
+    for doc in [&mut doc, &mut uncached] {
+        let mut tx = doc.transaction();
+        let map = tx.put_object(ROOT, "map", ObjType::Map).unwrap();
+        // keys are inserted before and after those already looked up, moving their ops
+        for key in ["m", "z", "a", "n", "b", "m", "y", "a"] {
+            let before = tx.get(&map, key).unwrap().map(|(v, _)| v.to_i64().unwrap());
+            tx.put(&map, key, before.unwrap_or(0) + 1).unwrap();
+        }
+        tx.commit();
+    }
+    let map = doc.get(ROOT, "map").unwrap().unwrap().1;
+    let uncached_map = uncached.get(ROOT, "map").unwrap().unwrap().1;
+    for key in ["a", "b", "m", "n", "y", "z"] {
+        assert_eq!(
+            doc.get(&map, key).unwrap().map(|(v, _)| v),
+            uncached.get(&uncached_map, key).unwrap().map(|(v, _)| v)
+        );
+    }
+    assert_eq!(doc.get(&map, "a").unwrap().unwrap().0, Value::int(2));
+
+    // an id with an actor index from elsewhere is resolved through the cache until the object
+    // goes away
+    let mut tx = doc.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List).unwrap();
+    let parsed: ExId = list.to_string().parse().unwrap();
+    tx.insert(&parsed, 0, "a").unwrap();
+    assert_eq!(tx.length(&parsed), 1);
+    tx.rollback();
+    assert!(doc.get(&parsed, 0).is_err());
+}
+
+#[test]
+fn bytes_streams() {
+    let data = (0..BYTES_CHUNK_SIZE * 2 + 100)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    let mut doc = AutoCommit::new();
+    let blob = doc
+        .put_bytes_stream(ROOT, "blob", std::io::Cursor::new(&data))
+        .unwrap();
+    assert_eq!(doc.length(&blob), 3);
+    doc.put(ROOT, "small", vec![1u8, 2, 3]).unwrap();
+    doc.put(ROOT, "text", "not bytes").unwrap();
+    doc.commit();
+
+    let loaded = Automerge::load(&doc.save()).unwrap();
+    let mut read = Vec::new();
+    loaded
+        .get_bytes_reader(ROOT, "blob")
+        .unwrap()
+        .unwrap()
+        .read_to_end(&mut read)
+        .unwrap();
+    assert_eq!(read, data);
+
+    let mut read = Vec::new();
+    doc.get_bytes_reader(ROOT, "small")
+        .unwrap()
+        .unwrap()
+        .read_to_end(&mut read)
+        .unwrap();
+    assert_eq!(read, vec![1, 2, 3]);
+    assert!(doc.get_bytes_reader(ROOT, "missing").unwrap().is_none());
+    assert!(matches!(
+        doc.get_bytes_reader(ROOT, "text"),
+        Err(AutomergeError::InvalidValueType { .. })
+    ));
+}
+
+#[test]
+fn snapshots_are_isolated_from_later_changes() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Snapshot>();
+
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List).unwrap();
+    tx.insert(&list, 0, "a").unwrap();
+    tx.put(ROOT, "title", "first").unwrap();
+    tx.commit();
+
+    let snapshot = doc.snapshot();
+    assert_eq!(snapshot.heads(), doc.get_heads());
+    let reader = std::thread::spawn(move || {
+        let title = snapshot.get(ROOT, "title").unwrap().unwrap().0;
+        let list = snapshot.get(ROOT, "list").unwrap().unwrap().1;
+        (title.into_owned(), snapshot.length(&list))
+    });
+
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "title", "second").unwrap();
+    tx.insert(&list, 1, "b").unwrap();
+    let (title, len) = reader.join().unwrap();
+    tx.commit();
+
+    assert_eq!(title, Value::str("first"));
+    assert_eq!(len, 1);
+    assert_eq!(doc.length(&list), 2);
+    assert_eq!(doc.audit().problems, vec![]);
+}
+
+#[test]
+fn snapshots_taken_during_a_transaction() {
+    let mut doc = Automerge::new();
+    doc.set_node_size(NodeSize::Fixed(2)).unwrap();
+    let mut tx = doc.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..100 {
+        tx.insert(&list, i, i as i64).unwrap();
+    }
+    tx.commit();
+
+    let mut tx = doc.transaction();
+    tx.put(&list, 50, "changed").unwrap();
+    let during = tx.snapshot();
+    // the nodes the snapshot shares with the document are copied when the transaction changes
+    // them, so neither sees the other's changes
+    tx.delete(&list, 0).unwrap();
+    tx.put(&list, 99 - 1, "last").unwrap();
+    tx.rollback();
+
+    assert_eq!(during.heads(), doc.get_heads());
+    assert_eq!(during.length(&list), 100);
+    assert_eq!(
+        during.get(&list, 50).unwrap().unwrap().0,
+        Value::str("changed")
+    );
+    assert_eq!(during.get(&list, 0).unwrap().unwrap().0, Value::int(0));
+    assert_eq!(during.get(&list, 99).unwrap().unwrap().0, Value::int(99));
+    assert_eq!(doc.get(&list, 50).unwrap().unwrap().0, Value::int(50));
+    assert_eq!(doc.audit().problems, vec![]);
+}
+
+#[test]
+fn actor_metadata() {
+    let mut doc1 = AutoCommit::new();
+    let mut doc2 = AutoCommit::new();
+    let actor1 = doc1.get_actor().clone();
+    let actor2 = doc2.get_actor().clone();
+    let alice = ActorMetadata {
+        name: Some("Alice".to_string()),
+        device: Some("laptop".to_string()),
+        public_key: Some(vec![1, 2, 3]),
+    };
+    let bob = ActorMetadata {
+        name: Some("Bob".to_string()),
+        ..Default::default()
+    };
+    // both peers create the registry concurrently
+    doc1.set_actor_metadata(&actor1, &alice).unwrap();
+    doc2.set_actor_metadata(&actor2, &bob).unwrap();
+    doc1.merge(&mut doc2).unwrap();
+    doc1.commit();
+
+    let loaded = Automerge::load(&doc1.save()).unwrap();
+    assert_eq!(loaded.actor_metadata(&actor1).unwrap(), Some(alice));
+    assert_eq!(loaded.actor_metadata(&actor2).unwrap(), Some(bob));
+    assert_eq!(loaded.actor_metadata(&ActorId::random()).unwrap(), None);
+
+    let renamed = ActorMetadata {
+        name: Some("Alice B".to_string()),
+        ..Default::default()
+    };
+    doc1.set_actor_metadata(&actor1, &renamed).unwrap();
+    assert_eq!(doc1.actor_metadata(&actor1).unwrap(), Some(renamed));
+}
+
+#[test]
+fn content_hash_of_incremented_counters() {
+    let mut doc1 = AutoCommit::new();
+    doc1.put(ROOT, "count", ScalarValue::counter(1)).unwrap();
+    doc1.increment(ROOT, "count", 2).unwrap();
+    let mut doc2 = AutoCommit::new();
+    doc2.put(ROOT, "count", ScalarValue::counter(3)).unwrap();
+    assert_eq!(
+        doc1.content_hash(&[]).unwrap(),
+        doc2.content_hash(&[]).unwrap()
+    );
+
+    let heads = doc1.get_heads();
+    let (_, value, _) = doc1.map_range_at(ROOT, .., &heads).next().unwrap();
+    assert_eq!(value, Value::counter(3));
+}
+
+#[test]
+fn content_hash_ignores_history() {
+    // one replica builds the state directly
+    let mut doc1 = AutoCommit::new();
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc1.insert(&list, 0, "a").unwrap();
+    doc1.insert(&list, 1, "b").unwrap();
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.splice_text(&text, 0, 0, "hello").unwrap();
+    doc1.put(ROOT, "n", 1).unwrap();
+
+    // the other gets there in a different order, with edits which are later undone
+    let mut doc2 = AutoCommit::new();
+    doc2.put(ROOT, "n", 5).unwrap();
+    let text = doc2.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc2.splice_text(&text, 0, 0, "help").unwrap();
+    doc2.commit();
+    doc2.splice_text(&text, 3, 1, "").unwrap();
+    doc2.splice_text(&text, 3, 0, "lo").unwrap();
+    let list = doc2.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc2.insert(&list, 0, "b").unwrap();
+    doc2.insert(&list, 0, "x").unwrap();
+    doc2.put(&list, 0, "a").unwrap();
+    doc2.put(ROOT, "n", 1).unwrap();
+    doc2.put(ROOT, "extra", true).unwrap();
+    doc2.delete(ROOT, "extra").unwrap();
+
+    let hash = doc1.content_hash(&[]).unwrap();
+    assert_eq!(doc2.content_hash(&[]).unwrap(), hash);
+
+    let heads = doc2.get_heads();
+    doc2.put(ROOT, "n", 1.0).unwrap();
+    assert_ne!(doc2.content_hash(&[]).unwrap(), hash);
+    assert_eq!(doc2.content_hash(&heads).unwrap(), hash);
+
+    let missing = ChangeHash([0; 32]);
+    assert!(matches!(
+        doc2.content_hash(&[missing]),
+        Err(AutomergeError::MissingHash(h)) if h == missing
+    ));
+}
+
+#[test]
+fn insert_sorted_keeps_list_sorted() {
+    let by_int = |a: &Value<'_>, b: &Value<'_>| a.to_i64().cmp(&b.to_i64());
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "scores", ObjType::List).unwrap();
+    for score in [5, 1, 9, 5, 3, 7] {
+        doc.insert_sorted(&list, score, by_int).unwrap();
+    }
+    let scores = doc
+        .list_range(&list, ..)
+        .map(|(_, v, _)| v.to_i64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(scores, vec![1, 3, 5, 5, 7, 9]);
+    // equal elements go after the existing ones
+    assert_eq!(doc.insert_sorted(&list, 5, by_int).unwrap(), 4);
+    assert_eq!(doc.insert_sorted(&list, 0, by_int).unwrap(), 0);
+    assert_eq!(doc.insert_sorted(&list, 10, by_int).unwrap(), 8);
+
+    doc.commit();
+    let doc = Automerge::load(&doc.save()).unwrap();
+    let search = |target: i64| {
+        doc.binary_search_by(&list, |v| v.to_i64().unwrap().cmp(&target))
+            .unwrap()
+    };
+    assert_eq!(search(3), Ok(2));
+    assert_eq!(search(4), Err(3));
+    assert_eq!(search(-1), Err(0));
+    assert_eq!(search(11), Err(9));
+    assert!(matches!(search(5), Ok(3..=5)));
+}
+
+#[test]
+fn history_fence_drops_old_changes_on_save() {
+    let mut doc = AutoCommit::new();
+    let actor = doc.get_actor().clone();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.put(ROOT, "counter", ScalarValue::counter(1)).unwrap();
+    for i in 0..100 {
+        doc.put(ROOT, "n", format!("overwritten {}", i * 7919 % 1000))
+            .unwrap();
+        doc.put(ROOT, "m", i * 7919).unwrap();
+        doc.insert(&list, 0, i).unwrap();
+        doc.splice_text(&text, 0, 0, "ab").unwrap();
+        doc.increment(ROOT, "counter", 1).unwrap();
+        doc.commit();
+    }
+    doc.splice_text(&text, 0, 150, "").unwrap();
+    doc.delete(&list, 0).unwrap();
+    doc.commit();
+    let fence = doc.get_heads();
+    doc.put(ROOT, "after", "fence").unwrap();
+    doc.increment(ROOT, "counter", 1).unwrap();
+    doc.commit();
+
+    let full = doc.save();
+    assert_eq!(doc.history_fence(), &[] as &[ChangeHash]);
+    doc.set_history_fence(&fence).unwrap();
+    assert_eq!(doc.history_fence(), fence.as_slice());
+    let fenced = doc.save();
+    assert!(fenced.len() < full.len() * 2 / 3);
+
+    let mut loaded = Automerge::load(&fenced).unwrap();
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+    assert_eq!(loaded.history_fence(), fence.as_slice());
+    assert_eq!(
+        loaded.content_hash(&[]).unwrap(),
+        doc.content_hash(&[]).unwrap()
+    );
+    assert_eq!(
+        loaded.get(ROOT, "counter").unwrap().unwrap().0,
+        Value::counter(102)
+    );
+    assert_eq!(loaded.text(&text).unwrap(), "ab".repeat(25));
+    assert_eq!(loaded.length(&list), 99);
+    assert_eq!(loaded.get_changes(&[]).unwrap().len(), 1);
+    assert_eq!(loaded.get_changes(&fence).unwrap().len(), 1);
+    // the fence itself can still be read, but nothing before it
+    assert_eq!(loaded.get_at(ROOT, "after", &fence).unwrap(), None);
+    let before = doc.get_changes(&[]).unwrap()[5].hash();
+    assert!(matches!(
+        loaded.get_at(ROOT, "n", &[before]),
+        Err(AutomergeError::MissingHash(_))
+    ));
+    assert!(matches!(
+        loaded.set_history_fence(&[before]),
+        Err(AutomergeError::MissingHash(_))
+    ));
+
+    // the original actor carries on where it left off, and both copies accept each other's
+    // changes
+    loaded.set_actor(actor);
+    let mut tx = loaded.transaction();
+    tx.insert(&list, 0, "new").unwrap();
+    tx.splice_text(&text, 1, 0, "x").unwrap();
+    tx.commit();
+    doc.set_actor(ActorId::random());
+    doc.put(ROOT, "other", true).unwrap();
+    doc.commit();
+    doc.merge(&mut AutoCommit::load(&loaded.save()).unwrap())
+        .unwrap();
+    loaded
+        .apply_changes(doc.get_changes(&fence).unwrap().into_iter().cloned())
+        .unwrap();
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+    assert_eq!(
+        loaded.content_hash(&[]).unwrap(),
+        doc.content_hash(&[]).unwrap()
+    );
+
+    // changes beneath the fence are already applied
+    let old = doc.get_changes(&[]).unwrap()[..10]
+        .iter()
+        .map(|c| (*c).clone())
+        .collect::<Vec<_>>();
+    let heads = loaded.get_heads();
+    loaded.apply_changes(old).unwrap();
+    assert_eq!(loaded.get_heads(), heads);
+
+    let reloaded = Automerge::load(&loaded.save()).unwrap();
+    assert_eq!(reloaded.get_heads(), doc.get_heads());
+    assert_eq!(reloaded.history_fence(), fence.as_slice());
+    assert!(matches!(
+        Automerge::new().load_incremental(&fenced),
+        Err(AutomergeError::Load(load::Error::Fenced))
+    ));
+}
+
+#[test]
+fn history_fence_must_be_an_ancestor_of_later_changes() {
+    let mut doc1 = AutoCommit::new();
+    doc1.put(ROOT, "a", 1).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork();
+    doc1.put(ROOT, "b", 1).unwrap();
+    doc1.commit();
+    let fence = doc1.get_heads();
+    doc2.put(ROOT, "c", 1).unwrap();
+    doc2.commit();
+    let concurrent = doc2.get_heads()[0];
+    doc1.merge(&mut doc2).unwrap();
+    assert!(matches!(
+        doc1.set_history_fence(&fence),
+        Err(AutomergeError::ConcurrentWithFence(h)) if h == concurrent
+    ));
+    let merged = doc1.get_heads();
+    doc1.set_history_fence(&merged).unwrap();
+
+    // a change concurrent with the fence which arrives after it is set means the document is
+    // saved in full
+    let mut doc3 = AutoCommit::new();
+    doc3.put(ROOT, "x", 1).unwrap();
+    doc3.commit();
+    let mut doc4 = doc3.fork();
+    doc3.put(ROOT, "y", 1).unwrap();
+    doc3.commit();
+    let fence = doc3.get_heads();
+    doc3.set_history_fence(&fence).unwrap();
+    doc4.put(ROOT, "z", 1).unwrap();
+    doc3.merge(&mut doc4).unwrap();
+    let loaded = Automerge::load(&doc3.save()).unwrap();
+    assert_eq!(loaded.history_fence(), &[] as &[ChangeHash]);
+    assert_eq!(loaded.get_changes(&[]).unwrap().len(), 3);
+}
+
+#[test]
+fn text_inserts_are_observed_as_splices() {
+    let mut doc = AutoCommit::new().with_observer(VecOpObserver::default());
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.commit();
+    doc.observer().take_patches();
+
+    let path = vec![(ROOT, Prop::Map("text".into()))];
+    doc.splice_text(&text, 0, 0, "hello").unwrap();
+    doc.insert(&text, 5, "!").unwrap();
+    doc.splice(&list, 0, 0, vec!["a".into(), "b".into()])
+        .unwrap();
+    doc.commit();
+    let patches = doc.observer().take_patches();
+    assert_eq!(
+        patches[..2],
+        [
+            Patch::SpliceText {
+                path: path.clone(),
+                obj: text.clone(),
+                index: 0,
+                value: "hello".into(),
+            },
+            Patch::SpliceText {
+                path: path.clone(),
+                obj: text.clone(),
+                index: 5,
+                value: "!".into(),
+            },
+        ]
+    );
+    // lists still see an insert per element
+    assert_eq!(patches.len(), 4);
+    assert!(matches!(patches[2], Patch::Insert { index: 0, .. }));
+
+    // contiguous inserts in a change are a single splice when the change is applied, and when
+    // a document is loaded
+    let mut observer = VecOpObserver::default();
+    Automerge::new()
+        .load_incremental_with(&doc.save(), Some(&mut observer))
+        .unwrap();
+    let splices = observer
+        .take_patches()
+        .into_iter()
+        .filter(|p| matches!(p, Patch::SpliceText { .. }))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        splices,
+        vec![Patch::SpliceText {
+            path: path.clone(),
+            obj: text.clone(),
+            index: 0,
+            value: "hello!".into(),
+        },]
+    );
+
+    let mut observer = VecOpObserver::default();
+    Automerge::load_with(&doc.save(), Some(&mut observer)).unwrap();
+    assert!(observer.take_patches().contains(&Patch::SpliceText {
+        path,
+        obj: text,
+        index: 0,
+        value: "hello!".into(),
+    }));
+}
+
+#[test]
+fn observers_without_splice_text_see_text_inserts() {
+    #[derive(Debug, Default, Clone)]
+    struct Inserts(Vec<(usize, String)>);
+
+    impl OpObserver for Inserts {
+        fn insert(&mut self, _: Parents<'_>, _: ExId, index: usize, (value, _): (Value<'_>, ExId)) {
+            self.0.push((index, value.into_string().unwrap()));
+        }
+
+        fn put(&mut self, _: Parents<'_>, _: ExId, _: Prop, _: (Value<'_>, ExId), _: bool) {}
+
+        fn increment(&mut self, _: Parents<'_>, _: ExId, _: Prop, _: (i64, ExId)) {}
+
+        fn delete(&mut self, _: Parents<'_>, _: ExId, _: Prop) {}
+
+        fn merge(&mut self, other: &Self) {
+            self.0.extend(other.0.iter().cloned());
+        }
+    }
+
+    let mut doc = AutoCommit::new().with_observer(Inserts::default());
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "ab").unwrap();
+    doc.commit();
+    assert_eq!(
+        doc.observer().0,
+        vec![(0, "a".to_string()), (1, "b".to_string())]
+    );
+}
+
+#[test]
+fn limits_are_enforced_in_transactions() {
+    let limits = Limits {
+        max_depth: Some(2),
+        max_ops_per_change: Some(4),
+        max_value_len: Some(5),
+    };
+    let mut doc = AutoCommit::new().with_limits(limits);
+    assert_eq!(doc.limits(), &limits);
+    let a = doc.put_object(ROOT, "a", ObjType::Map).unwrap();
+    let b = doc.put_object(&a, "b", ObjType::List).unwrap();
+    let err = doc.insert_object(&b, 0, ObjType::Map).unwrap_err();
+    assert!(matches!(
+        err,
+        AutomergeError::LimitExceeded(LimitExceeded::Depth { depth: 3, max: 2 })
+    ));
+    assert_eq!(err.category(), ErrorCategory::LimitExceeded);
+    assert!(matches!(
+        doc.put(&a, "s", "toolong"),
+        Err(AutomergeError::LimitExceeded(LimitExceeded::ValueLen {
+            len: 7,
+            max: 5
+        }))
+    ));
+    doc.put(&a, "s", "short").unwrap();
+    doc.insert(&b, 0, 1).unwrap();
+    assert!(matches!(
+        doc.insert(&b, 1, 2),
+        Err(AutomergeError::LimitExceeded(LimitExceeded::OpsPerChange {
+            ops: 5,
+            max: 4
+        }))
+    ));
+    doc.commit();
+    doc.insert(&b, 1, 2).unwrap();
+    doc.commit();
+    assert_eq!(doc.length(&b), 2);
+}
+
+#[test]
+fn limits_are_enforced_when_applying_changes() {
+    let limits = Limits {
+        max_depth: Some(3),
+        ..Default::default()
+    };
+    let mut doc = Automerge::new().with_limits(limits);
+
+    let mut remote = AutoCommit::new();
+    let mut obj = ROOT;
+    for i in 0..3 {
+        obj = remote.put_object(&obj, "child", ObjType::Map).unwrap();
+        remote.put(&obj, "depth", i + 1).unwrap();
+    }
+    remote.commit();
+    doc.apply_changes(remote.get_changes(&[]).unwrap().into_iter().cloned())
+        .unwrap();
+
+    // the depth is checked against objects made earlier in the same change and in the document
+    let shallow = remote.get_heads();
+    remote.put(&obj, "leaf", "ok").unwrap();
+    let deep = remote.put_object(&obj, "child", ObjType::List).unwrap();
+    remote.insert(&deep, 0, 1).unwrap();
+    remote.commit();
+    let changes = remote.get_changes(&shallow).unwrap();
+    assert!(matches!(
+        doc.apply_changes(changes.into_iter().cloned()),
+        Err(AutomergeError::LimitExceeded(LimitExceeded::Depth {
+            depth: 4,
+            max: 3
+        }))
+    ));
+    assert_eq!(doc.get_heads(), shallow);
+
+    // changes with too many ops or too long values are rejected before any are applied
+    doc.set_limits(Limits {
+        max_ops_per_change: Some(1),
+        ..Default::default()
+    });
+    let mut remote = AutoCommit::new();
+    remote.put(ROOT, "a", 1).unwrap();
+    remote.commit();
+    remote.put(ROOT, "a", 2).unwrap();
+    remote.put(ROOT, "b", 2).unwrap();
+    remote.commit();
+    let heads = doc.get_heads();
+    assert!(matches!(
+        doc.load_incremental(&remote.save()),
+        Err(AutomergeError::LimitExceeded(LimitExceeded::OpsPerChange {
+            ops: 2,
+            max: 1
+        }))
+    ));
+    assert_eq!(doc.get_heads(), heads);
+
+    doc.set_limits(Limits {
+        max_value_len: Some(3),
+        ..Default::default()
+    });
+    let mut remote = AutoCommit::new();
+    remote.put(ROOT, "bytes", vec![0_u8; 4]).unwrap();
+    remote.commit();
+    assert!(matches!(
+        doc.merge(&mut remote.document().clone()),
+        Err(AutomergeError::LimitExceeded(LimitExceeded::ValueLen {
+            len: 4,
+            max: 3
+        }))
+    ));
+}
+
+#[test]
+fn limits_are_enforced_when_loading() {
+    let mut doc = AutoCommit::new();
+    let outer = doc.put_object(ROOT, "outer", ObjType::Map).unwrap();
+    let inner = doc.put_object(&outer, "inner", ObjType::List).unwrap();
+    doc.insert(&inner, 0, "four").unwrap();
+    let saved = doc.save();
+
+    let load =
+        |limits| Automerge::load_with_options(&saved, &LoadOptions::default().with_limits(limits));
+    assert!(matches!(
+        load(Limits {
+            max_depth: Some(1),
+            ..Default::default()
+        }),
+        Err(AutomergeError::LimitExceeded(LimitExceeded::Depth {
+            depth: 2,
+            max: 1
+        }))
+    ));
+    assert!(matches!(
+        load(Limits {
+            max_ops_per_change: Some(2),
+            ..Default::default()
+        }),
+        Err(AutomergeError::LimitExceeded(LimitExceeded::OpsPerChange {
+            ops: 3,
+            max: 2
+        }))
+    ));
+    assert!(matches!(
+        load(Limits {
+            max_value_len: Some(3),
+            ..Default::default()
+        }),
+        Err(AutomergeError::LimitExceeded(LimitExceeded::ValueLen {
+            len: 4,
+            max: 3
+        }))
+    ));
+
+    // a document within the limits loads, and the limits are enforced on it
+    let limits = Limits {
+        max_depth: Some(2),
+        ..Default::default()
+    };
+    let mut loaded = load(limits).unwrap();
+    assert_eq!(loaded.limits(), &limits);
+    let inner = loaded.get(&outer, "inner").unwrap().unwrap().1;
+    assert!(loaded
+        .transact::<_, _, AutomergeError>(|tx| tx.insert_object(&inner, 1, ObjType::Map))
+        .is_err());
+}
+
+#[test]
+fn parents_of_deleted_and_overwritten_objects() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, "a").unwrap();
+    let map = doc.insert_object(&list, 1, ObjType::Map).unwrap();
+    doc.insert(&list, 2, "c").unwrap();
+    let text = doc.put_object(&map, "text", ObjType::Text).unwrap();
+
+    doc.put(&map, "text", "overwritten").unwrap();
+    doc.delete(&list, 1).unwrap();
+
+    let mut parents = doc.parents(&text).unwrap();
+    assert_eq!(
+        parents.next(),
+        Some((map.clone(), Prop::Map("text".into()), false))
+    );
+    assert_eq!(parents.next(), Some((list.clone(), Prop::Seq(1), false)));
+    assert_eq!(parents.next(), Some((ROOT, Prop::Map("list".into()), true)));
+    assert_eq!(parents.next(), None);
+
+    assert_eq!(
+        doc.path_to_object(&text).unwrap(),
+        vec![
+            (ROOT, Prop::Map("list".into())),
+            (list, Prop::Seq(1)),
+            (map, Prop::Map("text".into())),
+        ]
+    );
+}
+
+#[test]
+fn lookups_by_index_in_long_lists() {
+    // enough lookups to build the sequence index when the `seq-index` feature is enabled
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from([1]));
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..100 {
+        doc1.insert(&list, i, i as i64).unwrap();
+    }
+    let mut doc2 = doc1.fork().with_actor(ActorId::from([2]));
+    for i in (0..100).step_by(3) {
+        doc1.put(&list, i, "one").unwrap();
+        doc2.put(&list, i, "two").unwrap();
+    }
+    for i in (0..30).rev() {
+        doc1.delete(&list, i * 3 + 1).unwrap();
+    }
+    doc1.merge(&mut doc2).unwrap();
+
+    let expected = doc1
+        .list_range(&list, ..)
+        .map(|(_, value, id)| (value.into_owned(), id))
+        .collect::<Vec<_>>();
+    // the index each element had before the deletes, multiples of three are conflicted
+    let original = (0..100)
+        .filter(|i| i % 3 != 1 || *i > 88)
+        .collect::<Vec<_>>();
+    for _ in 0..2 {
+        for (i, expected) in expected.iter().enumerate() {
+            let (value, id) = doc1.get(&list, i).unwrap().unwrap();
+            assert_eq!(&(value.into_owned(), id), expected);
+            let conflicts = if original[i] % 3 == 0 { 2 } else { 1 };
+            assert_eq!(doc1.get_all(&list, i).unwrap().len(), conflicts);
+        }
+        assert!(doc1.get(&list, expected.len()).unwrap().is_none());
+    }
+
+    doc1.insert(&list, 0, "first").unwrap();
+    assert_eq!(doc1.get(&list, 0).unwrap().unwrap().0, Value::from("first"));
+    assert_eq!(
+        doc1.get(&list, 1).unwrap(),
+        doc1.list_range(&list, 1..2)
+            .next()
+            .map(|(_, v, id)| (v, id))
+    );
+}
+
+#[test]
+fn replay_changes_between_heads() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1).unwrap();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    let from = doc.get_heads();
+    doc.insert(&list, 0, "x").unwrap();
+    doc.put(ROOT, "b", 2).unwrap();
+    doc.commit();
+    doc.delete(ROOT, "a").unwrap();
+    let to = doc.get_heads();
+    doc.put(ROOT, "c", 3).unwrap();
+    doc.commit();
+
+    let mut observer = VecOpObserver::default();
+    doc.replay_changes(&from, &to, &mut observer).unwrap();
+    let patches = observer.take_patches();
+    assert_eq!(patches.len(), 3);
+    assert!(matches!(
+        &patches[0],
+        Patch::Delete { prop: Prop::Map(prop), .. } if prop == "a"
+    ));
+    assert!(matches!(
+        &patches[1],
+        Patch::Put { prop: Prop::Map(prop), value: (value, _), .. } if prop == "b" && value == &Value::from(2)
+    ));
+    assert!(matches!(
+        &patches[2],
+        Patch::Insert { obj, index: 0, value: (value, _), path } if obj == &list && value == &Value::from("x") && path == &vec![(ROOT, Prop::Map("list".into()))]
+    ));
+
+    // replaying from the start reports the difference, so "a", which was put and deleted in
+    // between, isn't reported
+    let mut observer = VecOpObserver::default();
+    doc.replay_changes(&[], &to, &mut observer).unwrap();
+    let patches = observer.take_patches();
+    assert_eq!(patches.len(), 3);
+    assert!(matches!(
+        &patches[1],
+        Patch::Put { prop: Prop::Map(prop), .. } if prop == "list"
+    ));
+
+    // objects which aren't in the document at the later heads aren't reported
+    let heads = doc.get_heads();
+    let map = doc.put_object(ROOT, "later", ObjType::Map).unwrap();
+    doc.put(&map, "x", 1).unwrap();
+    doc.delete(ROOT, "later").unwrap();
+    let end = doc.get_heads();
+    let mut observer = VecOpObserver::default();
+    doc.replay_changes(&heads, &end, &mut observer).unwrap();
+    assert_eq!(observer.take_patches(), vec![]);
+
+    let mut observer = VecOpObserver::default();
+    assert!(doc
+        .replay_changes(&[ChangeHash([0; 32])], &to, &mut observer)
+        .is_err());
+}
+
+#[test]
+fn commit_with_extra_bytes() {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "a", 1).unwrap();
+    tx.commit_with(CommitOptions::default().with_extra_bytes(b"client 1.2.3".to_vec()));
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "b", 2).unwrap();
+    tx.commit();
+    let saved = doc.save();
+
+    let changes = doc.get_changes(&[]).unwrap();
+    assert_eq!(changes[0].extra_bytes(), b"client 1.2.3");
+    assert_eq!(changes[1].extra_bytes(), b"");
+
+    let loaded = Automerge::load(&saved).unwrap();
+    let loaded_changes = loaded.get_changes(&[]).unwrap();
+    assert_eq!(loaded_changes[0].extra_bytes(), b"client 1.2.3");
+    assert_eq!(loaded_changes[0].hash(), changes[0].hash());
+
+    assert_eq!(changes[0].signature(), None);
+
+    // signed changes keep the data, and the signature covers it
+    let mut doc = AutoCommit::new();
+    doc.set_signer(|bytes| bytes.ends_with(b"request 7").to_string().into_bytes());
+    doc.put(ROOT, "a", 1).unwrap();
+    doc.commit_with(CommitOptions::default().with_extra_bytes("request 7"));
+    let change = doc.get_last_local_change().unwrap().clone();
+    assert_eq!(change.extra_bytes(), b"request 7");
+    assert_eq!(change.signature(), Some(&b"true"[..]));
+
+    let mut other = Automerge::new();
+    other.set_verifier(|_, bytes, signature| {
+        signature == bytes.ends_with(b"request 7").to_string().as_bytes()
+    });
+    other.apply_changes(vec![change.clone()]).unwrap();
+    let loaded = Automerge::load(&doc.save()).unwrap();
+    assert_eq!(
+        loaded
+            .get_change_by_hash(&change.hash())
+            .unwrap()
+            .extra_bytes(),
+        b"request 7"
+    );
+}
+
+#[test]
+fn save_and_load_with_codec() {
+    struct Xor(u8);
+    impl ChunkCodec for Xor {
+        fn id(&self) -> u64 {
+            u64::from(self.0)
+        }
+        fn encode(&self, chunk: &[u8]) -> Vec<u8> {
+            chunk.iter().map(|b| b ^ self.0).collect()
+        }
+        fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+            Ok(data.iter().map(|b| b ^ self.0).collect())
+        }
+    }
+
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "secret", "the password is swordfish")
+        .unwrap();
+    let mut saved = doc.save_with_codec(&Xor(0x5a));
+    assert!(!saved.windows(9).any(|w| w == b"swordfish"));
+    doc.put(ROOT, "more", 1).unwrap();
+    saved.extend(doc.save_incremental_with_codec(&Xor(0x5a)));
+    // chunks saved without a codec can be mixed in
+    doc.put(ROOT, "plain", 2).unwrap();
+    saved.extend(doc.save_incremental());
+
+    let mut loaded = AutoCommit::load_with_codec(&saved, &Xor(0x5a)).unwrap();
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+    assert_eq!(
+        loaded.get(ROOT, "secret").unwrap().unwrap().0,
+        Value::from("the password is swordfish")
+    );
+
+    assert!(AutoCommit::load(&saved).is_err());
+    assert!(matches!(
+        AutoCommit::load_with_codec(&saved, &Xor(0x33)),
+        Err(AutomergeError::Load(load::Error::WrongCodec {
+            expected: 0x33,
+            found: 0x5a
+        }))
+    ));
+}
+
+#[test]
+fn apply_changes_batched_out_of_order() {
+    let mut doc1 = AutoCommit::new();
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork();
+    let base = doc1.get_heads();
+    for i in 0..200 {
+        doc1.insert(&list, i / 2, i as i64).unwrap();
+        doc1.splice_text(&text, i / 3, 0, "x").unwrap();
+        doc1.put(ROOT, "count", i as i64).unwrap();
+        doc1.commit();
+    }
+
+    let mut changes = doc1
+        .get_changes(&base)
+        .unwrap()
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    changes.reverse();
+    let (early, late) = changes.split_at(50);
+    // the changes which depend on a missing one wait in the queue for the next batch
+    doc2.apply_changes_batched(early.to_vec()).unwrap();
+    assert_eq!(doc2.get_heads(), base);
+    doc2.apply_changes_batched(late.to_vec()).unwrap();
+
+    assert_eq!(doc2.get_heads(), doc1.get_heads());
+    assert!(doc2.get_missing_deps(&[]).is_empty());
+    assert_eq!(doc2.text(&text).unwrap(), doc1.text(&text).unwrap());
+    assert_eq!(
+        doc2.list_range(&list, ..).collect::<Vec<_>>(),
+        doc1.list_range(&list, ..).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn apply_changes_batched_reports_the_difference() {
+    let mut doc1 = AutoCommit::new();
+    doc1.put(ROOT, "title", "a").unwrap();
+    doc1.put(ROOT, "n", ScalarValue::counter(0)).unwrap();
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 1..=3 {
+        doc1.insert(&list, i - 1, i as i64).unwrap();
+    }
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.splice_text(&text, 0, 0, "ab").unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_observer(VecOpObserver::default());
+    let base = doc1.get_heads();
+
+    doc1.put(ROOT, "title", "b").unwrap();
+    doc1.commit();
+    doc1.put(ROOT, "title", "c").unwrap();
+    doc1.delete(&list, 0).unwrap();
+    doc1.insert(&list, 2, 4).unwrap();
+    doc1.commit();
+    doc1.increment(ROOT, "n", 3).unwrap();
+    doc1.splice_text(&text, 1, 0, "xy").unwrap();
+    let tmp = doc1.put_object(ROOT, "tmp", ObjType::Map).unwrap();
+    doc1.put(&tmp, "a", 1).unwrap();
+    doc1.commit();
+    doc1.delete(ROOT, "tmp").unwrap();
+    doc1.commit();
+
+    let changes = doc1
+        .get_changes(&base)
+        .unwrap()
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    doc2.apply_changes_batched(changes).unwrap();
+    assert_eq!(doc2.get_heads(), doc1.get_heads());
+    assert_eq!(doc2.text(&text).unwrap(), "axyb");
+
+    let patches = doc2.observer().take_patches();
+    assert_eq!(patches.len(), 5, "{:?}", patches);
+    assert!(matches!(
+        &patches[0],
+        Patch::Put { prop: Prop::Map(p), value: (Value::Scalar(v), _), conflict: false, .. }
+            if p == "n" && v.to_i64() == Some(3)
+    ));
+    assert!(matches!(
+        &patches[1],
+        Patch::Put { prop: Prop::Map(p), value: (v, _), .. } if p == "title" && *v == Value::from("c")
+    ));
+    assert!(matches!(
+        &patches[2],
+        Patch::Delete { obj, prop: Prop::Seq(0), .. } if obj == &list
+    ));
+    assert!(matches!(
+        &patches[3],
+        Patch::Insert { obj, index: 2, value: (v, _), .. } if obj == &list && *v == Value::int(4)
+    ));
+    assert!(matches!(
+        &patches[4],
+        Patch::SpliceText { obj, index: 1, value, .. } if obj == &text && value == "xy"
+    ));
+}
+
+#[test]
+fn deep_equality() {
+    let mut doc1 = AutoCommit::new();
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc1.insert(&list, 0, "a").unwrap();
+    let inner = doc1.insert_object(&list, 1, ObjType::Map).unwrap();
+    doc1.put(&inner, "n", ScalarValue::counter(1)).unwrap();
+    doc1.commit();
+    let first = doc1.get_heads();
+
+    // the same contents built by another actor in a different order
+    let mut doc2 = AutoCommit::new();
+    let list2 = doc2.put_object(ROOT, "list", ObjType::List).unwrap();
+    let inner2 = doc2.insert_object(&list2, 0, ObjType::Map).unwrap();
+    doc2.put(&inner2, "n", ScalarValue::counter(0)).unwrap();
+    doc2.increment(&inner2, "n", 1).unwrap();
+    doc2.insert(&list2, 0, "b").unwrap();
+    doc2.put(&list2, 0, "a").unwrap();
+    assert!(doc1.materialized_eq(&mut doc2));
+
+    doc2.increment(&inner2, "n", 1).unwrap();
+    assert!(!doc1.materialized_eq(&mut doc2));
+
+    doc1.put(&inner, "n", ScalarValue::counter(5)).unwrap();
+    doc1.commit();
+    let copy = doc1.put_object(ROOT, "copy", ObjType::Map).unwrap();
+    doc1.put(&copy, "n", ScalarValue::counter(1)).unwrap();
+    assert!(doc1.deep_eq_at(&inner, &first, &copy, &[]).unwrap());
+    assert!(!doc1.deep_eq_at(&inner, &[], &copy, &[]).unwrap());
+    assert!(!doc1.deep_eq_at(&list, &[], &copy, &[]).unwrap());
+    assert!(doc1.deep_eq_at(ROOT, &first, ROOT, &first).unwrap());
+    assert!(matches!(
+        doc1.deep_eq_at(ROOT, &[ChangeHash([1; 32])], ROOT, &[]),
+        Err(AutomergeError::MissingHash(_))
+    ));
+}
+
+#[test]
+fn splice_chunked_across_changes() {
+    let mut doc = AutoCommit::new().with_observer(VecOpObserver::coalescing());
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.splice(&list, 0, 0, (0..5).map(ScalarValue::from))
+        .unwrap();
+    doc.commit();
+    doc.observer().take_patches();
+    let before = doc.get_changes(&[]).unwrap().len();
+
+    doc.splice_chunked(&list, 1, 3, (10..1010).map(ScalarValue::from), 100)
+        .unwrap();
+
+    let changes = doc.get_changes(&[]).unwrap();
+    assert_eq!(changes.len() - before, 11);
+    assert!(changes.iter().all(|c| c.len() <= 100));
+    assert_eq!(doc.length(&list), 1002);
+    let values = doc
+        .list_range(&list, ..)
+        .map(|(_, v, _)| v.to_i64().unwrap())
+        .collect::<Vec<_>>();
+    let expected = std::iter::once(0)
+        .chain(10..1010)
+        .chain(std::iter::once(4))
+        .collect::<Vec<_>>();
+    assert_eq!(values, expected);
+
+    let patches = doc.observer().take_patches();
+    assert_eq!(patches.len(), 2, "{:?}", patches);
+    assert!(matches!(
+        &patches[0],
+        Patch::DeleteRange {
+            index: 1,
+            length: 3,
+            ..
+        }
+    ));
+    assert!(matches!(
+        &patches[1],
+        Patch::Splice { index: 1, values, .. } if values.len() == 1000
+    ));
+
+    assert!(matches!(
+        doc.splice_chunked(&list, 1000, 5, Vec::new(), 100),
+        Err(AutomergeError::InvalidIndex(1005))
+    ));
+}
+
+#[test]
+fn frozen_doc_is_shared_between_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<FrozenDoc>();
+
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..10 {
+        doc.insert(&list, i, i as i64).unwrap();
+    }
+    doc.commit();
+    let heads = doc.get_heads();
+    doc.put(ROOT, "done", true).unwrap();
+    let saved = doc.save();
+    let frozen = doc.freeze();
+    assert!(frozen.ops.lookup_cache.is_frozen());
+
+    let handles = (0..4)
+        .map(|_| {
+            let frozen = frozen.clone();
+            let list = list.clone();
+            let heads = heads.clone();
+            std::thread::spawn(move || {
+                assert_eq!(frozen.length(&list), 10);
+                assert_eq!(frozen.keys_at(ROOT, &heads).count(), 1);
+                frozen.get(ROOT, "done").unwrap().is_some()
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        assert!(handle.join().unwrap());
+    }
+
+    let copy = frozen.clone();
+    assert!(copy.ptr_eq(&frozen));
+    assert_eq!(frozen.save(), saved);
+    drop(copy);
+    let mut doc = frozen.thaw();
+    assert!(!doc.ops.lookup_cache.is_frozen());
+    doc.transact::<_, _, AutomergeError>(|tx| tx.put(ROOT, "done", false))
+        .unwrap();
+    assert_eq!(doc.get_changes(&[]).unwrap().len(), 3);
+}
+
+#[test]
+fn last_modified_props() {
+    let alice = ActorId::from(b"alice".to_vec());
+    let bob = ActorId::from(b"bob".to_vec());
+    let mut doc1 = AutoCommit::new().with_actor(alice.clone());
+    doc1.put(ROOT, "title", "draft").unwrap();
+    doc1.put(ROOT, "views", ScalarValue::counter(0)).unwrap();
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc1.insert(&list, 0, "a").unwrap();
+    doc1.insert(&list, 1, "b").unwrap();
+    doc1.commit_with(CommitOptions::default().with_time(1000));
+    let first = doc1.get_heads()[0];
+
+    let mut doc2 = doc1.fork().with_actor(bob.clone());
+    doc2.put(ROOT, "title", "final").unwrap();
+    doc2.increment(ROOT, "views", 1).unwrap();
+    doc2.delete(&list, 0).unwrap();
+    doc2.commit();
+    let second = doc2.get_heads()[0];
+
+    let title = doc2.last_modified(ROOT, "title").unwrap().unwrap();
+    assert_eq!(title.hash, second);
+    assert_eq!(title.actor, bob);
+    assert_eq!(title.time, None);
+    assert_eq!(
+        doc2.last_modified(ROOT, "views").unwrap().unwrap().hash,
+        second
+    );
+    let elem = doc2.last_modified(&list, 0).unwrap().unwrap();
+    assert_eq!(elem.hash, first);
+    assert_eq!(elem.actor, alice);
+    assert_eq!(elem.time, Some(1000));
+    assert_eq!(doc2.last_modified(ROOT, "missing").unwrap(), None);
+    assert_eq!(doc2.last_modified(&list, 1).unwrap(), None);
+
+    let all = doc2.last_modified_all(ROOT).unwrap();
+    let props = all
+        .iter()
+        .map(|(p, m)| (p.clone(), m.hash))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        props,
+        vec![
+            (Prop::from("list"), first),
+            (Prop::from("title"), second),
+            (Prop::from("views"), second),
+        ]
+    );
+    let elems = doc2.last_modified_all(&list).unwrap();
+    assert_eq!(elems.len(), 1);
+    assert_eq!(elems[0].0, Prop::Seq(0));
+}
+
+#[test]
+fn last_modified_follows_the_winner_of_a_conflict() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(vec![1]));
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(vec![2]));
+    doc1.put(ROOT, "x", 10).unwrap();
+    doc1.commit();
+    let first = doc1.get_heads()[0];
+    doc2.put(ROOT, "x", 20).unwrap();
+    doc2.commit();
+    let second = doc2.get_heads()[0];
+    doc1.merge(&mut doc2).unwrap();
+    assert_eq!(doc1.get_all(ROOT, "x").unwrap().len(), 2);
+
+    assert_eq!(doc1.get(ROOT, "x").unwrap().unwrap().0, Value::int(20));
+    assert_eq!(doc1.last_modified(ROOT, "x").unwrap().unwrap().hash, second);
+
+    doc1.set_conflict_policy(ROOT, "x", Some(ConflictPolicy::MinWins))
+        .unwrap();
+    assert_eq!(doc1.get(ROOT, "x").unwrap().unwrap().0, Value::int(10));
+    let modified = doc1.last_modified(ROOT, "x").unwrap().unwrap();
+    assert_eq!(modified.hash, first);
+    assert_eq!(modified.actor, ActorId::from(vec![1]));
+    assert_eq!(
+        doc1.last_modified_all(ROOT).unwrap(),
+        vec![(Prop::from("x"), modified)]
+    );
+}
+
+#[test]
+fn schema_rejects_invalid_transactions() {
+    let schema = Schema::new()
+        .field("title", &[SchemaType::Str])
+        .field("todos/*/done", &[SchemaType::Boolean])
+        .validator(|path, value| match value.to_i64() {
+            Some(n) if n < 0 => Err(format!("{} must not be negative", path[0])),
+            _ => Ok(()),
+        });
+    let mut doc = AutoCommit::new().with_schema(schema.clone());
+    doc.put(ROOT, "title", "shopping").unwrap();
+    let todos = doc.put_object(ROOT, "todos", ObjType::List).unwrap();
+    let todo = doc.insert_object(&todos, 0, ObjType::Map).unwrap();
+    doc.put(&todo, "done", false).unwrap();
+    doc.try_commit().unwrap();
+    let heads = doc.get_heads();
+
+    // values are checked as they are put, so the implicit commits of an AutoCommit can't
+    // contain an invalid value either
+    doc.put(ROOT, "other", 1).unwrap();
+    let err = doc.put(&todo, "done", "yes").unwrap_err();
+    match &err {
+        AutomergeError::SchemaViolation(violation) => assert_eq!(
+            violation.path,
+            vec![Prop::from("todos"), Prop::Seq(0), Prop::from("done")]
+        ),
+        other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!(err.category(), ErrorCategory::UserInput);
+    assert!(doc.put(ROOT, "count", -1).is_err());
+    assert!(doc
+        .splice(&todos, 1, 0, vec![ScalarValue::Int(-1)])
+        .is_err());
+    assert_eq!(doc.length(&todos), 1);
+    assert_eq!(
+        doc.get(&todo, "done").unwrap().unwrap().0,
+        Value::from(false)
+    );
+    assert_eq!(doc.get(ROOT, "other").unwrap().unwrap().0, Value::from(1));
+    assert_eq!(doc.get(ROOT, "count").unwrap(), None);
+    doc.commit();
+    let changes = doc.get_changes(&heads).unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].len(), 1);
+
+    // including those of commit and transact
+    let mut doc = Automerge::new().with_schema(schema);
+    let mut tx = doc.transaction();
+    assert!(matches!(
+        tx.put(ROOT, "title", 5),
+        Err(AutomergeError::SchemaViolation(_))
+    ));
+    tx.commit();
+    assert_eq!(doc.get(ROOT, "title").unwrap(), None);
+    assert!(doc
+        .transact::<_, _, AutomergeError>(|tx| tx.put(ROOT, "title", 5))
+        .is_err());
+    assert_eq!(doc.get_changes(&[]).unwrap().len(), 1);
+}
+
+#[test]
+fn path_cache_matches_parents() {
+    let mut doc1 = AutoCommit::new();
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    let map = doc1.insert_object(&list, 0, ObjType::Map).unwrap();
+    let text = doc1.put_object(&map, "text", ObjType::Text).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork();
+    doc2.insert(&list, 0, "first").unwrap();
+    doc2.insert(&list, 0, "second").unwrap();
+    doc1.merge(&mut doc2).unwrap();
+
+    let mut loaded = Automerge::load(&doc1.save()).unwrap();
+    for doc in [doc1.document(), &loaded] {
+        let cache = doc.path_cache();
+        assert_eq!(
+            cache.path(&text).unwrap(),
+            doc.parents(&text).unwrap().path()
+        );
+        assert_eq!(
+            cache.path(&text).unwrap(),
+            vec![
+                (ROOT, Prop::from("list")),
+                (list.clone(), Prop::Seq(2)),
+                (map.clone(), Prop::from("text")),
+            ]
+        );
+        assert_eq!(cache.parent(&map), Some((list.clone(), Prop::Seq(2))));
+        assert_eq!(cache.path(ROOT), Some(vec![]));
+        assert_eq!(cache.parent(ROOT), None);
+        assert!(cache.is_within(&text, &list));
+        assert!(cache.is_within(&text, ROOT));
+        assert!(!cache.is_within(&list, &text));
+    }
+
+    let mut tx = loaded.transaction();
+    tx.delete(&list, 0).unwrap();
+    let nested = tx.put_object(&map, "nested", ObjType::Map).unwrap();
+    tx.commit();
+    assert_eq!(
+        loaded.path_cache().path(&nested).unwrap(),
+        loaded.parents(&nested).unwrap().path()
+    );
+    assert_eq!(loaded.path_cache().parent(&map), Some((list, Prop::Seq(1))));
+}
+
+#[test]
+fn dry_run_transaction_does_not_change_the_document() {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "a", 1).unwrap();
+    tx.commit();
+    let heads = doc.get_heads();
+
+    let mut tx = doc.transaction_dry_run();
+    let list = tx.put_object(ROOT, "list", ObjType::List).unwrap();
+    tx.insert(&list, 0, "x").unwrap();
+    tx.put(ROOT, "a", 2).unwrap();
+    let change = tx.commit_with(CommitOptions::default().with_message("dry".to_owned()));
+    assert_eq!(doc.get_heads(), heads);
+    assert_eq!(doc.get(ROOT, "a").unwrap().unwrap().0, Value::int(1));
+    assert!(doc.get(ROOT, "list").unwrap().is_none());
+    assert_eq!(change.deps(), heads.as_slice());
+    assert_eq!(change.message(), Some(&"dry".to_owned()));
+    assert_eq!(change.seq(), 2);
+
+    doc.apply_changes(vec![change.clone()]).unwrap();
+    assert_eq!(doc.get_heads(), vec![change.hash()]);
+    assert_eq!(doc.get(ROOT, "a").unwrap().unwrap().0, Value::int(2));
+    assert_eq!(doc.length(&list), 1);
+
+    // a fresh document's actor is only kept once a change is applied
+    let mut fresh = Automerge::new();
+    let mut tx = fresh.transaction_dry_run();
+    tx.put(ROOT, "b", true).unwrap();
+    let change = tx.commit();
+    assert_eq!(change.actor_id(), fresh.get_actor());
+    fresh.apply_changes(vec![change]).unwrap();
+    let mut tx = fresh.transaction();
+    tx.put(ROOT, "b", false).unwrap();
+    tx.commit();
+    assert_eq!(fresh.get_changes(&[]).unwrap().len(), 2);
+}
+
+#[test]
+fn apply_changes_with_actor_map_rewrites_actors_and_deps() {
+    let js_actor = ActorId::from(vec![0xab; 16]);
+    let user = ActorId::from(vec![0x01, 0x02]);
+    let mut js = AutoCommit::new().with_actor(js_actor.clone());
+    let list = js.put_object(ROOT, "list", ObjType::List).unwrap();
+    js.insert(&list, 0, "a").unwrap();
+    js.commit();
+    js.insert(&list, 1, "b").unwrap();
+    js.delete(&list, 0).unwrap();
+    js.commit();
+    let blobs = js
+        .get_changes(&[])
+        .unwrap()
+        .into_iter()
+        .map(|c| c.raw_bytes().to_vec())
+        .collect::<Vec<_>>();
+
+    // the changes are passed in reverse order so the dependency is remapped before it is seen
+    let changes = blobs
+        .iter()
+        .rev()
+        .map(|b| Change::from_js_bytes(b).unwrap())
+        .collect::<Vec<_>>();
+    let old_heads = js.get_heads();
+    let mut doc = Automerge::new();
+    let map = HashMap::from([(js_actor.clone(), user.clone())]);
+    let hashes = doc.apply_changes_with_actor_map(changes, &map).unwrap();
+    assert_eq!(hashes.len(), 2);
+    assert_eq!(doc.get_heads(), vec![hashes[&old_heads[0]]]);
+
+    let list = doc.get(ROOT, "list").unwrap().unwrap().1;
+    assert_eq!(doc.length(&list), 1);
+    assert_eq!(doc.get(&list, 0).unwrap().unwrap().0, Value::str("b"));
+    assert!(doc
+        .get_changes(&[])
+        .unwrap()
+        .iter()
+        .all(|c| c.actor_id() == &user));
+
+    let ambiguous = HashMap::from([(js_actor, user.clone()), (ActorId::from(vec![0xcd]), user)]);
+    let err = Automerge::new()
+        .apply_changes_with_actor_map(vec![], &ambiguous)
+        .unwrap_err();
+    assert!(matches!(err, AutomergeError::AmbiguousActorMap(_)));
+    assert!(Change::from_js_bytes(&js.save()).is_err());
+}
+
+#[test]
+fn text_spans_have_stable_ids() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "hello").unwrap();
+    let spans = doc.text_spans(&text).unwrap().collect::<Vec<_>>();
+    assert_eq!(
+        spans.iter().map(|s| s.text.as_str()).collect::<String>(),
+        "hello"
+    );
+    let e = spans[1].id.clone();
+    assert_eq!(doc.text_index_of(&text, &e).unwrap(), Some(1));
+
+    doc.splice_text(&text, 0, 1, "jj").unwrap();
+    assert_eq!(doc.text(&text).unwrap(), "jjello");
+    assert_eq!(doc.text_index_of(&text, &e).unwrap(), Some(2));
+    let spans = doc.text_spans(&text).unwrap().collect::<Vec<_>>();
+    assert_eq!(
+        spans[2],
+        TextSpan {
+            text: "e".into(),
+            id: e.clone(),
+        }
+    );
+
+    doc.delete(&text, 2).unwrap();
+    assert_eq!(doc.text_index_of(&text, &e).unwrap(), None);
+    assert_eq!(doc.text_spans(&text).unwrap().len(), 5);
+}
+
+#[test]
+fn keys_in_custom_orders() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(vec![1]));
+    for key in ["10", "item10", "2", "item9", "b", "a", "02"] {
+        doc1.put(ROOT, key, 1).unwrap();
+    }
+    doc1.delete(ROOT, "b").unwrap();
+    doc1.put(ROOT, "b", 2).unwrap();
+    assert_eq!(
+        doc1.keys_ordered(ROOT, KeyOrder::Lexicographic),
+        doc1.keys(ROOT).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        doc1.keys_ordered(ROOT, KeyOrder::Numeric),
+        vec!["02", "2", "10", "a", "b", "item9", "item10"]
+    );
+    assert_eq!(
+        doc1.keys_ordered(ROOT, KeyOrder::Insertion),
+        vec!["10", "item10", "2", "item9", "b", "a", "02"]
+    );
+
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(vec![2]));
+    doc2.put(ROOT, "z", 1).unwrap();
+    doc1.put(ROOT, "y", 1).unwrap();
+    doc1.merge(&mut doc2).unwrap();
+    doc2.merge(&mut doc1).unwrap();
+    assert_eq!(
+        doc1.keys_ordered(ROOT, KeyOrder::Insertion),
+        doc2.keys_ordered(ROOT, KeyOrder::Insertion)
+    );
+    assert_eq!(
+        doc1.keys_ordered(ROOT, KeyOrder::Insertion)[7..],
+        ["y".to_string(), "z".to_string()]
+    );
+}
+
+#[test]
+fn consecutive_inserts_match_searching_for_each_one() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.commit();
+    let mut expected: Vec<i64> = Vec::new();
+    for i in 0..200i64 {
+        doc.insert(&list, expected.len(), i).unwrap();
+        expected.push(i);
+    }
+    // insert a run in the middle, interrupted by other ops on the list
+    for i in 0..50usize {
+        doc.insert(&list, 10 + i, 1000 + i as i64).unwrap();
+        expected.insert(10 + i, 1000 + i as i64);
+        if i % 7 == 0 {
+            doc.delete(&list, 0).unwrap();
+            expected.remove(0);
+        }
+        if i % 11 == 0 {
+            doc.put(&list, 5, -1).unwrap();
+            expected[5] = -1;
+        }
+    }
+    doc.splice(&list, 3, 2, (0..5i64).map(|i| ScalarValue::Int(-10 - i)))
+        .unwrap();
+    expected.splice(3..5, (0..5i64).map(|i| -10 - i));
+    doc.commit();
+
+    let actual = doc
+        .list_range(&list, ..)
+        .map(|(_, v, _)| v.to_i64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(actual, expected);
+    let mut reloaded = AutoCommit::load(&doc.save()).unwrap();
+    assert_eq!(
+        reloaded
+            .list_range(&list, ..)
+            .map(|(_, v, _)| v.to_i64().unwrap())
+            .collect::<Vec<_>>(),
+        expected
+    );
+    assert_eq!(doc.document().audit(), reloaded.document().audit());
+}
+
+#[test]
+fn headers_are_saved_but_not_part_of_history() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1).unwrap();
+    doc.commit();
+    let heads = doc.get_heads();
+    doc.set_header("schema_version", vec![2]);
+    doc.set_header("migrated", b"yes".to_vec());
+    assert_eq!(doc.get_heads(), heads);
+    assert_eq!(doc.header("schema_version"), Some(&[2][..]));
+
+    let saved = doc.save();
+    let loaded = Automerge::load(&saved).unwrap();
+    assert_eq!(
+        loaded.headers().collect::<Vec<_>>(),
+        vec![("migrated", &b"yes"[..]), ("schema_version", &[2][..])]
+    );
+    assert_eq!(loaded.get_heads(), heads);
+    assert!(
+        Automerge::load_with_progress(&saved, |_| {}, &CancellationToken::new())
+            .unwrap()
+            .header("migrated")
+            .is_some()
+    );
+    assert!(Automerge::load_lazy(&saved).is_ok());
+
+    // headers are neither merged nor synced
+    let mut other = AutoCommit::new();
+    other.merge(&mut doc).unwrap();
+    assert_eq!(other.header("migrated"), None);
+    let mut incremental = Automerge::new();
+    incremental.load_incremental(&saved).unwrap();
+    assert_eq!(incremental.header("migrated"), None);
+    assert_eq!(incremental.get_heads(), heads);
+
+    assert_eq!(doc.remove_header("migrated"), Some(b"yes".to_vec()));
+    let mut only_headers = AutoCommit::new();
+    only_headers.set_header("k", vec![]);
+    let loaded = Automerge::load(&only_headers.save()).unwrap();
+    assert_eq!(loaded.header("k"), Some(&[][..]));
+}
+
+#[test]
+fn squash_redundant_ops_keeps_the_final_put() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "slider", 0).unwrap();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, "a").unwrap();
+    doc.commit();
+
+    for i in 1..=10 {
+        doc.put(ROOT, "slider", i).unwrap();
+        doc.put(&list, 0, format!("a{}", i)).unwrap();
+    }
+    doc.put(ROOT, "counter", ScalarValue::counter(1)).unwrap();
+    doc.increment(ROOT, "counter", 2).unwrap();
+    doc.put(ROOT, "new", "x").unwrap();
+    doc.put(ROOT, "new", "y").unwrap();
+    let map = doc.put_object(ROOT, "map", ObjType::Map).unwrap();
+    doc.commit_with(CommitOptions::default().squash_redundant_ops(true));
+
+    // the overwritten values are null, and the ops after them keep their ids
+    let change = doc.get_last_local_change().unwrap().clone();
+    assert_eq!(change.len(), 25);
+    let values = change
+        .decode()
+        .operations
+        .into_iter()
+        .filter_map(|op| match op.action {
+            legacy::OpType::Put(value) => Some(value),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(values.iter().filter(|v| v.is_null()).count(), 19);
+    assert!(values.contains(&ScalarValue::Int(10)));
+    assert!(!values.contains(&ScalarValue::Int(9)));
+    doc.put(&map, "x", 1).unwrap();
+    assert_eq!(doc.get(&map, "x").unwrap().unwrap().0, Value::int(1));
+    assert_eq!(doc.get(ROOT, "slider").unwrap().unwrap().0, Value::int(10));
+    assert_eq!(doc.get(&list, 0).unwrap().unwrap().0, Value::str("a10"));
+    assert_eq!(doc.get(ROOT, "new").unwrap().unwrap().0, Value::str("y"));
+    assert_eq!(
+        doc.get(ROOT, "counter").unwrap().unwrap().0,
+        Value::counter(3)
+    );
+    assert_eq!(doc.get_all(ROOT, "slider").unwrap().len(), 1);
+
+    let mut other = AutoCommit::load(&doc.save()).unwrap();
+    assert_eq!(
+        other.get(ROOT, "slider").unwrap().unwrap().0,
+        Value::int(10)
+    );
+    assert_eq!(other.get(&list, 0).unwrap().unwrap().0, Value::str("a10"));
+    assert_eq!(other.document().audit(), doc.document().audit());
+
+    // the next change carries on from the squashed one
+    doc.put(ROOT, "slider", 11).unwrap();
+    doc.commit();
+    other.merge(&mut doc).unwrap();
+    assert_eq!(
+        other.get(ROOT, "slider").unwrap().unwrap().0,
+        Value::int(11)
+    );
+    assert_eq!(other.get_all(ROOT, "slider").unwrap().len(), 1);
+}
+
+#[test]
+fn failing_observer_aborts_merges_and_transactions() {
+    use crate::exid::ExId;
+
+    #[derive(Debug, Default, Clone)]
+    struct NoSecrets {
+        keys: Vec<String>,
+    }
+
+    impl FallibleOpObserver for NoSecrets {
+        type Error = String;
+
+        fn insert(
+            &mut self,
+            _parents: Parents<'_>,
+            _objid: ExId,
+            _index: usize,
+            _tagged_value: (Value<'_>, ExId),
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn splice_text(
+            &mut self,
+            _parents: Parents<'_>,
+            _objid: ExId,
+            _index: usize,
+            _value: &str,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn put(
+            &mut self,
+            _parents: Parents<'_>,
+            _objid: ExId,
+            prop: Prop,
+            _tagged_value: (Value<'_>, ExId),
+            _conflict: bool,
+        ) -> Result<(), String> {
+            if prop == Prop::from("secret") {
+                return Err("secrets are not allowed".to_string());
+            }
+            self.keys.push(prop.to_string());
+            Ok(())
+        }
+
+        fn increment(
+            &mut self,
+            _parents: Parents<'_>,
+            _objid: ExId,
+            _prop: Prop,
+            _tagged_value: (i64, ExId),
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn delete(
+            &mut self,
+            _parents: Parents<'_>,
+            _objid: ExId,
+            _prop: Prop,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn merge(&mut self, other: &Self) {
+            self.keys.extend(other.keys.iter().cloned());
+        }
+    }
+
+    let mut doc = Automerge::new();
+    let mut other = doc.fork();
+    let mut tx = other.transaction();
+    tx.put(ROOT, "a", 1).unwrap();
+    tx.commit();
+    let mut tx = other.transaction();
+    tx.put(ROOT, "secret", 2).unwrap();
+    tx.commit();
+
+    let mut observer = NoSecrets::default();
+    let result = doc.merge_fallible(&mut other, &mut observer);
+    assert!(matches!(result, Err(ObserverError::Observer(e)) if e == "secrets are not allowed"));
+    assert!(doc.get_heads().is_empty());
+    assert!(doc.get(ROOT, "a").unwrap().is_none());
+    assert!(observer.keys.is_empty());
+
+    let changes = other.get_changes(&[]).unwrap()[..1]
+        .iter()
+        .map(|c| (*c).clone())
+        .collect::<Vec<_>>();
+    doc.apply_changes_fallible(changes, &mut observer).unwrap();
+    assert_eq!(observer.keys, vec!["a".to_string()]);
+    assert!(doc.get(ROOT, "a").unwrap().is_some());
+
+    let result = doc.transact_fallible::<_, _, AutomergeError, NoSecrets>(|tx| {
+        tx.put(ROOT, "b", 1)?;
+        tx.put(ROOT, "secret", 2)?;
+        Ok(())
+    });
+    let failure = result.unwrap_err();
+    assert!(matches!(failure.error, ObserverError::Observer(_)));
+    assert_eq!(failure.cancelled, 2);
+    assert!(doc.get(ROOT, "b").unwrap().is_none());
+
+    let success = doc
+        .transact_fallible::<_, _, AutomergeError, NoSecrets>(|tx| tx.put(ROOT, "b", 1))
+        .unwrap();
+    assert_eq!(success.op_observer.keys, vec!["b".to_string()]);
+}
+
+#[test]
+fn list_elements_meta_records_who_inserted_each_element() {
+    let mut doc1 = AutoCommit::new();
+    doc1.set_actor(ActorId::from(b"aaaa".to_vec()));
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc1.insert(&list, 0, "a").unwrap();
+    doc1.commit_with(CommitOptions::default().with_time(100));
+    let first = doc1.get_last_local_change().unwrap().hash();
+
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"bbbb".to_vec()));
+    doc2.insert(&list, 0, "b").unwrap();
+    doc2.commit();
+    let second = doc2.get_last_local_change().unwrap().hash();
+    // overwriting an element doesn't change who inserted it
+    doc2.put(&list, 1, "c").unwrap();
+    doc2.commit();
+
+    let meta = doc2.list_elements_meta(&list).unwrap();
+    assert_eq!(meta.len(), 2);
+    assert_eq!(meta[0].index, 0);
+    assert_eq!(meta[0].actor, ActorId::from(b"bbbb".to_vec()));
+    assert_eq!(meta[0].hash, Some(second));
+    assert_eq!(meta[0].time, None);
+    assert_eq!(meta[1].index, 1);
+    assert_eq!(meta[1].actor, ActorId::from(b"aaaa".to_vec()));
+    assert_eq!(meta[1].hash, Some(first));
+    assert_eq!(meta[1].time, Some(100));
+    let ids = doc2
+        .text_spans(&list)
+        .unwrap()
+        .map(|s| s.id)
+        .collect::<Vec<_>>();
+    assert_eq!(meta.iter().map(|m| m.id.clone()).collect::<Vec<_>>(), ids);
+}
+
+#[test]
+fn try_transact_commits_on_ok_and_rolls_back_on_err() {
+    let mut doc = Automerge::new();
+    let (value, heads) = doc
+        .try_transact(|tx| {
+            tx.put(ROOT, "a", 1)?;
+            Ok::<_, AutomergeError>("done")
+        })
+        .unwrap();
+    assert_eq!(value, "done");
+    assert_eq!(heads, doc.get_heads());
+    assert_eq!(heads.len(), 1);
+
+    let result = doc.try_transact(|tx| {
+        tx.put(ROOT, "b", 1).unwrap();
+        Err::<(), _>("nope")
+    });
+    assert_eq!(result, Err("nope"));
+    assert_eq!(doc.get_heads(), heads);
+    assert!(doc.get(ROOT, "b").unwrap().is_none());
+
+    let (_, patches, heads) = doc
+        .try_transact_observed(|tx| tx.put(ROOT, "c", 2))
+        .unwrap();
+    assert_eq!(heads, doc.get_heads());
+    assert_eq!(patches.len(), 1);
+    assert!(matches!(&patches[0], Patch::Put { prop: Prop::Map(k), .. } if k == "c"));
+}
+
+#[test]
+fn doc_handle_applies_queued_changes_in_the_background() {
+    let mut remote = AutoCommit::new();
+    remote.put(ROOT, "a", 1).unwrap();
+    remote.commit();
+    let first = remote.save_incremental();
+    remote.put(ROOT, "b", 2).unwrap();
+    remote.commit();
+    let second = remote.save_incremental();
+
+    let handle = DocHandle::new(Automerge::new());
+    let events = handle.subscribe();
+    handle.enqueue_changes(first).unwrap();
+    handle.enqueue_changes(second).unwrap();
+    handle.flush().unwrap();
+    assert_eq!(handle.pending(), 0);
+
+    let mut patches = Vec::new();
+    for event in events.try_iter() {
+        match event {
+            HandleEvent::Changed { patches: p, .. } => patches.extend(p),
+            HandleEvent::Failed(e) => panic!("unexpected failure: {}", e),
+            HandleEvent::Stopped => panic!("unexpected panic on the worker"),
+        }
+    }
+    assert_eq!(patches.len(), 2);
+    assert_eq!(handle.with_doc(|doc| doc.get_heads()), remote.get_heads());
+
+    // a different change with the same actor and sequence number as one already applied
+    let mut rogue = AutoCommit::new().with_actor(remote.get_actor().clone());
+    rogue.put(ROOT, "x", 1).unwrap();
+    rogue.commit();
+    handle.enqueue_changes(rogue.save_incremental()).unwrap();
+    handle.flush().unwrap();
+    assert!(matches!(events.try_recv(), Ok(HandleEvent::Failed(_))));
+
+    handle.with_doc(|doc| {
+        let mut tx = doc.transaction();
+        tx.put(ROOT, "c", 3).unwrap();
+        tx.commit();
+    });
+    let doc = handle.into_inner();
+    assert_eq!(doc.keys(ROOT).count(), 3);
+}
+
+#[test]
+fn tagged_heads_name_versions() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "title", "draft").unwrap();
+    doc.commit();
+    let v1 = doc.get_heads();
+    doc.tag_heads("v1.0", &v1).unwrap();
+    doc.put(ROOT, "title", "final").unwrap();
+    doc.commit();
+
+    let heads = doc.heads_for_tag("v1.0").unwrap().unwrap();
+    assert_eq!(heads, v1);
+    assert_eq!(
+        doc.get_at(ROOT, "title", &heads).unwrap().unwrap().0,
+        Value::str("draft")
+    );
+    assert_eq!(doc.heads_for_tag("v2.0").unwrap(), None);
+
+    let mut other = AutoCommit::new();
+    other.merge(&mut doc).unwrap();
+    let tags = other.document().tags().unwrap();
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags["v1.0"], v1);
+
+    doc.remove_tag("v1.0").unwrap();
+    doc.remove_tag("v1.0").unwrap();
+    assert!(doc.tags().unwrap().is_empty());
+    assert!(matches!(
+        doc.tag_heads(REGISTRY_MARKER_KEY, &v1),
+        Err(AutomergeError::ReservedKey(_))
+    ));
+}
+
+#[test]
+fn registries_do_not_replace_user_values_at_reserved_keys() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, TAGS_KEY, "mine").unwrap();
+    let user_map = doc
+        .put_object(ROOT, ACTOR_METADATA_KEY, ObjType::Map)
+        .unwrap();
+    doc.put(&user_map, "name", "mine too").unwrap();
+    let heads = doc.get_heads();
+
+    assert!(matches!(
+        doc.tag_heads("v1", &heads),
+        Err(AutomergeError::ReservedKey(_))
+    ));
+    let actor = doc.get_actor().clone();
+    let metadata = ActorMetadata {
+        name: Some("Alice".to_string()),
+        ..Default::default()
+    };
+    assert!(matches!(
+        doc.set_actor_metadata(&actor, &metadata),
+        Err(AutomergeError::ReservedKey(_))
+    ));
+    doc.remove_tag("v1").unwrap();
+
+    // the user's values are untouched and are not read as tags or metadata
+    assert_eq!(
+        doc.get(ROOT, TAGS_KEY).unwrap().unwrap().0,
+        Value::str("mine")
+    );
+    assert_eq!(doc.keys(&user_map).collect::<Vec<_>>(), vec!["name"]);
+    assert!(doc.tags().unwrap().is_empty());
+    assert_eq!(doc.actor_metadata(&actor).unwrap(), None);
+}
+
+#[test]
+fn load_filtered_only_decodes_chosen_objects() {
+    let mut doc = AutoCommit::new();
+    let settings = doc.put_object(ROOT, "settings", ObjType::Map).unwrap();
+    doc.put(&settings, "theme", "dark").unwrap();
+    let fonts = doc.put_object(&settings, "fonts", ObjType::List).unwrap();
+    doc.insert(&fonts, 0, "mono").unwrap();
+    let empty = doc.put_object(&settings, "empty", ObjType::Map).unwrap();
+    let archive = doc.put_object(ROOT, "archive", ObjType::List).unwrap();
+    let entry = doc.insert_object(&archive, 0, ObjType::Map).unwrap();
+    doc.put(&entry, "title", "old").unwrap();
+    let saved = doc.save();
+
+    let mut paths = Vec::new();
+    let mut partial = Automerge::load_filtered(&saved, |path| {
+        paths.push(path.to_vec());
+        path.first().map_or(false, |p| *p == Prop::from("settings"))
+    })
+    .unwrap();
+    paths.sort_by_key(|p| p.len());
+    assert_eq!(paths.len(), 6);
+    assert!(paths.contains(&vec![Prop::from("archive"), Prop::from(0)]));
+    assert_eq!(partial.decoded_objects(), 3);
+    assert_eq!(
+        partial.get(&settings, "theme").unwrap().unwrap().0,
+        Value::str("dark")
+    );
+    assert_eq!(partial.text(&fonts).unwrap(), "mono");
+    assert_eq!(partial.length(&empty).unwrap(), 0);
+    assert!(matches!(
+        partial.get(&entry, "title"),
+        Err(AutomergeError::NotLoaded(_))
+    ));
+    assert!(matches!(
+        partial.keys(ROOT),
+        Err(AutomergeError::NotLoaded(_))
+    ));
+
+    let mut full = partial.into_automerge().unwrap();
+    let mut other = doc.fork();
+    other.put(&entry, "title", "new").unwrap();
+    other.commit();
+    full.merge(&mut other.document().clone()).unwrap();
+    assert_eq!(
+        full.get(&entry, "title").unwrap().unwrap().0,
+        Value::str("new")
+    );
+}
+
+#[test]
+fn compare_heads() {
+    let mut doc1 = AutoCommit::new();
+    doc1.put(ROOT, "a", 1).unwrap();
+    doc1.commit();
+    let base = doc1.get_heads();
+    let mut doc2 = doc1.fork();
+
+    doc1.put(ROOT, "b", 1).unwrap();
+    doc1.commit();
+    let left = doc1.get_heads();
+    doc2.put(ROOT, "c", 1).unwrap();
+    doc2.commit();
+    let right = doc2.get_heads();
+    doc1.merge(&mut doc2).unwrap();
+    let merged = doc1.get_heads();
+
+    assert!(doc1.is_ancestor(&base[0], &left[0]).unwrap());
+    assert!(doc1.is_ancestor(&left[0], &left[0]).unwrap());
+    assert!(!doc1.is_ancestor(&left[0], &base[0]).unwrap());
+    assert!(!doc1.is_ancestor(&left[0], &right[0]).unwrap());
+
+    assert!(doc1.heads_equal(&merged, &merged).unwrap());
+    // listing an ancestor of a head doesn't change the version
+    let mut redundant = merged.clone();
+    redundant.push(base[0]);
+    assert!(doc1.heads_equal(&merged, &redundant).unwrap());
+    assert!(!doc1.heads_equal(&left, &right).unwrap());
+
+    assert_eq!(
+        doc1.heads_diff(&left, &right).unwrap(),
+        (left.clone(), right.clone())
+    );
+    assert_eq!(doc1.heads_diff(&merged, &base).unwrap().0.len(), 2);
+    assert!(doc1.heads_diff(&base, &merged).unwrap().0.is_empty());
+    assert!(matches!(
+        doc1.is_ancestor(&ChangeHash([0; 32]), &left[0]),
+        Err(AutomergeError::MissingHash(_))
+    ));
+}
+
+#[test]
+fn project_rows_of_a_table() {
+    let mut doc = AutoCommit::new();
+    let users = doc.put_object(ROOT, "users", ObjType::Map).unwrap();
+    let alice = doc.put_object(&users, "alice", ObjType::Map).unwrap();
+    doc.put(&alice, "age", 30).unwrap();
+    doc.put_object(&alice, "tags", ObjType::List).unwrap();
+    let bob = doc.put_object(&users, "bob", ObjType::Map).unwrap();
+    doc.put(&bob, "age", 40).unwrap();
+    doc.put(&users, "count", 2).unwrap();
+    doc.commit();
+
+    let rows = doc.project_rows(&users).unwrap();
+    assert_eq!(
+        rows[0],
+        RowOp::Upsert {
+            key: "alice".to_string(),
+            columns: [("age".to_string(), ScalarValue::Int(30))]
+                .into_iter()
+                .collect(),
+        }
+    );
+    assert_eq!(rows.len(), 2);
+
+    let mut projection = RowProjection::new(users.clone());
+    assert_eq!(projection.refresh(doc.document()).unwrap(), rows);
+
+    let mut observed = AutoCommit::load(&doc.save())
+        .unwrap()
+        .with_observer(VecOpObserver::default());
+    observed.put(&alice, "age", 31).unwrap();
+    observed.put(&bob, "age", 40).unwrap();
+    observed.commit();
+    let patches = observed.observer().take_patches();
+    let ops = projection.update(observed.document(), &patches).unwrap();
+    assert_eq!(ops.len(), 1);
+    assert!(matches!(&ops[0], RowOp::Upsert { key, .. } if key == "alice"));
+
+    observed.delete(&users, "bob").unwrap();
+    observed.commit();
+    let patches = observed.observer().take_patches();
+    let ops = projection.update(observed.document(), &patches).unwrap();
+    assert_eq!(
+        ops,
+        vec![RowOp::Delete {
+            key: "bob".to_string()
+        }]
+    );
+
+    // rows of a list are keyed by their ids
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    let row = doc.insert_object(&list, 0, ObjType::Map).unwrap();
+    let rows = doc.project_rows(&list).unwrap();
+    assert!(matches!(&rows[0], RowOp::Upsert { key, .. } if *key == row.to_string()));
+}
+
+#[test]
+fn actor_policies_catch_reused_and_stale_actors() {
+    let actor = ActorId::from(b"device".as_slice());
+    let mut doc = Automerge::new()
+        .with_actor(actor.clone())
+        .with_actor_policy(ActorPolicy::PerSession);
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "a", 1).unwrap();
+    tx.try_commit().unwrap();
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "a", 2).unwrap();
+    tx.try_commit().unwrap();
+    assert_eq!(doc.local_seq(), 2);
+    let saved = doc.save();
+
+    // a new session which reuses the actor is refused
+    let mut next = Automerge::load(&saved)
+        .unwrap()
+        .with_actor(actor.clone())
+        .with_actor_policy(ActorPolicy::PerSession);
+    let mut tx = next.transaction();
+    assert!(matches!(
+        tx.put(ROOT, "a", 3),
+        Err(AutomergeError::ActorReused(a)) if a == actor
+    ));
+    assert!(matches!(
+        tx.try_commit(),
+        Err(AutomergeError::ActorReused(_))
+    ));
+    let result = next.transact::<_, _, AutomergeError>(|tx| tx.put(ROOT, "a", 3));
+    assert!(matches!(
+        result,
+        Err(f) if matches!(f.error, AutomergeError::ActorReused(_))
+    ));
+    assert_eq!(next.get_heads(), doc.get_heads());
+
+    // a device which has made two changes can't carry on from a copy with only the first
+    let first = doc.get_changes(&[]).unwrap()[0].clone();
+    let mut old = AutoCommit::new().with_actor(actor.clone());
+    old.apply_changes(vec![first]).unwrap();
+    old.set_actor_policy(ActorPolicy::PerDevice { last_seq: 2 });
+    assert!(matches!(
+        old.put(ROOT, "a", 4),
+        Err(AutomergeError::StaleActor {
+            seq: 1,
+            last_seq: 2,
+            ..
+        })
+    ));
+    old.merge(&mut AutoCommit::load(&saved).unwrap()).unwrap();
+    old.put(ROOT, "a", 4).unwrap();
+    old.try_commit().unwrap();
+    assert_eq!(old.local_seq(), 3);
+
+    #[cfg(feature = "rand")]
+    {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let a = ActorId::generate_with(&mut rng);
+        let b = ActorId::generate_with(&mut rand::rngs::StdRng::seed_from_u64(1));
+        assert_eq!(a, b);
+        assert_eq!(a.to_bytes().len(), 16);
+        assert_ne!(a, ActorId::generate_with(&mut rng));
+    }
+}
+
+#[test]
+fn map_range_of_large_maps_seeks_to_its_bounds() {
+    use std::ops::Bound;
+
+    let mut doc = AutoCommit::new();
+    for i in 0..2000 {
+        doc.put(ROOT, format!("{:04}", i), i).unwrap();
+    }
+    // a conflict and a deleted key at the edges of the ranges below
+    let mut other = doc.fork().with_actor(ActorId::random());
+    other.put(ROOT, "0100", "other").unwrap();
+    doc.put(ROOT, "0100", "ours").unwrap();
+    doc.merge(&mut other).unwrap();
+    doc.delete(ROOT, "0110").unwrap();
+
+    let keys = |range: (Bound<String>, Bound<String>)| {
+        doc.map_range(ROOT, range.clone())
+            .map(|(k, _, _)| k.to_string())
+            .collect::<Vec<_>>()
+    };
+    let expected = |lo: u32, hi: u32| {
+        (lo..hi)
+            .filter(|i| *i != 110)
+            .map(|i| format!("{:04}", i))
+            .collect::<Vec<_>>()
+    };
+    let key = |s: &str| s.to_string();
+
+    assert_eq!(
+        keys((Bound::Included(key("0100")), Bound::Excluded(key("0112")))),
+        expected(100, 112)
+    );
+    assert_eq!(
+        keys((Bound::Excluded(key("0100")), Bound::Included(key("0111")))),
+        expected(101, 112)
+    );
+    assert_eq!(
+        keys((Bound::Included(key("0099a")), Bound::Excluded(key("0101")))),
+        expected(100, 101)
+    );
+    assert_eq!(
+        keys((Bound::Unbounded, Bound::Excluded(key("0003")))),
+        expected(0, 3)
+    );
+    assert_eq!(
+        keys((Bound::Included(key("1998")), std::ops::Bound::Unbounded)),
+        expected(1998, 2000)
+    );
+    assert!(keys((Bound::Included(key("2")), std::ops::Bound::Unbounded)).is_empty());
+    assert!(keys((Bound::Included(key("0200")), Bound::Excluded(key("0100")))).is_empty());
+
+    // the winner of the conflict is returned from both ends
+    let range = doc.map_range(ROOT, key("0100")..key("0101"));
+    let winner = doc.get(ROOT, "0100").unwrap().unwrap().0;
+    assert_eq!(
+        range.map(|(_, v, _)| v).collect::<Vec<_>>(),
+        vec![winner.clone()]
+    );
+    let range = doc.map_range(ROOT, key("0100")..key("0101"));
+    assert_eq!(
+        range.rev().map(|(_, v, _)| v).collect::<Vec<_>>(),
+        vec![winner]
+    );
+
+    // and in the past
+    let heads = doc.get_heads();
+    doc.put(ROOT, "0105", "later").unwrap();
+    let at = doc
+        .map_range_at(ROOT, key("0104")..=key("0105"), &heads)
+        .map(|(k, v, _)| (k.to_string(), v))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        at,
+        vec![
+            (key("0104"), Value::int(104)),
+            (key("0105"), Value::int(105))
+        ]
+    );
+}
+
+#[test]
+fn patches_serialize_to_a_stable_format() {
+    let mut doc = AutoCommit::new()
+        .with_actor(ActorId::from(&[0xaa][..]))
+        .with_observer(VecOpObserver::default());
+    let todos = doc.put_object(ROOT, "todos", ObjType::List).unwrap();
+    let todo = doc.insert_object(&todos, 0, ObjType::Map).unwrap();
+    doc.put(&todo, "done", false).unwrap();
+    doc.put(&todo, "count", ScalarValue::counter(1)).unwrap();
+    doc.put(&todo, "when", ScalarValue::Timestamp(5)).unwrap();
+    doc.put(&todo, "big", 7_u64).unwrap();
+    doc.put(&todo, "none", ()).unwrap();
+    doc.increment(&todo, "count", 2).unwrap();
+    doc.delete(&todo, "big").unwrap();
+    let text = doc.put_object(&todo, "title", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "hi").unwrap();
+    doc.commit();
+    doc.observer().take_patches();
+
+    doc.put(&todo, "done", true).unwrap();
+    doc.increment(&todo, "count", 2).unwrap();
+    doc.insert(&todos, 1, "x").unwrap();
+    doc.insert(&todos, 2, "y").unwrap();
+    doc.delete(&todos, 1).unwrap();
+    doc.splice_text(&text, 2, 0, "!").unwrap();
+    doc.delete(&todo, "none").unwrap();
+    doc.commit();
+    let patches = doc.observer().take_patches();
+
+    let path = serde_json::json!([
+        { "obj": "_root", "prop": "todos" },
+        { "obj": todos.to_string(), "prop": 0 },
+    ]);
+    let json = serde_json::to_value(&patches).unwrap();
+    let json = json.as_array().unwrap();
+    assert_eq!(
+        json[0],
+        serde_json::json!({
+            "action": "put",
+            "obj": todo.to_string(),
+            "path": path,
+            "prop": "done",
+            "value": { "datatype": "boolean", "value": true, "id": "13@aa" },
+            "conflict": false,
+        })
+    );
+    assert_eq!(
+        json[1],
+        serde_json::json!({
+            "action": "increment",
+            "obj": todo.to_string(),
+            "path": path,
+            "prop": "count",
+            "value": 2,
+            "id": "14@aa",
+        })
+    );
+    assert_eq!(
+        json[2],
+        serde_json::json!({
+            "action": "insert",
+            "obj": todos.to_string(),
+            "path": [{ "obj": "_root", "prop": "todos" }],
+            "index": 1,
+            "value": { "datatype": "str", "value": "x", "id": "15@aa" },
+        })
+    );
+    assert_eq!(
+        json[4],
+        serde_json::json!({
+            "action": "delete",
+            "obj": todos.to_string(),
+            "path": [{ "obj": "_root", "prop": "todos" }],
+            "prop": 1,
+            "length": 1,
+        })
+    );
+    assert_eq!(json[5]["action"], "spliceText");
+    assert_eq!(json[5]["index"], 2);
+    assert_eq!(json[5]["value"], "!");
+    assert_eq!(
+        json[5]["path"][2],
+        serde_json::json!({ "obj": todo.to_string(), "prop": "title" })
+    );
+    assert_eq!(json[6]["prop"], "none");
+
+    // every datatype of value
+    let values = serde_json::to_value(Patch::Splice {
+        path: vec![],
+        obj: ExId::Root,
+        index: 0,
+        values: vec![
+            (Value::map(), ExId::Root),
+            (Value::counter(3), ExId::Root),
+            (
+                Value::Scalar(std::borrow::Cow::Owned(ScalarValue::Timestamp(5))),
+                ExId::Root,
+            ),
+            (Value::uint(7), ExId::Root),
+            (
+                Value::Scalar(std::borrow::Cow::Owned(ScalarValue::Null)),
+                ExId::Root,
+            ),
+            (Value::bytes(vec![1, 2]), ExId::Root),
+            (
+                Value::Scalar(std::borrow::Cow::Owned(ScalarValue::Unknown {
+                    type_code: 20,
+                    bytes: vec![3],
+                })),
+                ExId::Root,
+            ),
+        ],
+    })
+    .unwrap();
+    assert_eq!(
+        values,
+        serde_json::json!({
+            "action": "splice",
+            "obj": "_root",
+            "path": [],
+            "index": 0,
+            "values": [
+                { "datatype": "map", "id": "_root" },
+                { "datatype": "counter", "value": 3, "id": "_root" },
+                { "datatype": "timestamp", "value": 5, "id": "_root" },
+                { "datatype": "uint", "value": 7, "id": "_root" },
+                { "datatype": "null", "id": "_root" },
+                { "datatype": "bytes", "value": [1, 2], "id": "_root" },
+                { "datatype": "unknown", "typeCode": 20, "value": [3], "id": "_root" },
+            ],
+        })
+    );
+    let range = Patch::DeleteRange {
+        path: vec![],
+        obj: ExId::Root,
+        index: 3,
+        length: 2,
+    };
+    assert_eq!(
+        serde_json::to_value(range).unwrap(),
+        serde_json::json!({ "action": "delete", "obj": "_root", "path": [], "prop": 3, "length": 2 })
+    );
+}
+
+#[cfg(feature = "testgen")]
+#[test]
+fn testgen_histories_are_reproducible_and_shrink() {
+    use crate::testgen::{generate_document, Config, History, InvalidConfig, Step};
+
+    let config = Config {
+        steps: 40,
+        ..Config::default()
+    };
+    let history = History::generate(3, &config).unwrap();
+    assert_eq!(history, History::generate(3, &config).unwrap());
+    assert_ne!(history, History::generate(4, &config).unwrap());
+    assert_eq!(history.steps().len(), 40);
+    assert!(history
+        .steps()
+        .iter()
+        .any(|s| matches!(s, Step::Merge { .. })));
+
+    // replaying makes the same changes every time, and peers which exchange them converge
+    let docs = history.replay();
+    assert_eq!(docs.len(), 3);
+    let merged = history.merged();
+    assert_eq!(
+        merged.get_heads(),
+        generate_document(3, &config).unwrap().get_heads()
+    );
+    for doc in docs {
+        let mut other = merged.clone();
+        let mut doc = doc;
+        doc.merge(&mut other).unwrap();
+        assert_eq!(doc.get_heads(), merged.get_heads());
+    }
+
+    // a failing property shrinks to the single edit which makes it fail
+    let fails = |h: &History| h.merged().get(ROOT, "k1").unwrap().is_some();
+    let history = (0..)
+        .map(|seed| History::generate(seed, &config).unwrap())
+        .find(|h| fails(h))
+        .unwrap();
+    let minimal = history.minimize(fails);
+    assert!(fails(&minimal));
+    assert_eq!(minimal.steps().len(), 1);
+    assert!(matches!(&minimal.steps()[0], Step::Change { edits, .. } if edits.len() == 1));
+
+    for merge_probability in [-0.1, 1.5, f64::NAN] {
+        let config = Config {
+            merge_probability,
+            ..Config::default()
+        };
+        assert!(matches!(
+            History::generate(0, &config),
+            Err(InvalidConfig::MergeProbability(_))
+        ));
+    }
+}
+
+#[test]
+fn commit_hooks_see_pending_ops_and_committed_changes() {
+    use std::sync::{Arc, Mutex};
+
+    let before = Arc::new(Mutex::new(Vec::new()));
+    let after = Arc::new(Mutex::new(Vec::new()));
+    let mut doc = Automerge::new();
+    let before_hook = {
+        let before = Arc::clone(&before);
+        doc.on_before_commit(move |pending| {
+            let props = pending.ops().map(|op| op.prop).collect::<Vec<_>>();
+            before.lock().unwrap().push((
+                pending.seq(),
+                pending.message().map(String::from),
+                props,
+            ));
+        })
+    };
+    {
+        let after = Arc::clone(&after);
+        doc.on_commit(move |change| after.lock().unwrap().push(change.hash()));
+    }
+
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "a", 1).unwrap();
+    tx.put(ROOT, "b", 2).unwrap();
+    let hash = tx.commit_with(CommitOptions::default().with_message("first"));
+    assert_eq!(
+        *before.lock().unwrap(),
+        vec![(
+            1,
+            Some("first".to_string()),
+            vec![Prop::Map("a".into()), Prop::Map("b".into())]
+        )]
+    );
+    assert_eq!(*after.lock().unwrap(), vec![hash]);
+
+    // rolled back transactions and applied changes don't run the hooks
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "c", 3).unwrap();
+    tx.rollback();
+    let mut other = doc.fork();
+    other
+        .transact::<_, _, AutomergeError>(|tx| tx.put(ROOT, "d", 4))
+        .unwrap();
+    doc.merge(&mut other).unwrap();
+    assert_eq!(after.lock().unwrap().len(), 1);
+
+    assert!(doc.remove_commit_hook(before_hook));
+    assert!(!doc.remove_commit_hook(before_hook));
+    let mut auto = AutoCommit::load(&doc.save()).unwrap();
+    let saved = Arc::new(Mutex::new(0));
+    {
+        let saved = Arc::clone(&saved);
+        auto.on_commit(move |_| *saved.lock().unwrap() += 1);
+    }
+    auto.put(ROOT, "e", 5).unwrap();
+    auto.put(ROOT, "f", 6).unwrap();
+    assert_eq!(*saved.lock().unwrap(), 0);
+    auto.save();
+    assert_eq!(*saved.lock().unwrap(), 1);
+    assert_eq!(before.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn delete_ranges_of_maps_and_lists() {
+    let mut doc = AutoCommit::new().with_observer(VecOpObserver::default());
+    for i in 0..20 {
+        doc.put(ROOT, format!("k{:02}", i), i).unwrap();
+    }
+    doc.delete(ROOT, "k05").unwrap();
+    let mut other = doc.fork().with_actor(ActorId::random());
+    other.put(ROOT, "k06", "theirs").unwrap();
+    doc.put(ROOT, "k06", "ours").unwrap();
+    doc.merge(&mut other).unwrap();
+    doc.commit();
+    doc.observer().take_patches();
+
+    // the conflicting values of k06 are deleted together
+    let deleted = doc
+        .delete_range(ROOT, "k04".to_string().."k08".to_string())
+        .unwrap();
+    assert_eq!(deleted, 3);
+    let keys = doc.keys(ROOT).collect::<Vec<_>>();
+    assert_eq!(keys.len(), 16);
+    assert!(doc.get(ROOT, "k03").unwrap().is_some());
+    assert!(doc.get(ROOT, "k06").unwrap().is_none());
+    assert!(doc.get_all(ROOT, "k06").unwrap().is_empty());
+    assert!(doc.get(ROOT, "k08").unwrap().is_some());
+    let patches = doc.observer().take_patches();
+    assert_eq!(patches.len(), 3);
+    assert!(patches.iter().all(|p| matches!(
+        p,
+        Patch::Delete {
+            prop: Prop::Map(_),
+            ..
+        }
+    )));
+    assert_eq!(
+        doc.delete_range(ROOT, "k04".to_string()..="k07".to_string())
+            .unwrap(),
+        0
+    );
+    assert_eq!(doc.delete_range(ROOT, "k18".to_string()..).unwrap(), 2);
+    assert_eq!(doc.length(ROOT), 14);
+
+    // a key put concurrently with the deletion survives it
+    let mut other = doc.fork().with_actor(ActorId::random());
+    other.put(ROOT, "k10b", "new").unwrap();
+    assert_eq!(doc.delete_range(ROOT, ..).unwrap(), 14);
+    doc.merge(&mut other).unwrap();
+    assert_eq!(doc.keys(ROOT).collect::<Vec<_>>(), vec!["k10b"]);
+
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.splice(&list, 0, 0, (0..10).map(ScalarValue::from))
+        .unwrap();
+    assert_eq!(doc.delete_list_range(&list, 2..5).unwrap(), 3);
+    assert_eq!(doc.delete_list_range(&list, 5..).unwrap(), 2);
+    assert!(matches!(
+        doc.delete_list_range(&list, 3..=5),
+        Err(AutomergeError::InvalidIndex(6))
+    ));
+    assert!(matches!(
+        doc.delete_list_range(&list, 0..=usize::MAX),
+        Err(AutomergeError::InvalidIndex(usize::MAX))
+    ));
+    assert!(matches!(
+        doc.delete_list_range(
+            &list,
+            (
+                std::ops::Bound::Excluded(usize::MAX),
+                std::ops::Bound::Unbounded
+            )
+        ),
+        Err(AutomergeError::InvalidIndex(usize::MAX))
+    ));
+    let values = doc
+        .list_range(&list, ..)
+        .map(|(_, v, _)| v.to_i64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(values, vec![0, 1, 5, 6, 7]);
+    assert!(matches!(
+        doc.delete_range(&list, ..),
+        Err(AutomergeError::InvalidValueType { .. })
+    ));
+    assert!(matches!(
+        doc.delete_list_range(ROOT, ..),
+        Err(AutomergeError::InvalidValueType { .. })
+    ));
+
+    let saved = doc.save();
+    let loaded = AutoCommit::load(&saved).unwrap();
+    assert_eq!(loaded.length(ROOT), 2);
+    assert_eq!(loaded.length(&list), 5);
+
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "a", 1).unwrap();
+    tx.put(ROOT, "b", 2).unwrap();
+    assert_eq!(tx.delete_range(ROOT, ..).unwrap(), 2);
+    tx.commit();
+    assert_eq!(doc.length(ROOT), 0);
+}
+
+#[test]
+fn merge_and_apply_changes_report_to_an_observer() {
+    let mut doc = Automerge::new();
+    let mut other = doc.fork();
+    let list = other
+        .transact::<_, _, AutomergeError>(|tx| {
+            let list = tx.put_object(ROOT, "list", ObjType::List)?;
+            tx.insert(&list, 0, 1)?;
+            Ok(list)
+        })
+        .unwrap()
+        .result;
+    other
+        .transact::<_, _, AutomergeError>(|tx| tx.put(ROOT, "key", "value"))
+        .unwrap();
+
+    let mut observer = VecOpObserver::default();
+    let heads = doc.merge_and_observe(&mut other, &mut observer).unwrap();
+    assert_eq!(heads, other.get_heads());
+    let patches = observer.take_patches();
+    assert_eq!(patches.len(), 3);
+    assert!(matches!(&patches[1], Patch::Insert { obj, index: 0, .. } if *obj == list));
+
+    // nothing new to merge
+    doc.merge_and_observe(&mut other, &mut observer).unwrap();
+    assert!(observer.take_patches().is_empty());
+
+    let mut fresh = Automerge::new();
+    let changes = doc.get_changes(&[]).unwrap().into_iter().cloned();
+    fresh
+        .apply_changes_and_observe(changes, &mut observer)
+        .unwrap();
+    assert_eq!(observer.take_patches(), patches);
+}
+
+#[test]
+fn repeated_strings_share_their_storage() {
+    let status = "waiting for review by the team";
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "items", ObjType::List).unwrap();
+    for i in 0..1000 {
+        doc.insert(&list, i, status).unwrap();
+    }
+    doc.insert(&list, 1000, "done").unwrap();
+    doc.commit();
+
+    let usage = doc.memory_usage();
+    assert_eq!(usage.values, status.len());
+    assert!(usage.history > 0);
+    assert_eq!(usage.total(), usage.ops + usage.values + usage.history);
+
+    let loaded = Automerge::load(&doc.save()).unwrap();
+    assert_eq!(loaded.memory_usage().values, status.len());
+
+    let mut other = Automerge::new();
+    other.merge(&mut doc.document().clone()).unwrap();
+    assert_eq!(other.memory_usage().values, status.len());
+}
+
+#[test]
+fn integers_survive_peers_which_write_them_back_as_floats() {
+    let big = (1_i64 << 60) + 1;
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "count", 3).unwrap();
+    doc.put(ROOT, "big", big).unwrap();
+    doc.put(ROOT, "ratio", 2.0).unwrap();
+
+    // a peer with only floats reads every value and writes it back
+    let mut js = doc.fork();
+    for key in ["count", "big", "ratio"] {
+        let (value, _) = js.get(ROOT, key).unwrap().unwrap();
+        let float = value.to_scalar().and_then(|v| v.to_f64()).unwrap();
+        js.put(ROOT, key, float).unwrap();
+    }
+    doc.merge(&mut js).unwrap();
+
+    assert_eq!(
+        doc.numeric_type(ROOT, "count").unwrap(),
+        Some(NumericType::F64)
+    );
+    doc.set_numeric_mode(NumericMode::PreserveIntegers);
+    let number = |doc: &AutoCommit, key| doc.get_number(ROOT, key).unwrap().map(|(v, _)| v);
+    assert_eq!(number(&doc, "count"), Some(ScalarValue::Int(3)));
+    assert_eq!(number(&doc, "big"), Some(ScalarValue::Int(big)));
+    assert_eq!(number(&doc, "ratio"), Some(ScalarValue::F64(2.0)));
+    assert_eq!(
+        doc.numeric_type(ROOT, "count").unwrap(),
+        Some(NumericType::Int)
+    );
+    assert_eq!(number(&doc, "missing"), None);
+
+    // writing a whole float over a preserved integer keeps the integer
+    doc.put(ROOT, "count", 3.0).unwrap();
+    assert_eq!(doc.get(ROOT, "count").unwrap().unwrap().0, Value::int(3));
+    doc.put(ROOT, "count", 3.5).unwrap();
+    assert_eq!(number(&doc, "count"), Some(ScalarValue::F64(3.5)));
+}
+
+#[test]
+fn numeric_mode_is_saved_in_the_config() {
+    let mut doc = AutoCommit::new();
+    doc.set_numeric_mode(NumericMode::PreserveIntegers);
+    doc.put(ROOT, "count", 3).unwrap();
+    assert_eq!(
+        doc.config().get("automerge.numeric_mode"),
+        Some("preserve_integers")
+    );
+
+    let saved = doc.save();
+    let loaded = AutoCommit::load(&saved).unwrap();
+    assert_eq!(loaded.numeric_mode(), NumericMode::PreserveIntegers);
+    assert_eq!(Automerge::load_lazy(&saved).unwrap().config(), doc.config());
+
+    // a new document adopts the mode of the first document it merges
+    let mut peer = AutoCommit::new();
+    peer.merge(&mut doc).unwrap();
+    assert_eq!(peer.numeric_mode(), NumericMode::PreserveIntegers);
+
+    // peers with different modes can't merge
+    let mut native = AutoCommit::new();
+    native.put(ROOT, "count", 1).unwrap();
+    assert!(matches!(
+        native.merge(&mut doc),
+        Err(AutomergeError::ConfigMismatch)
+    ));
+
+    doc.set_numeric_mode(NumericMode::Native);
+    assert!(doc.config().is_empty());
+    assert_eq!(doc.numeric_mode(), NumericMode::Native);
+}
+
+#[test]
+fn timestamps_convert_to_and_from_system_time() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    let time = UNIX_EPOCH + Duration::from_millis(1_650_000_000_123);
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "at", ScalarValue::try_from(time).unwrap())
+        .unwrap();
+    let (value, _) = doc.get(ROOT, "at").unwrap().unwrap();
+    assert_eq!(value, Value::timestamp(1_650_000_000_123));
+    assert_eq!(value.to_system_time(), Some(time));
+
+    // sub-millisecond precision is dropped towards the past
+    let before = UNIX_EPOCH - Duration::from_micros(1500);
+    let value = ScalarValue::try_from(before).unwrap();
+    assert_eq!(value, ScalarValue::Timestamp(-2));
+    assert_eq!(
+        value.to_system_time(),
+        Some(UNIX_EPOCH - Duration::from_millis(2))
+    );
+    assert_eq!(
+        ScalarValue::try_from(UNIX_EPOCH + Duration::from_micros(1500)).unwrap(),
+        ScalarValue::Timestamp(1)
+    );
+
+    assert_eq!(ScalarValue::Int(5).to_system_time(), None);
+    if let Some(far) = SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(1 << 60)) {
+        assert_eq!(ScalarValue::try_from(far), Err(InvalidTimestamp(far)));
+    }
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn timestamps_convert_to_and_from_chrono() {
+    use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+
+    let time = Utc.timestamp_millis_opt(1_650_000_000_123).unwrap();
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "at", time).unwrap();
+    let (value, _) = doc.get(ROOT, "at").unwrap().unwrap();
+    assert_eq!(value, Value::timestamp(1_650_000_000_123));
+    assert_eq!(value.to_date_time(), Some(time));
+
+    // the offset of the time zone doesn't change the instant
+    let offset = FixedOffset::east_opt(3600).unwrap();
+    assert_eq!(
+        ScalarValue::from(time.with_timezone(&offset)),
+        ScalarValue::Timestamp(1_650_000_000_123)
+    );
+
+    // sub-millisecond precision is dropped towards the past
+    let before = DateTime::parse_from_rfc3339("1969-12-31T23:59:59.9985Z").unwrap();
+    assert_eq!(ScalarValue::from(before), ScalarValue::Timestamp(-2));
+    let after = DateTime::parse_from_rfc3339("1970-01-01T00:00:00.0015Z").unwrap();
+    assert_eq!(ScalarValue::from(after), ScalarValue::Timestamp(1));
+
+    assert_eq!(ScalarValue::Timestamp(i64::MAX).to_date_time(), None);
+    assert_eq!(ScalarValue::Timestamp(i64::MIN).to_date_time(), None);
+    assert_eq!(ScalarValue::Int(5).to_date_time(), None);
+}
+
+#[cfg(feature = "time")]
+#[test]
+fn timestamps_convert_to_and_from_time() {
+    use time::{OffsetDateTime, UtcOffset};
+
+    let time = OffsetDateTime::from_unix_timestamp_nanos(1_650_000_000_123_000_000).unwrap();
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "at", time).unwrap();
+    let (value, _) = doc.get(ROOT, "at").unwrap().unwrap();
+    assert_eq!(value, Value::timestamp(1_650_000_000_123));
+    assert_eq!(value.to_offset_date_time(), Some(time));
+
+    // the offset of the time zone doesn't change the instant
+    let offset = UtcOffset::from_hms(1, 0, 0).unwrap();
+    assert_eq!(
+        ScalarValue::from(time.to_offset(offset)),
+        ScalarValue::Timestamp(1_650_000_000_123)
+    );
+
+    // sub-millisecond precision is dropped towards the past
+    let before = OffsetDateTime::from_unix_timestamp_nanos(-1_500_000).unwrap();
+    assert_eq!(ScalarValue::from(before), ScalarValue::Timestamp(-2));
+    let after = OffsetDateTime::from_unix_timestamp_nanos(1_500_000).unwrap();
+    assert_eq!(ScalarValue::from(after), ScalarValue::Timestamp(1));
+
+    assert_eq!(ScalarValue::Timestamp(i64::MAX).to_offset_date_time(), None);
+    assert_eq!(ScalarValue::Timestamp(i64::MIN).to_offset_date_time(), None);
+    assert_eq!(ScalarValue::Int(5).to_offset_date_time(), None);
+}
+
+#[test]
+fn tuples_of_observers_each_see_every_op() {
+    #[derive(Debug, Default, Clone)]
+    struct CountOps(usize);
+
+    impl OpObserver for CountOps {
+        fn insert(&mut self, _: Parents<'_>, _: ExId, _: usize, _: (Value<'_>, ExId)) {
+            self.0 += 1;
+        }
+
+        fn splice_text(&mut self, _: Parents<'_>, _: ExId, _: usize, value: &str) {
+            self.0 += value.chars().count();
+        }
+
+        fn put(&mut self, _: Parents<'_>, _: ExId, _: Prop, _: (Value<'_>, ExId), _: bool) {
+            self.0 += 1;
+        }
+
+        fn increment(&mut self, _: Parents<'_>, _: ExId, _: Prop, _: (i64, ExId)) {
+            self.0 += 1;
+        }
+
+        fn delete(&mut self, _: Parents<'_>, _: ExId, _: Prop) {
+            self.0 += 1;
+        }
+
+        fn merge(&mut self, other: &Self) {
+            self.0 += other.0;
+        }
+    }
+
+    let mut doc = AutoCommit::new().with_observer((VecOpObserver::default(), CountOps(0)));
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, "a").unwrap();
+    doc.put(ROOT, "key", 1).unwrap();
+    doc.delete(ROOT, "key").unwrap();
+    doc.commit();
+
+    let (patches, count) = doc.observer();
+    assert_eq!(patches.take_patches().len(), 4);
+    assert_eq!(count.0, 4);
+
+    // observers can be nested
+    let mut observer = (CountOps(0), (CountOps(0), VecOpObserver::default()));
+    let mut other = Automerge::new();
+    other
+        .merge_and_observe(&mut doc.document().clone(), &mut observer)
+        .unwrap();
+    let patches = (observer.1).1.take_patches();
+    assert_eq!(observer.0 .0, 4);
+    assert_eq!((observer.1).0 .0, 4);
+    assert_eq!(patches.len(), 4);
+}
+
+#[test]
+fn get_returns_the_value_with_the_greatest_id() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(&[1][..]));
+    let mut doc2 = AutoCommit::new().with_actor(ActorId::from(&[2][..]));
+    for i in 1..=3 {
+        doc1.put(ROOT, "x", i).unwrap();
+    }
+    doc2.put(ROOT, "x", "b").unwrap();
+    doc1.merge(&mut doc2).unwrap();
+    let heads = doc1.get_heads();
+
+    let (value, id) = doc1.get(ROOT, "x").unwrap().unwrap();
+    assert_eq!(value, Value::int(3));
+    assert_eq!(id.to_string(), "3@01");
+    assert_eq!(doc1.get_int(ROOT, "x").unwrap(), Some(3));
+    assert_eq!(doc1.get_at(ROOT, "x", &heads).unwrap().unwrap().1, id);
+    let all = doc1.get_all(ROOT, "x").unwrap();
+    assert_eq!(
+        all.iter().map(|(_, id)| id.to_string()).collect::<Vec<_>>(),
+        vec!["1@02", "3@01"]
+    );
+    assert_eq!(doc1.get_all_at(ROOT, "x", &heads).unwrap(), all);
+    assert_eq!(doc1.map_range(ROOT, ..).next().unwrap().2, id);
+}
@@ -0,0 +1,96 @@
+use std::borrow::Cow;
+use std::io::{self, Read};
+use std::ops::RangeFull;
+
+use crate::{AutomergeError, ListRange, ScalarValue, Value};
+
+/// The size of the chunks [`crate::transaction::Transactable::put_bytes_stream`] splits bytes
+/// into.
+pub const BYTES_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Reads a bytes value without copying it into a single buffer, see
+/// [`crate::Automerge::get_bytes_reader`].
+#[derive(Debug)]
+pub struct BytesReader<'a> {
+    /// The chunk being read
+    current: Cow<'a, [u8]>,
+    pos: usize,
+    /// The chunks still to read, for bytes written in chunks
+    chunks: Option<ListRange<'a, RangeFull>>,
+}
+
+impl<'a> BytesReader<'a> {
+    /// A reader for `value`, which is either a bytes value or a list of chunks of bytes, whose
+    /// elements `chunks` iterates over.
+    pub(crate) fn new<F>(value: Value<'a>, chunks: F) -> Result<Self, AutomergeError>
+    where
+        F: FnOnce() -> ListRange<'a, RangeFull>,
+    {
+        match value {
+            Value::Object(crate::ObjType::List) => Ok(Self {
+                current: Cow::Borrowed(&[]),
+                pos: 0,
+                chunks: Some(chunks()),
+            }),
+            value => match bytes(value) {
+                Ok(current) => Ok(Self {
+                    current,
+                    pos: 0,
+                    chunks: None,
+                }),
+                Err(value) => Err(AutomergeError::InvalidValueType {
+                    expected: "bytes".to_string(),
+                    unexpected: value.type_name().to_string(),
+                }),
+            },
+        }
+    }
+}
+
+impl<'a> Read for BytesReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.current.len() {
+            let next = self.chunks.as_mut().and_then(|chunks| chunks.next());
+            match next {
+                Some((index, value, _)) => {
+                    self.current = bytes(value).map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("chunk {} is not bytes", index),
+                        )
+                    })?;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.current.len() - self.pos);
+        buf[..n].copy_from_slice(&self.current[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// The bytes in `value`, borrowed from the document if possible.
+fn bytes(value: Value<'_>) -> Result<Cow<'_, [u8]>, Value<'_>> {
+    match value {
+        Value::Scalar(Cow::Borrowed(ScalarValue::Bytes(b))) => Ok(Cow::Borrowed(b.as_slice())),
+        Value::Scalar(Cow::Owned(ScalarValue::Bytes(b))) => Ok(Cow::Owned(b)),
+        value => Err(value),
+    }
+}
+
+/// Fill `buf` from `reader`, returning how much was read, which is less than the length of `buf`
+/// only at the end of the input.
+pub(crate) fn read_chunk<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
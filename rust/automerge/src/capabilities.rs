@@ -0,0 +1,104 @@
+use serde::Serialize;
+
+/// What this build of automerge supports, returned by [`capabilities`].
+///
+/// Each capability is identified by a stable name so that bindings can pass the list on to code
+/// in other languages and sync peers can compare what they support.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    /// The version of the automerge crate.
+    pub version: &'static str,
+    /// The codecs which saved documents and changes may be compressed with.
+    pub compression: Vec<&'static str>,
+    /// Optional parts of the storage format which can be read and written.
+    ///
+    /// * `document-config` - a [`crate::DocumentConfig`] stored ahead of the document
+    /// * `history-fence` - a history fence, chunk type 4, stored ahead of a document whose
+    ///   history beneath the fence was dropped, see [`crate::Automerge::set_history_fence`]
+    /// * `encoded-chunks` - chunks transformed by a [`crate::ChunkCodec`], chunk type 5, see
+    ///   [`crate::Automerge::save_with_codec`]
+    /// * `document-headers` - headers kept outside the change history, chunk type 6, see
+    ///   [`crate::Automerge::set_header`]
+    pub storage_extensions: Vec<&'static str>,
+    /// Optional parts of the sync protocol which can be read and written.
+    ///
+    /// * `chunked-messages` - messages split by [`crate::Automerge::generate_sync_chunk`]
+    /// * `versioned-messages` - messages with a version header, starting with `0x46`, in any of
+    ///   [`crate::sync::WireVersion::SUPPORTED`]
+    pub sync_extensions: Vec<&'static str>,
+    /// The units text indexes can be expressed in.
+    ///
+    /// * `unicode-scalar` - each `char` is one index
+    pub text_encodings: Vec<&'static str>,
+    /// The optional cargo features automerge was compiled with.
+    pub features: Vec<&'static str>,
+}
+
+impl Capabilities {
+    pub fn supports_compression(&self, codec: &str) -> bool {
+        self.compression.contains(&codec)
+    }
+
+    pub fn supports_storage_extension(&self, extension: &str) -> bool {
+        self.storage_extensions.contains(&extension)
+    }
+
+    pub fn supports_sync_extension(&self, extension: &str) -> bool {
+        self.sync_extensions.contains(&extension)
+    }
+
+    pub fn supports_text_encoding(&self, encoding: &str) -> bool {
+        self.text_encodings.contains(&encoding)
+    }
+}
+
+/// The capabilities of this build of automerge.
+pub fn capabilities() -> Capabilities {
+    let mut features = Vec::new();
+    if cfg!(feature = "optree-visualisation") {
+        features.push("optree-visualisation");
+    }
+    if cfg!(feature = "rayon") {
+        features.push("rayon");
+    }
+    if cfg!(feature = "serde_json") {
+        features.push("serde_json");
+    }
+    if cfg!(feature = "wasm") {
+        features.push("wasm");
+    }
+    if cfg!(feature = "wasm-abi") {
+        features.push("wasm-abi");
+    }
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        compression: vec!["deflate"],
+        storage_extensions: vec![
+            "document-config",
+            "history-fence",
+            "encoded-chunks",
+            "document-headers",
+        ],
+        sync_extensions: vec!["chunked-messages", "versioned-messages"],
+        text_encodings: vec!["unicode-scalar"],
+        features,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities_match_build() {
+        let caps = capabilities();
+        assert!(caps.supports_compression("deflate"));
+        assert!(!caps.supports_compression("zstd"));
+        assert!(caps.supports_sync_extension("chunked-messages"));
+        assert!(caps.supports_sync_extension("versioned-messages"));
+        assert!(caps.supports_storage_extension("history-fence"));
+        assert!(caps.supports_storage_extension("encoded-chunks"));
+        assert!(caps.supports_storage_extension("document-headers"));
+        assert_eq!(caps.features.contains(&"rayon"), cfg!(feature = "rayon"));
+    }
+}
@@ -2,6 +2,7 @@ use std::{borrow::Cow, num::NonZeroU64};
 
 use crate::{
     columnar::Key as StoredKey,
+    extra_bytes::ExtraBytes,
     storage::{
         change::{Unverified, Verified},
         parse, Change as StoredChange, ChangeOp, Chunk, Compressed, ReadChangeOpError,
@@ -104,6 +105,10 @@ impl Change {
         }
     }
 
+    pub(crate) fn checksum_valid(&self) -> bool {
+        self.stored.checksum_valid()
+    }
+
     pub fn raw_bytes(&self) -> &[u8] {
         self.stored.bytes()
     }
@@ -112,15 +117,55 @@ impl Change {
         self.stored.iter_ops()
     }
 
+    /// The data stored with [`crate::transaction::CommitOptions::with_extra_bytes`].
     pub fn extra_bytes(&self) -> &[u8] {
+        ExtraBytes::parse(self.stored.extra_bytes()).data
+    }
+
+    /// The extra bytes as they are stored, holding both the application data and the signature.
+    pub(crate) fn raw_extra_bytes(&self) -> &[u8] {
         self.stored.extra_bytes()
     }
 
+    /// The signature of this change, if it was signed, see [`crate::Automerge::set_signer`].
+    pub fn signature(&self) -> Option<&[u8]> {
+        ExtraBytes::parse(self.stored.extra_bytes())
+            .signature
+            .map(|(_, signature)| signature)
+    }
+
+    /// The bytes covered by the signature of this change.
+    ///
+    /// This is the body of the change, including the data stored with
+    /// [`crate::transaction::CommitOptions::with_extra_bytes`] but not the signature itself.
+    /// See [`crate::Automerge::set_signer`].
+    pub fn signed_bytes(&self) -> &[u8] {
+        let body = self.stored.body_bytes();
+        match ExtraBytes::parse(self.stored.extra_bytes()).signature {
+            Some((offset, _)) => {
+                let extra_start = body.len() - self.stored.extra_bytes().len();
+                &body[..extra_start + offset]
+            }
+            None => body,
+        }
+    }
+
     // TODO replace all uses of this with TryFrom<&[u8]>
     pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, LoadError> {
         Self::try_from(&bytes[..])
     }
 
+    /// Parse a change produced by the JavaScript implementation, e.g. one of the changes returned
+    /// by `Automerge.getChanges` in `automerge@1.x`, compressed or not.
+    ///
+    /// Version 1 of the JavaScript library writes changes in the same binary format as this
+    /// library so they can be applied as they are. When migrating a document the actors of the
+    /// JavaScript peers often need replacing as well, see
+    /// [`crate::Automerge::apply_changes_with_actor_map`].
+    pub fn from_js_bytes(bytes: &[u8]) -> Result<Self, LoadError> {
+        Self::try_from(bytes)
+    }
+
     pub fn decode(&self) -> crate::ExpandedChange {
         crate::ExpandedChange::from(self)
     }
@@ -314,7 +359,7 @@ impl From<&Change> for crate::ExpandedChange {
             deps: c.deps().to_vec(),
             seq: c.seq(),
             start_op: c.start_op(),
-            extra_bytes: c.extra_bytes().to_vec(),
+            extra_bytes: c.raw_extra_bytes().to_vec(),
             message: c.message().cloned(),
         }
     }
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::clock::Clock;
+use crate::{Automerge, AutomergeError, Change, ChangeHash};
+
+/// The graph formed by the changes in a document and their dependencies.
+///
+/// Created by [`Automerge::change_graph`]. Each node is a change and there is an edge from each
+/// change to each of its dependencies.
+#[derive(Debug)]
+pub struct ChangeGraph<'a> {
+    doc: &'a Automerge,
+    dependents: HashMap<ChangeHash, Vec<ChangeHash>>,
+}
+
+impl<'a> ChangeGraph<'a> {
+    pub(crate) fn new(doc: &'a Automerge) -> Self {
+        let mut dependents: HashMap<ChangeHash, Vec<ChangeHash>> = HashMap::new();
+        for change in &doc.history {
+            for dep in change.deps() {
+                dependents.entry(*dep).or_default().push(change.hash());
+            }
+        }
+        Self { doc, dependents }
+    }
+
+    /// The number of changes in the graph.
+    pub fn len(&self) -> usize {
+        self.doc.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.doc.history.is_empty()
+    }
+
+    /// Whether `hash` is a change in the graph.
+    pub fn contains(&self, hash: &ChangeHash) -> bool {
+        self.doc.history_index.contains_key(hash)
+    }
+
+    /// The change with this hash.
+    pub fn change(&self, hash: &ChangeHash) -> Option<&'a Change> {
+        self.doc.get_change_by_hash(hash)
+    }
+
+    /// The hashes of every change, ordered so that each change comes after its dependencies.
+    pub fn topological_order(&self) -> impl Iterator<Item = ChangeHash> + 'a {
+        self.doc.history.iter().map(|c| c.hash())
+    }
+
+    /// Every edge in the graph, as a pair of a change and one of its dependencies.
+    pub fn edges(&self) -> impl Iterator<Item = (ChangeHash, ChangeHash)> + 'a {
+        self.doc
+            .history
+            .iter()
+            .flat_map(|c| c.deps().iter().map(move |dep| (c.hash(), *dep)))
+    }
+
+    /// The changes with no dependents.
+    pub fn heads(&self) -> Vec<ChangeHash> {
+        self.doc.get_heads()
+    }
+
+    /// The dependencies of `hash`.
+    pub fn deps(&self, hash: &ChangeHash) -> Result<&'a [ChangeHash], AutomergeError> {
+        self.change(hash)
+            .map(|c| c.deps())
+            .ok_or(AutomergeError::MissingHash(*hash))
+    }
+
+    /// The changes which depend directly on `hash`.
+    pub fn dependents(&self, hash: &ChangeHash) -> Result<&[ChangeHash], AutomergeError> {
+        if !self.contains(hash) {
+            return Err(AutomergeError::MissingHash(*hash));
+        }
+        Ok(self
+            .dependents
+            .get(hash)
+            .map(|d| d.as_slice())
+            .unwrap_or(&[]))
+    }
+
+    /// Every change which `hash` depends on, directly or indirectly, in topological order.
+    pub fn ancestors(&self, hash: &ChangeHash) -> Result<Vec<ChangeHash>, AutomergeError> {
+        let mut ancestors = self.ancestors_inclusive(hash)?;
+        ancestors.remove(hash);
+        Ok(self.sorted(ancestors))
+    }
+
+    /// The most recent changes which are ancestors of both `a` and `b`, where a change counts as
+    /// its own ancestor.
+    ///
+    /// There can be several such changes if the history has concurrent branches which were later
+    /// merged, or none if `a` and `b` have no history in common.
+    pub fn common_ancestors(
+        &self,
+        a: &ChangeHash,
+        b: &ChangeHash,
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
+        let of_a = self.ancestors_inclusive(a)?;
+        let of_b = self.ancestors_inclusive(b)?;
+        let common = of_a.intersection(&of_b).copied().collect::<HashSet<_>>();
+        // `common` contains the ancestors of all its members, so a change in `common` is an
+        // ancestor of another one exactly when it is a direct dependency of one
+        let superseded = common
+            .iter()
+            .flat_map(|hash| self.doc.history[self.doc.history_index[hash]].deps())
+            .copied()
+            .collect::<HashSet<_>>();
+        Ok(self.sorted(common.difference(&superseded).copied().collect()))
+    }
+
+    fn ancestors_inclusive(
+        &self,
+        hash: &ChangeHash,
+    ) -> Result<HashSet<ChangeHash>, AutomergeError> {
+        if !self.contains(hash) {
+            return Err(AutomergeError::MissingHash(*hash));
+        }
+        let mut seen = HashSet::new();
+        let mut to_visit = vec![*hash];
+        while let Some(hash) = to_visit.pop() {
+            if seen.insert(hash) {
+                to_visit.extend(self.doc.history[self.doc.history_index[&hash]].deps());
+            }
+        }
+        Ok(seen)
+    }
+
+    fn sorted(&self, hashes: HashSet<ChangeHash>) -> Vec<ChangeHash> {
+        let mut indexes = hashes
+            .iter()
+            .map(|h| self.doc.history_index[h])
+            .collect::<Vec<_>>();
+        indexes.sort_unstable();
+        indexes
+            .into_iter()
+            .map(|i| self.doc.history[i].hash())
+            .collect()
+    }
+}
+
+impl Automerge {
+    /// Whether `a` is `b` or one of the changes `b` depends on, directly or indirectly.
+    ///
+    /// This looks at the vector clock of `b`, so unlike [`ChangeGraph::ancestors`] it doesn't
+    /// walk the history.
+    pub fn is_ancestor(&self, a: &ChangeHash, b: &ChangeHash) -> Result<bool, AutomergeError> {
+        let change = self
+            .get_change_by_hash(a)
+            .ok_or(AutomergeError::MissingHash(*a))?;
+        let clock = self.clock_at(&[*b])?;
+        Ok(self.clock_contains(&clock, change))
+    }
+
+    /// Whether `x` and `y` are the heads of the same version of the document, i.e. include
+    /// exactly the same changes. This is true even if one of them lists a change which is an
+    /// ancestor of another of its changes.
+    pub fn heads_equal(&self, x: &[ChangeHash], y: &[ChangeHash]) -> Result<bool, AutomergeError> {
+        Ok(self.clock_at(x)? == self.clock_at(y)?)
+    }
+
+    /// The hashes of the changes included in the version at heads `x` but not in that at heads
+    /// `y`, and of those in `y` but not in `x`, each in topological order.
+    pub fn heads_diff(
+        &self,
+        x: &[ChangeHash],
+        y: &[ChangeHash],
+    ) -> Result<(Vec<ChangeHash>, Vec<ChangeHash>), AutomergeError> {
+        let clock_x = self.clock_at(x)?;
+        let clock_y = self.clock_at(y)?;
+        let mut only_x = Vec::new();
+        let mut only_y = Vec::new();
+        for change in &self.history {
+            match (
+                self.clock_contains(&clock_x, change),
+                self.clock_contains(&clock_y, change),
+            ) {
+                (true, false) => only_x.push(change.hash()),
+                (false, true) => only_y.push(change.hash()),
+                _ => {}
+            }
+        }
+        Ok((only_x, only_y))
+    }
+
+    fn clock_contains(&self, clock: &Clock, change: &Change) -> bool {
+        self.ops
+            .m
+            .actors
+            .lookup(change.actor_id())
+            .and_then(|actor| clock.get_for_actor(&actor))
+            .map_or(false, |data| data.seq >= change.seq())
+    }
+}
@@ -71,6 +71,11 @@ impl Clock {
         self.0.get(actor_index)
     }
 
+    /// The data recorded for each actor, in no particular order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&usize, &ClockData)> {
+        self.0.iter()
+    }
+
     pub(crate) fn merge(&mut self, other: &Self) {
         for (actor, data) in &other.0 {
             self.include(*actor, *data);
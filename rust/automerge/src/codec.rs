@@ -0,0 +1,18 @@
+/// Transforms the bytes of each chunk of a saved document, for example to encrypt or compress
+/// them, see [`crate::Automerge::save_with_codec`].
+///
+/// Each chunk is encoded separately and stored in a chunk of its own whose header records the
+/// codec's [`ChunkCodec::id`], so loading with a different codec is an error rather than
+/// producing garbage, and chunks saved without a codec, such as those appended by
+/// [`crate::Automerge::save_incremental`], can be mixed in.
+pub trait ChunkCodec {
+    /// Identifies the codec and whatever configuration it needs to decode, e.g. which key it
+    /// encrypts with.
+    fn id(&self) -> u64;
+
+    /// Encode the bytes of a chunk.
+    fn encode(&self, chunk: &[u8]) -> Vec<u8>;
+
+    /// Decode the bytes produced by [`ChunkCodec::encode`].
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>;
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::exid::ExId;
+use crate::transaction::TransactionInner;
+use crate::{ActorId, Automerge, Change, ChangeHash, OpType, Prop};
+
+/// Called with a transaction which is about to be committed, see
+/// [`Automerge::on_before_commit`].
+pub type BeforeCommitHook = Arc<dyn Fn(&PendingCommit<'_>) + Send + Sync>;
+
+/// Called with the change a transaction was committed as, see [`Automerge::on_commit`].
+pub type CommitHook = Arc<dyn Fn(&Change) + Send + Sync>;
+
+/// Identifies a hook registered with a document, to remove it with
+/// [`Automerge::remove_commit_hook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HookId(u64);
+
+/// The hooks registered with a document.
+#[derive(Clone, Default)]
+pub(crate) struct CommitHooks {
+    next_id: u64,
+    before: Vec<(HookId, BeforeCommitHook)>,
+    after: Vec<(HookId, CommitHook)>,
+}
+
+impl fmt::Debug for CommitHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommitHooks")
+            .field("before", &self.before.len())
+            .field("after", &self.after.len())
+            .finish()
+    }
+}
+
+impl CommitHooks {
+    fn next_id(&mut self) -> HookId {
+        self.next_id += 1;
+        HookId(self.next_id)
+    }
+
+    pub(crate) fn run_before(&self, pending: &PendingCommit<'_>) {
+        for (_, hook) in &self.before {
+            hook(pending);
+        }
+    }
+
+    pub(crate) fn run_after(&self, change: &Change) {
+        for (_, hook) in &self.after {
+            hook(change);
+        }
+    }
+}
+
+/// A transaction which is about to be committed, passed to the hooks registered with
+/// [`Automerge::on_before_commit`].
+pub struct PendingCommit<'a> {
+    pub(crate) doc: &'a Automerge,
+    pub(crate) tx: &'a TransactionInner,
+}
+
+impl<'a> fmt::Debug for PendingCommit<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PendingCommit")
+            .field("actor", self.actor())
+            .field("seq", &self.seq())
+            .field("ops", &self.len())
+            .finish()
+    }
+}
+
+/// An op of a [`PendingCommit`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingOp {
+    /// The id of the op, which is also the id of the object it creates or the element it inserts
+    pub id: ExId,
+    /// The object the op applies to
+    pub obj: ExId,
+    /// The key or index in the object the op applies to
+    pub prop: Prop,
+    /// What the op does
+    pub action: OpType,
+    /// Whether the op inserts a new element rather than updating an existing one
+    pub insert: bool,
+}
+
+impl<'a> PendingCommit<'a> {
+    /// The actor the change is made by.
+    pub fn actor(&self) -> &ActorId {
+        &self.doc.ops.m.actors[self.tx.actor]
+    }
+
+    /// The sequence number of the change.
+    pub fn seq(&self) -> u64 {
+        self.tx.seq
+    }
+
+    /// The message of the change, if it has one.
+    pub fn message(&self) -> Option<&str> {
+        self.tx.message.as_deref()
+    }
+
+    /// The time of the change, in milliseconds since the epoch.
+    pub fn time(&self) -> i64 {
+        self.tx.time
+    }
+
+    /// The heads the change depends on.
+    pub fn deps(&self) -> &[ChangeHash] {
+        &self.tx.deps
+    }
+
+    /// The number of ops in the change.
+    pub fn len(&self) -> usize {
+        self.tx.pending_ops()
+    }
+
+    /// Whether the change has no ops.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The ops of the change in the order they were made.
+    pub fn ops(&self) -> impl Iterator<Item = PendingOp> + '_ {
+        self.tx
+            .operations
+            .iter()
+            .map(move |(obj, prop, op)| PendingOp {
+                id: self.doc.id_to_exid(op.id),
+                obj: self.doc.id_to_exid(obj.0),
+                prop: prop.clone(),
+                action: op.action.clone(),
+                insert: op.insert,
+            })
+    }
+}
+
+impl Automerge {
+    /// Call `hook` each time a transaction on this document is about to be committed, with the
+    /// ops it made, e.g. to record metrics. Returns an id to remove the hook with
+    /// [`Self::remove_commit_hook`].
+    ///
+    /// Hooks are local to a document: they are not saved, and a [`Self::fork`] of the document
+    /// doesn't have them. They run for changes made by this document, not for changes it
+    /// receives, which an [`crate::OpObserver`] sees. A hook is called while the document is
+    /// borrowed, so it can't change the document; it should send what it needs elsewhere
+    /// instead, e.g. down a channel.
+    pub fn on_before_commit<F>(&mut self, hook: F) -> HookId
+    where
+        F: Fn(&PendingCommit<'_>) + Send + Sync + 'static,
+    {
+        let id = self.commit_hooks.next_id();
+        self.commit_hooks.before.push((id, Arc::new(hook)));
+        id
+    }
+
+    /// Call `hook` with the change each transaction on this document is committed as, once it
+    /// is part of the document, e.g. to save it or broadcast it to peers. Returns an id to
+    /// remove the hook with [`Self::remove_commit_hook`]. See [`Self::on_before_commit`] for
+    /// what hooks can do.
+    pub fn on_commit<F>(&mut self, hook: F) -> HookId
+    where
+        F: Fn(&Change) + Send + Sync + 'static,
+    {
+        let id = self.commit_hooks.next_id();
+        self.commit_hooks.after.push((id, Arc::new(hook)));
+        id
+    }
+
+    /// Remove a hook registered with [`Self::on_before_commit`] or [`Self::on_commit`],
+    /// returning whether there was such a hook.
+    pub fn remove_commit_hook(&mut self, id: HookId) -> bool {
+        let hooks = &mut self.commit_hooks;
+        let before = hooks.before.len() + hooks.after.len();
+        hooks.before.retain(|(h, _)| *h != id);
+        hooks.after.retain(|(h, _)| *h != id);
+        hooks.before.len() + hooks.after.len() < before
+    }
+}
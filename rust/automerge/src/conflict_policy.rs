@@ -0,0 +1,327 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::exid::ExId;
+use crate::schema::SchemaType;
+use crate::types::ObjId;
+use crate::{OpObserver, Parents, Prop, ScalarValue, Value};
+
+/// Chooses the custom winner of a set of conflicting values, see [`ConflictPolicy::Custom`].
+pub type ConflictResolver = Arc<dyn Fn(&[(Value<'_>, ExId)]) -> Option<usize> + Send + Sync>;
+
+/// How [`crate::Automerge::get`] chooses between conflicting values of a property.
+///
+/// Without a policy the value written by the op with the greatest id wins, that is the op with
+/// the greatest counter and, between ops with the same counter, the greatest actor. This is the
+/// last of the values returned by [`crate::Automerge::get_all`]. A policy changes
+/// which of the values `get`, the range and `values` iterators, materialization and the puts
+/// reported to an [`OpObserver`] by `apply_changes` return: every replica with the same
+/// policies picks the same winner, and [`crate::Automerge::get_all`] still returns all of the
+/// conflicting values. Policies are local to a document, they are not saved or synced.
+///
+/// Ties, and values a policy can't compare, are resolved as if there were no policy.
+#[derive(Clone)]
+pub enum ConflictPolicy {
+    /// The greatest value wins. Numbers of any type compare by value, and otherwise booleans are
+    /// less than numbers, which are less than strings, which are less than bytes. Objects and
+    /// nulls never win.
+    MaxWins,
+    /// The least value wins, ordered as for [`Self::MaxWins`].
+    MinWins,
+    /// The value written by the change with the latest timestamp wins. Values written by the
+    /// transaction in progress are later than any committed change.
+    LastWriterWins,
+    /// The value at the index returned by the closure wins. The values are in the order returned
+    /// by `get_all`; returning `None`, or an index which is out of range, falls back to the
+    /// default winner.
+    Custom(ConflictResolver),
+}
+
+impl fmt::Debug for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConflictPolicy::MaxWins => f.write_str("MaxWins"),
+            ConflictPolicy::MinWins => f.write_str("MinWins"),
+            ConflictPolicy::LastWriterWins => f.write_str("LastWriterWins"),
+            ConflictPolicy::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+impl ConflictPolicy {
+    /// The index in `values` of the winner, or `None` for the default winner.
+    ///
+    /// `timestamp` returns the timestamp of the change which created a value.
+    pub(crate) fn choose<F>(&self, values: &[(Value<'_>, ExId)], timestamp: F) -> Option<usize>
+    where
+        F: Fn(&ExId) -> i64,
+    {
+        // the default winner is the last value, so we start from it and only replace the best
+        // value so far with one which is strictly better
+        let best = |candidates: Vec<usize>, better: &dyn Fn(usize, usize) -> bool| {
+            candidates
+                .into_iter()
+                .rev()
+                .fold(None, |best: Option<usize>, i| match best {
+                    Some(b) if !better(i, b) => Some(b),
+                    _ => Some(i),
+                })
+        };
+        match self {
+            ConflictPolicy::MaxWins | ConflictPolicy::MinWins => {
+                let want = match self {
+                    ConflictPolicy::MaxWins => Ordering::Greater,
+                    _ => Ordering::Less,
+                };
+                let candidates = (0..values.len())
+                    .filter(|i| Rank::of(&values[*i].0).is_some())
+                    .collect();
+                best(candidates, &|i, b| {
+                    Rank::of(&values[i].0).partial_cmp(&Rank::of(&values[b].0)) == Some(want)
+                })
+            }
+            ConflictPolicy::LastWriterWins => best((0..values.len()).collect(), &|i, b| {
+                timestamp(&values[i].1) > timestamp(&values[b].1)
+            }),
+            ConflictPolicy::Custom(resolve) => resolve(values).filter(|i| *i < values.len()),
+        }
+    }
+}
+
+/// The order used by [`ConflictPolicy::MaxWins`] and [`ConflictPolicy::MinWins`].
+#[derive(Debug, PartialEq, PartialOrd)]
+enum Rank<'a> {
+    Boolean(bool),
+    Number(Number),
+    Str(&'a str),
+    Bytes(&'a [u8]),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => a.partial_cmp(b),
+            (a, b) => a.as_f64().partial_cmp(&b.as_f64()),
+        }
+    }
+}
+
+impl Number {
+    fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+}
+
+impl<'a> Rank<'a> {
+    fn of(value: &'a Value<'_>) -> Option<Self> {
+        let scalar = match value {
+            Value::Scalar(s) => s.as_ref(),
+            Value::Object(_) => return None,
+        };
+        Some(match scalar {
+            ScalarValue::Boolean(b) => Rank::Boolean(*b),
+            ScalarValue::Int(i) | ScalarValue::Timestamp(i) => {
+                Rank::Number(Number::Int(*i as i128))
+            }
+            ScalarValue::Uint(u) => Rank::Number(Number::Int(*u as i128)),
+            ScalarValue::Counter(c) => Rank::Number(Number::Int(i64::from(c) as i128)),
+            ScalarValue::F64(f) if !f.is_nan() => Rank::Number(Number::Float(*f)),
+            ScalarValue::Str(s) => Rank::Str(s.as_str()),
+            ScalarValue::Bytes(b) => Rank::Bytes(b.as_slice()),
+            _ => return None,
+        })
+    }
+}
+
+/// The policies registered with a document.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConflictPolicies {
+    keys: HashMap<(ObjId, String), ConflictPolicy>,
+    objects: HashMap<ObjId, ConflictPolicy>,
+    types: HashMap<SchemaType, ConflictPolicy>,
+}
+
+impl ConflictPolicies {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.objects.is_empty() && self.types.is_empty()
+    }
+
+    pub(crate) fn set_key(&mut self, obj: ObjId, key: String, policy: Option<ConflictPolicy>) {
+        match policy {
+            Some(policy) => self.keys.insert((obj, key), policy),
+            None => self.keys.remove(&(obj, key)),
+        };
+    }
+
+    pub(crate) fn set_object(&mut self, obj: ObjId, policy: Option<ConflictPolicy>) {
+        match policy {
+            Some(policy) => self.objects.insert(obj, policy),
+            None => self.objects.remove(&obj),
+        };
+    }
+
+    pub(crate) fn set_type(&mut self, ty: SchemaType, policy: Option<ConflictPolicy>) {
+        match policy {
+            Some(policy) => self.types.insert(ty, policy),
+            None => self.types.remove(&ty),
+        };
+    }
+
+    /// The policy for `key` of `obj`, or for every property of `obj` if `key` has none, or for
+    /// the type of `values` if neither has one and all of them are of the same type.
+    pub(crate) fn get(
+        &self,
+        obj: ObjId,
+        key: Option<&str>,
+        values: &[(Value<'_>, ExId)],
+    ) -> Option<&ConflictPolicy> {
+        key.and_then(|k| self.keys.get(&(obj, k.to_string())))
+            .or_else(|| self.objects.get(&obj))
+            .or_else(|| {
+                let ty = SchemaType::of(&values.first()?.0)?;
+                if values[1..]
+                    .iter()
+                    .all(|(v, _)| SchemaType::of(v) == Some(ty))
+                {
+                    self.types.get(&ty)
+                } else {
+                    None
+                }
+            })
+    }
+}
+
+/// An op reported while [`PolicyBuffer`] was observing.
+#[derive(Debug, Clone)]
+enum BufferedOp {
+    Insert(ExId, usize, (Value<'static>, ExId)),
+    SpliceText(ExId, usize, String),
+    Put(ExId, Prop, (Value<'static>, ExId), bool),
+    Increment(ExId, Prop, (i64, ExId)),
+    Delete(ExId, Prop),
+}
+
+/// Holds back the ops reported while applying changes to a document with conflict policies,
+/// so that the value of each conflicting put can be chosen once the changes are applied, see
+/// [`crate::Automerge::replay_with_conflict_policies`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PolicyBuffer {
+    ops: Vec<BufferedOp>,
+}
+
+impl PolicyBuffer {
+    /// Report the buffered ops to `observer`. `parents` gives the parents of an object and
+    /// `resolve` the value of a conflicting put, both of which return `None` for an object
+    /// which is no longer in the document.
+    pub(crate) fn replay<'a, Obs, P, R>(self, observer: &mut Obs, parents: P, resolve: R)
+    where
+        Obs: OpObserver,
+        P: Fn(&ExId) -> Option<Parents<'a>>,
+        R: Fn(&ExId, &Prop, (Value<'static>, ExId)) -> (Value<'static>, ExId),
+    {
+        for op in self.ops {
+            match op {
+                BufferedOp::Insert(obj, index, value) => {
+                    if let Some(p) = parents(&obj) {
+                        observer.insert(p, obj, index, value);
+                    }
+                }
+                BufferedOp::SpliceText(obj, index, value) => {
+                    if let Some(p) = parents(&obj) {
+                        observer.splice_text(p, obj, index, &value);
+                    }
+                }
+                BufferedOp::Put(obj, prop, value, conflict) => {
+                    if let Some(p) = parents(&obj) {
+                        let value = if conflict {
+                            resolve(&obj, &prop, value)
+                        } else {
+                            value
+                        };
+                        observer.put(p, obj, prop, value, conflict);
+                    }
+                }
+                BufferedOp::Increment(obj, prop, value) => {
+                    if let Some(p) = parents(&obj) {
+                        observer.increment(p, obj, prop, value);
+                    }
+                }
+                BufferedOp::Delete(obj, prop) => {
+                    if let Some(p) = parents(&obj) {
+                        observer.delete(p, obj, prop);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl OpObserver for PolicyBuffer {
+    fn insert(
+        &mut self,
+        _parents: Parents<'_>,
+        objid: ExId,
+        index: usize,
+        (value, id): (Value<'_>, ExId),
+    ) {
+        self.ops
+            .push(BufferedOp::Insert(objid, index, (value.into_owned(), id)));
+    }
+
+    fn splice_text(&mut self, _parents: Parents<'_>, objid: ExId, index: usize, value: &str) {
+        self.ops
+            .push(BufferedOp::SpliceText(objid, index, value.to_string()));
+    }
+
+    fn put(
+        &mut self,
+        _parents: Parents<'_>,
+        objid: ExId,
+        prop: Prop,
+        (value, id): (Value<'_>, ExId),
+        conflict: bool,
+    ) {
+        self.ops.push(BufferedOp::Put(
+            objid,
+            prop,
+            (value.into_owned(), id),
+            conflict,
+        ));
+    }
+
+    fn increment(
+        &mut self,
+        _parents: Parents<'_>,
+        objid: ExId,
+        prop: Prop,
+        tagged_value: (i64, ExId),
+    ) {
+        self.ops
+            .push(BufferedOp::Increment(objid, prop, tagged_value));
+    }
+
+    fn delete(&mut self, _parents: Parents<'_>, objid: ExId, prop: Prop) {
+        self.ops.push(BufferedOp::Delete(objid, prop));
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.ops.extend(other.ops.iter().cloned());
+    }
+}
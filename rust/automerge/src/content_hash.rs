@@ -0,0 +1,135 @@
+use sha2::{Digest, Sha256};
+
+use crate::exid::ExId;
+use crate::{Automerge, AutomergeError, ChangeHash, ObjType, ScalarValue, Value, ROOT};
+
+// Tags which start the encoding of each kind of value, so that no two different values
+// encode to the same bytes
+const MAP: u8 = 0;
+const LIST: u8 = 1;
+const TEXT: u8 = 2;
+const TABLE: u8 = 3;
+const BYTES: u8 = 4;
+const STR: u8 = 5;
+const INT: u8 = 6;
+const UINT: u8 = 7;
+const F64: u8 = 8;
+const COUNTER: u8 = 9;
+const TIMESTAMP: u8 = 10;
+const BOOLEAN: u8 = 11;
+const UNKNOWN: u8 = 12;
+const NULL: u8 = 13;
+
+impl Automerge {
+    /// A SHA-256 digest of the state of the document as of `heads`, or of its current state if
+    /// `heads` is empty.
+    ///
+    /// The digest depends only on the values in the document, not on how they got there, so two
+    /// replicas which have converged to the same value have the same digest however their
+    /// histories differ, and comparing digests is much cheaper than comparing saved documents.
+    /// Object IDs, the actors which made changes and values which lost a conflict are not part
+    /// of the digest. Conflicts are resolved with the default winner rather than the document's
+    /// conflict policies, which are local to each replica.
+    pub fn content_hash(&self, heads: &[ChangeHash]) -> Result<[u8; 32], AutomergeError> {
+        let heads = if heads.is_empty() {
+            self.get_heads()
+        } else {
+            self.clock_at(heads)?;
+            heads.to_vec()
+        };
+        let mut hasher = Sha256::new();
+        self.hash_object(&mut hasher, &ROOT, ObjType::Map, &heads)?;
+        Ok(hasher.finalize().into())
+    }
+
+    fn hash_object(
+        &self,
+        hasher: &mut Sha256,
+        obj: &ExId,
+        obj_type: ObjType,
+        heads: &[ChangeHash],
+    ) -> Result<(), AutomergeError> {
+        match obj_type {
+            ObjType::Map | ObjType::Table => {
+                hasher.update([if obj_type == ObjType::Map { MAP } else { TABLE }]);
+                let entries = self.map_range_at(obj, .., heads).collect::<Vec<_>>();
+                hash_len(hasher, entries.len());
+                for (key, value, id) in entries {
+                    hash_bytes(hasher, key.as_bytes());
+                    self.hash_value(hasher, value, &id, heads)?;
+                }
+            }
+            ObjType::List => {
+                hasher.update([LIST]);
+                let elems = self.list_range_at(obj, .., heads).collect::<Vec<_>>();
+                hash_len(hasher, elems.len());
+                for (_, value, id) in elems {
+                    self.hash_value(hasher, value, &id, heads)?;
+                }
+            }
+            ObjType::Text => {
+                hasher.update([TEXT]);
+                hash_bytes(hasher, self.text_at(obj, heads)?.as_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    fn hash_value(
+        &self,
+        hasher: &mut Sha256,
+        value: Value<'_>,
+        id: &ExId,
+        heads: &[ChangeHash],
+    ) -> Result<(), AutomergeError> {
+        match value {
+            Value::Object(obj_type) => self.hash_object(hasher, id, obj_type, heads)?,
+            Value::Scalar(s) => match s.as_ref() {
+                ScalarValue::Bytes(b) => {
+                    hasher.update([BYTES]);
+                    hash_bytes(hasher, b);
+                }
+                ScalarValue::Str(s) => {
+                    hasher.update([STR]);
+                    hash_bytes(hasher, s.as_bytes());
+                }
+                ScalarValue::Int(i) => {
+                    hasher.update([INT]);
+                    hasher.update(i.to_be_bytes());
+                }
+                ScalarValue::Uint(u) => {
+                    hasher.update([UINT]);
+                    hasher.update(u.to_be_bytes());
+                }
+                ScalarValue::F64(f) => {
+                    hasher.update([F64]);
+                    hasher.update(f.to_bits().to_be_bytes());
+                }
+                ScalarValue::Counter(c) => {
+                    hasher.update([COUNTER]);
+                    hasher.update(i64::from(c).to_be_bytes());
+                }
+                ScalarValue::Timestamp(t) => {
+                    hasher.update([TIMESTAMP]);
+                    hasher.update(t.to_be_bytes());
+                }
+                ScalarValue::Boolean(b) => hasher.update([BOOLEAN, *b as u8]),
+                ScalarValue::Unknown { type_code, bytes } => {
+                    hasher.update([UNKNOWN, *type_code]);
+                    hash_bytes(hasher, bytes);
+                }
+                ScalarValue::Null => hasher.update([NULL]),
+            },
+        }
+        Ok(())
+    }
+}
+
+fn hash_len(hasher: &mut Sha256, len: usize) {
+    hasher.update((len as u64).to_be_bytes());
+}
+
+fn hash_bytes(hasher: &mut Sha256, bytes: &[u8]) {
+    hash_len(hasher, bytes.len());
+    hasher.update(bytes);
+}
@@ -0,0 +1,57 @@
+use crate::exid::ExId;
+use crate::types::{ElemId, Key};
+use crate::{Automerge, AutomergeError};
+
+/// A position in a list or text object which moves with the elements around it, returned by
+/// [`Automerge::cursor`].
+///
+/// An index into a sequence is only meaningful for the version of the document it was taken
+/// from: once elements are inserted or deleted before it, it refers to a different place. A cursor
+/// remembers the element just before the position instead, so [`Automerge::cursor_position`]
+/// keeps finding the same place as the document changes, including when that element has since
+/// been deleted. Cursors only contain ids, so they can be sent to other peers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cursor {
+    obj: ExId,
+    /// The element before the position, or `None` for the start of the sequence
+    after: Option<ExId>,
+}
+
+impl Cursor {
+    pub(crate) fn new(obj: ExId, after: Option<ExId>) -> Self {
+        Self { obj, after }
+    }
+
+    /// The sequence the cursor is in.
+    pub fn obj(&self) -> &ExId {
+        &self.obj
+    }
+}
+
+impl Automerge {
+    /// A cursor at `index` of the list or text object `obj`, which may be equal to its length.
+    pub fn cursor<O: AsRef<ExId>>(&self, obj: O, index: usize) -> Result<Cursor, AutomergeError> {
+        let obj = obj.as_ref();
+        let obj_id = self.exid_to_obj(obj)?;
+        let after = match index.checked_sub(1) {
+            None => None,
+            Some(prev) => match self.ops.search_nth(&obj_id, prev).key()? {
+                Key::Seq(elem) => Some(self.id_to_exid(elem.0)),
+                Key::Map(_) => return Err(AutomergeError::InvalidIndex(index)),
+            },
+        };
+        Ok(Cursor::new(obj.clone(), after))
+    }
+
+    /// The current index of `cursor`.
+    pub fn cursor_position(&self, cursor: &Cursor) -> Result<usize, AutomergeError> {
+        let obj = self.exid_to_obj(&cursor.obj)?;
+        match &cursor.after {
+            None => Ok(0),
+            Some(after) => self
+                .exid_to_opid(after)
+                .and_then(|elem| self.ops.index_after(&obj, ElemId(elem)))
+                .ok_or(AutomergeError::InvalidCursor),
+        }
+    }
+}
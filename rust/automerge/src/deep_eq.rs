@@ -0,0 +1,132 @@
+use crate::exid::ExId;
+use crate::{Automerge, AutomergeError, ChangeHash, ObjType, Value, ROOT};
+
+/// An object of a document as of some heads.
+#[derive(Clone, Copy)]
+struct At<'a> {
+    doc: &'a Automerge,
+    heads: &'a [ChangeHash],
+}
+
+impl Automerge {
+    /// Whether the object `obj_a` as of `heads_a` has the same value as the object `obj_b` as of
+    /// `heads_b`, comparing everything nested in them. Empty heads stand for the current state
+    /// of the document.
+    ///
+    /// Only values are compared, like [`Self::content_hash`], so objects built by different
+    /// actors or in a different order are equal if they end up with the same contents. Values
+    /// which lost a conflict are ignored. Unlike comparing content hashes this stops at the first
+    /// difference and doesn't hash anything.
+    pub fn deep_eq_at<A: AsRef<ExId>, B: AsRef<ExId>>(
+        &self,
+        obj_a: A,
+        heads_a: &[ChangeHash],
+        obj_b: B,
+        heads_b: &[ChangeHash],
+    ) -> Result<bool, AutomergeError> {
+        let heads_a = self.resolve_heads(heads_a)?;
+        let heads_b = self.resolve_heads(heads_b)?;
+        let type_a = self.object_type(obj_a.as_ref());
+        let type_b = self.object_type(obj_b.as_ref());
+        match (type_a, type_b) {
+            (Some(type_a), Some(type_b)) => Ok(type_a == type_b
+                && objects_eq(
+                    At {
+                        doc: self,
+                        heads: &heads_a,
+                    },
+                    obj_a.as_ref(),
+                    At {
+                        doc: self,
+                        heads: &heads_b,
+                    },
+                    obj_b.as_ref(),
+                    type_a,
+                )),
+            (None, _) => Err(AutomergeError::InvalidObjId(obj_a.as_ref().to_string())),
+            (_, None) => Err(AutomergeError::InvalidObjId(obj_b.as_ref().to_string())),
+        }
+    }
+
+    /// Whether this document currently has the same value as `other`, however their histories
+    /// differ. See [`Self::deep_eq_at`] for what is compared.
+    pub fn materialized_eq(&self, other: &Automerge) -> bool {
+        let heads = self.get_heads();
+        let other_heads = other.get_heads();
+        objects_eq(
+            At {
+                doc: self,
+                heads: &heads,
+            },
+            &ROOT,
+            At {
+                doc: other,
+                heads: &other_heads,
+            },
+            &ROOT,
+            ObjType::Map,
+        )
+    }
+
+    fn resolve_heads(&self, heads: &[ChangeHash]) -> Result<Vec<ChangeHash>, AutomergeError> {
+        if heads.is_empty() {
+            Ok(self.get_heads())
+        } else {
+            self.clock_at(heads)?;
+            Ok(heads.to_vec())
+        }
+    }
+}
+
+/// Whether `obj_a` and `obj_b`, which both have type `obj_type`, have the same contents.
+fn objects_eq(a: At<'_>, obj_a: &ExId, b: At<'_>, obj_b: &ExId, obj_type: ObjType) -> bool {
+    match obj_type {
+        ObjType::Map | ObjType::Table => {
+            let mut entries_a = a.doc.map_range_at(obj_a, .., a.heads);
+            let mut entries_b = b.doc.map_range_at(obj_b, .., b.heads);
+            loop {
+                match (entries_a.next(), entries_b.next()) {
+                    (None, None) => return true,
+                    (Some((key_a, value_a, id_a)), Some((key_b, value_b, id_b))) => {
+                        if key_a != key_b || !values_eq(a, value_a, &id_a, b, value_b, &id_b) {
+                            return false;
+                        }
+                    }
+                    _ => return false,
+                }
+            }
+        }
+        ObjType::List | ObjType::Text => {
+            let mut elems_a = a.doc.list_range_at(obj_a, .., a.heads);
+            let mut elems_b = b.doc.list_range_at(obj_b, .., b.heads);
+            loop {
+                match (elems_a.next(), elems_b.next()) {
+                    (None, None) => return true,
+                    (Some((_, value_a, id_a)), Some((_, value_b, id_b))) => {
+                        if !values_eq(a, value_a, &id_a, b, value_b, &id_b) {
+                            return false;
+                        }
+                    }
+                    _ => return false,
+                }
+            }
+        }
+    }
+}
+
+fn values_eq(
+    a: At<'_>,
+    value_a: Value<'_>,
+    id_a: &ExId,
+    b: At<'_>,
+    value_b: Value<'_>,
+    id_b: &ExId,
+) -> bool {
+    match (value_a, value_b) {
+        (Value::Object(type_a), Value::Object(type_b)) => {
+            type_a == type_b && objects_eq(a, id_a, b, id_b, type_a)
+        }
+        (Value::Scalar(scalar_a), Value::Scalar(scalar_b)) => scalar_a == scalar_b,
+        _ => false,
+    }
+}
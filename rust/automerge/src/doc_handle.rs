@@ -0,0 +1,239 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+use crate::{Automerge, AutomergeError, ChangeHash, Patch, VecOpObserver};
+
+/// What happened to a document shared by a [`DocHandle`], sent to its subscribers.
+#[derive(Debug, Clone)]
+pub enum HandleEvent {
+    /// Queued data was applied to the document
+    Changed {
+        /// The heads of the document once the data was applied
+        heads: Vec<ChangeHash>,
+        /// The patches made by applying the data
+        patches: Vec<Patch>,
+    },
+    /// Queued data could not be applied. Data queued before and after it is still applied.
+    Failed(Arc<AutomergeError>),
+    /// The worker panicked while applying queued data and has stopped. Nothing which was
+    /// waiting to be applied will be, and [`DocHandle::enqueue_changes`] and
+    /// [`DocHandle::flush`] return [`WorkerStopped`] from now on.
+    Stopped,
+}
+
+/// The worker of a [`DocHandle`] panicked and no longer applies queued data.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("the document handle's worker has stopped")]
+pub struct WorkerStopped;
+
+/// A document shared between threads, with a queue of changes applied on a background thread.
+///
+/// A GUI which receives changes from the network can [`Self::enqueue_changes`] them and carry on
+/// while a worker thread applies them, instead of blocking while a large merge is done. The
+/// worker takes everything queued since it last looked, applies it, and sends a
+/// [`HandleEvent`] with the new heads and the patches to every receiver returned by
+/// [`Self::subscribe`].
+///
+/// The document itself is behind a lock which [`Self::with_doc`] holds while its closure runs.
+/// The worker takes the lock once for each queued payload, so local edits and reads are never
+/// blocked for longer than applying one payload. Local edits are not sent to subscribers.
+///
+/// Dropping the handle applies anything still queued and stops the worker.
+#[derive(Debug)]
+pub struct DocHandle {
+    shared: Arc<Shared>,
+    queue: Option<Sender<Vec<u8>>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    doc: Mutex<Automerge>,
+    subscribers: Mutex<Vec<Sender<HandleEvent>>>,
+    pending: Mutex<Pending>,
+    applied: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    /// The number of queued payloads which have not been applied yet
+    count: usize,
+    /// Whether the worker has panicked
+    stopped: bool,
+}
+
+/// Marks the worker as stopped if it unwinds, so that nothing waits for it forever.
+struct StopOnPanic<'a>(&'a Shared);
+
+impl<'a> Drop for StopOnPanic<'a> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            {
+                let mut pending = self.0.pending();
+                pending.count = 0;
+                pending.stopped = true;
+            }
+            self.0.applied.notify_all();
+            self.0.publish(HandleEvent::Stopped);
+        }
+    }
+}
+
+impl Shared {
+    fn doc(&self) -> MutexGuard<'_, Automerge> {
+        // a panic in `with_doc` can't leave the document half changed, as transactions are
+        // rolled back when they are dropped
+        self.doc.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn publish(&self, event: HandleEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.retain(|s| s.send(event.clone()).is_ok());
+    }
+
+    fn pending(&self) -> MutexGuard<'_, Pending> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn finished(&self, count: usize) {
+        self.pending().count -= count;
+        self.applied.notify_all();
+    }
+
+    fn run(&self, queue: Receiver<Vec<u8>>) {
+        let _guard = StopOnPanic(self);
+        while let Ok(first) = queue.recv() {
+            let batch = std::iter::once(first)
+                .chain(queue.try_iter())
+                .collect::<Vec<_>>();
+            let count = batch.len();
+            let mut observer = VecOpObserver::default();
+            let mut changed = false;
+            for data in batch {
+                match self.doc().load_incremental_with(&data, Some(&mut observer)) {
+                    Ok(applied) => changed |= applied > 0,
+                    Err(e) => self.publish(HandleEvent::Failed(Arc::new(e))),
+                }
+            }
+            if changed {
+                let heads = self.doc().get_heads();
+                self.publish(HandleEvent::Changed {
+                    heads,
+                    patches: observer.take_patches(),
+                });
+            }
+            self.finished(count);
+        }
+    }
+}
+
+impl DocHandle {
+    /// Share `doc`, starting a worker thread to apply queued changes to it.
+    pub fn new(doc: Automerge) -> Self {
+        let shared = Arc::new(Shared {
+            doc: Mutex::new(doc),
+            subscribers: Mutex::new(Vec::new()),
+            pending: Mutex::new(Pending::default()),
+            applied: Condvar::new(),
+        });
+        let (queue, receiver) = mpsc::channel();
+        let worker = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || shared.run(receiver))
+        };
+        Self {
+            shared,
+            queue: Some(queue),
+            worker: Some(worker),
+        }
+    }
+
+    /// Queue `data` to be applied to the document by the worker, without waiting for it.
+    ///
+    /// `data` is anything [`Automerge::load_incremental`] accepts: changes, incremental saves or
+    /// a whole saved document.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`WorkerStopped`] if the worker has panicked, in which case `data` is dropped.
+    pub fn enqueue_changes(&self, data: Vec<u8>) -> Result<(), WorkerStopped> {
+        let mut pending = self.shared.pending();
+        if pending.stopped {
+            return Err(WorkerStopped);
+        }
+        // the queue is only taken by `stop`, which needs `&mut self`
+        if let Some(queue) = &self.queue {
+            // holding the lock means the worker can't decrement the count before it is incremented
+            queue.send(data).map_err(|_| WorkerStopped)?;
+            pending.count += 1;
+        }
+        Ok(())
+    }
+
+    /// A receiver of an event for each batch of queued data the worker applies from now on.
+    pub fn subscribe(&self) -> Receiver<HandleEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared
+            .subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(sender);
+        receiver
+    }
+
+    /// Run `f` with the document locked, to read it or make local changes.
+    pub fn with_doc<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Automerge) -> R,
+    {
+        f(&mut self.shared.doc())
+    }
+
+    /// The number of queued payloads the worker has not applied yet.
+    pub fn pending(&self) -> usize {
+        self.shared.pending().count
+    }
+
+    /// Wait until the worker has applied everything queued so far.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`WorkerStopped`] if the worker has panicked, whether before or while waiting.
+    pub fn flush(&self) -> Result<(), WorkerStopped> {
+        let mut pending = self.shared.pending();
+        while pending.count > 0 && !pending.stopped {
+            pending = self
+                .shared
+                .applied
+                .wait(pending)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        if pending.stopped {
+            Err(WorkerStopped)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Apply everything still queued, stop the worker and return the document.
+    pub fn into_inner(mut self) -> Automerge {
+        self.stop();
+        let mut doc = self.shared.doc();
+        std::mem::take(&mut *doc)
+    }
+
+    fn stop(&mut self) {
+        self.queue.take();
+        if let Some(worker) = self.worker.take() {
+            // a panic on the worker has already been published as `HandleEvent::Stopped`
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for DocHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
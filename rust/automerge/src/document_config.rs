@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+
+/// Settings which are chosen when a document is created and which every replica must agree on.
+///
+/// The settings are for applications whose semantics depend on choices made up front, for
+/// example whether text indexes count UTF-16 code units or whether a list should be treated as
+/// sorted. Automerge only interprets keys starting with `automerge.`, which are reserved for
+/// settings such as the [`crate::NumericMode`] of the document. The configuration is written at the start of the data
+/// returned by [`crate::Automerge::save`] and read back by [`crate::Automerge::load`]. Loading
+/// incremental data or merging a document whose configuration differs from ours fails with
+/// [`crate::AutomergeError::ConfigMismatch`], unless ours is a new document with no changes and no
+/// configuration, which adopts theirs.
+///
+/// The sync protocol does not exchange configuration, so replicas which only sync should be
+/// created from the same saved document or with the same configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentConfig {
+    settings: BTreeMap<String, String>,
+}
+
+impl DocumentConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a setting, replacing any existing setting with the same key.
+    pub fn with<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.settings.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.settings.get(key).map(|v| v.as_str())
+    }
+
+    /// Set the setting `key` to `value`, or remove it with `None`.
+    pub(crate) fn set(&mut self, key: &str, value: Option<&str>) {
+        match value {
+            Some(value) => self.settings.insert(key.to_string(), value.to_string()),
+            None => self.settings.remove(key),
+        };
+    }
+
+    /// The settings in ascending order of key.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.settings.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.settings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.settings.is_empty()
+    }
+}
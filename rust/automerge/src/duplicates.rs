@@ -0,0 +1,264 @@
+//! Finding and removing repeated large string and bytes values.
+//!
+//! Documents built from copy-pasted content often contain the same large value many times over,
+//! and every copy is stored in full. [`Automerge::duplicate_value_report`] finds such values and
+//! [`replace_duplicates`] rewrites them so that each is stored once, in a table object, with the
+//! original locations holding a reference into that table.
+//!
+//! A reference is a value of the type [`DUPLICATE_REF_TYPE_CODE`], which other implementations
+//! keep as a value of an unknown type, so it can't be mistaken for a string or bytes value put by
+//! the application. Use [`resolve_duplicate`] to read the value a reference stands for.
+//!
+//! Replacing a value is a change like any other, so the ops which put the copies stay in the
+//! history and a document saved with its history does not get smaller. The copies are no longer
+//! part of the current state, so they are not materialized or counted by
+//! [`crate::Automerge::memory_usage`] as values, and a copy of the document without the old
+//! history, from [`crate::Automerge::instantiate_template`] or saved beyond a history fence (see
+//! [`crate::Automerge::set_history_fence`]), stores each value once.
+use std::collections::HashMap;
+
+use sha2::{Digest, Sha256};
+
+use crate::exid::ExId;
+use crate::transaction::Transactable;
+use crate::{Automerge, AutomergeError, ObjType, Prop, ScalarValue, Value};
+
+/// The type code of the values [`replace_duplicates`] puts in place of a duplicated value. The
+/// bytes of such a value are the SHA-256 hash of the value, see [`DuplicateValue::reference`].
+pub const DUPLICATE_REF_TYPE_CODE: u8 = 15;
+
+/// A string or bytes value which appears in more than one place in a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateValue {
+    /// The repeated value.
+    pub value: ScalarValue,
+    /// The object and property of every occurrence of the value, in document order.
+    pub locations: Vec<(ExId, Prop)>,
+}
+
+impl DuplicateValue {
+    /// The length in bytes of a single copy of the value.
+    pub fn size(&self) -> usize {
+        match &self.value {
+            ScalarValue::Str(s) => s.len(),
+            ScalarValue::Bytes(b) => b.len(),
+            _ => 0,
+        }
+    }
+
+    /// The total length in bytes of every copy of the value.
+    pub fn total_cost(&self) -> usize {
+        self.size() * self.locations.len()
+    }
+
+    /// The number of bytes which would be saved by storing the value once.
+    pub fn wasted(&self) -> usize {
+        self.size() * (self.locations.len() - 1)
+    }
+
+    /// The key under which [`replace_duplicates`] stores the value: the hex encoded SHA-256 hash
+    /// of the value.
+    pub fn key(&self) -> String {
+        hex::encode(self.hash())
+    }
+
+    /// The value [`replace_duplicates`] puts in place of each copy of the value.
+    pub fn reference(&self) -> ScalarValue {
+        ScalarValue::Unknown {
+            type_code: DUPLICATE_REF_TYPE_CODE,
+            bytes: self.hash().to_vec(),
+        }
+    }
+
+    fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        match &self.value {
+            ScalarValue::Str(s) => {
+                hasher.update([0]);
+                hasher.update(s.as_bytes());
+            }
+            ScalarValue::Bytes(b) => {
+                hasher.update([1]);
+                hasher.update(b);
+            }
+            _ => {}
+        }
+        hasher.finalize().into()
+    }
+}
+
+/// The key into the table of a reference put by [`replace_duplicates`], or `None` if `value`
+/// isn't a reference.
+pub fn duplicate_key(value: &ScalarValue) -> Option<String> {
+    match value {
+        ScalarValue::Unknown { type_code, bytes }
+            if *type_code == DUPLICATE_REF_TYPE_CODE && bytes.len() == 32 =>
+        {
+            Some(hex::encode(bytes))
+        }
+        _ => None,
+    }
+}
+
+/// The value `value` stands for if it is a reference put by [`replace_duplicates`] with `table`,
+/// or `value` itself if it isn't a reference.
+pub fn resolve_duplicate<'a>(
+    doc: &'a Automerge,
+    table: &ExId,
+    value: Value<'a>,
+) -> Result<Value<'a>, AutomergeError> {
+    let key = match &value {
+        Value::Scalar(s) => duplicate_key(s),
+        Value::Object(_) => None,
+    };
+    match key {
+        Some(key) => Ok(doc.get(table, key)?.map(|(v, _)| v).unwrap_or(value)),
+        None => Ok(value),
+    }
+}
+
+impl Automerge {
+    /// Find every string or bytes value of at least `min_len` bytes which appears more than once
+    /// in the current state of the document.
+    ///
+    /// The results are sorted so that the values wasting the most space come first. Characters
+    /// in text objects are not considered.
+    pub fn duplicate_value_report(&self, min_len: usize) -> Vec<DuplicateValue> {
+        let mut found: HashMap<(bool, Vec<u8>), DuplicateValue> = HashMap::new();
+        let mut order = Vec::new();
+        self.collect_values(&ExId::Root, min_len, &mut |value, obj, prop| {
+            let key = match &value {
+                ScalarValue::Str(s) => (false, s.as_bytes().to_vec()),
+                ScalarValue::Bytes(b) => (true, b.clone()),
+                _ => return,
+            };
+            found
+                .entry(key.clone())
+                .or_insert_with(|| {
+                    order.push(key);
+                    DuplicateValue {
+                        value,
+                        locations: Vec::new(),
+                    }
+                })
+                .locations
+                .push((obj, prop));
+        });
+        let mut report = order
+            .into_iter()
+            .filter_map(|key| found.remove(&key))
+            .filter(|dup| dup.locations.len() > 1)
+            .collect::<Vec<_>>();
+        report.sort_by_key(|dup| std::cmp::Reverse(dup.wasted()));
+        report
+    }
+
+    fn collect_values<F>(&self, obj: &ExId, min_len: usize, f: &mut F)
+    where
+        F: FnMut(ScalarValue, ExId, Prop),
+    {
+        let large = |value: &ScalarValue| match value {
+            ScalarValue::Str(s) => s.len() >= min_len,
+            ScalarValue::Bytes(b) => b.len() >= min_len,
+            _ => false,
+        };
+        match self.object_type(obj) {
+            Some(ObjType::Map) | Some(ObjType::Table) => {
+                for (key, value, id) in self.map_range(obj, ..) {
+                    match value {
+                        Value::Object(_) => self.collect_values(&id, min_len, f),
+                        Value::Scalar(s) if large(&s) => f(s.into_owned(), obj.clone(), key.into()),
+                        Value::Scalar(_) => {}
+                    }
+                }
+            }
+            Some(ObjType::List) => {
+                for (index, value, id) in self.list_range(obj, ..) {
+                    match value {
+                        Value::Object(_) => self.collect_values(&id, min_len, f),
+                        Value::Scalar(s) if large(&s) => {
+                            f(s.into_owned(), obj.clone(), index.into())
+                        }
+                        Value::Scalar(_) => {}
+                    }
+                }
+            }
+            Some(ObjType::Text) | None => {}
+        }
+    }
+}
+
+/// Store each value in `report` once in the map `table`, under [`DuplicateValue::key`], and
+/// replace every occurrence of it with [`DuplicateValue::reference`].
+///
+/// The report should have been generated from the current state of the document `tx` is
+/// modifying, otherwise the locations may no longer hold the duplicated value. See the
+/// [module documentation](self) for when this makes a document smaller.
+pub fn replace_duplicates<T: Transactable>(
+    tx: &mut T,
+    report: &[DuplicateValue],
+    table: &ExId,
+) -> Result<(), AutomergeError> {
+    for dup in report {
+        tx.put(table, dup.key(), dup.value.clone())?;
+        let reference = dup.reference();
+        for (obj, prop) in &dup.locations {
+            tx.put(obj, prop.clone(), reference.clone())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AutoCommit, ROOT};
+
+    #[test]
+    fn reports_and_replaces_duplicates() {
+        let big = "x".repeat(100);
+        let mut doc = AutoCommit::new();
+        doc.put(&ROOT, "a", big.as_str()).unwrap();
+        doc.put(&ROOT, "small", "hi").unwrap();
+        doc.put(&ROOT, "small2", "hi").unwrap();
+        let list = doc.put_object(&ROOT, "list", ObjType::List).unwrap();
+        doc.insert(&list, 0, big.as_str()).unwrap();
+        doc.insert(&list, 1, vec![1u8; 50]).unwrap();
+        doc.insert(&list, 2, vec![1u8; 50]).unwrap();
+        doc.insert(&list, 3, "unique ".repeat(20)).unwrap();
+
+        let report = doc.document().duplicate_value_report(10);
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].value, ScalarValue::from(big.as_str()));
+        assert_eq!(
+            report[0].locations,
+            vec![(ROOT, "a".into()), (list.clone(), 0.into())]
+        );
+        assert_eq!(report[0].total_cost(), 200);
+        assert_eq!(report[0].wasted(), 100);
+        assert_eq!(report[1].value, ScalarValue::Bytes(vec![1u8; 50]));
+
+        let table = doc.put_object(&ROOT, "values", ObjType::Map).unwrap();
+        replace_duplicates(&mut doc, &report, &table).unwrap();
+        let reference = Value::from(report[0].reference());
+        assert_eq!(doc.get(&ROOT, "a").unwrap().unwrap().0, reference);
+        assert_eq!(doc.get(&list, 0).unwrap().unwrap().0, reference);
+        assert_eq!(duplicate_key(&report[0].reference()), Some(report[0].key()));
+        assert_eq!(
+            resolve_duplicate(doc.document(), &table, reference.clone()).unwrap(),
+            Value::from(big.as_str())
+        );
+        // values which aren't references resolve to themselves, even if they look like a key
+        let key = Value::from(report[0].key());
+        assert_eq!(
+            resolve_duplicate(doc.document(), &table, key.clone()).unwrap(),
+            key
+        );
+        // references are neither strings nor bytes, so they aren't reported themselves
+        assert!(doc.document().duplicate_value_report(10).is_empty());
+
+        let saved = doc.save();
+        let loaded = Automerge::load(&saved).unwrap();
+        assert_eq!(loaded.get(&ROOT, "a").unwrap().unwrap().0, reference);
+    }
+}
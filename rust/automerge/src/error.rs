@@ -6,8 +6,18 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum AutomergeError {
+    #[error("more than one actor is mapped to {0}")]
+    AmbiguousActorMap(ActorId),
+    #[error("actor {0} has made changes in another session")]
+    ActorReused(ActorId),
     #[error(transparent)]
     Clocks(#[from] crate::clocks::MissingDep),
+    #[error("the operation was cancelled")]
+    Cancelled,
+    #[error("change {0} is neither beneath the history fence nor after it")]
+    ConcurrentWithFence(ChangeHash),
+    #[error("the document configuration does not match ours")]
+    ConfigMismatch,
     #[error("failed to load compressed data: {0}")]
     Deflate(#[source] std::io::Error),
     #[error("duplicate seq {0} found for actor {1}")]
@@ -16,26 +26,36 @@ pub enum AutomergeError {
     EmptyStringKey,
     #[error("general failure")]
     Fail,
+    #[error("the history fence must include the fence the history was dropped beneath")]
+    FenceBeforeDroppedHistory,
     #[error("invalid actor ID `{0}`")]
     InvalidActorId(String),
+    #[error("the cursor refers to an element which is not in this document")]
+    InvalidCursor,
     #[error("invalid UTF-8 character at {0}")]
     InvalidCharacter(usize),
     #[error("invalid hash {0}")]
     InvalidHash(ChangeHash),
     #[error("index {0} is out of bounds")]
     InvalidIndex(usize),
+    #[error("node size {0} is too small, the branching factor must be at least 2")]
+    InvalidNodeSize(usize),
     #[error("invalid obj id `{0}`")]
     InvalidObjId(String),
     #[error("invalid obj id format `{0}`")]
     InvalidObjIdFormat(String),
     #[error("seq {0} is out of bounds")]
     InvalidSeq(u64),
+    #[error("change {0} has an invalid signature")]
+    InvalidSignature(ChangeHash),
     #[error("invalid type of value, expected `{expected}` but received `{unexpected}`")]
     InvalidValueType {
         expected: String,
         unexpected: String,
     },
     #[error(transparent)]
+    LimitExceeded(#[from] crate::limits::LimitExceeded),
+    #[error(transparent)]
     Load(#[from] LoadError),
     #[error("increment operations must be against a counter value")]
     MissingCounter,
@@ -45,6 +65,91 @@ pub enum AutomergeError {
     NonChangeCompressed,
     #[error("id was not an object id")]
     NotAnObject,
+    #[error("object `{0}` was not loaded")]
+    NotLoaded(String),
+    #[error("failed to read bytes: {0}")]
+    Read(#[source] std::io::Error),
+    #[error("the key `{0}` is reserved for automerge's own data")]
+    ReservedKey(String),
+    #[error(transparent)]
+    SchemaViolation(#[from] crate::schema::SchemaViolation),
+    #[error(
+        "actor {actor} has used seq {last_seq} but the document only has changes up to seq {seq}"
+    )]
+    StaleActor {
+        actor: ActorId,
+        seq: u64,
+        last_seq: u64,
+    },
+    #[error(transparent)]
+    Verification(#[from] crate::storage::verify::VerificationError),
+}
+
+/// The broad kind of failure an [`AutomergeError`] represents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// The arguments to the operation were invalid, retrying with the same input will fail again.
+    UserInput,
+    /// Data being loaded or applied is malformed or fails an integrity check.
+    Corruption,
+    /// The operation would exceed a configured limit.
+    LimitExceeded,
+    /// The operation refers to changes this document has not seen yet. Retrying once more
+    /// changes have been received may succeed.
+    ConcurrencyConflict,
+    /// The operation was stopped by a [`crate::CancellationToken`].
+    Cancelled,
+    /// Something went wrong inside automerge.
+    Internal,
+}
+
+impl AutomergeError {
+    /// The category of this error, for translating errors into e.g. HTTP status codes without
+    /// matching on every variant.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Self::Clocks(_)
+            | Self::InvalidCursor
+            | Self::InvalidHash(_)
+            | Self::MissingHash(_)
+            | Self::StaleActor { .. } => ErrorCategory::ConcurrencyConflict,
+            Self::Deflate(_)
+            | Self::DuplicateSeqNumber(..)
+            | Self::InvalidSeq(_)
+            | Self::InvalidSignature(_)
+            | Self::Load(_)
+            | Self::NonChangeCompressed
+            | Self::Verification(_) => ErrorCategory::Corruption,
+            Self::ActorReused(_)
+            | Self::AmbiguousActorMap(_)
+            | Self::ConcurrentWithFence(_)
+            | Self::ConfigMismatch
+            | Self::EmptyStringKey
+            | Self::FenceBeforeDroppedHistory
+            | Self::InvalidActorId(_)
+            | Self::InvalidCharacter(_)
+            | Self::InvalidIndex(_)
+            | Self::InvalidNodeSize(_)
+            | Self::InvalidObjId(_)
+            | Self::InvalidObjIdFormat(_)
+            | Self::InvalidValueType { .. }
+            | Self::MissingCounter
+            | Self::NotAnObject
+            | Self::NotLoaded(_)
+            | Self::Read(_)
+            | Self::ReservedKey(_)
+            | Self::SchemaViolation(_) => ErrorCategory::UserInput,
+            Self::LimitExceeded(_) => ErrorCategory::LimitExceeded,
+            Self::Cancelled => ErrorCategory::Cancelled,
+            Self::Fail => ErrorCategory::Internal,
+        }
+    }
+
+    /// Whether the operation which produced this error might succeed if retried later, once
+    /// more changes have been received from other peers.
+    pub fn is_retryable(&self) -> bool {
+        self.category() == ErrorCategory::ConcurrencyConflict
+    }
 }
 
 #[cfg(feature = "wasm")]
@@ -67,6 +172,10 @@ pub(crate) struct InvalidScalarValue {
     pub(crate) expected: String,
 }
 
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+#[error("Invalid timestamp, {0:?} is too far from the Unix epoch")]
+pub struct InvalidTimestamp(pub std::time::SystemTime);
+
 #[derive(Error, Debug, Eq, PartialEq)]
 #[error("Invalid change hash slice: {0:?}")]
 pub struct InvalidChangeHashSlice(pub Vec<u8>);
@@ -1,10 +1,20 @@
-use crate::ActorId;
+use crate::{ActorId, AutomergeError};
 use serde::Serialize;
 use serde::Serializer;
 use std::cmp::{Ord, Ordering};
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
+/// The ID of an object or operation, valid in any document which contains it.
+///
+/// # String encoding
+///
+/// `to_string` encodes the root object as `_root` and anything else as `{counter}@{actor}`, with
+/// the actor in lowercase hex, and [`FromStr`] reverses it. The encoding depends only on the
+/// operation, so it will keep referring to the same object after the document is saved and
+/// loaded, forked or merged with other copies, and can be stored outside the document, for
+/// example in a database. This format will not change.
 #[derive(Debug, Clone)]
 pub enum ExId {
     Root,
@@ -36,6 +46,22 @@ impl fmt::Display for ExId {
     }
 }
 
+impl FromStr for ExId {
+    type Err = AutomergeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "_root" {
+            return Ok(ExId::Root);
+        }
+        let invalid = || AutomergeError::InvalidObjIdFormat(s.to_owned());
+        let (counter, actor) = s.split_once('@').ok_or_else(invalid)?;
+        let counter = counter.parse().map_err(|_| invalid())?;
+        let actor = ActorId::try_from(actor).map_err(|_| invalid())?;
+        // documents look the actor up when the index doesn't match it
+        Ok(ExId::Id(counter, actor, usize::MAX))
+    }
+}
+
 impl Hash for ExId {
     fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
@@ -54,8 +80,7 @@ impl Ord for ExId {
             (ExId::Root, ExId::Root) => Ordering::Equal,
             (ExId::Root, _) => Ordering::Less,
             (_, ExId::Root) => Ordering::Greater,
-            (ExId::Id(c1, a1, _), ExId::Id(c2, a2, _)) if c1 == c2 => a2.cmp(a1),
-            (ExId::Id(c1, _, _), ExId::Id(c2, _, _)) => c1.cmp(c2),
+            (ExId::Id(c1, a1, _), ExId::Id(c2, a2, _)) => c1.cmp(c2).then_with(|| a1.cmp(a2)),
         }
     }
 }
@@ -0,0 +1,111 @@
+//! The layout of the extra bytes at the end of a change.
+//!
+//! The extra bytes hold the application data stored with
+//! [`crate::transaction::CommitOptions::with_extra_bytes`] and the signature of a signed change
+//! (see [`crate::Automerge::set_signer`]). They are a sequence of sections, each a tag byte
+//! followed by the uLEB128 encoded length of its contents and the contents. The application data
+//! comes before the signature so that the bytes the signature covers, which are everything
+//! before the signature section, include it.
+//!
+//! Extra bytes which aren't a sequence of known sections, such as those written by other
+//! implementations, are all application data.
+
+const DATA: u8 = 1;
+const SIGNATURE: u8 = 2;
+
+/// The sections of the extra bytes of a change.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ExtraBytes<'a> {
+    pub(crate) data: &'a [u8],
+    /// The signature and the offset of its section in the extra bytes.
+    pub(crate) signature: Option<(usize, &'a [u8])>,
+}
+
+impl<'a> ExtraBytes<'a> {
+    pub(crate) fn parse(bytes: &'a [u8]) -> Self {
+        Self::parse_sections(bytes).unwrap_or(Self {
+            data: bytes,
+            signature: None,
+        })
+    }
+
+    fn parse_sections(bytes: &'a [u8]) -> Option<Self> {
+        let mut parsed = Self::default();
+        let mut rest = bytes;
+        while let Some((&tag, mut after_tag)) = rest.split_first() {
+            let offset = bytes.len() - rest.len();
+            let len = leb128::read::unsigned(&mut after_tag).ok()?;
+            let len = usize::try_from(len)
+                .ok()
+                .filter(|len| *len <= after_tag.len())?;
+            let (contents, after) = after_tag.split_at(len);
+            match tag {
+                DATA if parsed.data.is_empty() && parsed.signature.is_none() => {
+                    parsed.data = contents
+                }
+                SIGNATURE if parsed.signature.is_none() => {
+                    parsed.signature = Some((offset, contents))
+                }
+                _ => return None,
+            }
+            rest = after;
+        }
+        Some(parsed)
+    }
+}
+
+/// The extra bytes holding the application data `data`.
+pub(crate) fn encode_data(data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    if !data.is_empty() {
+        encode_section(&mut bytes, DATA, data);
+    }
+    bytes
+}
+
+/// Add the `signature` section after the application data in `extra_bytes`.
+pub(crate) fn append_signature(extra_bytes: &mut Vec<u8>, signature: &[u8]) {
+    encode_section(extra_bytes, SIGNATURE, signature);
+}
+
+fn encode_section(bytes: &mut Vec<u8>, tag: u8, contents: &[u8]) {
+    bytes.push(tag);
+    leb128::write::unsigned(bytes, contents.len() as u64).unwrap();
+    bytes.extend_from_slice(contents);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_and_signature_round_trip() {
+        let mut bytes = encode_data(b"data");
+        append_signature(&mut bytes, b"sig");
+        assert_eq!(
+            ExtraBytes::parse(&bytes),
+            ExtraBytes {
+                data: b"data",
+                signature: Some((6, b"sig")),
+            }
+        );
+        assert_eq!(ExtraBytes::parse(&encode_data(b"")), ExtraBytes::default());
+    }
+
+    #[test]
+    fn unknown_layouts_are_application_data() {
+        for bytes in [
+            &b"client 1.2.3"[..],
+            &[DATA, 5, 0],
+            &[SIGNATURE, 0, DATA, 0],
+        ] {
+            assert_eq!(
+                ExtraBytes::parse(bytes),
+                ExtraBytes {
+                    data: bytes,
+                    signature: None,
+                }
+            );
+        }
+    }
+}
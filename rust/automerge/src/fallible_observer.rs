@@ -0,0 +1,315 @@
+use crate::exid::ExId;
+use crate::transaction::{self, CommitOptions, Failure, Observed, Success, Transaction};
+use crate::{Automerge, AutomergeError, Change, ChangeHash, OpObserver, Parents, Prop, Value};
+
+/// An observer of operations applied to the document which can fail, like [`OpObserver`] but
+/// with each method returning a result.
+///
+/// A failing observer aborts what it is observing when it is used with
+/// [`Automerge::apply_changes_fallible`], [`Automerge::merge_fallible`] or
+/// [`Automerge::transact_fallible`]: the document and the observer are left as they were before.
+/// Undoing a transaction is cheap, but undoing applied changes is not, see the cost of
+/// [`Automerge::apply_changes_fallible`]. To use one anywhere else an [`OpObserver`] is expected wrap it in a [`Fallible`], which
+/// records the first error instead.
+///
+/// Errors are cloned when the observer of a transaction is merged into that of its document, so
+/// they must be `Clone`.
+pub trait FallibleOpObserver: Default + Clone {
+    type Error: Clone;
+
+    /// A new value has been inserted into the given object, see [`OpObserver::insert`].
+    fn insert(
+        &mut self,
+        parents: Parents<'_>,
+        objid: ExId,
+        index: usize,
+        tagged_value: (Value<'_>, ExId),
+    ) -> Result<(), Self::Error>;
+
+    /// Characters have been inserted into a text object, see [`OpObserver::splice_text`].
+    fn splice_text(
+        &mut self,
+        parents: Parents<'_>,
+        objid: ExId,
+        index: usize,
+        value: &str,
+    ) -> Result<(), Self::Error>;
+
+    /// A new value has been put into the given object, see [`OpObserver::put`].
+    fn put(
+        &mut self,
+        parents: Parents<'_>,
+        objid: ExId,
+        prop: Prop,
+        tagged_value: (Value<'_>, ExId),
+        conflict: bool,
+    ) -> Result<(), Self::Error>;
+
+    /// A counter has been incremented, see [`OpObserver::increment`].
+    fn increment(
+        &mut self,
+        parents: Parents<'_>,
+        objid: ExId,
+        prop: Prop,
+        tagged_value: (i64, ExId),
+    ) -> Result<(), Self::Error>;
+
+    /// A value has been deleted, see [`OpObserver::delete`].
+    fn delete(&mut self, parents: Parents<'_>, objid: ExId, prop: Prop) -> Result<(), Self::Error>;
+
+    /// Branch of a new observer later to be merged, see [`OpObserver::branch`].
+    fn branch(&self) -> Self {
+        Self::default()
+    }
+
+    /// Merge observed information from a transaction, see [`OpObserver::merge`].
+    fn merge(&mut self, other: &Self);
+}
+
+/// An [`OpObserver`] which passes everything it observes to a [`FallibleOpObserver`] until it
+/// fails.
+///
+/// The first error is kept and nothing observed after it is passed on, so the wrapped observer
+/// only ever sees the operations up to its failure. Check [`Self::error`] once the operations
+/// are applied.
+#[derive(Debug, Clone)]
+pub struct Fallible<O: FallibleOpObserver> {
+    observer: O,
+    error: Option<O::Error>,
+}
+
+impl<O: FallibleOpObserver> Fallible<O> {
+    pub fn new(observer: O) -> Self {
+        Self {
+            observer,
+            error: None,
+        }
+    }
+
+    /// The wrapped observer
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// The first error returned by the wrapped observer, if it has failed
+    pub fn error(&self) -> Option<&O::Error> {
+        self.error.as_ref()
+    }
+
+    /// The wrapped observer, or the first error it returned if it has failed
+    pub fn into_result(self) -> Result<O, O::Error> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.observer),
+        }
+    }
+
+    fn observe<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut O) -> Result<(), O::Error>,
+    {
+        if self.error.is_none() {
+            self.error = f(&mut self.observer).err();
+        }
+    }
+}
+
+impl<O: FallibleOpObserver> Default for Fallible<O> {
+    fn default() -> Self {
+        Self::new(O::default())
+    }
+}
+
+impl<O: FallibleOpObserver> OpObserver for Fallible<O> {
+    fn insert(
+        &mut self,
+        parents: Parents<'_>,
+        objid: ExId,
+        index: usize,
+        tagged_value: (Value<'_>, ExId),
+    ) {
+        self.observe(|o| o.insert(parents, objid, index, tagged_value))
+    }
+
+    fn splice_text(&mut self, parents: Parents<'_>, objid: ExId, index: usize, value: &str) {
+        self.observe(|o| o.splice_text(parents, objid, index, value))
+    }
+
+    fn put(
+        &mut self,
+        parents: Parents<'_>,
+        objid: ExId,
+        prop: Prop,
+        tagged_value: (Value<'_>, ExId),
+        conflict: bool,
+    ) {
+        self.observe(|o| o.put(parents, objid, prop, tagged_value, conflict))
+    }
+
+    fn increment(
+        &mut self,
+        parents: Parents<'_>,
+        objid: ExId,
+        prop: Prop,
+        tagged_value: (i64, ExId),
+    ) {
+        self.observe(|o| o.increment(parents, objid, prop, tagged_value))
+    }
+
+    fn delete(&mut self, parents: Parents<'_>, objid: ExId, prop: Prop) {
+        self.observe(|o| o.delete(parents, objid, prop))
+    }
+
+    fn branch(&self) -> Self {
+        Self::new(self.observer.branch())
+    }
+
+    fn merge(&mut self, other: &Self) {
+        if self.error.is_none() {
+            self.observer.merge(&other.observer);
+            self.error = other.error.clone();
+        }
+    }
+}
+
+/// The error of an operation observed by a [`FallibleOpObserver`], which is either the error of
+/// the operation itself or of the observer.
+#[derive(Debug, thiserror::Error)]
+pub enum ObserverError<E, O> {
+    #[error("{0}")]
+    Operation(E),
+    #[error("observer failed: {0}")]
+    Observer(O),
+}
+
+impl Automerge {
+    /// Apply changes to this document like [`Self::apply_changes_with`], observing them with a
+    /// [`FallibleOpObserver`].
+    ///
+    /// If the observer fails, or the changes can't be applied, none of the changes are applied
+    /// and `observer` is left as it was.
+    ///
+    /// ### Cost
+    ///
+    /// **Every call clones the whole document**, history included, so that it can be restored if
+    /// the changes fail, whether or not they do. This makes it much slower than
+    /// [`Self::apply_changes_with`] for large documents, and it needs memory for a second copy
+    /// of the document while the changes are applied. Where the observer can't fail, or changes
+    /// can be checked before they are applied, prefer [`Self::apply_changes_with`].
+    pub fn apply_changes_fallible<I, Obs>(
+        &mut self,
+        changes: I,
+        observer: &mut Obs,
+    ) -> Result<(), ObserverError<AutomergeError, Obs::Error>>
+    where
+        I: IntoIterator<Item = Change>,
+        Obs: FallibleOpObserver,
+    {
+        let before = self.clone();
+        let mut fallible = Fallible::new(observer.clone());
+        let result = self.apply_changes_with(changes, Some(&mut fallible));
+        let result = match (result, fallible.into_result()) {
+            (Err(e), _) => Err(ObserverError::Operation(e)),
+            (Ok(()), Err(e)) => Err(ObserverError::Observer(e)),
+            (Ok(()), Ok(observed)) => {
+                *observer = observed;
+                return Ok(());
+            }
+        };
+        *self = before;
+        result
+    }
+
+    /// Take all the changes in `other` which are not in `self` and apply them like
+    /// [`Self::apply_changes_fallible`], returning the new heads.
+    ///
+    /// Like [`Self::apply_changes_fallible`] **this clones the whole document** on every call.
+    pub fn merge_fallible<Obs: FallibleOpObserver>(
+        &mut self,
+        other: &mut Self,
+        observer: &mut Obs,
+    ) -> Result<Vec<ChangeHash>, ObserverError<AutomergeError, Obs::Error>> {
+        if !other.history.is_empty() {
+            self.check_config(&other.config)
+                .map_err(ObserverError::Operation)?;
+        }
+        let changes = self
+            .get_changes_added(other)
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        self.apply_changes_fallible(changes, observer)?;
+        Ok(self.get_heads())
+    }
+
+    /// Run a transaction on this document in a closure like [`Self::transact_observed`],
+    /// observing its ops with a [`FallibleOpObserver`].
+    ///
+    /// The transaction is rolled back if the closure or the observer fails. The closure can
+    /// check [`Fallible::error`] through [`Transaction::observer`] to stop early once the
+    /// observer has failed.
+    pub fn transact_fallible<F, O, E, Obs>(
+        &mut self,
+        f: F,
+    ) -> transaction::Result<O, Obs, ObserverError<E, Obs::Error>>
+    where
+        F: FnOnce(&mut Transaction<'_, Observed<Fallible<Obs>>>) -> Result<O, E>,
+        Obs: FallibleOpObserver,
+    {
+        self.transact_fallible_impl(None::<&dyn Fn(&O) -> CommitOptions>, f)
+    }
+
+    /// Like [`Self::transact_fallible`] but with a function for generating the commit options.
+    pub fn transact_fallible_with<F, O, E, C, Obs>(
+        &mut self,
+        c: C,
+        f: F,
+    ) -> transaction::Result<O, Obs, ObserverError<E, Obs::Error>>
+    where
+        F: FnOnce(&mut Transaction<'_, Observed<Fallible<Obs>>>) -> Result<O, E>,
+        C: FnOnce(&O) -> CommitOptions,
+        Obs: FallibleOpObserver,
+    {
+        self.transact_fallible_impl(Some(c), f)
+    }
+
+    fn transact_fallible_impl<F, O, E, C, Obs>(
+        &mut self,
+        c: Option<C>,
+        f: F,
+    ) -> transaction::Result<O, Obs, ObserverError<E, Obs::Error>>
+    where
+        F: FnOnce(&mut Transaction<'_, Observed<Fallible<Obs>>>) -> Result<O, E>,
+        C: FnOnce(&O) -> CommitOptions,
+        Obs: FallibleOpObserver,
+    {
+        let mut tx = self.transaction_with_observer(Fallible::new(Obs::default()));
+        let result = match f(&mut tx) {
+            Ok(result) => result,
+            Err(error) => {
+                return Err(Failure {
+                    error: ObserverError::Operation(error),
+                    cancelled: tx.rollback(),
+                })
+            }
+        };
+        if let Some(error) = tx.observer().error.take() {
+            return Err(Failure {
+                error: ObserverError::Observer(error),
+                cancelled: tx.rollback(),
+            });
+        }
+        let (observer, hash) = match c {
+            Some(c) => {
+                let options = c(&result);
+                tx.commit_with(options)
+            }
+            None => tx.commit(),
+        };
+        Ok(Success {
+            result,
+            hash,
+            op_observer: observer.observer,
+        })
+    }
+}
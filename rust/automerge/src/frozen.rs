@@ -0,0 +1,66 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::Automerge;
+
+/// A document which can no longer change, returned by [`Automerge::freeze`].
+///
+/// Cloning a frozen document only clones a reference to it, and it is `Send` and `Sync`, so a
+/// server can hand the same document to any number of request handlers without copying it or
+/// taking a lock to read it. Every read of [`Automerge`], including the historical `*_at` reads
+/// and generating sync messages, is available through `Deref`, but nothing which needs
+/// `&mut Automerge`.
+///
+/// The caches which speed up repeated reads of a document, see
+/// [`Automerge::set_cache_capacity`], sit behind a lock, so a frozen document bypasses them.
+///
+/// Unlike a [`crate::Snapshot`] a frozen document keeps its history. To change it again turn it
+/// back into a document with [`Self::thaw`].
+#[derive(Debug, Clone)]
+pub struct FrozenDoc {
+    doc: Arc<Automerge>,
+}
+
+impl FrozenDoc {
+    pub(crate) fn new(doc: Automerge) -> Self {
+        Self { doc: Arc::new(doc) }
+    }
+
+    /// Turn this back into a document which can be changed. This copies the document if there
+    /// are other clones of it.
+    pub fn thaw(self) -> Automerge {
+        let mut doc = Arc::try_unwrap(self.doc).unwrap_or_else(|doc| doc.as_ref().clone());
+        doc.ops.lookup_cache.set_frozen(false);
+        doc
+    }
+
+    /// Save the entirety of the document like [`Automerge::save`].
+    pub fn save(&self) -> Vec<u8> {
+        self.doc.encode(None)
+    }
+
+    /// Whether `self` and `other` are clones of the same frozen document.
+    pub fn ptr_eq(&self, other: &FrozenDoc) -> bool {
+        Arc::ptr_eq(&self.doc, &other.doc)
+    }
+}
+
+impl Deref for FrozenDoc {
+    type Target = Automerge;
+
+    fn deref(&self) -> &Automerge {
+        &self.doc
+    }
+}
+
+impl AsRef<Automerge> for FrozenDoc {
+    fn as_ref(&self) -> &Automerge {
+        &self.doc
+    }
+}
+
+impl From<Automerge> for FrozenDoc {
+    fn from(doc: Automerge) -> Self {
+        doc.freeze()
+    }
+}
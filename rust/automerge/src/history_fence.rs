@@ -0,0 +1,275 @@
+use std::cmp::Ordering;
+
+use crate::clock::{Clock, ClockData};
+use crate::op_observer::OpObserver;
+use crate::op_set::OpSet;
+use crate::storage::fence::{Fence, FenceHead};
+use crate::storage::load::{self, reconstruct_base, Reconstructed};
+use crate::storage::{self, CompressConfig};
+use crate::types::{ObjId, Op, OpType};
+use crate::{Automerge, AutomergeError, Change, ChangeHash};
+
+/// Heads beneath which changes are dropped when a document is saved, see
+/// [`Automerge::set_history_fence`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HistoryFence {
+    /// The heads of the fence, sorted
+    pub(crate) heads: Vec<ChangeHash>,
+    /// The clock of the heads, which covers every change beneath the fence
+    pub(crate) clock: Clock,
+}
+
+impl HistoryFence {
+    /// The number of changes by `actor` beneath the fence.
+    pub(crate) fn seqs(&self, actor: usize) -> u64 {
+        self.clock.get_for_actor(&actor).map_or(0, |d| d.seq)
+    }
+
+    /// Whether the change `seq` of `actor` is beneath the fence.
+    pub(crate) fn covers(&self, actor: usize, seq: u64) -> bool {
+        self.seqs(actor) >= seq
+    }
+
+    /// Whether `clock`, the clock of a change, includes every head of the fence.
+    fn is_before(&self, clock: &Clock) -> bool {
+        matches!(
+            self.clock.partial_cmp(clock),
+            Some(Ordering::Less) | Some(Ordering::Equal)
+        )
+    }
+}
+
+impl Automerge {
+    /// Set the heads beneath which changes are dropped when the document is saved, so that
+    /// documents with a long history stay small. An empty `heads` removes the fence.
+    ///
+    /// The saved document contains the state of the document at the fence, without the ops
+    /// which were overwritten or deleted by then, followed by the changes since. Once loaded it
+    /// can no longer be read at, forked at or asked for the changes since heads beneath the
+    /// fence, and changes which don't include every head of the fence in their history can't
+    /// be applied to it. Changes beneath the fence which are applied to it are ignored, as it
+    /// already contains their ops.
+    ///
+    /// A peer which is missing changes beneath the fence can't be sent them in a sync message,
+    /// see [`crate::sync::State::peer_behind_fence`], it needs a copy of the saved document
+    /// instead. Documents saved with a fence can't be loaded by earlier versions of this library,
+    /// nor with [`Self::load_incremental`] or [`Self::load_lazy`].
+    ///
+    /// If changes which neither are beneath the fence nor include it in their history are
+    /// applied after the fence is set the document is saved as if the fence had not been set,
+    /// or beneath the fence it was loaded with if it was saved with one.
+    ///
+    /// # Errors
+    ///
+    /// * [`AutomergeError::MissingHash`] if one of `heads` is not in this document
+    /// * [`AutomergeError::ConcurrentWithFence`] if the document contains a change which
+    ///   neither is beneath the fence nor includes it in its history
+    /// * [`AutomergeError::FenceBeforeDroppedHistory`] if the document was loaded with a fence
+    ///   which the new fence does not include
+    pub fn set_history_fence(&mut self, heads: &[ChangeHash]) -> Result<(), AutomergeError> {
+        if heads.is_empty() {
+            self.history_fence = None;
+            return Ok(());
+        }
+        let mut heads = heads.to_vec();
+        heads.sort();
+        heads.dedup();
+        let fence = HistoryFence {
+            clock: self.clock_at(&heads)?,
+            heads,
+        };
+        if let Some(dropped) = &self.dropped_history {
+            if !dropped.is_before(&fence.clock) {
+                return Err(AutomergeError::FenceBeforeDroppedHistory);
+            }
+        }
+        if let Some(hash) = self.concurrent_with(&fence) {
+            return Err(AutomergeError::ConcurrentWithFence(hash));
+        }
+        self.history_fence = Some(fence);
+        Ok(())
+    }
+
+    /// The heads of the history fence, see [`Self::set_history_fence`], which is the fence the
+    /// document was loaded with if no other has been set. Empty if there is no fence.
+    pub fn history_fence(&self) -> &[ChangeHash] {
+        self.history_fence
+            .as_ref()
+            .or(self.dropped_history.as_ref())
+            .map_or(&[], |f| &f.heads)
+    }
+
+    /// Whether `hash` is in the history, or is a head of the fence beneath which the history
+    /// was dropped.
+    pub(crate) fn has_change(&self, hash: &ChangeHash) -> bool {
+        self.history_index.contains_key(hash)
+            || self
+                .dropped_history
+                .as_ref()
+                .map_or(false, |f| f.heads.binary_search(hash).is_ok())
+    }
+
+    /// Whether `change` has already been applied, either because it is in the history or
+    /// because it was dropped beneath a fence.
+    pub(crate) fn has_applied(&self, change: &Change) -> bool {
+        self.history_index.contains_key(&change.hash())
+            || self.dropped_history.as_ref().map_or(false, |f| {
+                self.ops
+                    .m
+                    .actors
+                    .lookup(change.actor_id())
+                    .map_or(false, |actor| f.covers(actor, change.seq()))
+            })
+    }
+
+    /// Whether `change`, whose dependencies have been applied, includes the fence beneath which
+    /// the history was dropped in its history, which it must to be applied.
+    pub(crate) fn follows_dropped_history(&self, change: &Change) -> bool {
+        match &self.dropped_history {
+            // changes after the fence include it by induction, so only changes which depend on
+            // nothing but heads of the fence need checking
+            Some(fence) => {
+                change
+                    .deps()
+                    .iter()
+                    .any(|d| self.history_index.contains_key(d))
+                    || fence.heads.iter().all(|h| change.deps().contains(h))
+            }
+            None => true,
+        }
+    }
+
+    /// The number of changes by `actor` dropped beneath a fence, which the seqs of its changes
+    /// in the history start after.
+    pub(crate) fn dropped_seqs(&self, actor: usize) -> u64 {
+        self.dropped_history.as_ref().map_or(0, |f| f.seqs(actor))
+    }
+
+    /// The fence to save the document with, if any.
+    pub(crate) fn fence_to_save(&self) -> Option<&HistoryFence> {
+        self.history_fence
+            .as_ref()
+            .filter(|f| self.concurrent_with(f).is_none())
+            .or(self.dropped_history.as_ref())
+    }
+
+    /// The first change in the history which neither is beneath `fence` nor includes it.
+    fn concurrent_with(&self, fence: &HistoryFence) -> Option<ChangeHash> {
+        self.history
+            .iter()
+            .find(|c| {
+                let covered = self
+                    .ops
+                    .m
+                    .actors
+                    .lookup(c.actor_id())
+                    .map_or(false, |actor| fence.covers(actor, c.seq()));
+                !covered && !fence.is_before(&self.clocks[&c.hash()])
+            })
+            .map(|c| c.hash())
+    }
+
+    /// Save the document as a fence chunk, a document chunk with the ops beneath `fence` and no
+    /// changes, and the changes after the fence.
+    pub(crate) fn save_fenced(
+        &self,
+        fence: &HistoryFence,
+        config: Option<CompressConfig>,
+    ) -> Vec<u8> {
+        let heads = fence
+            .heads
+            .iter()
+            .map(|hash| FenceHead {
+                hash: *hash,
+                clock: self.clocks[hash]
+                    .iter()
+                    .map(|(actor, data)| (self.ops.m.actors[*actor].clone(), data.seq, data.max_op))
+                    .collect(),
+            })
+            .collect();
+        let mut bytes = storage::fence::write(&Fence { heads });
+
+        let ops = self
+            .ops
+            .iter()
+            .filter(|(_, op)| fence.clock.covers(&op.id))
+            .filter_map(|(obj, op)| {
+                let mut op = op.clone();
+                op.succ.retain(|id| fence.clock.covers(id));
+                keep_beneath_fence(&op).then(|| (*obj, op))
+            })
+            .collect::<Vec<(ObjId, Op)>>();
+        bytes.extend(storage::save::save_document(
+            self.history[..0].iter(),
+            ops.iter().map(|(obj, op)| (obj, op)),
+            &self.ops.m.actors,
+            &self.ops.m.props,
+            &[],
+            config,
+        ));
+
+        for change in &self.history {
+            let actor = self.ops.m.actors.lookup(change.actor_id());
+            if !actor.map_or(false, |a| fence.covers(a, change.seq())) {
+                bytes.extend(change.raw_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Load the document chunk `d`, which holds the ops beneath `fence`.
+    pub(crate) fn from_fenced<Obs: OpObserver>(
+        fence: &Fence,
+        d: &storage::Document<'_>,
+        observer: &mut Option<&mut Obs>,
+    ) -> Result<Self, AutomergeError> {
+        let Reconstructed {
+            max_op,
+            result: mut op_set,
+            ..
+        } = match observer {
+            Some(o) => reconstruct_base(d, OpSet::observed_builder(*o)),
+            None => reconstruct_base(d, OpSet::builder()),
+        }
+        .map_err(|e| load::Error::InflateDocument(Box::new(e)))?;
+
+        let mut doc = Self::new();
+        let mut fence_clock = Clock::new();
+        let mut heads = Vec::with_capacity(fence.heads.len());
+        for head in &fence.heads {
+            let mut clock = Clock::new();
+            for (actor, seq, max_op) in &head.clock {
+                let data = ClockData {
+                    max_op: *max_op,
+                    seq: *seq,
+                };
+                clock.include(op_set.m.actors.cache(actor.clone()), data);
+            }
+            fence_clock.merge(&clock);
+            doc.clocks.insert(head.hash, clock);
+            heads.push(head.hash);
+        }
+        heads.sort();
+        // ops which were dropped may have had higher counters than any which were kept
+        doc.max_op = fence_clock
+            .iter()
+            .map(|(_, data)| data.max_op)
+            .fold(max_op, std::cmp::max);
+        doc.ops = op_set;
+        doc.deps = heads.iter().copied().collect();
+        doc.dropped_history = Some(HistoryFence {
+            heads,
+            clock: fence_clock,
+        });
+        Ok(doc)
+    }
+}
+
+/// Whether `op`, an op beneath a fence whose successors after the fence have been removed, is
+/// saved. Ops which were overwritten or deleted by the time of the fence are dropped, except
+/// for those which make objects, as the objects' ops refer to them, those which insert list
+/// elements, as later elements are positioned relative to them, and increments, which the
+/// values of counters are computed from.
+fn keep_beneath_fence(op: &Op) -> bool {
+    op.insert || op.visible() || matches!(op.action, OpType::Make(_) | OpType::Increment(_))
+}
@@ -0,0 +1,56 @@
+use crate::{Automerge, ChangeHash};
+
+/// The states a document passed through as its changes were applied, oldest first.
+///
+/// Created by [`Automerge::history_states`]. Each change is applied to a single working document,
+/// so stepping through the whole history costs about as much as loading the document once.
+///
+/// Use [`Self::next_state`] to look at each state in place. The [`Iterator`] implementation
+/// yields a clone of each state instead, which is convenient but copies the document at every
+/// step.
+#[derive(Debug)]
+pub struct HistoryStates<'a> {
+    source: &'a Automerge,
+    next: usize,
+    doc: Automerge,
+}
+
+impl<'a> HistoryStates<'a> {
+    pub(crate) fn new(source: &'a Automerge) -> Self {
+        let mut doc = Automerge::new();
+        doc.ops.set_node_size(source.ops.node_size());
+        Self {
+            source,
+            next: 0,
+            doc,
+        }
+    }
+
+    /// Apply the next change, returning its hash, the heads of the document after it was applied
+    /// and the document in that state.
+    pub fn next_state(&mut self) -> Option<(ChangeHash, Vec<ChangeHash>, &Automerge)> {
+        let change = self.source.history.get(self.next)?.clone();
+        self.next += 1;
+        let hash = change.hash();
+        // SAFETY: the source history is topologically sorted, so the dependencies of every change
+        // have already been applied, and the changes were verified when the source loaded them
+        self.doc.apply_changes([change]).unwrap();
+        Some((hash, self.doc.get_heads(), &self.doc))
+    }
+}
+
+impl<'a> Iterator for HistoryStates<'a> {
+    type Item = (ChangeHash, Vec<ChangeHash>, Automerge);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_state()
+            .map(|(hash, heads, doc)| (hash, heads, doc.clone()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.source.history.len() - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for HistoryStates<'a> {}
@@ -0,0 +1,48 @@
+use std::collections::HashSet;
+
+use fxhash::FxBuildHasher;
+use smol_str::SmolStr;
+
+use crate::types::{Op, OpType};
+use crate::ScalarValue;
+
+/// The string values of an op set, so that ops which put the same string share its storage.
+///
+/// Short strings are stored inline in a [`SmolStr`] and cost nothing extra however often they are
+/// repeated, so only strings which are allocated on the heap are interned. Those are reference
+/// counted, so interning a string replaces it with a clone of an equal string already in the op
+/// set, and the duplicate allocation is dropped once the op which brought it in is inserted.
+///
+/// Strings are kept for as long as the op set is, which is also how long the ops which use them
+/// are kept: ops are only removed when a transaction is rolled back.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Interner {
+    strings: HashSet<SmolStr, FxBuildHasher>,
+}
+
+impl Interner {
+    pub(crate) fn intern_op(&mut self, op: &mut Op) {
+        if let OpType::Put(ScalarValue::Str(s)) = &mut op.action {
+            self.intern(s);
+        }
+    }
+
+    fn intern(&mut self, s: &mut SmolStr) {
+        if !s.is_heap_allocated() {
+            return;
+        }
+        match self.strings.get(s) {
+            Some(shared) => *s = shared.clone(),
+            None => {
+                self.strings.insert(s.clone());
+            }
+        }
+    }
+}
+
+/// The interner doesn't affect the content of a document, so any two interners are equal.
+impl PartialEq for Interner {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
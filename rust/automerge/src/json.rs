@@ -0,0 +1,380 @@
+//! Conversion between documents and [`serde_json::Value`].
+//!
+//! JSON has fewer types than automerge so the conversion is lossy in both directions. The
+//! [`JsonOptions`] passed to [`Automerge::from_json_with`] and [`Automerge::to_json_with`] control
+//! how the ambiguous cases are handled.
+use std::io::Write;
+
+use serde_json::{Map, Number, Value as JsonValue};
+
+use crate::exid::ExId;
+use crate::transaction::Transactable;
+use crate::{Automerge, AutomergeError, ObjType, Prop, ScalarValue, Value};
+
+/// How to import JSON numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberMode {
+    /// Numbers without a fractional part become [`ScalarValue::Int`], or [`ScalarValue::Uint`] if
+    /// they are too large for an `i64`. All other numbers become [`ScalarValue::F64`].
+    Auto,
+    /// Every number becomes a [`ScalarValue::F64`].
+    Float,
+}
+
+/// How to import JSON strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringMode {
+    /// Strings become [`ScalarValue::Str`].
+    Scalar,
+    /// Strings become [`ObjType::Text`] objects, which can be edited concurrently.
+    Text,
+}
+
+/// How to export [`ScalarValue::Bytes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BytesMode {
+    /// As an array of numbers, one per byte.
+    Array,
+    /// As a lowercase hex encoded string.
+    Hex,
+}
+
+/// Options controlling conversion between documents and JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JsonOptions {
+    pub numbers: NumberMode,
+    pub strings: StringMode,
+    pub bytes: BytesMode,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        JsonOptions {
+            numbers: NumberMode::Auto,
+            strings: StringMode::Scalar,
+            bytes: BytesMode::Array,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum JsonError {
+    #[error("the root of a document must be a JSON object")]
+    RootNotObject,
+    #[error(transparent)]
+    Automerge(#[from] AutomergeError),
+    #[error("failed to write JSON: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl Automerge {
+    /// Create a document from a JSON object using the default [`JsonOptions`].
+    ///
+    /// The whole document is created in a single change.
+    pub fn from_json(json: &JsonValue) -> Result<Self, JsonError> {
+        Self::from_json_with(json, &JsonOptions::default())
+    }
+
+    /// Create a document from a JSON object.
+    ///
+    /// The whole document is created in a single change.
+    pub fn from_json_with(json: &JsonValue, options: &JsonOptions) -> Result<Self, JsonError> {
+        let map = match json {
+            JsonValue::Object(map) => map,
+            _ => return Err(JsonError::RootNotObject),
+        };
+        let mut doc = Self::new();
+        let mut tx = doc.transaction();
+        for (key, value) in map {
+            put_json(&mut tx, &ExId::Root, key.as_str().into(), value, options)?;
+        }
+        tx.commit();
+        Ok(doc)
+    }
+
+    /// The current state of the document as JSON, using the default [`JsonOptions`].
+    pub fn to_json(&self) -> JsonValue {
+        self.to_json_with(&JsonOptions::default())
+    }
+
+    /// The current state of the document as JSON.
+    ///
+    /// Text objects become strings, counters become their current value and timestamps become
+    /// milliseconds since the epoch. Values with no JSON representation, such as non-finite
+    /// floats and values of unknown type, become `null`.
+    pub fn to_json_with(&self, options: &JsonOptions) -> JsonValue {
+        self.object_to_json(&ExId::Root, ObjType::Map, options)
+    }
+
+    /// Write `obj` and everything beneath it to `writer` as JSON, using the default
+    /// [`JsonOptions`].
+    ///
+    /// See [`Self::to_json_writer_with`].
+    pub fn to_json_writer<O: AsRef<ExId>, W: Write>(
+        &self,
+        obj: O,
+        writer: W,
+    ) -> Result<(), JsonError> {
+        self.to_json_writer_with(obj, writer, &JsonOptions::default())
+    }
+
+    /// Write `obj` and everything beneath it to `writer` as JSON.
+    ///
+    /// This produces the same output as [`Self::to_json_with`] but writes it as it walks the
+    /// document rather than building a [`serde_json::Value`] first, so serving a large document
+    /// needs little more memory than its largest text object. The output is written in many
+    /// small pieces, so `writer` should usually be buffered.
+    pub fn to_json_writer_with<O: AsRef<ExId>, W: Write>(
+        &self,
+        obj: O,
+        mut writer: W,
+        options: &JsonOptions,
+    ) -> Result<(), JsonError> {
+        let obj = obj.as_ref();
+        let obj_type = self.object_type(obj).ok_or(AutomergeError::NotAnObject)?;
+        self.write_object(obj, obj_type, &mut writer, options)
+    }
+
+    fn write_object<W: Write>(
+        &self,
+        obj: &ExId,
+        obj_type: ObjType,
+        writer: &mut W,
+        options: &JsonOptions,
+    ) -> Result<(), JsonError> {
+        let write_value = |writer: &mut W, value: Value<'_>, id: ExId| match value {
+            Value::Object(obj_type) => self.write_object(&id, obj_type, writer, options),
+            Value::Scalar(s) => write_json(writer, &scalar_to_json(&s, options)),
+        };
+        match obj_type {
+            ObjType::Map | ObjType::Table => {
+                writer.write_all(b"{")?;
+                for (i, (key, value, id)) in self.map_range(obj, ..).enumerate() {
+                    if i > 0 {
+                        writer.write_all(b",")?;
+                    }
+                    write_json(writer, key)?;
+                    writer.write_all(b":")?;
+                    write_value(writer, value, id)?;
+                }
+                writer.write_all(b"}")?;
+            }
+            ObjType::List => {
+                writer.write_all(b"[")?;
+                for (i, (_, value, id)) in self.list_range(obj, ..).enumerate() {
+                    if i > 0 {
+                        writer.write_all(b",")?;
+                    }
+                    write_value(writer, value, id)?;
+                }
+                writer.write_all(b"]")?;
+            }
+            ObjType::Text => write_json(writer, &self.text(obj)?)?,
+        }
+        Ok(())
+    }
+
+    fn object_to_json(&self, obj: &ExId, obj_type: ObjType, options: &JsonOptions) -> JsonValue {
+        let to_json = |value: Value<'_>, id: ExId| match value {
+            Value::Object(obj_type) => self.object_to_json(&id, obj_type, options),
+            Value::Scalar(s) => scalar_to_json(&s, options),
+        };
+        match obj_type {
+            ObjType::Map | ObjType::Table => JsonValue::Object(
+                self.map_range(obj, ..)
+                    .map(|(key, value, id)| (key.to_string(), to_json(value, id)))
+                    .collect::<Map<_, _>>(),
+            ),
+            ObjType::List => JsonValue::Array(
+                self.list_range(obj, ..)
+                    .map(|(_, value, id)| to_json(value, id))
+                    .collect(),
+            ),
+            // SAFETY: `obj` is an object we found while traversing the document
+            ObjType::Text => JsonValue::String(self.text(obj).unwrap()),
+        }
+    }
+}
+
+fn write_json<W: Write, T: serde::Serialize + ?Sized>(
+    writer: &mut W,
+    value: &T,
+) -> Result<(), JsonError> {
+    serde_json::to_writer(writer, value).map_err(|e| JsonError::Io(e.into()))
+}
+
+fn scalar_to_json(value: &ScalarValue, options: &JsonOptions) -> JsonValue {
+    match value {
+        ScalarValue::Bytes(b) => match options.bytes {
+            BytesMode::Array => JsonValue::Array(b.iter().map(|b| JsonValue::from(*b)).collect()),
+            BytesMode::Hex => JsonValue::String(hex::encode(b)),
+        },
+        ScalarValue::Str(s) => JsonValue::String(s.to_string()),
+        ScalarValue::Int(i) => JsonValue::from(*i),
+        ScalarValue::Uint(u) => JsonValue::from(*u),
+        ScalarValue::F64(f) => Number::from_f64(*f)
+            .map(JsonValue::Number)
+            .unwrap_or(JsonValue::Null),
+        ScalarValue::Counter(c) => JsonValue::from(i64::from(c)),
+        ScalarValue::Timestamp(t) => JsonValue::from(*t),
+        ScalarValue::Boolean(b) => JsonValue::Bool(*b),
+        ScalarValue::Unknown { .. } | ScalarValue::Null => JsonValue::Null,
+    }
+}
+
+fn json_to_scalar(value: &JsonValue, options: &JsonOptions) -> Option<ScalarValue> {
+    match value {
+        JsonValue::Null => Some(ScalarValue::Null),
+        JsonValue::Bool(b) => Some(ScalarValue::Boolean(*b)),
+        JsonValue::Number(n) => Some(match options.numbers {
+            NumberMode::Auto => {
+                if let Some(i) = n.as_i64() {
+                    ScalarValue::Int(i)
+                } else if let Some(u) = n.as_u64() {
+                    ScalarValue::Uint(u)
+                } else {
+                    // SAFETY: a serde_json number is always one of i64, u64 or f64
+                    ScalarValue::F64(n.as_f64().unwrap())
+                }
+            }
+            // SAFETY: a serde_json number is always one of i64, u64 or f64, all of which can be
+            // converted to an f64
+            NumberMode::Float => ScalarValue::F64(n.as_f64().unwrap()),
+        }),
+        JsonValue::String(s) if options.strings == StringMode::Scalar => {
+            Some(ScalarValue::Str(s.into()))
+        }
+        _ => None,
+    }
+}
+
+/// Write `value` to `prop` of `obj`, creating objects as needed.
+fn put_json<T: Transactable>(
+    tx: &mut T,
+    obj: &ExId,
+    prop: Prop,
+    value: &JsonValue,
+    options: &JsonOptions,
+) -> Result<(), AutomergeError> {
+    if let Some(scalar) = json_to_scalar(value, options) {
+        return tx.put(obj, prop, scalar);
+    }
+    let obj_type = json_obj_type(value);
+    let new_obj = tx.put_object(obj, prop, obj_type)?;
+    fill_object(tx, &new_obj, value, options)
+}
+
+/// Insert `value` at `index` in the list `obj`, creating objects as needed.
+fn insert_json<T: Transactable>(
+    tx: &mut T,
+    obj: &ExId,
+    index: usize,
+    value: &JsonValue,
+    options: &JsonOptions,
+) -> Result<(), AutomergeError> {
+    if let Some(scalar) = json_to_scalar(value, options) {
+        return tx.insert(obj, index, scalar);
+    }
+    let obj_type = json_obj_type(value);
+    let new_obj = tx.insert_object(obj, index, obj_type)?;
+    fill_object(tx, &new_obj, value, options)
+}
+
+fn json_obj_type(value: &JsonValue) -> ObjType {
+    match value {
+        JsonValue::Object(_) => ObjType::Map,
+        JsonValue::Array(_) => ObjType::List,
+        _ => ObjType::Text,
+    }
+}
+
+fn fill_object<T: Transactable>(
+    tx: &mut T,
+    obj: &ExId,
+    value: &JsonValue,
+    options: &JsonOptions,
+) -> Result<(), AutomergeError> {
+    match value {
+        JsonValue::Object(map) => {
+            for (key, value) in map {
+                put_json(tx, obj, key.as_str().into(), value, options)?;
+            }
+        }
+        JsonValue::Array(values) => {
+            for (index, value) in values.iter().enumerate() {
+                insert_json(tx, obj, index, value, options)?;
+            }
+        }
+        JsonValue::String(s) => tx.splice_text(obj, 0, 0, s)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn json_round_trip() {
+        let json = json!({
+            "name": "automerge",
+            "version": 2,
+            "ratio": 0.5,
+            "big": u64::MAX,
+            "tags": ["crdt", null, true, {"nested": []}],
+        });
+        let doc = Automerge::from_json(&json).unwrap();
+        assert_eq!(doc.get_changes(&[]).unwrap().len(), 1);
+        assert_eq!(
+            doc.get(ExId::Root, "version").unwrap().unwrap().0,
+            Value::int(2)
+        );
+        assert_eq!(
+            doc.get(ExId::Root, "big").unwrap().unwrap().0,
+            Value::uint(u64::MAX)
+        );
+        assert_eq!(doc.to_json(), json);
+
+        let mut written = Vec::new();
+        doc.to_json_writer(ExId::Root, &mut written).unwrap();
+        assert_eq!(serde_json::from_slice::<JsonValue>(&written).unwrap(), json);
+        let (_, tags) = doc.get(ExId::Root, "tags").unwrap().unwrap();
+        let mut written = Vec::new();
+        doc.to_json_writer(&tags, &mut written).unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            r#"["crdt",null,true,{"nested":[]}]"#
+        );
+    }
+
+    #[test]
+    fn json_options() {
+        let json = json!({"n": 1, "s": "hello"});
+        let options = JsonOptions {
+            numbers: NumberMode::Float,
+            strings: StringMode::Text,
+            bytes: BytesMode::Hex,
+        };
+        let mut doc = Automerge::from_json_with(&json, &options).unwrap();
+        assert_eq!(
+            doc.get(ExId::Root, "n").unwrap().unwrap().0,
+            Value::f64(1.0)
+        );
+        let (value, text) = doc.get(ExId::Root, "s").unwrap().unwrap();
+        assert_eq!(value, Value::Object(ObjType::Text));
+        assert_eq!(doc.text(&text).unwrap(), "hello");
+
+        let mut tx = doc.transaction();
+        tx.put(ExId::Root, "b", vec![0xde, 0xad]).unwrap();
+        tx.commit();
+        assert_eq!(doc.to_json_with(&options)["b"], json!("dead"));
+        assert_eq!(doc.to_json()["b"], json!([0xde, 0xad]));
+
+        assert!(matches!(
+            Automerge::from_json(&json!([1, 2])),
+            Err(JsonError::RootNotObject)
+        ));
+    }
+}
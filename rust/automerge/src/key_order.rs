@@ -0,0 +1,103 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::exid::ExId;
+use crate::types::{Key, OpId};
+use crate::Automerge;
+
+/// The order of the keys returned by [`Automerge::keys_ordered`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyOrder {
+    /// By the bytes of the keys, like [`Automerge::keys`]
+    Lexicographic,
+    /// Like [`Self::Lexicographic`] except that runs of digits are compared by their numeric
+    /// value, so `"2"` comes before `"10"` and `"item9"` before `"item10"`
+    Numeric,
+    /// By when each key was first put, oldest first. Keys put concurrently by different actors
+    /// are in the same order on every peer.
+    Insertion,
+}
+
+impl Automerge {
+    /// The keys of the map `obj` in the given order.
+    ///
+    /// For a list this returns the element ids like [`Self::keys`], in list order for
+    /// [`KeyOrder::Insertion`].
+    pub fn keys_ordered<O: AsRef<ExId>>(&self, obj: O, order: KeyOrder) -> Vec<String> {
+        let mut keys = self.keys(obj.as_ref()).collect::<Vec<_>>();
+        match order {
+            KeyOrder::Lexicographic => {}
+            KeyOrder::Numeric => keys.sort_by(|a, b| numeric_cmp(a, b)),
+            KeyOrder::Insertion => {
+                let first_put = self.first_put(obj.as_ref());
+                keys.sort_by(|a, b| match (first_put.get(a), first_put.get(b)) {
+                    (Some(a), Some(b)) => self.ops.m.lamport_cmp(*a, *b),
+                    _ => Ordering::Equal,
+                });
+            }
+        }
+        keys
+    }
+
+    /// The id of the first op which put each key of the map `obj`.
+    fn first_put(&self, obj: &ExId) -> HashMap<String, OpId> {
+        let mut first = HashMap::new();
+        let obj = match self.exid_to_obj(obj) {
+            Ok(obj) => obj,
+            Err(_) => return first,
+        };
+        for op in self.ops.iter_obj(&obj).into_iter().flatten() {
+            if let Key::Map(p) = op.key {
+                first
+                    .entry(self.ops.m.props[p].clone())
+                    .and_modify(|id| {
+                        if self.ops.m.lamport_cmp(op.id, *id) == Ordering::Less {
+                            *id = op.id
+                        }
+                    })
+                    .or_insert(op.id);
+            }
+        }
+        first
+    }
+}
+
+/// Compare `a` and `b` treating each run of ASCII digits as a number.
+fn numeric_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_rest, mut b_rest) = (a, b);
+    loop {
+        match (a_rest.chars().next(), b_rest.chars().next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (a_num, a_tail) = split_digits(a_rest);
+                let (b_num, b_tail) = split_digits(b_rest);
+                let a_trimmed = a_num.trim_start_matches('0');
+                let b_trimmed = b_num.trim_start_matches('0');
+                // numbers without leading zeros compare by length first, then digit by digit
+                let ordering = a_trimmed
+                    .len()
+                    .cmp(&b_trimmed.len())
+                    .then_with(|| a_trimmed.cmp(b_trimmed));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_rest = a_tail;
+                b_rest = b_tail;
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a_rest = &a_rest[x.len_utf8()..];
+                b_rest = &b_rest[y.len_utf8()..];
+            }
+        }
+    }
+}
+
+fn split_digits(s: &str) -> (&str, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(end)
+}
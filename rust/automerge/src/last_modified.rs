@@ -0,0 +1,111 @@
+use crate::exid::ExId;
+use crate::types::{Key, Op, OpId};
+use crate::{ActorId, Automerge, AutomergeError, Change, ChangeHash, Prop};
+
+/// The change which last modified a value, returned by [`Automerge::last_modified`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastModified {
+    /// The hash of the change
+    pub hash: ChangeHash,
+    /// The actor which made the change
+    pub actor: ActorId,
+    /// When the change was made, in the units passed to
+    /// [`crate::transaction::CommitOptions::with_time`], if it recorded a time
+    pub time: Option<i64>,
+}
+
+impl LastModified {
+    fn new(change: &Change) -> Self {
+        Self {
+            hash: change.hash(),
+            actor: change.actor_id().clone(),
+            time: Some(change.timestamp()).filter(|t| *t != 0),
+        }
+    }
+}
+
+impl Automerge {
+    /// The change which last modified the value of `prop` in `obj`, or `None` if it has no value.
+    ///
+    /// This is the change which put the value [`Self::get`] returns, so when there is a conflict
+    /// it is the change which put the winner, chosen by the same [`crate::ConflictPolicy`] as
+    /// `get`. Incrementing a counter modifies it. The change is found from the ids of the ops of
+    /// the value, so this doesn't scan the history.
+    pub fn last_modified<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<LastModified>, AutomergeError> {
+        let prop = prop.into();
+        let id = self.exid_to_obj(obj.as_ref())?;
+        let ops = match &prop {
+            Prop::Map(p) => match self.ops.m.props.lookup(p) {
+                Some(p) => self.ops.search_prop(&id, p).ops,
+                None => return Ok(None),
+            },
+            Prop::Seq(n) => self.ops.search_nth(&id, *n).ops,
+        };
+        Ok(self
+            .winner(obj.as_ref(), &prop, ops)
+            .and_then(|op| self.last_modified_by(op)))
+    }
+
+    /// The change which last modified each prop of `obj` which has a value, like
+    /// [`Self::last_modified`], in the order of the props.
+    pub fn last_modified_all<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+    ) -> Result<Vec<(Prop, LastModified)>, AutomergeError> {
+        let exid = obj.as_ref();
+        let obj = self.exid_to_obj(exid)?;
+        let mut keys: Vec<(Key, Vec<&Op>)> = Vec::new();
+        for op in self.ops.iter_obj(&obj).into_iter().flatten() {
+            let key = op.elemid_or_key();
+            match keys.last_mut() {
+                Some((k, visible)) if *k == key => {
+                    if op.visible() {
+                        visible.push(op);
+                    }
+                }
+                _ => keys.push((key, op.visible().then(|| op).into_iter().collect())),
+            }
+        }
+        let result = keys
+            .into_iter()
+            .filter(|(_, visible)| !visible.is_empty())
+            .enumerate()
+            .filter_map(|(index, (key, visible))| {
+                let prop = match key {
+                    Key::Map(p) => Prop::Map(self.ops.m.props[p].clone()),
+                    Key::Seq(_) => Prop::Seq(index),
+                };
+                let modified = self.last_modified_by(self.winner(exid, &prop, visible)?)?;
+                Some((prop, modified))
+            })
+            .collect();
+        Ok(result)
+    }
+
+    /// The op of the visible `ops` of `prop` whose value [`Self::get`] returns.
+    fn winner<'a>(&self, obj: &ExId, prop: &Prop, ops: Vec<&'a Op>) -> Option<&'a Op> {
+        let mut values = ops
+            .iter()
+            .map(|op| (op.value(), self.id_to_exid(op.id)))
+            .collect::<Vec<_>>();
+        values.sort_by(|a, b| a.1.cmp(&b.1));
+        let (_, id) = self.resolve_conflict(obj, prop, values)?;
+        ops.into_iter().find(|op| self.id_to_exid(op.id) == id)
+    }
+
+    /// The change containing `op`, or the latest of its increments if it is a counter.
+    fn last_modified_by(&self, op: &Op) -> Option<LastModified> {
+        // the successors of a visible counter are its increments
+        let increments = op.succ.into_iter().filter(|_| op.is_counter());
+        std::iter::once(&op.id)
+            .chain(increments)
+            .copied()
+            .max_by(|a: &OpId, b: &OpId| self.ops.m.lamport_cmp(*a, *b))
+            .and_then(|id| self.change_index_for_op(id))
+            .map(|index| LastModified::new(&self.history[index]))
+    }
+}
@@ -0,0 +1,360 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::columnar::Key as DocKey;
+use crate::exid::ExId;
+use crate::storage::{self, load, parse, DocOp};
+use crate::types::{ElemId, ObjId, OpId};
+use crate::{
+    ActorId, Automerge, AutomergeError, ChangeHash, DocumentConfig, ObjType, OpType, Prop,
+    ScalarValue, Value,
+};
+
+/// A saved document whose objects are decoded when they are first read, returned by
+/// [`Automerge::load_lazy`].
+///
+/// Loading an [`Automerge`] decodes every op and change of the document up front. A
+/// `LazyDocument` only checks the framing and checksum of the saved data; reading an object
+/// decodes the op columns up to and including that object, and the result is cached so later
+/// reads of the same object are free. This makes answering a few queries about a large document,
+/// e.g. in a server which just wants the title of each document it stores, much cheaper.
+///
+/// A document loaded with [`Automerge::load_filtered`] instead decodes the objects chosen by its
+/// filter as it is loaded, and never decodes the others.
+///
+/// The change columns are never decoded, so the heads returned by [`Self::heads`] are those
+/// recorded in the document and are not verified against the changes until the document is fully
+/// loaded with [`Self::into_automerge`].
+#[derive(Debug)]
+pub struct LazyDocument {
+    doc: storage::Document<'static>,
+    config: DocumentConfig,
+    /// The types of the objects created by the ops decoded so far
+    types: HashMap<ObjId, ObjType>,
+    objects: HashMap<ObjId, LazyObject>,
+    /// Whether only the objects chosen by a filter were decoded, see
+    /// [`Automerge::load_filtered`]
+    filtered: bool,
+}
+
+#[derive(Debug)]
+enum LazyObject {
+    Map(BTreeMap<String, (Value<'static>, ExId)>),
+    Seq(Vec<(Value<'static>, ExId)>),
+}
+
+impl LazyDocument {
+    pub(crate) fn load(data: &[u8]) -> Result<Self, AutomergeError> {
+        let config = load::config(data)?.unwrap_or_default();
+        let chunks = load::split_chunks(data)
+            .into_iter()
+            .filter(|c| !load::is_settings_chunk(c))
+            .collect::<Vec<_>>();
+        let chunk = match chunks.as_slice() {
+            [chunk] if load::is_document_chunk(chunk) => chunk,
+            _ => return Err(load::Error::NotLazyLoadable.into()),
+        };
+        let (_, chunk) = storage::Chunk::parse(parse::Input::new(chunk))
+            .map_err(|e| load::Error::Parse(Box::new(e)))?;
+        if !chunk.checksum_valid() {
+            return Err(load::Error::BadChecksum.into());
+        }
+        let doc = match chunk {
+            storage::Chunk::Document(doc) => doc.into_owned(),
+            _ => return Err(load::Error::NotLazyLoadable.into()),
+        };
+        Ok(Self {
+            doc,
+            config,
+            types: HashMap::from([(ObjId::root(), ObjType::Map)]),
+            objects: HashMap::new(),
+            filtered: false,
+        })
+    }
+
+    pub(crate) fn load_filtered<F>(data: &[u8], mut filter: F) -> Result<Self, AutomergeError>
+    where
+        F: FnMut(&[Prop]) -> bool,
+    {
+        let mut doc = Self::load(data)?;
+        doc.filtered = true;
+        let Self {
+            doc: stored,
+            types,
+            objects,
+            ..
+        } = &mut doc;
+        let actors = stored.actors();
+        // objects are sorted by id and an object is always created after the object it is
+        // created in, so the path of an object is known by the time its ops are reached
+        let mut paths = HashMap::from([(ObjId::root(), Vec::new())]);
+        let mut ops: Vec<DocOp> = Vec::new();
+        let mut iter = stored.iter_ops();
+        loop {
+            let op = iter
+                .next()
+                .transpose()
+                .map_err(|e| load::Error::InflateDocument(Box::new(e)))?;
+            if let Some(first) = ops.first() {
+                if op.as_ref().map(|op| op.object) != Some(first.object) {
+                    let id = first.object;
+                    let object_ops = std::mem::take(&mut ops);
+                    if let Some(object) =
+                        decode_filtered(id, object_ops, types, actors, &mut paths, &mut filter)?
+                    {
+                        objects.insert(id, object);
+                    }
+                }
+            }
+            match op {
+                Some(op) => {
+                    if let Ok(OpType::Make(obj_type)) =
+                        load::parse_optype(op.action, ScalarValue::Null)
+                    {
+                        types.insert(ObjId(op.id), obj_type);
+                    }
+                    ops.push(op);
+                }
+                None => break,
+            }
+        }
+        // objects without any ops are empty
+        for (id, path) in paths {
+            if filter(&path) {
+                objects.insert(id, LazyObject::new(types[&id], Vec::new(), actors)?);
+            }
+        }
+        drop(iter);
+        Ok(doc)
+    }
+
+    /// The heads recorded in the saved document.
+    pub fn heads(&self) -> Vec<ChangeHash> {
+        self.doc.heads().to_vec()
+    }
+
+    /// The configuration stored alongside the document.
+    pub fn config(&self) -> &DocumentConfig {
+        &self.config
+    }
+
+    /// Get the winning value of `prop` in `obj`, as [`Automerge::get`] does.
+    ///
+    /// The winner is the value with the greatest id, which is what `get` returns for a document
+    /// without a [`crate::ConflictPolicy`]. Policies are not saved, so a document loaded lazily
+    /// never has any.
+    pub fn get<O: AsRef<ExId>, P: Into<Prop>>(
+        &mut self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<(Value<'static>, ExId)>, AutomergeError> {
+        Ok(match (self.object(obj.as_ref())?, prop.into()) {
+            (LazyObject::Map(map), Prop::Map(key)) => map.get(&key).cloned(),
+            (LazyObject::Seq(seq), Prop::Seq(index)) => seq.get(index).cloned(),
+            _ => None,
+        })
+    }
+
+    /// The keys of the map `obj`, in order. Empty for sequences.
+    pub fn keys<O: AsRef<ExId>>(&mut self, obj: O) -> Result<Vec<String>, AutomergeError> {
+        Ok(match self.object(obj.as_ref())? {
+            LazyObject::Map(map) => map.keys().cloned().collect(),
+            LazyObject::Seq(_) => Vec::new(),
+        })
+    }
+
+    /// The number of keys of the map or elements of the sequence `obj`.
+    pub fn length<O: AsRef<ExId>>(&mut self, obj: O) -> Result<usize, AutomergeError> {
+        Ok(match self.object(obj.as_ref())? {
+            LazyObject::Map(map) => map.len(),
+            LazyObject::Seq(seq) => seq.len(),
+        })
+    }
+
+    /// The contents of the text object `obj`, as [`Automerge::text`] returns.
+    pub fn text<O: AsRef<ExId>>(&mut self, obj: O) -> Result<String, AutomergeError> {
+        Ok(match self.object(obj.as_ref())? {
+            LazyObject::Seq(seq) => seq
+                .iter()
+                .filter_map(|(value, _)| match value {
+                    Value::Scalar(s) => match s.as_ref() {
+                        ScalarValue::Str(s) => Some(s.as_str()),
+                        _ => None,
+                    },
+                    Value::Object(_) => None,
+                })
+                .collect(),
+            LazyObject::Map(_) => String::new(),
+        })
+    }
+
+    /// The number of objects decoded so far.
+    pub fn decoded_objects(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Decode the rest of the document, verifying its changes and heads.
+    pub fn into_automerge(self) -> Result<Automerge, AutomergeError> {
+        Ok(Automerge::from_document(&self.doc, &mut None::<&mut ()>)?.with_config(self.config))
+    }
+
+    fn obj_id(&self, obj: &ExId) -> Result<ObjId, AutomergeError> {
+        match obj {
+            ExId::Root => Ok(ObjId::root()),
+            ExId::Id(counter, actor, _) => self
+                .doc
+                .actors()
+                .iter()
+                .position(|a| a == actor)
+                .map(|index| ObjId(OpId(*counter, index)))
+                .ok_or_else(|| AutomergeError::InvalidObjId(obj.to_string())),
+        }
+    }
+
+    fn object(&mut self, obj: &ExId) -> Result<&LazyObject, AutomergeError> {
+        let id = self.obj_id(obj)?;
+        if self.filtered && !self.objects.contains_key(&id) {
+            return Err(AutomergeError::NotLoaded(obj.to_string()));
+        }
+        if !self.objects.contains_key(&id) {
+            // ops are sorted by object, so everything before `id` is skipped without being
+            // collected, but we note the objects it creates so we know the type of `id`
+            let mut ops = Vec::new();
+            for op in self.doc.iter_ops() {
+                let op = op.map_err(|e| load::Error::InflateDocument(Box::new(e)))?;
+                if op.object > id {
+                    break;
+                }
+                if let Ok(OpType::Make(obj_type)) = load::parse_optype(op.action, ScalarValue::Null)
+                {
+                    self.types.insert(ObjId(op.id), obj_type);
+                }
+                if op.object == id {
+                    ops.push(op);
+                }
+            }
+            let obj_type = *self
+                .types
+                .get(&id)
+                .ok_or_else(|| AutomergeError::InvalidObjId(obj.to_string()))?;
+            let object = LazyObject::new(obj_type, ops, self.doc.actors())?;
+            self.objects.insert(id, object);
+        }
+        Ok(&self.objects[&id])
+    }
+}
+
+/// Decode the object `id` from its `ops` if its path is chosen by `filter`, noting the paths of
+/// the objects in it.
+fn decode_filtered<F>(
+    id: ObjId,
+    ops: Vec<DocOp>,
+    types: &HashMap<ObjId, ObjType>,
+    actors: &[ActorId],
+    paths: &mut HashMap<ObjId, Vec<Prop>>,
+    filter: &mut F,
+) -> Result<Option<LazyObject>, AutomergeError>
+where
+    F: FnMut(&[Prop]) -> bool,
+{
+    // an object with no path has been deleted or overwritten, so it can't be read
+    let (path, obj_type) = match (paths.remove(&id), types.get(&id)) {
+        (Some(path), Some(obj_type)) => (path, *obj_type),
+        _ => return Ok(None),
+    };
+    let object = LazyObject::new(obj_type, ops, actors)?;
+    let children: Vec<(Prop, &ExId)> = match &object {
+        LazyObject::Map(map) => map
+            .iter()
+            .filter(|(_, (v, _))| matches!(v, Value::Object(_)))
+            .map(|(k, (_, id))| (Prop::Map(k.clone()), id))
+            .collect(),
+        LazyObject::Seq(seq) => seq
+            .iter()
+            .enumerate()
+            .filter(|(_, (v, _))| matches!(v, Value::Object(_)))
+            .map(|(i, (_, id))| (Prop::Seq(i), id))
+            .collect(),
+    };
+    for (prop, child) in children {
+        // the id of an object is the id of the op which made it
+        if let ExId::Id(counter, _, actor) = child {
+            let mut child_path = path.clone();
+            child_path.push(prop);
+            paths.insert(ObjId(OpId(*counter, *actor)), child_path);
+        }
+    }
+    Ok(filter(&path).then(|| object))
+}
+
+impl LazyObject {
+    fn new(obj_type: ObjType, ops: Vec<DocOp>, actors: &[ActorId]) -> Result<Self, AutomergeError> {
+        let increments: HashMap<OpId, i64> = ops
+            .iter()
+            .filter_map(|op| match (op.action, &op.value) {
+                (5, ScalarValue::Int(by)) => Some((op.id, *by)),
+                _ => None,
+            })
+            .collect();
+        let exid = |id: OpId| ExId::Id(id.counter(), actors[id.actor()].clone(), id.actor());
+        let visible = |op: &DocOp| -> Result<Option<Value<'static>>, AutomergeError> {
+            let action = load::parse_optype(op.action, op.value.clone())
+                .map_err(|e| load::Error::InflateDocument(Box::new(e)))?;
+            Ok(match action {
+                OpType::Make(obj_type) if op.succ.is_empty() => Some(Value::Object(obj_type)),
+                // a counter stays visible when it has only been incremented
+                OpType::Put(ScalarValue::Counter(mut counter))
+                    if op.succ.iter().all(|s| increments.contains_key(s)) =>
+                {
+                    counter.increment(op.succ.iter().map(|s| increments[s]));
+                    Some(Value::Scalar(Cow::Owned(ScalarValue::Counter(counter))))
+                }
+                OpType::Put(value) if op.succ.is_empty() => Some(Value::Scalar(Cow::Owned(value))),
+                _ => None,
+            })
+        };
+        match obj_type {
+            ObjType::Map | ObjType::Table => {
+                // the visible op with the greatest id wins, as in `Automerge::get`
+                let mut map: BTreeMap<String, (Value<'static>, ExId)> = BTreeMap::new();
+                for op in &ops {
+                    if let DocKey::Prop(key) = &op.key {
+                        if let Some(value) = visible(op)? {
+                            let id = exid(op.id);
+                            if map.get(key.as_str()).map(|(_, w)| *w < id).unwrap_or(true) {
+                                map.insert(key.to_string(), (value, id));
+                            }
+                        }
+                    }
+                }
+                Ok(LazyObject::Map(map))
+            }
+            ObjType::List | ObjType::Text => {
+                // each element is its insert op followed by the ops which update it
+                let mut seq = Vec::new();
+                let mut current: Option<(ElemId, Option<(Value<'static>, ExId)>)> = None;
+                for op in &ops {
+                    let elem = match (&op.key, op.insert) {
+                        (_, true) => ElemId(op.id),
+                        (DocKey::Elem(elem), false) => *elem,
+                        (DocKey::Prop(_), false) => continue,
+                    };
+                    if current.as_ref().map(|(e, _)| *e) != Some(elem) {
+                        seq.extend(current.take().and_then(|(_, winner)| winner));
+                        current = Some((elem, None));
+                    }
+                    if let Some(value) = visible(op)? {
+                        let id = exid(op.id);
+                        if let Some((_, winner)) = current.as_mut() {
+                            if winner.as_ref().map(|(_, w)| *w < id).unwrap_or(true) {
+                                *winner = Some((value, id));
+                            }
+                        }
+                    }
+                }
+                seq.extend(current.and_then(|(_, winner)| winner));
+                Ok(LazyObject::Seq(seq))
+            }
+        }
+    }
+}
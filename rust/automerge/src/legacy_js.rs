@@ -0,0 +1,456 @@
+//! Importing documents from the JavaScript implementation of automerge before the binary format.
+//!
+//! Versions of the JavaScript library before 0.10 saved a document as the JSON array of its
+//! changes. Each change names its actor, sequence number and dependencies, and holds ops which
+//! refer to objects by UUID and to list elements by `<actor>:<counter>` ids:
+//!
+//! ```json
+//! [{"actor": "1f9c...", "seq": 1, "deps": {}, "ops": [
+//!     {"action": "makeList", "obj": "6d4e..."},
+//!     {"action": "ins", "obj": "6d4e...", "key": "_head", "elem": 1},
+//!     {"action": "set", "obj": "6d4e...", "key": "1f9c...:1", "value": "milk"},
+//!     {"action": "link", "obj": "00000000-0000-0000-0000-000000000000", "key": "todos", "value": "6d4e..."}
+//! ]}]
+//! ```
+//!
+//! [`Automerge::load_legacy_js`] replays these changes into a new document, one change for each
+//! legacy change by the same actor and with the same sequence number, message and dependencies.
+//! A change is made on top of exactly the changes it depended on, so concurrent edits merge as
+//! they did in the JavaScript library; the only difference is that the order of concurrent
+//! insertions at the same position may differ, because it is now decided by op ids rather than
+//! by element counters.
+use std::collections::{HashMap, HashSet};
+
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+
+use crate::exid::ExId;
+use crate::query;
+use crate::transaction::{CommitOptions, Transactable, Transaction, UnObserved};
+use crate::types::ElemId;
+use crate::{ActorId, Automerge, AutomergeError, ChangeHash, ObjType, ScalarValue};
+
+/// The UUID of the root object.
+const ROOT_UUID: &str = "00000000-0000-0000-0000-000000000000";
+
+/// The key of an `ins` op which inserts at the start of a list.
+const HEAD: &str = "_head";
+
+#[derive(Debug, thiserror::Error)]
+pub enum LegacyJsError {
+    #[error("invalid legacy change: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("the changes of `{actor}` from sequence number {seq} have missing dependencies")]
+    MissingDependencies { actor: String, seq: u64 },
+    #[error("unknown action `{0}`")]
+    UnknownAction(String),
+    #[error("unknown object `{0}`")]
+    UnknownObject(String),
+    #[error("unknown list element `{0}`")]
+    UnknownElement(String),
+    #[error("object `{0}` was created but never linked into the document")]
+    UnlinkedObject(String),
+    #[error("op `{0}` is missing its key")]
+    MissingKey(String),
+    #[error("invalid value for op `{0}`")]
+    InvalidValue(String),
+    #[error(transparent)]
+    Automerge(#[from] AutomergeError),
+}
+
+#[derive(Debug, Deserialize)]
+struct LegacyChange {
+    actor: String,
+    seq: u64,
+    #[serde(default)]
+    deps: HashMap<String, u64>,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    time: Option<i64>,
+    ops: Vec<LegacyOp>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct LegacyOp {
+    action: String,
+    obj: String,
+    #[serde(default)]
+    key: Option<String>,
+    #[serde(default)]
+    value: JsonValue,
+    #[serde(default)]
+    elem: Option<u64>,
+    #[serde(default)]
+    datatype: Option<String>,
+}
+
+impl Automerge {
+    /// Load a document saved by the JavaScript implementation before the binary format, see the
+    /// [module documentation](crate::legacy_js).
+    ///
+    /// `json` is the array of changes, in any order. The returned document has a new random
+    /// actor id.
+    pub fn load_legacy_js(json: &JsonValue) -> Result<Self, LegacyJsError> {
+        let mut changes: Vec<LegacyChange> = serde_json::from_value(json.clone())?;
+        changes.sort_by(|a, b| (&a.actor, a.seq).cmp(&(&b.actor, b.seq)));
+
+        let mut doc = Automerge::new();
+        let mut import = Import {
+            objects: HashMap::from([(ROOT_UUID.to_string(), (ExId::Root, ObjType::Map))]),
+            elems: HashMap::new(),
+        };
+        let mut hashes: HashMap<(String, u64), ChangeHash> = HashMap::new();
+        let mut pending = changes;
+        while !pending.is_empty() {
+            let before = pending.len();
+            let mut waiting = Vec::new();
+            for change in pending {
+                let mut deps = change.deps.clone();
+                if change.seq > 1 {
+                    deps.insert(change.actor.clone(), change.seq - 1);
+                }
+                let heads = deps
+                    .into_iter()
+                    .filter(|(_, seq)| *seq > 0)
+                    .map(|dep| hashes.get(&dep).copied())
+                    .collect::<Option<Vec<_>>>();
+                match heads {
+                    Some(heads) => {
+                        let hash = import.apply(&mut doc, &change, &heads)?;
+                        hashes.insert((change.actor, change.seq), hash);
+                    }
+                    None => waiting.push(change),
+                }
+            }
+            if waiting.len() == before {
+                let first = &waiting[0];
+                return Err(LegacyJsError::MissingDependencies {
+                    actor: first.actor.clone(),
+                    seq: first.seq,
+                });
+            }
+            pending = waiting;
+        }
+        doc.set_actor(ActorId::random());
+        Ok(doc)
+    }
+}
+
+/// The state of an import which is shared between changes.
+struct Import {
+    /// The ids and types of the objects by UUID
+    objects: HashMap<String, (ExId, ObjType)>,
+    /// The ids of the list elements by legacy element id
+    elems: HashMap<String, ExId>,
+}
+
+/// The value of a `set` or `link` op.
+enum NewValue {
+    Scalar(ScalarValue),
+    /// The UUID, type and ops so far of the object being linked
+    Object(String, ObjType, Vec<LegacyOp>),
+}
+
+/// The state of the change being imported.
+#[derive(Default)]
+struct ChangeState {
+    /// Objects which have been made but not yet linked, and the ops on them so far
+    unlinked: HashMap<String, (ObjType, Vec<LegacyOp>)>,
+    /// Elements which have been inserted but not yet given a value, and the element they follow
+    inserted: HashMap<String, Option<String>>,
+}
+
+impl Import {
+    /// Make `change` on top of `heads` and add it to `doc`.
+    fn apply(
+        &mut self,
+        doc: &mut Automerge,
+        change: &LegacyChange,
+        heads: &[ChangeHash],
+    ) -> Result<ChangeHash, LegacyJsError> {
+        let actor = match uuid::Uuid::parse_str(&change.actor) {
+            Ok(uuid) => ActorId::from(uuid),
+            Err(_) => ActorId::from(change.actor.as_bytes()),
+        };
+        let mut options = CommitOptions::default();
+        if let Some(message) = &change.message {
+            options.set_message(message.clone());
+        }
+        if let Some(time) = change.time {
+            options.set_time(time);
+        }
+
+        let current = heads.iter().collect::<HashSet<_>>() == doc.get_heads().iter().collect();
+        let mut fork = if current {
+            None
+        } else {
+            Some(doc.fork_at(heads)?)
+        };
+        let target = fork.as_mut().unwrap_or(doc);
+        target.set_actor(actor);
+        let mut tx = target.transaction();
+        let mut state = ChangeState::default();
+        for op in &change.ops {
+            self.apply_op(&mut tx, &mut state, &change.actor, op.clone())?;
+        }
+        if let Some(uuid) = state.unlinked.keys().next() {
+            return Err(LegacyJsError::UnlinkedObject(uuid.clone()));
+        }
+        let hash = tx.commit_with(options);
+
+        if let Some(fork) = fork {
+            let change = fork.get_change_by_hash(&hash).cloned();
+            doc.apply_changes(change)?;
+        }
+        Ok(hash)
+    }
+
+    fn apply_op(
+        &mut self,
+        tx: &mut Transaction<'_, UnObserved>,
+        state: &mut ChangeState,
+        actor: &str,
+        op: LegacyOp,
+    ) -> Result<(), LegacyJsError> {
+        let make = match op.action.as_str() {
+            "makeMap" => Some(ObjType::Map),
+            "makeTable" => Some(ObjType::Table),
+            "makeList" => Some(ObjType::List),
+            "makeText" => Some(ObjType::Text),
+            _ => None,
+        };
+        if let Some(obj_type) = make {
+            state.unlinked.insert(op.obj, (obj_type, Vec::new()));
+            return Ok(());
+        }
+        // ops on an object which isn't in the document yet are made once it is linked
+        if let Some((_, ops)) = state.unlinked.get_mut(&op.obj) {
+            ops.push(op);
+            return Ok(());
+        }
+        let (obj, obj_type) = self
+            .objects
+            .get(&op.obj)
+            .cloned()
+            .ok_or_else(|| LegacyJsError::UnknownObject(op.obj.clone()))?;
+        let key = op
+            .key
+            .clone()
+            .ok_or_else(|| LegacyJsError::MissingKey(op.action.clone()))?;
+        let is_seq = matches!(obj_type, ObjType::List | ObjType::Text);
+        match op.action.as_str() {
+            "ins" => {
+                let elem = op
+                    .elem
+                    .ok_or_else(|| LegacyJsError::MissingKey(op.action.clone()))?;
+                let prev = if key == HEAD { None } else { Some(key) };
+                state.inserted.insert(format!("{}:{}", actor, elem), prev);
+            }
+            "set" | "link" => {
+                let value = if op.action == "link" {
+                    let uuid = op
+                        .value
+                        .as_str()
+                        .ok_or_else(|| LegacyJsError::InvalidValue(op.action.clone()))?;
+                    let (obj_type, ops) = state
+                        .unlinked
+                        .remove(uuid)
+                        .ok_or_else(|| LegacyJsError::UnknownObject(uuid.to_string()))?;
+                    NewValue::Object(uuid.to_string(), obj_type, ops)
+                } else {
+                    NewValue::Scalar(scalar(&op)?)
+                };
+                let inserted = state.inserted.remove(&key);
+                let (id, linked) = match value {
+                    NewValue::Scalar(value) => {
+                        match (inserted, is_seq) {
+                            (Some(prev), true) => {
+                                let index = self.index_after(tx, &obj, prev.as_deref())?;
+                                tx.insert(&obj, index, value)?;
+                                let id = tx.get(&obj, index)?.map(|(_, id)| id);
+                                self.elems.extend(id.map(|id| (key, id)));
+                            }
+                            (_, true) => {
+                                let index = self.index_of(tx, &obj, &key)?;
+                                tx.put(&obj, index, value)?;
+                            }
+                            (_, false) => tx.put(&obj, key.as_str(), value)?,
+                        }
+                        return Ok(());
+                    }
+                    NewValue::Object(uuid, obj_type, ops) => {
+                        let id = match (inserted, is_seq) {
+                            (Some(prev), true) => {
+                                let index = self.index_after(tx, &obj, prev.as_deref())?;
+                                let id = tx.insert_object(&obj, index, obj_type)?;
+                                self.elems.insert(key, id.clone());
+                                id
+                            }
+                            (_, true) => {
+                                let index = self.index_of(tx, &obj, &key)?;
+                                tx.put_object(&obj, index, obj_type)?
+                            }
+                            (_, false) => tx.put_object(&obj, key.as_str(), obj_type)?,
+                        };
+                        (id, (uuid, obj_type, ops))
+                    }
+                };
+                // make the ops on the new object now that it exists
+                let (uuid, obj_type, ops) = linked;
+                self.objects.insert(uuid, (id, obj_type));
+                for op in ops {
+                    self.apply_op(tx, state, actor, op)?;
+                }
+            }
+            "del" if is_seq => {
+                let index = self.index_of(tx, &obj, &key)?;
+                tx.delete(&obj, index)?;
+            }
+            "del" => tx.delete(&obj, key.as_str())?,
+            "inc" => {
+                let by = op
+                    .value
+                    .as_i64()
+                    .ok_or_else(|| LegacyJsError::InvalidValue(op.action.clone()))?;
+                if is_seq {
+                    let index = self.index_of(tx, &obj, &key)?;
+                    tx.increment(&obj, index, by)?;
+                } else {
+                    tx.increment(&obj, key.as_str(), by)?;
+                }
+            }
+            _ => return Err(LegacyJsError::UnknownAction(op.action)),
+        }
+        Ok(())
+    }
+
+    fn elem_id(&self, doc: &Automerge, elem: &str) -> Result<ElemId, LegacyJsError> {
+        self.elems
+            .get(elem)
+            .and_then(|id| doc.exid_to_opid(id))
+            .map(ElemId)
+            .ok_or_else(|| LegacyJsError::UnknownElement(elem.to_string()))
+    }
+
+    /// The index of the visible element `elem` of `obj`.
+    fn index_of(
+        &self,
+        tx: &Transaction<'_, UnObserved>,
+        obj: &ExId,
+        elem: &str,
+    ) -> Result<usize, LegacyJsError> {
+        let doc = &*tx.doc;
+        let obj = doc.exid_to_obj(obj)?;
+        let elem_id = self.elem_id(doc, elem)?;
+        doc.ops
+            .search(&obj, query::ElemIdPos::new(elem_id))
+            .index()
+            .ok_or_else(|| LegacyJsError::UnknownElement(elem.to_string()))
+    }
+
+    /// The index at which to insert an element after `prev` in `obj`, which may have been
+    /// deleted.
+    fn index_after(
+        &self,
+        tx: &Transaction<'_, UnObserved>,
+        obj: &ExId,
+        prev: Option<&str>,
+    ) -> Result<usize, LegacyJsError> {
+        let prev = match prev {
+            Some(prev) => prev,
+            None => return Ok(0),
+        };
+        let doc = &*tx.doc;
+        let obj = doc.exid_to_obj(obj)?;
+        let prev_id = self.elem_id(doc, prev)?;
+        doc.ops
+            .index_after(&obj, prev_id)
+            .ok_or_else(|| LegacyJsError::UnknownElement(prev.to_string()))
+    }
+}
+
+fn scalar(op: &LegacyOp) -> Result<ScalarValue, LegacyJsError> {
+    let invalid = || LegacyJsError::InvalidValue(op.action.clone());
+    Ok(match (op.datatype.as_deref(), &op.value) {
+        (Some("counter"), value) => ScalarValue::counter(value.as_i64().ok_or_else(invalid)?),
+        (Some("timestamp"), value) => ScalarValue::Timestamp(value.as_i64().ok_or_else(invalid)?),
+        (Some(_), _) => return Err(invalid()),
+        (None, JsonValue::Null) => ScalarValue::Null,
+        (None, JsonValue::Bool(b)) => ScalarValue::Boolean(*b),
+        (None, JsonValue::Number(n)) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => ScalarValue::Int(i),
+            (None, Some(f)) => ScalarValue::F64(f),
+            (None, None) => return Err(invalid()),
+        },
+        (None, JsonValue::String(s)) => ScalarValue::Str(s.into()),
+        (None, _) => return Err(invalid()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{Value, ROOT};
+
+    const ALICE: &str = "aaaaaaaa-aaaa-aaaa-aaaa-aaaaaaaaaaaa";
+    const BOB: &str = "bbbbbbbb-bbbb-bbbb-bbbb-bbbbbbbbbbbb";
+    const LIST: &str = "11111111-1111-1111-1111-111111111111";
+    const ITEM: &str = "22222222-2222-2222-2222-222222222222";
+
+    fn elem(actor: &str, n: u64) -> String {
+        format!("{}:{}", actor, n)
+    }
+
+    #[test]
+    fn load_legacy_js_document() {
+        let json = json!([
+            // bob's change depends on alice's first change and is concurrent with her second
+            {"actor": BOB, "seq": 1, "deps": {ALICE: 1}, "ops": [
+                {"action": "del", "obj": LIST, "key": elem(ALICE, 1)},
+                {"action": "ins", "obj": LIST, "key": elem(ALICE, 1), "elem": 3},
+                {"action": "set", "obj": LIST, "key": elem(BOB, 3), "value": "eggs"},
+                {"action": "set", "obj": ROOT_UUID, "key": "title", "value": "bob"},
+            ]},
+            {"actor": ALICE, "seq": 1, "deps": {}, "message": "create", "time": 1000, "ops": [
+                {"action": "makeList", "obj": LIST},
+                {"action": "ins", "obj": LIST, "key": "_head", "elem": 1},
+                {"action": "set", "obj": LIST, "key": elem(ALICE, 1), "value": "milk"},
+                {"action": "ins", "obj": LIST, "key": elem(ALICE, 1), "elem": 2},
+                {"action": "makeMap", "obj": ITEM},
+                {"action": "set", "obj": ITEM, "key": "count", "value": 1, "datatype": "counter"},
+                {"action": "link", "obj": LIST, "key": elem(ALICE, 2), "value": ITEM},
+                {"action": "link", "obj": ROOT_UUID, "key": "todos", "value": LIST},
+            ]},
+            {"actor": ALICE, "seq": 2, "deps": {}, "ops": [
+                {"action": "inc", "obj": ITEM, "key": "count", "value": 2},
+                {"action": "set", "obj": ROOT_UUID, "key": "title", "value": "alice"},
+            ]},
+        ]);
+        let doc = Automerge::load_legacy_js(&json).unwrap();
+        assert_eq!(doc.get_changes(&[]).unwrap().len(), 3);
+        let first = doc.get_changes(&[]).unwrap()[0].clone();
+        assert_eq!(first.message().map(String::as_str), Some("create"));
+        assert_eq!(first.timestamp(), 1000);
+
+        let (_, todos) = doc.get(ROOT, "todos").unwrap().unwrap();
+        let items: Vec<_> = doc.list_range(&todos, ..).map(|(_, v, _)| v).collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0], Value::str("eggs"));
+        let (_, item) = doc.get(&todos, 1).unwrap().unwrap();
+        assert_eq!(
+            doc.get(&item, "count").unwrap().unwrap().0,
+            Value::counter(3)
+        );
+        // the concurrent puts conflict
+        assert_eq!(doc.get_all(ROOT, "title").unwrap().len(), 2);
+
+        let missing = json!([{"actor": BOB, "seq": 2, "deps": {}, "ops": []}]);
+        assert!(matches!(
+            Automerge::load_legacy_js(&missing),
+            Err(LegacyJsError::MissingDependencies { .. })
+        ));
+    }
+}
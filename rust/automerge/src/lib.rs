@@ -54,32 +54,94 @@ macro_rules! __log {
      }
  }
 
+#[cfg(feature = "wasm-abi")]
+pub mod abi;
+mod actor_map;
+mod actor_metadata;
+mod actor_policy;
+mod audit;
 mod autocommit;
 mod automerge;
 mod autoserde;
+mod bytes_stream;
+mod capabilities;
 mod change;
+mod change_graph;
 mod clock;
 mod clocks;
+mod codec;
 mod columnar;
+mod commit_hooks;
+mod conflict_policy;
+mod content_hash;
 mod convert;
+mod cursor;
+mod deep_eq;
+mod doc_handle;
+mod document_config;
+pub mod duplicates;
 mod error;
 mod exid;
+mod extra_bytes;
+mod fallible_observer;
+mod frozen;
+mod history_fence;
+mod history_states;
 mod indexed_cache;
+mod interner;
+#[cfg(feature = "serde_json")]
+pub mod json;
+mod key_order;
 mod keys;
 mod keys_at;
+mod last_modified;
+mod lazy_document;
 mod legacy;
+#[cfg(feature = "serde_json")]
+pub mod legacy_js;
+mod limits;
+mod list_meta;
 mod list_range;
 mod list_range_at;
+mod list_window;
+mod load_options;
+mod lookup_cache;
 mod map_range;
 mod map_range_at;
+pub mod materialize;
+mod message_index;
+mod numeric;
+mod object_stats;
 mod op_observer;
 mod op_set;
+mod op_sources;
 mod op_tree;
 mod parents;
+mod patch_serde;
+mod path_cache;
+mod progress;
+mod projection;
+pub mod proof;
 mod query;
+mod raw_ops;
+mod read_transaction;
+pub mod repo;
+mod reserved;
+mod schema;
 mod sequence_tree;
+mod signing;
+mod snapshot;
+mod sorted_list;
 mod storage;
+pub mod storage_adapter;
 pub mod sync;
+mod tags;
+#[cfg(feature = "testgen")]
+pub mod testgen;
+mod text_attribution;
+mod text_diff;
+mod text_session;
+mod text_spans;
 pub mod transaction;
 mod types;
 mod value;
@@ -88,25 +150,68 @@ mod values;
 mod visualisation;
 
 pub use crate::automerge::Automerge;
+pub use actor_metadata::{ActorMetadata, ACTOR_METADATA_KEY};
+pub use actor_policy::ActorPolicy;
+pub use audit::{AuditProblem, AuditReport};
 pub use autocommit::{AutoCommit, AutoCommitWithObs};
 pub use autoserde::AutoSerde;
+pub use bytes_stream::{BytesReader, BYTES_CHUNK_SIZE};
+pub use capabilities::{capabilities, Capabilities};
 pub use change::{Change, LoadError as LoadChangeError};
+pub use change_graph::ChangeGraph;
+pub use codec::ChunkCodec;
+pub use commit_hooks::{BeforeCommitHook, CommitHook, HookId, PendingCommit, PendingOp};
+pub use conflict_policy::{ConflictPolicy, ConflictResolver};
+pub use cursor::Cursor;
+pub use doc_handle::{DocHandle, HandleEvent, WorkerStopped};
+pub use document_config::DocumentConfig;
 pub use error::AutomergeError;
+pub use error::ErrorCategory;
 pub use error::InvalidActorId;
 pub use error::InvalidChangeHashSlice;
+pub use error::InvalidTimestamp;
 pub use exid::ExId as ObjId;
+pub use fallible_observer::{Fallible, FallibleOpObserver, ObserverError};
+pub use frozen::FrozenDoc;
+pub use history_states::HistoryStates;
+pub use key_order::KeyOrder;
 pub use keys::Keys;
 pub use keys_at::KeysAt;
+pub use last_modified::LastModified;
+pub use lazy_document::LazyDocument;
 pub use legacy::Change as ExpandedChange;
+pub use limits::{LimitExceeded, Limits};
+pub use list_meta::ListElementMeta;
 pub use list_range::ListRange;
 pub use list_range_at::ListRangeAt;
+pub use list_window::{ListWindow, ListWindowItem};
+pub use load_options::{LoadOptions, VerificationMode};
 pub use map_range::MapRange;
 pub use map_range_at::MapRangeAt;
+pub use message_index::CommitQuery;
+pub use numeric::{NumericMode, NumericType};
+pub use object_stats::{DocumentStats, MemoryUsage, ObjectStats};
 pub use op_observer::OpObserver;
 pub use op_observer::Patch;
+pub use op_observer::PatchSink;
+pub use op_observer::SpillingOpObserver;
 pub use op_observer::VecOpObserver;
+pub use op_tree::NodeSize;
 pub use parents::Parents;
+pub use path_cache::PathCache;
+pub use progress::{ApplyProgress, CancellationToken};
+pub use projection::{Row, RowOp, RowProjection};
+pub use raw_ops::{RawKey, RawOp, RawOps};
+pub use read_transaction::ReadTransaction;
+pub use reserved::REGISTRY_MARKER_KEY;
+pub use schema::{PathPattern, Schema, SchemaType, SchemaViolation};
 pub use sequence_tree::SequenceTree;
+pub use snapshot::Snapshot;
+pub use storage::verify::VerificationError;
+pub use tags::TAGS_KEY;
+pub use text_attribution::TextAttribution;
+pub use text_session::{TextEdit, TextSession};
+pub use text_spans::{TextSpan, TextSpans};
 pub use types::{ActorId, ChangeHash, ObjType, OpType, Prop};
 pub use value::{ScalarValue, Value};
 pub use values::Values;
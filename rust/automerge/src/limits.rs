@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use crate::types::{ObjId, Op, OpType, ScalarValue};
+use crate::{Automerge, Change};
+
+/// Limits on the shape of a document, which protect e.g. servers accepting changes from
+/// untrusted peers from documents which nest objects thousands of levels deep or hold huge
+/// values. See [`Automerge::with_limits`].
+///
+/// Each limit is `None`, meaning unlimited, by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// The deepest an object may be nested, where the objects in the root map are at depth 1.
+    pub max_depth: Option<usize>,
+    /// The most ops a single change may contain.
+    pub max_ops_per_change: Option<usize>,
+    /// The longest a string or bytes value may be, in bytes.
+    pub max_value_len: Option<usize>,
+}
+
+/// The limit of a [`Limits`] which an operation or change would exceed.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum LimitExceeded {
+    #[error("object would be nested {depth} levels deep, more than the limit of {max}")]
+    Depth { depth: usize, max: usize },
+    #[error("change has {ops} ops, more than the limit of {max}")]
+    OpsPerChange { ops: usize, max: usize },
+    #[error("value is {len} bytes long, more than the limit of {max}")]
+    ValueLen { len: usize, max: usize },
+}
+
+impl Limits {
+    fn check_ops(&self, ops: usize) -> Result<(), LimitExceeded> {
+        match self.max_ops_per_change {
+            Some(max) if ops > max => Err(LimitExceeded::OpsPerChange { ops, max }),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_value(&self, value: &ScalarValue) -> Result<(), LimitExceeded> {
+        let len = match value {
+            ScalarValue::Str(s) => s.len(),
+            ScalarValue::Bytes(b) => b.len(),
+            _ => return Ok(()),
+        };
+        match self.max_value_len {
+            Some(max) if len > max => Err(LimitExceeded::ValueLen { len, max }),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_depth(&self, depth: usize) -> Result<(), LimitExceeded> {
+        match self.max_depth {
+            Some(max) if depth > max => Err(LimitExceeded::Depth { depth, max }),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_action(
+        &self,
+        action: &OpType,
+        depth: impl FnOnce() -> usize,
+    ) -> Result<(), LimitExceeded> {
+        match action {
+            OpType::Put(value) => self.check_value(value),
+            OpType::Make(_) => self.check_depth(depth() + 1),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Automerge {
+    /// Enforce `limits` on the changes made to and applied to this document, see [`Limits`].
+    ///
+    /// Transactions return [`crate::AutomergeError::LimitExceeded`] from the operation which would
+    /// exceed a limit, and applying, loading incrementally or syncing a change which exceeds one
+    /// fails with the same error.
+    ///
+    /// Applying a batch of changes is **not** atomic. The number of ops and the length of the
+    /// values of every change in the batch are checked before any of them are applied, but the
+    /// depth of the objects a change makes depends on the changes applied before it, so it is
+    /// checked as each change is applied. A change which is too deep fails the call and leaves
+    /// the changes of the batch before it applied. To discard the whole batch instead, apply it to
+    /// a [fork](Self::fork) of the document and only keep the fork if the call succeeds.
+    ///
+    /// This does not check the document itself, to load a document from an untrusted source
+    /// with limits use [`crate::LoadOptions::with_limits`].
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Change the limits enforced on this document, see [`Self::with_limits`].
+    pub fn set_limits(&mut self, limits: Limits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    /// The limits enforced on this document, see [`Self::with_limits`].
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Check a new op with `action` in `obj`, made by a transaction which has made `pending` ops
+    /// so far.
+    pub(crate) fn check_local_op(
+        &self,
+        pending: usize,
+        obj: &ObjId,
+        action: &OpType,
+    ) -> Result<(), LimitExceeded> {
+        self.limits.check_ops(pending + 1)?;
+        self.limits.check_action(action, || self.ops.depth(obj))
+    }
+
+    /// Check a document which has just been loaded, whose changes weren't checked as they were
+    /// applied.
+    pub(crate) fn check_loaded(&self) -> Result<(), LimitExceeded> {
+        for change in &self.history {
+            self.limits.check_ops(change.len())?;
+        }
+        if self.limits.max_value_len.is_some() {
+            for (_, op) in self.ops.iter() {
+                if let OpType::Put(value) = &op.action {
+                    self.limits.check_value(value)?;
+                }
+            }
+        }
+        self.limits.check_depth(self.ops.max_depth())
+    }
+
+    /// Check the number of ops and the values of `change`, which don't depend on the document it
+    /// is applied to.
+    pub(crate) fn check_change(&self, change: &Change) -> Result<(), LimitExceeded> {
+        self.limits.check_ops(change.len())?;
+        if self.limits.max_value_len.is_some() {
+            for op in change.iter_ops() {
+                self.limits.check_value(&op.val)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the depth of the objects made by `ops`, the ops of a change which is about to be
+    /// applied.
+    pub(crate) fn check_change_depth(&self, ops: &[(ObjId, Op)]) -> Result<(), LimitExceeded> {
+        if self.limits.max_depth.is_none() {
+            return Ok(());
+        }
+        // objects made by earlier ops of the change aren't in the document yet
+        let mut made = HashMap::new();
+        for (obj, op) in ops {
+            if let OpType::Make(_) = op.action {
+                let depth = made
+                    .get(obj)
+                    .copied()
+                    .unwrap_or_else(|| self.ops.depth(obj))
+                    + 1;
+                self.limits.check_depth(depth)?;
+                made.insert(ObjId(op.id), depth);
+            }
+        }
+        Ok(())
+    }
+}
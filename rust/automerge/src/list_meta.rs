@@ -0,0 +1,53 @@
+use crate::exid::ExId;
+use crate::query;
+use crate::{ActorId, Automerge, AutomergeError, ChangeHash};
+
+/// Who inserted an element of a list or text object and when, returned by
+/// [`Automerge::list_elements_meta`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListElementMeta {
+    /// The current index of the element
+    pub index: usize,
+    /// The id of the element, as in [`crate::TextSpan::id`]
+    pub id: ExId,
+    /// The actor which inserted the element
+    pub actor: ActorId,
+    /// The hash of the change which inserted the element, or `None` if that change is beneath
+    /// the history fence
+    pub hash: Option<ChangeHash>,
+    /// When the element was inserted, in the units passed to
+    /// [`crate::transaction::CommitOptions::with_time`], if the change recorded a time
+    pub time: Option<i64>,
+}
+
+impl Automerge {
+    /// Who inserted each element of the list or text object `obj` and when, in order.
+    ///
+    /// This is about the insertion of the element, so it doesn't change when the value at an
+    /// index is overwritten; use [`Self::last_modified`] for that. It is found from the ids of
+    /// the elements, so this doesn't replay the history.
+    pub fn list_elements_meta<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+    ) -> Result<Vec<ListElementMeta>, AutomergeError> {
+        let obj = self.exid_to_obj(obj.as_ref())?;
+        let query = self.ops.search(&obj, query::ListVals::new());
+        let result = query
+            .ops
+            .iter()
+            .filter_map(|op| op.elemid())
+            .enumerate()
+            .map(|(index, elem)| {
+                let change = self.change_index_for_op(elem.0).map(|i| &self.history[i]);
+                ListElementMeta {
+                    index,
+                    id: self.id_to_exid(elem.0),
+                    actor: self.ops.m.actors[elem.0.actor()].clone(),
+                    hash: change.map(|c| c.hash()),
+                    time: change.map(|c| c.timestamp()).filter(|t| *t != 0),
+                }
+            })
+            .collect();
+        Ok(result)
+    }
+}
@@ -1,17 +1,23 @@
 use crate::{exid::ExId, Value};
 
+use crate::types::ObjId;
 use crate::{query, Automerge};
 use std::ops::RangeBounds;
 
 #[derive(Debug)]
 pub struct ListRange<'a, R: RangeBounds<usize>> {
     range: Option<query::ListRange<'a, R>>,
+    obj: ObjId,
     doc: &'a Automerge,
 }
 
 impl<'a, R: RangeBounds<usize>> ListRange<'a, R> {
-    pub(crate) fn new(doc: &'a Automerge, range: Option<query::ListRange<'a, R>>) -> Self {
-        Self { range, doc }
+    pub(crate) fn new(
+        doc: &'a Automerge,
+        obj: ObjId,
+        range: Option<query::ListRange<'a, R>>,
+    ) -> Self {
+        Self { range, obj, doc }
     }
 }
 
@@ -19,9 +25,13 @@ impl<'a, R: RangeBounds<usize>> Iterator for ListRange<'a, R> {
     type Item = (usize, Value<'a>, ExId);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.range
-            .as_mut()?
-            .next()
-            .map(|(idx, value, id)| (idx, value, self.doc.id_to_exid(id)))
+        let (idx, value, id) = self.range.as_mut()?.next()?;
+        let (value, id) = self.doc.with_conflict_policy(
+            &self.obj,
+            idx.into(),
+            (value, self.doc.id_to_exid(id)),
+            None,
+        );
+        Some((idx, value, id))
     }
 }
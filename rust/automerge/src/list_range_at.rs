@@ -1,17 +1,30 @@
 use crate::{exid::ExId, Value};
 use std::ops::RangeBounds;
 
+use crate::types::{ChangeHash, ObjId};
 use crate::{query, Automerge};
 
 #[derive(Debug)]
 pub struct ListRangeAt<'a, R: RangeBounds<usize>> {
     range: Option<query::ListRangeAt<'a, R>>,
+    obj: ObjId,
+    heads: Vec<ChangeHash>,
     doc: &'a Automerge,
 }
 
 impl<'a, R: RangeBounds<usize>> ListRangeAt<'a, R> {
-    pub(crate) fn new(doc: &'a Automerge, range: Option<query::ListRangeAt<'a, R>>) -> Self {
-        Self { range, doc }
+    pub(crate) fn new(
+        doc: &'a Automerge,
+        obj: ObjId,
+        heads: &[ChangeHash],
+        range: Option<query::ListRangeAt<'a, R>>,
+    ) -> Self {
+        Self {
+            range,
+            obj,
+            heads: heads.to_vec(),
+            doc,
+        }
     }
 }
 
@@ -19,9 +32,13 @@ impl<'a, R: RangeBounds<usize>> Iterator for ListRangeAt<'a, R> {
     type Item = (usize, Value<'a>, ExId);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.range
-            .as_mut()?
-            .next()
-            .map(|(key, value, id)| (key, value, self.doc.id_to_exid(id)))
+        let (idx, value, id) = self.range.as_mut()?.next()?;
+        let (value, id) = self.doc.with_conflict_policy(
+            &self.obj,
+            idx.into(),
+            (value, self.doc.id_to_exid(id)),
+            Some(&self.heads),
+        );
+        Some((idx, value, id))
     }
 }
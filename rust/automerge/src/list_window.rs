@@ -0,0 +1,152 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::exid::ExId;
+use crate::types::{ElemId, ObjId, Op};
+use crate::{Automerge, AutomergeError, ChangeHash, Value};
+
+/// A contiguous run of the elements of a list, returned by [`Automerge::list_window`].
+///
+/// This is meant for virtual scrolling: an application renders only the window which is on
+/// screen, keyed by each element's [`ListWindowItem::elem`], and uses
+/// [`Automerge::is_window_current`] after applying changes to find out whether the window needs
+/// to be fetched again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListWindow {
+    /// The list the window is over.
+    pub obj: ExId,
+    /// The index of the first element of the window.
+    pub start: usize,
+    /// The length of the window which was asked for, `items` is shorter if the list ends first.
+    pub len: usize,
+    /// The length of the whole list.
+    pub total: usize,
+    pub items: Vec<ListWindowItem>,
+    generation: WindowGeneration,
+}
+
+/// An element of a [`ListWindow`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListWindowItem {
+    pub value: Value<'static>,
+    /// The id of the element, which stays the same when the value is changed or elements are
+    /// inserted or deleted around it.
+    pub elem: ExId,
+    /// The id of the operation which set the value.
+    pub id: ExId,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct WindowGeneration {
+    heads: Vec<ChangeHash>,
+    /// A hash of the element and value ids of the window, and the length of the list
+    digest: u64,
+}
+
+impl ListWindow {
+    /// A hash of the ids of the window's elements and values and the length of the list, which
+    /// changes whenever the contents of the window or the scrollable length do.
+    pub fn generation(&self) -> u64 {
+        self.generation.digest
+    }
+}
+
+impl Automerge {
+    /// Get up to `len` elements of the list or text `obj`, starting at index `start`.
+    ///
+    /// Finding the start of the window uses the op tree's index, so the cost depends on the size
+    /// of the window rather than its position in the list.
+    pub fn list_window<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        start: usize,
+        len: usize,
+    ) -> Result<ListWindow, AutomergeError> {
+        let exid = obj.as_ref();
+        let obj = self.exid_to_obj(exid)?;
+        let ops = self.window_ops(&obj, start, len);
+        let total = self.length(exid);
+        let items = ops
+            .iter()
+            .map(|(elem, op)| ListWindowItem {
+                value: op.value().into_owned(),
+                elem: self.id_to_exid(elem.0),
+                id: self.id_to_exid(op.id),
+            })
+            .collect();
+        Ok(ListWindow {
+            obj: exid.clone(),
+            start,
+            len,
+            total,
+            items,
+            generation: WindowGeneration {
+                heads: self.get_heads(),
+                digest: digest(&ops, total),
+            },
+        })
+    }
+
+    /// Whether fetching `window` again would return the same elements and values.
+    ///
+    /// This is cheap if the document has not changed since the window was fetched, otherwise it
+    /// costs about as much as fetching the window.
+    pub fn is_window_current(&self, window: &ListWindow) -> bool {
+        if window.generation.heads == self.get_heads() {
+            return true;
+        }
+        match self.exid_to_obj(&window.obj) {
+            Ok(obj) => {
+                let ops = self.window_ops(&obj, window.start, window.len);
+                digest(&ops, self.length(&window.obj)) == window.generation.digest
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// The element id and winning op of each visible element of `obj` in `start..start + len`.
+    fn window_ops(&self, obj: &ObjId, start: usize, len: usize) -> Vec<(ElemId, &Op)> {
+        let mut result: Vec<(ElemId, &Op)> = Vec::new();
+        if len == 0 {
+            return result;
+        }
+        let pos = match self.ops.search_nth(obj, start).ops_pos.first() {
+            Some(pos) => *pos,
+            None => return result,
+        };
+        let tree = match self.ops.iter_obj(obj) {
+            Some(tree) => tree,
+            None => return result,
+        };
+        // conflicting values of an element are adjacent and the last visible one wins
+        for op in tree.skip(pos) {
+            if !op.visible() {
+                continue;
+            }
+            let elem = match op.elemid() {
+                Some(elem) => elem,
+                None => continue,
+            };
+            match result.last_mut() {
+                Some((last, winner)) if *last == elem => *winner = op,
+                _ => {
+                    if result.len() == len {
+                        break;
+                    }
+                    result.push((elem, op));
+                }
+            }
+        }
+        result
+    }
+}
+
+fn digest(ops: &[(ElemId, &Op)], total: usize) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    total.hash(&mut hasher);
+    for (elem, op) in ops {
+        elem.hash(&mut hasher);
+        op.id.hash(&mut hasher);
+    }
+    hasher.finish()
+}
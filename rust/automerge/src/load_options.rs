@@ -0,0 +1,72 @@
+use crate::Limits;
+
+/// Options for [`crate::Automerge::load_with_options`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// The number of threads to decode chunks on. Values above 1 only have an effect when the
+    /// `rayon` feature is enabled, otherwise chunks are decoded on the calling thread.
+    pub threads: usize,
+    /// How thoroughly to check the data before loading it.
+    pub verification: VerificationMode,
+    /// The limits the loaded document must be within, which are then enforced on the changes
+    /// applied to it, see [`crate::Automerge::with_limits`].
+    pub limits: Limits,
+}
+
+impl LoadOptions {
+    /// Decode chunks on a pool of `threads` threads.
+    ///
+    /// Saved data consists of an optional document chunk followed by any number of change chunks,
+    /// for example from [`crate::Automerge::save_incremental`]. Each chunk is decompressed,
+    /// checksummed and decoded on the pool and the resulting changes are then applied in order on
+    /// the calling thread. A document chunk is decoded alongside the change chunks but is itself
+    /// loaded on a single thread, so this only helps when there are many change chunks.
+    pub fn parallel(threads: usize) -> Self {
+        Self {
+            threads,
+            ..Default::default()
+        }
+    }
+
+    /// Check the data with `verification` before loading it.
+    pub fn with_verification(mut self, verification: VerificationMode) -> Self {
+        self.verification = verification;
+        self
+    }
+
+    /// Fail to load documents which exceed `limits`, with
+    /// [`crate::AutomergeError::LimitExceeded`], and enforce `limits` on the loaded document.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            threads: 1,
+            verification: VerificationMode::default(),
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// How thoroughly [`crate::Automerge::load_with_options`] checks the data it is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VerificationMode {
+    /// Check as much as is needed to load the data. Unknown columns, which may have been added
+    /// by a newer version of the format, are ignored.
+    Standard,
+    /// Before loading, check every chunk for trailing bytes, unknown columns, missing or out of
+    /// range head indices and ops with out of range counters, actors or successors, failing with
+    /// a [`crate::VerificationError`] describing the first problem. Intended for servers loading
+    /// documents from untrusted sources.
+    Strict,
+}
+
+impl Default for VerificationMode {
+    fn default() -> Self {
+        Self::Standard
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use fxhash::FxBuildHasher;
+
+use crate::exid::ExId;
+use crate::types::ObjId;
+
+/// The number of entries of each kind a [`LookupCache`] holds by default.
+pub(crate) const DEFAULT_CAPACITY: usize = 256;
+
+/// Remembers recent lookups so repeatedly reading or writing the same properties doesn't have to
+/// search the op tree each time, see [`crate::Automerge::set_cache_capacity`].
+///
+/// Two kinds of lookup are cached: the object an [`ExId`] from another document refers to, which
+/// needs the actor to be looked up, and the position in an object's op tree at which the ops of
+/// a map key start. Positions go stale whenever ops are inserted before them, so callers must
+/// check a cached position before using it. Objects only go away when a transaction is rolled
+/// back, which clears them.
+///
+/// The cache sits behind a mutex so that lookups, which only borrow the document, can fill it.
+/// Each lookup takes the lock once, and both kinds of entry are evicted least recently used
+/// first in constant time. The cache of a [`crate::FrozenDoc`] is bypassed, so that reads of a
+/// frozen document shared between threads never wait for each other.
+#[derive(Debug)]
+pub(crate) struct LookupCache {
+    inner: Mutex<Inner>,
+    /// Whether the document is frozen, in which case the cache, and the sequence indexes of the
+    /// op trees, which are also behind a mutex, are bypassed.
+    frozen: bool,
+}
+
+#[derive(Debug)]
+struct Inner {
+    capacity: usize,
+    objects: Lru<ExId, ObjId>,
+    props: Lru<(ObjId, usize), usize>,
+}
+
+impl LookupCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                capacity,
+                objects: Lru::default(),
+                props: Lru::default(),
+            }),
+            frozen: false,
+        }
+    }
+
+    #[cfg(any(test, feature = "seq-index"))]
+    pub(crate) fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Bypass the cache, and the sequence indexes, while `frozen` is true.
+    pub(crate) fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.with(|inner| inner.capacity).unwrap_or(0)
+    }
+
+    /// Change the capacity, evicting entries if it has shrunk. A capacity of zero disables the
+    /// cache.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.with(|inner| {
+            inner.capacity = capacity;
+            inner.objects.shrink(capacity);
+            inner.props.shrink(capacity);
+        });
+    }
+
+    pub(crate) fn object(&self, id: &ExId) -> Option<ObjId> {
+        if self.frozen {
+            return None;
+        }
+        self.with(|inner| inner.objects.get(id)).flatten()
+    }
+
+    pub(crate) fn insert_object(&self, id: ExId, obj: ObjId) {
+        if self.frozen {
+            return;
+        }
+        self.with(|inner| inner.objects.insert(id, obj, inner.capacity));
+    }
+
+    /// Forget every object, for when objects have been removed from the document.
+    pub(crate) fn clear_objects(&mut self) {
+        self.with(|inner| inner.objects.clear());
+    }
+
+    /// Look up the ops for the map key `prop` of `obj` with `search`, which is given where they
+    /// were last found to start and returns its result and where they start now.
+    ///
+    /// The cache is locked for the whole lookup, so reading and updating the start only takes
+    /// the lock once.
+    pub(crate) fn with_prop_start<F, R>(&self, obj: ObjId, prop: usize, search: F) -> R
+    where
+        F: FnOnce(Option<usize>) -> (R, Option<usize>),
+    {
+        if self.frozen {
+            return search(None).0;
+        }
+        match self.inner.lock() {
+            Ok(mut inner) => {
+                let (result, start) = search(inner.props.get(&(obj, prop)));
+                if let Some(start) = start {
+                    let capacity = inner.capacity;
+                    inner.props.insert((obj, prop), start, capacity);
+                }
+                result
+            }
+            Err(_) => search(None).0,
+        }
+    }
+
+    /// Run `f` on the cache, or return `None` if a panic while it was locked has poisoned it.
+    fn with<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut Inner) -> R,
+    {
+        self.inner.lock().ok().map(|mut inner| f(&mut inner))
+    }
+}
+
+impl Default for LookupCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+/// A copy of a document starts with an empty cache of the same capacity.
+impl Clone for LookupCache {
+    fn clone(&self) -> Self {
+        Self::new(self.capacity())
+    }
+}
+
+/// The cache doesn't affect the content of a document, so any two caches are equal.
+impl PartialEq for LookupCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// Marks the ends of the list of entries.
+const NONE: usize = usize::MAX;
+
+/// A least recently used cache.
+///
+/// The entries are kept in a doubly linked list, most recently used first, whose links are
+/// indexes into `entries`, so using an entry moves it to the front and evicting one takes it
+/// from the back, both in constant time.
+#[derive(Debug)]
+struct Lru<K, V> {
+    index: HashMap<K, usize, FxBuildHasher>,
+    entries: Vec<Entry<K, V>>,
+    /// The most recently used entry
+    head: usize,
+    /// The least recently used entry
+    tail: usize,
+}
+
+#[derive(Debug)]
+struct Entry<K, V> {
+    key: K,
+    value: V,
+    prev: usize,
+    next: usize,
+}
+
+impl<K, V> Default for Lru<K, V> {
+    fn default() -> Self {
+        Self {
+            index: Default::default(),
+            entries: Vec::new(),
+            head: NONE,
+            tail: NONE,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Copy> Lru<K, V> {
+    fn get(&mut self, key: &K) -> Option<V> {
+        let i = *self.index.get(key)?;
+        self.move_to_front(i);
+        Some(self.entries[i].value)
+    }
+
+    fn insert(&mut self, key: K, value: V, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        if let Some(&i) = self.index.get(&key) {
+            self.entries[i].value = value;
+            self.move_to_front(i);
+            return;
+        }
+        self.shrink(capacity - 1);
+        let i = self.entries.len();
+        self.entries.push(Entry {
+            key: key.clone(),
+            value,
+            prev: NONE,
+            next: NONE,
+        });
+        self.index.insert(key, i);
+        self.push_front(i);
+    }
+
+    /// Evict the least recently used entries until there are at most `capacity`.
+    fn shrink(&mut self, capacity: usize) {
+        while self.entries.len() > capacity {
+            let i = self.tail;
+            self.unlink(i);
+            // move the last entry into the evicted entry's slot, so the entries stay contiguous
+            let last = self.entries.len() - 1;
+            if i != last {
+                let (prev, next) = (self.entries[last].prev, self.entries[last].next);
+                self.entries.swap(i, last);
+                self.relink(prev, next, i);
+                *self.index.get_mut(&self.entries[i].key).unwrap() = i;
+            }
+            let evicted = self.entries.pop().unwrap();
+            self.index.remove(&evicted.key);
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn move_to_front(&mut self, i: usize) {
+        if self.head != i {
+            self.unlink(i);
+            self.push_front(i);
+        }
+    }
+
+    fn push_front(&mut self, i: usize) {
+        self.entries[i].prev = NONE;
+        self.entries[i].next = self.head;
+        if self.head != NONE {
+            self.entries[self.head].prev = i;
+        }
+        self.head = i;
+        if self.tail == NONE {
+            self.tail = i;
+        }
+    }
+
+    fn unlink(&mut self, i: usize) {
+        let Entry { prev, next, .. } = self.entries[i];
+        if prev == NONE {
+            self.head = next;
+        } else {
+            self.entries[prev].next = next;
+        }
+        if next == NONE {
+            self.tail = prev;
+        } else {
+            self.entries[next].prev = prev;
+        }
+    }
+
+    /// Point the neighbours `prev` and `next` of an entry which has moved to slot `i` at it.
+    fn relink(&mut self, prev: usize, next: usize, i: usize) {
+        if prev == NONE {
+            self.head = i;
+        } else {
+            self.entries[prev].next = i;
+        }
+        if next == NONE {
+            self.tail = i;
+        } else {
+            self.entries[next].prev = i;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut lru = Lru::default();
+        for i in 0..4 {
+            lru.insert(i, i * 10, 3);
+        }
+        // 0 was evicted to make room for 3
+        assert_eq!(lru.get(&0), None);
+        // using 1 makes 2 the least recently used
+        assert_eq!(lru.get(&1), Some(10));
+        lru.insert(4, 40, 3);
+        assert_eq!(lru.get(&2), None);
+        assert_eq!(lru.get(&3), Some(30));
+        // updating an entry doesn't evict anything
+        lru.insert(1, 11, 3);
+        assert_eq!(
+            [1, 3, 4].iter().map(|k| lru.get(k)).collect::<Vec<_>>(),
+            vec![Some(11), Some(30), Some(40)]
+        );
+        lru.shrink(1);
+        assert_eq!(lru.entries.len(), 1);
+        assert_eq!(lru.get(&4), Some(40));
+        lru.insert(5, 50, 0);
+        assert_eq!(lru.get(&5), None);
+    }
+
+    #[test]
+    fn frozen_caches_are_bypassed() {
+        let mut cache = LookupCache::default();
+        let obj = ObjId::root();
+        cache.set_frozen(true);
+        cache.insert_object(ExId::Root, obj);
+        assert_eq!(cache.object(&ExId::Root), None);
+        assert_eq!(
+            cache.with_prop_start(obj, 0, |start| (start, Some(1))),
+            None
+        );
+        assert_eq!(cache.with_prop_start(obj, 0, |start| (start, None)), None);
+
+        cache.set_frozen(false);
+        cache.insert_object(ExId::Root, obj);
+        assert_eq!(cache.object(&ExId::Root), Some(obj));
+        cache.with_prop_start(obj, 0, |_| ((), Some(1)));
+        assert_eq!(
+            cache.with_prop_start(obj, 0, |start| (start, None)),
+            Some(1)
+        );
+    }
+}
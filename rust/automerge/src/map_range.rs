@@ -1,17 +1,30 @@
 use crate::{exid::ExId, Value};
 use std::ops::RangeBounds;
 
+use crate::types::ObjId;
 use crate::{query, Automerge};
 
 #[derive(Debug)]
 pub struct MapRange<'a, R: RangeBounds<String>> {
     range: Option<query::MapRange<'a, R>>,
+    obj: ObjId,
     doc: &'a Automerge,
 }
 
 impl<'a, R: RangeBounds<String>> MapRange<'a, R> {
-    pub(crate) fn new(doc: &'a Automerge, range: Option<query::MapRange<'a, R>>) -> Self {
-        Self { range, doc }
+    pub(crate) fn new(
+        doc: &'a Automerge,
+        obj: ObjId,
+        range: Option<query::MapRange<'a, R>>,
+    ) -> Self {
+        Self { range, obj, doc }
+    }
+
+    fn resolve(&self, key: &'a str, value: Value<'a>, id: ExId) -> (&'a str, Value<'a>, ExId) {
+        let (value, id) = self
+            .doc
+            .with_conflict_policy(&self.obj, key.into(), (value, id), None);
+        (key, value, id)
     }
 }
 
@@ -19,18 +32,14 @@ impl<'a, R: RangeBounds<String>> Iterator for MapRange<'a, R> {
     type Item = (&'a str, Value<'a>, ExId);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.range
-            .as_mut()?
-            .next()
-            .map(|(key, value, id)| (key, value, self.doc.id_to_exid(id)))
+        let (key, value, id) = self.range.as_mut()?.next()?;
+        Some(self.resolve(key, value, self.doc.id_to_exid(id)))
     }
 }
 
 impl<'a, R: RangeBounds<String>> DoubleEndedIterator for MapRange<'a, R> {
     fn next_back(&mut self) -> Option<Self::Item> {
-        self.range
-            .as_mut()?
-            .next_back()
-            .map(|(key, value, id)| (key, value, self.doc.id_to_exid(id)))
+        let (key, value, id) = self.range.as_mut()?.next_back()?;
+        Some(self.resolve(key, value, self.doc.id_to_exid(id)))
     }
 }
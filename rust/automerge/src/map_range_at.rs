@@ -1,17 +1,37 @@
 use crate::{exid::ExId, Value};
 use std::ops::RangeBounds;
 
+use crate::types::{ChangeHash, ObjId};
 use crate::{query, Automerge};
 
 #[derive(Debug)]
 pub struct MapRangeAt<'a, R: RangeBounds<String>> {
     range: Option<query::MapRangeAt<'a, R>>,
+    obj: ObjId,
+    heads: Vec<ChangeHash>,
     doc: &'a Automerge,
 }
 
 impl<'a, R: RangeBounds<String>> MapRangeAt<'a, R> {
-    pub(crate) fn new(doc: &'a Automerge, range: Option<query::MapRangeAt<'a, R>>) -> Self {
-        Self { range, doc }
+    pub(crate) fn new(
+        doc: &'a Automerge,
+        obj: ObjId,
+        heads: &[ChangeHash],
+        range: Option<query::MapRangeAt<'a, R>>,
+    ) -> Self {
+        Self {
+            range,
+            obj,
+            heads: heads.to_vec(),
+            doc,
+        }
+    }
+
+    fn resolve(&self, key: &'a str, value: Value<'a>, id: ExId) -> (&'a str, Value<'a>, ExId) {
+        let (value, id) =
+            self.doc
+                .with_conflict_policy(&self.obj, key.into(), (value, id), Some(&self.heads));
+        (key, value, id)
     }
 }
 
@@ -19,18 +39,14 @@ impl<'a, R: RangeBounds<String>> Iterator for MapRangeAt<'a, R> {
     type Item = (&'a str, Value<'a>, ExId);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.range
-            .as_mut()?
-            .next()
-            .map(|(key, value, id)| (key, value, self.doc.id_to_exid(id)))
+        let (key, value, id) = self.range.as_mut()?.next()?;
+        Some(self.resolve(key, value, self.doc.id_to_exid(id)))
     }
 }
 
 impl<'a, R: RangeBounds<String>> DoubleEndedIterator for MapRangeAt<'a, R> {
     fn next_back(&mut self) -> Option<Self::Item> {
-        self.range
-            .as_mut()?
-            .next_back()
-            .map(|(key, value, id)| (key, value, self.doc.id_to_exid(id)))
+        let (key, value, id) = self.range.as_mut()?.next_back()?;
+        Some(self.resolve(key, value, self.doc.id_to_exid(id)))
     }
 }
@@ -0,0 +1,310 @@
+//! Keeping application data structures in sync with a document.
+//!
+//! A [`MaterializeTarget`] is any structure which can be updated by the same operations that
+//! patches describe. [`Automerge::materialize_into`] builds the current state of a document in a
+//! target and [`apply_patches`] then keeps it up to date with the patches produced by a
+//! [`crate::VecOpObserver`], so the target always reflects the document without the application
+//! re-reading it.
+//!
+//! Every method receives the path from the root to the object being modified, as returned by
+//! [`crate::Parents::path`], as well as the object's id. A target which mirrors the whole
+//! document can index its objects by id, while one which only cares about part of the document
+//! can match on the path and ignore everything else.
+use crate::exid::ExId;
+use crate::{Automerge, ObjType, Patch, Prop, Value};
+
+/// A structure which can be updated by patches, see the [module documentation](self).
+///
+/// When a value is an object, [`Value::Object`], the id passed alongside it is the id of the new
+/// object, which later calls will use as `obj` to fill it in.
+pub trait MaterializeTarget {
+    /// `prop` of `obj` has been set to `value`.
+    fn put(&mut self, path: &[(ExId, Prop)], obj: &ExId, prop: Prop, value: Value<'_>, id: &ExId);
+
+    /// `value` has been inserted at `index` of the sequence `obj`.
+    fn insert(
+        &mut self,
+        path: &[(ExId, Prop)],
+        obj: &ExId,
+        index: usize,
+        value: Value<'_>,
+        id: &ExId,
+    );
+
+    /// The characters of `value` have been inserted at `index` of the text `obj`, each as an
+    /// element of its own.
+    ///
+    /// Patches don't carry the ids of the characters, so by default each one is passed to
+    /// [`Self::insert`] with the id of `obj`.
+    fn splice_text(&mut self, path: &[(ExId, Prop)], obj: &ExId, index: usize, value: &str) {
+        for (i, c) in value.chars().enumerate() {
+            self.insert(path, obj, index + i, Value::str(&c.to_string()), obj);
+        }
+    }
+
+    /// The counter at `prop` of `obj` has been incremented by `by`.
+    fn increment(&mut self, path: &[(ExId, Prop)], obj: &ExId, prop: Prop, by: i64);
+
+    /// `prop` has been removed from `obj`.
+    fn delete(&mut self, path: &[(ExId, Prop)], obj: &ExId, prop: Prop);
+}
+
+/// Apply each of `patches` to `target`, in order.
+pub fn apply_patches<T, I>(target: &mut T, patches: I)
+where
+    T: MaterializeTarget + ?Sized,
+    I: IntoIterator<Item = Patch>,
+{
+    for patch in patches {
+        apply_patch(target, patch);
+    }
+}
+
+/// Apply a single patch to `target`.
+pub fn apply_patch<T: MaterializeTarget + ?Sized>(target: &mut T, patch: Patch) {
+    match patch {
+        Patch::Put {
+            path,
+            obj,
+            prop,
+            value: (value, id),
+            ..
+        } => target.put(&path, &obj, prop, value, &id),
+        Patch::Insert {
+            path,
+            obj,
+            index,
+            value: (value, id),
+        } => target.insert(&path, &obj, index, value, &id),
+        Patch::Increment {
+            path,
+            obj,
+            prop,
+            value: (by, _),
+        } => target.increment(&path, &obj, prop, by),
+        Patch::Delete { path, obj, prop } => target.delete(&path, &obj, prop),
+        Patch::Splice {
+            path,
+            obj,
+            index,
+            values,
+        } => {
+            for (i, (value, id)) in values.into_iter().enumerate() {
+                target.insert(&path, &obj, index + i, value, &id);
+            }
+        }
+        Patch::SpliceText {
+            path,
+            obj,
+            index,
+            value,
+        } => target.splice_text(&path, &obj, index, &value),
+        Patch::DeleteRange {
+            path,
+            obj,
+            index,
+            length,
+        } => {
+            for _ in 0..length {
+                target.delete(&path, &obj, Prop::Seq(index));
+            }
+        }
+    }
+}
+
+impl Automerge {
+    /// Build the current state of the document in `target`, as if every object had just been
+    /// created.
+    ///
+    /// The root object is assumed to exist already, so the first calls are puts of the keys of
+    /// the root map. Objects are filled in depth first, each immediately after it is created.
+    pub fn materialize_into<T: MaterializeTarget + ?Sized>(&self, target: &mut T) {
+        let mut path = Vec::new();
+        self.materialize_object(&ExId::Root, ObjType::Map, &mut path, target);
+    }
+
+    fn materialize_object<T: MaterializeTarget + ?Sized>(
+        &self,
+        obj: &ExId,
+        obj_type: ObjType,
+        path: &mut Vec<(ExId, Prop)>,
+        target: &mut T,
+    ) {
+        match obj_type {
+            ObjType::Map | ObjType::Table => {
+                for (key, value, id) in self.map_range(obj, ..) {
+                    let prop = Prop::Map(key.to_string());
+                    self.materialize_value(obj, prop, value, id, path, target);
+                }
+            }
+            ObjType::List | ObjType::Text => {
+                for (index, value, id) in self.list_range(obj, ..) {
+                    self.materialize_value(obj, Prop::Seq(index), value, id, path, target);
+                }
+            }
+        }
+    }
+
+    fn materialize_value<T: MaterializeTarget + ?Sized>(
+        &self,
+        obj: &ExId,
+        prop: Prop,
+        value: Value<'_>,
+        id: ExId,
+        path: &mut Vec<(ExId, Prop)>,
+        target: &mut T,
+    ) {
+        let child_type = match &value {
+            Value::Object(obj_type) => Some(*obj_type),
+            Value::Scalar(_) => None,
+        };
+        match &prop {
+            Prop::Map(_) => target.put(path, obj, prop.clone(), value, &id),
+            Prop::Seq(index) => target.insert(path, obj, *index, value, &id),
+        }
+        if let Some(child_type) = child_type {
+            path.push((obj.clone(), prop));
+            self.materialize_object(&id, child_type, path, target);
+            path.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use super::*;
+    use crate::transaction::Observed;
+    use crate::transaction::Transactable;
+    use crate::{AutoCommit, AutoCommitWithObs, ScalarValue, VecOpObserver, ROOT};
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Leaf {
+        Scalar(ScalarValue),
+        Counter(i64),
+        Object(ExId),
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Node {
+        Map(BTreeMap<String, Leaf>),
+        List(Vec<Leaf>),
+    }
+
+    /// Mirrors a whole document, indexing objects by id.
+    #[derive(Debug, PartialEq)]
+    struct Mirror(HashMap<ExId, Node>);
+
+    impl Mirror {
+        fn new() -> Self {
+            Mirror(HashMap::from([(ROOT, Node::Map(BTreeMap::new()))]))
+        }
+
+        fn leaf(&mut self, value: Value<'_>, id: &ExId) -> Leaf {
+            match value {
+                Value::Object(ObjType::Map | ObjType::Table) => {
+                    self.0.insert(id.clone(), Node::Map(BTreeMap::new()));
+                    Leaf::Object(id.clone())
+                }
+                Value::Object(ObjType::List | ObjType::Text) => {
+                    self.0.insert(id.clone(), Node::List(Vec::new()));
+                    Leaf::Object(id.clone())
+                }
+                Value::Scalar(s) => match s.as_ref() {
+                    ScalarValue::Counter(c) => Leaf::Counter(c.into()),
+                    s => Leaf::Scalar(s.clone()),
+                },
+            }
+        }
+
+        fn slot(&mut self, obj: &ExId, prop: Prop) -> &mut Leaf {
+            match (self.0.get_mut(obj).unwrap(), prop) {
+                (Node::Map(m), Prop::Map(k)) => m.get_mut(&k).unwrap(),
+                (Node::List(l), Prop::Seq(i)) => &mut l[i],
+                _ => panic!("mismatched prop"),
+            }
+        }
+    }
+
+    impl MaterializeTarget for Mirror {
+        fn put(&mut self, _: &[(ExId, Prop)], obj: &ExId, prop: Prop, value: Value<'_>, id: &ExId) {
+            let leaf = self.leaf(value, id);
+            match (self.0.get_mut(obj).unwrap(), prop) {
+                (Node::Map(m), Prop::Map(k)) => {
+                    m.insert(k, leaf);
+                }
+                (Node::List(l), Prop::Seq(i)) => l[i] = leaf,
+                _ => panic!("mismatched prop"),
+            }
+        }
+
+        fn insert(
+            &mut self,
+            _: &[(ExId, Prop)],
+            obj: &ExId,
+            index: usize,
+            value: Value<'_>,
+            id: &ExId,
+        ) {
+            let leaf = self.leaf(value, id);
+            match self.0.get_mut(obj).unwrap() {
+                Node::List(l) => l.insert(index, leaf),
+                Node::Map(_) => panic!("insert into map"),
+            }
+        }
+
+        fn increment(&mut self, _: &[(ExId, Prop)], obj: &ExId, prop: Prop, by: i64) {
+            match self.slot(obj, prop) {
+                Leaf::Counter(c) => *c += by,
+                _ => panic!("increment of non counter"),
+            }
+        }
+
+        fn delete(&mut self, _: &[(ExId, Prop)], obj: &ExId, prop: Prop) {
+            match (self.0.get_mut(obj).unwrap(), prop) {
+                (Node::Map(m), Prop::Map(k)) => {
+                    m.remove(&k);
+                }
+                (Node::List(l), Prop::Seq(i)) => {
+                    l.remove(i);
+                }
+                _ => panic!("mismatched prop"),
+            }
+        }
+    }
+
+    fn materialized(doc: &AutoCommitWithObs<Observed<VecOpObserver>>) -> Mirror {
+        let mut mirror = Mirror::new();
+        doc.materialize_into(&mut mirror);
+        mirror
+    }
+
+    #[test]
+    fn patches_keep_a_materialized_view_in_sync() {
+        for observer in [VecOpObserver::default(), VecOpObserver::coalescing()] {
+            let mut doc = AutoCommit::new().with_observer(observer);
+            doc.put(ROOT, "title", "hello").unwrap();
+            doc.commit();
+            let mut mirror = materialized(&doc);
+            doc.observer().take_patches();
+
+            let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+            let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+            doc.splice_text(&text, 0, 0, "hello world").unwrap();
+            doc.splice_text(&text, 2, 3, "").unwrap();
+            let map = doc.insert_object(&list, 0, ObjType::Map).unwrap();
+            doc.put(&map, "count", ScalarValue::counter(1)).unwrap();
+            doc.increment(&map, "count", 2).unwrap();
+            doc.insert(&list, 1, 10).unwrap();
+            doc.insert(&list, 1, 20).unwrap();
+            doc.delete(&list, 2).unwrap();
+            doc.put(ROOT, "title", "goodbye").unwrap();
+            doc.delete(ROOT, "title").unwrap();
+            doc.commit();
+
+            apply_patches(&mut mirror, doc.observer().take_patches());
+            assert_eq!(mirror, materialized(&doc));
+        }
+    }
+}
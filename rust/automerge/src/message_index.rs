@@ -0,0 +1,87 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::Change;
+
+/// What [`crate::Automerge::find_commits`] searches commit messages for. Both kinds of query
+/// ignore case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommitQuery {
+    /// Messages which contain the string anywhere.
+    Substring(String),
+    /// Messages with a word which starts with the string, where words are runs of alphanumeric
+    /// characters. `"pric"` matches "Add pricing section" but not "Fix caprice".
+    WordPrefix(String),
+}
+
+impl CommitQuery {
+    /// Whether `message` matches, without an index.
+    pub(crate) fn matches(&self, message: &str) -> bool {
+        match self {
+            CommitQuery::Substring(s) => message.to_lowercase().contains(&s.to_lowercase()),
+            CommitQuery::WordPrefix(p) => {
+                let p = p.to_lowercase();
+                words(message).any(|w| w.starts_with(&p))
+            }
+        }
+    }
+}
+
+/// An index of the messages of the changes in a document, see
+/// [`crate::Automerge::enable_message_index`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MessageIndex {
+    /// The lowercase message of every change which has one, by index in the history
+    messages: Vec<(usize, String)>,
+    /// The changes containing each lowercase word
+    words: BTreeMap<String, BTreeSet<usize>>,
+}
+
+impl MessageIndex {
+    pub(crate) fn new<'a, I: Iterator<Item = &'a Change>>(history: I) -> Self {
+        let mut index = Self::default();
+        for (i, change) in history.enumerate() {
+            index.insert(i, change);
+        }
+        index
+    }
+
+    pub(crate) fn insert(&mut self, history_index: usize, change: &Change) {
+        if let Some(message) = change.message() {
+            for word in words(message) {
+                self.words.entry(word).or_default().insert(history_index);
+            }
+            self.messages.push((history_index, message.to_lowercase()));
+        }
+    }
+
+    /// The indexes in the history of the changes matching `query`, in order.
+    pub(crate) fn find(&self, query: &CommitQuery) -> Vec<usize> {
+        match query {
+            CommitQuery::Substring(s) => {
+                let s = s.to_lowercase();
+                self.messages
+                    .iter()
+                    .filter(|(_, m)| m.contains(&s))
+                    .map(|(i, _)| *i)
+                    .collect()
+            }
+            CommitQuery::WordPrefix(p) => {
+                let p = p.to_lowercase();
+                let found: BTreeSet<usize> = self
+                    .words
+                    .range(p.clone()..)
+                    .take_while(|(w, _)| w.starts_with(&p))
+                    .flat_map(|(_, changes)| changes.iter().copied())
+                    .collect();
+                found.into_iter().collect()
+            }
+        }
+    }
+}
+
+fn words(message: &str) -> impl Iterator<Item = String> + '_ {
+    message
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+}
@@ -0,0 +1,189 @@
+use crate::exid::ExId;
+use crate::types::{ObjId, Op, OpType};
+use crate::{Automerge, AutomergeError, DocumentConfig, Prop, ScalarValue};
+
+/// The key of the [`DocumentConfig`] setting which records the [`NumericMode`] of a document.
+const NUMERIC_MODE_KEY: &str = "automerge.numeric_mode";
+
+/// How a document treats numbers written by peers which only have one number type, like
+/// JavaScript, see [`Automerge::set_numeric_mode`].
+///
+/// A JavaScript peer reads an `Int`, a `Uint` and an `F64` all as a JavaScript number, which is a
+/// float. If it writes the number back, e.g. because it saves a whole object it read, the new
+/// value is written as an `F64`, or as an `Int` if it happens to be whole, so the type the value
+/// was originally written with is lost, and an integer larger than 2^53 also loses precision.
+///
+/// The mode is part of the [`DocumentConfig`] of the document, so every peer must agree on it:
+/// it is saved with the document, and merging or loading incremental data from a document with
+/// another mode fails with [`AutomergeError::ConfigMismatch`]. As the sync protocol does not
+/// exchange configuration, peers which only sync must each set the same mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericMode {
+    /// Numbers are read with the type they were last written with, which is the default
+    Native,
+    /// A float which overwrote an integer with the same value, as a JavaScript peer writing back
+    /// a number it read does, is read as the integer. A whole float put by this document over
+    /// such an integer is written as the integer, so the integer type is kept by every peer.
+    ///
+    /// The encoding of changes already records whether each number is an `Int`, a `Uint` or an
+    /// `F64`, and each op records the ops it overwrote, so no new encoding is needed: the
+    /// original number is found by following the ops a float overwrote while their values are
+    /// equal. Comparing values as floats means an integer too large to be represented exactly
+    /// is still recovered exactly.
+    PreserveIntegers,
+}
+
+impl Default for NumericMode {
+    fn default() -> Self {
+        Self::Native
+    }
+}
+
+impl NumericMode {
+    /// The mode recorded in `config`, the default if it records none or one this version doesn't
+    /// know.
+    fn from_config(config: &DocumentConfig) -> Self {
+        match config.get(NUMERIC_MODE_KEY) {
+            Some("preserve_integers") => Self::PreserveIntegers,
+            _ => Self::Native,
+        }
+    }
+
+    /// The value of the setting recording this mode, `None` for the default.
+    fn config_value(self) -> Option<&'static str> {
+        match self {
+            Self::Native => None,
+            Self::PreserveIntegers => Some("preserve_integers"),
+        }
+    }
+}
+
+/// The type a number was written with, see [`Automerge::numeric_type`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericType {
+    Int,
+    Uint,
+    F64,
+}
+
+impl NumericType {
+    fn of(value: &ScalarValue) -> Option<Self> {
+        match value {
+            ScalarValue::Int(_) => Some(Self::Int),
+            ScalarValue::Uint(_) => Some(Self::Uint),
+            ScalarValue::F64(_) => Some(Self::F64),
+            _ => None,
+        }
+    }
+}
+
+/// The value of `value` as a float, if it is a number.
+fn as_f64(value: &ScalarValue) -> Option<f64> {
+    match value {
+        ScalarValue::Int(i) => Some(*i as f64),
+        ScalarValue::Uint(u) => Some(*u as f64),
+        ScalarValue::F64(f) => Some(*f),
+        _ => None,
+    }
+}
+
+impl Automerge {
+    /// Read numbers of this document with `mode`.
+    pub fn with_numeric_mode(mut self, mode: NumericMode) -> Self {
+        self.set_numeric_mode(mode);
+        self
+    }
+
+    /// Change how numbers written by peers with only one number type are read and overwritten,
+    /// see [`NumericMode`].
+    ///
+    /// The mode affects [`Self::get_number`], [`Self::numeric_type`] and puts made by this
+    /// document. It is recorded in the [configuration](Self::config) of the document, which
+    /// every peer must agree on, so it should be set when the document is created.
+    pub fn set_numeric_mode(&mut self, mode: NumericMode) -> &mut Self {
+        self.config.set(NUMERIC_MODE_KEY, mode.config_value());
+        self
+    }
+
+    /// How numbers of this document are read.
+    pub fn numeric_mode(&self) -> NumericMode {
+        NumericMode::from_config(&self.config)
+    }
+
+    /// The number at `prop` in `obj` with the type it was originally written with, according to
+    /// the [`NumericMode`] of the document, or `None` if the value isn't a number.
+    pub fn get_number<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<(ScalarValue, ExId)>, AutomergeError> {
+        let id = match self.get(obj.as_ref(), prop)? {
+            Some((_, id)) => id,
+            None => return Ok(None),
+        };
+        let obj = self.exid_to_obj(obj.as_ref())?;
+        let op = self
+            .exid_to_opid(&id)
+            .and_then(|op| self.ops.find_op(&obj, op));
+        Ok(op
+            .and_then(|op| self.original_number(&obj, op))
+            .map(|value| (value, id)))
+    }
+
+    /// The type the number at `prop` in `obj` was originally written with, see
+    /// [`Self::get_number`].
+    pub fn numeric_type<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<NumericType>, AutomergeError> {
+        Ok(self
+            .get_number(obj, prop)?
+            .and_then(|(value, _)| NumericType::of(&value)))
+    }
+
+    /// The number `op` puts, following the ops it overwrote back to an integer with the same
+    /// value if the mode preserves integers.
+    fn original_number<'a>(&'a self, obj: &ObjId, mut op: &'a Op) -> Option<ScalarValue> {
+        let mut value = match &op.action {
+            OpType::Put(v) => NumericType::of(v).map(|_| v.clone())?,
+            _ => return None,
+        };
+        if self.numeric_mode() == NumericMode::Native {
+            return Some(value);
+        }
+        while let ScalarValue::F64(f) = value {
+            let pred = match op.pred.len() {
+                1 => op.pred.get(0).and_then(|pred| self.ops.find_op(obj, *pred)),
+                _ => None,
+            };
+            match pred.map(|p| (p, &p.action)) {
+                Some((p, OpType::Put(v))) if as_f64(v) == Some(f) => {
+                    value = v.clone();
+                    op = p;
+                }
+                _ => break,
+            }
+        }
+        Some(value)
+    }
+
+    /// `value` as it should be put at `prop` in `obj`: with integers preserved, a whole float
+    /// over an integer with the same value is written as that integer.
+    pub(crate) fn preserve_number(
+        &self,
+        obj: &ExId,
+        prop: &Prop,
+        value: ScalarValue,
+    ) -> ScalarValue {
+        match value {
+            ScalarValue::F64(f) if self.numeric_mode() == NumericMode::PreserveIntegers => {
+                match self.get_number(obj, prop.clone()) {
+                    Ok(Some((current, _))) if as_f64(&current) == Some(f) => current,
+                    _ => value,
+                }
+            }
+            value => value,
+        }
+    }
+}
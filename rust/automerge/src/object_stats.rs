@@ -0,0 +1,122 @@
+use std::collections::HashSet;
+use std::mem::size_of;
+use std::ops::AddAssign;
+
+use crate::op_tree::OpTree;
+use crate::types::{Key, Op, OpId, OpType};
+use crate::ScalarValue;
+
+/// Storage statistics for a single object, see [`crate::Automerge::object_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ObjectStats {
+    /// The number of operations stored for the object.
+    pub ops: usize,
+    /// The number of operations which contribute to the current value of the object. Conflicting
+    /// values are all counted.
+    pub visible_ops: usize,
+    /// The number of list elements or map keys which currently have a value.
+    pub live_elements: usize,
+    /// The number of list elements or map keys which have been deleted. Their operations are
+    /// kept so that concurrent changes can be merged.
+    pub tombstones: usize,
+    /// An estimate of the memory in bytes used by the object's operations.
+    pub memory: usize,
+}
+
+/// Storage statistics for a whole document, see [`crate::Automerge::document_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocumentStats {
+    /// The number of objects in the document, including deleted objects.
+    pub objects: usize,
+    /// The sum of the statistics of every object.
+    pub totals: ObjectStats,
+}
+
+/// An estimate of the memory used by a document, see [`crate::Automerge::memory_usage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The bytes used by the operations of the document, not counting their values.
+    pub ops: usize,
+    /// The bytes used by the strings and byte arrays the operations put. A string which is put
+    /// by several operations is stored once and counted once.
+    pub values: usize,
+    /// The bytes used by the changes which make up the history of the document.
+    pub history: usize,
+}
+
+impl MemoryUsage {
+    /// The total number of bytes used.
+    pub fn total(&self) -> usize {
+        self.ops + self.values + self.history
+    }
+
+    /// Count `op`, where `strings` is the strings which have already been counted.
+    pub(crate) fn add_op(&mut self, op: &Op, strings: &mut HashSet<*const u8>) {
+        self.ops += size_of::<Op>() + (op.pred.len() + op.succ.len()) * size_of::<OpId>();
+        self.values += match &op.action {
+            // short strings are stored inside the op
+            OpType::Put(ScalarValue::Str(s)) if !s.is_heap_allocated() => 0,
+            OpType::Put(ScalarValue::Str(s)) if !strings.insert(s.as_ptr()) => 0,
+            OpType::Put(ScalarValue::Str(s)) => s.len(),
+            OpType::Put(ScalarValue::Bytes(b)) => b.len(),
+            OpType::Put(ScalarValue::Unknown { bytes, .. }) => bytes.len(),
+            _ => 0,
+        };
+    }
+}
+
+impl AddAssign for ObjectStats {
+    fn add_assign(&mut self, other: Self) {
+        self.ops += other.ops;
+        self.visible_ops += other.visible_ops;
+        self.live_elements += other.live_elements;
+        self.tombstones += other.tombstones;
+        self.memory += other.memory;
+    }
+}
+
+impl ObjectStats {
+    pub(crate) fn for_tree(tree: &OpTree) -> Self {
+        let mut stats = ObjectStats::default();
+        let mut current: Option<(Key, bool)> = None;
+        for op in tree.iter() {
+            stats.ops += 1;
+            stats.memory += op_memory(op);
+            let visible = op.visible();
+            if visible {
+                stats.visible_ops += 1;
+            }
+            let key = op.elemid_or_key();
+            match &mut current {
+                Some((k, live)) if *k == key => *live |= visible,
+                _ => {
+                    if let Some((_, live)) = current.replace((key, visible)) {
+                        stats.count_element(live);
+                    }
+                }
+            }
+        }
+        if let Some((_, live)) = current {
+            stats.count_element(live);
+        }
+        stats
+    }
+
+    fn count_element(&mut self, live: bool) {
+        if live {
+            self.live_elements += 1;
+        } else {
+            self.tombstones += 1;
+        }
+    }
+}
+
+fn op_memory(op: &Op) -> usize {
+    let value = match &op.action {
+        OpType::Put(ScalarValue::Str(s)) => s.len(),
+        OpType::Put(ScalarValue::Bytes(b)) => b.len(),
+        OpType::Put(ScalarValue::Unknown { bytes, .. }) => bytes.len(),
+        _ => 0,
+    };
+    size_of::<Op>() + value
+}
@@ -1,9 +1,18 @@
+use std::fmt;
+use std::sync::Arc;
+
+use smol_str::SmolStr;
+
 use crate::exid::ExId;
 use crate::Parents;
 use crate::Prop;
 use crate::Value;
 
 /// An observer of operations applied to the document.
+///
+/// A tuple of up to four observers is an observer which passes every callback to each of them in
+/// order, so independent observers, such as one which patches a UI and one which records
+/// metrics, can watch the same document or transaction without a wrapper which fans out to them.
 pub trait OpObserver: Default + Clone {
     /// A new value has been inserted into the given object.
     ///
@@ -20,6 +29,39 @@ pub trait OpObserver: Default + Clone {
         tagged_value: (Value<'_>, ExId),
     );
 
+    /// Characters have been inserted into a text object.
+    ///
+    /// Inserts of single characters into text objects are reported here rather than to
+    /// [`Self::insert`], and runs of them which are contiguous, such as those made by a single
+    /// [`crate::transaction::Transactable::splice_text`] or those of a change which appends to
+    /// the text, are reported as a single splice.
+    ///
+    /// - `parents`: A parents iterator that can be used to collect path information
+    /// - `objid`: the text object that has been inserted into.
+    /// - `index`: the index the first character has been inserted at.
+    /// - `value`: the characters which have been inserted, each of which is one element of
+    ///   the text.
+    ///
+    /// This method was added after [`Self::insert`] and observers written before it existed
+    /// saw text inserts through that method. The default implementation therefore forwards each
+    /// character to [`Self::insert`] as a string value. A splice does not carry the ids of the
+    /// operations which inserted its characters, so those inserts are reported with
+    /// [`ExId::Root`] as the id; observers which need the ids should implement this method.
+    ///
+    /// Note that reporting text inserts here, and the matching [`Patch::SpliceText`], is a
+    /// breaking change for observers which relied on seeing every character as an insert with
+    /// its id.
+    fn splice_text(&mut self, parents: Parents<'_>, objid: ExId, index: usize, value: &str) {
+        for (offset, c) in value.chars().enumerate() {
+            self.insert(
+                parents.clone(),
+                objid.clone(),
+                index + offset,
+                (Value::str(&c.to_string()), ExId::Root),
+            );
+        }
+    }
+
     /// A new value has been put into the given object.
     ///
     /// - `parents`: A parents iterator that can be used to collect path information
@@ -74,6 +116,13 @@ pub trait OpObserver: Default + Clone {
     ///
     /// - `other`: Another Op Observer of the same type
     fn merge(&mut self, other: &Self);
+
+    /// The ops reported from now on were made with the source label `source`, or without one if
+    /// it is `None`, see [`crate::transaction::Transaction::set_source`].
+    ///
+    /// Labels aren't part of changes, so only the ops of transactions on this document have
+    /// them, and the label is reset to `None` when the transaction is committed.
+    fn source(&mut self, _source: Option<&str>) {}
 }
 
 impl OpObserver for () {
@@ -86,6 +135,8 @@ impl OpObserver for () {
     ) {
     }
 
+    fn splice_text(&mut self, _parents: Parents<'_>, _objid: ExId, _index: usize, _value: &str) {}
+
     fn put(
         &mut self,
         _parents: Parents<'_>,
@@ -110,18 +161,311 @@ impl OpObserver for () {
     fn merge(&mut self, _other: &Self) {}
 }
 
+macro_rules! impl_op_observer_for_tuple {
+    ($($name:ident . $idx:tt),+) => {
+        impl<$($name: OpObserver),+> OpObserver for ($($name,)+) {
+            fn insert(
+                &mut self,
+                parents: Parents<'_>,
+                objid: ExId,
+                index: usize,
+                tagged_value: (Value<'_>, ExId),
+            ) {
+                $(self.$idx.insert(parents.clone(), objid.clone(), index, tagged_value.clone());)+
+            }
+
+            fn splice_text(&mut self, parents: Parents<'_>, objid: ExId, index: usize, value: &str) {
+                $(self.$idx.splice_text(parents.clone(), objid.clone(), index, value);)+
+            }
+
+            fn put(
+                &mut self,
+                parents: Parents<'_>,
+                objid: ExId,
+                prop: Prop,
+                tagged_value: (Value<'_>, ExId),
+                conflict: bool,
+            ) {
+                $(self.$idx.put(
+                    parents.clone(),
+                    objid.clone(),
+                    prop.clone(),
+                    tagged_value.clone(),
+                    conflict,
+                );)+
+            }
+
+            fn increment(
+                &mut self,
+                parents: Parents<'_>,
+                objid: ExId,
+                prop: Prop,
+                tagged_value: (i64, ExId),
+            ) {
+                $(self.$idx.increment(
+                    parents.clone(),
+                    objid.clone(),
+                    prop.clone(),
+                    tagged_value.clone(),
+                );)+
+            }
+
+            fn delete(&mut self, parents: Parents<'_>, objid: ExId, prop: Prop) {
+                $(self.$idx.delete(parents.clone(), objid.clone(), prop.clone());)+
+            }
+
+            fn branch(&self) -> Self {
+                ($(self.$idx.branch(),)+)
+            }
+
+            fn merge(&mut self, other: &Self) {
+                $(self.$idx.merge(&other.$idx);)+
+            }
+
+            fn source(&mut self, source: Option<&str>) {
+                $(self.$idx.source(source);)+
+            }
+        }
+    };
+}
+
+impl_op_observer_for_tuple!(A.0, B.1);
+impl_op_observer_for_tuple!(A.0, B.1, C.2);
+impl_op_observer_for_tuple!(A.0, B.1, C.2, D.3);
+
 /// Capture operations into a [`Vec`] and store them as patches.
+///
+/// By default every operation produces its own patch. An observer created with
+/// [`VecOpObserver::coalescing`] instead merges successive operations on the same object into a
+/// single patch where it can:
+///
+/// - runs of inserts into a sequence become one [`Patch::Splice`], and splices into a text object
+///   one [`Patch::SpliceText`]
+/// - runs of deletions from a sequence become one [`Patch::DeleteRange`]
+/// - a put or increment of the same prop as the previous patch updates that patch
+///
+/// A put into an element of a splice updates the splice, unless the put conflicts, in which case
+/// it is its own [`Patch::Put`] so the conflict is still reported.
+///
+/// Only adjacent patches are merged, so the coalesced patches applied in order produce the same
+/// result as the uncoalesced ones. Coalescing happens within a branch of the observer, which for
+/// [`crate::AutoCommit`] means within a single transaction, and only between operations with the
+/// same source label.
+///
+/// The source label of each patch, see [`crate::transaction::Transaction::set_source`], is
+/// returned by [`Self::take_patches_with_sources`].
 #[derive(Default, Debug, Clone)]
 pub struct VecOpObserver {
     patches: Vec<Patch>,
+    /// The source label of each patch
+    sources: Vec<Option<SmolStr>>,
+    /// The source label of the ops being observed
+    source: Option<SmolStr>,
+    coalesce: bool,
 }
 
 impl VecOpObserver {
+    /// Create an observer which coalesces successive operations into as few patches as possible.
+    pub fn coalescing() -> Self {
+        VecOpObserver {
+            coalesce: true,
+            ..Default::default()
+        }
+    }
+
     /// Take the current list of patches, leaving the internal list empty and ready for new
     /// patches.
     pub fn take_patches(&mut self) -> Vec<Patch> {
+        self.sources.clear();
         std::mem::take(&mut self.patches)
     }
+
+    /// Take the current list of patches like [`Self::take_patches`], along with the source label
+    /// of the ops each patch describes.
+    pub fn take_patches_with_sources(&mut self) -> Vec<(Patch, Option<String>)> {
+        let sources = std::mem::take(&mut self.sources);
+        self.take_patches()
+            .into_iter()
+            .zip(sources)
+            .map(|(patch, source)| (patch, source.map(String::from)))
+            .collect()
+    }
+
+    fn push(&mut self, patch: Patch) {
+        self.patches.push(patch);
+        self.sources.push(self.source.clone());
+    }
+
+    fn pop(&mut self) {
+        self.patches.pop();
+        self.sources.pop();
+    }
+
+    /// The last patch, if the op being observed can be coalesced into it.
+    fn tail(&mut self) -> Option<&mut Patch> {
+        if self.sources.last() == Some(&self.source) {
+            self.patches.last_mut()
+        } else {
+            None
+        }
+    }
+
+    /// An estimate of the memory held by the current patches, as the number of patches plus the
+    /// number of values in each splice.
+    fn pending(&self) -> usize {
+        self.patches
+            .iter()
+            .map(|p| match p {
+                Patch::Splice { values, .. } => 1 + values.len(),
+                Patch::SpliceText { value, .. } => 1 + value.len(),
+                _ => 1,
+            })
+            .sum()
+    }
+
+    fn coalesce_insert(
+        &mut self,
+        obj: &ExId,
+        index: usize,
+        value: &(Value<'static>, ExId),
+    ) -> bool {
+        if let Some(Patch::Splice {
+            obj: tail_obj,
+            index: tail_index,
+            values,
+            ..
+        }) = self.tail()
+        {
+            let range = *tail_index..=*tail_index + values.len();
+            if tail_obj == obj && range.contains(&index) {
+                values.insert(index - *tail_index, value.clone());
+                return true;
+            }
+        }
+        false
+    }
+
+    fn coalesce_splice_text(&mut self, obj: &ExId, index: usize, value: &str) -> bool {
+        if let Some(Patch::SpliceText {
+            obj: tail_obj,
+            index: tail_index,
+            value: tail_value,
+            ..
+        }) = self.tail()
+        {
+            let len = tail_value.chars().count();
+            if tail_obj == obj && (*tail_index..=*tail_index + len).contains(&index) {
+                let offset = char_offset(tail_value, index - *tail_index);
+                tail_value.insert_str(offset, value);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn coalesce_delete(&mut self, obj: &ExId, index: usize) -> bool {
+        match self.tail() {
+            Some(Patch::Splice {
+                obj: tail_obj,
+                index: tail_index,
+                values,
+                ..
+            }) if tail_obj == obj && (*tail_index..*tail_index + values.len()).contains(&index) => {
+                values.remove(index - *tail_index);
+                if values.is_empty() {
+                    self.pop();
+                }
+                true
+            }
+            Some(Patch::SpliceText {
+                obj: tail_obj,
+                index: tail_index,
+                value,
+                ..
+            }) if tail_obj == obj
+                && (*tail_index..*tail_index + value.chars().count()).contains(&index) =>
+            {
+                value.remove(char_offset(value, index - *tail_index));
+                if value.is_empty() {
+                    self.pop();
+                }
+                true
+            }
+            Some(Patch::DeleteRange {
+                obj: tail_obj,
+                index: tail_index,
+                length,
+                ..
+            }) if tail_obj == obj => {
+                if index == *tail_index {
+                    // deleting forwards
+                    *length += 1;
+                    true
+                } else if index + 1 == *tail_index {
+                    // deleting backwards
+                    *tail_index = index;
+                    *length += 1;
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn coalesce_put(
+        &mut self,
+        obj: &ExId,
+        prop: &Prop,
+        value: &(Value<'static>, ExId),
+        conflict: bool,
+    ) -> bool {
+        match self.tail() {
+            Some(Patch::Put {
+                obj: tail_obj,
+                prop: tail_prop,
+                value: tail_value,
+                conflict: tail_conflict,
+                ..
+            }) if tail_obj == obj && tail_prop == prop => {
+                *tail_value = value.clone();
+                *tail_conflict = conflict;
+                true
+            }
+            Some(Patch::Splice {
+                obj: tail_obj,
+                index: tail_index,
+                values,
+                ..
+            }) if tail_obj == obj && !conflict => match prop {
+                // a splice can't record a conflict, so a conflicting put gets its own patch
+                Prop::Seq(index) if (*tail_index..*tail_index + values.len()).contains(index) => {
+                    values[index - *tail_index] = value.clone();
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn coalesce_increment(&mut self, obj: &ExId, prop: &Prop, value: &(i64, ExId)) -> bool {
+        if let Some(Patch::Increment {
+            obj: tail_obj,
+            prop: tail_prop,
+            value: (amount, id),
+            ..
+        }) = self.tail()
+        {
+            if tail_obj == obj && tail_prop == prop {
+                *amount += value.0;
+                *id = value.1.clone();
+                return true;
+            }
+        }
+        false
+    }
 }
 
 impl OpObserver for VecOpObserver {
@@ -132,12 +476,39 @@ impl OpObserver for VecOpObserver {
         index: usize,
         (value, id): (Value<'_>, ExId),
     ) {
+        let value = (value.into_owned(), id);
+        if self.coalesce {
+            if self.coalesce_insert(&obj, index, &value) {
+                return;
+            }
+            let path = parents.path();
+            self.push(Patch::Splice {
+                obj,
+                path,
+                index,
+                values: vec![value],
+            });
+            return;
+        }
         let path = parents.path();
-        self.patches.push(Patch::Insert {
+        self.push(Patch::Insert {
             obj,
             path,
             index,
-            value: (value.into_owned(), id),
+            value,
+        });
+    }
+
+    fn splice_text(&mut self, mut parents: Parents<'_>, obj: ExId, index: usize, value: &str) {
+        if self.coalesce && self.coalesce_splice_text(&obj, index, value) {
+            return;
+        }
+        let path = parents.path();
+        self.push(Patch::SpliceText {
+            obj,
+            path,
+            index,
+            value: value.to_string(),
         });
     }
 
@@ -149,12 +520,16 @@ impl OpObserver for VecOpObserver {
         (value, id): (Value<'_>, ExId),
         conflict: bool,
     ) {
+        let value = (value.into_owned(), id);
+        if self.coalesce && self.coalesce_put(&obj, &prop, &value, conflict) {
+            return;
+        }
         let path = parents.path();
-        self.patches.push(Patch::Put {
+        self.push(Patch::Put {
             obj,
             path,
             prop,
-            value: (value.into_owned(), id),
+            value,
             conflict,
         });
     }
@@ -166,8 +541,11 @@ impl OpObserver for VecOpObserver {
         prop: Prop,
         tagged_value: (i64, ExId),
     ) {
+        if self.coalesce && self.coalesce_increment(&obj, &prop, &tagged_value) {
+            return;
+        }
         let path = parents.path();
-        self.patches.push(Patch::Increment {
+        self.push(Patch::Increment {
             obj,
             path,
             prop,
@@ -176,16 +554,186 @@ impl OpObserver for VecOpObserver {
     }
 
     fn delete(&mut self, mut parents: Parents<'_>, obj: ExId, prop: Prop) {
+        if self.coalesce {
+            if let Prop::Seq(index) = prop {
+                if self.coalesce_delete(&obj, index) {
+                    return;
+                }
+                let path = parents.path();
+                self.push(Patch::DeleteRange {
+                    obj,
+                    path,
+                    index,
+                    length: 1,
+                });
+                return;
+            }
+        }
         let path = parents.path();
-        self.patches.push(Patch::Delete { obj, path, prop })
+        self.push(Patch::Delete { obj, path, prop })
+    }
+
+    fn branch(&self) -> Self {
+        VecOpObserver {
+            source: self.source.clone(),
+            coalesce: self.coalesce,
+            ..Default::default()
+        }
     }
 
     fn merge(&mut self, other: &Self) {
-        self.patches.extend_from_slice(other.patches.as_slice())
+        self.patches.extend_from_slice(other.patches.as_slice());
+        self.sources.extend_from_slice(other.sources.as_slice());
+    }
+
+    fn source(&mut self, source: Option<&str>) {
+        self.source = source.map(SmolStr::new);
+    }
+}
+
+/// Where a [`SpillingOpObserver`] sends its batches of patches.
+pub type PatchSink = Arc<dyn Fn(Vec<Patch>) + Send + Sync>;
+
+/// A [`VecOpObserver`] which hands its patches to a sink whenever they exceed a budget, so that
+/// observing a very large number of operations, e.g. loading years of history, does not hold
+/// every patch in memory at once.
+///
+/// The budget is measured in patches, with each value of a [`Patch::Splice`] counting as one
+/// more. The sink receives the batches in order; whatever is left under the budget is returned
+/// by [`Self::take_patches`] or sent by [`Self::flush`].
+///
+/// Branches, which [`crate::AutoCommit`] creates for each transaction, do not spill, as their
+/// patches would be lost if the transaction was rolled back. Their patches are checked against
+/// the budget when they are merged on commit.
+#[derive(Clone, Default)]
+pub struct SpillingOpObserver {
+    inner: VecOpObserver,
+    budget: usize,
+    sink: Option<PatchSink>,
+}
+
+impl SpillingOpObserver {
+    /// Collect patches with `inner`, which may be [`VecOpObserver::coalescing`], and pass them to
+    /// `sink` once there are more than `budget` of them.
+    pub fn new<F>(inner: VecOpObserver, budget: usize, sink: F) -> Self
+    where
+        F: Fn(Vec<Patch>) + Send + Sync + 'static,
+    {
+        SpillingOpObserver {
+            inner,
+            budget,
+            sink: Some(Arc::new(sink)),
+        }
+    }
+
+    /// Take the patches which have not been sent to the sink yet.
+    pub fn take_patches(&mut self) -> Vec<Patch> {
+        self.inner.take_patches()
+    }
+
+    /// Take the patches which have not been sent to the sink yet, with their source labels, see
+    /// [`VecOpObserver::take_patches_with_sources`]. Patches sent to the sink don't carry labels.
+    pub fn take_patches_with_sources(&mut self) -> Vec<(Patch, Option<String>)> {
+        self.inner.take_patches_with_sources()
+    }
+
+    /// Send any patches which have not been sent yet to the sink.
+    pub fn flush(&mut self) {
+        if let Some(sink) = &self.sink {
+            let patches = self.inner.take_patches();
+            if !patches.is_empty() {
+                sink(patches);
+            }
+        }
+    }
+
+    fn spill_if_over_budget(&mut self) {
+        if self.sink.is_some() && self.inner.pending() > self.budget {
+            self.flush();
+        }
+    }
+}
+
+impl fmt::Debug for SpillingOpObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpillingOpObserver")
+            .field("inner", &self.inner)
+            .field("budget", &self.budget)
+            .field("has_sink", &self.sink.is_some())
+            .finish()
+    }
+}
+
+impl OpObserver for SpillingOpObserver {
+    fn insert(
+        &mut self,
+        parents: Parents<'_>,
+        objid: ExId,
+        index: usize,
+        tagged_value: (Value<'_>, ExId),
+    ) {
+        self.inner.insert(parents, objid, index, tagged_value);
+        self.spill_if_over_budget();
+    }
+
+    fn splice_text(&mut self, parents: Parents<'_>, objid: ExId, index: usize, value: &str) {
+        self.inner.splice_text(parents, objid, index, value);
+        self.spill_if_over_budget();
+    }
+
+    fn put(
+        &mut self,
+        parents: Parents<'_>,
+        objid: ExId,
+        prop: Prop,
+        tagged_value: (Value<'_>, ExId),
+        conflict: bool,
+    ) {
+        self.inner.put(parents, objid, prop, tagged_value, conflict);
+        self.spill_if_over_budget();
+    }
+
+    fn increment(
+        &mut self,
+        parents: Parents<'_>,
+        objid: ExId,
+        prop: Prop,
+        tagged_value: (i64, ExId),
+    ) {
+        self.inner.increment(parents, objid, prop, tagged_value);
+        self.spill_if_over_budget();
+    }
+
+    fn delete(&mut self, parents: Parents<'_>, objid: ExId, prop: Prop) {
+        self.inner.delete(parents, objid, prop);
+        self.spill_if_over_budget();
+    }
+
+    fn branch(&self) -> Self {
+        SpillingOpObserver {
+            inner: self.inner.branch(),
+            budget: self.budget,
+            sink: None,
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.inner.merge(&other.inner);
+        self.spill_if_over_budget();
+    }
+
+    fn source(&mut self, source: Option<&str>) {
+        self.inner.source(source);
     }
 }
 
 /// A notification to the application that something has changed in a document.
+///
+/// Patches implement `serde::Serialize` in a stable format which can be sent to frontends
+/// written in other languages, described on the implementation.
+///
+/// `Splice`, `SpliceText` and `DeleteRange` were added after the other variants, which is a
+/// breaking change for code which matches on every variant.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Patch {
     /// Associating a new value with a prop in a map, or an existing list element
@@ -233,4 +781,44 @@ pub enum Patch {
         /// The prop that was deleted.
         prop: Prop,
     },
+    /// Inserting a run of consecutive elements into a list/text, produced by a coalescing
+    /// [`VecOpObserver`]
+    Splice {
+        /// path to the object
+        path: Vec<(ExId, Prop)>,
+        /// The object that was inserted into.
+        obj: ExId,
+        /// The index of the first inserted value.
+        index: usize,
+        /// The values that were inserted, and the ids of the operations that inserted them.
+        values: Vec<(Value<'static>, ExId)>,
+    },
+    /// Inserting characters into a text object
+    SpliceText {
+        /// path to the object
+        path: Vec<(ExId, Prop)>,
+        /// The object that was inserted into.
+        obj: ExId,
+        /// The index of the first inserted character.
+        index: usize,
+        /// The characters that were inserted, one per element of the text.
+        value: String,
+    },
+    /// Deleting a run of consecutive elements from a list/text, produced by a coalescing
+    /// [`VecOpObserver`]
+    DeleteRange {
+        /// path to the object
+        path: Vec<(ExId, Prop)>,
+        /// The object that was deleted from.
+        obj: ExId,
+        /// The index of the first deleted element.
+        index: usize,
+        /// The number of elements deleted.
+        length: usize,
+    },
+}
+
+/// The byte offset of the `n`th char of `s`, or its length if it has `n` chars.
+fn char_offset(s: &str, n: usize) -> usize {
+    s.char_indices().nth(n).map_or(s.len(), |(i, _)| i)
 }
@@ -1,30 +1,53 @@
+use crate::audit::{AuditProblem, AuditReport};
 use crate::clock::Clock;
 use crate::exid::ExId;
 use crate::indexed_cache::IndexedCache;
-use crate::op_tree::{self, OpTree};
+use crate::interner::Interner;
+use crate::lookup_cache::LookupCache;
+use crate::op_tree::{self, NodeSize, OpTree, OpTreeInternal};
 use crate::parents::Parents;
 use crate::query::{self, OpIdSearch, TreeQuery};
-use crate::types::{self, ActorId, Key, ObjId, Op, OpId, OpIds, OpType, Prop};
-use crate::{ObjType, OpObserver};
+use crate::types::{self, ActorId, ElemId, Key, ObjId, Op, OpId, OpIds, OpType, Prop};
+use crate::{DocumentStats, MemoryUsage, ObjType, ObjectStats, OpObserver};
 use fxhash::FxBuildHasher;
 use std::borrow::Borrow;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::RangeBounds;
+use std::sync::Arc;
 
+mod diff;
 mod load;
 pub(crate) use load::{ObservedOpSetBuilder, OpSetBuilder};
 
 pub(crate) type OpSet = OpSetInternal;
 
+/// A run of characters inserted into a text object which has not been reported to an observer
+/// yet, see [`OpSetInternal::insert_op_with_observer`].
+#[derive(Debug)]
+pub(crate) struct PendingSplice {
+    obj: ObjId,
+    index: usize,
+    /// The number of characters in `text`
+    len: usize,
+    text: String,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct OpSetInternal {
-    /// The map of objects to their type and ops.
-    trees: HashMap<ObjId, OpTree, FxBuildHasher>,
+    /// The map of objects to their type and ops. Trees, and the nodes within them, are shared
+    /// with the op sets of [`crate::Snapshot`]s and copied when they are changed.
+    trees: HashMap<ObjId, Arc<OpTree>, FxBuildHasher>,
     /// The number of operations in the opset.
     length: usize,
     /// Metadata about the operations in this opset.
     pub(crate) m: OpSetMetadata,
+    /// The node size to use for the op trees of new objects
+    node_size: NodeSize,
+    /// Recent lookups of objects and map keys
+    pub(crate) lookup_cache: LookupCache,
+    /// The string values of the ops, shared between ops which put the same string
+    strings: Interner,
 }
 
 impl OpSetInternal {
@@ -40,7 +63,7 @@ impl OpSetInternal {
 
     pub(crate) fn new() -> Self {
         let mut trees: HashMap<_, _, _> = Default::default();
-        trees.insert(ObjId::root(), OpTree::new());
+        trees.insert(ObjId::root(), Arc::new(OpTree::new()));
         OpSetInternal {
             trees,
             length: 0,
@@ -48,6 +71,20 @@ impl OpSetInternal {
                 actors: IndexedCache::new(),
                 props: IndexedCache::new(),
             },
+            node_size: NodeSize::default(),
+            lookup_cache: LookupCache::default(),
+            strings: Interner::default(),
+        }
+    }
+
+    pub(crate) fn node_size(&self) -> NodeSize {
+        self.node_size
+    }
+
+    pub(crate) fn set_node_size(&mut self, node_size: NodeSize) {
+        self.node_size = node_size;
+        for tree in self.trees.values_mut() {
+            Arc::make_mut(tree).internal.set_node_size(node_size);
         }
     }
 
@@ -60,7 +97,7 @@ impl OpSetInternal {
     }
 
     pub(crate) fn iter(&self) -> Iter<'_> {
-        let mut objs: Vec<_> = self.trees.iter().collect();
+        let mut objs: Vec<_> = self.trees.iter().map(|(id, tree)| (id, &**tree)).collect();
         objs.sort_by(|a, b| self.m.lamport_cmp((a.0).0, (b.0).0));
         Iter {
             opset: self,
@@ -69,24 +106,206 @@ impl OpSetInternal {
         }
     }
 
+    /// Iterate over the ops of a single object
+    pub(crate) fn iter_obj(&self, obj: &ObjId) -> Option<op_tree::OpTreeIter<'_>> {
+        self.trees.get(obj).map(|tree| tree.iter())
+    }
+
     pub(crate) fn parents(&self, obj: ObjId) -> Parents<'_> {
         Parents { obj, ops: self }
     }
 
-    pub(crate) fn parent_object(&self, obj: &ObjId) -> Option<(ObjId, Key)> {
+    pub(crate) fn object_stats(&self, obj: &ObjId) -> Option<ObjectStats> {
+        self.trees.get(obj).map(|tree| ObjectStats::for_tree(tree))
+    }
+
+    pub(crate) fn document_stats(&self) -> DocumentStats {
+        let mut stats = DocumentStats {
+            objects: self.trees.len(),
+            ..Default::default()
+        };
+        for tree in self.trees.values() {
+            stats.totals += ObjectStats::for_tree(tree);
+        }
+        stats
+    }
+
+    /// The memory used by the ops and their values, see [`crate::Automerge::memory_usage`].
+    pub(crate) fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        let mut strings = HashSet::new();
+        for op in self.trees.values().flat_map(|tree| tree.iter()) {
+            usage.add_op(op, &mut strings);
+        }
+        usage
+    }
+
+    /// Check the invariants of every object, see [`crate::Automerge::audit`].
+    pub(crate) fn audit(&self) -> AuditReport {
+        let mut report = AuditReport {
+            objects: self.trees.len(),
+            ..Default::default()
+        };
+        let mut objs: Vec<_> = self.trees.iter().collect();
+        objs.sort_by(|a, b| self.m.lamport_cmp((a.0).0, (b.0).0));
+        for (obj, tree) in objs {
+            let exid = self.id_to_exid(obj.0);
+            let problems = &mut report.problems;
+            report.ops += tree.len();
+            if let Some(parent) = tree.parent {
+                if !self.trees.contains_key(&parent) {
+                    problems.push(AuditProblem::Orphan {
+                        obj: exid.clone(),
+                        parent: self.id_to_exid(parent.0),
+                    });
+                }
+            }
+
+            let audit = tree.internal.audit();
+            if !audit.length_ok {
+                problems.push(AuditProblem::Length { obj: exid.clone() });
+            }
+            if !audit.index_ok {
+                problems.push(AuditProblem::Index { obj: exid.clone() });
+            }
+            if !audit.balanced {
+                problems.push(AuditProblem::Unbalanced { obj: exid.clone() });
+            }
+
+            let recorded = tree
+                .internal
+                .root_node
+                .as_ref()
+                .map_or(0, |n| n.index.visible.values().sum());
+            let found = tree.iter().filter(|op| op.visible()).count();
+            if recorded != found {
+                problems.push(AuditProblem::VisibleCount {
+                    obj: exid.clone(),
+                    recorded,
+                    found,
+                });
+            }
+
+            let ops = tree.iter().map(|op| (op.id, op)).collect::<HashMap<_, _>>();
+            for op in tree.iter() {
+                for pred in op.pred.iter() {
+                    match ops.get(pred) {
+                        None => problems.push(AuditProblem::DanglingPred {
+                            obj: exid.clone(),
+                            op: self.id_to_exid(op.id),
+                            pred: self.id_to_exid(*pred),
+                        }),
+                        Some(p) if !p.succ.iter().any(|s| *s == op.id) => {
+                            problems.push(AuditProblem::MissingSucc {
+                                obj: exid.clone(),
+                                op: self.id_to_exid(op.id),
+                                pred: self.id_to_exid(*pred),
+                            })
+                        }
+                        Some(_) => {}
+                    }
+                }
+            }
+        }
+        if report.ops != self.length {
+            report.problems.push(AuditProblem::OpCount {
+                recorded: self.length,
+                found: report.ops,
+            });
+        }
+        report
+    }
+
+    /// The number of ops in `obj`, including ops which are no longer visible.
+    pub(crate) fn object_len(&self, obj: &ObjId) -> Option<usize> {
+        self.trees.get(obj).map(|tree| tree.len())
+    }
+
+    /// The number of objects `obj` is nested in, which is zero for the root.
+    /// The depth of the most deeply nested object, see [`Self::depth`].
+    pub(crate) fn max_depth(&self) -> usize {
+        self.trees
+            .keys()
+            .map(|obj| self.depth(obj))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn depth(&self, obj: &ObjId) -> usize {
+        let mut depth = 0;
+        let mut obj = *obj;
+        while let Some(parent) = self.trees.get(&obj).and_then(|tree| tree.parent) {
+            depth += 1;
+            obj = parent;
+        }
+        depth
+    }
+
+    /// The object `obj` is in, the key it is at and whether it is still there, i.e. the op which
+    /// made it has not been overwritten or deleted.
+    /// The op with id `id` in `obj`, whether or not it is visible.
+    pub(crate) fn find_op(&self, obj: &ObjId, id: OpId) -> Option<&Op> {
+        let index = self.search(obj, OpIdSearch::new(id)).index()?;
+        self.trees.get(obj)?.internal.get(index)
+    }
+
+    pub(crate) fn parent_object(&self, obj: &ObjId) -> Option<(ObjId, Key, bool)> {
         let parent = self.trees.get(obj)?.parent?;
-        let key = self.search(&parent, OpIdSearch::new(obj.0)).key().unwrap();
-        Some((parent, key))
+        let query = self.search(&parent, OpIdSearch::new(obj.0));
+        Some((parent, query.key().unwrap(), query.visible()))
+    }
+
+    /// The object `obj` was created in and the key it was created at, without searching the
+    /// parent.
+    pub(crate) fn created_at(&self, obj: &ObjId) -> Option<(ObjId, Key)> {
+        let tree = self.trees.get(obj)?;
+        match (tree.parent?, tree.key) {
+            (parent, Some(key)) => Some((parent, key)),
+            (_, None) => self
+                .parent_object(obj)
+                .map(|(parent, key, _)| (parent, key)),
+        }
+    }
+
+    /// The number of visible elements of the sequence `obj` up to and including `elem`, which
+    /// may have been deleted, i.e. the index at which an element inserted after `elem` appears.
+    pub(crate) fn index_after(&self, obj: &ObjId, elem: ElemId) -> Option<usize> {
+        self.search(obj, query::IndexAfter::new(elem)).index()
+    }
+
+    /// `obj` and every object which has been created inside it, including objects which have
+    /// since been deleted.
+    ///
+    /// Every op which created an object is still in the object it was created in, so this visits
+    /// the ops of the subtree and nothing else.
+    pub(crate) fn subtree(&self, obj: &ObjId) -> Vec<ObjId> {
+        let mut result = Vec::new();
+        let mut to_visit = vec![*obj];
+        while let Some(o) = to_visit.pop() {
+            if let Some(tree) = self.trees.get(&o) {
+                to_visit.extend(
+                    tree.internal
+                        .iter()
+                        .filter(|op| matches!(op.action, OpType::Make(_)))
+                        .map(|op| ObjId(op.id))
+                        .filter(|id| self.trees.contains_key(id)),
+                );
+                result.push(o);
+            }
+        }
+        result
     }
 
     pub(crate) fn export_key(&self, obj: ObjId, key: Key) -> Prop {
         match key {
             Key::Map(m) => Prop::Map(self.m.props.get(m).into()),
             Key::Seq(opid) => {
+                // a deleted element is at the index of the element after it
                 let i = self
                     .search(&obj, query::ElemIdPos::new(opid))
                     .index()
-                    .unwrap();
+                    .or_else(|| self.index_after(&obj, opid))
+                    .unwrap_or(0);
                 Prop::Seq(i)
             }
         }
@@ -169,11 +388,93 @@ impl OpSetInternal {
         }
     }
 
+    /// Search for the ops of element `index` of the sequence `obj`.
+    pub(crate) fn search_nth(&self, obj: &ObjId, index: usize) -> query::Nth<'_> {
+        #[cfg(feature = "seq-index")]
+        if let Some(start) = self
+            .trees
+            .get(obj)
+            .filter(|_| !self.lookup_cache.is_frozen())
+            .and_then(|tree| tree.internal.seq_start(index))
+        {
+            return self.search(obj, query::Nth::with_start(index, start));
+        }
+        self.search(obj, query::Nth::new(index))
+    }
+
+    /// Search for the ops of the map key `prop` of `obj`, starting from where they were last
+    /// found if they are still there.
+    pub(crate) fn search_prop(&self, obj: &ObjId, prop: usize) -> query::Prop<'_> {
+        let key = Key::Map(prop);
+        self.lookup_cache.with_prop_start(*obj, prop, |start| {
+            let query = match start.filter(|start| self.is_key_start(obj, &key, *start)) {
+                Some(start) => self.search(obj, query::Prop::with_start(prop, start)),
+                None => self.search(obj, query::Prop::new(prop)),
+            };
+            let start = query.start();
+            (query, start)
+        })
+    }
+
+    /// Whether the ops of `obj` for `key` start at index `start`, i.e. whether the ops before
+    /// it are for earlier keys and those from it are not.
+    fn is_key_start(&self, obj: &ObjId, key: &Key, start: usize) -> bool {
+        let tree = match self.trees.get(obj) {
+            Some(tree) => &tree.internal,
+            None => return false,
+        };
+        if start > tree.len() {
+            return false;
+        }
+        let before = start == 0
+            || tree
+                .get(start - 1)
+                .map_or(false, |op| self.m.key_cmp(&op.key, key) == Ordering::Less);
+        let after = start == tree.len()
+            || tree
+                .get(start)
+                .map_or(false, |op| self.m.key_cmp(&op.key, key) != Ordering::Less);
+        before && after
+    }
+
+    /// The keys of the map `obj` in `range` which have a value, each with the ids and positions
+    /// in the op tree of its visible ops, found in one pass over the ops in the range.
+    pub(crate) fn visible_ops_in_range<R: RangeBounds<String>>(
+        &self,
+        obj: &ObjId,
+        range: &R,
+    ) -> Vec<(usize, Vec<(OpId, usize)>)> {
+        let root = match self
+            .trees
+            .get(obj)
+            .and_then(|t| t.internal.root_node.as_ref())
+        {
+            Some(root) => root,
+            None => return Vec::new(),
+        };
+        let mut keys: Vec<(usize, Vec<(OpId, usize)>)> = Vec::new();
+        for pos in query::map_key_range(root, &self.m, range) {
+            let op = match root.get(pos) {
+                Some(op) if op.visible() => op,
+                _ => continue,
+            };
+            let prop = match op.key {
+                Key::Map(prop) => prop,
+                Key::Seq(_) => continue,
+            };
+            match keys.last_mut() {
+                Some((last, ops)) if *last == prop => ops.push((op.id, pos)),
+                _ => keys.push((prop, vec![(op.id, pos)])),
+            }
+        }
+        keys
+    }
+
     pub(crate) fn replace<F>(&mut self, obj: &ObjId, index: usize, f: F)
     where
         F: Fn(&mut Op),
     {
-        if let Some(tree) = self.trees.get_mut(obj) {
+        if let Some(tree) = self.trees.get_mut(obj).map(Arc::make_mut) {
             tree.internal.update(index, f)
         }
     }
@@ -185,7 +486,7 @@ impl OpSetInternal {
         op_indices: I,
         op: &Op,
     ) {
-        if let Some(tree) = self.trees.get_mut(obj) {
+        if let Some(tree) = self.trees.get_mut(obj).map(Arc::make_mut) {
             for i in op_indices {
                 tree.internal.update(i, |old_op| {
                     old_op.add_succ(op, |left, right| self.m.lamport_cmp(*left, *right))
@@ -196,11 +497,12 @@ impl OpSetInternal {
 
     pub(crate) fn remove(&mut self, obj: &ObjId, index: usize) -> Op {
         // this happens on rollback - be sure to go back to the old state
-        let tree = self.trees.get_mut(obj).unwrap();
+        let tree = Arc::make_mut(self.trees.get_mut(obj).unwrap());
         self.length -= 1;
         let op = tree.internal.remove(index);
         if let OpType::Make(_) = &op.action {
             self.trees.remove(&op.id.into());
+            self.lookup_cache.clear_objects();
         }
         op
     }
@@ -210,19 +512,21 @@ impl OpSetInternal {
     }
 
     #[tracing::instrument(skip(self, index))]
-    pub(crate) fn insert(&mut self, index: usize, obj: &ObjId, element: Op) {
+    pub(crate) fn insert(&mut self, index: usize, obj: &ObjId, mut element: Op) {
+        self.strings.intern_op(&mut element);
         if let OpType::Make(typ) = element.action {
             self.trees.insert(
                 element.id.into(),
-                OpTree {
-                    internal: Default::default(),
+                Arc::new(OpTree {
+                    internal: OpTreeInternal::with_node_size(self.node_size),
                     objtype: typ,
                     parent: Some(*obj),
-                },
+                    key: Some(element.elemid_or_key()),
+                }),
             );
         }
 
-        if let Some(tree) = self.trees.get_mut(obj) {
+        if let Some(tree) = self.trees.get_mut(obj).map(Arc::make_mut) {
             //let tree = self.trees.get_mut(&element.obj).unwrap();
             tree.internal.insert(index, element);
             self.length += 1;
@@ -245,11 +549,29 @@ impl OpSetInternal {
         op
     }
 
+    /// Insert each of `ops` like [`Self::insert_op_with_observer`], reporting runs of characters
+    /// inserted into a text object as a single splice.
+    pub(crate) fn insert_ops_with_observer<Obs: OpObserver, I: IntoIterator<Item = (ObjId, Op)>>(
+        &mut self,
+        ops: I,
+        observer: &mut Obs,
+    ) {
+        let mut pending = None;
+        for (obj, op) in ops {
+            self.insert_op_with_observer(&obj, op, observer, &mut pending);
+        }
+        self.flush_splice(&mut pending, observer);
+    }
+
+    /// Insert `op` and report it to `observer`. Inserts of characters into text objects are added
+    /// to `pending`, and the splice they make up is reported once an op which doesn't extend it
+    /// is inserted, or by the caller with [`Self::flush_splice`] once every op is inserted.
     pub(crate) fn insert_op_with_observer<Obs: OpObserver>(
         &mut self,
         obj: &ObjId,
         op: Op,
         observer: &mut Obs,
+        pending: &mut Option<PendingSplice>,
     ) -> Op {
         let q = self.search(obj, query::SeekOpWithPatch::new(&op));
 
@@ -270,9 +592,18 @@ impl OpSetInternal {
             Key::Seq(_) => seen.into(),
         };
 
+        let text_char = self.text_char(obj, &op);
+        if text_char.is_none() || !op.insert {
+            self.flush_splice(pending, observer);
+        }
+
         if op.insert {
-            let value = (op.value(), self.id_to_exid(op.id));
-            observer.insert(parents, ex_obj, seen, value);
+            if let Some(c) = text_char {
+                self.splice_char(pending, obj, seen, c, observer);
+            } else {
+                let value = (op.value(), self.id_to_exid(op.id));
+                observer.insert(parents, ex_obj, seen, value);
+            }
         } else if op.is_delete() {
             if let Some(winner) = &values.last() {
                 let value = (winner.value(), self.id_to_exid(winner.id));
@@ -305,7 +636,10 @@ impl OpSetInternal {
             };
             let value = (winner.value(), self.id_to_exid(winner.id));
             if op.is_list_op() && !had_value_before {
-                observer.insert(parents, ex_obj, seen, value);
+                match self.text_char(obj, winner) {
+                    Some(c) => observer.splice_text(parents, ex_obj, seen, &c.to_string()),
+                    None => observer.insert(parents, ex_obj, seen, value),
+                }
             } else {
                 let conflict = !values.is_empty();
                 observer.put(parents, ex_obj, key, value, conflict);
@@ -321,6 +655,71 @@ impl OpSetInternal {
         op
     }
 
+    /// The character `op` puts into `obj`, if `obj` is a text object and the value of `op` is a
+    /// string of a single character.
+    pub(crate) fn text_char(&self, obj: &ObjId, op: &Op) -> Option<char> {
+        if self.object_type(obj) != Some(ObjType::Text) {
+            return None;
+        }
+        match &op.action {
+            OpType::Put(types::ScalarValue::Str(s)) => {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(c),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Add the character `c`, inserted at `index` of the text `obj`, to `pending`, reporting
+    /// `pending` first if `c` isn't inserted within it.
+    fn splice_char<Obs: OpObserver>(
+        &self,
+        pending: &mut Option<PendingSplice>,
+        obj: &ObjId,
+        index: usize,
+        c: char,
+        observer: &mut Obs,
+    ) {
+        if let Some(splice) = pending {
+            if splice.obj == *obj && (splice.index..=splice.index + splice.len).contains(&index) {
+                let offset = splice
+                    .text
+                    .char_indices()
+                    .nth(index - splice.index)
+                    .map_or(splice.text.len(), |(i, _)| i);
+                splice.text.insert(offset, c);
+                splice.len += 1;
+                return;
+            }
+        }
+        self.flush_splice(pending, observer);
+        *pending = Some(PendingSplice {
+            obj: *obj,
+            index,
+            len: 1,
+            text: c.to_string(),
+        });
+    }
+
+    /// Report the splice in `pending`, if there is one, to `observer`.
+    pub(crate) fn flush_splice<Obs: OpObserver>(
+        &self,
+        pending: &mut Option<PendingSplice>,
+        observer: &mut Obs,
+    ) {
+        if let Some(splice) = pending.take() {
+            observer.splice_text(
+                self.parents(splice.obj),
+                self.id_to_exid(splice.obj.0),
+                splice.index,
+                &splice.text,
+            );
+        }
+    }
+
     pub(crate) fn object_type(&self, id: &ObjId) -> Option<ObjType> {
         self.trees.get(id).map(|tree| tree.objtype)
     }
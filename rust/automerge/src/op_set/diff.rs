@@ -0,0 +1,208 @@
+use super::{OpSetInternal, PendingSplice};
+use crate::clock::Clock;
+use crate::query::{OpIdSearch, VisWindow};
+use crate::types::{Key, ObjId, Op, OpType, ScalarValue};
+use crate::OpObserver;
+
+/// The visible ops of a map key or sequence element at some point in the history.
+#[derive(Debug, Default)]
+struct Side {
+    /// The op whose value wins, for a counter the op which created it with its value then
+    winner: Option<Op>,
+    visible: usize,
+}
+
+impl Side {
+    fn conflict(&self) -> bool {
+        self.visible > 1
+    }
+}
+
+impl OpSetInternal {
+    /// Report to `observer` how every object which is in the document at `after` has changed
+    /// between the states covered by `before` and `after`, parents before their children.
+    pub(crate) fn observe_diff_between<Obs: OpObserver>(
+        &self,
+        before: &Clock,
+        after: &Clock,
+        observer: &mut Obs,
+    ) {
+        let mut objs = self
+            .trees
+            .keys()
+            .filter(|obj| self.visible_at(obj, after))
+            .copied()
+            .collect::<Vec<_>>();
+        objs.sort_by(|a, b| self.m.lamport_cmp(a.0, b.0));
+        for obj in objs {
+            self.observe_diff(&obj, before, Some(after), observer);
+        }
+    }
+
+    /// Whether `obj` and every object it is in had been created and not deleted in the state
+    /// covered by `clock`.
+    fn visible_at(&self, obj: &ObjId, clock: &Clock) -> bool {
+        let mut obj = *obj;
+        while let Some(parent) = self.trees.get(&obj).and_then(|tree| tree.parent) {
+            if !clock.covers(&obj.0) {
+                return false;
+            }
+            let made = self
+                .search(&parent, OpIdSearch::new(obj.0))
+                .index()
+                .and_then(|pos| self.trees.get(&parent)?.internal.get(pos));
+            match made {
+                Some(op) if !op.succ.into_iter().any(|id| clock.covers(id)) => obj = parent,
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Report to `observer` how `obj` has changed from the state covered by `before` to the
+    /// state covered by `after`, or the current state if `after` is `None`.
+    ///
+    /// This reports the same state as observing each op since `before` as it was inserted, but
+    /// visits each op of `obj` once however many ops changed it, and reports each key or element
+    /// at most once.
+    pub(crate) fn observe_diff<Obs: OpObserver>(
+        &self,
+        obj: &ObjId,
+        before: &Clock,
+        after: Option<&Clock>,
+        observer: &mut Obs,
+    ) {
+        let ops = match self.iter_obj(obj) {
+            Some(ops) => ops,
+            None => return,
+        };
+        let mut window = VisWindow::default();
+        let mut after_window = VisWindow::default();
+        let mut pending = None;
+        let mut index = 0;
+        let mut group: Option<(Key, Side, Side)> = None;
+        for (pos, op) in ops.enumerate() {
+            let key = op.elemid_or_key();
+            if group.as_ref().map_or(true, |(k, _, _)| *k != key) {
+                if let Some((key, then, now)) = group.take() {
+                    self.report(obj, key, then, now, &mut index, &mut pending, observer);
+                }
+                group = Some((key, Side::default(), Side::default()));
+            }
+            if let Some((_, then, now)) = &mut group {
+                if window.visible_at(op, pos, before) {
+                    then.visible += 1;
+                    then.winner = winner_at(&window, op, pos);
+                }
+                match after {
+                    Some(after) => {
+                        if after_window.visible_at(op, pos, after) {
+                            now.visible += 1;
+                            now.winner = winner_at(&after_window, op, pos);
+                        }
+                    }
+                    None => {
+                        if op.visible() {
+                            now.visible += 1;
+                            now.winner = Some(op.clone());
+                        }
+                    }
+                }
+            }
+        }
+        if let Some((key, then, now)) = group {
+            self.report(obj, key, then, now, &mut index, &mut pending, observer);
+        }
+        self.flush_splice(&mut pending, observer);
+    }
+
+    /// Report the change to `key` of `obj` from `then` to `now`. `index` is the index `key` has
+    /// in a sequence once the elements before it have been reported.
+    #[allow(clippy::too_many_arguments)]
+    fn report<Obs: OpObserver>(
+        &self,
+        obj: &ObjId,
+        key: Key,
+        then: Side,
+        now: Side,
+        index: &mut usize,
+        pending: &mut Option<PendingSplice>,
+        observer: &mut Obs,
+    ) {
+        let prop = match key {
+            Key::Map(p) => self.m.props[p].clone().into(),
+            Key::Seq(_) => (*index).into(),
+        };
+        let seq = matches!(key, Key::Seq(_));
+        let conflict = now.conflict();
+        let was_conflict = then.conflict();
+        match (then.winner, now.winner) {
+            (None, None) => {}
+            (Some(_), None) => {
+                self.flush_splice(pending, observer);
+                observer.delete(self.parents(*obj), self.id_to_exid(obj.0), prop);
+            }
+            (None, Some(op)) => {
+                if seq {
+                    match self.text_char(obj, &op) {
+                        Some(c) if !conflict => self.splice_char(pending, obj, *index, c, observer),
+                        _ => {
+                            self.flush_splice(pending, observer);
+                            let value = (op.value(), self.id_to_exid(op.id));
+                            observer.insert(
+                                self.parents(*obj),
+                                self.id_to_exid(obj.0),
+                                *index,
+                                value,
+                            );
+                        }
+                    }
+                    *index += 1;
+                }
+                if !seq || conflict {
+                    self.flush_splice(pending, observer);
+                    let value = (op.value(), self.id_to_exid(op.id));
+                    observer.put(
+                        self.parents(*obj),
+                        self.id_to_exid(obj.0),
+                        prop,
+                        value,
+                        conflict,
+                    );
+                }
+            }
+            (Some(old), Some(op)) => {
+                if old.id != op.id || old.value() != op.value() || was_conflict != conflict {
+                    self.flush_splice(pending, observer);
+                    let value = (op.value(), self.id_to_exid(op.id));
+                    observer.put(
+                        self.parents(*obj),
+                        self.id_to_exid(obj.0),
+                        prop,
+                        value,
+                        conflict,
+                    );
+                }
+                if seq {
+                    *index += 1;
+                }
+            }
+        }
+    }
+}
+
+/// The op standing for the value of `op` at the point `window` has reached.
+///
+/// Counters are updated in place by their increments, so the value they had then is their start
+/// plus the increments before it.
+fn winner_at(window: &VisWindow, op: &Op, pos: usize) -> Option<Op> {
+    match &op.action {
+        OpType::Increment(_) => window.seen_op(op, pos).pop().map(|(_, counter)| counter),
+        OpType::Put(ScalarValue::Counter(c)) => {
+            let mut counter = op.clone();
+            counter.action = OpType::Put(ScalarValue::counter(c.start));
+            Some(counter)
+        }
+        _ => Some(op.clone()),
+    }
+}
@@ -1,12 +1,14 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use fxhash::FxBuildHasher;
 
 use super::{OpSet, OpTree};
 use crate::{
+    interner::Interner,
     op_tree::OpTreeInternal,
     storage::load::{DocObserver, LoadedObject},
-    types::{ObjId, Op},
+    types::{ObjId, Op, OpType},
     OpObserver,
 };
 
@@ -15,12 +17,14 @@ use crate::{
 /// works because the ops in the document format are in the same order as in the optrees.
 pub(crate) struct OpSetBuilder {
     completed_objects: HashMap<ObjId, OpTree, FxBuildHasher>,
+    strings: Interner,
 }
 
 impl OpSetBuilder {
     pub(crate) fn new() -> OpSetBuilder {
         Self {
             completed_objects: HashMap::default(),
+            strings: Interner::default(),
         }
     }
 }
@@ -30,29 +34,51 @@ impl DocObserver for OpSetBuilder {
 
     fn object_loaded(&mut self, loaded: LoadedObject) {
         let mut internal = OpTreeInternal::new();
-        for (index, op) in loaded.ops.into_iter().enumerate() {
+        for (index, mut op) in loaded.ops.into_iter().enumerate() {
+            self.strings.intern_op(&mut op);
             internal.insert(index, op);
         }
         let tree = OpTree {
             internal,
             objtype: loaded.obj_type,
             parent: loaded.parent,
+            key: None,
         };
         self.completed_objects.insert(loaded.id, tree);
     }
 
-    fn finish(self, metadata: super::OpSetMetadata) -> Self::Output {
+    fn finish(mut self, metadata: super::OpSetMetadata) -> Self::Output {
         let len = self.completed_objects.values().map(|t| t.len()).sum();
+        // the key of each object is only known once the object it is in has been loaded
+        let keys = self
+            .completed_objects
+            .values()
+            .flat_map(|tree| tree.iter())
+            .filter(|op| matches!(op.action, OpType::Make(_)))
+            .map(|op| (ObjId(op.id), op.elemid_or_key()))
+            .collect::<Vec<_>>();
+        for (obj, key) in keys {
+            if let Some(tree) = self.completed_objects.get_mut(&obj) {
+                tree.key = Some(key);
+            }
+        }
         OpSet {
-            trees: self.completed_objects,
+            trees: self
+                .completed_objects
+                .into_iter()
+                .map(|(id, tree)| (id, Arc::new(tree)))
+                .collect(),
             length: len,
             m: metadata,
+            node_size: Default::default(),
+            lookup_cache: Default::default(),
+            strings: self.strings,
         }
     }
 }
 
 /// A DocObserver which just accumulates ops until the document has finished reconstructing and
-/// then inserts all of the ops using `OpSet::insert_op_with_observer`
+/// then inserts all of the ops using `OpSet::insert_ops_with_observer`
 pub(crate) struct ObservedOpSetBuilder<'a, O: OpObserver> {
     observer: &'a mut O,
     ops: Vec<(ObjId, Op)>,
@@ -77,11 +103,10 @@ impl<'a, O: OpObserver> DocObserver for ObservedOpSetBuilder<'a, O> {
         }
     }
 
-    fn finish(self, _metadata: super::OpSetMetadata) -> Self::Output {
+    fn finish(self, metadata: super::OpSetMetadata) -> Self::Output {
         let mut opset = OpSet::new();
-        for (obj, op) in self.ops {
-            opset.insert_op_with_observer(&obj, op, self.observer);
-        }
+        opset.m = metadata;
+        opset.insert_ops_with_observer(self.ops, self.observer);
         opset
     }
 }
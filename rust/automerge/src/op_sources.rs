@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use smol_str::SmolStr;
+
+use crate::types::OpId;
+
+/// The source labels of the ops created by this document, see
+/// [`crate::transaction::Transaction::set_source`].
+///
+/// The ops of a transaction have consecutive counters, so we store a run of ops with the same
+/// label as a single entry keyed by its actor and first counter.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct OpSources {
+    runs: BTreeMap<(usize, u64), (u64, SmolStr)>,
+}
+
+impl OpSources {
+    /// Label the ops of `actor` with counters in `counters` with `source`.
+    pub(crate) fn insert(&mut self, actor: usize, counters: Range<u64>, source: SmolStr) {
+        self.runs
+            .insert((actor, counters.start), (counters.end, source));
+    }
+
+    pub(crate) fn get(&self, id: OpId) -> Option<&str> {
+        let (&(actor, _), (end, source)) =
+            self.runs.range(..=(id.actor(), id.counter())).next_back()?;
+        (actor == id.actor() && id.counter() < *end).then(|| source.as_str())
+    }
+
+    /// The number of ops with each label among the ops of `actor` with counters in `counters`.
+    pub(crate) fn count(&self, actor: usize, counters: Range<u64>) -> Vec<(&str, usize)> {
+        let mut counts: Vec<(&str, usize)> = Vec::new();
+        if counters.is_empty() {
+            return counts;
+        }
+        // the run containing the first counter may start before it
+        let first = self
+            .runs
+            .range(..=(actor, counters.start))
+            .next_back()
+            .filter(|((a, _), _)| *a == actor);
+        let rest = self
+            .runs
+            .range((actor, counters.start + 1)..(actor, counters.end));
+        for (&(_, start), (end, source)) in first.into_iter().chain(rest) {
+            let overlap = (*end)
+                .min(counters.end)
+                .saturating_sub(start.max(counters.start));
+            if overlap == 0 {
+                continue;
+            }
+            match counts.iter_mut().find(|(s, _)| *s == source.as_str()) {
+                Some((_, n)) => *n += overlap as usize,
+                None => counts.push((source.as_str(), overlap as usize)),
+            }
+        }
+        counts
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::Mutex;
+
+use super::OpTreeInternal;
+use crate::types::Op;
+
+/// How many ops building a [`SeqIndex`] costs about as much as one lookup without it.
+///
+/// Looking up an element by searching the op tree visits a few nodes at each level, and building
+/// the index visits every op, so the index is only built once there have been enough lookups
+/// since the object last changed to pay for it. This stops lookups interleaved with changes, like
+/// typing, from rebuilding the index after every keystroke.
+const OPS_PER_LOOKUP: usize = 256;
+
+/// Maps the index of each visible element of a sequence to the position in the op tree of the op
+/// which inserted it, so that looking up an element by index doesn't have to search the tree.
+///
+/// The index is built lazily by lookups and thrown away whenever the tree changes. Like the
+/// [`crate::lookup_cache::LookupCache`] it sits behind a mutex so that lookups, which only borrow
+/// the document, can build it.
+#[derive(Debug, Default)]
+pub(crate) struct SeqIndex {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    starts: Option<Vec<usize>>,
+    /// Lookups since the tree last changed which didn't use the index
+    misses: usize,
+}
+
+impl SeqIndex {
+    /// The position in `tree` of the op which inserted visible element `index`, or `None` if the
+    /// index hasn't been built yet or `index` is out of bounds.
+    pub(crate) fn start(&self, index: usize, tree: &OpTreeInternal) -> Option<usize> {
+        let mut inner = self.inner.lock().ok()?;
+        if inner.starts.is_none() {
+            inner.misses += 1;
+            if inner.misses.saturating_mul(OPS_PER_LOOKUP) < tree.len() {
+                return None;
+            }
+            inner.starts = Some(build(tree.iter()));
+        }
+        inner.starts.as_ref().and_then(|s| s.get(index).copied())
+    }
+
+    /// Forget the index, for when the tree has changed.
+    pub(crate) fn clear(&mut self) {
+        if let Ok(inner) = self.inner.get_mut() {
+            inner.starts = None;
+            inner.misses = 0;
+        }
+    }
+}
+
+/// A copy of a tree starts without an index.
+impl Clone for SeqIndex {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+fn build<'a, I: Iterator<Item = &'a Op>>(ops: I) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut start = 0;
+    let mut counted = false;
+    for (pos, op) in ops.enumerate() {
+        if op.insert {
+            start = pos;
+            counted = false;
+        }
+        // an element is visible if any of its ops are, which happens when there are conflicts
+        if !counted && op.visible() {
+            starts.push(start);
+            counted = true;
+        }
+    }
+    starts
+}
@@ -2,31 +2,37 @@ use crate::op_set::OpSet;
 use crate::types::ObjId;
 use crate::{exid::ExId, Prop};
 
-#[derive(Debug)]
+/// An iterator over the ancestors of an object, from the object it is in up to the root.
+///
+/// Each item is `(parent, prop, visible)` where `prop` is the prop of `parent` the child is at and
+/// `visible` is whether the child is still there, i.e. it has not been overwritten or deleted.
+#[derive(Debug, Clone)]
 pub struct Parents<'a> {
     pub(crate) obj: ObjId,
     pub(crate) ops: &'a OpSet,
 }
 
 impl<'a> Parents<'a> {
+    /// The path from the root to the object, as the object and prop of each step.
     pub fn path(&mut self) -> Vec<(ExId, Prop)> {
-        let mut path = self.collect::<Vec<_>>();
+        let mut path = self.map(|(obj, prop, _)| (obj, prop)).collect::<Vec<_>>();
         path.reverse();
         path
     }
 }
 
 impl<'a> Iterator for Parents<'a> {
-    type Item = (ExId, Prop);
+    type Item = (ExId, Prop, bool);
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.obj.is_root() {
             None
-        } else if let Some((obj, key)) = self.ops.parent_object(&self.obj) {
+        } else if let Some((obj, key, visible)) = self.ops.parent_object(&self.obj) {
             self.obj = obj;
             Some((
                 self.ops.id_to_exid(self.obj.0),
                 self.ops.export_key(self.obj, key),
+                visible,
             ))
         } else {
             None
@@ -0,0 +1,214 @@
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Serialize, Serializer};
+
+use crate::exid::ExId;
+use crate::{Patch, Prop, ScalarValue, Value};
+
+/// Patches serialize to a self-describing format for frontends which aren't written in Rust,
+/// which is stable across releases. In JSON each patch is an object like
+///
+/// ```json
+/// {
+///   "action": "put",
+///   "obj": "2@aabbcc",
+///   "path": [{ "obj": "_root", "prop": "todos" }, { "obj": "1@aabbcc", "prop": 0 }],
+///   "prop": "done",
+///   "value": { "datatype": "boolean", "value": true, "id": "3@aabbcc" },
+///   "conflict": false
+/// }
+/// ```
+///
+/// Every patch has an `action`, the id of the object it changes as `obj`, and the `path` from
+/// the root to that object, each step of which is an object id and the prop of the next object
+/// in it: a string key of a map or a numeric index of a list. The other fields depend on the
+/// action:
+///
+/// - `put`: `prop`, `value` and `conflict`
+/// - `insert`: the `index` of the new element and its `value`
+/// - `splice`: the `index` of the first new element and their `values`
+/// - `spliceText`: the `index` of the first new character and the characters as a string `value`
+/// - `increment`: `prop` and the `value` the counter was incremented by, which is a number, and
+///   the `id` of the increment
+/// - `delete`: the `prop` deleted and the `length` of the run of elements deleted from it, which
+///   is 1 when a single element or key is deleted
+///
+/// A value is an object with the `datatype` of the value, the same names the JavaScript library
+/// uses (`map`, `table`, `list`, `text`, `str`, `int`, `uint`, `f64`, `counter`, `timestamp`,
+/// `boolean`, `bytes`, `null` and `unknown`), and the `id` of the operation which created it.
+/// Scalars other than null have a `value` as well, which is the number of a counter or the
+/// milliseconds since the epoch of a timestamp. Bytes are an array of numbers. A value of unknown
+/// type also has the `typeCode` it was stored with.
+impl Serialize for Patch {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            Patch::Put {
+                path,
+                obj,
+                prop,
+                value,
+                conflict,
+            } => {
+                header(&mut map, "put", obj, path)?;
+                map.serialize_entry("prop", &SerProp(prop))?;
+                map.serialize_entry("value", &SerValue(&value.0, &value.1))?;
+                map.serialize_entry("conflict", conflict)?;
+            }
+            Patch::Insert {
+                path,
+                obj,
+                index,
+                value,
+            } => {
+                header(&mut map, "insert", obj, path)?;
+                map.serialize_entry("index", index)?;
+                map.serialize_entry("value", &SerValue(&value.0, &value.1))?;
+            }
+            Patch::Splice {
+                path,
+                obj,
+                index,
+                values,
+            } => {
+                header(&mut map, "splice", obj, path)?;
+                map.serialize_entry("index", index)?;
+                map.serialize_entry("values", &SerValues(values))?;
+            }
+            Patch::SpliceText {
+                path,
+                obj,
+                index,
+                value,
+            } => {
+                header(&mut map, "spliceText", obj, path)?;
+                map.serialize_entry("index", index)?;
+                map.serialize_entry("value", value)?;
+            }
+            Patch::Increment {
+                path,
+                obj,
+                prop,
+                value,
+            } => {
+                header(&mut map, "increment", obj, path)?;
+                map.serialize_entry("prop", &SerProp(prop))?;
+                map.serialize_entry("value", &value.0)?;
+                map.serialize_entry("id", &value.1)?;
+            }
+            Patch::Delete { path, obj, prop } => {
+                header(&mut map, "delete", obj, path)?;
+                map.serialize_entry("prop", &SerProp(prop))?;
+                map.serialize_entry("length", &1)?;
+            }
+            Patch::DeleteRange {
+                path,
+                obj,
+                index,
+                length,
+            } => {
+                header(&mut map, "delete", obj, path)?;
+                map.serialize_entry("prop", index)?;
+                map.serialize_entry("length", length)?;
+            }
+        }
+        map.end()
+    }
+}
+
+fn header<M: SerializeMap>(
+    map: &mut M,
+    action: &str,
+    obj: &ExId,
+    path: &[(ExId, Prop)],
+) -> Result<(), M::Error> {
+    map.serialize_entry("action", action)?;
+    map.serialize_entry("obj", obj)?;
+    map.serialize_entry("path", &SerPath(path))
+}
+
+struct SerProp<'a>(&'a Prop);
+
+impl<'a> Serialize for SerProp<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.0 {
+            Prop::Map(key) => serializer.serialize_str(key),
+            Prop::Seq(index) => serializer.serialize_u64(*index as u64),
+        }
+    }
+}
+
+struct SerPath<'a>(&'a [(ExId, Prop)]);
+
+impl<'a> Serialize for SerPath<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for (obj, prop) in self.0 {
+            seq.serialize_element(&SerStep(obj, prop))?;
+        }
+        seq.end()
+    }
+}
+
+struct SerStep<'a>(&'a ExId, &'a Prop);
+
+impl<'a> Serialize for SerStep<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("obj", self.0)?;
+        map.serialize_entry("prop", &SerProp(self.1))?;
+        map.end()
+    }
+}
+
+struct SerValues<'a>(&'a [(Value<'static>, ExId)]);
+
+impl<'a> Serialize for SerValues<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for (value, id) in self.0 {
+            seq.serialize_element(&SerValue(value, id))?;
+        }
+        seq.end()
+    }
+}
+
+struct SerValue<'a>(&'a Value<'static>, &'a ExId);
+
+impl<'a> Serialize for SerValue<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("datatype", self.0.type_name())?;
+        match self.0 {
+            Value::Object(_) => {}
+            Value::Scalar(s) => match s.as_ref() {
+                ScalarValue::Null => {}
+                ScalarValue::Unknown { type_code, bytes } => {
+                    map.serialize_entry("typeCode", type_code)?;
+                    map.serialize_entry("value", bytes)?;
+                }
+                // scalars serialize untagged, as the bare value
+                other => map.serialize_entry("value", other)?,
+            },
+        }
+        map.serialize_entry("id", self.1)?;
+        map.end()
+    }
+}
@@ -0,0 +1,79 @@
+use crate::exid::ExId;
+use crate::types::{Key, ObjId};
+use crate::{Automerge, Prop};
+
+/// Lookups of where objects are in a document, returned by [`Automerge::path_cache`].
+///
+/// The document records, for every object, the object it was created in and the key it was
+/// created at as the object is created or loaded, so finding the path to an object takes one
+/// step per level of nesting rather than a search of each parent like [`crate::Parents`]. A
+/// step into a map is a lookup, a step into a list or text finds the index of the element, which
+/// is logarithmic in its length.
+///
+/// Objects never move, so the path of an object only changes when elements are inserted or
+/// deleted before it in a list it is in. Paths are computed when they are asked for and always
+/// reflect the current state of the document. Unlike [`crate::Parents`] the path of an object
+/// which has been overwritten or deleted is where it was, without saying so.
+#[derive(Debug, Clone, Copy)]
+pub struct PathCache<'a> {
+    doc: &'a Automerge,
+}
+
+impl<'a> PathCache<'a> {
+    pub(crate) fn new(doc: &'a Automerge) -> Self {
+        Self { doc }
+    }
+
+    /// The object `obj` is in and the prop it is at, or `None` for the root or an object which
+    /// is not in this document.
+    pub fn parent<O: AsRef<ExId>>(&self, obj: O) -> Option<(ExId, Prop)> {
+        let obj = self.doc.exid_to_obj(obj.as_ref()).ok()?;
+        let (parent, key) = self.parent_key(&obj)?;
+        Some((
+            self.doc.id_to_exid(parent.0),
+            self.doc.ops.export_key(parent, key),
+        ))
+    }
+
+    /// The path from the root to `obj`, as the object and prop of each step like
+    /// [`crate::Parents::path`], or `None` if `obj` is not in this document.
+    pub fn path<O: AsRef<ExId>>(&self, obj: O) -> Option<Vec<(ExId, Prop)>> {
+        let mut obj = self.doc.exid_to_obj(obj.as_ref()).ok()?;
+        let mut path = Vec::new();
+        while let Some((parent, key)) = self.parent_key(&obj) {
+            path.push((
+                self.doc.id_to_exid(parent.0),
+                self.doc.ops.export_key(parent, key),
+            ));
+            obj = parent;
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Whether `obj` is `ancestor` or was created somewhere inside it, which is what decides
+    /// whether a subscription to `ancestor` is interested in a patch to `obj`. This doesn't
+    /// look at any ops so it is cheaper than [`Self::path`].
+    pub fn is_within<O: AsRef<ExId>, A: AsRef<ExId>>(&self, obj: O, ancestor: A) -> bool {
+        let (mut obj, ancestor) = match (
+            self.doc.exid_to_obj(obj.as_ref()),
+            self.doc.exid_to_obj(ancestor.as_ref()),
+        ) {
+            (Ok(obj), Ok(ancestor)) => (obj, ancestor),
+            _ => return false,
+        };
+        loop {
+            if obj == ancestor {
+                return true;
+            }
+            match self.parent_key(&obj) {
+                Some((parent, _)) => obj = parent,
+                None => return false,
+            }
+        }
+    }
+
+    fn parent_key(&self, obj: &ObjId) -> Option<(ObjId, Key)> {
+        self.doc.ops.created_at(obj)
+    }
+}
@@ -0,0 +1,33 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How far through applying a batch of changes we are, see
+/// [`crate::Automerge::apply_changes_with_progress`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ApplyProgress {
+    /// The number of changes applied so far.
+    pub applied: usize,
+    /// The number of changes in the batch which were not already in the document.
+    pub total: usize,
+}
+
+/// A flag which can be used to cancel a long running operation from another thread.
+///
+/// Clones of a token share the same flag, so keep one clone and pass another to the operation.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask any operation holding a clone of this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
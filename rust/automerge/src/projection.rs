@@ -0,0 +1,172 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::exid::ExId;
+use crate::{Automerge, AutomergeError, ObjType, Patch, Prop, ScalarValue, Value};
+
+/// The columns of a row, keyed by column name.
+pub type Row = BTreeMap<String, ScalarValue>;
+
+/// A change to a row of a table-shaped object, for mirroring it into a relational store, see
+/// [`Automerge::project_rows`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowOp {
+    /// Insert the row with this key, or replace it if there is one
+    Upsert { key: String, columns: Row },
+    /// Delete the row with this key
+    Delete { key: String },
+}
+
+impl Automerge {
+    /// The rows of the table-shaped object `obj`, as an upsert of each row.
+    ///
+    /// A table-shaped object is a map or table whose values are maps, one per row, keyed by the
+    /// key of the row, or a list of maps, keyed by the id of each map, which stays the same as
+    /// rows are inserted and deleted around it. The columns of a row are the scalar values of its
+    /// map; nested objects are left out, and conflicts are resolved to the winning value as in
+    /// [`Self::get`]. Values of `obj` which aren't maps are not rows.
+    ///
+    /// To keep a store up to date as the document changes use a [`RowProjection`].
+    pub fn project_rows<O: AsRef<ExId>>(&self, obj: O) -> Result<Vec<RowOp>, AutomergeError> {
+        Ok(self
+            .rows(obj.as_ref())?
+            .into_iter()
+            .map(|(key, (_, columns))| RowOp::Upsert { key, columns })
+            .collect())
+    }
+
+    /// The rows of `table` by key, with the id of the map of each.
+    fn rows(&self, table: &ExId) -> Result<BTreeMap<String, (ExId, Row)>, AutomergeError> {
+        let entries: Vec<(String, ExId)> = match self.object_type(table) {
+            Some(ObjType::Map | ObjType::Table) => self
+                .map_range(table, ..)
+                .filter(|(_, v, _)| is_row(v))
+                .map(|(k, _, id)| (k.to_string(), id))
+                .collect(),
+            Some(ObjType::List) => self
+                .list_range(table, ..)
+                .filter(|(_, v, _)| is_row(v))
+                .map(|(_, _, id)| (id.to_string(), id))
+                .collect(),
+            Some(ObjType::Text) => Vec::new(),
+            None => return Err(AutomergeError::InvalidObjId(table.to_string())),
+        };
+        Ok(entries
+            .into_iter()
+            .map(|(key, id)| {
+                let columns = self.columns(&id);
+                (key, (id, columns))
+            })
+            .collect())
+    }
+
+    fn columns(&self, row: &ExId) -> Row {
+        self.map_range(row, ..)
+            .filter_map(|(k, v, _)| match v {
+                Value::Scalar(s) => Some((k.to_string(), s.into_owned())),
+                Value::Object(_) => None,
+            })
+            .collect()
+    }
+}
+
+fn is_row(value: &Value<'_>) -> bool {
+    matches!(value, Value::Object(ObjType::Map | ObjType::Table))
+}
+
+/// Keeps a relational store in step with a table-shaped object, see [`Automerge::project_rows`].
+///
+/// The projection remembers the rows it last produced. [`Self::update`] is passed the patches
+/// made to the document since then, e.g. by a [`crate::VecOpObserver`], and returns the row
+/// operations which bring the store up to date, only looking at the rows the patches touch. A
+/// patch to the table itself, which may add or remove rows, compares every row.
+#[derive(Debug, Clone)]
+pub struct RowProjection {
+    table: ExId,
+    rows: BTreeMap<String, (ExId, Row)>,
+}
+
+impl RowProjection {
+    /// A projection of `table` which hasn't produced any rows yet, so the first call to
+    /// [`Self::refresh`] upserts every row.
+    pub fn new(table: ExId) -> Self {
+        Self {
+            table,
+            rows: BTreeMap::new(),
+        }
+    }
+
+    /// The table this projects.
+    pub fn table(&self) -> &ExId {
+        &self.table
+    }
+
+    /// Compare every row of the table in `doc` with the rows last produced, returning the
+    /// operations for the rows which have changed.
+    pub fn refresh(&mut self, doc: &Automerge) -> Result<Vec<RowOp>, AutomergeError> {
+        let rows = doc.rows(&self.table)?;
+        let mut ops = self
+            .rows
+            .keys()
+            .filter(|key| !rows.contains_key(*key))
+            .map(|key| RowOp::Delete { key: key.clone() })
+            .collect::<Vec<_>>();
+        for (key, row) in &rows {
+            if self.rows.get(key) != Some(row) {
+                ops.push(RowOp::Upsert {
+                    key: key.clone(),
+                    columns: row.1.clone(),
+                });
+            }
+        }
+        self.rows = rows;
+        Ok(ops)
+    }
+
+    /// The operations for the rows changed by `patches`, which must be every patch made to
+    /// `doc` since the projection last produced rows.
+    pub fn update(
+        &mut self,
+        doc: &Automerge,
+        patches: &[Patch],
+    ) -> Result<Vec<RowOp>, AutomergeError> {
+        let mut touched = HashSet::new();
+        for patch in patches {
+            let (obj, path) = location(patch);
+            if *obj == self.table {
+                return self.refresh(doc);
+            }
+            // the step of the path out of the table is into the row
+            if let Some(i) = path.iter().position(|(o, _)| *o == self.table) {
+                let row = path.get(i + 1).map_or(obj, |(o, _)| o);
+                touched.insert(row.clone());
+            }
+        }
+        let mut ops = Vec::new();
+        for (key, (id, columns)) in self.rows.iter_mut() {
+            if touched.contains(id) {
+                let current = doc.columns(id);
+                if *columns != current {
+                    *columns = current;
+                    ops.push(RowOp::Upsert {
+                        key: key.clone(),
+                        columns: columns.clone(),
+                    });
+                }
+            }
+        }
+        Ok(ops)
+    }
+}
+
+/// The object a patch is to and the path to it.
+fn location(patch: &Patch) -> (&ExId, &[(ExId, Prop)]) {
+    match patch {
+        Patch::Put { obj, path, .. }
+        | Patch::Insert { obj, path, .. }
+        | Patch::Increment { obj, path, .. }
+        | Patch::Delete { obj, path, .. }
+        | Patch::Splice { obj, path, .. }
+        | Patch::SpliceText { obj, path, .. }
+        | Patch::DeleteRange { obj, path, .. } => (obj, path),
+    }
+}
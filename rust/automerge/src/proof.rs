@@ -0,0 +1,345 @@
+//! Proofs that a value in a document was introduced by a particular change.
+//!
+//! A [`ValueProof`] contains the change which created a value, along with a chain of changes
+//! linking it, via their dependency hashes, to a set of document heads. Because change hashes are
+//! computed over the full bytes of each change, a third party who trusts the heads can check the
+//! proof without having access to the rest of the document.
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::exid::ExId;
+use crate::storage::parse;
+use crate::sync::{encode_hashes, encode_many};
+use crate::types::OpType;
+use crate::{ActorId, Automerge, AutomergeError, Change, ChangeHash, Prop, Value};
+
+const PROOF_TYPE: u8 = 0x44; // first byte of an encoded proof, for identification
+
+/// A proof that a value was introduced by a specific change, and that this change is an ancestor
+/// of some heads.
+///
+/// Created with [`Automerge::prove_value`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueProof {
+    heads: Vec<ChangeHash>,
+    counter: u64,
+    /// The change which introduced the value, followed by the changes on the path from that change
+    /// to one of `heads`.
+    changes: Vec<Change>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum VerificationError {
+    #[error("proof contained no changes")]
+    NoChanges,
+    #[error("change {0} has an invalid checksum")]
+    BadChecksum(ChangeHash),
+    #[error("operation {0} is not part of the introducing change")]
+    MissingOp(u64),
+    #[error("operation {0} does not create a value")]
+    NotAValue(u64),
+    #[error("change {0} is not a dependency of the next change in the chain")]
+    BrokenChain(ChangeHash),
+    #[error("change {0} is not one of the proof heads")]
+    NotAHead(ChangeHash),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    #[error("{0}")]
+    Parse(String),
+    #[error("wrong type: expected one of {expected_one_of:?} but found {found}")]
+    WrongType { expected_one_of: Vec<u8>, found: u8 },
+    #[error("not enough input")]
+    NotEnoughInput,
+}
+
+impl From<parse::leb128::Error> for DecodeError {
+    fn from(e: parse::leb128::Error) -> Self {
+        Self::Parse(e.to_string())
+    }
+}
+
+impl ValueProof {
+    /// The heads the proof links the value to.
+    pub fn heads(&self) -> &[ChangeHash] {
+        &self.heads
+    }
+
+    /// The change which introduced the value.
+    pub fn change(&self) -> &Change {
+        // SAFETY: proofs are only constructed with at least one change, either in
+        // `Automerge::prove_value` or in `Self::parse`
+        &self.changes[0]
+    }
+
+    /// The chain of changes from the introducing change to one of the heads, inclusive.
+    pub fn chain(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// The actor who authored the change which introduced the value.
+    pub fn author(&self) -> &ActorId {
+        self.change().actor_id()
+    }
+
+    /// Check that the proof is internally consistent, returning the value which was proven.
+    ///
+    /// This checks that
+    /// - every change in the chain has a valid checksum
+    /// - the introducing change contains an operation which creates a value
+    /// - every change in the chain is a dependency of the next change
+    /// - the last change in the chain is one of the heads
+    pub fn verify(&self) -> Result<Value<'static>, VerificationError> {
+        let first = self.changes.first().ok_or(VerificationError::NoChanges)?;
+        for change in &self.changes {
+            if !change.checksum_valid() {
+                return Err(VerificationError::BadChecksum(change.hash()));
+            }
+        }
+
+        let start = first.start_op().get();
+        if self.counter < start || self.counter > first.max_op() {
+            return Err(VerificationError::MissingOp(self.counter));
+        }
+        let op = first
+            .iter_ops()
+            .nth((self.counter - start) as usize)
+            .ok_or(VerificationError::MissingOp(self.counter))?;
+        let value = match OpType::from_index_and_value(op.action, op.val) {
+            Ok(OpType::Put(v)) => Value::Scalar(std::borrow::Cow::Owned(v)),
+            Ok(OpType::Make(t)) => Value::Object(t),
+            _ => return Err(VerificationError::NotAValue(self.counter)),
+        };
+
+        for pair in self.changes.windows(2) {
+            if !pair[1].deps().contains(&pair[0].hash()) {
+                return Err(VerificationError::BrokenChain(pair[0].hash()));
+            }
+        }
+
+        // SAFETY: we checked above that there is at least one change
+        let last = self.changes.last().unwrap().hash();
+        if !self.heads.contains(&last) {
+            return Err(VerificationError::NotAHead(last));
+        }
+
+        Ok(value)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![PROOF_TYPE];
+        encode_hashes(&mut buf, &self.heads);
+        leb128::write::unsigned(&mut buf, self.counter).unwrap();
+        encode_many(&mut buf, self.changes.iter(), |buf, change| {
+            leb128::write::unsigned(buf, change.raw_bytes().len() as u64).unwrap();
+            buf.extend(change.raw_bytes())
+        });
+        buf
+    }
+
+    pub fn decode(input: &[u8]) -> Result<Self, DecodeError> {
+        let input = parse::Input::new(input);
+        match Self::parse(input) {
+            Ok((_, proof)) => Ok(proof),
+            Err(parse::ParseError::Incomplete(_)) => Err(DecodeError::NotEnoughInput),
+            Err(parse::ParseError::Error(e)) => Err(e),
+        }
+    }
+
+    pub(crate) fn parse(input: parse::Input<'_>) -> parse::ParseResult<'_, Self, DecodeError> {
+        let (i, proof_type) = parse::take1(input)?;
+        if proof_type != PROOF_TYPE {
+            return Err(parse::ParseError::Error(DecodeError::WrongType {
+                expected_one_of: vec![PROOF_TYPE],
+                found: proof_type,
+            }));
+        }
+
+        let (i, heads) = parse::length_prefixed(parse::change_hash)(i)?;
+        let (i, counter) = parse::leb128_u64(i)?;
+        let change_parser = |i| {
+            let (i, bytes) = parse::length_prefixed_bytes(i)?;
+            let change = Change::try_from(bytes)
+                .map_err(|e| parse::ParseError::Error(DecodeError::Parse(e.to_string())))?;
+            Ok((i, change))
+        };
+        let (i, changes) = parse::length_prefixed(change_parser)(i)?;
+        if changes.is_empty() {
+            return Err(parse::ParseError::Error(DecodeError::Parse(
+                "proof contained no changes".to_string(),
+            )));
+        }
+
+        Ok((
+            i,
+            Self {
+                heads,
+                counter,
+                changes,
+            },
+        ))
+    }
+}
+
+impl Automerge {
+    /// Create a proof that the value at `prop` in `obj`, as of `heads`, was introduced by a
+    /// particular change.
+    ///
+    /// Returns `None` if there is no value at `prop`. If there are conflicting values the proof is
+    /// for the winning value.
+    pub fn prove_value<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+        heads: &[ChangeHash],
+    ) -> Result<Option<ValueProof>, AutomergeError> {
+        let (counter, actor) = match self.get_at(obj, prop, heads)? {
+            Some((_, ExId::Id(counter, actor, _))) => (counter, actor),
+            _ => return Ok(None),
+        };
+        let introducing = match self.change_for_op(counter, &actor) {
+            Some(hash) => hash,
+            None => return Ok(None),
+        };
+        let changes = self
+            .dependency_path(introducing, heads)
+            .into_iter()
+            .map(|hash| self.history[self.history_index[&hash]].clone())
+            .collect();
+        let mut heads = heads.to_vec();
+        heads.sort();
+        Ok(Some(ValueProof {
+            heads,
+            counter,
+            changes,
+        }))
+    }
+
+    /// Find the change by `actor` which contains the op with counter `counter`.
+    fn change_for_op(&self, counter: u64, actor: &ActorId) -> Option<ChangeHash> {
+        let actor_index = self.ops.m.actors.lookup(actor)?;
+        let indices = self.states.get(&actor_index)?;
+        // changes by a single actor are stored in seq order and so also in start op order
+        let pos = indices.partition_point(|i| self.history[*i].start_op().get() <= counter);
+        let change = &self.history[indices[pos.checked_sub(1)?]];
+        if counter <= change.max_op() {
+            Some(change.hash())
+        } else {
+            None
+        }
+    }
+
+    /// The shortest path through the dependency graph from `from` to one of `heads`, starting with
+    /// `from` and ending with the head.
+    fn dependency_path(&self, from: ChangeHash, heads: &[ChangeHash]) -> Vec<ChangeHash> {
+        // breadth first search from the heads towards `from`, recording the child we came from
+        let mut came_from: HashMap<ChangeHash, Option<ChangeHash>> = HashMap::new();
+        let mut queue = VecDeque::new();
+        let mut seen = HashSet::new();
+        for head in heads {
+            if seen.insert(*head) {
+                came_from.insert(*head, None);
+                queue.push_back(*head);
+            }
+        }
+        while let Some(hash) = queue.pop_front() {
+            if hash == from {
+                break;
+            }
+            if let Some(change) = self.get_change_by_hash(&hash) {
+                for dep in change.deps() {
+                    if seen.insert(*dep) {
+                        came_from.insert(*dep, Some(hash));
+                        queue.push_back(*dep);
+                    }
+                }
+            }
+        }
+        let mut path = vec![from];
+        let mut current = from;
+        while let Some(Some(next)) = came_from.get(&current) {
+            path.push(*next);
+            current = *next;
+        }
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::Transactable;
+    use crate::{AutoCommit, ObjType, ScalarValue, ROOT};
+
+    #[test]
+    fn prove_value_round_trip() {
+        let mut doc = AutoCommit::new();
+        doc.put(&ROOT, "author", "alice").unwrap();
+        doc.commit();
+        let list = doc.put_object(&ROOT, "list", ObjType::List).unwrap();
+        doc.commit();
+        for i in 0..5 {
+            doc.insert(&list, i, i as i64).unwrap();
+            doc.commit();
+        }
+        let heads = doc.get_heads();
+
+        let proof = doc
+            .document()
+            .prove_value(&ROOT, "author", &heads)
+            .unwrap()
+            .unwrap();
+        assert_eq!(proof.chain().len(), 7);
+        assert_eq!(proof.author(), doc.get_actor());
+
+        let decoded = ValueProof::decode(&proof.encode()).unwrap();
+        assert_eq!(decoded, proof);
+        assert_eq!(
+            decoded.verify().unwrap(),
+            Value::Scalar(std::borrow::Cow::Owned(ScalarValue::from("alice")))
+        );
+
+        let proof = doc
+            .document()
+            .prove_value(&ROOT, "list", &heads)
+            .unwrap()
+            .unwrap();
+        assert_eq!(proof.verify().unwrap(), Value::Object(ObjType::List));
+    }
+
+    #[test]
+    fn prove_missing_value() {
+        let mut doc = AutoCommit::new();
+        doc.put(&ROOT, "a", 1).unwrap();
+        let heads = doc.get_heads();
+        assert!(doc
+            .document()
+            .prove_value(&ROOT, "b", &heads)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn broken_chain_fails_verification() {
+        let mut doc = AutoCommit::new();
+        doc.put(&ROOT, "a", 1).unwrap();
+        doc.commit();
+        doc.put(&ROOT, "b", 2).unwrap();
+        doc.commit();
+        let heads = doc.get_heads();
+        let mut proof = doc
+            .document()
+            .prove_value(&ROOT, "a", &heads)
+            .unwrap()
+            .unwrap();
+        assert!(proof.verify().is_ok());
+
+        let first = proof.changes[0].hash();
+        proof.changes.remove(1);
+        proof.changes.push(doc.document().history[0].clone());
+        assert_eq!(proof.verify(), Err(VerificationError::BrokenChain(first)));
+
+        proof.changes.truncate(1);
+        assert_eq!(proof.verify(), Err(VerificationError::NotAHead(first)));
+    }
+}
@@ -1,11 +1,14 @@
 use crate::op_tree::{OpSetMetadata, OpTreeNode};
 use crate::types::{Clock, Counter, Key, Op, OpId, OpType, ScalarValue};
+use crate::value::Value;
 use fxhash::FxBuildHasher;
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::ops::{Bound, Range, RangeBounds};
 
 mod elem_id_pos;
+mod index_after;
 mod insert;
 mod keys;
 mod keys_at;
@@ -26,6 +29,7 @@ mod seek_op;
 mod seek_op_with_patch;
 
 pub(crate) use elem_id_pos::ElemIdPos;
+pub(crate) use index_after::IndexAfter;
 pub(crate) use insert::InsertNth;
 pub(crate) use keys::Keys;
 pub(crate) use keys_at::KeysAt;
@@ -196,7 +200,7 @@ pub(crate) struct VisWindow {
 }
 
 impl VisWindow {
-    fn visible_at(&mut self, op: &Op, pos: usize, clock: &Clock) -> bool {
+    pub(crate) fn visible_at(&mut self, op: &Op, pos: usize, clock: &Clock) -> bool {
         if !clock.covers(&op.id) {
             return false;
         }
@@ -239,6 +243,19 @@ impl VisWindow {
         visible
     }
 
+    /// The value of `op`, which `visible_at` has said is visible. An increment is only visible
+    /// when it is the last one of its counter, whose value it then stands for.
+    pub(crate) fn value_at<'a>(&self, op: &'a Op, pos: usize) -> Value<'a> {
+        match op.action {
+            OpType::Increment(_) => self
+                .seen_op(op, pos)
+                .pop()
+                .map(|(_, op)| op.value().into_owned())
+                .unwrap_or_else(|| op.value()),
+            _ => op.value(),
+        }
+    }
+
     pub(crate) fn seen_op(&self, op: &Op, pos: usize) -> Vec<(usize, Op)> {
         let mut result = vec![];
         for pred in &op.pred {
@@ -269,3 +286,33 @@ where
     }
     left
 }
+
+/// The indices in the op tree of a map of the ops whose keys are in `range`.
+///
+/// The ops of a map are sorted by key, so both ends are found with a binary search and a narrow
+/// range of a large map doesn't have to look at the ops outside it. Lists have no keys, and
+/// their range is empty.
+pub(crate) fn map_key_range<R: RangeBounds<String>>(
+    node: &OpTreeNode,
+    m: &OpSetMetadata,
+    range: &R,
+) -> Range<usize> {
+    // the first index at which `before` is false for the key
+    let position = |before: &dyn Fn(&str) -> bool| {
+        binary_search_by(node, |op| match op.key {
+            Key::Map(p) if before(m.props.get(p)) => Ordering::Less,
+            _ => Ordering::Greater,
+        })
+    };
+    let start = match range.start_bound() {
+        Bound::Included(s) => position(&|p| p < s.as_str()),
+        Bound::Excluded(s) => position(&|p| p <= s.as_str()),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(e) => position(&|p| p <= e.as_str()),
+        Bound::Excluded(e) => position(&|p| p < e.as_str()),
+        Bound::Unbounded => node.len(),
+    };
+    start..end.max(start)
+}
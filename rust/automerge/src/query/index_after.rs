@@ -0,0 +1,72 @@
+use crate::op_tree::OpTreeNode;
+use crate::query::{QueryResult, TreeQuery};
+use crate::types::{ElemId, Key, Op};
+use std::fmt::Debug;
+
+/// The number of visible elements up to and including `elemid`, which may have been deleted,
+/// i.e. the index at which an element inserted after `elemid` appears.
+///
+/// Nodes which don't contain the op which inserted `elemid` are skipped using their index, so
+/// this only looks at the ops of the nodes on the path to the element.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct IndexAfter {
+    elemid: ElemId,
+    seen: usize,
+    last_seen: Option<Key>,
+    found: bool,
+}
+
+impl IndexAfter {
+    pub(crate) fn new(elemid: ElemId) -> Self {
+        Self {
+            elemid,
+            seen: 0,
+            last_seen: None,
+            found: false,
+        }
+    }
+
+    pub(crate) fn index(&self) -> Option<usize> {
+        if self.found {
+            Some(self.seen)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> TreeQuery<'a> for IndexAfter {
+    fn query_node(&mut self, child: &OpTreeNode) -> QueryResult {
+        // once the element is found we only need the ops which update it, which are next
+        if self.found || child.index.ops.contains(&self.elemid.0) {
+            return QueryResult::Descend;
+        }
+        let mut num_vis = child.index.visible_len();
+        if let Some(last_seen) = self.last_seen {
+            if child.index.has_visible(&last_seen) {
+                num_vis -= 1;
+            }
+        }
+        self.seen += num_vis;
+        let last_elemid = child.last().elemid_or_key();
+        if child.index.has_visible(&last_elemid) {
+            self.last_seen = Some(last_elemid);
+        }
+        QueryResult::Next
+    }
+
+    fn query_element(&mut self, element: &'a Op) -> QueryResult {
+        if element.insert {
+            if self.found {
+                return QueryResult::Finish;
+            }
+            self.last_seen = None;
+            self.found = ElemId(element.id) == self.elemid;
+        }
+        if element.visible() && self.last_seen.is_none() {
+            self.seen += 1;
+            self.last_seen = Some(element.elemid_or_key());
+        }
+        QueryResult::Next
+    }
+}
@@ -2,7 +2,7 @@ use crate::exid::ExId;
 use crate::op_tree::OpTreeNode;
 use crate::types::{ElemId, OpId};
 use crate::values::ValueIter;
-use crate::{Automerge, Value};
+use crate::{Automerge, Prop, Value};
 use std::fmt::Debug;
 use std::ops::RangeBounds;
 
@@ -32,8 +32,9 @@ impl<'a, R: RangeBounds<usize>> ListRange<'a, R> {
 }
 
 impl<'a, R: RangeBounds<usize>> ValueIter<'a> for ListRange<'a, R> {
-    fn next_value(&mut self, doc: &'a Automerge) -> Option<(Value<'a>, ExId)> {
-        self.next().map(|(_, val, id)| (val, doc.id_to_exid(id)))
+    fn next_value(&mut self, doc: &'a Automerge) -> Option<(Prop, Value<'a>, ExId)> {
+        self.next()
+            .map(|(idx, val, id)| (idx.into(), val, doc.id_to_exid(id)))
     }
 }
 
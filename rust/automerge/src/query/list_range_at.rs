@@ -3,7 +3,7 @@ use crate::exid::ExId;
 use crate::op_tree::OpTreeNode;
 use crate::types::{Clock, ElemId, OpId};
 use crate::values::ValueIter;
-use crate::{Automerge, Value};
+use crate::{Automerge, Prop, Value};
 use std::fmt::Debug;
 use std::ops::RangeBounds;
 
@@ -21,8 +21,9 @@ pub(crate) struct ListRangeAt<'a, R: RangeBounds<usize>> {
 }
 
 impl<'a, R: RangeBounds<usize>> ValueIter<'a> for ListRangeAt<'a, R> {
-    fn next_value(&mut self, doc: &'a Automerge) -> Option<(Value<'a>, ExId)> {
-        self.next().map(|(_, val, id)| (val, doc.id_to_exid(id)))
+    fn next_value(&mut self, doc: &'a Automerge) -> Option<(Prop, Value<'a>, ExId)> {
+        self.next()
+            .map(|(idx, val, id)| (idx.into(), val, doc.id_to_exid(id)))
     }
 }
 
@@ -55,13 +56,17 @@ impl<'a, R: RangeBounds<usize>> Iterator for ListRangeAt<'a, R> {
                     self.last_elemid = op.elemid();
                     self.pos += 1;
                     if self.range.contains(&(self.pos - 1)) {
-                        let result = self.next_result.replace((self.pos - 1, op.value(), op.id));
+                        let result = self.next_result.replace((
+                            self.pos - 1,
+                            self.window.value_at(op, i),
+                            op.id,
+                        ));
                         if result.is_some() {
                             return result;
                         }
                     }
                 } else if self.pos > 0 && self.range.contains(&(self.pos - 1)) {
-                    self.next_result = Some((self.pos - 1, op.value(), op.id));
+                    self.next_result = Some((self.pos - 1, self.window.value_at(op, i), op.id));
                 }
             }
         }
@@ -1,10 +1,11 @@
 use crate::exid::ExId;
 use crate::op_tree::{OpSetMetadata, OpTreeNode};
+use crate::query::map_key_range;
 use crate::types::{Key, OpId};
 use crate::values::ValueIter;
-use crate::{Automerge, Value};
+use crate::{Automerge, Prop, Value};
 use std::fmt::Debug;
-use std::ops::RangeBounds;
+use std::ops::{Range, RangeBounds};
 
 #[derive(Debug)]
 pub(crate) struct MapRange<'a, R: RangeBounds<String>> {
@@ -19,19 +20,21 @@ pub(crate) struct MapRange<'a, R: RangeBounds<String>> {
 }
 
 impl<'a, R: RangeBounds<String>> ValueIter<'a> for MapRange<'a, R> {
-    fn next_value(&mut self, doc: &'a Automerge) -> Option<(Value<'a>, ExId)> {
-        self.next().map(|(_, val, id)| (val, doc.id_to_exid(id)))
+    fn next_value(&mut self, doc: &'a Automerge) -> Option<(Prop, Value<'a>, ExId)> {
+        self.next()
+            .map(|(key, val, id)| (key.into(), val, doc.id_to_exid(id)))
     }
 }
 
 impl<'a, R: RangeBounds<String>> MapRange<'a, R> {
     pub(crate) fn new(range: R, root_child: &'a OpTreeNode, meta: &'a OpSetMetadata) -> Self {
+        let Range { start, end } = map_key_range(root_child, meta, &range);
         Self {
             range,
-            index: 0,
+            index: start,
             last_key: None,
             next_result: None,
-            index_back: root_child.len(),
+            index_back: end,
             last_key_back: None,
             root_child,
             meta,
@@ -42,9 +45,6 @@ impl<'a, R: RangeBounds<String>> MapRange<'a, R> {
 impl<'a, R: RangeBounds<String>> Iterator for MapRange<'a, R> {
     type Item = (&'a str, Value<'a>, OpId);
 
-    // FIXME: this is fine if we're scanning everything (see values()) but could be much more efficient
-    // if we're scanning a narrow range on a map with many keys... we should be able to seek to the starting
-    // point and stop at the end point and not needless scan all the ops before and after the range
     fn next(&mut self) -> Option<Self::Item> {
         for i in self.index..self.index_back {
             let op = self.root_child.get(i)?;
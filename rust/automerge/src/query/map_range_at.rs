@@ -1,11 +1,12 @@
 use crate::clock::Clock;
 use crate::exid::ExId;
 use crate::op_tree::{OpSetMetadata, OpTreeNode};
+use crate::query::map_key_range;
 use crate::types::{Key, OpId};
 use crate::values::ValueIter;
-use crate::{Automerge, Value};
+use crate::{Automerge, Prop, Value};
 use std::fmt::Debug;
-use std::ops::RangeBounds;
+use std::ops::{Range, RangeBounds};
 
 use super::VisWindow;
 
@@ -27,8 +28,9 @@ pub(crate) struct MapRangeAt<'a, R: RangeBounds<String>> {
 }
 
 impl<'a, R: RangeBounds<String>> ValueIter<'a> for MapRangeAt<'a, R> {
-    fn next_value(&mut self, doc: &'a Automerge) -> Option<(Value<'a>, ExId)> {
-        self.next().map(|(_, val, id)| (val, doc.id_to_exid(id)))
+    fn next_value(&mut self, doc: &'a Automerge) -> Option<(Prop, Value<'a>, ExId)> {
+        self.next()
+            .map(|(key, val, id)| (key.into(), val, doc.id_to_exid(id)))
     }
 }
 
@@ -39,14 +41,15 @@ impl<'a, R: RangeBounds<String>> MapRangeAt<'a, R> {
         meta: &'a OpSetMetadata,
         clock: Clock,
     ) -> Self {
+        let Range { start, end } = map_key_range(root_child, meta, &range);
         Self {
             clock,
             window: VisWindow::default(),
             range,
-            index: 0,
+            index: start,
             last_key: None,
             next_result: None,
-            index_back: root_child.len(),
+            index_back: end,
             last_key_back: None,
             root_child,
             meta,
@@ -68,7 +71,9 @@ impl<'a, R: RangeBounds<String>> Iterator for MapRangeAt<'a, R> {
                     Key::Seq(_) => return None, // this is a list
                 };
                 if self.range.contains(prop) {
-                    let result = self.next_result.replace((prop, op.value(), op.id));
+                    let result =
+                        self.next_result
+                            .replace((prop, self.window.value_at(op, i), op.id));
                     if Some(op.key) != self.last_key {
                         self.last_key = Some(op.key);
                         if result.is_some() {
@@ -95,7 +100,7 @@ impl<'a, R: RangeBounds<String>> DoubleEndedIterator for MapRangeAt<'a, R> {
                     Key::Seq(_) => return None, // this is a list
                 };
                 if self.range.contains(prop) {
-                    return Some((prop, op.value(), op.id));
+                    return Some((prop, self.window.value_at(op, i), op.id));
                 }
             }
         }
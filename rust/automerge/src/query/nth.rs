@@ -1,5 +1,5 @@
 use crate::error::AutomergeError;
-use crate::op_tree::OpTreeNode;
+use crate::op_tree::{OpSetMetadata, OpTreeNode};
 use crate::query::{QueryResult, TreeQuery};
 use crate::types::{Key, Op};
 use std::fmt::Debug;
@@ -14,6 +14,8 @@ pub(crate) struct Nth<'a> {
     pub(crate) ops: Vec<&'a Op>,
     pub(crate) ops_pos: Vec<usize>,
     pub(crate) pos: usize,
+    /// Where in the op tree the target element starts, if it is already known
+    start: Option<usize>,
 }
 
 impl<'a> Nth<'a> {
@@ -25,6 +27,17 @@ impl<'a> Nth<'a> {
             ops: vec![],
             ops_pos: vec![],
             pos: 0,
+            start: None,
+        }
+    }
+
+    /// Search for element `target` starting at `start`, the position in the op tree of the op
+    /// which inserted it, which must be correct.
+    #[cfg(feature = "seq-index")]
+    pub(crate) fn with_start(target: usize, start: usize) -> Self {
+        Nth {
+            start: Some(start),
+            ..Self::new(target)
         }
     }
 
@@ -40,6 +53,21 @@ impl<'a> Nth<'a> {
 }
 
 impl<'a> TreeQuery<'a> for Nth<'a> {
+    fn query_node_with_metadata(
+        &mut self,
+        child: &'a OpTreeNode,
+        _m: &OpSetMetadata,
+    ) -> QueryResult {
+        // only the root is queried before the start is taken, skip straight to the target
+        if let Some(start) = self.start.take() {
+            self.seen = self.target;
+            self.pos = start;
+            QueryResult::Skip(start)
+        } else {
+            self.query_node(child)
+        }
+    }
+
     fn query_node(&mut self, child: &OpTreeNode) -> QueryResult {
         let mut num_vis = child.index.visible_len();
         if let Some(last_seen) = self.last_seen {
@@ -10,6 +10,7 @@ pub(crate) struct OpIdSearch {
     pos: usize,
     found: bool,
     key: Option<Key>,
+    visible: bool,
 }
 
 impl OpIdSearch {
@@ -19,6 +20,7 @@ impl OpIdSearch {
             pos: 0,
             found: false,
             key: None,
+            visible: false,
         }
     }
 
@@ -34,6 +36,11 @@ impl OpIdSearch {
     pub(crate) fn key(&self) -> &Option<Key> {
         &self.key
     }
+
+    /// Whether the operation, if found, has not been overwritten or deleted.
+    pub(crate) fn visible(&self) -> bool {
+        self.visible
+    }
 }
 
 impl<'a> TreeQuery<'a> for OpIdSearch {
@@ -49,6 +56,7 @@ impl<'a> TreeQuery<'a> for OpIdSearch {
     fn query_element(&mut self, element: &Op) -> QueryResult {
         if element.id == self.target {
             self.found = true;
+            self.visible = element.visible();
             if element.insert {
                 self.key = Some(Key::Seq(ElemId(element.id)));
             } else {
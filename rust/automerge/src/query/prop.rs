@@ -10,6 +10,7 @@ pub(crate) struct Prop<'a> {
     pub(crate) ops_pos: Vec<usize>,
     pub(crate) pos: usize,
     start: Option<Start>,
+    cached_start: Option<usize>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -28,8 +29,23 @@ impl<'a> Prop<'a> {
             ops_pos: vec![],
             pos: 0,
             start: None,
+            cached_start: None,
         }
     }
+
+    /// Search for the ops of `prop` starting at `start`, the index in the op tree at which a
+    /// previous search found them to start, which must still be correct.
+    pub(crate) fn with_start(prop: usize, start: usize) -> Self {
+        Prop {
+            cached_start: Some(start),
+            ..Self::new(prop)
+        }
+    }
+
+    /// The index in the op tree at which the ops of the key start, once the search is done.
+    pub(crate) fn start(&self) -> Option<usize> {
+        self.start.as_ref().map(|s| s.idx)
+    }
 }
 
 impl<'a> TreeQuery<'a> for Prop<'a> {
@@ -62,7 +78,9 @@ impl<'a> TreeQuery<'a> for Prop<'a> {
             }
         } else {
             // in the root node find the first op position for the key
-            let start = binary_search_by(child, |op| m.key_cmp(&op.key, &self.key));
+            let start = self
+                .cached_start
+                .unwrap_or_else(|| binary_search_by(child, |op| m.key_cmp(&op.key, &self.key)));
             self.start = Some(Start {
                 idx: start,
                 optree_len: child.len(),
@@ -0,0 +1,87 @@
+use crate::exid::ExId;
+use crate::op_set::OpSet;
+use crate::op_tree::OpTreeIter;
+use crate::types::{Key, Op};
+use crate::{OpType, ScalarValue};
+
+/// An op as it is stored in the document, returned by [`crate::Automerge::iter_ops`].
+///
+/// Every op which has been applied to an object is included, whether or not it is still visible,
+/// so this is the place to start for analysis such as who wrote each character of a text object
+/// or which keys of a map are edited most often. Deletes are not stored as ops of their own, a
+/// deleted op has the id of the delete among its [`Self::succ`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawOp {
+    /// The id of the op, which is also the id of the object it creates or of the element it
+    /// inserts.
+    pub id: ExId,
+    /// What the op does. The value of a counter is the value it was created with, not including
+    /// any increments.
+    pub action: OpType,
+    pub key: RawKey,
+    /// Whether the op inserts a new element after [`RawKey::Seq`] rather than updating it.
+    pub insert: bool,
+    /// The ops this op overwrites, deletes or increments.
+    pub pred: Vec<ExId>,
+    /// The ops which overwrite, delete or increment this op.
+    pub succ: Vec<ExId>,
+    /// Whether the op contributes to the current state of the object.
+    pub visible: bool,
+}
+
+/// The property of an object an op applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawKey {
+    Map(String),
+    /// The id of an element of a sequence, or `None` for the start of the sequence when
+    /// inserting.
+    Seq(Option<ExId>),
+}
+
+/// An iterator over the ops of an object in the order they are stored: map ops sorted by key and
+/// then by id, and sequence ops in the order of their elements, each element's insert op followed
+/// by the ops which update it.
+pub struct RawOps<'a> {
+    pub(crate) iter: Option<OpTreeIter<'a>>,
+    pub(crate) ops: &'a OpSet,
+}
+
+impl<'a> std::fmt::Debug for RawOps<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RawOps").finish()
+    }
+}
+
+impl<'a> RawOps<'a> {
+    fn export(&self, op: &Op) -> RawOp {
+        let action = match &op.action {
+            OpType::Put(ScalarValue::Counter(counter)) => {
+                OpType::Put(ScalarValue::counter(counter.start))
+            }
+            action => action.clone(),
+        };
+        let key = match op.key {
+            Key::Map(prop) => RawKey::Map(self.ops.m.props[prop].clone()),
+            Key::Seq(elem) if elem.is_head() => RawKey::Seq(None),
+            Key::Seq(elem) => RawKey::Seq(Some(self.ops.id_to_exid(elem.0))),
+        };
+        RawOp {
+            id: self.ops.id_to_exid(op.id),
+            action,
+            key,
+            insert: op.insert,
+            pred: op.pred.iter().map(|id| self.ops.id_to_exid(*id)).collect(),
+            succ: op.succ.iter().map(|id| self.ops.id_to_exid(*id)).collect(),
+            visible: op.visible(),
+        }
+    }
+}
+
+impl<'a> Iterator for RawOps<'a> {
+    type Item = RawOp;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let op = self.iter.as_mut()?.next()?;
+        Some(self.export(op))
+    }
+}
@@ -0,0 +1,85 @@
+use std::ops::RangeBounds;
+
+use crate::exid::ExId;
+use crate::{
+    Automerge, AutomergeError, ChangeHash, KeysAt, ListRangeAt, MapRangeAt, ObjType, Prop, Value,
+    Values,
+};
+
+/// A sequence of reads which all observe the document as of the same heads, returned by
+/// [`Automerge::read_tx`].
+///
+/// Every read is answered with the historical (`_at`) version of the corresponding method on
+/// [`Automerge`], so reads made through the same `ReadTransaction` agree with each other even if
+/// the document has had more changes applied in between. Applications which share a document
+/// between threads, e.g. behind a mutex, can release the lock between reads and continue at the
+/// same [`Self::heads`] with [`Automerge::read_tx_at`] once they have reacquired it.
+#[derive(Debug, Clone)]
+pub struct ReadTransaction<'a> {
+    doc: &'a Automerge,
+    heads: Vec<ChangeHash>,
+}
+
+impl<'a> ReadTransaction<'a> {
+    pub(crate) fn new(doc: &'a Automerge, heads: Vec<ChangeHash>) -> Self {
+        Self { doc, heads }
+    }
+
+    /// The heads every read is made at.
+    pub fn heads(&self) -> &[ChangeHash] {
+        &self.heads
+    }
+
+    pub fn get<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<(Value<'a>, ExId)>, AutomergeError> {
+        self.doc.get_at(obj, prop, &self.heads)
+    }
+
+    pub fn get_all<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Vec<(Value<'a>, ExId)>, AutomergeError> {
+        self.doc.get_all_at(obj, prop, &self.heads)
+    }
+
+    pub fn keys<O: AsRef<ExId>>(&self, obj: O) -> KeysAt<'a, '_> {
+        self.doc.keys_at(obj, &self.heads)
+    }
+
+    pub fn map_range<O: AsRef<ExId>, R: RangeBounds<String>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> MapRangeAt<'a, R> {
+        self.doc.map_range_at(obj, range, &self.heads)
+    }
+
+    pub fn list_range<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> ListRangeAt<'a, R> {
+        self.doc.list_range_at(obj, range, &self.heads)
+    }
+
+    pub fn values<O: AsRef<ExId>>(&self, obj: O) -> Values<'a> {
+        self.doc.values_at(obj, &self.heads)
+    }
+
+    pub fn length<O: AsRef<ExId>>(&self, obj: O) -> usize {
+        self.doc.length_at(obj, &self.heads)
+    }
+
+    pub fn text<O: AsRef<ExId>>(&self, obj: O) -> Result<String, AutomergeError> {
+        self.doc.text_at(obj, &self.heads)
+    }
+
+    /// The type of `obj`, which may have been created after the pinned heads.
+    pub fn object_type<O: AsRef<ExId>>(&self, obj: O) -> Option<ObjType> {
+        self.doc.object_type(obj)
+    }
+}
@@ -0,0 +1,261 @@
+//! Managing many documents which link to each other.
+//!
+//! A [`Repo`] holds a set of documents keyed by [`DocId`]. A document refers to another by
+//! storing a link, which is a string value of the form `automerge:<id>` built by
+//! [`DocId::link`]. Links are ordinary strings, so documents containing them can be read and
+//! synced by any automerge implementation; [`DocId::from_link`] and [`Repo::resolve`] turn them
+//! back into documents.
+//!
+//! The repo also keeps a [`sync::State`] per peer and document, so that syncing every document
+//! with a peer is a single call to [`Repo::generate_sync_messages`] on one side and
+//! [`Repo::receive_sync_message`] for each message on the other.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::exid::ExId;
+use crate::{sync, Automerge, AutomergeError, ObjType, Prop, ScalarValue, Value, ROOT};
+
+/// The prefix of the string values which link to another document.
+pub const LINK_SCHEME: &str = "automerge:";
+
+/// The identifier of a document in a [`Repo`].
+///
+/// Any non empty string without whitespace is a valid id.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DocId(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid document id `{0}`")]
+pub struct InvalidDocId(pub String);
+
+impl DocId {
+    /// The value to store in a document to link to this one.
+    pub fn link(&self) -> ScalarValue {
+        ScalarValue::Str(format!("{}{}", LINK_SCHEME, self.0).into())
+    }
+
+    /// The document `value` links to, if it is a link.
+    pub fn from_link(value: &ScalarValue) -> Option<DocId> {
+        match value {
+            ScalarValue::Str(s) => s.strip_prefix(LINK_SCHEME)?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for DocId {
+    type Err = InvalidDocId;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s.chars().any(char::is_whitespace) {
+            Err(InvalidDocId(s.to_string()))
+        } else {
+            Ok(DocId(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for DocId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A set of documents keyed by id, see the [module documentation](self).
+#[derive(Debug, Default)]
+pub struct Repo {
+    docs: BTreeMap<DocId, Automerge>,
+    sync_states: HashMap<(String, DocId), sync::State>,
+}
+
+impl Repo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `doc` as `id`, returning the document it replaces.
+    pub fn insert(&mut self, id: DocId, doc: Automerge) -> Option<Automerge> {
+        self.docs.insert(id, doc)
+    }
+
+    /// Get the document `id`, creating an empty one if there is none.
+    pub fn get_or_create(&mut self, id: &DocId) -> &mut Automerge {
+        self.docs.entry(id.clone()).or_default()
+    }
+
+    pub fn get(&self, id: &DocId) -> Option<&Automerge> {
+        self.docs.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &DocId) -> Option<&mut Automerge> {
+        self.docs.get_mut(id)
+    }
+
+    /// Remove the document `id` and forget its sync states.
+    pub fn remove(&mut self, id: &DocId) -> Option<Automerge> {
+        self.sync_states.retain(|(_, doc), _| doc != id);
+        self.docs.remove(id)
+    }
+
+    /// The ids of the documents in the repo, in order.
+    pub fn ids(&self) -> impl Iterator<Item = &DocId> + '_ {
+        self.docs.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// The document linked to by the value at `prop` of `obj` in document `id`.
+    ///
+    /// Returns `Ok(None)` if the value is not a link or the linked document is not in the repo.
+    pub fn resolve<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        id: &DocId,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<(DocId, &Automerge)>, AutomergeError> {
+        let doc = match self.docs.get(id) {
+            Some(doc) => doc,
+            None => return Ok(None),
+        };
+        let target = match doc.get(obj, prop)? {
+            Some((Value::Scalar(s), _)) => DocId::from_link(&s),
+            _ => None,
+        };
+        Ok(target.and_then(|t| self.docs.get(&t).map(|doc| (t, doc))))
+    }
+
+    /// The ids of every document linked to from anywhere in document `id`, in order, including
+    /// documents which are not in the repo.
+    pub fn links(&self, id: &DocId) -> Vec<DocId> {
+        let mut links = Vec::new();
+        if let Some(doc) = self.docs.get(id) {
+            collect_links(doc, &ROOT, ObjType::Map, &mut links);
+        }
+        links.sort();
+        links.dedup();
+        links
+    }
+
+    /// Generate a sync message for every document which has something to send to `peer`.
+    pub fn generate_sync_messages(&mut self, peer: &str) -> Vec<(DocId, sync::Message)> {
+        let mut messages = Vec::new();
+        for (id, doc) in &self.docs {
+            let state = self
+                .sync_states
+                .entry((peer.to_string(), id.clone()))
+                .or_default();
+            if let Some(message) = doc.generate_sync_message(state) {
+                messages.push((id.clone(), message));
+            }
+        }
+        messages
+    }
+
+    /// Apply a sync message for document `id` from `peer`, creating the document if the repo
+    /// does not have it yet.
+    pub fn receive_sync_message(
+        &mut self,
+        peer: &str,
+        id: &DocId,
+        message: sync::Message,
+    ) -> Result<(), AutomergeError> {
+        let doc = self.docs.entry(id.clone()).or_default();
+        let state = self
+            .sync_states
+            .entry((peer.to_string(), id.clone()))
+            .or_default();
+        doc.receive_sync_message(state, message)
+    }
+
+    /// Forget the sync states for `peer`, e.g. when it disconnects.
+    pub fn remove_peer(&mut self, peer: &str) {
+        self.sync_states.retain(|(p, _), _| p != peer);
+    }
+}
+
+fn collect_links(doc: &Automerge, obj: &ExId, obj_type: ObjType, links: &mut Vec<DocId>) {
+    let values: Vec<(Value<'_>, ExId)> = match obj_type {
+        ObjType::Map | ObjType::Table => doc.map_range(obj, ..).map(|(_, v, id)| (v, id)).collect(),
+        ObjType::List => doc.list_range(obj, ..).map(|(_, v, id)| (v, id)).collect(),
+        // text can't hold links
+        ObjType::Text => return,
+    };
+    for (value, id) in values {
+        match value {
+            Value::Object(child_type) => collect_links(doc, &id, child_type, links),
+            Value::Scalar(s) => links.extend(DocId::from_link(&s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::Transactable;
+
+    fn id(s: &str) -> DocId {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn links_resolve_and_sync() {
+        assert!("".parse::<DocId>().is_err());
+        assert!("a b".parse::<DocId>().is_err());
+        assert_eq!(DocId::from_link(&id("x").link()), Some(id("x")));
+        assert_eq!(DocId::from_link(&ScalarValue::from("x")), None);
+
+        let mut repo = Repo::new();
+        let mut tx = repo.get_or_create(&id("child")).transaction();
+        tx.put(ROOT, "name", "child").unwrap();
+        tx.commit();
+        let mut tx = repo.get_or_create(&id("parent")).transaction();
+        tx.put(ROOT, "child", id("child").link()).unwrap();
+        let list = tx.put_object(ROOT, "others", ObjType::List).unwrap();
+        tx.insert(&list, 0, id("missing").link()).unwrap();
+        tx.insert(&list, 1, id("child").link()).unwrap();
+        tx.commit();
+
+        let (target, child) = repo.resolve(&id("parent"), ROOT, "child").unwrap().unwrap();
+        assert_eq!(target, id("child"));
+        assert_eq!(
+            child.get(ROOT, "name").unwrap().unwrap().0,
+            Value::str("child")
+        );
+        assert!(repo.resolve(&id("parent"), &list, 0).unwrap().is_none());
+        assert_eq!(repo.links(&id("parent")), vec![id("child"), id("missing")]);
+
+        let mut other = Repo::new();
+        for _ in 0..10 {
+            let to_other = repo.generate_sync_messages("other");
+            let to_repo = other.generate_sync_messages("repo");
+            if to_other.is_empty() && to_repo.is_empty() {
+                break;
+            }
+            for (doc, msg) in to_other {
+                other.receive_sync_message("repo", &doc, msg).unwrap();
+            }
+            for (doc, msg) in to_repo {
+                repo.receive_sync_message("other", &doc, msg).unwrap();
+            }
+        }
+        assert_eq!(
+            other.ids().collect::<Vec<_>>(),
+            vec![&id("child"), &id("parent")]
+        );
+        assert_eq!(
+            other.get(&id("parent")).unwrap().get_heads(),
+            repo.get(&id("parent")).unwrap().get_heads()
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use crate::exid::ExId;
+use crate::transaction::Transactable;
+use crate::{AutomergeError, ObjType, ScalarValue, Value, ROOT};
+
+/// The key of the map under which each of automerge's registries records which registry it is.
+///
+/// Some features, such as [`crate::ActorMetadata`] and tags, keep their data in a map under a
+/// reserved key of the root map. A map is only taken to be a registry if its `_registry` key is
+/// the reserved key it is under, so a user's own value at a reserved key is never read as a
+/// registry or overwritten by one: writing to a registry whose key holds something else returns
+/// [`AutomergeError::ReservedKey`].
+pub const REGISTRY_MARKER_KEY: &str = "_registry";
+
+/// Whether the value `(value, id)` at the reserved root key `key` is the registry for it, where
+/// `get_all` is the `get_all` method of a document.
+fn is_registry<'a, F>(key: &str, value: &Value<'_>, id: &ExId, get_all: &F) -> bool
+where
+    F: Fn(&ExId, &str) -> Result<Vec<(Value<'a>, ExId)>, AutomergeError>,
+{
+    matches!(value, Value::Object(ObjType::Map))
+        && get_all(id, REGISTRY_MARKER_KEY)
+            .ok()
+            .and_then(|mut marker| marker.pop())
+            .map_or(false, |(marker, _)| marker.to_str() == Some(key))
+}
+
+/// The registries under the reserved root key `key`, winner first, where `get_all` is the
+/// `get_all` method of a document. If two peers created the registry concurrently there are
+/// several.
+pub(crate) fn registries<'a, F>(key: &str, get_all: F) -> Result<Vec<ExId>, AutomergeError>
+where
+    F: Fn(&ExId, &str) -> Result<Vec<(Value<'a>, ExId)>, AutomergeError>,
+{
+    Ok(get_all(&ROOT, key)?
+        .into_iter()
+        .rev()
+        .filter(|(value, id)| is_registry(key, value, id, &get_all))
+        .map(|(_, id)| id)
+        .collect())
+}
+
+/// The registry under the reserved root key `key` to write to, which is created if there is no
+/// value at `key`.
+///
+/// # Errors
+///
+/// Returns [`AutomergeError::ReservedKey`] if the value at `key` is not a registry, rather than
+/// replacing it.
+pub(crate) fn registry_for_write<T: Transactable>(
+    tx: &mut T,
+    key: &str,
+) -> Result<ExId, AutomergeError> {
+    match tx.get(ROOT, key)? {
+        None => {
+            let registry = tx.put_object(ROOT, key, ObjType::Map)?;
+            tx.put(&registry, REGISTRY_MARKER_KEY, ScalarValue::from(key))?;
+            Ok(registry)
+        }
+        Some((value, id)) if is_registry(key, &value, &id, &|o, k| tx.get_all(o, k)) => Ok(id),
+        Some(_) => Err(AutomergeError::ReservedKey(key.to_string())),
+    }
+}
+
+/// The winning registry under the reserved root key `key`, if there is one, for removing entries.
+pub(crate) fn registry_for_delete<T: Transactable>(
+    tx: &T,
+    key: &str,
+) -> Result<Option<ExId>, AutomergeError> {
+    Ok(registries(key, |o, k| tx.get_all(o, k))?.into_iter().next())
+}
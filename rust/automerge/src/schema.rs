@@ -0,0 +1,246 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::types::{ObjId, OpType};
+use crate::{Automerge, ObjType, Prop, ScalarValue, Value};
+
+type Validator = Arc<dyn Fn(&[Prop], &Value<'_>) -> Result<(), String> + Send + Sync>;
+
+/// The type of a value, as expected by a [`Schema`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SchemaType {
+    Map,
+    Table,
+    List,
+    Text,
+    Str,
+    Int,
+    Uint,
+    F64,
+    Counter,
+    Timestamp,
+    Boolean,
+    Bytes,
+    Null,
+}
+
+impl SchemaType {
+    pub(crate) fn of(value: &Value<'_>) -> Option<Self> {
+        Some(match value {
+            Value::Object(ObjType::Map) => SchemaType::Map,
+            Value::Object(ObjType::Table) => SchemaType::Table,
+            Value::Object(ObjType::List) => SchemaType::List,
+            Value::Object(ObjType::Text) => SchemaType::Text,
+            Value::Scalar(s) => match s.as_ref() {
+                ScalarValue::Str(_) => SchemaType::Str,
+                ScalarValue::Int(_) => SchemaType::Int,
+                ScalarValue::Uint(_) => SchemaType::Uint,
+                ScalarValue::F64(_) => SchemaType::F64,
+                ScalarValue::Counter(_) => SchemaType::Counter,
+                ScalarValue::Timestamp(_) => SchemaType::Timestamp,
+                ScalarValue::Boolean(_) => SchemaType::Boolean,
+                ScalarValue::Bytes(_) => SchemaType::Bytes,
+                ScalarValue::Null => SchemaType::Null,
+                ScalarValue::Unknown { .. } => return None,
+            },
+        })
+    }
+}
+
+/// One step of the path of a [`Schema`] rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathPattern {
+    /// The map key with this name
+    Key(String),
+    /// Any map key or list index
+    Any,
+}
+
+impl From<&str> for PathPattern {
+    fn from(s: &str) -> Self {
+        if s == "*" {
+            PathPattern::Any
+        } else {
+            PathPattern::Key(s.to_string())
+        }
+    }
+}
+
+impl PathPattern {
+    fn matches(&self, prop: &Prop) -> bool {
+        match (self, prop) {
+            (PathPattern::Any, _) => true,
+            (PathPattern::Key(k), Prop::Map(p)) => k == p,
+            (PathPattern::Key(_), Prop::Seq(_)) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    path: Vec<PathPattern>,
+    types: Vec<SchemaType>,
+}
+
+/// The types of the values a transaction may put into a document, checked as each value is put.
+/// See [`Automerge::with_schema`].
+///
+/// A schema is a list of rules, each giving the types allowed at the paths which match a
+/// pattern, and of validators, closures which are passed the path and value of every put. Values
+/// at paths which no rule matches may have any type.
+///
+/// ```
+/// # use automerge::{Schema, SchemaType};
+/// let schema = Schema::new()
+///     .field("title", &[SchemaType::Str, SchemaType::Text])
+///     .field("todos", &[SchemaType::List])
+///     .field("todos/*/done", &[SchemaType::Boolean])
+///     .validator(|path, value| match value.to_i64() {
+///         Some(n) if n < 0 => Err(format!("{:?} must not be negative", path)),
+///         _ => Ok(()),
+///     });
+/// ```
+#[derive(Clone, Default)]
+pub struct Schema {
+    rules: Vec<Rule>,
+    validators: Vec<Validator>,
+}
+
+impl fmt::Debug for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Schema")
+            .field("rules", &self.rules)
+            .field("validators", &self.validators.len())
+            .finish()
+    }
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow only `types` at the paths matching `path`, which is a list of map keys separated by
+    /// `/` where `*` matches any key or list index.
+    pub fn field(self, path: &str, types: &[SchemaType]) -> Self {
+        self.field_at(path.split('/').map(PathPattern::from).collect(), types)
+    }
+
+    /// Like [`Self::field`] with the path as a list of patterns, for keys which contain `/` or
+    /// are `*`.
+    pub fn field_at(mut self, path: Vec<PathPattern>, types: &[SchemaType]) -> Self {
+        self.rules.push(Rule {
+            path,
+            types: types.to_vec(),
+        });
+        self
+    }
+
+    /// Check every value put with `validator`, which is passed the path from the root to the
+    /// value and the value, and returns an explanation of what is wrong with it if it is invalid.
+    pub fn validator<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&[Prop], &Value<'_>) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.validators.push(Arc::new(validator));
+        self
+    }
+
+    /// Check that `value` may be put at `path`.
+    pub fn check(&self, path: &[Prop], value: &Value<'_>) -> Result<(), SchemaViolation> {
+        let found = SchemaType::of(value);
+        for rule in &self.rules {
+            let matches = rule.path.len() == path.len()
+                && rule.path.iter().zip(path).all(|(p, prop)| p.matches(prop));
+            if matches && !found.map_or(false, |t| rule.types.contains(&t)) {
+                return Err(SchemaViolation {
+                    path: path.to_vec(),
+                    reason: format!(
+                        "expected one of {:?} but found {}",
+                        rule.types,
+                        found.map_or_else(
+                            || "a value of unknown type".to_string(),
+                            |t| format!("{:?}", t)
+                        )
+                    ),
+                });
+            }
+        }
+        for validator in &self.validators {
+            validator(path, value).map_err(|reason| SchemaViolation {
+                path: path.to_vec(),
+                reason,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// A value which a transaction put into a document but its [`Schema`] doesn't allow.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error("invalid value at {}: {reason}", display_path(path))]
+pub struct SchemaViolation {
+    /// The path from the root to the value
+    pub path: Vec<Prop>,
+    /// What is wrong with the value
+    pub reason: String,
+}
+
+fn display_path(path: &[Prop]) -> String {
+    let steps = path.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    format!("/{}", steps.join("/"))
+}
+
+impl Automerge {
+    /// Check the values put by transactions on this document against `schema`, see [`Schema`].
+    ///
+    /// Each value is checked as it is put or inserted, and an operation which puts a value the
+    /// schema doesn't allow fails with [`crate::AutomergeError::SchemaViolation`] without changing
+    /// the document, so no commit, whether with `commit`, `try_commit`, `transact` or the
+    /// implicit commits of [`crate::AutoCommit`], can contain one. Only local changes are
+    /// checked, changes applied from other peers are not, and neither is anything already in the
+    /// document.
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Change the schema of this document, or remove it, see [`Self::with_schema`].
+    pub fn set_schema(&mut self, schema: Option<Schema>) -> &mut Self {
+        self.schema = schema;
+        self
+    }
+
+    /// The schema of this document, see [`Self::with_schema`].
+    pub fn schema(&self) -> Option<&Schema> {
+        self.schema.as_ref()
+    }
+
+    /// Check a new op with `action` at `prop` of `obj` against the schema, if there is one.
+    pub(crate) fn check_schema(
+        &self,
+        obj: &ObjId,
+        prop: &Prop,
+        action: &OpType,
+    ) -> Result<(), SchemaViolation> {
+        let schema = match &self.schema {
+            Some(schema) => schema,
+            None => return Ok(()),
+        };
+        let value = match action {
+            OpType::Put(value) => Value::Scalar(Cow::Borrowed(value)),
+            OpType::Make(obj_type) => Value::Object(*obj_type),
+            _ => return Ok(()),
+        };
+        let mut path = self
+            .ops
+            .parents(*obj)
+            .path()
+            .into_iter()
+            .map(|(_, p)| p)
+            .collect::<Vec<_>>();
+        path.push(prop.clone());
+        schema.check(&path, &value)
+    }
+}
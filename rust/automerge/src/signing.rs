@@ -0,0 +1,61 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::{ActorId, AutomergeError, Change};
+
+type SignFn = dyn Fn(&[u8]) -> Vec<u8> + Send + Sync;
+type VerifyFn = dyn Fn(&ActorId, &[u8], &[u8]) -> bool + Send + Sync;
+
+/// Produces a signature over the bytes of a change at commit time.
+#[derive(Clone)]
+pub(crate) struct Signer(Arc<SignFn>);
+
+impl Signer {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static,
+    {
+        Self(Arc::new(f))
+    }
+
+    pub(crate) fn sign(&self, bytes: &[u8]) -> Vec<u8> {
+        (self.0)(bytes)
+    }
+}
+
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Signer")
+    }
+}
+
+/// Checks the signature of changes received from elsewhere.
+#[derive(Clone)]
+pub(crate) struct Verifier(Arc<VerifyFn>);
+
+impl Verifier {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&ActorId, &[u8], &[u8]) -> bool + Send + Sync + 'static,
+    {
+        Self(Arc::new(f))
+    }
+
+    pub(crate) fn verify(&self, change: &Change) -> Result<(), AutomergeError> {
+        if (self.0)(
+            change.actor_id(),
+            change.signed_bytes(),
+            change.signature().unwrap_or_default(),
+        ) {
+            Ok(())
+        } else {
+            Err(AutomergeError::InvalidSignature(change.hash()))
+        }
+    }
+}
+
+impl fmt::Debug for Verifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Verifier")
+    }
+}
@@ -0,0 +1,107 @@
+use std::ops::RangeBounds;
+
+use crate::exid::ExId;
+use crate::{
+    Automerge, AutomergeError, ChangeHash, Keys, ListRange, MapRange, ObjType, Parents, Prop,
+    Value, Values,
+};
+
+/// A read-only copy of the current state of a document, returned by [`Automerge::snapshot`].
+///
+/// Taking a snapshot is cheap: the nodes of the op trees of each object are shared with the
+/// document, and the document copies a node the first time it changes it, so a change only
+/// copies the nodes on the path to the ops it changes. A snapshot is `Send` and `Sync`, so one
+/// taken before or during a transaction, see [`crate::transaction::Transaction::snapshot`], can
+/// be handed to another thread, e.g. to render the document while the transaction is in
+/// progress, and it is not affected by the transaction or anything else that happens to the
+/// document afterwards.
+///
+/// Snapshots have no history, so they can only be read as of [`Self::heads`]. Conflicts are
+/// resolved with the document's conflict policies, except that
+/// [`crate::ConflictPolicy::LastWriterWins`] can't see when changes were made and falls back
+/// to the default winner.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    doc: Automerge,
+    heads: Vec<ChangeHash>,
+}
+
+impl Snapshot {
+    pub(crate) fn new(doc: &Automerge) -> Self {
+        let mut copy = Automerge::new();
+        copy.ops = doc.ops.clone();
+        copy.conflict_policies = doc.conflict_policies.clone();
+        Self {
+            doc: copy,
+            heads: doc.get_heads(),
+        }
+    }
+
+    /// The heads of the document when the snapshot was taken.
+    pub fn heads(&self) -> &[ChangeHash] {
+        &self.heads
+    }
+
+    pub fn get<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        self.doc.get(obj, prop)
+    }
+
+    pub fn get_all<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Vec<(Value<'_>, ExId)>, AutomergeError> {
+        self.doc.get_all(obj, prop)
+    }
+
+    pub fn keys<O: AsRef<ExId>>(&self, obj: O) -> Keys<'_, '_> {
+        self.doc.keys(obj)
+    }
+
+    pub fn map_range<O: AsRef<ExId>, R: RangeBounds<String>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> MapRange<'_, R> {
+        self.doc.map_range(obj, range)
+    }
+
+    pub fn list_range<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> ListRange<'_, R> {
+        self.doc.list_range(obj, range)
+    }
+
+    pub fn values<O: AsRef<ExId>>(&self, obj: O) -> Values<'_> {
+        self.doc.values(obj)
+    }
+
+    pub fn length<O: AsRef<ExId>>(&self, obj: O) -> usize {
+        self.doc.length(obj)
+    }
+
+    pub fn object_type<O: AsRef<ExId>>(&self, obj: O) -> Option<ObjType> {
+        self.doc.object_type(obj)
+    }
+
+    pub fn text<O: AsRef<ExId>>(&self, obj: O) -> Result<String, AutomergeError> {
+        self.doc.text(obj)
+    }
+
+    pub fn parents<O: AsRef<ExId>>(&self, obj: O) -> Result<Parents<'_>, AutomergeError> {
+        self.doc.parents(obj)
+    }
+
+    pub fn path_to_object<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+    ) -> Result<Vec<(ExId, Prop)>, AutomergeError> {
+        self.doc.path_to_object(obj)
+    }
+}
@@ -0,0 +1,54 @@
+use std::cmp::Ordering;
+
+use crate::exid::ExId;
+use crate::{Automerge, AutomergeError, Value};
+
+/// Binary search the `len` elements of a list, read with `get`, for one which `f` says is
+/// [`Ordering::Equal`], in the manner of [`slice::binary_search_by`].
+///
+/// Reading an element by index only descends the op tree, so this takes O(log² n) rather than
+/// scanning the list.
+pub(crate) fn binary_search_by<'a, G, F>(
+    len: usize,
+    mut get: G,
+    mut f: F,
+) -> Result<Result<usize, usize>, AutomergeError>
+where
+    G: FnMut(usize) -> Result<Option<(Value<'a>, ExId)>, AutomergeError>,
+    F: FnMut(&Value<'a>) -> Ordering,
+{
+    let mut low = 0;
+    let mut high = len;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let (value, _) = get(mid)?.ok_or(AutomergeError::InvalidIndex(mid))?;
+        match f(&value) {
+            Ordering::Less => low = mid + 1,
+            Ordering::Greater => high = mid,
+            Ordering::Equal => return Ok(Ok(mid)),
+        }
+    }
+    Ok(Err(low))
+}
+
+impl Automerge {
+    /// Binary search the list `obj`, which is assumed to be sorted, with `f`, which returns the
+    /// ordering of an element relative to the target.
+    ///
+    /// As with [`slice::binary_search_by`], if an element matches its index is returned in `Ok`,
+    /// otherwise the index at which a matching element could be inserted to keep the list sorted
+    /// is returned in `Err`. This reads O(log n) elements, each of which is found with the op
+    /// tree's index, so it is much faster than scanning long lists.
+    pub fn binary_search_by<O, F>(
+        &self,
+        obj: O,
+        f: F,
+    ) -> Result<Result<usize, usize>, AutomergeError>
+    where
+        O: AsRef<ExId>,
+        F: FnMut(&Value<'_>) -> Ordering,
+    {
+        let obj = obj.as_ref();
+        binary_search_by(self.length(obj), |index| self.get(obj, index), f)
+    }
+}
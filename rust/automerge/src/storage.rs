@@ -3,11 +3,16 @@ use std::ops::Range;
 pub(crate) mod change;
 mod chunk;
 mod columns;
+pub(crate) mod config;
 pub(crate) mod convert;
 mod document;
+pub(crate) mod encoded;
+pub(crate) mod fence;
+pub(crate) mod headers;
 pub(crate) mod load;
 pub(crate) mod parse;
 pub(crate) mod save;
+pub(crate) mod verify;
 
 pub(crate) use {
     change::{AsChangeOp, Change, ChangeOp, Compressed, ReadChangeOpError},
@@ -1,5 +1,6 @@
 use std::{
     borrow::Cow,
+    collections::BTreeMap,
     convert::{TryFrom, TryInto},
     io::Read,
     ops::Range,
@@ -7,18 +8,24 @@ use std::{
 
 use sha2::{Digest, Sha256};
 
-use super::{change::Unverified, parse, Change, Compressed, Document, MAGIC_BYTES};
-use crate::{columnar::encoding::leb128::ulebsize, ChangeHash};
+use super::{
+    change::Unverified, config, encoded, fence, headers, parse, Change, Compressed, Document,
+    MAGIC_BYTES,
+};
+use crate::{columnar::encoding::leb128::ulebsize, ChangeHash, DocumentConfig};
 
 pub(crate) enum Chunk<'a> {
     Document(Document<'a>),
     Change(Change<'a, Unverified>),
     CompressedChange(Change<'static, Unverified>, Compressed<'a>),
+    Config(Header, DocumentConfig),
+    Fence(Header, fence::Fence),
+    Headers(Header, BTreeMap<String, Vec<u8>>),
 }
 
 pub(crate) mod error {
     use super::parse;
-    use crate::storage::{change, document};
+    use crate::storage::{change, config, document, encoded, fence, headers};
 
     #[derive(thiserror::Error, Debug)]
     pub(crate) enum Chunk {
@@ -32,6 +39,16 @@ pub(crate) mod error {
         Change(#[from] change::ParseError),
         #[error("bad document chunk: {0}")]
         Document(#[from] document::ParseError),
+        #[error("bad config chunk: {0}")]
+        Config(#[from] config::ParseError),
+        #[error("bad fence chunk: {0}")]
+        Fence(#[from] fence::ParseError),
+        #[error("bad headers chunk: {0}")]
+        Headers(#[from] headers::ParseError),
+        #[error("bad encoded chunk: {0}")]
+        Encoded(#[from] encoded::ParseError),
+        #[error("the chunk is encoded with codec {0}, load it with that codec")]
+        NeedsCodec(u64),
         #[error("unable to decompresse compressed chunk")]
         Deflate,
     }
@@ -74,6 +91,31 @@ impl<'a> Chunk<'a> {
                 }
                 Chunk::Document(doc)
             }
+            ChunkType::Config => {
+                let (remaining, config) = config::parse(chunk_input).map_err(|e| e.lift())?;
+                if !remaining.is_empty() {
+                    return Err(parse::ParseError::Error(error::Chunk::LeftoverData));
+                }
+                Chunk::Config(header, config)
+            }
+            ChunkType::Fence => {
+                let (remaining, fence) = fence::parse(chunk_input).map_err(|e| e.lift())?;
+                if !remaining.is_empty() {
+                    return Err(parse::ParseError::Error(error::Chunk::LeftoverData));
+                }
+                Chunk::Fence(header, fence)
+            }
+            ChunkType::Headers => {
+                let (remaining, headers) = headers::parse(chunk_input).map_err(|e| e.lift())?;
+                if !remaining.is_empty() {
+                    return Err(parse::ParseError::Error(error::Chunk::LeftoverData));
+                }
+                Chunk::Headers(header, headers)
+            }
+            ChunkType::Encoded => {
+                let (_, codec_id) = encoded::parse_codec_id(chunk_input).map_err(|e| e.lift())?;
+                return Err(parse::ParseError::Error(error::Chunk::NeedsCodec(codec_id)));
+            }
             ChunkType::Compressed => {
                 let compressed = &input.unconsumed_bytes()[header.data_bytes()];
                 let mut decoder = flate2::bufread::DeflateDecoder::new(compressed);
@@ -106,6 +148,9 @@ impl<'a> Chunk<'a> {
             Self::CompressedChange(change, compressed) => {
                 compressed.checksum() == change.checksum() && change.checksum_valid()
             }
+            Self::Config(header, _) | Self::Fence(header, _) | Self::Headers(header, _) => {
+                header.checksum_valid()
+            }
         }
     }
 }
@@ -115,6 +160,10 @@ pub(crate) enum ChunkType {
     Document,
     Change,
     Compressed,
+    Config,
+    Fence,
+    Encoded,
+    Headers,
 }
 
 impl TryFrom<u8> for ChunkType {
@@ -125,6 +174,10 @@ impl TryFrom<u8> for ChunkType {
             0 => Ok(Self::Document),
             1 => Ok(Self::Change),
             2 => Ok(Self::Compressed),
+            3 => Ok(Self::Config),
+            4 => Ok(Self::Fence),
+            5 => Ok(Self::Encoded),
+            6 => Ok(Self::Headers),
             other => Err(other),
         }
     }
@@ -136,6 +189,10 @@ impl From<ChunkType> for u8 {
             ChunkType::Document => 0,
             ChunkType::Change => 1,
             ChunkType::Compressed => 2,
+            ChunkType::Config => 3,
+            ChunkType::Fence => 4,
+            ChunkType::Encoded => 5,
+            ChunkType::Headers => 6,
         }
     }
 }
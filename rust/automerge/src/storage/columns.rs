@@ -74,6 +74,10 @@ impl Columns {
         self.columns.push(col)
     }
 
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Column> + '_ {
+        self.columns.iter()
+    }
+
     pub(crate) fn parse<'a, I: Iterator<Item = &'a RawColumn<compression::Uncompressed>>>(
         data_size: usize,
         cols: I,
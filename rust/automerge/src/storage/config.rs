@@ -0,0 +1,46 @@
+use super::{parse, ChunkType, Header};
+use crate::DocumentConfig;
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum ParseError {
+    #[error(transparent)]
+    Leb128(#[from] parse::leb128::Error),
+    #[error(transparent)]
+    Utf8(#[from] parse::InvalidUtf8),
+}
+
+/// Parse the data of a config chunk, which is a LEB128 count of settings followed by each key and
+/// value as a length prefixed UTF-8 string.
+pub(crate) fn parse(input: parse::Input<'_>) -> parse::ParseResult<'_, DocumentConfig, ParseError> {
+    let (i, settings) = parse::length_prefixed(|i| {
+        let (i, key) = string(i)?;
+        let (i, value) = string(i)?;
+        Ok((i, (key, value)))
+    })(input)?;
+    let config = settings
+        .into_iter()
+        .fold(DocumentConfig::new(), |config, (k, v)| config.with(k, v));
+    Ok((i, config))
+}
+
+fn string(input: parse::Input<'_>) -> parse::ParseResult<'_, String, ParseError> {
+    let (i, len) = parse::leb128_u64::<ParseError>(input)?;
+    parse::utf_8(len as usize, i)
+}
+
+/// Encode `config` as a complete chunk, including the header.
+pub(crate) fn write(config: &DocumentConfig) -> Vec<u8> {
+    let mut data = Vec::new();
+    leb128::write::unsigned(&mut data, config.len() as u64).unwrap();
+    for (key, value) in config.iter() {
+        for s in [key, value] {
+            leb128::write::unsigned(&mut data, s.len() as u64).unwrap();
+            data.extend(s.as_bytes());
+        }
+    }
+    let header = Header::new(ChunkType::Config, &data);
+    let mut out = Vec::with_capacity(header.len() + data.len());
+    header.write(&mut out);
+    out.extend(data);
+    out
+}
@@ -320,6 +320,21 @@ impl<'a> Document<'a> {
             .iter(&self.bytes[self.change_bytes.clone()])
     }
 
+    pub(crate) fn into_owned(self) -> Document<'static> {
+        Document {
+            bytes: Cow::Owned(self.bytes.into_owned()),
+            compressed_bytes: self.compressed_bytes.map(|c| Cow::Owned(c.into_owned())),
+            header: self.header,
+            actors: self.actors,
+            heads: self.heads,
+            op_metadata: self.op_metadata,
+            op_bytes: self.op_bytes,
+            change_metadata: self.change_metadata,
+            change_bytes: self.change_bytes,
+            head_indices: self.head_indices,
+        }
+    }
+
     pub(crate) fn into_bytes(self) -> Vec<u8> {
         if let Some(compressed) = self.compressed_bytes {
             compressed.into_owned()
@@ -339,4 +354,20 @@ impl<'a> Document<'a> {
     pub(crate) fn heads(&self) -> &[ChangeHash] {
         &self.heads
     }
+
+    /// The index of the change for each head, empty if the document was written by an
+    /// implementation which did not record them.
+    pub(crate) fn head_indices(&self) -> &[u64] {
+        &self.head_indices
+    }
+
+    /// The specifications of the op and change columns which we don't recognise.
+    pub(crate) fn unknown_columns(&self) -> Vec<u32> {
+        self.op_metadata
+            .unknown_columns()
+            .iter()
+            .chain(self.change_metadata.unknown_columns().iter())
+            .map(|c| u32::from(c.spec()))
+            .collect()
+    }
 }
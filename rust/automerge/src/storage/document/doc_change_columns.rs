@@ -61,11 +61,15 @@ pub(crate) struct DocChangeColumns {
     message: RleRange<smol_str::SmolStr>,
     deps: DepsRange,
     extra: ValueRange,
-    #[allow(dead_code)]
     other: Columns,
 }
 
 impl DocChangeColumns {
+    /// Columns with a specification we don't recognise, which are ignored
+    pub(crate) fn unknown_columns(&self) -> &Columns {
+        &self.other
+    }
+
     pub(crate) fn iter<'a>(&self, data: &'a [u8]) -> DocChangeColumnIter<'a> {
         DocChangeColumnIter {
             actors: self.actor.decoder(data),
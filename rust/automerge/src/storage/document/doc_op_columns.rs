@@ -50,7 +50,6 @@ pub(crate) struct DocOpColumns {
     action: RleRange<u64>,
     val: ValueRange,
     succ: OpIdListRange,
-    #[allow(dead_code)]
     other: Columns,
 }
 
@@ -181,6 +180,11 @@ impl DocOpColumns {
         }
     }
 
+    /// Columns with a specification we don't recognise, which are ignored
+    pub(crate) fn unknown_columns(&self) -> &Columns {
+        &self.other
+    }
+
     pub(crate) fn iter<'a>(&self, data: &'a [u8]) -> DocOpColumnIter<'a> {
         DocOpColumnIter {
             id: self.id.iter(data),
@@ -0,0 +1,58 @@
+use super::{load, parse, ChunkType, Header};
+use crate::ChunkCodec;
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum ParseError {
+    #[error(transparent)]
+    Leb128(#[from] parse::leb128::Error),
+}
+
+/// Parse the id of the codec at the start of the data of an encoded chunk, which is followed by
+/// the output of the codec.
+pub(crate) fn parse_codec_id(input: parse::Input<'_>) -> parse::ParseResult<'_, u64, ParseError> {
+    parse::leb128_u64(input)
+}
+
+/// Encode each chunk of `data` with `codec`.
+pub(crate) fn encode(data: &[u8], codec: &dyn ChunkCodec) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for chunk in load::split_chunks(data) {
+        let mut encoded = Vec::new();
+        leb128::write::unsigned(&mut encoded, codec.id()).unwrap();
+        encoded.extend(codec.encode(chunk));
+        let header = Header::new(ChunkType::Encoded, &encoded);
+        header.write(&mut out);
+        out.extend(encoded);
+    }
+    out
+}
+
+/// Decode each encoded chunk of `data` with `codec`, leaving any other chunks as they are.
+pub(crate) fn decode(data: &[u8], codec: &dyn ChunkCodec) -> Result<Vec<u8>, load::Error> {
+    let mut out = Vec::with_capacity(data.len());
+    for chunk in load::split_chunks(data) {
+        if !load::is_encoded_chunk(chunk) {
+            out.extend(chunk);
+            continue;
+        }
+        let (_, header) = Header::parse::<super::chunk::error::Header>(parse::Input::new(chunk))
+            .map_err(|e| load::Error::Parse(Box::new(e)))?;
+        if !header.checksum_valid() {
+            return Err(load::Error::BadChecksum);
+        }
+        let data = parse::Input::new(&chunk[header.data_bytes()]);
+        let (payload, found) = parse_codec_id(data).map_err(|e| load::Error::Parse(Box::new(e)))?;
+        if found != codec.id() {
+            return Err(load::Error::WrongCodec {
+                expected: codec.id(),
+                found,
+            });
+        }
+        out.extend(
+            codec
+                .decode(payload.unconsumed_bytes())
+                .map_err(load::Error::Codec)?,
+        );
+    }
+    Ok(out)
+}
@@ -0,0 +1,62 @@
+use super::{parse, ChunkType, Header};
+use crate::{ActorId, ChangeHash};
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum ParseError {
+    #[error(transparent)]
+    Leb128(#[from] parse::leb128::Error),
+}
+
+/// The contents of a fence chunk, which precedes the document chunk of a document saved without
+/// the changes beneath a history fence, see [`crate::Automerge::set_history_fence`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Fence {
+    pub(crate) heads: Vec<FenceHead>,
+}
+
+/// One of the heads of a fence, along with its vector clock, which can't be recomputed without
+/// the changes beneath it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FenceHead {
+    pub(crate) hash: ChangeHash,
+    /// The actor, seq and max op of the last change of each actor beneath the head
+    pub(crate) clock: Vec<(ActorId, u64, u64)>,
+}
+
+/// Parse the data of a fence chunk, which is a LEB128 count of heads followed by each head's hash
+/// and its clock, as a LEB128 count of entries followed by each length prefixed actor ID, seq and
+/// max op.
+pub(crate) fn parse(input: parse::Input<'_>) -> parse::ParseResult<'_, Fence, ParseError> {
+    let (i, heads) = parse::length_prefixed(|i| {
+        let (i, hash) = parse::change_hash(i)?;
+        let (i, clock) = parse::length_prefixed(|i| {
+            let (i, actor) = parse::actor_id(i)?;
+            let (i, seq) = parse::leb128_u64::<ParseError>(i)?;
+            let (i, max_op) = parse::leb128_u64::<ParseError>(i)?;
+            Ok((i, (actor, seq, max_op)))
+        })(i)?;
+        Ok((i, FenceHead { hash, clock }))
+    })(input)?;
+    Ok((i, Fence { heads }))
+}
+
+/// Encode `fence` as a complete chunk, including the header.
+pub(crate) fn write(fence: &Fence) -> Vec<u8> {
+    let mut data = Vec::new();
+    leb128::write::unsigned(&mut data, fence.heads.len() as u64).unwrap();
+    for head in &fence.heads {
+        data.extend(head.hash.as_bytes());
+        leb128::write::unsigned(&mut data, head.clock.len() as u64).unwrap();
+        for (actor, seq, max_op) in &head.clock {
+            leb128::write::unsigned(&mut data, actor.to_bytes().len() as u64).unwrap();
+            data.extend(actor.to_bytes());
+            leb128::write::unsigned(&mut data, *seq).unwrap();
+            leb128::write::unsigned(&mut data, *max_op).unwrap();
+        }
+    }
+    let header = Header::new(ChunkType::Fence, &data);
+    let mut out = Vec::with_capacity(header.len() + data.len());
+    header.write(&mut out);
+    out.extend(data);
+    out
+}
@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+
+use super::{parse, ChunkType, Header};
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum ParseError {
+    #[error(transparent)]
+    Leb128(#[from] parse::leb128::Error),
+    #[error(transparent)]
+    Utf8(#[from] parse::InvalidUtf8),
+}
+
+/// Parse the data of a headers chunk, which is a LEB128 count of headers followed by each key as
+/// a length prefixed UTF-8 string and each value as length prefixed bytes.
+pub(crate) fn parse(
+    input: parse::Input<'_>,
+) -> parse::ParseResult<'_, BTreeMap<String, Vec<u8>>, ParseError> {
+    let (i, headers) = parse::length_prefixed(|i| {
+        let (i, len) = parse::leb128_u64::<ParseError>(i)?;
+        let (i, key) = parse::utf_8(len as usize, i)?;
+        let (i, value) = parse::length_prefixed_bytes(i)?;
+        Ok((i, (key, value.to_vec())))
+    })(input)?;
+    Ok((i, headers.into_iter().collect()))
+}
+
+/// Encode `headers` as a complete chunk, including the header.
+pub(crate) fn write(headers: &BTreeMap<String, Vec<u8>>) -> Vec<u8> {
+    let mut data = Vec::new();
+    leb128::write::unsigned(&mut data, headers.len() as u64).unwrap();
+    for (key, value) in headers {
+        for bytes in [key.as_bytes(), value.as_slice()] {
+            leb128::write::unsigned(&mut data, bytes.len() as u64).unwrap();
+            data.extend(bytes);
+        }
+    }
+    let header = Header::new(ChunkType::Headers, &data);
+    let mut out = Vec::with_capacity(header.len() + data.len());
+    header.write(&mut out);
+    out.extend(data);
+    out
+}
@@ -1,14 +1,17 @@
+use std::collections::BTreeMap;
+
 use tracing::instrument;
 
 use crate::{
     change::Change,
     storage::{self, parse},
+    DocumentConfig,
 };
 
 mod change_collector;
 mod reconstruct_document;
 pub(crate) use reconstruct_document::{
-    reconstruct_document, DocObserver, LoadedObject, Reconstructed,
+    parse_optype, reconstruct_base, reconstruct_document, DocObserver, LoadedObject, Reconstructed,
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -26,6 +29,16 @@ pub enum Error {
     InflateDocument(Box<dyn std::error::Error + Send + Sync + 'static>),
     #[error("bad checksum")]
     BadChecksum,
+    #[error("the data contains more than one document configuration")]
+    ConflictingConfig,
+    #[error("lazy loading needs a single document chunk")]
+    NotLazyLoadable,
+    #[error("a document saved with a history fence can only be loaded as a whole")]
+    Fenced,
+    #[error("the data was encoded with codec {found} but is being decoded with codec {expected}")]
+    WrongCodec { expected: u64, found: u64 },
+    #[error("error decoding chunk: {0}")]
+    Codec(Box<dyn std::error::Error + Send + Sync + 'static>),
 }
 
 pub(crate) enum LoadedChanges<'a> {
@@ -70,6 +83,112 @@ pub(crate) fn load_changes<'a>(mut data: parse::Input<'a>) -> LoadedChanges<'a>
     LoadedChanges::Complete(changes)
 }
 
+/// The document configuration stored in `data`, if there is one.
+///
+/// Configuration chunks are skipped by [`load_changes`], this finds and parses them without
+/// parsing any of the other chunks.
+pub(crate) fn config(data: &[u8]) -> Result<Option<DocumentConfig>, Error> {
+    let mut result = None;
+    for chunk in split_chunks(data)
+        .into_iter()
+        .filter(|c| is_config_chunk(c))
+    {
+        let (_, chunk) = storage::Chunk::parse(parse::Input::new(chunk))
+            .map_err(|e| Error::Parse(Box::new(e)))?;
+        if !chunk.checksum_valid() {
+            return Err(Error::BadChecksum);
+        }
+        if let storage::Chunk::Config(_, config) = chunk {
+            match &result {
+                Some(existing) if existing != &config => return Err(Error::ConflictingConfig),
+                _ => result = Some(config),
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// The document headers stored in `data`, see [`crate::Automerge::set_header`]. Where more than
+/// one chunk sets a header the last one wins.
+pub(crate) fn headers(data: &[u8]) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+    let mut result = BTreeMap::new();
+    for chunk in split_chunks(data)
+        .into_iter()
+        .filter(|c| is_headers_chunk(c))
+    {
+        let (_, chunk) = storage::Chunk::parse(parse::Input::new(chunk))
+            .map_err(|e| Error::Parse(Box::new(e)))?;
+        if !chunk.checksum_valid() {
+            return Err(Error::BadChecksum);
+        }
+        if let storage::Chunk::Headers(_, headers) = chunk {
+            result.extend(headers);
+        }
+    }
+    Ok(result)
+}
+
+/// Split `data` into its chunks without parsing or checksumming them, so that they can be loaded
+/// independently.
+///
+/// If the framing of a chunk is invalid then it and all the remaining data are returned as the
+/// last element, so that loading it produces the appropriate error.
+pub(crate) fn split_chunks(mut data: &[u8]) -> Vec<&[u8]> {
+    // magic bytes, checksum and chunk type
+    const PREFIX_LEN: usize = 4 + 4 + 1;
+    let mut chunks = Vec::new();
+    while !data.is_empty() {
+        let chunk_len = data
+            .get(PREFIX_LEN..)
+            .filter(|_| data[..4] == storage::MAGIC_BYTES)
+            .and_then(|mut rest| {
+                let before = rest.len();
+                let len = leb128::read::unsigned(&mut rest).ok()? as usize;
+                PREFIX_LEN
+                    .checked_add(before - rest.len())?
+                    .checked_add(len)
+            })
+            .filter(|len| *len <= data.len())
+            .unwrap_or(data.len());
+        let (chunk, rest) = data.split_at(chunk_len);
+        chunks.push(chunk);
+        data = rest;
+    }
+    chunks
+}
+
+/// Whether `chunk` is a document chunk, as opposed to a change chunk
+pub(crate) fn is_document_chunk(chunk: &[u8]) -> bool {
+    chunk.get(8) == Some(&u8::from(storage::ChunkType::Document))
+}
+
+/// Whether `chunk` is a document configuration chunk
+pub(crate) fn is_config_chunk(chunk: &[u8]) -> bool {
+    chunk.get(8) == Some(&u8::from(storage::ChunkType::Config))
+}
+
+/// Whether `chunk` is a document headers chunk
+pub(crate) fn is_headers_chunk(chunk: &[u8]) -> bool {
+    chunk.get(8) == Some(&u8::from(storage::ChunkType::Headers))
+}
+
+/// Whether `chunk` describes the document rather than containing any of its changes, i.e. is a
+/// configuration or headers chunk
+pub(crate) fn is_settings_chunk(chunk: &[u8]) -> bool {
+    is_config_chunk(chunk) || is_headers_chunk(chunk)
+}
+
+/// Whether `chunk` is a chunk encoded with a [`crate::ChunkCodec`]
+pub(crate) fn is_encoded_chunk(chunk: &[u8]) -> bool {
+    chunk.get(8) == Some(&u8::from(storage::ChunkType::Encoded))
+}
+
+/// Whether `chunk` is a history fence chunk
+#[cfg(feature = "rayon")]
+pub(crate) fn is_fence_chunk(chunk: &[u8]) -> bool {
+    chunk.get(8) == Some(&u8::from(storage::ChunkType::Fence))
+}
+
 fn load_next_change<'a>(
     data: parse::Input<'a>,
     changes: &mut Vec<Change>,
@@ -108,6 +227,13 @@ fn load_next_change<'a>(
                     .map_err(|e| Error::InvalidChangeColumns(Box::new(e)))?;
             changes.push(change);
         }
+        storage::Chunk::Config(..) => {
+            tracing::trace!("skipping config chunk");
+        }
+        storage::Chunk::Headers(..) => {
+            tracing::trace!("skipping headers chunk");
+        }
+        storage::Chunk::Fence(..) => return Err(Error::Fenced),
     };
     Ok(remaining)
 }
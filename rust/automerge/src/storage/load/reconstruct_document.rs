@@ -69,8 +69,30 @@ pub(crate) struct Reconstructed<Output> {
 
 #[instrument(skip(doc, observer))]
 pub(crate) fn reconstruct_document<'a, O: DocObserver>(
+    doc: &'a Document<'a>,
+    observer: O,
+) -> Result<Reconstructed<O::Output>, Error> {
+    let collector = ChangeCollector::new(doc.iter_changes())?;
+    reconstruct(doc, observer, Some(collector))
+}
+
+/// Load the ops of a document chunk which has no changes, the base of a document saved without
+/// the changes beneath its history fence.
+///
+/// The ops can't be checked against changes, so the result has no changes and no heads, and the
+/// deletions recorded in the ops' successors are not reconstructed.
+#[instrument(skip(doc, observer))]
+pub(crate) fn reconstruct_base<'a, O: DocObserver>(
+    doc: &'a Document<'a>,
+    observer: O,
+) -> Result<Reconstructed<O::Output>, Error> {
+    reconstruct(doc, observer, None)
+}
+
+fn reconstruct<'a, O: DocObserver>(
     doc: &'a Document<'a>,
     mut observer: O,
+    mut collector: Option<ChangeCollector<'a>>,
 ) -> Result<Reconstructed<O::Output>, Error> {
     // The document format does not contain the bytes of the changes which are encoded in it
     // directly. Instead the metadata about the changes (the actor, the start op, etc.) are all
@@ -97,8 +119,6 @@ pub(crate) fn reconstruct_document<'a, O: DocObserver>(
     let mut metadata = OpSetMetadata::from_actors(doc.actors().to_vec());
     // The object we are currently loading, starts with the root
     let mut current_object = LoadingObject::root();
-    // The changes we are collecting to later construct the change graph from
-    let mut collector = ChangeCollector::new(doc.iter_changes())?;
     // A map where we record the create operations so that when the object ID the incoming
     // operations refer to switches we can lookup the object type for the new object. We also
     // need it so we can pass the parent object ID to the observer
@@ -150,7 +170,7 @@ pub(crate) fn reconstruct_document<'a, O: DocObserver>(
                 tracing::error!(?op, previous_obj=?current_object.id, "op referenced an object ID which was smaller than the previous object ID");
                 return Err(Error::OpsOutOfOrder);
             } else {
-                let loaded = current_object.finish(&mut collector, &metadata)?;
+                let loaded = current_object.finish(collector.as_mut(), &metadata)?;
                 objs_loaded.insert(loaded.id);
                 observer.object_loaded(loaded);
                 current_object =
@@ -159,7 +179,7 @@ pub(crate) fn reconstruct_document<'a, O: DocObserver>(
             }
         }
     }
-    let loaded = current_object.finish(&mut collector, &metadata)?;
+    let loaded = current_object.finish(collector.as_mut(), &metadata)?;
     objs_loaded.insert(loaded.id);
     observer.object_loaded(loaded);
 
@@ -183,13 +203,19 @@ pub(crate) fn reconstruct_document<'a, O: DocObserver>(
         }
     }
 
-    let super::change_collector::CollectedChanges { history, heads } =
-        collector.finish(&metadata)?;
-    let expected_heads: BTreeSet<_> = doc.heads().iter().cloned().collect();
-    if expected_heads != heads {
-        tracing::error!(?expected_heads, ?heads, "mismatching heads");
-        return Err(Error::MismatchingHeads);
-    }
+    let (history, heads) = match collector {
+        Some(collector) => {
+            let super::change_collector::CollectedChanges { history, heads } =
+                collector.finish(&metadata)?;
+            let expected_heads: BTreeSet<_> = doc.heads().iter().cloned().collect();
+            if expected_heads != heads {
+                tracing::error!(?expected_heads, ?heads, "mismatching heads");
+                return Err(Error::MismatchingHeads);
+            }
+            (history, heads)
+        }
+        None => (Vec::new(), BTreeSet::new()),
+    };
     let result = observer.finish(metadata);
 
     Ok(Reconstructed {
@@ -262,7 +288,7 @@ impl LoadingObject {
 
     fn finish(
         mut self,
-        collector: &mut ChangeCollector<'_>,
+        mut collector: Option<&mut ChangeCollector<'_>>,
         meta: &OpSetMetadata,
     ) -> Result<LoadedObject, Error> {
         let mut ops = Vec::new();
@@ -274,9 +300,22 @@ impl LoadingObject {
                 let inc_ops = op.succ.iter().filter_map(|s| self.inc_ops.get(s).copied());
                 c.increment(inc_ops);
             }
-            collector.collect(self.id, op.clone())?;
+            if let Some(collector) = collector.as_mut() {
+                collector.collect(self.id, op.clone())?;
+            }
             ops.push(op)
         }
+        let collector = match collector {
+            Some(collector) => collector,
+            None => {
+                return Ok(LoadedObject {
+                    id: self.id,
+                    parent: self.parent_id,
+                    ops,
+                    obj_type: self.obj_type,
+                })
+            }
+        };
         // Any remaining pred ops must be delete operations
         // TODO (alex): Figure out what index these should be inserted at. Does it even matter?
         for (opid, preds) in self.preds.into_iter() {
@@ -339,7 +378,7 @@ fn check_opid(m: &OpSetMetadata, opid: OpId) -> Result<OpId, Error> {
     }
 }
 
-fn parse_optype(action_index: usize, value: ScalarValue) -> Result<OpType, Error> {
+pub(crate) fn parse_optype(action_index: usize, value: ScalarValue) -> Result<OpType, Error> {
     match action_index {
         0 => Ok(OpType::Make(ObjType::Map)),
         1 => Ok(OpType::Put(value)),
@@ -104,7 +104,7 @@ impl<'a> AsChangeMeta<'a> for ChangeWithGraph<'a> {
     }
 
     fn extra(&self) -> Cow<'a, [u8]> {
-        self.change.extra_bytes().into()
+        self.change.raw_extra_bytes().into()
     }
 
     fn max_op(&self) -> u64 {
@@ -0,0 +1,139 @@
+use super::{load, parse, Chunk};
+use crate::columnar::Key;
+use crate::types::ObjId;
+
+/// A reason [`crate::VerificationMode::Strict`] rejected saved data.
+///
+/// Offsets are in bytes from the start of the data and identify the chunk at fault.
+#[derive(Debug, thiserror::Error)]
+pub enum VerificationError {
+    #[error("the chunk at {offset} could not be parsed: {source}")]
+    InvalidChunk {
+        offset: usize,
+        #[source]
+        source: load::Error,
+    },
+    #[error("the chunk at {offset} has a bad checksum")]
+    BadChecksum { offset: usize },
+    #[error("{len} bytes of trailing data at {offset}")]
+    TrailingData { offset: usize, len: usize },
+    #[error("the document at {offset} has columns with unknown specifications {specs:?}")]
+    UnknownColumns { offset: usize, specs: Vec<u32> },
+    #[error("the document at {offset} has {found} head indices for {expected} heads")]
+    HeadIndicesMismatch {
+        offset: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("the document at {offset} has head index {index} but only {changes} changes")]
+    HeadIndexOutOfRange {
+        offset: usize,
+        index: u64,
+        changes: usize,
+    },
+    #[error("op {op} of the document at {offset} has a zero counter")]
+    ZeroCounter { offset: usize, op: usize },
+    #[error("op {op} of the document at {offset} refers to actor {actor} of {actors}")]
+    ActorOutOfRange {
+        offset: usize,
+        op: usize,
+        actor: usize,
+        actors: usize,
+    },
+    #[error("op {op} of the document at {offset} has a successor which does not come after it")]
+    SuccessorBeforeOp { offset: usize, op: usize },
+}
+
+/// Check every chunk of `data` beyond what loading it requires.
+///
+/// Loading already rejects malformed column layouts and values which can't be decoded. This
+/// additionally requires that each chunk is framed exactly, with no bytes following the last one,
+/// and that document chunks have no unknown columns, a head index for every head and ops whose
+/// counters, actor indices and successors are in range.
+pub(crate) fn strict(data: &[u8]) -> Result<(), VerificationError> {
+    let mut offset = 0;
+    for chunk in load::split_chunks(data) {
+        if offset > 0 && !chunk.starts_with(&super::MAGIC_BYTES) {
+            return Err(VerificationError::TrailingData {
+                offset,
+                len: chunk.len(),
+            });
+        }
+        let (rest, parsed) = Chunk::parse(parse::Input::new(chunk)).map_err(|e| {
+            VerificationError::InvalidChunk {
+                offset,
+                source: load::Error::Parse(Box::new(e)),
+            }
+        })?;
+        if !rest.is_empty() {
+            // split_chunks puts everything after a badly framed chunk in the last chunk
+            let len = rest.bytes().len();
+            return Err(VerificationError::TrailingData {
+                offset: offset + chunk.len() - len,
+                len,
+            });
+        }
+        if !parsed.checksum_valid() {
+            return Err(VerificationError::BadChecksum { offset });
+        }
+        if let Chunk::Document(doc) = &parsed {
+            document(offset, doc)?;
+        }
+        offset += chunk.len();
+    }
+    Ok(())
+}
+
+fn document(offset: usize, doc: &super::Document<'_>) -> Result<(), VerificationError> {
+    let specs = doc.unknown_columns();
+    if !specs.is_empty() {
+        return Err(VerificationError::UnknownColumns { offset, specs });
+    }
+    if doc.head_indices().len() != doc.heads().len() {
+        return Err(VerificationError::HeadIndicesMismatch {
+            offset,
+            expected: doc.heads().len(),
+            found: doc.head_indices().len(),
+        });
+    }
+    let changes = doc.iter_changes().count();
+    if let Some(index) = doc.head_indices().iter().find(|i| **i as usize >= changes) {
+        return Err(VerificationError::HeadIndexOutOfRange {
+            offset,
+            index: *index,
+            changes,
+        });
+    }
+    let actors = doc.actors().len();
+    for (index, op) in doc.iter_ops().enumerate() {
+        let op = op.map_err(|e| VerificationError::InvalidChunk {
+            offset,
+            source: load::Error::InflateDocument(Box::new(e)),
+        })?;
+        if op.id.counter() == 0 {
+            return Err(VerificationError::ZeroCounter { offset, op: index });
+        }
+        let mut ids = vec![op.id];
+        if op.object != ObjId::root() {
+            ids.push(op.object.0);
+        }
+        if let Key::Elem(elem) = op.key {
+            if !elem.is_head() {
+                ids.push(elem.0);
+            }
+        }
+        ids.extend(op.succ.iter().copied());
+        if let Some(id) = ids.iter().find(|id| id.actor() >= actors) {
+            return Err(VerificationError::ActorOutOfRange {
+                offset,
+                op: index,
+                actor: id.actor(),
+                actors,
+            });
+        }
+        if op.succ.iter().any(|s| s.counter() <= op.id.counter()) {
+            return Err(VerificationError::SuccessorBeforeOp { offset, op: index });
+        }
+    }
+    Ok(())
+}
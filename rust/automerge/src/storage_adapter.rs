@@ -0,0 +1,325 @@
+//! Persisting documents to pluggable storage backends.
+//!
+//! A [`StorageAdapter`] knows how to append chunks of saved data to some storage and how to read
+//! them back; a [`Persister`] decides what to write. Each call to [`Persister::save`] writes the
+//! changes made since the last save as a new chunk and, once enough chunks have accumulated,
+//! replaces them all with a single compacted snapshot of the document.
+//!
+//! Loading concatenates every stored chunk and loads the result, and changes which appear in more
+//! than one chunk are only applied once. This means an adapter never has to worry about a chunk
+//! being stored twice, and can implement [`StorageAdapter::compact`] by writing the snapshot
+//! before removing the old chunks rather than needing an atomic replace.
+//!
+//! [`FileAdapter`] is a simple implementation which stores each chunk in its own file.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{Automerge, AutomergeError};
+
+/// Somewhere a document's chunks can be stored.
+pub trait StorageAdapter {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Store a new chunk of saved data alongside the existing chunks.
+    fn save_chunk(&mut self, chunk: &[u8]) -> Result<(), Self::Error>;
+
+    /// Load every stored chunk, in the order they were stored.
+    fn load_chunks(&mut self) -> Result<Vec<Vec<u8>>, Self::Error>;
+
+    /// Replace every stored chunk with `snapshot`.
+    ///
+    /// If this fails part way through then loading must still return either the old chunks or
+    /// the snapshot, or both.
+    fn compact(&mut self, snapshot: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// When a [`Persister`] should compact the stored chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionPolicy {
+    /// Compact once this many incremental chunks have been written.
+    pub max_chunks: usize,
+    /// Compact once the incremental chunks written since the document was loaded or last
+    /// compacted add up to this many bytes.
+    pub max_bytes: usize,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        Self {
+            max_chunks: 100,
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PersistError<E: std::error::Error + 'static> {
+    #[error("storage adapter failed: {0}")]
+    Adapter(#[source] E),
+    #[error(transparent)]
+    Automerge(#[from] AutomergeError),
+}
+
+/// Writes a document to a [`StorageAdapter`], see the [module documentation](self).
+#[derive(Debug)]
+pub struct Persister<A> {
+    adapter: A,
+    policy: CompactionPolicy,
+    chunks: usize,
+    bytes: usize,
+}
+
+impl<A: StorageAdapter> Persister<A> {
+    pub fn new(adapter: A) -> Self {
+        Self::with_policy(adapter, CompactionPolicy::default())
+    }
+
+    pub fn with_policy(adapter: A, policy: CompactionPolicy) -> Self {
+        Self {
+            adapter,
+            policy,
+            chunks: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Load the document stored in the adapter, which is empty if nothing has been stored.
+    pub fn load(&mut self) -> Result<Automerge, PersistError<A::Error>> {
+        let chunks = self.adapter.load_chunks().map_err(PersistError::Adapter)?;
+        self.chunks = chunks.len();
+        self.bytes = 0;
+        let mut doc = Automerge::load(&chunks.concat())?;
+        // everything we just loaded is already stored
+        doc.saved = doc.get_heads();
+        Ok(doc)
+    }
+
+    /// Store the changes made to `doc` since it was loaded or last saved, compacting if the
+    /// [`CompactionPolicy`] says it is time to.
+    ///
+    /// Returns `true` if the stored chunks were compacted. The changes only count as saved once
+    /// the adapter has stored them, so if the adapter fails the next call writes them again.
+    pub fn save(&mut self, doc: &mut Automerge) -> Result<bool, PersistError<A::Error>> {
+        let heads = doc.get_heads();
+        let chunk = doc.save_after(&doc.saved)?;
+        if chunk.is_empty() {
+            return Ok(false);
+        }
+        if self.chunks + 1 >= self.policy.max_chunks
+            || self.bytes + chunk.len() >= self.policy.max_bytes
+        {
+            self.compact(doc)?;
+            Ok(true)
+        } else {
+            self.adapter
+                .save_chunk(&chunk)
+                .map_err(PersistError::Adapter)?;
+            self.chunks += 1;
+            self.bytes += chunk.len();
+            doc.saved = heads;
+            Ok(false)
+        }
+    }
+
+    /// Replace the stored chunks with a snapshot of `doc`.
+    pub fn compact(&mut self, doc: &mut Automerge) -> Result<(), PersistError<A::Error>> {
+        let heads = doc.get_heads();
+        self.adapter
+            .compact(&doc.encode(None))
+            .map_err(PersistError::Adapter)?;
+        self.chunks = 0;
+        self.bytes = 0;
+        doc.saved = heads;
+        Ok(())
+    }
+
+    pub fn adapter(&self) -> &A {
+        &self.adapter
+    }
+
+    pub fn into_adapter(self) -> A {
+        self.adapter
+    }
+}
+
+const SNAPSHOT_FILE: &str = "snapshot.automerge";
+const CHUNK_PREFIX: &str = "chunk-";
+const CHUNK_SUFFIX: &str = ".automerge";
+
+/// A [`StorageAdapter`] which stores each chunk as a file in a directory.
+///
+/// Every file is written to a temporary name and then renamed into place, so a crash never
+/// leaves a partially written chunk behind.
+#[derive(Debug)]
+pub struct FileAdapter {
+    dir: PathBuf,
+    next_chunk: u64,
+}
+
+impl FileAdapter {
+    /// Store chunks in `dir`, creating it if necessary.
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut adapter = Self { dir, next_chunk: 0 };
+        adapter.next_chunk = adapter.chunk_numbers()?.last().map(|n| n + 1).unwrap_or(0);
+        Ok(adapter)
+    }
+
+    /// The numbers of the chunk files in the directory, in ascending order.
+    fn chunk_numbers(&self) -> io::Result<Vec<u64>> {
+        let mut numbers = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let number = name
+                .to_str()
+                .and_then(|n| n.strip_prefix(CHUNK_PREFIX))
+                .and_then(|n| n.strip_suffix(CHUNK_SUFFIX))
+                .and_then(|n| n.parse::<u64>().ok());
+            if let Some(number) = number {
+                numbers.push(number);
+            }
+        }
+        numbers.sort_unstable();
+        Ok(numbers)
+    }
+
+    fn chunk_path(&self, number: u64) -> PathBuf {
+        self.dir
+            .join(format!("{}{:020}{}", CHUNK_PREFIX, number, CHUNK_SUFFIX))
+    }
+
+    fn write_atomically(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+impl StorageAdapter for FileAdapter {
+    type Error = io::Error;
+
+    fn save_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+        let path = self.chunk_path(self.next_chunk);
+        self.write_atomically(&path, chunk)?;
+        self.next_chunk += 1;
+        Ok(())
+    }
+
+    fn load_chunks(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        let mut chunks = Vec::new();
+        match fs::read(self.dir.join(SNAPSHOT_FILE)) {
+            Ok(snapshot) => chunks.push(snapshot),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        for number in self.chunk_numbers()? {
+            chunks.push(fs::read(self.chunk_path(number))?);
+        }
+        Ok(chunks)
+    }
+
+    fn compact(&mut self, snapshot: &[u8]) -> io::Result<()> {
+        let numbers = self.chunk_numbers()?;
+        self.write_atomically(&self.dir.join(SNAPSHOT_FILE), snapshot)?;
+        for number in numbers {
+            fs::remove_file(self.chunk_path(number))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::Transactable;
+    use crate::ROOT;
+
+    #[test]
+    fn file_adapter_saves_and_compacts() {
+        let dir = std::env::temp_dir().join(format!("automerge-storage-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let policy = CompactionPolicy {
+            max_chunks: 3,
+            max_bytes: usize::MAX,
+        };
+        let mut persister = Persister::with_policy(FileAdapter::new(&dir).unwrap(), policy);
+        let mut doc = persister.load().unwrap();
+        assert!(doc.get_heads().is_empty());
+
+        let mut compactions = 0;
+        for i in 0..5 {
+            let mut tx = doc.transaction();
+            tx.put(ROOT, "n", i).unwrap();
+            tx.commit();
+            if persister.save(&mut doc).unwrap() {
+                compactions += 1;
+            }
+        }
+        assert_eq!(compactions, 1);
+        assert!(!persister.save(&mut doc).unwrap());
+        assert_eq!(persister.adapter().chunk_numbers().unwrap().len(), 2);
+
+        let mut reloaded = Persister::new(FileAdapter::new(&dir).unwrap());
+        let loaded = reloaded.load().unwrap();
+        assert_eq!(loaded.get_heads(), doc.get_heads());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Stores chunks in memory, failing while `fail` is set.
+    #[derive(Default)]
+    struct FlakyAdapter {
+        chunks: Vec<Vec<u8>>,
+        fail: bool,
+    }
+
+    impl StorageAdapter for FlakyAdapter {
+        type Error = io::Error;
+
+        fn save_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+            if self.fail {
+                return Err(io::Error::new(io::ErrorKind::Other, "unavailable"));
+            }
+            self.chunks.push(chunk.to_vec());
+            Ok(())
+        }
+
+        fn load_chunks(&mut self) -> io::Result<Vec<Vec<u8>>> {
+            Ok(self.chunks.clone())
+        }
+
+        fn compact(&mut self, snapshot: &[u8]) -> io::Result<()> {
+            if self.fail {
+                return Err(io::Error::new(io::ErrorKind::Other, "unavailable"));
+            }
+            self.chunks = vec![snapshot.to_vec()];
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_saves_are_retried() {
+        let mut persister = Persister::new(FlakyAdapter::default());
+        let mut doc = persister.load().unwrap();
+        let mut tx = doc.transaction();
+        tx.put(ROOT, "a", 1).unwrap();
+        tx.commit();
+
+        persister.adapter.fail = true;
+        assert!(matches!(
+            persister.save(&mut doc),
+            Err(PersistError::Adapter(_))
+        ));
+        assert!(matches!(
+            persister.compact(&mut doc),
+            Err(PersistError::Adapter(_))
+        ));
+        persister.adapter.fail = false;
+        assert!(!persister.save(&mut doc).unwrap());
+        assert!(!persister.save(&mut doc).unwrap());
+
+        let loaded = persister.load().unwrap();
+        assert_eq!(loaded.get_heads(), doc.get_heads());
+    }
+}
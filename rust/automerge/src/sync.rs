@@ -3,18 +3,23 @@ use serde::ser::SerializeMap;
 use std::collections::{HashMap, HashSet};
 
 use crate::{
+    exid::ExId,
     storage::{parse, Change as StoredChange, ReadChangeOpError},
     Automerge, AutomergeError, Change, ChangeHash, OpObserver,
 };
 
 mod bloom;
+mod chunk;
+mod http;
 mod state;
+mod version;
 
 pub use bloom::BloomFilter;
+pub use chunk::ChunkProgress;
+pub use http::{respond, PollError};
 pub use state::DecodeError as DecodeStateError;
-pub use state::{Have, State};
-
-const MESSAGE_TYPE_SYNC: u8 = 0x42; // first byte of a sync message, for identification
+pub use state::{Have, State, SyncOptions, SyncStats};
+pub use version::WireVersion;
 
 impl Automerge {
     pub fn generate_sync_message(&self, sync_state: &mut State) -> Option<Message> {
@@ -27,10 +32,13 @@ impl Automerge {
         } else {
             HashSet::new()
         };
-        let our_have = if our_need.iter().all(|hash| their_heads_set.contains(hash)) {
-            vec![self.make_bloom_filter(sync_state.shared_heads.clone())]
-        } else {
+        // if we are missing dependencies of changes they sent then they didn't send them because
+        // our filter claimed we had them
+        let false_positive = !our_need.iter().all(|hash| their_heads_set.contains(hash));
+        let our_have = if false_positive {
             Vec::new()
+        } else {
+            vec![self.make_bloom_filter(sync_state, sync_state.shared_heads.clone())]
         };
 
         if let Some(ref their_have) = sync_state.their_have {
@@ -38,14 +46,17 @@ impl Automerge {
                 if !first_have
                     .last_sync
                     .iter()
-                    .all(|hash| self.get_change_by_hash(hash).is_some())
+                    .all(|hash| self.has_change(hash))
                 {
                     let reset_msg = Message {
                         heads: our_heads,
                         need: Vec::new(),
                         have: vec![Have::default()],
                         changes: Vec::new(),
+                        version: sync_state.wire_version(),
+                        supported_versions: WireVersion::SUPPORTED.to_vec(),
                     };
+                    sync_state.stats.messages_sent += 1;
                     return Some(reset_msg);
                 }
             }
@@ -80,11 +91,26 @@ impl Automerge {
                 }
             })
             .collect::<Vec<_>>();
+        let changes_to_send = self.scope_changes(sync_state, changes_to_send);
+        let pending = changes_to_send.len();
+        let changes_to_send = self.prioritise_changes(sync_state, changes_to_send);
+        sync_state.stats.estimated_remaining = pending - changes_to_send.len();
+
+        // a peer which needs changes we dropped beneath our history fence can't catch up either,
+        // whether or not it has told us it lost its data
+        if sync_state.behind_fence && changes_to_send.is_empty() && our_need.is_empty() {
+            return None;
+        }
 
         if heads_unchanged {
             if heads_equal && changes_to_send.is_empty() {
                 return None;
             }
+            // a peer with a scope never catches up with our heads, so stop once there is
+            // nothing more in scope to send
+            if !sync_state.scope.is_empty() && changes_to_send.is_empty() && our_need.is_empty() {
+                return None;
+            }
             if sync_state.in_flight {
                 return None;
             }
@@ -94,18 +120,105 @@ impl Automerge {
         sync_state
             .sent_hashes
             .extend(changes_to_send.iter().map(|c| c.hash()));
+        sync_state.stats.messages_sent += 1;
+        sync_state.stats.changes_sent += changes_to_send.len() as u64;
+        sync_state.stats.bytes_sent += changes_bytes(&changes_to_send);
 
         let sync_message = Message {
             heads: our_heads,
             have: our_have,
             need: our_need,
             changes: changes_to_send,
+            version: sync_state.wire_version(),
+            supported_versions: WireVersion::SUPPORTED.to_vec(),
         };
 
+        if false_positive {
+            sync_state.bloom_false_positive();
+        }
         sync_state.in_flight = true;
         Some(sync_message)
     }
 
+    /// Move the changes to the objects prioritised by `sync_state`, and the changes they depend
+    /// on, to the front of `changes`, and drop any beyond the state's limit on changes per
+    /// message. Changes keep their relative order otherwise, so the result is still causally
+    /// ordered and the peer can apply every change in it.
+    fn prioritise_changes(&self, sync_state: &State, changes: Vec<Change>) -> Vec<Change> {
+        let limit = match sync_state.max_changes_per_message {
+            Some(limit) if changes.len() > limit => limit,
+            _ => return changes,
+        };
+        let prioritised = self.affecting_with_deps(&sync_state.priority, &changes);
+        let (mut first, rest): (Vec<_>, Vec<_>) = changes
+            .into_iter()
+            .partition(|c| prioritised.contains(&c.hash()));
+        first.extend(rest);
+        first.truncate(limit);
+        first
+    }
+
+    /// Drop the changes which don't affect the scope of `sync_state` and which none of the
+    /// changes that do affect it depend on.
+    fn scope_changes(&self, sync_state: &State, changes: Vec<Change>) -> Vec<Change> {
+        if sync_state.scope.is_empty() {
+            return changes;
+        }
+        let in_scope = self.affecting_with_deps(&sync_state.scope, &changes);
+        changes
+            .into_iter()
+            .filter(|c| in_scope.contains(&c.hash()))
+            .collect()
+    }
+
+    /// The hashes of the changes among `changes` which affect any of `objs` or their
+    /// descendants, along with those of the changes among `changes` they depend on.
+    fn affecting_with_deps(&self, objs: &[ExId], changes: &[Change]) -> HashSet<ChangeHash> {
+        let mut found = objs
+            .iter()
+            .filter_map(|obj| self.changes_affecting(obj).ok())
+            .flatten()
+            .map(|c| c.hash())
+            .collect::<HashSet<_>>();
+        // walk backwards so that each change's dependents have been seen before it
+        for change in changes.iter().rev() {
+            if found.contains(&change.hash()) {
+                found.extend(change.deps().iter().copied());
+            }
+        }
+        found
+    }
+
+    /// Like [`Self::generate_sync_message`] but returns the encoded message split into chunks no
+    /// longer than `max_chunk_len` bytes, for transports which limit the size of a message.
+    ///
+    /// Each call returns the next chunk of the current message, generating a new message once all
+    /// the chunks of the previous one have been handed out. Progress is available from
+    /// [`State::send_progress`]. The receiver passes each chunk to [`State::receive_chunk`], which
+    /// returns the reassembled message once every chunk has arrived.
+    pub fn generate_sync_chunk(
+        &self,
+        sync_state: &mut State,
+        max_chunk_len: usize,
+    ) -> Option<Vec<u8>> {
+        if sync_state.outgoing_chunks.is_empty() {
+            let message = self.generate_sync_message(sync_state)?;
+            sync_state.outgoing_chunks = chunk::split(&message.encode(), max_chunk_len).into();
+            sync_state.send_progress = Some(ChunkProgress {
+                done: 0,
+                total: sync_state.outgoing_chunks.len(),
+            });
+        }
+        let next = sync_state.outgoing_chunks.pop_front();
+        if let Some(progress) = sync_state.send_progress.as_mut() {
+            progress.done += 1;
+        }
+        if sync_state.outgoing_chunks.is_empty() {
+            sync_state.send_progress = None;
+        }
+        next
+    }
+
     pub fn receive_sync_message(
         &mut self,
         sync_state: &mut State,
@@ -127,7 +240,14 @@ impl Automerge {
             changes: message_changes,
             need: message_need,
             have: message_have,
+            supported_versions,
+            ..
         } = message;
+        sync_state.their_versions = supported_versions;
+        sync_state.stats.messages_received += 1;
+        sync_state.stats.changes_received += message_changes.len() as u64;
+        sync_state.stats.bytes_received += changes_bytes(&message_changes);
+        sync_state.stats.last_need = message_need.len();
 
         let changes_is_empty = message_changes.is_empty();
         if !changes_is_empty {
@@ -152,7 +272,7 @@ impl Automerge {
 
         let known_heads = message_heads
             .iter()
-            .filter(|head| self.get_change_by_hash(head).is_some())
+            .filter(|head| self.has_change(head))
             .collect::<Vec<_>>();
         if known_heads.len() == message_heads.len() {
             sync_state.shared_heads = message_heads.clone();
@@ -173,6 +293,11 @@ impl Automerge {
                 .collect::<Vec<_>>();
         }
 
+        sync_state.behind_fence = self.dropped_history.as_ref().map_or(false, |fence| {
+            message_need
+                .iter()
+                .any(|hash| fence.heads.binary_search(hash).is_ok())
+        });
         sync_state.their_have = Some(message_have);
         sync_state.their_heads = Some(message_heads);
         sync_state.their_need = Some(message_need);
@@ -180,14 +305,14 @@ impl Automerge {
         Ok(())
     }
 
-    fn make_bloom_filter(&self, last_sync: Vec<ChangeHash>) -> Have {
+    fn make_bloom_filter(&self, sync_state: &State, last_sync: Vec<ChangeHash>) -> Have {
         let new_changes = self
             .get_changes(&last_sync)
             .expect("Should have only used hashes that are in the document");
         let hashes = new_changes.iter().map(|change| change.hash());
         Have {
             last_sync,
-            bloom: BloomFilter::from_hashes(hashes),
+            bloom: sync_state.bloom_filter(hashes),
         }
     }
 
@@ -273,6 +398,8 @@ pub enum ReadMessageError {
     ReadChangeOps(#[from] ReadChangeOpError),
     #[error("not enough input")]
     NotEnoughInput,
+    #[error("unsupported sync message version {0}")]
+    UnsupportedVersion(u64),
 }
 
 impl From<parse::leb128::Error> for ReadMessageError {
@@ -309,7 +436,12 @@ impl From<parse::ParseError<ReadMessageError>> for ReadMessageError {
 }
 
 /// The sync message to be sent.
+///
+/// The `version` and `supported_versions` fields were added with wire versioning, which broke
+/// code constructing messages with a struct literal. To avoid breaking it again when more fields
+/// are added the struct is `#[non_exhaustive]`, construct messages with [`Message::new`] instead.
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub struct Message {
     /// The heads of the sender.
     pub heads: Vec<ChangeHash>,
@@ -319,6 +451,10 @@ pub struct Message {
     pub have: Vec<Have>,
     /// The changes for the recipient to apply.
     pub changes: Vec<Change>,
+    /// The version the message is encoded in.
+    pub version: WireVersion,
+    /// The versions the sender can read, empty if the sender predates versioning.
+    pub supported_versions: Vec<WireVersion>,
 }
 
 impl serde::Serialize for Message {
@@ -350,6 +486,36 @@ fn parse_have(input: parse::Input<'_>) -> parse::ParseResult<'_, Have, ReadMessa
 }
 
 impl Message {
+    /// A message in [`WireVersion::V1`], which every peer can read, which doesn't list the
+    /// versions we support.
+    pub fn new(
+        heads: Vec<ChangeHash>,
+        need: Vec<ChangeHash>,
+        have: Vec<Have>,
+        changes: Vec<Change>,
+    ) -> Self {
+        Self {
+            heads,
+            need,
+            have,
+            changes,
+            version: WireVersion::V1,
+            supported_versions: Vec::new(),
+        }
+    }
+
+    /// Send this message in `version` and tell the recipient that we can read
+    /// `supported_versions`.
+    pub fn with_versions(
+        mut self,
+        version: WireVersion,
+        supported_versions: Vec<WireVersion>,
+    ) -> Self {
+        self.version = version;
+        self.supported_versions = supported_versions;
+        self
+    }
+
     pub fn decode(input: &[u8]) -> Result<Self, ReadMessageError> {
         let input = parse::Input::new(input);
         match Self::parse(input) {
@@ -360,14 +526,7 @@ impl Message {
     }
 
     pub(crate) fn parse(input: parse::Input<'_>) -> parse::ParseResult<'_, Self, ReadMessageError> {
-        let (i, message_type) = parse::take1(input)?;
-        if message_type != MESSAGE_TYPE_SYNC {
-            return Err(parse::ParseError::Error(ReadMessageError::WrongType {
-                expected_one_of: vec![MESSAGE_TYPE_SYNC],
-                found: message_type,
-            }));
-        }
-
+        let (i, version) = version::parse_header(input)?;
         let (i, heads) = parse::length_prefixed(parse::change_hash)(i)?;
         let (i, need) = parse::length_prefixed(parse::change_hash)(i)?;
         let (i, have) = parse::length_prefixed(parse_have)(i)?;
@@ -391,6 +550,7 @@ impl Message {
                     Ok(acc)
                 },
             )?;
+        let (i, supported_versions) = version::parse_supported(i)?;
 
         Ok((
             i,
@@ -399,12 +559,15 @@ impl Message {
                 need,
                 have,
                 changes,
+                version,
+                supported_versions,
             },
         ))
     }
 
     pub fn encode(mut self) -> Vec<u8> {
-        let mut buf = vec![MESSAGE_TYPE_SYNC];
+        let mut buf = Vec::new();
+        version::encode_header(&mut buf, self.version);
 
         encode_hashes(&mut buf, &self.heads);
         encode_hashes(&mut buf, &self.need);
@@ -418,12 +581,13 @@ impl Message {
             leb128::write::unsigned(buf, change.raw_bytes().len() as u64).unwrap();
             buf.extend(change.raw_bytes().as_ref())
         });
+        version::encode_supported(&mut buf, &self.supported_versions);
 
         buf
     }
 }
 
-fn encode_many<'a, I, It, F>(out: &mut Vec<u8>, data: I, f: F)
+pub(crate) fn encode_many<'a, I, It, F>(out: &mut Vec<u8>, data: I, f: F)
 where
     I: Iterator<Item = It> + ExactSizeIterator + 'a,
     F: Fn(&mut Vec<u8>, It),
@@ -434,7 +598,7 @@ where
     }
 }
 
-fn encode_hashes(buf: &mut Vec<u8>, hashes: &[ChangeHash]) {
+pub(crate) fn encode_hashes(buf: &mut Vec<u8>, hashes: &[ChangeHash]) {
     debug_assert!(
         hashes.windows(2).all(|h| h[0] <= h[1]),
         "hashes were not sorted"
@@ -442,6 +606,11 @@ fn encode_hashes(buf: &mut Vec<u8>, hashes: &[ChangeHash]) {
     encode_many(buf, hashes.iter(), |buf, hash| buf.extend(hash.as_bytes()))
 }
 
+/// The encoded size of `changes`
+fn changes_bytes(changes: &[Change]) -> u64 {
+    changes.iter().map(|c| c.raw_bytes().len() as u64).sum()
+}
+
 fn advance_heads(
     my_old_heads: &HashSet<&ChangeHash>,
     my_new_heads: &HashSet<ChangeHash>,
@@ -475,7 +644,7 @@ mod tests {
     use crate::storage::parse::Input;
     use crate::transaction::Transactable;
     use crate::types::gen::gen_hash;
-    use crate::ActorId;
+    use crate::{ActorId, ObjType, Value};
     use proptest::prelude::*;
 
     prop_compose! {
@@ -506,12 +675,16 @@ mod tests {
             need in gen_sorted_hashes(0..10),
             have in proptest::collection::vec(gen_have(), 0..10),
             changes in proptest::collection::vec(gen_change(), 0..10),
+            version in proptest::sample::select(WireVersion::SUPPORTED.to_vec()),
+            supported_versions in proptest::sample::subsequence(WireVersion::SUPPORTED.to_vec(), 0..=2),
         ) -> Message {
             Message {
                 heads,
                 need,
                 have,
                 changes,
+                version,
+                supported_versions,
             }
         }
 
@@ -524,6 +697,8 @@ mod tests {
             need: vec![],
             have: vec![],
             changes: vec![],
+            version: WireVersion::V1,
+            supported_versions: vec![],
         };
         let encoded = msg.encode();
         Message::parse(Input::new(&encoded)).unwrap();
@@ -539,6 +714,54 @@ mod tests {
         }
     }
 
+    #[test]
+    fn bloom_filter_options() {
+        let mut doc = crate::AutoCommit::new();
+        doc.put(crate::ROOT, "key", "value").unwrap();
+        let options = SyncOptions {
+            bloom_bits_per_entry: 20,
+            bloom_probes: 14,
+            adaptive_bloom: false,
+        };
+        let mut sync_state = State::with_options(options);
+        let message = doc.generate_sync_message(&mut sync_state).unwrap();
+        assert_eq!(message.have[0].bloom.num_bits_per_entry(), 20);
+        assert_eq!(message.have[0].bloom.num_probes(), 14);
+    }
+
+    #[test]
+    fn adaptive_bloom_filter_grows_after_false_positive() {
+        let mut doc1 = crate::AutoCommit::new();
+        doc1.put(crate::ROOT, "a", 1).unwrap();
+        doc1.commit();
+        doc1.put(crate::ROOT, "b", 2).unwrap();
+        doc1.commit();
+        let changes = doc1
+            .get_changes(&[])
+            .unwrap()
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // doc2 received the second change but the first was withheld, as if our filter had
+        // wrongly claimed that we have it
+        let mut doc2 = crate::AutoCommit::new();
+        doc2.apply_changes(vec![changes[1].clone()]).unwrap();
+        let mut sync_state = State::with_options(SyncOptions {
+            adaptive_bloom: true,
+            ..Default::default()
+        });
+        sync_state.their_heads = Some(doc1.get_heads());
+        let message = doc2.generate_sync_message(&mut sync_state).unwrap();
+        assert!(message.have.is_empty());
+        assert_eq!(message.need, vec![changes[0].hash()]);
+
+        doc2.apply_changes(vec![changes[0].clone()]).unwrap();
+        let message = doc2.generate_sync_message(&mut sync_state).unwrap();
+        assert_eq!(message.have[0].bloom.num_bits_per_entry(), 20);
+        assert_eq!(message.have[0].bloom.num_probes(), 14);
+    }
+
     #[test]
     fn generate_sync_message_twice_does_nothing() {
         let mut doc = crate::AutoCommit::new();
@@ -549,6 +772,97 @@ mod tests {
         assert!(doc.generate_sync_message(&mut sync_state).is_none());
     }
 
+    #[test]
+    fn chunked_sync_reassembles_large_messages() {
+        let mut doc1 = crate::AutoCommit::new();
+        let mut doc2 = crate::AutoCommit::new();
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+        for i in 0..20 {
+            doc1.put(crate::ROOT, "x", i).unwrap();
+            doc1.commit();
+        }
+
+        let mut saw_partial = false;
+        for _ in 0..1000 {
+            let mut quiet = true;
+            if let Some(chunk) = doc1.generate_sync_chunk(&mut s1, 64) {
+                assert!(chunk.len() <= 64);
+                quiet = false;
+                match s2.receive_chunk(&chunk).unwrap() {
+                    Some(msg) => doc2.receive_sync_message(&mut s2, msg).unwrap(),
+                    None => saw_partial = s2.receive_progress().is_some(),
+                }
+            }
+            if let Some(chunk) = doc2.generate_sync_chunk(&mut s2, 64) {
+                quiet = false;
+                if let Some(msg) = s1.receive_chunk(&chunk).unwrap() {
+                    doc1.receive_sync_message(&mut s1, msg).unwrap();
+                }
+            }
+            if quiet {
+                break;
+            }
+        }
+        assert!(saw_partial);
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+        assert!(s1.send_progress().is_none());
+        assert!(s2.receive_progress().is_none());
+    }
+
+    #[test]
+    fn out_of_order_chunks_are_rejected() {
+        let mut doc = crate::AutoCommit::new();
+        for i in 0..10 {
+            doc.put(crate::ROOT, "x", i).unwrap();
+            doc.commit();
+        }
+        let mut s1 = State::new();
+        let msg = doc.generate_sync_message(&mut s1).unwrap();
+        let chunks = chunk::split(&msg.encode(), 32);
+        assert!(chunks.len() > 2);
+
+        let mut s2 = State::new();
+        assert!(s2.receive_chunk(&chunks[1]).is_err());
+        assert!(s2.receive_chunk(&chunks[0]).unwrap().is_none());
+        assert_eq!(
+            s2.receive_progress(),
+            Some(ChunkProgress {
+                done: 1,
+                total: chunks.len()
+            })
+        );
+        assert!(s2.receive_chunk(&chunks[2]).is_err());
+    }
+
+    #[test]
+    fn reset_for_reconnect_recovers_from_lost_messages() {
+        let mut doc1 = crate::AutoCommit::new();
+        let mut doc2 = crate::AutoCommit::new();
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+        doc1.put(crate::ROOT, "a", 1).unwrap();
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        let shared = s1.shared_heads.clone();
+        assert_eq!(shared, doc2.get_heads());
+
+        // a message is sent, but the connection drops before it arrives
+        doc1.put(crate::ROOT, "b", 2).unwrap();
+        assert!(doc1.generate_sync_message(&mut s1).is_some());
+        assert!(doc1.generate_sync_message(&mut s1).is_none());
+
+        s1.reset_for_reconnect();
+        s2.reset_for_reconnect();
+        assert_eq!(s1.shared_heads, shared);
+        assert!(!s1.in_flight && s1.sent_hashes.is_empty() && s1.their_heads.is_none());
+
+        let msg = doc1.generate_sync_message(&mut s1).unwrap();
+        assert_eq!(msg.have[0].last_sync, shared);
+        doc2.receive_sync_message(&mut s2, msg).unwrap();
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+    }
+
     #[test]
     fn should_not_reply_if_we_have_no_data() {
         let mut doc1 = crate::AutoCommit::new();
@@ -771,6 +1085,349 @@ mod tests {
         assert_eq!(doc2.get_heads(), all_heads);
     }
 
+    #[test]
+    fn prioritised_objects_are_sent_first() {
+        let mut doc1 = crate::AutoCommit::new();
+        let mut doc2 = crate::AutoCommit::new();
+        let log = doc1.put_object(crate::ROOT, "log", ObjType::List).unwrap();
+        let view = doc1.put_object(crate::ROOT, "view", ObjType::Map).unwrap();
+        doc1.commit();
+        // the log is written concurrently with the view, so the view's change doesn't depend on
+        // the log's
+        let mut logger = doc1.fork();
+        for i in 0..6 {
+            logger.insert(&log, i, i as i64).unwrap();
+            logger.commit();
+        }
+        doc1.put(&view, "title", "hello").unwrap();
+        doc1.commit();
+        doc1.merge(&mut logger).unwrap();
+
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+        s1.set_priority(vec![view.clone()], 2);
+        let msg = doc1.generate_sync_message(&mut s1).unwrap();
+        doc2.receive_sync_message(&mut s2, msg).unwrap();
+        let msg = doc2.generate_sync_message(&mut s2).unwrap();
+        doc1.receive_sync_message(&mut s1, msg).unwrap();
+
+        let msg = doc1.generate_sync_message(&mut s1).unwrap();
+        assert_eq!(msg.changes.len(), 2);
+        doc2.receive_sync_message(&mut s2, msg).unwrap();
+        assert_eq!(
+            doc2.get(&view, "title").unwrap().unwrap().0,
+            Value::str("hello")
+        );
+        assert_eq!(doc2.length(&log), 0);
+
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc2.length(&log), 6);
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+    }
+
+    #[test]
+    fn scoped_sync_only_sends_changes_in_scope() {
+        let mut doc1 = crate::AutoCommit::new();
+        let mut doc2 = crate::AutoCommit::new();
+        let log = doc1.put_object(crate::ROOT, "log", ObjType::List).unwrap();
+        let view = doc1.put_object(crate::ROOT, "view", ObjType::Map).unwrap();
+        doc1.commit();
+        let mut logger = doc1.fork();
+        for i in 0..6 {
+            logger.insert(&log, i, i as i64).unwrap();
+            logger.commit();
+        }
+        doc1.put(&view, "title", "hello").unwrap();
+        doc1.commit();
+        doc1.merge(&mut logger).unwrap();
+
+        let mut s1 = State::with_scope(vec![view.clone()]);
+        let mut s2 = State::new();
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(
+            doc2.get(&view, "title").unwrap().unwrap().0,
+            Value::str("hello")
+        );
+        assert_eq!(doc2.length(&log), 0);
+        assert_eq!(doc2.get_changes(&[]).unwrap().len(), 2);
+
+        // changes the thin client makes still reach us
+        doc2.put(&view, "title", "hi").unwrap();
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(
+            doc1.get(&view, "title").unwrap().unwrap().0,
+            Value::str("hi")
+        );
+        assert_eq!(doc1.length(&log), 6);
+    }
+
+    #[test]
+    fn sync_with_fenced_document() {
+        let mut doc1 = crate::AutoCommit::new();
+        for i in 0..10 {
+            doc1.put(crate::ROOT, "n", i).unwrap();
+            doc1.commit();
+        }
+        let fence = doc1.get_heads();
+        doc1.set_history_fence(&fence).unwrap();
+        let mut fenced = crate::AutoCommit::load(&doc1.save()).unwrap();
+        fenced.set_actor(crate::ActorId::random());
+
+        // peers which have the fence sync as usual
+        doc1.put(crate::ROOT, "a", 1).unwrap();
+        fenced.put(crate::ROOT, "b", 2).unwrap();
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+        sync(&mut doc1, &mut fenced, &mut s1, &mut s2);
+        assert_eq!(doc1.get_heads(), fenced.get_heads());
+        assert!(!s2.peer_behind_fence());
+
+        // a peer without the fence can't be brought up to date
+        let mut empty = crate::AutoCommit::new();
+        let mut s3 = State::new();
+        let mut s4 = State::new();
+        sync(&mut fenced, &mut empty, &mut s3, &mut s4);
+        assert!(s3.peer_behind_fence());
+        assert!(empty.get_heads().is_empty());
+    }
+
+    /// The first message an empty document sends, as encoded by peers which predate versioning
+    const LEGACY_EMPTY_MESSAGE: [u8; 7] = [0x42, 0, 0, 1, 0, 0, 0];
+
+    #[test]
+    fn decode_messages_from_peers_without_versioning() {
+        let msg = Message::decode(&LEGACY_EMPTY_MESSAGE).unwrap();
+        assert_eq!(msg.version, WireVersion::V1);
+        assert!(msg.supported_versions.is_empty());
+        assert_eq!(msg.have, vec![Have::default()]);
+
+        // we keep sending them the original encoding
+        let mut doc = crate::AutoCommit::new();
+        let mut state = State::new();
+        doc.receive_sync_message(&mut state, msg).unwrap();
+        assert_eq!(state.wire_version(), WireVersion::V1);
+    }
+
+    #[test]
+    fn first_message_is_readable_by_peers_without_versioning() {
+        let mut doc = crate::AutoCommit::new();
+        let encoded = doc
+            .generate_sync_message(&mut State::new())
+            .unwrap()
+            .encode();
+        // the original encoding followed by the versions we support, which older peers ignore
+        assert_eq!(&encoded[..7], &LEGACY_EMPTY_MESSAGE);
+        assert_eq!(&encoded[7..], &[2, 1, 2]);
+    }
+
+    #[test]
+    fn peers_negotiate_the_highest_common_version() {
+        let mut doc1 = crate::AutoCommit::new();
+        let mut doc2 = crate::AutoCommit::new();
+        doc1.put(crate::ROOT, "key", "value").unwrap();
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+
+        let msg = doc1.generate_sync_message(&mut s1).unwrap();
+        assert_eq!(msg.version, WireVersion::V1);
+        let msg = Message::decode(&msg.encode()).unwrap();
+        doc2.receive_sync_message(&mut s2, msg).unwrap();
+        assert_eq!(s2.wire_version(), WireVersion::V2);
+
+        let encoded = doc2.generate_sync_message(&mut s2).unwrap().encode();
+        assert_eq!(&encoded[..2], &[0x46, 2]);
+        let msg = Message::decode(&encoded).unwrap();
+        assert_eq!(msg.version, WireVersion::V2);
+        doc1.receive_sync_message(&mut s1, msg).unwrap();
+
+        let msg = doc1.generate_sync_message(&mut s1).unwrap();
+        assert_eq!(msg.version, WireVersion::V2);
+        doc2.receive_sync_message(&mut s2, Message::decode(&msg.encode()).unwrap())
+            .unwrap();
+        assert_eq!(
+            doc2.get(crate::ROOT, "key").unwrap().unwrap().0,
+            Value::from("value")
+        );
+
+        assert_eq!(
+            WireVersion::negotiate(&WireVersion::SUPPORTED, &[WireVersion::V1]),
+            WireVersion::V1
+        );
+    }
+
+    #[test]
+    fn unknown_versions_are_an_error() {
+        assert!(matches!(
+            Message::decode(&[0x46, 9, 0, 0, 0, 0]),
+            Err(ReadMessageError::UnsupportedVersion(9))
+        ));
+        assert!(matches!(
+            Message::decode(&[0x46, 1, 0, 0, 0, 0]),
+            Err(ReadMessageError::UnsupportedVersion(1))
+        ));
+        assert!(matches!(
+            Message::decode(&[0x44, 0, 0, 0, 0]),
+            Err(ReadMessageError::WrongType { found: 0x44, .. })
+        ));
+        // sync states are not messages, whether encoded or persisted
+        let state = State::new();
+        for bytes in [state.encode(), state.persist()] {
+            assert!(matches!(
+                Message::decode(&bytes),
+                Err(ReadMessageError::WrongType { .. })
+            ));
+        }
+        // versions we don't know about in the list of supported versions are skipped
+        let msg = Message::decode(&[0x42, 0, 0, 0, 0, 3, 1, 2, 7]).unwrap();
+        assert_eq!(
+            msg.supported_versions,
+            vec![WireVersion::V1, WireVersion::V2]
+        );
+    }
+
+    #[test]
+    fn sync_stats() {
+        let mut doc1 = crate::AutoCommit::new();
+        for i in 0..5 {
+            doc1.put(crate::ROOT, "key", i).unwrap();
+            doc1.commit();
+        }
+        let mut doc2 = crate::AutoCommit::new();
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+        s1.set_priority(vec![], 2);
+
+        let msg = doc1.generate_sync_message(&mut s1).unwrap();
+        doc2.receive_sync_message(&mut s2, msg).unwrap();
+        let msg = doc2.generate_sync_message(&mut s2).unwrap();
+        doc1.receive_sync_message(&mut s1, msg).unwrap();
+        let msg = doc1.generate_sync_message(&mut s1).unwrap();
+        assert_eq!(msg.changes.len(), 2);
+        assert_eq!(s1.stats().estimated_remaining, 3);
+        doc2.receive_sync_message(&mut s2, msg).unwrap();
+
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        let (stats1, stats2) = (s1.stats(), s2.stats());
+        assert_eq!(stats1.changes_sent, 5);
+        assert_eq!(stats2.changes_received, 5);
+        assert_eq!(stats2.changes_sent, 0);
+        assert_eq!(stats1.bytes_sent, stats2.bytes_received);
+        let total: u64 = doc1
+            .get_changes(&[])
+            .unwrap()
+            .iter()
+            .map(|c| c.raw_bytes().len() as u64)
+            .sum();
+        assert_eq!(stats1.bytes_sent, total);
+        assert_eq!(stats1.messages_sent, stats2.messages_received);
+        assert_eq!(stats1.estimated_remaining, 0);
+        assert_eq!(stats1.last_need, 0);
+
+        s1.reset_for_reconnect();
+        assert_eq!(s1.stats().changes_sent, 5);
+    }
+
+    #[test]
+    fn persisted_state_resumes_where_it_left_off() {
+        let mut doc1 = crate::AutoCommit::new();
+        let list = doc1.put_object(crate::ROOT, "list", ObjType::List).unwrap();
+        for i in 0..5 {
+            doc1.insert(&list, i, i as i64).unwrap();
+            doc1.commit();
+        }
+        let mut doc2 = crate::AutoCommit::new();
+        let mut s1 = State::with_options(SyncOptions {
+            adaptive_bloom: true,
+            ..Default::default()
+        });
+        s1.set_priority(vec![list], 2);
+        let mut s2 = State::new();
+
+        let msg = doc1.generate_sync_message(&mut s1).unwrap();
+        doc2.receive_sync_message(&mut s2, msg).unwrap();
+        let msg = doc2.generate_sync_message(&mut s2).unwrap();
+        doc1.receive_sync_message(&mut s1, msg).unwrap();
+        let msg = doc1.generate_sync_message(&mut s1).unwrap();
+        assert!(s1.in_flight);
+
+        let mut s1 = State::resume(&s1.persist()).unwrap();
+        let mut s2 = State::resume(&s2.persist()).unwrap();
+        assert!(s1.in_flight);
+        assert!(doc1.generate_sync_message(&mut s1).is_none());
+        assert_eq!(State::resume(&s1.persist()).unwrap(), s1);
+
+        doc2.receive_sync_message(&mut s2, msg).unwrap();
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+        assert_eq!(s1.stats().changes_sent, 5);
+
+        let mut persisted = s1.persist();
+        persisted[1] = 2;
+        assert!(matches!(
+            State::resume(&persisted),
+            Err(DecodeStateError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            State::resume(&s1.encode()),
+            Err(DecodeStateError::WrongType { .. })
+        ));
+
+        // the bits per entry, the probes and the Bloom steps of a new state
+        let persisted = State::new().persist();
+        assert_eq!(&persisted[13..17], &[10, 7, 0, 0]);
+        for (index, value) in [(13, 0), (14, 0), (16, 4), (16, 40)] {
+            let mut invalid = persisted.clone();
+            invalid[index] = value;
+            assert!(matches!(
+                State::resume(&invalid),
+                Err(DecodeStateError::Parse(_))
+            ));
+        }
+        let mut max_steps = persisted;
+        max_steps[16] = 3;
+        assert!(State::resume(&max_steps).is_ok());
+    }
+
+    #[test]
+    fn polling_over_http_converges() {
+        let mut server = crate::AutoCommit::new();
+        server.put(crate::ROOT, "server", 1).unwrap();
+        server.commit();
+        let mut client = crate::AutoCommit::new();
+        client.put(crate::ROOT, "client", 1).unwrap();
+        client.commit();
+        let mut server = server.document().clone();
+        let mut client = client.document().clone();
+
+        let mut state = State::new();
+        let mut poll = |client: &mut Automerge, server: &mut Automerge| {
+            for round in 1..=5 {
+                let request = state.encode_request(client);
+                let response = respond(server, &request).unwrap();
+                if state.receive_response(client, &response).unwrap() {
+                    return round;
+                }
+            }
+            panic!("failed to sync in 5 round trips");
+        };
+
+        assert!(poll(&mut client, &mut server) <= 3);
+        assert_eq!(client.get_heads(), server.get_heads());
+        assert!(client.get(crate::ROOT, "server").unwrap().is_some());
+
+        let mut tx = client.transaction();
+        tx.put(crate::ROOT, "later", 2).unwrap();
+        tx.commit();
+        poll(&mut client, &mut server);
+        assert_eq!(client.get_heads(), server.get_heads());
+        assert!(server.get(crate::ROOT, "later").unwrap().is_some());
+
+        assert!(matches!(
+            respond(&mut server, b"not a sync message"),
+            Err(PollError::Decode(_))
+        ));
+    }
+
     fn sync(
         a: &mut crate::AutoCommit,
         b: &mut crate::AutoCommit,
@@ -6,8 +6,8 @@ use crate::ChangeHash;
 // These constants correspond to a 1% false positive rate. The values can be changed without
 // breaking compatibility of the network protocol, since the parameters used for a particular
 // Bloom filter are encoded in the wire format.
-const BITS_PER_ENTRY: u32 = 10;
-const NUM_PROBES: u32 = 7;
+pub(crate) const BITS_PER_ENTRY: u32 = 10;
+pub(crate) const NUM_PROBES: u32 = 7;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
 pub struct BloomFilter {
@@ -107,6 +107,14 @@ impl BloomFilter {
             .map(|byte| byte & (1 << (probe & 7)))
     }
 
+    pub fn num_bits_per_entry(&self) -> u32 {
+        self.num_bits_per_entry
+    }
+
+    pub fn num_probes(&self) -> u32 {
+        self.num_probes
+    }
+
     pub fn contains_hash(&self, hash: &ChangeHash) -> bool {
         if self.num_entries == 0 {
             false
@@ -123,9 +131,21 @@ impl BloomFilter {
     }
 
     pub fn from_hashes<H: Borrow<ChangeHash>>(hashes: impl ExactSizeIterator<Item = H>) -> Self {
+        Self::from_hashes_with(hashes, BITS_PER_ENTRY, NUM_PROBES)
+    }
+
+    /// Build a filter using `num_bits_per_entry` bits for each hash and setting `num_probes` bits
+    /// per hash. More bits per entry lower the false positive rate at the cost of a larger
+    /// filter, and for a given size the false positive rate is lowest when `num_probes` is about
+    /// 0.7 times `num_bits_per_entry`.
+    pub fn from_hashes_with<H: Borrow<ChangeHash>>(
+        hashes: impl ExactSizeIterator<Item = H>,
+        num_bits_per_entry: u32,
+        num_probes: u32,
+    ) -> Self {
         let num_entries = hashes.len() as u32;
-        let num_bits_per_entry = BITS_PER_ENTRY;
-        let num_probes = NUM_PROBES;
+        let num_bits_per_entry = num_bits_per_entry.max(1);
+        let num_probes = num_probes.max(1);
         let bits = vec![0; bits_capacity(num_entries, num_bits_per_entry) as usize];
         let mut filter = Self {
             num_entries,
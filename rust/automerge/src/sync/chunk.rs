@@ -0,0 +1,78 @@
+use crate::storage::parse;
+
+use super::ReadMessageError;
+
+const MESSAGE_TYPE_SYNC_CHUNK: u8 = 0x45; // first byte of a sync message chunk, for identification
+
+/// The largest possible chunk header: the type byte followed by two LEB128 encoded u64s
+pub(crate) const MAX_CHUNK_HEADER_LEN: usize = 1 + 10 + 10;
+
+/// How far through a chunked transfer we are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ChunkProgress {
+    /// The number of chunks sent or received so far.
+    pub done: usize,
+    /// The total number of chunks in the message.
+    pub total: usize,
+}
+
+/// Split an encoded sync message into chunks no longer than `max_chunk_len`.
+///
+/// Each chunk is prefixed with its index and the total number of chunks. If `max_chunk_len` is
+/// too small to fit a header and at least one byte of the message then chunks will be
+/// `MAX_CHUNK_HEADER_LEN + 1` bytes long.
+pub(crate) fn split(message: &[u8], max_chunk_len: usize) -> Vec<Vec<u8>> {
+    let payload_len = max_chunk_len.saturating_sub(MAX_CHUNK_HEADER_LEN).max(1);
+    let payloads = message.chunks(payload_len).collect::<Vec<_>>();
+    let total = payloads.len() as u64;
+    payloads
+        .into_iter()
+        .enumerate()
+        .map(|(index, payload)| {
+            let mut buf = vec![MESSAGE_TYPE_SYNC_CHUNK];
+            leb128::write::unsigned(&mut buf, index as u64).unwrap();
+            leb128::write::unsigned(&mut buf, total).unwrap();
+            buf.extend(payload);
+            buf
+        })
+        .collect()
+}
+
+/// A single chunk of a sync message
+pub(crate) struct Chunk<'a> {
+    pub(crate) index: usize,
+    pub(crate) total: usize,
+    pub(crate) payload: &'a [u8],
+}
+
+impl<'a> Chunk<'a> {
+    pub(crate) fn decode(input: &'a [u8]) -> Result<Self, ReadMessageError> {
+        let input = parse::Input::new(input);
+        match Self::parse(input) {
+            Ok((_, chunk)) => Ok(chunk),
+            Err(parse::ParseError::Error(e)) => Err(e),
+            Err(parse::ParseError::Incomplete(_)) => Err(ReadMessageError::NotEnoughInput),
+        }
+    }
+
+    fn parse(input: parse::Input<'a>) -> parse::ParseResult<'a, Self, ReadMessageError> {
+        let (i, message_type) = parse::take1(input)?;
+        if message_type != MESSAGE_TYPE_SYNC_CHUNK {
+            return Err(parse::ParseError::Error(ReadMessageError::WrongType {
+                expected_one_of: vec![MESSAGE_TYPE_SYNC_CHUNK],
+                found: message_type,
+            }));
+        }
+        let (i, index) = parse::leb128_u64(i)?;
+        let (i, total) = parse::leb128_u64(i)?;
+        let (i, payload) = parse::take_rest(i)?;
+        Ok((
+            i,
+            Chunk {
+                index: index as usize,
+                total: total as usize,
+                payload,
+            },
+        ))
+    }
+}
@@ -0,0 +1,77 @@
+use crate::{Automerge, AutomergeError};
+
+use super::{Message, ReadMessageError, State, WireVersion};
+
+/// An error handling a request or response of the half-duplex sync protocol, see [`respond`].
+#[derive(Debug, thiserror::Error)]
+pub enum PollError {
+    #[error("invalid sync payload: {0}")]
+    Decode(#[from] ReadMessageError),
+    #[error(transparent)]
+    Apply(#[from] AutomergeError),
+}
+
+impl State {
+    /// The body of the next request a client polling a server over HTTP sends, see [`respond`].
+    ///
+    /// Unlike [`Automerge::generate_sync_message`] this always returns a payload, as the server
+    /// can only send anything in response to a request. The payload carries our heads, a summary
+    /// of our changes, the changes we need and any changes the server is missing.
+    pub fn encode_request(&mut self, doc: &Automerge) -> Vec<u8> {
+        doc.poll_message(self).encode()
+    }
+
+    /// Apply the body of the server's response to a request made with
+    /// [`Self::encode_request`].
+    ///
+    /// Returns whether we and the server had the same heads once the response was applied, at
+    /// which point the client can stop polling until it has new changes of its own.
+    pub fn receive_response(
+        &mut self,
+        doc: &mut Automerge,
+        response: &[u8],
+    ) -> Result<bool, PollError> {
+        let message = Message::decode(response)?;
+        doc.receive_sync_message(self, message)?;
+        Ok(self.their_heads.as_ref() == Some(&doc.get_heads()))
+    }
+}
+
+/// Handle the body of a request from a client polling a server over HTTP, returning the body of
+/// the response.
+///
+/// This is a half-duplex mode of the sync protocol: the client sends one request made with
+/// [`State::encode_request`], which the server answers with one response, passed to
+/// [`State::receive_response`]. Each payload batches the heads, summary, needs and changes of
+/// one side, so the client repeats the exchange until `receive_response` returns `true`, which
+/// usually takes two or three round trips.
+///
+/// The server keeps no state for its clients. Everything needed to pick up where the previous
+/// request left off is in the request, and the client's [`State`] tracks what has been sent.
+pub fn respond(doc: &mut Automerge, request: &[u8]) -> Result<Vec<u8>, PollError> {
+    let message = Message::decode(request)?;
+    let mut state = State::new();
+    doc.receive_sync_message(&mut state, message)?;
+    Ok(doc.poll_message(&mut state).encode())
+}
+
+impl Automerge {
+    /// The next sync message for `sync_state`, or a message with our heads, summary and needs
+    /// but no changes if there is nothing new to say.
+    fn poll_message(&self, sync_state: &mut State) -> Message {
+        self.generate_sync_message(sync_state).unwrap_or_else(|| {
+            let heads = self.get_heads();
+            let need = self.get_missing_deps(sync_state.their_heads.as_deref().unwrap_or(&[]));
+            let have = vec![self.make_bloom_filter(sync_state, sync_state.shared_heads.clone())];
+            sync_state.stats.messages_sent += 1;
+            Message {
+                heads,
+                need,
+                have,
+                changes: Vec::new(),
+                version: sync_state.wire_version(),
+                supported_versions: WireVersion::SUPPORTED.to_vec(),
+            }
+        })
+    }
+}
@@ -1,10 +1,19 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, VecDeque};
 
-use super::{encode_hashes, BloomFilter};
+use super::bloom::{self, BITS_PER_ENTRY, NUM_PROBES};
+use super::chunk::{Chunk, ChunkProgress};
+use super::{encode_hashes, encode_many, BloomFilter, Message, ReadMessageError, WireVersion};
+use crate::exid::ExId;
 use crate::storage::parse;
 use crate::ChangeHash;
 
 const SYNC_STATE_TYPE: u8 = 0x43; // first byte of an encoded sync state, for identification
+const PERSISTED_SYNC_STATE_TYPE: u8 = 0x44; // first byte of a persisted sync state
+/// The version of the format written by [`State::persist`], which follows the type byte
+const PERSIST_VERSION: u8 = 1;
+
+/// The number of times an adaptive Bloom filter can double in size.
+const MAX_BLOOM_STEPS: u32 = 3;
 
 #[derive(Debug, thiserror::Error)]
 pub enum DecodeError {
@@ -14,6 +23,8 @@ pub enum DecodeError {
     WrongType { expected_one_of: Vec<u8>, found: u8 },
     #[error("not enough input")]
     NotEnoughInput,
+    #[error("unsupported persisted sync state version {0}")]
+    UnsupportedVersion(u8),
 }
 
 impl From<parse::leb128::Error> for DecodeError {
@@ -22,6 +33,12 @@ impl From<parse::leb128::Error> for DecodeError {
     }
 }
 
+impl From<bloom::ParseError> for DecodeError {
+    fn from(e: bloom::ParseError) -> Self {
+        Self::Parse(e.to_string())
+    }
+}
+
 /// The state of synchronisation with a peer.
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
 pub struct State {
@@ -40,6 +57,81 @@ pub struct State {
     /// there are in fact changes to send). If it is `true` then we don't. This flag is cleared
     /// in `receive_sync_message`.
     pub in_flight: bool,
+
+    /// Chunks of the current outgoing message which `generate_sync_chunk` has yet to return
+    pub(crate) outgoing_chunks: VecDeque<Vec<u8>>,
+    pub(crate) send_progress: Option<ChunkProgress>,
+    /// The payloads of the chunks of the current incoming message received so far
+    pub(crate) incoming_chunks: Vec<u8>,
+    pub(crate) receive_progress: Option<ChunkProgress>,
+
+    pub(crate) options: SyncOptions,
+    /// How many times an adaptive Bloom filter has been made more precise
+    pub(crate) bloom_steps: u32,
+    /// The objects whose changes are sent first, see [`Self::set_priority`]
+    pub(crate) priority: Vec<ExId>,
+    pub(crate) max_changes_per_message: Option<usize>,
+    /// The objects the peer wants changes to, see [`Self::with_scope`]
+    pub(crate) scope: Vec<ExId>,
+    /// Whether the peer needs changes beneath our history fence, see [`Self::peer_behind_fence`]
+    pub(crate) behind_fence: bool,
+    /// The versions of the sync message encoding the peer told us it supports
+    pub(crate) their_versions: Vec<WireVersion>,
+    pub(crate) stats: SyncStats,
+}
+
+/// How much has been exchanged with a peer, see [`State::stats`].
+///
+/// Sizes are the encoded sizes of the changes, which make up almost all of a sync message once
+/// there are changes to send.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SyncStats {
+    /// The number of messages generated for the peer.
+    pub messages_sent: u64,
+    /// The number of messages received from the peer.
+    pub messages_received: u64,
+    /// The number of changes sent to the peer.
+    pub changes_sent: u64,
+    /// The number of changes received from the peer.
+    pub changes_received: u64,
+    /// The number of bytes of changes sent to the peer.
+    pub bytes_sent: u64,
+    /// The number of bytes of changes received from the peer.
+    pub bytes_received: u64,
+    /// The number of changes we know the peer is missing but haven't sent yet, for example
+    /// because of the limit set by [`State::set_priority`] or because the previous message is
+    /// still in flight.
+    pub estimated_remaining: usize,
+    /// The number of changes the peer explicitly asked for in its last message. The peer asks
+    /// for changes it knows it is missing, so this staying above zero across round trips means
+    /// the peer isn't getting what it needs.
+    pub last_need: usize,
+}
+
+/// How a [`State`] builds the Bloom filters it sends to the peer.
+///
+/// The filter tells the peer which changes we already have. A false positive makes the peer
+/// think we have a change we don't, so it is not sent and we have to ask for it explicitly, which
+/// costs an extra round trip. The defaults give a false positive rate of about 1%.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SyncOptions {
+    /// The number of bits in the filter for each change it contains, at least 1.
+    pub bloom_bits_per_entry: u32,
+    /// The number of bits set in the filter for each change, at least 1.
+    pub bloom_probes: u32,
+    /// Double the bits per entry and the probes each time we have to ask for changes which were
+    /// not sent because of a false positive, up to three times.
+    pub adaptive_bloom: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            bloom_bits_per_entry: BITS_PER_ENTRY,
+            bloom_probes: NUM_PROBES,
+            adaptive_bloom: false,
+        }
+    }
 }
 
 /// A summary of the changes that the sender of the message already has.
@@ -59,6 +151,136 @@ impl State {
         Default::default()
     }
 
+    pub fn with_options(options: SyncOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// A state for syncing with a peer which only wants the objects `scope` and everything
+    /// inside them, such as a thin client of a very large document.
+    ///
+    /// Only changes which affect the scope are sent, along with any changes they depend on, since
+    /// the peer can't apply a change without its dependencies. How much this saves depends on the
+    /// history: a change always depends on every earlier change by the same actor, so a change in
+    /// scope brings along everything its author did before it. The peer never has all our changes
+    /// and so never has our heads; we stop sending messages once we have nothing more in scope to
+    /// send. The peer doesn't learn the scope from the sync protocol, so the applications have to
+    /// agree on it some other way.
+    pub fn with_scope(scope: Vec<ExId>) -> Self {
+        Self {
+            scope,
+            ..Default::default()
+        }
+    }
+
+    /// The objects changes are sent for, empty if every change is sent.
+    pub fn scope(&self) -> &[ExId] {
+        &self.scope
+    }
+
+    pub fn options(&self) -> SyncOptions {
+        self.options
+    }
+
+    /// Change the options, for example after [`Self::decode`], which does not restore them.
+    pub fn set_options(&mut self, options: SyncOptions) {
+        self.options = options;
+        self.bloom_steps = 0;
+    }
+
+    /// Prepare to resume syncing with the same peer over a new connection.
+    ///
+    /// Messages which were in flight when the old connection dropped may never have arrived, so
+    /// everything we know about the peer's latest state, what we have sent them and any partially
+    /// sent or received chunked message is discarded. The shared heads are kept, so the next
+    /// message only advertises the changes made since we were last in sync rather than the
+    /// whole document. Both peers should reset their state for each other.
+    pub fn reset_for_reconnect(&mut self) {
+        *self = Self {
+            shared_heads: std::mem::take(&mut self.shared_heads),
+            options: self.options,
+            bloom_steps: self.bloom_steps,
+            priority: std::mem::take(&mut self.priority),
+            max_changes_per_message: self.max_changes_per_message,
+            scope: std::mem::take(&mut self.scope),
+            stats: SyncStats {
+                estimated_remaining: 0,
+                last_need: 0,
+                ..self.stats
+            },
+            ..Default::default()
+        };
+    }
+
+    /// Send the changes which touch `objects`, or any object inside them, before any others, at
+    /// most `max_changes_per_message` at a time.
+    ///
+    /// This lets the peer show part of a large document, such as whatever is on screen, without
+    /// waiting for the whole of it to arrive over a slow link. Each message also includes the
+    /// changes that the prioritised ones depend on, so every message can be applied as soon as it
+    /// arrives, and once the prioritised changes are sent the rest follow in later messages.
+    /// Because of those dependencies prioritising only pays off for changes made concurrently
+    /// with the others, such as those from a different peer: a change always brings every
+    /// earlier change by the same actor with it.
+    pub fn set_priority(&mut self, objects: Vec<ExId>, max_changes_per_message: usize) {
+        self.priority = objects;
+        self.max_changes_per_message = Some(max_changes_per_message.max(1));
+    }
+
+    /// Whether the peer's last message asked for changes which were dropped beneath our history
+    /// fence, see [`crate::Automerge::set_history_fence`].
+    ///
+    /// We can't send those changes, so syncing with the peer can't make progress. It needs a copy
+    /// of a document saved with our fence, or a peer which still has the changes, instead.
+    pub fn peer_behind_fence(&self) -> bool {
+        self.behind_fence
+    }
+
+    /// How much has been exchanged with the peer so far, for showing progress and spotting peers
+    /// which are stuck. The totals are kept by [`Self::reset_for_reconnect`] and
+    /// [`Self::persist`] but not by [`Self::encode`].
+    pub fn stats(&self) -> SyncStats {
+        self.stats
+    }
+
+    /// The version of the sync message encoding we send the peer, the highest one we both
+    /// support. This is [`WireVersion::V1`] until the peer tells us which versions it supports.
+    pub fn wire_version(&self) -> WireVersion {
+        WireVersion::negotiate(&WireVersion::SUPPORTED, &self.their_versions)
+    }
+
+    /// Go back to sending every change the peer needs in a single message.
+    pub fn clear_priority(&mut self) {
+        self.priority.clear();
+        self.max_changes_per_message = None;
+    }
+
+    /// Build a Bloom filter of `hashes` using the current options.
+    pub(crate) fn bloom_filter<H, I>(&self, hashes: I) -> BloomFilter
+    where
+        H: std::borrow::Borrow<ChangeHash>,
+        I: ExactSizeIterator<Item = H>,
+    {
+        BloomFilter::from_hashes_with(
+            hashes,
+            self.options
+                .bloom_bits_per_entry
+                .saturating_mul(1 << self.bloom_steps),
+            self.options
+                .bloom_probes
+                .saturating_mul(1 << self.bloom_steps),
+        )
+    }
+
+    /// Record that the peer omitted changes because of a false positive in our filter.
+    pub(crate) fn bloom_false_positive(&mut self) {
+        if self.options.adaptive_bloom {
+            self.bloom_steps = (self.bloom_steps + 1).min(MAX_BLOOM_STEPS);
+        }
+    }
+
     pub fn encode(&self) -> Vec<u8> {
         let mut buf = vec![SYNC_STATE_TYPE];
         encode_hashes(&mut buf, &self.shared_heads);
@@ -94,7 +316,341 @@ impl State {
                 their_have: Some(Vec::new()),
                 sent_hashes: BTreeSet::new(),
                 in_flight: false,
+                outgoing_chunks: VecDeque::new(),
+                send_progress: None,
+                incoming_chunks: Vec::new(),
+                receive_progress: None,
+                options: SyncOptions::default(),
+                bloom_steps: 0,
+                priority: Vec::new(),
+                max_changes_per_message: None,
+                scope: Vec::new(),
+                behind_fence: false,
+                their_versions: Vec::new(),
+                stats: SyncStats::default(),
             },
         ))
     }
+
+    /// Encode everything about the peer this state has learned, unlike [`Self::encode`] which
+    /// only keeps the shared heads, so that a long lived peer can [`Self::resume`] after a
+    /// restart without resending changes the peer already has or asking for ones it already
+    /// asked for.
+    ///
+    /// This includes the peer's heads, needs and Bloom filters, the changes sent to it, whether
+    /// a message is in flight, partially sent or received chunked messages, the options,
+    /// priority, scope and statistics. If the peer may have lost what it was sent, e.g. because
+    /// the connection dropped, call [`Self::reset_for_reconnect`] after resuming.
+    ///
+    /// The format starts with a version, so states persisted by this version of the library can
+    /// be resumed by later ones.
+    pub fn persist(&self) -> Vec<u8> {
+        let mut buf = vec![PERSISTED_SYNC_STATE_TYPE, PERSIST_VERSION];
+        encode_hashes(&mut buf, &self.shared_heads);
+        encode_hash_list(&mut buf, &self.last_sent_heads);
+        encode_option(&mut buf, self.their_heads.as_ref(), |buf, h| {
+            encode_hash_list(buf, h)
+        });
+        encode_option(&mut buf, self.their_need.as_ref(), |buf, h| {
+            encode_hash_list(buf, h)
+        });
+        encode_option(&mut buf, self.their_have.as_ref(), |buf, have| {
+            encode_many(buf, have.iter(), |buf, h| {
+                encode_hash_list(buf, &h.last_sync);
+                encode_bytes(buf, &h.bloom.to_bytes());
+            })
+        });
+        encode_many(&mut buf, self.sent_hashes.iter(), |buf, h| {
+            buf.extend(h.as_bytes())
+        });
+        buf.push(self.in_flight as u8);
+
+        encode_many(&mut buf, self.outgoing_chunks.iter(), |buf, c| {
+            encode_bytes(buf, c)
+        });
+        encode_option(&mut buf, self.send_progress.as_ref(), encode_progress);
+        encode_bytes(&mut buf, &self.incoming_chunks);
+        encode_option(&mut buf, self.receive_progress.as_ref(), encode_progress);
+
+        encode_uint(&mut buf, self.options.bloom_bits_per_entry as u64);
+        encode_uint(&mut buf, self.options.bloom_probes as u64);
+        buf.push(self.options.adaptive_bloom as u8);
+        encode_uint(&mut buf, self.bloom_steps as u64);
+        encode_many(&mut buf, self.priority.iter(), encode_exid);
+        encode_option(&mut buf, self.max_changes_per_message.as_ref(), |buf, n| {
+            encode_uint(buf, *n as u64)
+        });
+        encode_many(&mut buf, self.scope.iter(), encode_exid);
+        buf.push(self.behind_fence as u8);
+        encode_many(&mut buf, self.their_versions.iter(), |buf, v| {
+            encode_uint(buf, v.number())
+        });
+
+        let stats = &self.stats;
+        for n in [
+            stats.messages_sent,
+            stats.messages_received,
+            stats.changes_sent,
+            stats.changes_received,
+            stats.bytes_sent,
+            stats.bytes_received,
+            stats.estimated_remaining as u64,
+            stats.last_need as u64,
+        ] {
+            encode_uint(&mut buf, n);
+        }
+        buf
+    }
+
+    /// Decode a state encoded by [`Self::persist`].
+    pub fn resume(input: &[u8]) -> Result<Self, DecodeError> {
+        let input = parse::Input::new(input);
+        match Self::parse_persisted(input) {
+            Ok((_, state)) => Ok(state),
+            Err(parse::ParseError::Incomplete(_)) => Err(DecodeError::NotEnoughInput),
+            Err(parse::ParseError::Error(e)) => Err(e),
+        }
+    }
+
+    fn parse_persisted(input: parse::Input<'_>) -> parse::ParseResult<'_, Self, DecodeError> {
+        let (i, record_type) = parse::take1(input)?;
+        if record_type != PERSISTED_SYNC_STATE_TYPE {
+            return Err(parse::ParseError::Error(DecodeError::WrongType {
+                expected_one_of: vec![PERSISTED_SYNC_STATE_TYPE],
+                found: record_type,
+            }));
+        }
+        let (i, version) = parse::take1(i)?;
+        if version != PERSIST_VERSION {
+            return Err(parse::ParseError::Error(DecodeError::UnsupportedVersion(
+                version,
+            )));
+        }
+
+        let (i, shared_heads) = parse::length_prefixed(parse::change_hash)(i)?;
+        let (i, last_sent_heads) = parse::length_prefixed(parse::change_hash)(i)?;
+        let (i, their_heads) = parse_option(i, parse::length_prefixed(parse::change_hash))?;
+        let (i, their_need) = parse_option(i, parse::length_prefixed(parse::change_hash))?;
+        let (i, their_have) = parse_option(i, parse::length_prefixed(parse_have))?;
+        let (i, sent_hashes) = parse::length_prefixed(parse::change_hash)(i)?;
+        let (i, in_flight) = parse_bool(i)?;
+
+        let (i, outgoing_chunks) = parse::length_prefixed(|i| {
+            let (i, chunk) = parse::length_prefixed_bytes(i)?;
+            Ok((i, chunk.to_vec()))
+        })(i)?;
+        let (i, send_progress) = parse_option(i, parse_progress)?;
+        let (i, incoming_chunks) = parse::length_prefixed_bytes(i)?;
+        let (i, receive_progress) = parse_option(i, parse_progress)?;
+
+        let (i, bloom_bits_per_entry) = parse::leb128_u32(i)?;
+        let (i, bloom_probes) = parse::leb128_u32(i)?;
+        let (i, adaptive_bloom) = parse_bool(i)?;
+        let (i, bloom_steps) = parse::leb128_u32(i)?;
+        if bloom_bits_per_entry == 0 || bloom_probes == 0 {
+            return Err(parse::ParseError::Error(DecodeError::Parse(
+                "Bloom filters need at least one bit per entry and one probe".into(),
+            )));
+        }
+        if bloom_steps > MAX_BLOOM_STEPS {
+            return Err(parse::ParseError::Error(DecodeError::Parse(format!(
+                "Bloom filters can't double in size {} times",
+                bloom_steps
+            ))));
+        }
+        let (i, priority) = parse::length_prefixed(parse_exid)(i)?;
+        let (i, max_changes_per_message) = parse_option(i, parse::leb128_u64)?;
+        let (i, scope) = parse::length_prefixed(parse_exid)(i)?;
+        let (i, behind_fence) = parse_bool(i)?;
+        let (i, their_versions) = parse::length_prefixed(parse::leb128_u64)(i)?;
+
+        let mut stats = [0; 8];
+        let mut i = i;
+        for n in stats.iter_mut() {
+            let (rest, value) = parse::leb128_u64(i)?;
+            *n = value;
+            i = rest;
+        }
+        let [messages_sent, messages_received, changes_sent, changes_received, bytes_sent, bytes_received, estimated_remaining, last_need] =
+            stats;
+
+        Ok((
+            i,
+            Self {
+                shared_heads,
+                last_sent_heads,
+                their_heads,
+                their_need,
+                their_have,
+                sent_hashes: sent_hashes.into_iter().collect(),
+                in_flight,
+                outgoing_chunks: outgoing_chunks.into_iter().collect(),
+                send_progress,
+                incoming_chunks: incoming_chunks.to_vec(),
+                receive_progress,
+                options: SyncOptions {
+                    bloom_bits_per_entry,
+                    bloom_probes,
+                    adaptive_bloom,
+                },
+                bloom_steps,
+                priority,
+                max_changes_per_message: max_changes_per_message.map(|n| n as usize),
+                scope,
+                behind_fence,
+                // versions added after this one was written are ones we don't support
+                their_versions: their_versions
+                    .into_iter()
+                    .filter_map(WireVersion::from_number)
+                    .collect(),
+                stats: SyncStats {
+                    messages_sent,
+                    messages_received,
+                    changes_sent,
+                    changes_received,
+                    bytes_sent,
+                    bytes_received,
+                    estimated_remaining: estimated_remaining as usize,
+                    last_need: last_need as usize,
+                },
+            },
+        ))
+    }
+
+    /// The progress of the chunked message currently being sent, if any
+    pub fn send_progress(&self) -> Option<ChunkProgress> {
+        self.send_progress
+    }
+
+    /// The progress of the chunked message currently being received, if any
+    pub fn receive_progress(&self) -> Option<ChunkProgress> {
+        self.receive_progress
+    }
+
+    /// Add a chunk produced by `Automerge::generate_sync_chunk` to the message being reassembled.
+    ///
+    /// Returns the message once the last chunk has been received. Chunks must be received in
+    /// order; receiving the first chunk of a message discards any partially received message.
+    pub fn receive_chunk(&mut self, chunk: &[u8]) -> Result<Option<Message>, ReadMessageError> {
+        let chunk = Chunk::decode(chunk)?;
+        if chunk.index >= chunk.total {
+            return Err(ReadMessageError::Parse(format!(
+                "chunk index {} out of range for {} chunks",
+                chunk.index, chunk.total
+            )));
+        }
+        if chunk.index == 0 {
+            self.incoming_chunks.clear();
+            self.receive_progress = Some(ChunkProgress {
+                done: 0,
+                total: chunk.total,
+            });
+        }
+        let progress = match self.receive_progress.as_mut() {
+            Some(p) if p.done == chunk.index && p.total == chunk.total => p,
+            _ => {
+                return Err(ReadMessageError::Parse(format!(
+                    "unexpected chunk {} of {}",
+                    chunk.index, chunk.total
+                )))
+            }
+        };
+        progress.done += 1;
+        self.incoming_chunks.extend(chunk.payload);
+        if progress.done < progress.total {
+            return Ok(None);
+        }
+        self.receive_progress = None;
+        let bytes = std::mem::take(&mut self.incoming_chunks);
+        Message::decode(&bytes).map(Some)
+    }
+}
+
+/// Encode `hashes`, which unlike [`encode_hashes`] don't have to be sorted.
+fn encode_hash_list(buf: &mut Vec<u8>, hashes: &[ChangeHash]) {
+    encode_many(buf, hashes.iter(), |buf, h| buf.extend(h.as_bytes()))
+}
+
+fn encode_uint(buf: &mut Vec<u8>, n: u64) {
+    leb128::write::unsigned(buf, n).unwrap();
+}
+
+fn encode_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    encode_uint(buf, bytes.len() as u64);
+    buf.extend(bytes);
+}
+
+fn encode_option<T, F: Fn(&mut Vec<u8>, &T)>(buf: &mut Vec<u8>, value: Option<&T>, f: F) {
+    match value {
+        Some(value) => {
+            buf.push(1);
+            f(buf, value);
+        }
+        None => buf.push(0),
+    }
+}
+
+fn encode_progress(buf: &mut Vec<u8>, progress: &ChunkProgress) {
+    encode_uint(buf, progress.done as u64);
+    encode_uint(buf, progress.total as u64);
+}
+
+fn encode_exid(buf: &mut Vec<u8>, id: &ExId) {
+    encode_bytes(buf, id.to_string().as_bytes());
+}
+
+fn parse_bool(input: parse::Input<'_>) -> parse::ParseResult<'_, bool, DecodeError> {
+    let (i, b) = parse::take1(input)?;
+    match b {
+        0 => Ok((i, false)),
+        1 => Ok((i, true)),
+        other => Err(parse::ParseError::Error(DecodeError::Parse(format!(
+            "invalid boolean {}",
+            other
+        )))),
+    }
+}
+
+fn parse_option<'a, O, P>(
+    input: parse::Input<'a>,
+    mut parser: P,
+) -> parse::ParseResult<'a, Option<O>, DecodeError>
+where
+    P: FnMut(parse::Input<'a>) -> parse::ParseResult<'a, O, DecodeError>,
+{
+    let (i, present) = parse_bool(input)?;
+    if present {
+        let (i, value) = parser(i)?;
+        Ok((i, Some(value)))
+    } else {
+        Ok((i, None))
+    }
+}
+
+fn parse_have(input: parse::Input<'_>) -> parse::ParseResult<'_, Have, DecodeError> {
+    let (i, last_sync) = parse::length_prefixed(parse::change_hash)(input)?;
+    let (i, bloom_bytes) = parse::length_prefixed_bytes(i)?;
+    let (_, bloom) = BloomFilter::parse(parse::Input::new(bloom_bytes)).map_err(|e| e.lift())?;
+    Ok((i, Have { last_sync, bloom }))
+}
+
+fn parse_progress(input: parse::Input<'_>) -> parse::ParseResult<'_, ChunkProgress, DecodeError> {
+    let (i, done) = parse::leb128_u64(input)?;
+    let (i, total) = parse::leb128_u64(i)?;
+    Ok((
+        i,
+        ChunkProgress {
+            done: done as usize,
+            total: total as usize,
+        },
+    ))
+}
+
+fn parse_exid(input: parse::Input<'_>) -> parse::ParseResult<'_, ExId, DecodeError> {
+    let (i, bytes) = parse::length_prefixed_bytes(input)?;
+    let id = std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| parse::ParseError::Error(DecodeError::Parse("invalid object id".into())))?;
+    Ok((i, id))
 }
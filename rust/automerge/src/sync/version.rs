@@ -0,0 +1,124 @@
+use crate::storage::parse;
+
+use super::ReadMessageError;
+
+/// The first byte of a message in the original encoding, which has no version header
+pub(crate) const MESSAGE_TYPE_SYNC: u8 = 0x42;
+/// The first byte of a message which is followed by a version header. The bytes between this
+/// and [`MESSAGE_TYPE_SYNC`] start encoded and persisted sync states and sync message chunks.
+pub(crate) const MESSAGE_TYPE_SYNC_VERSIONED: u8 = 0x46;
+
+/// A version of the encoding of sync messages.
+///
+/// Peers tell each other which versions they support at the end of every message and send
+/// messages in the highest version both support, see [`WireVersion::negotiate`]. Until we have
+/// heard from a peer we send [`WireVersion::V1`], which every peer understands. Peers which
+/// predate versioning ignore the list of supported versions, and never send one, so we keep
+/// sending them `V1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WireVersion {
+    /// The original encoding, which starts with `0x42`.
+    V1,
+    /// Starts with `0x46` followed by the version number, so that a peer which doesn't
+    /// understand a later version reports an error rather than misreading the message.
+    V2,
+}
+
+impl WireVersion {
+    /// The versions this implementation can read and write, lowest first.
+    pub const SUPPORTED: [WireVersion; 2] = [WireVersion::V1, WireVersion::V2];
+
+    /// The number of this version on the wire.
+    pub fn number(&self) -> u64 {
+        match self {
+            WireVersion::V1 => 1,
+            WireVersion::V2 => 2,
+        }
+    }
+
+    /// The version with the number `number`, if we support it.
+    pub fn from_number(number: u64) -> Option<Self> {
+        Self::SUPPORTED
+            .iter()
+            .copied()
+            .find(|v| v.number() == number)
+    }
+
+    /// The highest version in both `ours` and `theirs`, or [`WireVersion::V1`] if there is none.
+    pub fn negotiate(ours: &[WireVersion], theirs: &[WireVersion]) -> WireVersion {
+        ours.iter()
+            .filter(|v| theirs.contains(v))
+            .max()
+            .copied()
+            .unwrap_or(WireVersion::V1)
+    }
+}
+
+impl Default for WireVersion {
+    fn default() -> Self {
+        WireVersion::V1
+    }
+}
+
+/// Write the type byte and header of a message in `version`.
+pub(crate) fn encode_header(buf: &mut Vec<u8>, version: WireVersion) {
+    match version {
+        WireVersion::V1 => buf.push(MESSAGE_TYPE_SYNC),
+        WireVersion::V2 => {
+            buf.push(MESSAGE_TYPE_SYNC_VERSIONED);
+            leb128::write::unsigned(buf, version.number()).unwrap();
+        }
+    }
+}
+
+pub(crate) fn parse_header(
+    input: parse::Input<'_>,
+) -> parse::ParseResult<'_, WireVersion, ReadMessageError> {
+    let (i, message_type) = parse::take1(input)?;
+    match message_type {
+        MESSAGE_TYPE_SYNC => Ok((i, WireVersion::V1)),
+        MESSAGE_TYPE_SYNC_VERSIONED => {
+            let (i, number) = parse::leb128_u64(i)?;
+            match WireVersion::from_number(number) {
+                Some(version) if version != WireVersion::V1 => Ok((i, version)),
+                _ => Err(parse::ParseError::Error(
+                    ReadMessageError::UnsupportedVersion(number),
+                )),
+            }
+        }
+        other => Err(parse::ParseError::Error(ReadMessageError::WrongType {
+            expected_one_of: vec![MESSAGE_TYPE_SYNC, MESSAGE_TYPE_SYNC_VERSIONED],
+            found: other,
+        })),
+    }
+}
+
+/// Write the versions we support, which follow the changes. Nothing is written if there are
+/// none, so that messages relayed from peers which predate versioning are unchanged.
+pub(crate) fn encode_supported(buf: &mut Vec<u8>, supported: &[WireVersion]) {
+    if supported.is_empty() {
+        return;
+    }
+    leb128::write::unsigned(buf, supported.len() as u64).unwrap();
+    for version in supported {
+        leb128::write::unsigned(buf, version.number()).unwrap();
+    }
+}
+
+/// Read the versions the sender supports, which are missing from messages sent by peers which
+/// predate versioning. Versions we don't know about are skipped.
+pub(crate) fn parse_supported(
+    input: parse::Input<'_>,
+) -> parse::ParseResult<'_, Vec<WireVersion>, ReadMessageError> {
+    if input.is_empty() {
+        return Ok((input, Vec::new()));
+    }
+    let (i, numbers) = parse::length_prefixed(parse::leb128_u64::<ReadMessageError>)(input)?;
+    Ok((
+        i,
+        numbers
+            .into_iter()
+            .filter_map(WireVersion::from_number)
+            .collect(),
+    ))
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use crate::exid::ExId;
+use crate::reserved;
+use crate::types::HASH_SIZE;
+use crate::{Automerge, AutomergeError, ChangeHash, ScalarValue, Value};
+
+/// The key of the root map under which tags are kept, see [`Automerge::tags`].
+pub const TAGS_KEY: &str = "_tags";
+
+/// The heads stored as the value of a tag, which is the hashes one after the other.
+pub(crate) fn encode_heads(heads: &[ChangeHash]) -> ScalarValue {
+    let mut heads = heads.to_vec();
+    heads.sort();
+    heads.dedup();
+    ScalarValue::Bytes(heads.iter().flat_map(|h| h.0).collect())
+}
+
+fn decode_heads(value: &Value<'_>) -> Option<Vec<ChangeHash>> {
+    match value {
+        Value::Scalar(s) => match s.as_ref() {
+            ScalarValue::Bytes(b) if b.len() % HASH_SIZE == 0 => b
+                .chunks(HASH_SIZE)
+                .map(|h| ChangeHash::try_from(h).ok())
+                .collect(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Read the heads of the tag `name` with `get_all`, which is the `get_all` method of a document.
+pub(crate) fn heads_for_tag<'a, F>(
+    name: &str,
+    get_all: F,
+) -> Result<Option<Vec<ChangeHash>>, AutomergeError>
+where
+    F: Fn(&ExId, &str) -> Result<Vec<(Value<'a>, ExId)>, AutomergeError>,
+{
+    // if two peers created the registry concurrently the winning one is consulted first
+    for registry in reserved::registries(TAGS_KEY, &get_all)? {
+        if let Some((value, _)) = get_all(&registry, name)?.pop() {
+            return Ok(decode_heads(&value));
+        }
+    }
+    Ok(None)
+}
+
+/// Read every tag with `get_all` and `keys`, which are the `get_all` and `keys` methods of a
+/// document.
+pub(crate) fn tags<'a, F, K>(
+    get_all: F,
+    keys: K,
+) -> Result<BTreeMap<String, Vec<ChangeHash>>, AutomergeError>
+where
+    F: Fn(&ExId, &str) -> Result<Vec<(Value<'a>, ExId)>, AutomergeError>,
+    K: Fn(&ExId) -> Vec<String>,
+{
+    let mut tags = BTreeMap::new();
+    for registry in reserved::registries(TAGS_KEY, &get_all)? {
+        for name in keys(&registry) {
+            if tags.contains_key(&name) {
+                continue;
+            }
+            if let Some(heads) = get_all(&registry, &name)?
+                .pop()
+                .and_then(|(v, _)| decode_heads(&v))
+            {
+                tags.insert(name, heads);
+            }
+        }
+    }
+    Ok(tags)
+}
+
+impl Automerge {
+    /// The heads tagged `name` with [`crate::transaction::Transactable::tag_heads`], if there is
+    /// such a tag. The heads can be passed to any of the `*_at` methods to read the tagged
+    /// version.
+    ///
+    /// Tags are part of the document, so they are saved and synced along with everything else.
+    /// They live in a registry map under [`TAGS_KEY`] in the root map, keyed by name, each with
+    /// the hashes of its heads as bytes. If two peers tag concurrently with the same name the
+    /// tag is a conflict like any other, and the winner's heads are returned. A value at
+    /// [`TAGS_KEY`] which is not a registry is never read as tags or replaced, see
+    /// [`crate::REGISTRY_MARKER_KEY`].
+    pub fn heads_for_tag(&self, name: &str) -> Result<Option<Vec<ChangeHash>>, AutomergeError> {
+        heads_for_tag(name, |obj, key| self.get_all(obj, key))
+    }
+
+    /// Every tag in the document and the heads it names, see [`Self::heads_for_tag`].
+    pub fn tags(&self) -> Result<BTreeMap<String, Vec<ChangeHash>>, AutomergeError> {
+        tags(
+            |obj, key| self.get_all(obj, key),
+            |obj| self.keys(obj).collect(),
+        )
+    }
+}
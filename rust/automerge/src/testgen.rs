@@ -0,0 +1,470 @@
+//! Generating random documents and histories to benchmark and property test with.
+//!
+//! A [`History`] is a sequence of [`Step`]s taken by a number of peers, each of which either
+//! makes a change to its copy of the document or merges the changes of another peer into it.
+//! Histories are generated from a seed with [`History::generate`], so a failing case can be
+//! reproduced from the seed alone. The random numbers come from ChaCha8, whose output is fixed,
+//! so a seed makes the same history on every platform and with every version of `rand`. And [`History::replay`] turns one into the documents of the
+//! peers.
+//!
+//! Edits address objects by their path from the root rather than by id, and edits which no
+//! longer make sense when they are replayed, e.g. because the object at their path was deleted,
+//! are skipped. This means any part of a history is itself a history, which is what
+//! [`History::shrink`] and [`History::minimize`] rely on to find a smaller history which still
+//! fails a test.
+//!
+//! ```
+//! use automerge::testgen::{Config, History};
+//!
+//! let history = History::generate(7, &Config::default()).unwrap();
+//! let doc = history.merged();
+//! assert_eq!(
+//!     doc.get_heads(),
+//!     History::generate(7, &Config::default()).unwrap().merged().get_heads()
+//! );
+//! ```
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::exid::ExId;
+use crate::transaction::Transactable;
+use crate::{ActorId, AutoCommit, Automerge, ObjType, Prop, ScalarValue, Value, ROOT};
+
+/// The shape of the histories to generate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// The number of peers editing the document
+    pub peers: usize,
+    /// The number of steps to generate
+    pub steps: usize,
+    /// The most edits in one change
+    pub max_edits: usize,
+    /// The chance that a step is a merge rather than a change, between 0 and 1
+    pub merge_probability: f64,
+    /// The number of distinct keys used in maps, fewer keys means more conflicts
+    pub keys: usize,
+    /// The deepest an object can be nested below the root
+    pub max_depth: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            peers: 3,
+            steps: 50,
+            max_edits: 5,
+            merge_probability: 0.25,
+            keys: 8,
+            max_depth: 3,
+        }
+    }
+}
+
+impl Config {
+    /// Check that the config describes histories which can be generated.
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+        if !(0.0..=1.0).contains(&self.merge_probability) {
+            return Err(InvalidConfig::MergeProbability(self.merge_probability));
+        }
+        Ok(())
+    }
+}
+
+/// Why a [`Config`] can't be used to generate histories.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum InvalidConfig {
+    #[error("merge probability {0} is not between 0 and 1")]
+    MergeProbability(f64),
+}
+
+/// An edit to the object at `path` from the root.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    /// Put a scalar in a map, or in the list element at this index
+    Put {
+        path: Vec<Prop>,
+        prop: Prop,
+        value: ScalarValue,
+    },
+    /// Put a new object in a map, or in the list element at this index
+    PutObject {
+        path: Vec<Prop>,
+        prop: Prop,
+        obj_type: ObjType,
+    },
+    /// Insert a scalar into a list
+    Insert {
+        path: Vec<Prop>,
+        index: usize,
+        value: ScalarValue,
+    },
+    /// Insert a new object into a list
+    InsertObject {
+        path: Vec<Prop>,
+        index: usize,
+        obj_type: ObjType,
+    },
+    /// Delete a key of a map or an element of a list
+    Delete { path: Vec<Prop>, prop: Prop },
+    /// Increment a counter
+    Increment {
+        path: Vec<Prop>,
+        prop: Prop,
+        by: i64,
+    },
+    /// Delete `delete` characters of a text object at `index` and insert `text` there
+    SpliceText {
+        path: Vec<Prop>,
+        index: usize,
+        delete: usize,
+        text: String,
+    },
+}
+
+/// One step of a [`History`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// The peer makes a change with these edits
+    Change { peer: usize, edits: Vec<Edit> },
+    /// The peer merges the changes of another into its document
+    Merge { peer: usize, from: usize },
+}
+
+/// The edits and merges of a number of peers, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq)]
+pub struct History {
+    peers: usize,
+    steps: Vec<Step>,
+}
+
+impl History {
+    /// A history of the given steps by `peers` peers. Steps of peers which don't exist are
+    /// skipped when it is replayed.
+    pub fn new(peers: usize, steps: Vec<Step>) -> Self {
+        Self { peers, steps }
+    }
+
+    /// A random history shaped by `config`, which is the same for the same seed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `config` is invalid, see [`Config::validate`].
+    pub fn generate(seed: u64, config: &Config) -> Result<Self, InvalidConfig> {
+        config.validate()?;
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let peers = config.peers.max(1);
+        // the documents are kept up to date so that edits are mostly to objects which exist
+        let mut docs = Self::peers(peers);
+        let mut steps = Vec::with_capacity(config.steps);
+        for _ in 0..config.steps {
+            let peer = rng.gen_range(0..peers);
+            let step = if peers > 1 && rng.gen_bool(config.merge_probability) {
+                let from = (peer + rng.gen_range(1..peers)) % peers;
+                Step::Merge { peer, from }
+            } else {
+                let edits = (0..rng.gen_range(1..=config.max_edits.max(1)))
+                    .filter_map(|_| {
+                        let edit = random_edit(&mut rng, &docs[peer], config)?;
+                        apply(&mut docs[peer], &edit);
+                        Some(edit)
+                    })
+                    .collect();
+                docs[peer].commit();
+                Step::Change { peer, edits }
+            };
+            if let Step::Merge { .. } = step {
+                take_step(&mut docs, &step);
+            }
+            steps.push(step);
+        }
+        Ok(Self { peers, steps })
+    }
+
+    /// The number of peers.
+    pub fn peer_count(&self) -> usize {
+        self.peers
+    }
+
+    /// The steps of the history in order.
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// The documents of each peer once every step is taken. The actor of each peer is its
+    /// index, so replaying the same history always makes the same changes.
+    pub fn replay(&self) -> Vec<Automerge> {
+        let mut docs = Self::peers(self.peers);
+        for step in &self.steps {
+            take_step(&mut docs, step);
+        }
+        docs.into_iter().map(|mut d| d.document().clone()).collect()
+    }
+
+    /// A document with the changes of every peer.
+    pub fn merged(&self) -> Automerge {
+        let mut docs = self.replay().into_iter();
+        let mut merged = docs.next().unwrap_or_default();
+        for mut doc in docs {
+            // the peers share the same config and their changes are valid
+            merged.merge(&mut doc).unwrap();
+        }
+        merged
+    }
+
+    /// Smaller histories to try in place of this one, biggest cuts first: without whole runs
+    /// of steps, without single steps, and then with fewer edits in a change.
+    pub fn shrink(&self) -> impl Iterator<Item = History> + '_ {
+        let len = self.steps.len();
+        let chunks = std::iter::successors(Some(len), |size| (*size > 1).then(|| size / 2))
+            .filter(|size| *size > 0)
+            .flat_map(move |size| (0..len / size).map(move |i| (i * size, size)));
+        let without_steps = chunks.map(move |(start, size)| {
+            let mut steps = self.steps.clone();
+            steps.drain(start..start + size);
+            History::new(self.peers, steps)
+        });
+        let without_edits = self
+            .steps
+            .iter()
+            .enumerate()
+            .flat_map(move |(i, step)| {
+                let edits = match step {
+                    Step::Change { edits, .. } => edits.len(),
+                    Step::Merge { .. } => 0,
+                };
+                (0..edits).map(move |j| (i, j))
+            })
+            .map(move |(i, j)| {
+                let mut steps = self.steps.clone();
+                if let Step::Change { edits, .. } = &mut steps[i] {
+                    edits.remove(j);
+                }
+                History::new(self.peers, steps)
+            });
+        without_steps.chain(without_edits)
+    }
+
+    /// The smallest history found by shrinking this one for which `fails` still returns true,
+    /// which it must do for this history. This is greedy, so the result is a local minimum: no
+    /// single candidate of [`Self::shrink`] fails.
+    pub fn minimize<F>(self, mut fails: F) -> History
+    where
+        F: FnMut(&History) -> bool,
+    {
+        let mut current = self;
+        loop {
+            let smaller = current.shrink().find(|h| fails(h));
+            match smaller {
+                Some(smaller) => current = smaller,
+                None => return current,
+            }
+        }
+    }
+
+    fn peers(peers: usize) -> Vec<AutoCommit> {
+        (0..peers)
+            .map(|i| AutoCommit::new().with_actor(ActorId::from(&(i as u32).to_be_bytes()[..])))
+            .collect()
+    }
+}
+
+/// A random document of the changes of every peer of a history generated from `seed`.
+///
+/// # Errors
+///
+/// Returns an error if `config` is invalid, see [`Config::validate`].
+pub fn generate_document(seed: u64, config: &Config) -> Result<Automerge, InvalidConfig> {
+    Ok(History::generate(seed, config)?.merged())
+}
+
+fn take_step(docs: &mut [AutoCommit], step: &Step) {
+    match step {
+        Step::Change { peer, edits } => {
+            if let Some(doc) = docs.get_mut(*peer) {
+                for edit in edits {
+                    apply(doc, edit);
+                }
+                doc.commit();
+            }
+        }
+        Step::Merge { peer, from } => {
+            if *peer < docs.len() && *from < docs.len() && peer != from {
+                let mut other = docs[*from].clone();
+                docs[*peer].merge(&mut other).unwrap();
+            }
+        }
+    }
+}
+
+/// The object at `path` and its type, if there is one.
+fn resolve(doc: &AutoCommit, path: &[Prop]) -> Option<(ExId, ObjType)> {
+    let mut obj = (ROOT, ObjType::Map);
+    for prop in path {
+        match doc.get(&obj.0, prop.clone()).ok()?? {
+            (Value::Object(obj_type), id) => obj = (id, obj_type),
+            _ => return None,
+        }
+    }
+    Some(obj)
+}
+
+/// Apply `edit` to `doc`, skipping it if it can't be applied.
+fn apply(doc: &mut AutoCommit, edit: &Edit) {
+    let path = match edit {
+        Edit::Put { path, .. }
+        | Edit::PutObject { path, .. }
+        | Edit::Insert { path, .. }
+        | Edit::InsertObject { path, .. }
+        | Edit::Delete { path, .. }
+        | Edit::Increment { path, .. }
+        | Edit::SpliceText { path, .. } => path,
+    };
+    let obj = match resolve(doc, path) {
+        Some((obj, _)) => obj,
+        None => return,
+    };
+    // any of these may fail if the history has been shrunk, which is fine
+    let _ = match edit {
+        Edit::Put { prop, value, .. } => doc.put(&obj, prop.clone(), value.clone()),
+        Edit::PutObject { prop, obj_type, .. } => {
+            doc.put_object(&obj, prop.clone(), *obj_type).map(|_| ())
+        }
+        Edit::Insert { index, value, .. } => doc.insert(&obj, *index, value.clone()),
+        Edit::InsertObject {
+            index, obj_type, ..
+        } => doc.insert_object(&obj, *index, *obj_type).map(|_| ()),
+        Edit::Delete { prop, .. } => doc.delete(&obj, prop.clone()),
+        Edit::Increment { prop, by, .. } => doc.increment(&obj, prop.clone(), *by),
+        Edit::SpliceText {
+            index,
+            delete,
+            text,
+            ..
+        } => doc.splice_text(&obj, *index, *delete, text),
+    };
+}
+
+/// A random edit which applies to `doc`, or `None` if the object chosen can't take one.
+fn random_edit<R: Rng>(rng: &mut R, doc: &AutoCommit, config: &Config) -> Option<Edit> {
+    let (path, obj, obj_type) = random_object(rng, doc, config.max_depth);
+    let nest = path.len() < config.max_depth;
+    match obj_type {
+        ObjType::Map | ObjType::Table => {
+            let prop = Prop::Map(format!("k{}", rng.gen_range(0..config.keys.max(1))));
+            let existing = doc.get(&obj, prop.clone()).ok()?;
+            Some(match rng.gen_range(0..10) {
+                0 | 1 if existing.is_some() => Edit::Delete { path, prop },
+                2 if nest => Edit::PutObject {
+                    path,
+                    prop,
+                    obj_type: random_obj_type(rng),
+                },
+                3 if matches!(&existing, Some((Value::Scalar(s), _)) if s.is_counter()) => {
+                    Edit::Increment {
+                        path,
+                        prop,
+                        by: rng.gen_range(-10..=10),
+                    }
+                }
+                _ => Edit::Put {
+                    path,
+                    prop,
+                    value: random_scalar(rng),
+                },
+            })
+        }
+        ObjType::List => {
+            let len = doc.length(&obj);
+            Some(match rng.gen_range(0..10) {
+                0 | 1 if len > 0 => Edit::Delete {
+                    path,
+                    prop: Prop::Seq(rng.gen_range(0..len)),
+                },
+                2 if len > 0 => Edit::Put {
+                    path,
+                    prop: Prop::Seq(rng.gen_range(0..len)),
+                    value: random_scalar(rng),
+                },
+                3 if nest => Edit::InsertObject {
+                    path,
+                    index: rng.gen_range(0..=len),
+                    obj_type: random_obj_type(rng),
+                },
+                _ => Edit::Insert {
+                    path,
+                    index: rng.gen_range(0..=len),
+                    value: random_scalar(rng),
+                },
+            })
+        }
+        ObjType::Text => {
+            let len = doc.length(&obj);
+            let index = rng.gen_range(0..=len);
+            let delete = rng.gen_range(0..=(len - index).min(3));
+            let text = (0..rng.gen_range(0..5))
+                .map(|_| rng.gen_range(b'a'..=b'z') as char)
+                .collect::<String>();
+            (delete > 0 || !text.is_empty()).then(|| Edit::SpliceText {
+                path,
+                index,
+                delete,
+                text,
+            })
+        }
+    }
+}
+
+/// A random object of `doc`, found by walking down from the root, with its path.
+fn random_object<R: Rng>(
+    rng: &mut R,
+    doc: &AutoCommit,
+    max_depth: usize,
+) -> (Vec<Prop>, ExId, ObjType) {
+    let mut path = Vec::new();
+    let mut obj = (ROOT, ObjType::Map);
+    while path.len() < max_depth && rng.gen_bool(0.6) {
+        let props = match obj.1 {
+            ObjType::Map | ObjType::Table => doc.keys(&obj.0).map(Prop::Map).collect(),
+            ObjType::List => (0..doc.length(&obj.0)).map(Prop::Seq).collect(),
+            ObjType::Text => Vec::new(),
+        };
+        // children are looked up with `get`, like `resolve` does, so that the edit is made to the
+        // object it was chosen for when a prop has conflicting values
+        let children = props
+            .into_iter()
+            .filter_map(|prop| match doc.get(&obj.0, prop.clone()).ok()?? {
+                (Value::Object(t), id) => Some((prop, id, t)),
+                (Value::Scalar(_), _) => None,
+            })
+            .collect::<Vec<_>>();
+        if children.is_empty() {
+            break;
+        }
+        let (prop, id, obj_type) = children[rng.gen_range(0..children.len())].clone();
+        path.push(prop);
+        obj = (id, obj_type);
+    }
+    (path, obj.0, obj.1)
+}
+
+fn random_obj_type<R: Rng>(rng: &mut R) -> ObjType {
+    match rng.gen_range(0..3) {
+        0 => ObjType::Map,
+        1 => ObjType::List,
+        _ => ObjType::Text,
+    }
+}
+
+fn random_scalar<R: Rng>(rng: &mut R) -> ScalarValue {
+    match rng.gen_range(0..8) {
+        0 => ScalarValue::Str(format!("s{}", rng.gen_range(0..100)).into()),
+        1 => ScalarValue::Int(rng.gen_range(-1000..1000)),
+        2 => ScalarValue::Uint(rng.gen_range(0..1000)),
+        3 => ScalarValue::F64(rng.gen_range(-1.0..1.0)),
+        4 => ScalarValue::counter(rng.gen_range(0..10)),
+        5 => ScalarValue::Timestamp(rng.gen_range(0..1_000_000)),
+        6 => ScalarValue::Boolean(rng.gen()),
+        _ => ScalarValue::Null,
+    }
+}
@@ -0,0 +1,90 @@
+use std::ops::Range;
+
+use crate::exid::ExId;
+use crate::types::OpId;
+use crate::{ActorId, Automerge, AutomergeError, ChangeHash};
+
+/// A run of characters of a text object written by the same change: the range of their
+/// indexes, the actor which made the change and the change's hash.
+pub type TextAttribution = (Range<usize>, ActorId, ChangeHash);
+
+impl Automerge {
+    /// Who wrote each character of the text object `obj`.
+    ///
+    /// Each character is attributed to the change which inserted it, or which last overwrote
+    /// it. Adjacent characters from the same change are grouped into a single span, so the spans
+    /// cover the whole text in order.
+    pub fn attribute_text<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+    ) -> Result<Vec<TextAttribution>, AutomergeError> {
+        let chars = self
+            .list_range(obj.as_ref(), ..)
+            .map(|(_, _, id)| id)
+            .collect::<Vec<_>>();
+        Ok(self.attribute(chars, |_| true))
+    }
+
+    /// Historical version of [`Self::attribute_text`].
+    pub fn attribute_text_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Vec<TextAttribution>, AutomergeError> {
+        self.clock_at(heads)?;
+        let chars = self
+            .list_range_at(obj.as_ref(), .., heads)
+            .map(|(_, _, id)| id)
+            .collect::<Vec<_>>();
+        Ok(self.attribute(chars, |_| true))
+    }
+
+    /// The characters of the text object `obj` as of `after` which were written since
+    /// `before`, like `git blame` restricted to a range of commits.
+    ///
+    /// The ranges are indexes into the text as of `after`. Characters which were already there
+    /// as of `before` are not included, so the spans may have gaps between them.
+    pub fn attribute_text_between<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        before: &[ChangeHash],
+        after: &[ChangeHash],
+    ) -> Result<Vec<TextAttribution>, AutomergeError> {
+        let before = self.clock_at(before)?;
+        self.clock_at(after)?;
+        let chars = self
+            .list_range_at(obj.as_ref(), .., after)
+            .map(|(_, _, id)| id)
+            .collect::<Vec<_>>();
+        Ok(self.attribute(chars, |op| !before.covers(&op)))
+    }
+
+    /// Group `chars`, the op ids of the visible characters, into spans by change, skipping
+    /// those whose op fails `include`.
+    fn attribute<F>(&self, chars: Vec<ExId>, include: F) -> Vec<TextAttribution>
+    where
+        F: Fn(OpId) -> bool,
+    {
+        let mut spans: Vec<TextAttribution> = Vec::new();
+        for (index, id) in chars.into_iter().enumerate() {
+            let op = match self.exid_to_opid(&id) {
+                Some(op) => op,
+                None => continue,
+            };
+            if !include(op) {
+                continue;
+            }
+            let change = match self.change_index_for_op(op) {
+                Some(i) => &self.history[i],
+                None => continue,
+            };
+            match spans.last_mut() {
+                Some((range, _, hash)) if range.end == index && *hash == change.hash() => {
+                    range.end += 1;
+                }
+                _ => spans.push((index..index + 1, change.actor_id().clone(), change.hash())),
+            }
+        }
+        spans
+    }
+}
@@ -0,0 +1,270 @@
+//! Computing the edits which turn one text into another, for
+//! [`crate::transaction::Transactable::update_text`].
+//!
+//! After trimming the common prefix and suffix small texts are diffed character by character. For
+//! large texts we first diff the lines, which is cheap because there are far fewer lines than
+//! characters, and then diff the characters of each changed block of lines. With the `rayon`
+//! feature enabled the blocks are diffed in parallel.
+//!
+//! The diff gives up and replaces the whole region if the texts are too different for it to find
+//! a minimal diff quickly; the result is always correct, just not always minimal.
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Texts with a changed region longer than this many characters are diffed line by line first.
+const LINE_DIFF_THRESHOLD: usize = 10_000;
+
+/// The largest edit distance we search for when diffing characters.
+const MAX_CHAR_EDITS: usize = 1_000;
+
+/// The largest edit distance we search for when diffing lines.
+const MAX_LINE_EDITS: usize = 1_000;
+
+/// A replacement of `old` characters of the old text with `new` characters of the new text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Edit {
+    pub(crate) old: Range<usize>,
+    pub(crate) new: Range<usize>,
+}
+
+/// The edits which turn `old` into `new`, in ascending order of position.
+pub(crate) fn diff(old: &[char], new: &[char]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = prefix..old.len() - suffix;
+    let new_mid = prefix..new.len() - suffix;
+    if old_mid.is_empty() && new_mid.is_empty() {
+        return Vec::new();
+    }
+    let whole = Edit {
+        old: old_mid.clone(),
+        new: new_mid.clone(),
+    };
+    if old_mid.len() + new_mid.len() <= LINE_DIFF_THRESHOLD {
+        return char_diff(old, new, whole);
+    }
+    let old_lines = lines(old, old_mid);
+    let new_lines = lines(new, new_mid);
+    let mut ids = HashMap::new();
+    let mut intern = |line: &Range<usize>, text: &[char]| {
+        let next = ids.len();
+        *ids.entry(text[line.clone()].to_vec()).or_insert(next)
+    };
+    let old_ids = old_lines.iter().map(|l| intern(l, old)).collect::<Vec<_>>();
+    let new_ids = new_lines.iter().map(|l| intern(l, new)).collect::<Vec<_>>();
+    let blocks = match myers(&old_ids, &new_ids, MAX_LINE_EDITS) {
+        Some(blocks) => blocks,
+        None => return vec![whole],
+    };
+    let blocks = blocks
+        .into_iter()
+        .map(|block| Edit {
+            old: span(&old_lines, block.old, whole.old.start),
+            new: span(&new_lines, block.new, whole.new.start),
+        })
+        .collect::<Vec<_>>();
+    diff_blocks(old, new, blocks)
+}
+
+#[cfg(feature = "rayon")]
+fn diff_blocks(old: &[char], new: &[char], blocks: Vec<Edit>) -> Vec<Edit> {
+    use rayon::prelude::*;
+    blocks
+        .into_par_iter()
+        .flat_map_iter(|block| char_diff(old, new, block))
+        .collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn diff_blocks(old: &[char], new: &[char], blocks: Vec<Edit>) -> Vec<Edit> {
+    blocks
+        .into_iter()
+        .flat_map(|block| char_diff(old, new, block))
+        .collect()
+}
+
+/// Diff the characters in `region`, falling back to replacing all of it.
+fn char_diff(old: &[char], new: &[char], region: Edit) -> Vec<Edit> {
+    match myers(
+        &old[region.old.clone()],
+        &new[region.new.clone()],
+        MAX_CHAR_EDITS,
+    ) {
+        Some(edits) => edits
+            .into_iter()
+            .map(|e| Edit {
+                old: e.old.start + region.old.start..e.old.end + region.old.start,
+                new: e.new.start + region.new.start..e.new.end + region.new.start,
+            })
+            .collect(),
+        None => vec![region],
+    }
+}
+
+/// The ranges of the lines in `range` of `text`, each including its trailing newline.
+fn lines(text: &[char], range: Range<usize>) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = range.start;
+    for i in range.clone() {
+        if text[i] == '\n' {
+            lines.push(start..i + 1);
+            start = i + 1;
+        }
+    }
+    if start < range.end {
+        lines.push(start..range.end);
+    }
+    lines
+}
+
+/// The range of characters covered by the lines `lines[range]`.
+fn span(lines: &[Range<usize>], range: Range<usize>, start_of_first: usize) -> Range<usize> {
+    if range.is_empty() {
+        let pos = match range.start.checked_sub(1) {
+            Some(prev) => lines[prev].end,
+            None => start_of_first,
+        };
+        pos..pos
+    } else {
+        lines[range.start].start..lines[range.end - 1].end
+    }
+}
+
+/// Myers' O(ND) diff, returning the blocks of `a` and `b` which differ or `None` if more than
+/// `max_edits` insertions and deletions are needed.
+fn myers<T: PartialEq>(a: &[T], b: &[T], max_edits: usize) -> Option<Vec<Edit>> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = (a.len() + b.len()).min(max_edits) as isize;
+    // `v[k + offset]` is the furthest x reached on diagonal k
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    // the diagonals -(d + 1)..=(d + 1) of `v` before each round d, for backtracking
+    let mut trace = Vec::new();
+    for d in 0..=max {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        let mut k = -d;
+        while k <= d {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m));
+            }
+            k += 2;
+        }
+    }
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Edit> {
+    let (mut x, mut y) = (n, m);
+    let mut edits: Vec<Edit> = Vec::new();
+    let mut push = |old: Range<isize>, new: Range<isize>| {
+        let old = old.start as usize..old.end as usize;
+        let new = new.start as usize..new.end as usize;
+        // walking backwards, so merge with the edit immediately after this one
+        match edits.last_mut() {
+            Some(last) if last.old.start == old.end && last.new.start == new.end => {
+                last.old.start = old.start;
+                last.new.start = new.start;
+            }
+            _ => edits.push(Edit { old, new }),
+        }
+    };
+    for d in (1..trace.len() as isize).rev() {
+        let v = &trace[d as usize];
+        let offset = d + 1;
+        let k = x - y;
+        let i = (k + offset) as usize;
+        let prev_k = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+        }
+        if x == prev_x {
+            push(x..x, prev_y..y);
+        } else {
+            push(prev_x..x, y..y);
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    edits.reverse();
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(old: &[char], new: &[char], edits: &[Edit]) -> String {
+        let mut result = old.to_vec();
+        for edit in edits.iter().rev() {
+            result.splice(edit.old.clone(), new[edit.new.clone()].iter().cloned());
+        }
+        result.into_iter().collect()
+    }
+
+    fn check(old: &str, new: &str) -> Vec<Edit> {
+        let old = old.chars().collect::<Vec<_>>();
+        let new_chars = new.chars().collect::<Vec<_>>();
+        let edits = diff(&old, &new_chars);
+        assert_eq!(apply(&old, &new_chars, &edits), new);
+        edits
+    }
+
+    #[test]
+    fn small_diffs() {
+        assert!(check("hello", "hello").is_empty());
+        assert_eq!(
+            check("hello world", "hello brave world"),
+            vec![Edit {
+                old: 6..6,
+                new: 6..12
+            }]
+        );
+        assert_eq!(check("abcabba", "cbabac").len(), 4);
+        check("", "something");
+        check("something", "");
+        check("héllo wörld", "hello world!");
+    }
+
+    #[test]
+    fn large_diffs() {
+        let old = (0..5000)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>();
+        let new = old
+            .replace("line 100\n", "line one hundred\n")
+            .replace("line 4000\n", "")
+            + "the end";
+        let edits = check(&old, &new);
+        assert!(edits.len() <= 4);
+
+        let unrelated = (0..5000)
+            .map(|i| format!("{} other\n", i * 7))
+            .collect::<String>();
+        check(&old, &unrelated);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::exid::ExId;
+use crate::transaction::Transactable;
+use crate::{Automerge, AutomergeError, ChangeHash, Cursor, ObjType};
+
+/// A change to make to an editor's buffer, returned by [`TextSession::reconcile`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub index: usize,
+    pub delete: usize,
+    pub insert: String,
+}
+
+/// Keeps an editor's buffer in step with a text object.
+///
+/// Input from the user is applied to the session's copy of the text with [`Self::input`], which
+/// doesn't touch the document, so the editor can echo it immediately. From time to time, and
+/// whenever changes from other peers have been applied to the document, [`Self::reconcile`]
+/// makes the pending input in the document and returns the edits which bring the editor's buffer
+/// up to date with everything else. Pending input is anchored to the characters around it, so it
+/// lands in the right place even if the document has changed underneath it, and the caret stays
+/// next to the same character rather than at the same index.
+#[derive(Debug, Clone)]
+pub struct TextSession {
+    obj: ExId,
+    /// The heads the text was last reconciled at
+    heads: Vec<ChangeHash>,
+    /// The text as the editor shows it
+    text: Vec<char>,
+    caret: usize,
+    /// Splices made by the user since the last reconcile
+    pending: Vec<(usize, usize, String)>,
+}
+
+impl TextSession {
+    /// Start a session for the text object `obj`, with the caret at the start.
+    pub fn new<O: AsRef<ExId>>(doc: &Automerge, obj: O) -> Result<Self, AutomergeError> {
+        let obj = obj.as_ref().clone();
+        let text = doc.text(&obj)?.chars().collect();
+        Ok(Self {
+            obj,
+            heads: doc.get_heads(),
+            text,
+            caret: 0,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn obj(&self) -> &ExId {
+        &self.obj
+    }
+
+    /// The text as the editor should show it, including input which hasn't been reconciled.
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    /// Move the caret, e.g. when the user clicks somewhere else.
+    pub fn set_caret(&mut self, caret: usize) -> Result<(), AutomergeError> {
+        if caret > self.text.len() {
+            return Err(AutomergeError::InvalidIndex(caret));
+        }
+        self.caret = caret;
+        Ok(())
+    }
+
+    /// Replace `delete` characters at `index` with `insert`, and leave the caret after the
+    /// inserted text.
+    pub fn input(
+        &mut self,
+        index: usize,
+        delete: usize,
+        insert: &str,
+    ) -> Result<(), AutomergeError> {
+        if index + delete > self.text.len() {
+            return Err(AutomergeError::InvalidIndex(index + delete));
+        }
+        let inserted = insert.chars().collect::<Vec<_>>();
+        self.caret = index + inserted.len();
+        self.text.splice(index..index + delete, inserted);
+        self.pending.push((index, delete, insert.to_string()));
+        Ok(())
+    }
+
+    /// Make the pending input in the document, in a single change, and return the edits which
+    /// turn the editor's buffer into the text of the document.
+    ///
+    /// The edits are in order, each index taking into account the edits before it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::MissingHash`] if `doc` doesn't have the changes the session was
+    /// last reconciled at, and [`AutomergeError::NotAnObject`] or
+    /// [`AutomergeError::InvalidIndex`] if the text object is not in `doc` or its text at those
+    /// changes is not the text the pending input was made to, e.g. because `doc` is not the
+    /// document the session was started with. The pending input is kept, and nothing is made in
+    /// the document.
+    pub fn reconcile(&mut self, doc: &mut Automerge) -> Result<Vec<TextEdit>, AutomergeError> {
+        if let Some(missing) = self.heads.iter().find(|h| !doc.has_change(h)) {
+            return Err(AutomergeError::MissingHash(*missing));
+        }
+        if doc.object_type(&self.obj) != Some(ObjType::Text) {
+            return Err(AutomergeError::NotAnObject);
+        }
+        // the ids of the characters of `self.text`, taken from the text as it was at the last
+        // reconcile and filled in as the pending input is made
+        let mut ids = doc
+            .list_range_at(&self.obj, .., &self.heads)
+            .map(|(_, _, id)| Some(id))
+            .collect::<Vec<_>>();
+        let mut len = ids.len();
+        for (index, delete, insert) in &self.pending {
+            if index + delete > len {
+                return Err(AutomergeError::InvalidIndex(index + delete));
+            }
+            len = len - delete + insert.chars().count();
+        }
+        if self.caret > len {
+            return Err(AutomergeError::InvalidIndex(self.caret));
+        }
+
+        let mut tx = doc.transaction();
+        for (index, delete, insert) in std::mem::take(&mut self.pending) {
+            for id in ids.drain(index..index + delete).flatten() {
+                // the character may have been deleted by someone else already
+                let pos = self.position(tx.doc, Some(id.clone()))?;
+                if pos > 0 && tx.get(&self.obj, pos - 1)?.map(|(_, i)| i) == Some(id) {
+                    tx.delete(&self.obj, pos - 1)?;
+                }
+            }
+            let pos = self.position(tx.doc, index.checked_sub(1).and_then(|i| ids[i].clone()))?;
+            tx.splice_text(&self.obj, pos, 0, &insert)?;
+            let len = insert.chars().count();
+            let new_ids = tx
+                .list_range(&self.obj, pos..pos + len)
+                .map(|(_, _, id)| Some(id))
+                .collect::<Vec<_>>();
+            ids.splice(index..index, new_ids);
+        }
+        let caret_after = self.caret.checked_sub(1).and_then(|i| ids[i].clone());
+        if tx.inner.as_ref().map(|t| t.pending_ops()).unwrap_or(0) > 0 {
+            tx.commit();
+        } else {
+            tx.rollback();
+        }
+
+        let text = doc.text(&self.obj)?.chars().collect::<Vec<_>>();
+        let mut offset = 0isize;
+        let edits = crate::text_diff::diff(&self.text, &text)
+            .into_iter()
+            .map(|e| {
+                let edit = TextEdit {
+                    index: (e.old.start as isize + offset) as usize,
+                    delete: e.old.len(),
+                    insert: text[e.new.clone()].iter().collect(),
+                };
+                offset += e.new.len() as isize - e.old.len() as isize;
+                edit
+            })
+            .collect();
+        self.caret = self.position(doc, caret_after).unwrap_or(0);
+        self.text = text;
+        self.heads = doc.get_heads();
+        Ok(edits)
+    }
+
+    /// The index just after the character `after`, or the start of the text for `None`.
+    fn position(&self, doc: &Automerge, after: Option<ExId>) -> Result<usize, AutomergeError> {
+        doc.cursor_position(&Cursor::new(self.obj.clone(), after))
+    }
+}
@@ -0,0 +1,75 @@
+use smol_str::SmolStr;
+
+use crate::exid::ExId;
+use crate::query::{self, ElemIdPos};
+use crate::types::{ElemId, Op, OpType};
+use crate::{Automerge, AutomergeError, ScalarValue};
+
+/// One element of a text object, returned by [`Automerge::text_spans`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextSpan {
+    /// The text of the element, which is usually a single character, or `U+FFFC` if it isn't a
+    /// string, as in [`Automerge::text`]
+    pub text: SmolStr,
+    /// The id of the element, which stays the same however the text around it changes
+    pub id: ExId,
+}
+
+/// An iterator over the elements of a text object, see [`Automerge::text_spans`].
+#[derive(Debug)]
+pub struct TextSpans<'a> {
+    doc: &'a Automerge,
+    ops: std::vec::IntoIter<Op>,
+}
+
+impl<'a> Iterator for TextSpans<'a> {
+    type Item = TextSpan;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let op = self.ops.next()?;
+        let text = match &op.action {
+            OpType::Put(ScalarValue::Str(s)) => s.clone(),
+            _ => SmolStr::new("\u{fffc}"),
+        };
+        let id = self.doc.id_to_exid(op.elemid()?.0);
+        Some(TextSpan { text, id })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ops.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for TextSpans<'a> {}
+
+impl Automerge {
+    /// The elements of the text object `obj` in order, each with the id of the element.
+    ///
+    /// Concatenating the text of the spans gives [`Self::text`]. Element ids don't change when
+    /// text is inserted or deleted around them, so an editor can anchor decorations and comments
+    /// to the ids of the characters they cover and find where they are now with
+    /// [`Self::text_index_of`].
+    pub fn text_spans<O: AsRef<ExId>>(&self, obj: O) -> Result<TextSpans<'_>, AutomergeError> {
+        let obj = self.exid_to_obj(obj.as_ref())?;
+        let query = self.ops.search(&obj, query::ListVals::new());
+        Ok(TextSpans {
+            doc: self,
+            ops: query.ops.into_iter(),
+        })
+    }
+
+    /// The current index of the element `id` of the text or list object `obj`, as given by
+    /// [`Self::text_spans`], or `None` if it has been deleted.
+    pub fn text_index_of<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        id: &ExId,
+    ) -> Result<Option<usize>, AutomergeError> {
+        let obj = self.exid_to_obj(obj.as_ref())?;
+        let elem = match self.exid_to_opid(id) {
+            Some(elem) => ElemId(elem),
+            None => return Ok(None),
+        };
+        Ok(self.ops.search(&obj, ElemIdPos::new(elem)).index())
+    }
+}
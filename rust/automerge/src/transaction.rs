@@ -1,4 +1,5 @@
 mod commit;
+mod dry_run;
 mod inner;
 mod manual_transaction;
 pub(crate) mod observation;
@@ -6,6 +7,8 @@ mod result;
 mod transactable;
 
 pub use self::commit::CommitOptions;
+pub use self::dry_run::DryRunTransaction;
+pub(crate) use self::transactable::typed;
 pub use self::transactable::Transactable;
 pub(crate) use inner::TransactionInner;
 pub use manual_transaction::Transaction;
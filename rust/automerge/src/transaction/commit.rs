@@ -3,6 +3,11 @@
 pub struct CommitOptions {
     pub message: Option<String>,
     pub time: Option<i64>,
+    /// Application data to store in the change, see [`crate::Change::extra_bytes`].
+    pub extra_bytes: Option<Vec<u8>>,
+    /// Leave out the puts which a later op in the same transaction overwrote, see
+    /// [`Self::squash_redundant_ops`].
+    pub squash_redundant_ops: bool,
 }
 
 impl CommitOptions {
@@ -29,4 +34,40 @@ impl CommitOptions {
         self.time = Some(time);
         self
     }
+
+    /// Store arbitrary application data in the commit, such as the version of the client which
+    /// made it or the id of the request it was made for.
+    ///
+    /// The data is stored in the extra bytes of the change, alongside the signature of a signed
+    /// change (see [`crate::Automerge::set_signer`]), which covers it.
+    pub fn with_extra_bytes<B: Into<Vec<u8>>>(mut self, extra_bytes: B) -> Self {
+        self.extra_bytes = Some(extra_bytes.into());
+        self
+    }
+
+    /// Store arbitrary application data in the commit, see [`Self::with_extra_bytes`].
+    pub fn set_extra_bytes<B: Into<Vec<u8>>>(&mut self, extra_bytes: B) -> &mut Self {
+        self.extra_bytes = Some(extra_bytes.into());
+        self
+    }
+
+    /// Leave the values which were put and then overwritten within the transaction out of the
+    /// change, so e.g. a slider which puts a large value on every movement between commits only
+    /// records the final one.
+    ///
+    /// The overwritten puts stay in the change, with their values replaced by null, as removing
+    /// them would change the ids of every op after them, including those of objects and elements
+    /// created in the transaction. No document ever shows the replaced values, as they were
+    /// overwritten in the change which made them. Only puts of scalar values are replaced, and
+    /// not those which were incremented.
+    pub fn squash_redundant_ops(mut self, squash: bool) -> Self {
+        self.squash_redundant_ops = squash;
+        self
+    }
+
+    /// Leave out overwritten values, see [`Self::squash_redundant_ops`].
+    pub fn set_squash_redundant_ops(&mut self, squash: bool) -> &mut Self {
+        self.squash_redundant_ops = squash;
+        self
+    }
 }
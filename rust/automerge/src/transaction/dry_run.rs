@@ -0,0 +1,72 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::Change;
+
+use super::{CommitOptions, Transaction, UnObserved};
+
+/// A transaction which doesn't change the document when it is committed, created from
+/// [`crate::Automerge::transaction_dry_run`].
+///
+/// Make changes with [`super::Transactable`] through `Deref` as with any other transaction.
+/// Committing returns the [`Change`] the transaction made and rolls the transaction back, so a
+/// client can send the change to a server to be validated and only apply it, with
+/// [`crate::Automerge::apply_changes`], once the server accepts it.
+///
+/// The change has the next sequence number of this document's actor and depends on its current
+/// heads. Any other change this document makes before the change is applied will have the same
+/// sequence number, so apply or discard the change before making another.
+///
+/// ```
+/// # use automerge::{Automerge, ROOT};
+/// # use automerge::transaction::Transactable;
+/// let mut doc = Automerge::new();
+/// let mut tx = doc.transaction_dry_run();
+/// tx.put(ROOT, "key", "value").unwrap();
+/// let change = tx.commit();
+/// assert!(doc.get(ROOT, "key").unwrap().is_none());
+///
+/// // once the server has accepted the change
+/// doc.apply_changes(vec![change]).unwrap();
+/// assert!(doc.get(ROOT, "key").unwrap().is_some());
+/// ```
+#[derive(Debug)]
+pub struct DryRunTransaction<'a> {
+    tx: Transaction<'a, UnObserved>,
+}
+
+impl<'a> DryRunTransaction<'a> {
+    pub(crate) fn new(tx: Transaction<'a, UnObserved>) -> Self {
+        Self { tx }
+    }
+
+    /// Roll back the operations performed in this transaction, returning the change they make.
+    pub fn commit(self) -> Change {
+        self.commit_with(CommitOptions::default())
+    }
+
+    /// Like [`Self::commit`] with some options, see [`Transaction::commit_with`].
+    pub fn commit_with(mut self, options: CommitOptions) -> Change {
+        let inner = self.tx.inner.take().unwrap();
+        inner.dry_run(self.tx.doc, options)
+    }
+
+    /// Undo the operations added in this transaction, returning the number of cancelled
+    /// operations.
+    pub fn rollback(self) -> usize {
+        self.tx.rollback()
+    }
+}
+
+impl<'a> Deref for DryRunTransaction<'a> {
+    type Target = Transaction<'a, UnObserved>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tx
+    }
+}
+
+impl<'a> DerefMut for DryRunTransaction<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.tx
+    }
+}
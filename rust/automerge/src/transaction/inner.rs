@@ -1,10 +1,17 @@
 use std::num::NonZeroU64;
+use std::ops::{Range, RangeBounds};
+
+use smol_str::SmolStr;
 
 use crate::automerge::Actor;
+use crate::commit_hooks::PendingCommit;
 use crate::exid::ExId;
+use crate::extra_bytes;
 use crate::query::{self, OpIdSearch};
-use crate::storage::Change as StoredChange;
-use crate::types::{Key, ObjId, OpId};
+use crate::signing::Signer;
+use crate::storage::{change::Verified, Change as StoredChange};
+use crate::transaction::CommitOptions;
+use crate::types::{ElemId, Key, ObjId, OpId};
 use crate::{op_tree::OpSetMetadata, types::Op, Automerge, Change, ChangeHash, OpObserver, Prop};
 use crate::{AutomergeError, ObjType, OpType, ScalarValue};
 
@@ -17,6 +24,33 @@ pub(crate) struct TransactionInner {
     pub(crate) message: Option<String>,
     pub(crate) deps: Vec<ChangeHash>,
     pub(crate) operations: Vec<(ObjId, Prop, Op)>,
+    /// The label given to new ops, see [`crate::transaction::Transaction::set_source`]
+    pub(crate) source: Option<SmolStr>,
+    /// The counters of the ops created with each label
+    pub(crate) sources: Vec<(Range<u64>, SmolStr)>,
+    /// Where the element after the last one inserted goes, see [`InsertCursor`]
+    pub(crate) next_insert: Option<InsertCursor>,
+}
+
+/// The position just after the element a transaction last inserted into a sequence.
+///
+/// An element inserted right after the element this transaction inserted last goes right after
+/// it in the op tree, as no other op can follow it yet and the new op has a greater id than any
+/// other. Appending a run of elements, e.g. to a log, then doesn't have to search the sequence
+/// for each one. Any other op this transaction makes on the object invalidates the cursor, and as
+/// a safeguard it is only used while the object has the same number of ops as it had after that
+/// insert.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct InsertCursor {
+    obj: ObjId,
+    /// The index of the next element
+    index: usize,
+    /// The position in the op tree of the next element
+    pos: usize,
+    /// The element inserted last
+    elem: ElemId,
+    /// The number of ops in `obj` after the last insert
+    len: usize,
 }
 
 impl TransactionInner {
@@ -24,15 +58,35 @@ impl TransactionInner {
         self.operations.len()
     }
 
+    /// Check a new op with `action` at `prop` of `obj` against the limits and the schema of
+    /// `doc`.
+    fn check_op(
+        &self,
+        doc: &Automerge,
+        obj: &ObjId,
+        prop: &Prop,
+        action: &OpType,
+    ) -> Result<(), AutomergeError> {
+        if self.pending_ops() == 0 {
+            // a transaction which breaks the actor policy fails on its first op, whichever way it
+            // is going to be committed
+            doc.check_actor()?;
+        }
+        doc.check_local_op(self.pending_ops(), obj, action)?;
+        doc.check_schema(obj, prop, action)?;
+        Ok(())
+    }
+
     /// Commit the operations performed in this transaction, returning the hashes corresponding to
     /// the new heads.
     #[tracing::instrument(skip(self, doc))]
-    pub(crate) fn commit(
-        mut self,
-        doc: &mut Automerge,
-        message: Option<String>,
-        time: Option<i64>,
-    ) -> ChangeHash {
+    pub(crate) fn commit(mut self, doc: &mut Automerge, options: CommitOptions) -> ChangeHash {
+        let CommitOptions {
+            message,
+            time,
+            extra_bytes,
+            squash_redundant_ops,
+        } = options;
         if message.is_some() {
             self.message = message;
         }
@@ -41,8 +95,17 @@ impl TransactionInner {
             self.time = t;
         }
 
+        if squash_redundant_ops {
+            self.clear_redundant_puts(Some(doc));
+        }
+
+        doc.commit_hooks
+            .run_before(&PendingCommit { doc, tx: &self });
         let num_ops = self.pending_ops();
-        let change = self.export(&doc.ops.m);
+        for (counters, source) in self.sources.drain(..) {
+            doc.op_sources.insert(self.actor, counters, source);
+        }
+        let change = self.export(&doc.ops.m, doc.signer.as_ref(), extra_bytes);
         let hash = change.hash();
         #[cfg(not(debug_assertions))]
         tracing::trace!(commit=?hash, deps=?change.deps(), "committing transaction");
@@ -51,44 +114,101 @@ impl TransactionInner {
             let ops = change.iter_ops().collect::<Vec<_>>();
             tracing::trace!(commit=?hash, ?ops, deps=?change.deps(), "committing transaction");
         }
-        doc.update_history(change, num_ops);
+        let index = doc.update_history(change, num_ops);
+        doc.session_changes += 1;
+        doc.commit_hooks.run_after(&doc.history[index]);
         debug_assert_eq!(doc.get_heads(), vec![hash]);
         hash
     }
 
-    #[tracing::instrument(skip(self, metadata))]
-    pub(crate) fn export(self, metadata: &OpSetMetadata) -> Change {
+    /// Build the change this transaction would commit and then roll it back, leaving `doc` as it
+    /// was before the transaction.
+    pub(crate) fn dry_run(mut self, doc: &mut Automerge, options: CommitOptions) -> Change {
+        let CommitOptions {
+            message,
+            time,
+            extra_bytes,
+            squash_redundant_ops,
+        } = options;
+        if message.is_some() {
+            self.message = message;
+        }
+        if let Some(t) = time {
+            self.time = t;
+        }
+        let mut exported = self.clone();
+        if squash_redundant_ops {
+            exported.clear_redundant_puts(None);
+        }
+        // export before rolling back, which may remove our actor from the metadata
+        let change = exported.export(&doc.ops.m, doc.signer.as_ref(), extra_bytes);
+        self.rollback(doc);
+        change
+    }
+
+    #[tracing::instrument(skip(self, metadata, signer))]
+    pub(crate) fn export(
+        self,
+        metadata: &OpSetMetadata,
+        signer: Option<&Signer>,
+        extra_bytes: Option<Vec<u8>>,
+    ) -> Change {
+        let mut extra_bytes = extra_bytes
+            .map(|data| extra_bytes::encode_data(&data))
+            .filter(|bytes| !bytes.is_empty());
+        let stored = match signer {
+            Some(signer) => {
+                // The signature section goes after everything it covers, including the
+                // application data, so the body we sign is unchanged when we rebuild the change
+                // with the signature.
+                let unsigned = self.build_stored(metadata, extra_bytes.clone());
+                let signature = signer.sign(unsigned.body_bytes());
+                let mut signed = extra_bytes.take().unwrap_or_default();
+                extra_bytes::append_signature(&mut signed, &signature);
+                self.build_stored(metadata, Some(signed))
+            }
+            None => self.build_stored(metadata, extra_bytes),
+        };
+        #[cfg(debug_assertions)]
+        {
+            let realized_ops = self.operations.iter().collect::<Vec<_>>();
+            tracing::trace!(?stored, ops=?realized_ops, "committing change");
+        }
+        #[cfg(not(debug_assertions))]
+        tracing::trace!(?stored, "committing change");
+        Change::new(stored)
+    }
+
+    fn build_stored(
+        &self,
+        metadata: &OpSetMetadata,
+        extra_bytes: Option<Vec<u8>>,
+    ) -> StoredChange<'static, Verified> {
         use crate::storage::{change::PredOutOfOrder, convert::op_as_actor_id};
 
         let actor = metadata.actors.get(self.actor).clone();
         let ops = self.operations.iter().map(|o| (&o.0, &o.2));
-        //let (ops, other_actors) = encode_change_ops(ops, actor.clone(), actors, props);
         let deps = self.deps.clone();
-        let stored = match StoredChange::builder()
+        let mut builder = StoredChange::builder()
             .with_actor(actor)
             .with_seq(self.seq)
             .with_start_op(self.start_op)
             .with_message(self.message.clone())
             .with_dependencies(deps)
-            .with_timestamp(self.time)
-            .build(
-                ops.into_iter()
-                    .map(|(obj, op)| op_as_actor_id(obj, op, metadata)),
-            ) {
+            .with_timestamp(self.time);
+        if let Some(extra_bytes) = extra_bytes {
+            builder = builder.with_extra_bytes(extra_bytes);
+        }
+        match builder.build(
+            ops.into_iter()
+                .map(|(obj, op)| op_as_actor_id(obj, op, metadata)),
+        ) {
             Ok(s) => s,
             Err(PredOutOfOrder) => {
                 // SAFETY: types::Op::preds is `types::OpIds` which ensures ops are always sorted
                 panic!("preds out of order");
             }
-        };
-        #[cfg(debug_assertions)]
-        {
-            let realized_ops = self.operations.iter().collect::<Vec<_>>();
-            tracing::trace!(?stored, ops=?realized_ops, "committing change");
         }
-        #[cfg(not(debug_assertions))]
-        tracing::trace!(?stored, "committing change");
-        Change::new(stored)
     }
 
     /// Undo the operations added in this transaction, returning the number of cancelled
@@ -108,7 +228,10 @@ impl TransactionInner {
         }
 
         // remove the actor from the cache so that it doesn't end up in the saved document
-        if doc.states.get(&self.actor).is_none() && doc.ops.m.actors.len() > 0 {
+        if doc.states.get(&self.actor).is_none()
+            && doc.dropped_seqs(self.actor) == 0
+            && doc.ops.m.actors.len() > 0
+        {
             let actor = doc.ops.m.actors.remove_last();
             doc.actor = Actor::Unused(actor);
         }
@@ -116,6 +239,55 @@ impl TransactionInner {
         num
     }
 
+    /// The positions in this transaction of the puts of scalar values which a later op in it
+    /// overwrote, whose values are never visible.
+    fn redundant_puts(&self) -> Vec<usize> {
+        let start = self.start_op.get();
+        let ops = &self.operations;
+        let index_of = |id: &OpId| {
+            (id.actor() == self.actor && id.counter() >= start)
+                .then(|| (id.counter() - start) as usize)
+        };
+
+        // whether each op was overwritten, and whether it was incremented, which keeps it
+        let mut overwritten = vec![false; ops.len()];
+        let mut incremented = vec![false; ops.len()];
+        for (_, _, op) in ops {
+            for i in op.pred.iter().filter_map(index_of) {
+                if matches!(op.action, OpType::Increment(_)) {
+                    incremented[i] = true;
+                } else {
+                    overwritten[i] = true;
+                }
+            }
+        }
+        ops.iter()
+            .enumerate()
+            .filter(|(i, (_, _, op))| {
+                overwritten[*i]
+                    && !incremented[*i]
+                    && !op.insert
+                    && matches!(op.action, OpType::Put(ref v) if *v != ScalarValue::Null)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Replace the values of the puts which a later op overwrote with null, in this transaction
+    /// and, if `doc` is given, in the document. The ops stay, so no ids change.
+    fn clear_redundant_puts(&mut self, mut doc: Option<&mut Automerge>) {
+        for i in self.redundant_puts() {
+            let (obj, _, op) = &mut self.operations[i];
+            op.action = OpType::Put(ScalarValue::Null);
+            if let Some(doc) = doc.as_mut() {
+                if let Some(pos) = doc.ops.search(obj, OpIdSearch::new(op.id)).index() {
+                    doc.ops
+                        .replace(obj, pos, |o| o.action = OpType::Put(ScalarValue::Null));
+                }
+            }
+        }
+    }
+
     /// Set the value of property `P` to value `V` in object `obj`.
     ///
     /// # Returns
@@ -138,8 +310,8 @@ impl TransactionInner {
         value: V,
     ) -> Result<(), AutomergeError> {
         let obj = doc.exid_to_obj(ex_obj)?;
-        let value = value.into();
         let prop = prop.into();
+        let value = doc.preserve_number(ex_obj, &prop, value.into());
         self.local_op(doc, op_observer, obj, prop, value.into())?;
         Ok(())
     }
@@ -189,6 +361,11 @@ impl TransactionInner {
         obj: ObjId,
         succ_pos: &[usize],
     ) {
+        // a delete hides an element without adding an op, so any op on the object moves the
+        // elements after it
+        if self.next_insert.map_or(false, |next| next.obj == obj) {
+            self.next_insert = None;
+        }
         doc.ops.add_succ(&obj, succ_pos.iter().copied(), &op);
 
         if !op.is_delete() {
@@ -235,11 +412,22 @@ impl TransactionInner {
         index: usize,
         action: OpType,
     ) -> Result<OpId, AutomergeError> {
+        self.check_op(doc, &obj, &Prop::Seq(index), &action)?;
         let id = self.next_id();
 
-        let query = doc.ops.search(&obj, query::InsertNth::new(index));
-
-        let key = query.key()?;
+        let (pos, key) = match self.next_insert {
+            Some(next)
+                if next.obj == obj
+                    && next.index == index
+                    && doc.ops.object_len(&obj) == Some(next.len) =>
+            {
+                (next.pos, Key::Seq(next.elem))
+            }
+            _ => {
+                let query = doc.ops.search(&obj, query::InsertNth::new(index));
+                (query.pos(), query.key()?)
+            }
+        };
 
         let op = Op {
             id,
@@ -250,7 +438,14 @@ impl TransactionInner {
             insert: true,
         };
 
-        doc.ops.insert(query.pos(), &obj, op.clone());
+        doc.ops.insert(pos, &obj, op.clone());
+        self.next_insert = doc.ops.object_len(&obj).map(|len| InsertCursor {
+            obj,
+            index: index + 1,
+            pos: pos + 1,
+            elem: ElemId(id),
+            len,
+        });
 
         self.finalize_op(doc, op_observer, obj, Prop::Seq(index), op);
 
@@ -265,6 +460,7 @@ impl TransactionInner {
         prop: Prop,
         action: OpType,
     ) -> Result<Option<OpId>, AutomergeError> {
+        self.check_op(doc, &obj, &prop, &action)?;
         match prop {
             Prop::Map(s) => self.local_map_op(doc, op_observer, obj, s, action),
             Prop::Seq(n) => self.local_list_op(doc, op_observer, obj, n, action),
@@ -285,7 +481,7 @@ impl TransactionInner {
 
         let id = self.next_id();
         let prop_index = doc.ops.m.props.cache(prop.clone());
-        let query = doc.ops.search(&obj, query::Prop::new(prop_index));
+        let query = doc.ops.search_prop(&obj, prop_index);
 
         // no key present to delete
         if query.ops.is_empty() && action == OpType::Delete {
@@ -328,7 +524,7 @@ impl TransactionInner {
         index: usize,
         action: OpType,
     ) -> Result<Option<OpId>, AutomergeError> {
-        let query = doc.ops.search(&obj, query::Nth::new(index));
+        let query = doc.ops.search_nth(&obj, index);
 
         let id = self.next_id();
         let pred = doc.ops.m.sorted_opids(query.ops.iter().map(|o| o.id));
@@ -386,6 +582,50 @@ impl TransactionInner {
         Ok(())
     }
 
+    /// Delete every key of the map `ex_obj` in `range`, returning how many were deleted.
+    pub(crate) fn delete_range<R: RangeBounds<String>, Obs: OpObserver>(
+        &mut self,
+        doc: &mut Automerge,
+        mut op_observer: Option<&mut Obs>,
+        ex_obj: &ExId,
+        range: R,
+    ) -> Result<usize, AutomergeError> {
+        let obj = doc.exid_to_obj(ex_obj)?;
+        match doc.ops.object_type(&obj) {
+            Some(ObjType::Map | ObjType::Table) => {}
+            Some(other) => {
+                return Err(AutomergeError::InvalidValueType {
+                    expected: "map".to_string(),
+                    unexpected: other.to_string(),
+                })
+            }
+            None => return Err(AutomergeError::NotAnObject),
+        }
+        // a delete doesn't add an op to the tree, so the positions found up front stay correct
+        let keys = doc.ops.visible_ops_in_range(&obj, &range);
+        for (key, ops) in &keys {
+            let prop = Prop::Map(doc.ops.m.props.get(*key).clone());
+            self.check_op(doc, &obj, &prop, &OpType::Delete)?;
+            let op = Op {
+                id: self.next_id(),
+                action: OpType::Delete,
+                key: Key::Map(*key),
+                succ: Default::default(),
+                pred: doc.ops.m.sorted_opids(ops.iter().map(|(id, _)| *id)),
+                insert: false,
+            };
+            let succ_pos = ops.iter().map(|(_, pos)| *pos).collect::<Vec<_>>();
+            let pos = succ_pos[0];
+            // This unwrap and rewrap of the option is necessary to appeas the borrow checker :(
+            if let Some(obs) = op_observer.as_mut() {
+                self.insert_local_op(doc, Some(*obs), prop, op, pos, obj, &succ_pos);
+            } else {
+                self.insert_local_op::<Obs>(doc, None, prop, op, pos, obj, &succ_pos);
+            }
+        }
+        Ok(keys.len())
+    }
+
     /// Splice new elements into the given sequence. Returns a vector of the OpIds used to insert
     /// the new elements
     pub(crate) fn splice<Obs: OpObserver>(
@@ -406,18 +646,52 @@ impl TransactionInner {
                 self.local_op::<Obs>(doc, None, obj, pos.into(), OpType::Delete)?;
             }
         }
+        // characters inserted into text are reported to the observer as a single splice
+        let is_text = doc.ops.object_type(&obj) == Some(ObjType::Text);
+        let mut splice = (pos, String::new());
         for v in vals {
+            let c = match &v {
+                ScalarValue::Str(s) if is_text && s.chars().count() == 1 => s.chars().next(),
+                _ => None,
+            };
+            if let Some(c) = c {
+                self.do_insert::<Obs>(doc, None, obj, pos, v.into())?;
+                splice.1.push(c);
+                pos += 1;
+                continue;
+            }
             // As above this unwrap and rewrap of the option is necessary to appeas the borrow checker :(
             if let Some(obs) = op_observer.as_mut() {
-                self.do_insert(doc, Some(*obs), obj, pos, v.clone().into())?;
+                Self::observe_splice(doc, *obs, obj, &mut splice);
+                self.do_insert(doc, Some(*obs), obj, pos, v.into())?;
             } else {
-                self.do_insert::<Obs>(doc, None, obj, pos, v.clone().into())?;
+                self.do_insert::<Obs>(doc, None, obj, pos, v.into())?;
             }
             pos += 1;
+            splice.0 = pos;
+        }
+        if let Some(obs) = op_observer {
+            Self::observe_splice(doc, obs, obj, &mut splice);
         }
         Ok(())
     }
 
+    /// Report the characters `splice.1`, inserted at `splice.0` of the text `obj`, to `observer`
+    /// if there are any, and clear them.
+    fn observe_splice<Obs: OpObserver>(
+        doc: &Automerge,
+        observer: &mut Obs,
+        obj: ObjId,
+        splice: &mut (usize, String),
+    ) {
+        if !splice.1.is_empty() {
+            let parents = doc.ops.parents(obj);
+            let ex_obj = doc.ops.id_to_exid(obj.0);
+            observer.splice_text(parents, ex_obj, splice.0, &splice.1);
+            splice.1.clear();
+        }
+    }
+
     fn finalize_op<Obs: OpObserver>(
         &mut self,
         doc: &mut Automerge,
@@ -432,9 +706,12 @@ impl TransactionInner {
             let parents = doc.ops.parents(obj);
             if op.insert {
                 let value = (op.value(), doc.ops.id_to_exid(op.id));
-                match prop {
-                    Prop::Map(_) => panic!("insert into a map"),
-                    Prop::Seq(index) => op_observer.insert(parents, ex_obj, index, value),
+                match (&prop, doc.ops.text_char(&obj, &op)) {
+                    (Prop::Map(_), _) => panic!("insert into a map"),
+                    (Prop::Seq(index), Some(c)) => {
+                        op_observer.splice_text(parents, ex_obj, *index, &c.to_string())
+                    }
+                    (Prop::Seq(index), None) => op_observer.insert(parents, ex_obj, *index, value),
                 }
             } else if op.is_delete() {
                 op_observer.delete(parents, ex_obj, prop.clone());
@@ -450,6 +727,15 @@ impl TransactionInner {
                 op_observer.put(parents, ex_obj, prop.clone(), value, false);
             }
         }
+        if let Some(source) = &self.source {
+            let counter = op.id.counter();
+            match self.sources.last_mut() {
+                Some((counters, last)) if last == source && counters.end == counter => {
+                    counters.end += 1;
+                }
+                _ => self.sources.push((counter..counter + 1, source.clone())),
+            }
+        }
         self.operations.push((obj, prop, op));
     }
 }
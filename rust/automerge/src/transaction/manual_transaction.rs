@@ -1,7 +1,11 @@
 use std::ops::RangeBounds;
 
+use smol_str::SmolStr;
+
 use crate::exid::ExId;
-use crate::{Automerge, ChangeHash, KeysAt, ObjType, OpObserver, Prop, ScalarValue, Value, Values};
+use crate::{
+    Automerge, ChangeHash, KeysAt, ObjType, OpObserver, Prop, ScalarValue, Snapshot, Value, Values,
+};
 use crate::{AutomergeError, Keys};
 use crate::{ListRange, ListRangeAt, MapRange, MapRangeAt};
 
@@ -41,12 +45,44 @@ impl<'a, Obs: observation::Observation> Transaction<'a, Obs> {
         self.doc.get_heads()
     }
 
+    /// Take a snapshot of the document as it is now, including the ops made so far in this
+    /// transaction, see [`Automerge::snapshot`].
+    ///
+    /// The transaction carries on afterwards and the snapshot is not affected by it, or by
+    /// rolling it back. Its [`Snapshot::heads`] are the heads the transaction started from.
+    pub fn snapshot(&self) -> Snapshot {
+        self.doc.snapshot()
+    }
+
+    /// Label the ops created from now on in this transaction with `source`, or stop labelling
+    /// them if `source` is `None`.
+    ///
+    /// Labels are a lightweight way to find out which part of an application is producing the
+    /// ops in a document, see [`Automerge::op_source`] and [`Automerge::change_sources`], and
+    /// the observer of the transaction is told about them, see [`OpObserver::source`]. They are
+    /// kept in memory by this document only; they are not saved or sent to other peers.
+    pub fn set_source(&mut self, source: Option<&str>) {
+        self.inner.as_mut().unwrap().source = source.map(SmolStr::new);
+        if let Some(observer) = self.observation.as_mut().and_then(|o| o.observer()) {
+            observer.source(source);
+        }
+    }
+
+    /// Take the observation, with the source label reset now that the transaction is over.
+    fn finish_observation(&mut self) -> Obs {
+        let mut obs = self.observation.take().unwrap();
+        if let Some(observer) = obs.observer() {
+            observer.source(None);
+        }
+        obs
+    }
+
     /// Commit the operations performed in this transaction, returning the hashes corresponding to
     /// the new heads.
     pub fn commit(mut self) -> Obs::CommitResult {
         let tx = self.inner.take().unwrap();
-        let hash = tx.commit(self.doc, None, None);
-        let obs = self.observation.take().unwrap();
+        let hash = tx.commit(self.doc, CommitOptions::default());
+        let obs = self.finish_observation();
         obs.make_result(hash)
     }
 
@@ -68,11 +104,39 @@ impl<'a, Obs: observation::Observation> Transaction<'a, Obs> {
     /// ```
     pub fn commit_with(mut self, options: CommitOptions) -> Obs::CommitResult {
         let tx = self.inner.take().unwrap();
-        let hash = tx.commit(self.doc, options.message, options.time);
-        let obs = self.observation.take().unwrap();
+        let hash = tx.commit(self.doc, options);
+        let obs = self.finish_observation();
         obs.make_result(hash)
     }
 
+    /// Commit the operations in this transaction like [`Self::commit`], unless the document's
+    /// actor breaks its [`crate::ActorPolicy`], in which case the transaction is rolled back and
+    /// the error of [`Automerge::check_actor`] is returned.
+    ///
+    /// The policy was already checked by the first op of the transaction, so this only makes a
+    /// difference to transactions without ops, which `commit` would make an empty change of.
+    ///
+    /// The values put by the transaction have already been checked against the document's
+    /// [`crate::Schema`] as they were put.
+    pub fn try_commit(self) -> Result<Obs::CommitResult, AutomergeError> {
+        self.try_commit_with(CommitOptions::default())
+    }
+
+    /// Like [`Self::try_commit`] with some options, see [`Self::commit_with`].
+    pub fn try_commit_with(
+        mut self,
+        options: CommitOptions,
+    ) -> Result<Obs::CommitResult, AutomergeError> {
+        let tx = self.inner.take().unwrap();
+        if let Err(e) = self.doc.check_actor() {
+            tx.rollback(self.doc);
+            return Err(e);
+        }
+        let hash = tx.commit(self.doc, options);
+        let obs = self.finish_observation();
+        Ok(obs.make_result(hash))
+    }
+
     /// Undo the operations added in this transaction, returning the number of cancelled
     /// operations.
     pub fn rollback(mut self) -> usize {
@@ -159,6 +223,14 @@ impl<'a, Obs: observation::Observation> Transactable for Transaction<'a, Obs> {
         self.do_tx(|tx, doc, obs| tx.delete(doc, obs, obj.as_ref(), prop))
     }
 
+    fn delete_range<O: AsRef<ExId>, R: RangeBounds<String>>(
+        &mut self,
+        obj: O,
+        range: R,
+    ) -> Result<usize, AutomergeError> {
+        self.do_tx(|tx, doc, obs| tx.delete_range(doc, obs, obj.as_ref(), range))
+    }
+
     /// Splice new elements into the given sequence. Returns a vector of the OpIds used to insert
     /// the new elements
     fn splice<O: AsRef<ExId>, V: IntoIterator<Item = ScalarValue>>(
@@ -1,9 +1,19 @@
-use std::ops::RangeBounds;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::ops::{Bound, RangeBounds};
 
+use crate::actor_metadata::{ActorMetadata, ACTOR_METADATA_KEY};
+use crate::bytes_stream::{read_chunk, BytesReader, BYTES_CHUNK_SIZE};
 use crate::exid::ExId;
+use crate::reserved::{self, REGISTRY_MARKER_KEY};
+use crate::sorted_list;
+use crate::tags::{self, TAGS_KEY};
+use crate::text_diff;
 use crate::{
-    AutomergeError, ChangeHash, Keys, KeysAt, ListRange, ListRangeAt, MapRange, MapRangeAt,
-    ObjType, Parents, Prop, ScalarValue, Value, Values,
+    ActorId, AutomergeError, ChangeHash, Keys, KeysAt, ListRange, ListRangeAt, MapRange,
+    MapRangeAt, ObjType, Parents, Prop, ScalarValue, Value, Values,
 };
 
 /// A way of mutating a document within a single change.
@@ -76,6 +86,62 @@ pub trait Transactable {
         prop: P,
     ) -> Result<(), AutomergeError>;
 
+    /// Delete every key of the map `obj` in `range`, returning the number of keys deleted.
+    ///
+    /// Only keys this document can see are deleted: a key put concurrently by another peer
+    /// survives the merge. By default this deletes the keys returned by [`Self::map_range`] one
+    /// by one. The transactions of this crate instead find the keys in one pass over the ops of
+    /// the range, which is much quicker.
+    fn delete_range<O: AsRef<ExId>, R: RangeBounds<String>>(
+        &mut self,
+        obj: O,
+        range: R,
+    ) -> Result<usize, AutomergeError> {
+        let obj = obj.as_ref();
+        check_object_type(self.object_type(obj), "map", |t| {
+            matches!(t, ObjType::Map | ObjType::Table)
+        })?;
+        let keys = self
+            .map_range(obj, range)
+            .map(|(key, _, _)| key.to_string())
+            .collect::<Vec<_>>();
+        for key in &keys {
+            self.delete(obj, key.as_str())?;
+        }
+        Ok(keys.len())
+    }
+
+    /// Delete the elements of the list or text `obj` at the indices in `range`, returning the
+    /// number of elements deleted. Fails with [`AutomergeError::InvalidIndex`] if the range
+    /// ends past the end of the list.
+    fn delete_list_range<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &mut self,
+        obj: O,
+        range: R,
+    ) -> Result<usize, AutomergeError> {
+        let obj = obj.as_ref();
+        check_object_type(self.object_type(obj), "list", |t| {
+            matches!(t, ObjType::List | ObjType::Text)
+        })?;
+        let len = self.length(obj);
+        let start = match range.start_bound() {
+            Bound::Included(i) => *i,
+            Bound::Excluded(i) => i.checked_add(1).ok_or(AutomergeError::InvalidIndex(*i))?,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(i) => i.checked_add(1).ok_or(AutomergeError::InvalidIndex(*i))?,
+            Bound::Excluded(i) => *i,
+            Bound::Unbounded => len,
+        };
+        if end > len {
+            return Err(AutomergeError::InvalidIndex(end));
+        }
+        let del = end.saturating_sub(start);
+        self.splice(obj, start, del, std::iter::empty())?;
+        Ok(del)
+    }
+
     fn splice<O: AsRef<ExId>, V: IntoIterator<Item = ScalarValue>>(
         &mut self,
         obj: O,
@@ -96,6 +162,200 @@ pub trait Transactable {
         self.splice(obj, pos, del, vals)
     }
 
+    /// Write everything `reader` produces to `prop` of `obj` as a list of bytes values of
+    /// [`BYTES_CHUNK_SIZE`] bytes each, returning the id of the list.
+    ///
+    /// Large values such as attachments never need to be in memory all at once, when writing them
+    /// or when reading them back with [`Self::get_bytes_reader`].
+    ///
+    /// # Errors
+    ///
+    /// As well as the errors [`Self::put_object`] can return this returns
+    /// [`AutomergeError::Read`] if `reader` fails. The chunks read so far have been written by
+    /// then.
+    fn put_bytes_stream<O: AsRef<ExId>, P: Into<Prop>, R: Read>(
+        &mut self,
+        obj: O,
+        prop: P,
+        mut reader: R,
+    ) -> Result<ExId, AutomergeError>
+    where
+        Self: Sized,
+    {
+        let list = self.put_object(obj, prop, ObjType::List)?;
+        let mut buf = vec![0; BYTES_CHUNK_SIZE];
+        for index in 0.. {
+            let n = read_chunk(&mut reader, &mut buf).map_err(AutomergeError::Read)?;
+            if n == 0 {
+                break;
+            }
+            self.insert(&list, index, buf[..n].to_vec())?;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(list)
+    }
+
+    /// Replace the metadata attached to `actor`, see [`ActorMetadata`]. Fields which are `None`
+    /// are removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::ReservedKey`] if the root map has a value at
+    /// [`ACTOR_METADATA_KEY`] which is not the metadata registry.
+    fn set_actor_metadata(
+        &mut self,
+        actor: &ActorId,
+        metadata: &ActorMetadata,
+    ) -> Result<(), AutomergeError>
+    where
+        Self: Sized,
+    {
+        let registry = reserved::registry_for_write(self, ACTOR_METADATA_KEY)?;
+        let key = actor.to_hex_string();
+        let entry = match self.get(&registry, key.as_str())? {
+            Some((Value::Object(ObjType::Map), id)) => id,
+            _ => self.put_object(&registry, key, ObjType::Map)?,
+        };
+        let fields = [
+            (
+                ActorMetadata::NAME,
+                metadata.name.clone().map(ScalarValue::from),
+            ),
+            (
+                ActorMetadata::DEVICE,
+                metadata.device.clone().map(ScalarValue::from),
+            ),
+            (
+                ActorMetadata::PUBLIC_KEY,
+                metadata.public_key.clone().map(ScalarValue::from),
+            ),
+        ];
+        for (field, value) in fields {
+            match value {
+                Some(value) => self.put(&entry, field, value)?,
+                None => self.delete(&entry, field)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// The metadata attached to `actor`, if any, see [`ActorMetadata`].
+    fn actor_metadata(&self, actor: &ActorId) -> Result<Option<ActorMetadata>, AutomergeError> {
+        ActorMetadata::read(actor, |obj, key| self.get_all(obj, key))
+    }
+
+    /// Name the version of the document at `heads` `name`, replacing any existing tag with that
+    /// name, see [`crate::Automerge::heads_for_tag`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::ReservedKey`] if the root map has a value at [`TAGS_KEY`] which
+    /// is not the tag registry, or if `name` is [`crate::REGISTRY_MARKER_KEY`].
+    fn tag_heads(&mut self, name: &str, heads: &[ChangeHash]) -> Result<(), AutomergeError>
+    where
+        Self: Sized,
+    {
+        if name == REGISTRY_MARKER_KEY {
+            return Err(AutomergeError::ReservedKey(name.to_string()));
+        }
+        let registry = reserved::registry_for_write(self, TAGS_KEY)?;
+        self.put(&registry, name, tags::encode_heads(heads))
+    }
+
+    /// Remove the tag `name`, if there is one.
+    fn remove_tag(&mut self, name: &str) -> Result<(), AutomergeError>
+    where
+        Self: Sized,
+    {
+        if let Some(registry) = reserved::registry_for_delete(self, TAGS_KEY)? {
+            if self.get(&registry, name)?.is_some() {
+                self.delete(&registry, name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The heads tagged `name`, see [`crate::Automerge::heads_for_tag`].
+    fn heads_for_tag(&self, name: &str) -> Result<Option<Vec<ChangeHash>>, AutomergeError> {
+        tags::heads_for_tag(name, |obj, key| self.get_all(obj, key))
+    }
+
+    /// Every tag and the heads it names, see [`crate::Automerge::tags`].
+    fn tags(&self) -> Result<BTreeMap<String, Vec<ChangeHash>>, AutomergeError> {
+        tags::tags(
+            |obj, key| self.get_all(obj, key),
+            |obj| self.keys(obj).collect(),
+        )
+    }
+
+    /// Replace the contents of the text object `obj` with `new_text`, using a diff so that only
+    /// the characters which changed are deleted or inserted.
+    ///
+    /// Unchanged characters keep their identity, so concurrent edits to them merge as expected.
+    /// Large texts are diffed line by line first, and with the `rayon` feature enabled the
+    /// changed blocks of lines are diffed in parallel. When the texts are too different for a
+    /// minimal diff to be found quickly the changed region is replaced wholesale.
+    fn update_text<O: AsRef<ExId>>(&mut self, obj: O, new_text: &str) -> Result<(), AutomergeError>
+    where
+        Self: Sized,
+    {
+        let obj = obj.as_ref();
+        let old = self.text(obj)?.chars().collect::<Vec<_>>();
+        let new = new_text.chars().collect::<Vec<_>>();
+        for edit in text_diff::diff(&old, &new).into_iter().rev() {
+            let inserted = new[edit.new].iter().collect::<String>();
+            self.splice_text(obj, edit.old.start, edit.old.len(), &inserted)?;
+        }
+        Ok(())
+    }
+
+    /// Insert `value` into the list `obj`, which is assumed to be sorted by `compare`, after any
+    /// elements equal to it, and return the index it was inserted at.
+    ///
+    /// `compare` is called with an element of the list and the value being inserted. The
+    /// position is found with [`Self::binary_search_by`], so this doesn't scan the list. Lists
+    /// edited concurrently by other peers may not stay sorted, as each peer inserts relative to
+    /// the elements it knows about.
+    fn insert_sorted<O, V, F>(
+        &mut self,
+        obj: O,
+        value: V,
+        mut compare: F,
+    ) -> Result<usize, AutomergeError>
+    where
+        Self: Sized,
+        O: AsRef<ExId>,
+        V: Into<ScalarValue>,
+        F: FnMut(&Value<'_>, &Value<'_>) -> Ordering,
+    {
+        let obj = obj.as_ref();
+        let value = value.into();
+        let new = Value::Scalar(Cow::Borrowed(&value));
+        // never report a match, so the search ends just past the last element which isn't
+        // greater than the new value
+        let index = match self.binary_search_by(obj, |elem| match compare(elem, &new) {
+            Ordering::Greater => Ordering::Greater,
+            _ => Ordering::Less,
+        })? {
+            Ok(index) | Err(index) => index,
+        };
+        self.insert(obj, index, value)?;
+        Ok(index)
+    }
+
+    /// Binary search the list `obj`, see [`crate::Automerge::binary_search_by`].
+    fn binary_search_by<O, F>(&self, obj: O, f: F) -> Result<Result<usize, usize>, AutomergeError>
+    where
+        Self: Sized,
+        O: AsRef<ExId>,
+        F: FnMut(&Value<'_>) -> Ordering,
+    {
+        let obj = obj.as_ref();
+        sorted_list::binary_search_by(self.length(obj), |index| self.get(obj, index), f)
+    }
+
     /// Get the keys of the given object, it should be a map.
     fn keys<O: AsRef<ExId>>(&self, obj: O) -> Keys<'_, '_>;
 
@@ -158,6 +418,97 @@ pub trait Transactable {
         prop: P,
     ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError>;
 
+    /// Get the string at this prop in the object.
+    ///
+    /// # Errors
+    ///
+    /// As well as the errors [`Self::get`] can return this returns
+    /// [`AutomergeError::InvalidValueType`] if the value is not a string. Text objects are not
+    /// strings, use [`Self::text`] to read them.
+    fn get_string<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<String>, AutomergeError> {
+        typed::string(self.get(obj, prop)?)
+    }
+
+    /// Get the signed integer at this prop in the object, see [`Self::get_string`].
+    fn get_int<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<i64>, AutomergeError> {
+        typed::int(self.get(obj, prop)?)
+    }
+
+    /// Get the unsigned integer at this prop in the object, see [`Self::get_string`].
+    fn get_uint<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<u64>, AutomergeError> {
+        typed::uint(self.get(obj, prop)?)
+    }
+
+    /// Get the float at this prop in the object, see [`Self::get_string`].
+    fn get_f64<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<f64>, AutomergeError> {
+        typed::f64(self.get(obj, prop)?)
+    }
+
+    /// Get the boolean at this prop in the object, see [`Self::get_string`].
+    fn get_bool<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<bool>, AutomergeError> {
+        typed::bool(self.get(obj, prop)?)
+    }
+
+    /// Get the bytes at this prop in the object, see [`Self::get_string`].
+    fn get_bytes<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<Vec<u8>>, AutomergeError> {
+        typed::bytes(self.get(obj, prop)?)
+    }
+
+    /// Read the bytes at this prop in the object, which may have been written whole or with
+    /// [`Self::put_bytes_stream`], without copying them.
+    fn get_bytes_reader<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<BytesReader<'_>>, AutomergeError> {
+        match self.get(obj, prop)? {
+            Some((value, id)) => BytesReader::new(value, || self.list_range(id, ..)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the id of the list at this prop in the object, see [`Self::get_string`].
+    fn get_list<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<ExId>, AutomergeError> {
+        typed::list(self.get(obj, prop)?)
+    }
+
+    /// Get the id of the map at this prop in the object, see [`Self::get_string`].
+    fn get_map<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<ExId>, AutomergeError> {
+        typed::map(self.get(obj, prop)?)
+    }
+
     /// Get the value at this prop in the object at a point in history.
     fn get_at<O: AsRef<ExId>, P: Into<Prop>>(
         &self,
@@ -192,12 +543,134 @@ pub trait Transactable {
     /// value.
     fn parents<O: AsRef<ExId>>(&self, obj: O) -> Result<Parents<'_>, AutomergeError>;
 
+    /// The path from the root to `obj`, see [`Automerge::path_to_object`].
     fn path_to_object<O: AsRef<ExId>>(&self, obj: O) -> Result<Vec<(ExId, Prop)>, AutomergeError> {
-        let mut path = self.parents(obj.as_ref().clone())?.collect::<Vec<_>>();
-        path.reverse();
-        Ok(path)
+        Ok(self.parents(obj)?.path())
     }
 
     /// The heads this transaction will be based on
     fn base_heads(&self) -> Vec<ChangeHash>;
 }
+
+/// The typed getters of [`Transactable`] and [`crate::Automerge`], which check the type of the
+/// result of a `get`.
+pub(crate) mod typed {
+    use crate::exid::ExId;
+    use crate::{AutomergeError, ObjType, ScalarValue, Value};
+
+    /// Convert the result of a `get` with `f`, or fail with an error saying we expected an
+    /// `expected`.
+    fn convert<T, F>(
+        found: Option<(Value<'_>, ExId)>,
+        expected: &str,
+        f: F,
+    ) -> Result<Option<T>, AutomergeError>
+    where
+        F: FnOnce(&Value<'_>, &ExId) -> Option<T>,
+    {
+        match found {
+            None => Ok(None),
+            Some((value, id)) => match f(&value, &id) {
+                Some(t) => Ok(Some(t)),
+                None => Err(AutomergeError::InvalidValueType {
+                    expected: expected.to_string(),
+                    unexpected: value.type_name().to_string(),
+                }),
+            },
+        }
+    }
+
+    pub(crate) fn string(
+        found: Option<(Value<'_>, ExId)>,
+    ) -> Result<Option<String>, AutomergeError> {
+        convert(found, "str", |value, _| match value {
+            Value::Scalar(s) => match s.as_ref() {
+                ScalarValue::Str(s) => Some(s.to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    pub(crate) fn int(found: Option<(Value<'_>, ExId)>) -> Result<Option<i64>, AutomergeError> {
+        convert(found, "int", |value, _| match value {
+            Value::Scalar(s) => match s.as_ref() {
+                ScalarValue::Int(i) => Some(*i),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    pub(crate) fn uint(found: Option<(Value<'_>, ExId)>) -> Result<Option<u64>, AutomergeError> {
+        convert(found, "uint", |value, _| match value {
+            Value::Scalar(s) => match s.as_ref() {
+                ScalarValue::Uint(u) => Some(*u),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    pub(crate) fn f64(found: Option<(Value<'_>, ExId)>) -> Result<Option<f64>, AutomergeError> {
+        convert(found, "f64", |value, _| match value {
+            Value::Scalar(s) => match s.as_ref() {
+                ScalarValue::F64(f) => Some(*f),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    pub(crate) fn bool(found: Option<(Value<'_>, ExId)>) -> Result<Option<bool>, AutomergeError> {
+        convert(found, "boolean", |value, _| match value {
+            Value::Scalar(s) => match s.as_ref() {
+                ScalarValue::Boolean(b) => Some(*b),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    pub(crate) fn bytes(
+        found: Option<(Value<'_>, ExId)>,
+    ) -> Result<Option<Vec<u8>>, AutomergeError> {
+        convert(found, "bytes", |value, _| match value {
+            Value::Scalar(s) => match s.as_ref() {
+                ScalarValue::Bytes(b) => Some(b.clone()),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    pub(crate) fn list(found: Option<(Value<'_>, ExId)>) -> Result<Option<ExId>, AutomergeError> {
+        convert(found, "list", |value, id| match value {
+            Value::Object(ObjType::List) => Some(id.clone()),
+            _ => None,
+        })
+    }
+
+    pub(crate) fn map(found: Option<(Value<'_>, ExId)>) -> Result<Option<ExId>, AutomergeError> {
+        convert(found, "map", |value, id| match value {
+            Value::Object(ObjType::Map) => Some(id.clone()),
+            _ => None,
+        })
+    }
+}
+
+/// Check that an object of type `obj_type` is the `expected` kind of object.
+fn check_object_type(
+    obj_type: Option<ObjType>,
+    expected: &str,
+    is_expected: impl Fn(ObjType) -> bool,
+) -> Result<(), AutomergeError> {
+    match obj_type {
+        Some(t) if is_expected(t) => Ok(()),
+        Some(other) => Err(AutomergeError::InvalidValueType {
+            expected: expected.to_string(),
+            unexpected: other.to_string(),
+        }),
+        None => Err(AutomergeError::NotAnObject),
+    }
+}
@@ -3,7 +3,9 @@ use crate::types::ObjType;
 use serde::{Deserialize, Serialize, Serializer};
 use smol_str::SmolStr;
 use std::borrow::Cow;
+use std::convert::TryFrom;
 use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value<'a> {
@@ -237,6 +239,57 @@ impl<'a> Value<'a> {
             _ => None,
         }
     }
+
+    /// If this value is a timestamp, return it as a [`SystemTime`], see
+    /// [`ScalarValue::to_system_time`]
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        match self {
+            Value::Scalar(s) => s.to_system_time(),
+            _ => None,
+        }
+    }
+
+    /// If this value is a timestamp, return it as a [`chrono::DateTime`], see
+    /// [`ScalarValue::to_date_time`]
+    #[cfg(feature = "chrono")]
+    pub fn to_date_time(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        match self {
+            Value::Scalar(s) => s.to_date_time(),
+            _ => None,
+        }
+    }
+
+    /// If this value is a timestamp, return it as a [`time::OffsetDateTime`], see
+    /// [`ScalarValue::to_offset_date_time`]
+    #[cfg(feature = "time")]
+    pub fn to_offset_date_time(&self) -> Option<time::OffsetDateTime> {
+        match self {
+            Value::Scalar(s) => s.to_offset_date_time(),
+            _ => None,
+        }
+    }
+
+    /// The name of the type of this value, for error messages
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::Object(ObjType::Map) => "map",
+            Value::Object(ObjType::Table) => "table",
+            Value::Object(ObjType::List) => "list",
+            Value::Object(ObjType::Text) => "text",
+            Value::Scalar(s) => match s.as_ref() {
+                ScalarValue::Bytes(_) => "bytes",
+                ScalarValue::Str(_) => "str",
+                ScalarValue::Int(_) => "int",
+                ScalarValue::Uint(_) => "uint",
+                ScalarValue::F64(_) => "f64",
+                ScalarValue::Counter(_) => "counter",
+                ScalarValue::Timestamp(_) => "timestamp",
+                ScalarValue::Boolean(_) => "boolean",
+                ScalarValue::Unknown { .. } => "unknown",
+                ScalarValue::Null => "null",
+            },
+        }
+    }
 }
 
 impl<'a> fmt::Display for Value<'a> {
@@ -642,6 +695,83 @@ impl ScalarValue {
     pub fn counter(n: i64) -> ScalarValue {
         ScalarValue::Counter(n.into())
     }
+
+    /// If this value is a timestamp, return it as a [`SystemTime`]. Timestamps are milliseconds
+    /// since the Unix epoch, in UTC, and may be before the epoch. Returns `None` if the timestamp
+    /// is outside the range of `SystemTime` on this platform.
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        match self {
+            ScalarValue::Timestamp(ms) if *ms >= 0 => {
+                UNIX_EPOCH.checked_add(Duration::from_millis(*ms as u64))
+            }
+            ScalarValue::Timestamp(ms) => {
+                UNIX_EPOCH.checked_sub(Duration::from_millis(ms.unsigned_abs()))
+            }
+            _ => None,
+        }
+    }
+
+    /// If this value is a timestamp, return it as a UTC [`chrono::DateTime`]. Returns `None` if
+    /// the timestamp is outside the range of `DateTime`.
+    #[cfg(feature = "chrono")]
+    pub fn to_date_time(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        use chrono::TimeZone;
+        match self {
+            ScalarValue::Timestamp(ms) => chrono::Utc.timestamp_millis_opt(*ms).single(),
+            _ => None,
+        }
+    }
+
+    /// If this value is a timestamp, return it as a UTC [`time::OffsetDateTime`]. Returns `None`
+    /// if the timestamp is outside the range of `OffsetDateTime`.
+    #[cfg(feature = "time")]
+    pub fn to_offset_date_time(&self) -> Option<time::OffsetDateTime> {
+        match self {
+            ScalarValue::Timestamp(ms) => {
+                time::OffsetDateTime::from_unix_timestamp_nanos(i128::from(*ms) * 1_000_000).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A timestamp of `time`, truncated to whole milliseconds towards the past. Every `DateTime` is
+/// within the range of a timestamp.
+#[cfg(feature = "chrono")]
+impl<Tz: chrono::TimeZone> From<chrono::DateTime<Tz>> for ScalarValue {
+    fn from(time: chrono::DateTime<Tz>) -> Self {
+        ScalarValue::Timestamp(time.timestamp_millis())
+    }
+}
+
+/// A timestamp of `time`, truncated to whole milliseconds towards the past. Every
+/// `OffsetDateTime` is within the range of a timestamp.
+#[cfg(feature = "time")]
+impl From<time::OffsetDateTime> for ScalarValue {
+    fn from(time: time::OffsetDateTime) -> Self {
+        let ms = time.unix_timestamp_nanos().div_euclid(1_000_000);
+        ScalarValue::Timestamp(ms as i64)
+    }
+}
+
+/// A timestamp of `time`, truncated to whole milliseconds towards the past. Fails if `time` is
+/// more than `i64::MAX` milliseconds from the Unix epoch.
+impl TryFrom<SystemTime> for ScalarValue {
+    type Error = error::InvalidTimestamp;
+
+    fn try_from(time: SystemTime) -> Result<Self, Self::Error> {
+        let ms = match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => i64::try_from(after.as_millis()).ok(),
+            Err(before) => {
+                let before = before.duration();
+                // round up so that the timestamp is never after `time`
+                let ms = before.as_millis() + u128::from(before.subsec_nanos() % 1_000_000 != 0);
+                i64::try_from(ms).ok().map(|ms| -ms)
+            }
+        };
+        ms.map(ScalarValue::Timestamp)
+            .ok_or(error::InvalidTimestamp(time))
+    }
 }
 
 impl From<&str> for ScalarValue {
@@ -1,9 +1,12 @@
 use crate::exid::ExId;
-use crate::{Automerge, Value};
+use crate::types::{ChangeHash, ObjId};
+use crate::{Automerge, Prop, Value};
 use std::fmt;
 
 pub struct Values<'a> {
     range: Box<dyn 'a + ValueIter<'a>>,
+    obj: ObjId,
+    heads: Option<Vec<ChangeHash>>,
     doc: &'a Automerge,
 }
 
@@ -14,22 +17,29 @@ impl<'a> fmt::Debug for Values<'a> {
 }
 
 pub(crate) trait ValueIter<'a> {
-    fn next_value(&mut self, doc: &'a Automerge) -> Option<(Value<'a>, ExId)>;
+    fn next_value(&mut self, doc: &'a Automerge) -> Option<(Prop, Value<'a>, ExId)>;
 }
 
 pub(crate) struct NoValues {}
 
 impl<'a> ValueIter<'a> for NoValues {
-    fn next_value(&mut self, _doc: &'a Automerge) -> Option<(Value<'a>, ExId)> {
+    fn next_value(&mut self, _doc: &'a Automerge) -> Option<(Prop, Value<'a>, ExId)> {
         None
     }
 }
 
 impl<'a> Values<'a> {
-    pub(crate) fn new<R: 'a + ValueIter<'a>>(doc: &'a Automerge, range: Option<R>) -> Self {
+    pub(crate) fn new<R: 'a + ValueIter<'a>>(
+        doc: &'a Automerge,
+        obj: ObjId,
+        heads: Option<&[ChangeHash]>,
+        range: Option<R>,
+    ) -> Self {
         if let Some(range) = range {
             Self {
                 range: Box::new(range),
+                obj,
+                heads: heads.map(|h| h.to_vec()),
                 doc,
             }
         } else {
@@ -40,6 +50,8 @@ impl<'a> Values<'a> {
     pub(crate) fn empty(doc: &'a Automerge) -> Self {
         Self {
             range: Box::new(NoValues {}),
+            obj: ObjId::root(),
+            heads: None,
             doc,
         }
     }
@@ -49,7 +61,11 @@ impl<'a> Iterator for Values<'a> {
     type Item = (Value<'a>, ExId);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.range.next_value(self.doc)
+        let (prop, value, id) = self.range.next_value(self.doc)?;
+        Some(
+            self.doc
+                .with_conflict_policy(&self.obj, prop, (value, id), self.heads.as_deref()),
+        )
     }
 }
 
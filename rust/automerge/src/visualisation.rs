@@ -44,7 +44,7 @@ impl<'a> GraphVisualisation<'a> {
     pub(super) fn construct(
         trees: &'a HashMap<
             crate::types::ObjId,
-            crate::op_tree::OpTree,
+            std::sync::Arc<crate::op_tree::OpTree>,
             BuildHasherDefault<FxHasher>,
         >,
         metadata: &'a crate::op_set::OpSetMetadata,
@@ -1373,3 +1373,78 @@ fn simple_bad_saveload() {
     let bytes = doc.save();
     Automerge::load(&bytes).unwrap();
 }
+
+#[test]
+fn signed_changes_are_verified() {
+    // a toy signature scheme: the signature is the sum of the signed bytes
+    fn sign(bytes: &[u8]) -> Vec<u8> {
+        let sum = bytes.iter().fold(0_u64, |acc, b| acc + *b as u64);
+        sum.to_be_bytes().to_vec()
+    }
+    fn verify(_actor: &ActorId, bytes: &[u8], signature: &[u8]) -> bool {
+        sign(bytes) == signature
+    }
+
+    let mut doc1 = AutoCommit::new();
+    doc1.set_signer(sign);
+    doc1.put(&ROOT, "key", "value").unwrap();
+    doc1.commit();
+    let change = doc1.get_last_local_change().unwrap().clone();
+    assert_eq!(
+        change.signature(),
+        Some(sign(change.signed_bytes()).as_slice())
+    );
+
+    let mut doc2 = AutoCommit::new();
+    doc2.set_verifier(verify);
+    doc2.apply_changes(vec![change]).unwrap();
+    assert_eq!(doc2.get(&ROOT, "key").unwrap().unwrap().0, "value".into());
+
+    let mut unsigned = AutoCommit::new();
+    unsigned.put(&ROOT, "other", "value").unwrap();
+    unsigned.commit();
+    let unsigned_change = unsigned.get_last_local_change().unwrap().clone();
+    assert!(matches!(
+        doc2.apply_changes(vec![unsigned_change]),
+        Err(AutomergeError::InvalidSignature(_))
+    ));
+
+    let saved = doc1.save();
+    AutoCommit::load_verified(&saved, verify).unwrap();
+    let saved = unsigned.save();
+    assert!(matches!(
+        AutoCommit::load_verified(&saved, verify),
+        Err(AutomergeError::InvalidSignature(_))
+    ));
+}
+
+#[test]
+fn record_and_replay_trace() {
+    use automerge_test::trace::{Trace, TraceRecorder};
+
+    let mut doc = AutoCommit::new().with_observer(TraceRecorder::default());
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "hello world").unwrap();
+    doc.commit();
+    doc.set_source(Some("todos"));
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.set_source(None);
+    let item = doc.insert_object(&list, 0, ObjType::Map).unwrap();
+    doc.put(&item, "count", ScalarValue::counter(1)).unwrap();
+    doc.increment(&item, "count", 2).unwrap();
+    doc.splice_text(&text, 0, 6, "").unwrap();
+    doc.commit();
+
+    let trace = doc.observer().take_trace();
+    let trace = Trace::from_json(&trace.to_json()).unwrap();
+
+    let mut replayed = new_doc();
+    let stats = trace.replay(&mut replayed).unwrap();
+    assert_eq!(stats.commits, 2);
+    assert_eq!(stats.ops, 22);
+    assert_eq!(realize(replayed.document()), realize(doc.document()));
+    let replayed_list = replayed.get(ROOT, "list").unwrap().unwrap().1;
+    assert_eq!(replayed.op_source(&replayed_list), Some("todos"));
+    let replayed_item = replayed.get(&replayed_list, 0).unwrap().unwrap().1;
+    assert_eq!(replayed.op_source(&replayed_item), None);
+}